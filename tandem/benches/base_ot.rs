@@ -0,0 +1,67 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use tandem::{
+    states::{Contributor, Evaluator},
+    BaseOt, Circuit, Gate, ProtocolOptions, ProtocolPlan,
+};
+
+/// ANDs the input bit of the contributor with the input bit of the evaluator `and_gates` times,
+/// so that the runtime is dominated by the setup of the OT extension.
+fn and_circuit(and_gates: u32) -> Circuit {
+    let mut gates = vec![Gate::InContrib, Gate::InEval];
+    gates.extend((0..and_gates).map(|_| Gate::And(0, 1)));
+    Circuit::new(gates, vec![and_gates + 1])
+}
+
+fn run(circuit: &Circuit, options: ProtocolOptions) -> Vec<bool> {
+    let mut eval =
+        Evaluator::new_with_options(circuit, vec![true], ChaCha20Rng::from_entropy(), options)
+            .unwrap();
+    let (mut contrib, mut msg_for_eval) =
+        Contributor::new_with_options(circuit, vec![true], ChaCha20Rng::from_entropy(), options)
+            .unwrap();
+    for _ in 0..eval.steps() {
+        let (next_state, msg_for_contrib) = eval.run(&msg_for_eval).unwrap();
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&msg_for_contrib).unwrap();
+        contrib = next_state;
+        msg_for_eval = reply;
+    }
+    eval.output(&msg_for_eval).unwrap()
+}
+
+fn base_ot_benchmarks(c: &mut Criterion) {
+    let base_ots = [
+        ("ChouOrlandi", BaseOt::ChouOrlandi),
+        ("PostQuantum", BaseOt::PostQuantum),
+    ];
+    let mut group = c.benchmark_group("base ot");
+    group.sample_size(10);
+    for and_gates in [10, 10000] {
+        let circuit = and_circuit(and_gates);
+        for (name, base_ot) in base_ots {
+            let options = ProtocolOptions {
+                base_ot,
+                ..Default::default()
+            };
+            let plan = ProtocolPlan::with_options(&circuit, &options);
+            println!(
+                "bytes sent for {and_gates} AND gates using {name}: {} (contributor), {} (evaluator)",
+                plan.total_contributor_bytes(),
+                plan.total_evaluator_bytes()
+            );
+            group.bench_with_input(BenchmarkId::new(name, and_gates), &circuit, |b, circuit| {
+                b.iter(|| run(circuit, options))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group! {
+  name = benches;
+  config = Criterion::default();
+  targets = base_ot_benchmarks
+}
+criterion_main!(benches);
@@ -0,0 +1,97 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use tandem::{
+    states::{Contributor, Evaluator},
+    Circuit, Gate, InputBits, InputSource,
+};
+
+/// Counts the bytes that are currently allocated, to compare the memory used by the inputs.
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const INPUT_BITS: u32 = 1_000_000;
+
+/// XORs all input bits of the evaluator with the single input bit of the contributor.
+fn wide_input_circuit() -> Circuit {
+    let mut gates = vec![Gate::InContrib];
+    gates.extend((0..INPUT_BITS).map(|_| Gate::InEval));
+    gates.extend((1..=INPUT_BITS).map(|i| Gate::Xor(0, i)));
+    let outputs = vec![INPUT_BITS + 1, 2 * INPUT_BITS];
+    Circuit::new(gates, outputs)
+}
+
+fn input_bit(i: u32) -> bool {
+    i % 3 == 0
+}
+
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let value = f();
+    (value, ALLOCATED.load(Ordering::Relaxed) - before)
+}
+
+fn run(circuit: &Circuit, input: impl InputSource) -> Vec<bool> {
+    let mut eval = Evaluator::new(circuit, input, ChaCha20Rng::from_entropy()).unwrap();
+    let (mut contrib, mut msg_for_eval) =
+        Contributor::new(circuit, vec![true], ChaCha20Rng::from_entropy()).unwrap();
+    for _ in 0..eval.steps() {
+        let (next_state, msg_for_contrib) = eval.run(&msg_for_eval).unwrap();
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&msg_for_contrib).unwrap();
+        contrib = next_state;
+        msg_for_eval = reply;
+    }
+    eval.output(&msg_for_eval).unwrap()
+}
+
+fn input_bits_benchmarks(c: &mut Criterion) {
+    let circuit = wide_input_circuit();
+
+    let (bools, bools_bytes) = measure(|| (0..INPUT_BITS).map(input_bit).collect::<Vec<bool>>());
+    let (packed, packed_bytes) = measure(|| (0..INPUT_BITS).map(input_bit).collect::<InputBits>());
+    println!("memory of {INPUT_BITS} input bits as Vec<bool>: {bools_bytes} bytes");
+    println!("memory of {INPUT_BITS} input bits as InputBits: {packed_bytes} bytes");
+
+    let mut group = c.benchmark_group("wide input");
+    group.sample_size(10);
+    group.bench_with_input(
+        BenchmarkId::new("Vec<bool>", INPUT_BITS),
+        &bools,
+        |b, input| b.iter(|| run(&circuit, &input[..])),
+    );
+    group.bench_with_input(
+        BenchmarkId::new("InputBits", INPUT_BITS),
+        &packed,
+        |b, input| b.iter(|| run(&circuit, input)),
+    );
+    group.finish();
+}
+
+criterion_group! {
+  name = benches;
+  config = Criterion::default();
+  targets = input_bits_benchmarks
+}
+criterion_main!(benches);
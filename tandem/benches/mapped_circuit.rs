@@ -0,0 +1,46 @@
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tandem::{Circuit, ColumnarCircuit, Gate, MappedCircuit};
+
+fn write_circuit(and_gates: u32) -> PathBuf {
+    let mut gates = vec![Gate::InContrib, Gate::InEval];
+    for i in 0..and_gates {
+        gates.append(&mut vec![
+            Gate::And(i * 2, i * 2 + 1),
+            Gate::Xor(i * 2, i * 2 + 2),
+        ]);
+    }
+    let output_gates = vec![and_gates * 2 + 1];
+    let circuit = Circuit::new(gates, output_gates);
+
+    let path = std::env::temp_dir().join(format!("tandem_bench_{and_gates}.circuit"));
+    let file = BufWriter::new(File::create(&path).unwrap());
+    circuit.write_columnar(file).unwrap();
+    path
+}
+
+fn mapped_circuit_benchmarks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("circuit startup");
+    for and_gates in [10_000, 100_000, 1_000_000] {
+        let path = write_circuit(and_gates);
+        group.bench_with_input(BenchmarkId::new("read", and_gates), &path, |b, path| {
+            b.iter(|| {
+                let bytes = std::fs::read(path).unwrap();
+                ColumnarCircuit::new(bytes).unwrap().to_circuit()
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("mmap", and_gates), &path, |b, path| {
+            b.iter(|| MappedCircuit::open(path).unwrap());
+        });
+        std::fs::remove_file(path).unwrap();
+    }
+    group.finish();
+}
+
+criterion_group! {
+  name = benches;
+  config = Criterion::default();
+  targets = mapped_circuit_benchmarks
+}
+criterion_main!(benches);
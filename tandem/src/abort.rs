@@ -0,0 +1,131 @@
+//! Control messages that abort the protocol and inform the other party about the reason.
+
+use crate::{states::Msg, Error};
+
+/// Prefix of abort messages, which cannot occur at the start of a regular protocol message.
+///
+/// Regular messages start with the length of a bincode-encoded `Vec`, which never comes close to
+/// `u64::MAX`.
+const ABORT_PREFIX: [u8; 8] = [0xFF; 8];
+
+/// The machine-readable reason why a party aborted the protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AbortReason {
+    /// The computation was cancelled by the user.
+    Cancelled,
+    /// The computation took too long.
+    Timeout,
+    /// A MAC or equality check failed, due to an accidental or deliberate data corruption.
+    CheckFailed,
+    /// The other party sent an unexpected or malformed message.
+    ProtocolViolation,
+    /// The party encountered an internal error.
+    Internal,
+    /// An application-specific reason, codes above `0xFEFF` are not distinguishable.
+    Other(u16),
+}
+
+/// First code of application-specific reasons, see [`AbortReason::Other`].
+const OTHER: u16 = 0x100;
+
+impl AbortReason {
+    /// The numeric code of the reason, as transmitted in an abort message.
+    pub fn code(&self) -> u16 {
+        match self {
+            AbortReason::Cancelled => 0,
+            AbortReason::Timeout => 1,
+            AbortReason::CheckFailed => 2,
+            AbortReason::ProtocolViolation => 3,
+            AbortReason::Internal => 4,
+            AbortReason::Other(code) => OTHER.saturating_add(*code),
+        }
+    }
+
+    /// Parses a numeric code into a reason, unknown codes are reported as [`AbortReason::Internal`].
+    pub fn from_code(code: u16) -> Self {
+        match code {
+            0 => AbortReason::Cancelled,
+            1 => AbortReason::Timeout,
+            2 => AbortReason::CheckFailed,
+            3 => AbortReason::ProtocolViolation,
+            OTHER.. => AbortReason::Other(code - OTHER),
+            _ => AbortReason::Internal,
+        }
+    }
+}
+
+impl From<&Error> for AbortReason {
+    fn from(e: &Error) -> Self {
+        match e {
+            Error::MacError
+            | Error::LeakyAndNotEqual
+            | Error::OtCorrelationCheckFailed
+            | Error::TranscriptMismatch
+            | Error::InvalidChannelMessage => AbortReason::CheckFailed,
+            Error::UnexpectedMessageType
+            | Error::InsufficientAndShares
+            | Error::UnexpectedGarbledTableShare
+            | Error::OtInitDeserializationError
+            | Error::OtBlockDeserializationError
+            | Error::BincodeError
+            | Error::ProtocolEnded => AbortReason::ProtocolViolation,
+            Error::PeerAborted(reason) => *reason,
+            _ => AbortReason::Internal,
+        }
+    }
+}
+
+impl std::fmt::Display for AbortReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AbortReason::Cancelled => f.write_str("cancelled"),
+            AbortReason::Timeout => f.write_str("timeout"),
+            AbortReason::CheckFailed => f.write_str("check failed"),
+            AbortReason::ProtocolViolation => f.write_str("protocol violation"),
+            AbortReason::Internal => f.write_str("internal error"),
+            AbortReason::Other(code) => write!(f, "application-specific reason {code}"),
+        }
+    }
+}
+
+/// Creates a message informing the other party that the protocol was aborted.
+///
+/// When passed to `run` or `output` of the other party, the message results in an
+/// [`Error::PeerAborted`] with the specified reason.
+pub fn abort_message(reason: AbortReason) -> Msg {
+    let mut msg = ABORT_PREFIX.to_vec();
+    msg.extend_from_slice(&reason.code().to_le_bytes());
+    msg
+}
+
+/// Returns an error if the message is an abort message sent by the other party.
+pub(crate) fn check_abort(msg: &[u8]) -> Result<(), Error> {
+    match msg {
+        [prefix @ .., a, b] if prefix == ABORT_PREFIX => Err(Error::PeerAborted(
+            AbortReason::from_code(u16::from_le_bytes([*a, *b])),
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[test]
+fn test_abort_reason_codes() {
+    for reason in [
+        AbortReason::Cancelled,
+        AbortReason::Timeout,
+        AbortReason::CheckFailed,
+        AbortReason::ProtocolViolation,
+        AbortReason::Internal,
+        AbortReason::Other(0),
+        AbortReason::Other(42),
+    ] {
+        assert_eq!(AbortReason::from_code(reason.code()), reason);
+        assert_eq!(
+            check_abort(&abort_message(reason)),
+            Err(Error::PeerAborted(reason))
+        );
+    }
+    assert_eq!(AbortReason::from_code(99), AbortReason::Internal);
+    assert_eq!(check_abort(&[0xFF; 8]), Ok(()));
+    assert_eq!(check_abort(&[0xFF; 11]), Ok(()));
+}
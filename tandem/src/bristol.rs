@@ -0,0 +1,172 @@
+//! Parser for circuits in the [Bristol Fashion](https://nigelsmart.github.io/MPC-Circuits/)
+//! netlist format.
+
+use std::collections::HashMap;
+
+use crate::{Circuit, Error, Gate, GateIndex};
+
+impl Circuit {
+    /// Parses a circuit in the Bristol Fashion netlist format.
+    ///
+    /// The netlist must declare exactly 2 input values: the first one is provided by the
+    /// contributor, the second one by the evaluator. All output values are concatenated into the
+    /// output of the circuit.
+    ///
+    /// Besides the standard `XOR`, `AND`, `INV`, `EQ`, `EQW` and `MAND` gates, the non-standard
+    /// `NAND` (`2 1 a b out NAND`) and `MUX` (`3 1 s a b out MUX`, selecting `a` if `s` is false,
+    /// otherwise `b`) gates are accepted.
+    pub fn from_bristol_fashion(netlist: &str) -> Result<Self, Error> {
+        let mut lines = netlist
+            .lines()
+            .enumerate()
+            .map(|(i, l)| (i + 1, l.split_whitespace().collect::<Vec<_>>()))
+            .filter(|(_, l)| !l.is_empty());
+
+        let (line, header) = lines
+            .next()
+            .ok_or(Error::InvalidBristolNetlist { line: 1 })?;
+        let (num_gates, num_wires) = match parse_numbers(&header, line)?[..] {
+            [num_gates, num_wires] => (num_gates, num_wires),
+            _ => return Err(Error::InvalidBristolNetlist { line }),
+        };
+
+        let (line, inputs) = lines.next().ok_or(Error::InvalidBristolNetlist { line })?;
+        let (contrib_inputs, eval_inputs) = match parse_counts(&inputs, line)?[..] {
+            [contrib_inputs, eval_inputs] => (contrib_inputs, eval_inputs),
+            _ => return Err(Error::InvalidBristolNetlist { line }),
+        };
+
+        let (line, outputs) = lines.next().ok_or(Error::InvalidBristolNetlist { line })?;
+        let outputs = parse_counts(&outputs, line)?
+            .into_iter()
+            .try_fold(0usize, usize::checked_add);
+        let inputs = contrib_inputs.checked_add(eval_inputs);
+        let (inputs, outputs) = match (inputs, outputs) {
+            (Some(inputs), Some(outputs))
+                if inputs <= num_wires
+                    && outputs <= num_wires
+                    && GateIndex::try_from(inputs).is_ok() =>
+            {
+                (inputs, outputs)
+            }
+            _ => return Err(Error::InvalidBristolNetlist { line }),
+        };
+
+        // apart from the inputs, memory is only allocated for the gates and wires that are parsed,
+        // as the wire count of the header is not trusted:
+        let mut gates = vec![];
+        gates
+            .try_reserve_exact(inputs)
+            .map_err(|_| Error::InvalidBristolNetlist { line })?;
+        gates.resize(contrib_inputs, Gate::InContrib);
+        gates.resize(inputs, Gate::InEval);
+        // maps each Bristol wire to the gate computing it, input wires map to their input gates:
+        let mut wires: HashMap<usize, GateIndex> = HashMap::new();
+        let wire = |wires: &HashMap<usize, GateIndex>, w: usize| {
+            let input = (w < inputs).then(|| w as GateIndex);
+            wires.get(&w).copied().or(input)
+        };
+        let mut constant_false = None;
+
+        let mut gates_parsed = 0;
+        for (line, tokens) in lines {
+            let err = Error::InvalidBristolNetlist { line };
+            let (kind, tokens) = tokens.split_last().ok_or(err)?;
+            let tokens = parse_numbers(tokens, line)?;
+            let (n_in, n_out, wire_ids) = match tokens[..] {
+                [n_in, n_out, ref wire_ids @ ..] => (n_in, n_out, wire_ids),
+                _ => return Err(Error::InvalidBristolNetlist { line }),
+            };
+            if n_in.checked_add(n_out) != Some(wire_ids.len()) {
+                return Err(Error::InvalidBristolNetlist { line });
+            }
+            let (ins, outs) = wire_ids.split_at(n_in);
+            if outs.iter().any(|&o| o >= num_wires) {
+                return Err(Error::InvalidBristolNetlist { line });
+            }
+            let input = |i: usize| -> Result<GateIndex, Error> {
+                wire(&wires, ins[i]).ok_or(Error::InvalidBristolNetlist { line })
+            };
+            let gate = match (*kind, n_in, n_out) {
+                ("XOR", 2, 1) => Gate::Xor(input(0)?, input(1)?),
+                ("AND", 2, 1) => Gate::And(input(0)?, input(1)?),
+                ("NAND", 2, 1) => Gate::Nand(input(0)?, input(1)?),
+                ("INV", 1, 1) => Gate::Not(input(0)?),
+                ("MUX", 3, 1) => Gate::Mux(input(0)?, input(1)?, input(2)?),
+                ("EQW", 1, 1) => {
+                    let w = input(0)?;
+                    wires.insert(outs[0], w);
+                    gates_parsed += 1;
+                    continue;
+                }
+                ("EQ", 1, 1) => {
+                    // constants are derived from the first input wire, as x ^ x = 0:
+                    let zero = match constant_false {
+                        Some(zero) => zero,
+                        None if !gates.is_empty() => {
+                            gates.push(Gate::Xor(0, 0));
+                            let zero = gates.len() as GateIndex - 1;
+                            constant_false = Some(zero);
+                            zero
+                        }
+                        None => return Err(Error::InvalidBristolNetlist { line }),
+                    };
+                    let constant = match ins[0] {
+                        0 => zero,
+                        1 => {
+                            gates.push(Gate::Not(zero));
+                            gates.len() as GateIndex - 1
+                        }
+                        _ => return Err(Error::InvalidBristolNetlist { line }),
+                    };
+                    wires.insert(outs[0], constant);
+                    gates_parsed += 1;
+                    continue;
+                }
+                ("MAND", _, _) if n_in == 2 * n_out => {
+                    let ands = (0..n_out)
+                        .map(|i| Ok(Gate::And(input(i)?, input(n_out + i)?)))
+                        .collect::<Result<Vec<_>, Error>>()?;
+                    for (and, &o) in ands.into_iter().zip(outs) {
+                        gates.push(and);
+                        wires.insert(o, gates.len() as GateIndex - 1);
+                    }
+                    gates_parsed += 1;
+                    continue;
+                }
+                _ => return Err(Error::InvalidBristolNetlist { line }),
+            };
+            gates.push(gate);
+            wires.insert(outs[0], gates.len() as GateIndex - 1);
+            gates_parsed += 1;
+        }
+        if gates_parsed != num_gates {
+            return Err(Error::InvalidBristolNetlist {
+                line: netlist.lines().count(),
+            });
+        }
+
+        let output_gates = (num_wires - outputs..num_wires)
+            .map(|w| wire(&wires, w).ok_or(Error::InvalidCircuit))
+            .collect::<Result<Vec<_>, _>>()?;
+        let circuit = Circuit::new(gates, output_gates);
+        circuit.validate()?;
+        Ok(circuit)
+    }
+}
+
+fn parse_numbers(tokens: &[&str], line: usize) -> Result<Vec<usize>, Error> {
+    tokens
+        .iter()
+        .map(|t| t.parse().map_err(|_| Error::InvalidBristolNetlist { line }))
+        .collect()
+}
+
+/// Parses a line of the form `<n> <count_1> ... <count_n>`, returning the counts.
+fn parse_counts(tokens: &[&str], line: usize) -> Result<Vec<usize>, Error> {
+    let numbers = parse_numbers(tokens, line)?;
+    match numbers.split_first() {
+        Some((&n, counts)) if n == counts.len() => Ok(counts.to_vec()),
+        _ => Err(Error::InvalidBristolNetlist { line }),
+    }
+}
@@ -0,0 +1,301 @@
+//! An encrypted channel for the messages of the protocol, which keeps them confidential even if the
+//! transport is not (for example if TLS is terminated at a proxy that is not trusted).
+//!
+//! The channel is established by a Noise-style handshake of ephemeral keys in the Ristretto group
+//! that is also used by the base OTs. The [`states::Evaluator`](crate::states::Evaluator) usually
+//! initiates the handshake using [`Handshake::initiate`], the
+//! [`states::Contributor`](crate::states::Contributor) responds using [`Handshake::respond`]. If
+//! the responder has a static [`ChannelKeyPair`] whose public key the initiator has pinned, the
+//! channel is authenticated (like the `NK` pattern of Noise), otherwise it only protects against
+//! passive observers (like the `NN` pattern).
+//!
+//! Messages are encrypted using ChaCha20-Poly1305, with a separate key for each direction and the
+//! id of the message as the nonce. Encrypting the same message id twice thus needs to produce the
+//! same message, which is the case for messages that are resent until they are acknowledged.
+//!
+//! ```
+//! use rand::SeedableRng;
+//! use rand_chacha::ChaCha20Rng;
+//! use tandem::channel::{ChannelKeyPair, Handshake};
+//!
+//! let mut rng = ChaCha20Rng::from_entropy();
+//! let server_key = ChannelKeyPair::generate(&mut rng);
+//! let handshake = Handshake::initiate(&mut rng);
+//! let (server, reply) =
+//!     Handshake::respond(&mut rng, &handshake.ephemeral_key(), Some(&server_key)).unwrap();
+//! let client = handshake.finish(&reply, Some(&server_key.public_key())).unwrap();
+//!
+//! let ciphertext = client.seal(0, b"hello");
+//! assert_eq!(server.open(0, &ciphertext).unwrap(), b"hello");
+//! assert!(server.open(1, &ciphertext).is_err());
+//! ```
+
+use std::fmt;
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use curve25519_dalek_ng::{
+    constants::RISTRETTO_BASEPOINT_TABLE,
+    ristretto::{CompressedRistretto, RistrettoPoint},
+    scalar::Scalar,
+    traits::Identity,
+};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{states::Msg, Error};
+
+/// The key derivation context of the channel keys, which must match the other party's.
+const CHANNEL_CONTEXT: &str = "tandem 2025-01-01 encrypted channel v1";
+
+/// A compressed Ristretto point, used as the public key of a party.
+pub type ChannelKey = [u8; 32];
+
+/// A static key pair, which authenticates the responder of the [`Handshake`].
+#[derive(Clone)]
+pub struct ChannelKeyPair {
+    secret: Scalar,
+    public: ChannelKey,
+}
+
+impl fmt::Debug for ChannelKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelKeyPair")
+            .field("public", &self.public)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ChannelKeyPair {
+    /// Generates a new random key pair.
+    pub fn generate(rng: &mut (impl RngCore + CryptoRng)) -> Self {
+        Self::from_scalar(Scalar::random(rng))
+    }
+
+    /// Restores a key pair from the bytes returned by [`ChannelKeyPair::secret_bytes`].
+    pub fn from_secret_bytes(bytes: [u8; 32]) -> Self {
+        Self::from_scalar(Scalar::from_bytes_mod_order(bytes))
+    }
+
+    /// Returns the secret key, which must be kept confidential.
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.secret.to_bytes()
+    }
+
+    /// Returns the public key, which initiators can pin to authenticate the responder.
+    pub fn public_key(&self) -> ChannelKey {
+        self.public
+    }
+
+    fn from_scalar(secret: Scalar) -> Self {
+        let public = (&RISTRETTO_BASEPOINT_TABLE * &secret).compress().to_bytes();
+        Self { secret, public }
+    }
+
+    fn diffie_hellman(&self, other: &RistrettoPoint) -> [u8; 32] {
+        (other * self.secret).compress().to_bytes()
+    }
+}
+
+/// The reply of the responder to the ephemeral key of the initiator of a [`Handshake`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HandshakeReply {
+    /// The ephemeral public key of the responder.
+    pub ephemeral_key: ChannelKey,
+    /// The static public key of the responder, if it authenticates itself.
+    pub static_key: Option<ChannelKey>,
+}
+
+/// The state of the initiator of the handshake, until it receives the [`HandshakeReply`].
+#[derive(Debug)]
+pub struct Handshake {
+    ephemeral: ChannelKeyPair,
+}
+
+impl Handshake {
+    /// Starts the handshake, whose ephemeral key needs to be sent to the responder.
+    pub fn initiate(rng: &mut (impl RngCore + CryptoRng)) -> Self {
+        Self {
+            ephemeral: ChannelKeyPair::generate(rng),
+        }
+    }
+
+    /// Returns the ephemeral public key of the initiator.
+    pub fn ephemeral_key(&self) -> ChannelKey {
+        self.ephemeral.public_key()
+    }
+
+    /// Responds to the ephemeral key of the initiator, authenticating the responder using the
+    /// static key (if any), and returns the channel of the responder together with the reply that
+    /// needs to be sent to the initiator.
+    pub fn respond(
+        rng: &mut (impl RngCore + CryptoRng),
+        initiator_key: &ChannelKey,
+        static_key: Option<&ChannelKeyPair>,
+    ) -> Result<(SecureChannel, HandshakeReply), Error> {
+        let initiator = decompress(initiator_key)?;
+        let ephemeral = ChannelKeyPair::generate(rng);
+        let reply = HandshakeReply {
+            ephemeral_key: ephemeral.public_key(),
+            static_key: static_key.map(ChannelKeyPair::public_key),
+        };
+        let ee = ephemeral.diffie_hellman(&initiator);
+        let es = static_key.map(|s| s.diffie_hellman(&initiator));
+        let channel = SecureChannel::derive(initiator_key, &reply, &ee, es.as_ref(), false);
+        Ok((channel, reply))
+    }
+
+    /// Completes the handshake using the reply of the responder.
+    ///
+    /// Fails with [`Error::InvalidChannelMessage`] if a static key is pinned and the responder
+    /// did not authenticate itself using this key.
+    pub fn finish(
+        self,
+        reply: &HandshakeReply,
+        pinned_key: Option<&ChannelKey>,
+    ) -> Result<SecureChannel, Error> {
+        if pinned_key.is_some() && reply.static_key.as_ref() != pinned_key {
+            return Err(Error::InvalidChannelMessage);
+        }
+        let ee = self
+            .ephemeral
+            .diffie_hellman(&decompress(&reply.ephemeral_key)?);
+        let es = match &reply.static_key {
+            Some(key) => Some(self.ephemeral.diffie_hellman(&decompress(key)?)),
+            None => None,
+        };
+        let initiator_key = self.ephemeral_key();
+        let channel = SecureChannel::derive(&initiator_key, reply, &ee, es.as_ref(), true);
+        Ok(channel)
+    }
+}
+
+fn decompress(key: &ChannelKey) -> Result<RistrettoPoint, Error> {
+    match CompressedRistretto(*key).decompress() {
+        Some(point) if point != RistrettoPoint::identity() => Ok(point),
+        _ => Err(Error::InvalidChannelMessage),
+    }
+}
+
+/// One end of an encrypted channel, established by a [`Handshake`].
+#[derive(Clone)]
+pub struct SecureChannel {
+    sender: ChaCha20Poly1305,
+    receiver: ChaCha20Poly1305,
+    binding_key: [u8; 32],
+}
+
+impl fmt::Debug for SecureChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureChannel").finish_non_exhaustive()
+    }
+}
+
+impl SecureChannel {
+    fn derive(
+        initiator_key: &ChannelKey,
+        reply: &HandshakeReply,
+        ee: &[u8; 32],
+        es: Option<&[u8; 32]>,
+        is_initiator: bool,
+    ) -> Self {
+        let mut hasher = blake3::Hasher::new_derive_key(CHANNEL_CONTEXT);
+        hasher.update(initiator_key);
+        hasher.update(&reply.ephemeral_key);
+        hasher.update(ee);
+        match (&reply.static_key, es) {
+            (Some(static_key), Some(es)) => {
+                hasher.update(&[1]);
+                hasher.update(static_key);
+                hasher.update(es);
+            }
+            _ => {
+                hasher.update(&[0]);
+            }
+        }
+        let mut keys = [0; 96];
+        hasher.finalize_xof().fill(&mut keys);
+        let (initiator, rest) = keys.split_at(32);
+        let (responder, binding) = rest.split_at(32);
+        let initiator = ChaCha20Poly1305::new(Key::from_slice(initiator));
+        let responder = ChaCha20Poly1305::new(Key::from_slice(responder));
+        let mut binding_key = [0; 32];
+        binding_key.copy_from_slice(binding);
+        if is_initiator {
+            Self {
+                sender: initiator,
+                receiver: responder,
+                binding_key,
+            }
+        } else {
+            Self {
+                sender: responder,
+                receiver: initiator,
+                binding_key,
+            }
+        }
+    }
+
+    /// A key shared by both ends of the channel, which binds values exchanged outside of the
+    /// channel (such as the configuration of a session) to it, for example as the key of a keyed
+    /// hash.
+    ///
+    /// Like the channel itself, the key is only unknown to an active attacker if the responder
+    /// authenticated itself using a pinned key.
+    pub fn binding_key(&self) -> &[u8; 32] {
+        &self.binding_key
+    }
+
+    /// Encrypts the message with the specified id, which must not be used for any other message
+    /// sent over this channel.
+    pub fn seal(&self, id: u64, msg: &[u8]) -> Msg {
+        self.sender
+            .encrypt(&nonce(id), msg)
+            .expect("messages are far below the size limit of ChaCha20-Poly1305")
+    }
+
+    /// Decrypts the message with the specified id, failing with [`Error::InvalidChannelMessage`]
+    /// if it was not sent over this channel using this id or was modified in transit.
+    pub fn open(&self, id: u64, ciphertext: &[u8]) -> Result<Msg, Error> {
+        self.receiver
+            .decrypt(&nonce(id), ciphertext)
+            .map_err(|_| Error::InvalidChannelMessage)
+    }
+}
+
+fn nonce(id: u64) -> Nonce {
+    let mut nonce = [0; 12];
+    nonce[4..].copy_from_slice(&id.to_le_bytes());
+    *Nonce::from_slice(&nonce)
+}
+
+#[test]
+fn test_unauthenticated_channel() {
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    let mut rng = ChaCha20Rng::seed_from_u64(0);
+    let handshake = Handshake::initiate(&mut rng);
+    let (responder, reply) =
+        Handshake::respond(&mut rng, &handshake.ephemeral_key(), None).unwrap();
+    assert_eq!(reply.static_key, None);
+    let initiator = handshake.finish(&reply, None).unwrap();
+
+    let ciphertext = responder.seal(7, b"final");
+    assert_eq!(initiator.open(7, &ciphertext).unwrap(), b"final");
+    // each direction uses its own key:
+    assert!(responder.open(7, &ciphertext).is_err());
+    assert_eq!(responder.binding_key(), initiator.binding_key());
+
+    // a pinned key rejects responders that do not authenticate themselves:
+    let handshake = Handshake::initiate(&mut rng);
+    let (_, reply) = Handshake::respond(&mut rng, &handshake.ephemeral_key(), None).unwrap();
+    let pinned = ChannelKeyPair::generate(&mut rng).public_key();
+    assert_eq!(
+        handshake.finish(&reply, Some(&pinned)).unwrap_err(),
+        Error::InvalidChannelMessage
+    );
+    assert!(Handshake::respond(&mut rng, &[0; 32], None).is_err());
+}
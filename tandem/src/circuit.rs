@@ -1,4 +1,5 @@
 use blake3::Hasher;
+use serde::{Deserialize, Serialize};
 
 use crate::Error;
 
@@ -6,6 +7,8 @@ use crate::Error;
 pub type GateIndex = u32;
 
 /// A circuit of AND, XOR and NOT gates that can be executed using MPC.
+///
+/// MUX and NAND gates are supported as well, by lowering them to AND, XOR and NOT gates.
 #[derive(Clone, Debug)]
 pub struct Circuit {
     /// A collection of connected gates, each implicitly identified by its index in the vector.
@@ -19,13 +22,35 @@ pub struct Circuit {
     eval_inputs: usize,
     /// number of contributor input bits
     contrib_inputs: usize,
+    /// blake3 hash of the gates and output gates, see [`Circuit::blake3_hash`]
+    hash: CircuitBlake3Hash,
+}
+
+/// The number of gates per type and the input and output widths of a [`Circuit`].
+///
+/// Circuits with different hashes but equal stats have the same shape and only differ in their
+/// wiring, which helps to diagnose why two parties compiled different circuits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitStats {
+    /// Number of AND gates.
+    pub and_gates: usize,
+    /// Number of XOR gates.
+    pub xor_gates: usize,
+    /// Number of NOT gates.
+    pub not_gates: usize,
+    /// Number of input bits by the contributor party.
+    pub contrib_inputs: usize,
+    /// Number of input bits by the evaluator party.
+    pub eval_inputs: usize,
+    /// Number of output bits.
+    pub outputs: usize,
 }
 
 /// A blake3 hash that can be used to compare circuits for equality.
 pub type CircuitBlake3Hash = [u8; 32];
 
-const MAX_GATES: usize = (u32::MAX >> 4) as usize;
-const MAX_AND_GATES: usize = (u32::MAX >> 8) as usize;
+pub(crate) const MAX_GATES: usize = (u32::MAX >> 4) as usize;
+pub(crate) const MAX_AND_GATES: usize = (u32::MAX >> 8) as usize;
 
 impl Circuit {
     /// the gates of the circuit
@@ -50,7 +75,15 @@ impl Circuit {
     }
 
     /// create new circuit from a collection of gates and a collection of output gate indexes
+    ///
+    /// MUX and NAND gates are lowered to AND, XOR and NOT gates, which shifts the indexes of all
+    /// subsequent gates (and the output gates) accordingly.
     pub fn new(gates: Vec<Gate>, output_gates: Vec<GateIndex>) -> Self {
+        let (gates, output_gates) = if gates.iter().any(Gate::is_composite) {
+            lower_composite_gates(gates, output_gates)
+        } else {
+            (gates, output_gates)
+        };
         let mut and_gates = 0;
         let mut eval_inputs = 0;
         let mut contrib_inputs = 0;
@@ -64,25 +97,138 @@ impl Circuit {
             }
         }
 
+        let hash = hash_gates(&gates, &output_gates);
         Self {
             gates,
             output_gates,
             and_gates,
             eval_inputs,
             contrib_inputs,
+            hash,
         }
     }
 
-    /// Calculates the blake3 hash of the circuit.
-    pub fn blake3_hash(&self) -> CircuitBlake3Hash {
-        let mut hasher = blake3::Hasher::new();
+    /// Fuses this circuit with another circuit into a single circuit, by connecting outputs of this
+    /// circuit to inputs of the other circuit.
+    ///
+    /// Each `(output, input)` pair of the wiring connects the `output`-th output of this circuit
+    /// to the [`Gate::InContrib`] or [`Gate::InEval`] gate at index `input` of the other circuit.
+    /// The inputs of the composed circuit are the inputs of this circuit, followed by all inputs
+    /// of the other circuit that are not wired. The outputs of the composed circuit are the
+    /// outputs of the other circuit, so that intermediate values are never revealed.
+    pub fn compose(
+        &self,
+        other: &Circuit,
+        wiring: &[(usize, GateIndex)],
+    ) -> Result<Circuit, Error> {
+        let mut wired = vec![None; other.gates.len()];
+        for &(output, input) in wiring {
+            let output = *self
+                .output_gates
+                .get(output)
+                .ok_or(Error::InvalidCompositionWiring)?;
+            match other.gates.get(input as usize) {
+                Some(Gate::InContrib | Gate::InEval) if wired[input as usize].is_none() => {
+                    wired[input as usize] = Some(output)
+                }
+                _ => return Err(Error::InvalidCompositionWiring),
+            }
+        }
+
+        let mut gates = self.gates.clone();
+        gates.reserve(other.gates.len());
+        // maps the index of each gate of the other circuit to its index in the composed circuit:
+        let mut wires: Vec<GateIndex> = Vec::with_capacity(other.gates.len());
+        let wire = |wires: &[GateIndex], w: GateIndex| {
+            wires.get(w as usize).copied().unwrap_or(GateIndex::MAX)
+        };
+        for (gate, wired) in other.gates.iter().zip(wired) {
+            if let Some(output) = wired {
+                wires.push(output);
+                continue;
+            }
+            gates.push(match *gate {
+                Gate::InContrib | Gate::InEval => gate.clone(),
+                Gate::Xor(x, y) => Gate::Xor(wire(&wires, x), wire(&wires, y)),
+                Gate::And(x, y) => Gate::And(wire(&wires, x), wire(&wires, y)),
+                Gate::Not(x) => Gate::Not(wire(&wires, x)),
+                Gate::Mux(s, x, y) => Gate::Mux(wire(&wires, s), wire(&wires, x), wire(&wires, y)),
+                Gate::Nand(x, y) => Gate::Nand(wire(&wires, x), wire(&wires, y)),
+            });
+            wires.push(gates.len() as GateIndex - 1);
+        }
+        let output_gates = other
+            .output_gates
+            .iter()
+            .map(|&o| wire(&wires, o))
+            .collect();
+
+        let circuit = Circuit::new(gates, output_gates);
+        circuit.validate()?;
+        Ok(circuit)
+    }
+
+    /// Counts the gates per type and the input and output bits of the circuit.
+    pub fn stats(&self) -> CircuitStats {
+        let mut stats = CircuitStats {
+            and_gates: self.and_gates,
+            contrib_inputs: self.contrib_inputs,
+            eval_inputs: self.eval_inputs,
+            outputs: self.output_gates.len(),
+            ..Default::default()
+        };
         for gate in self.gates.iter() {
-            gate.update_hash(&mut hasher);
+            match gate {
+                Gate::Xor(_, _) => stats.xor_gates += 1,
+                Gate::Not(_) => stats.not_gates += 1,
+                _ => {}
+            }
         }
-        for output_gate in self.output_gates.iter() {
-            hasher.update(&output_gate.to_be_bytes());
+        stats
+    }
+
+    /// Returns the maximum number of (non-input) gates on any path from an input to an output.
+    pub fn depth(&self) -> usize {
+        self.longest_path(|_| true)
+    }
+
+    /// Returns the maximum number of AND gates on any path from an input to an output.
+    pub fn and_depth(&self) -> usize {
+        self.longest_path(Gate::is_and)
+    }
+
+    fn longest_path(&self, counts: impl Fn(&Gate) -> bool) -> usize {
+        let mut depths: Vec<usize> = Vec::with_capacity(self.gates.len());
+        for gate in self.gates.iter() {
+            let wires = match *gate {
+                Gate::InContrib | Gate::InEval => {
+                    depths.push(0);
+                    continue;
+                }
+                Gate::Xor(x, y) | Gate::And(x, y) | Gate::Nand(x, y) => vec![x, y],
+                Gate::Not(x) => vec![x],
+                Gate::Mux(s, x, y) => vec![s, x, y],
+            };
+            let depth = wires
+                .into_iter()
+                .map(|w| depths.get(w as usize).copied().unwrap_or(0))
+                .max()
+                .unwrap_or(0);
+            depths.push(depth + counts(gate) as usize);
         }
-        *hasher.finalize().as_bytes()
+        self.output_gates
+            .iter()
+            .map(|&o| depths.get(o as usize).copied().unwrap_or(0))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the blake3 hash of the circuit, which is calculated once when it is constructed.
+    ///
+    /// Circuits can be hashed while they are generated, without building them, using a
+    /// [`CircuitHasher`].
+    pub fn blake3_hash(&self) -> CircuitBlake3Hash {
+        self.hash
     }
 
     /// Performs a syntax check of the circuit.
@@ -93,74 +239,113 @@ impl Circuit {
     ///   - the output gate indexes do not occur in the circuit
     ///   - the number of gates exceeds the maximum number supported
     ///   - the number of AND gates exceeds the maximum number supported
+    ///
+    /// Circuits that are executed in many sessions can be validated once using [`ValidatedCircuit`].
     pub fn validate(&self) -> Result<(), Error> {
-        let mut num_and_gates = 0;
-        for (i, g) in self.gates.iter().enumerate() {
-            let i = i as u32;
-            match g {
-                Gate::InContrib | Gate::InEval => {}
-                &Gate::Xor(x, y) => {
-                    if x >= i || y >= i {
-                        return Err(Error::InvalidCircuit);
-                    }
-                }
-                &Gate::And(x, y) => {
-                    if x >= i || y >= i {
-                        return Err(Error::InvalidCircuit);
-                    }
-                    num_and_gates += 1;
-                }
-                &Gate::Not(x) => {
-                    if x >= i {
-                        return Err(Error::InvalidCircuit);
-                    }
-                }
-            }
-        }
-        if self.output_gates.is_empty() {
-            return Err(Error::InvalidCircuit);
-        }
-        for &o in self.output_gates.iter() {
-            if o >= self.gates.len() as u32 {
-                return Err(Error::InvalidCircuit);
-            }
-        }
-        if num_and_gates > MAX_AND_GATES {
-            return Err(Error::MaxCircuitSizeExceeded);
-        }
-        if self.gates.len() > MAX_GATES {
-            return Err(Error::MaxCircuitSizeExceeded);
-        }
-        Ok(())
+        crate::source::validate(self)
     }
+}
 
-    pub(crate) fn validate_contributor_input(&self, input: &[bool]) -> Result<(), Error> {
-        if self
-            .gates
-            .iter()
-            .filter(|g| matches!(g, Gate::InContrib))
-            .count()
-            == input.len()
-        {
-            Ok(())
-        } else {
-            Err(Error::InsufficientInput)
-        }
+/// A [`Circuit`] that passed [`Circuit::validate`] when it was constructed.
+///
+/// The circuit cannot be modified afterwards, so that [`crate::states::Contributor`] and
+/// [`crate::states::Evaluator`] (which validate all other circuits during their initialization)
+/// do not need to walk all of its gates again, which is noticeable for large circuits that are
+/// executed in many sessions.
+#[derive(Clone, Debug)]
+pub struct ValidatedCircuit(Circuit);
+
+impl ValidatedCircuit {
+    /// Validates the circuit, see [`Circuit::validate`].
+    pub fn new(circuit: Circuit) -> Result<Self, Error> {
+        circuit.validate()?;
+        Ok(Self(circuit))
     }
 
-    pub(crate) fn validate_evaluator_input(&self, input: &[bool]) -> Result<(), Error> {
-        if self
-            .gates
-            .iter()
-            .filter(|g| matches!(g, Gate::InEval))
-            .count()
-            == input.len()
-        {
-            Ok(())
-        } else {
-            Err(Error::InsufficientInput)
+    /// Returns the (unvalidated) circuit.
+    pub fn into_inner(self) -> Circuit {
+        self.0
+    }
+}
+
+impl TryFrom<Circuit> for ValidatedCircuit {
+    type Error = Error;
+
+    fn try_from(circuit: Circuit) -> Result<Self, Error> {
+        Self::new(circuit)
+    }
+}
+
+impl std::ops::Deref for ValidatedCircuit {
+    type Target = Circuit;
+
+    fn deref(&self) -> &Circuit {
+        &self.0
+    }
+}
+
+impl AsRef<Circuit> for ValidatedCircuit {
+    fn as_ref(&self) -> &Circuit {
+        &self.0
+    }
+}
+
+/// Incrementally calculates the [`Circuit::blake3_hash`] of a circuit while its gates are being
+/// generated, for example to look up a cached circuit before building it:
+///
+/// ```
+/// use tandem::{Circuit, CircuitHasher, Gate};
+///
+/// let gates = vec![Gate::InContrib, Gate::InEval, Gate::Nand(0, 1)];
+/// let mut hasher = CircuitHasher::new();
+/// for gate in gates.iter() {
+///     hasher.update(gate);
+/// }
+/// let hash = hasher.finalize(&[2]);
+/// assert_eq!(hash, Circuit::new(gates, vec![2]).blake3_hash());
+/// ```
+///
+/// MUX and NAND gates are lowered on the fly just like in [`Circuit::new`], so that the hash is
+/// the hash of the circuit built from the same gates.
+#[derive(Clone, Debug, Default)]
+pub struct CircuitHasher {
+    hasher: Hasher,
+    lowering: Lowering,
+}
+
+impl CircuitHasher {
+    /// Starts hashing a circuit without any gates.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the next gate of the circuit to the hash.
+    pub fn update(&mut self, gate: &Gate) -> &mut Self {
+        let hasher = &mut self.hasher;
+        self.lowering
+            .lower(gate.clone(), |gate| gate.update_hash(hasher));
+        self
+    }
+
+    /// Adds the output gates (using the indexes before lowering) and returns the hash.
+    pub fn finalize(mut self, output_gates: &[GateIndex]) -> CircuitBlake3Hash {
+        for &output_gate in output_gates {
+            let output_gate = self.lowering.wire(output_gate);
+            self.hasher.update(&output_gate.to_be_bytes());
         }
+        *self.hasher.finalize().as_bytes()
+    }
+}
+
+fn hash_gates(gates: &[Gate], output_gates: &[GateIndex]) -> CircuitBlake3Hash {
+    let mut hasher = blake3::Hasher::new();
+    for gate in gates.iter() {
+        gate.update_hash(&mut hasher);
+    }
+    for output_gate in output_gates.iter() {
+        hasher.update(&output_gate.to_be_bytes());
     }
+    *hasher.finalize().as_bytes()
 }
 
 /// A single gate in a larger [`Circuit`].
@@ -176,6 +361,14 @@ pub enum Gate {
     And(GateIndex, GateIndex),
     /// A gate computing the NOT of the specified gate.
     Not(GateIndex),
+    /// A gate selecting the second gate if the first gate is false, otherwise the third gate.
+    ///
+    /// Lowered to 1 AND and 2 XOR gates by [`Circuit::new`].
+    Mux(GateIndex, GateIndex, GateIndex),
+    /// A gate computing the NAND of the two specified gates.
+    ///
+    /// Lowered to 1 AND and 1 NOT gate by [`Circuit::new`].
+    Nand(GateIndex, GateIndex),
 }
 
 impl Gate {
@@ -184,6 +377,11 @@ impl Gate {
         matches!(self, Gate::And { .. })
     }
 
+    #[inline]
+    fn is_composite(&self) -> bool {
+        matches!(self, Gate::Mux { .. } | Gate::Nand { .. })
+    }
+
     pub(crate) fn update_hash(&self, hasher: &mut Hasher) {
         let type_byte = match self {
             Gate::InContrib => 0,
@@ -202,7 +400,81 @@ impl Gate {
                 hasher.update(&x.to_be_bytes());
                 4
             }
+            Gate::Mux(s, x, y) => {
+                hasher.update(&s.to_be_bytes());
+                hasher.update(&x.to_be_bytes());
+                hasher.update(&y.to_be_bytes());
+                5
+            }
+            Gate::Nand(x, y) => {
+                hasher.update(&x.to_be_bytes());
+                hasher.update(&y.to_be_bytes());
+                6
+            }
         };
         hasher.update(&[type_byte]);
     }
 }
+
+/// Replaces all MUX and NAND gates with equivalent AND, XOR and NOT gates.
+///
+/// References to wires that do not precede the gate (or do not exist at all) are mapped to an
+/// invalid index, so that the lowered circuit is still rejected by [`Circuit::validate`].
+fn lower_composite_gates(
+    gates: Vec<Gate>,
+    output_gates: Vec<GateIndex>,
+) -> (Vec<Gate>, Vec<GateIndex>) {
+    let mut lowered = Vec::with_capacity(gates.len());
+    let mut lowering = Lowering::default();
+    for gate in gates {
+        lowering.lower(gate, |gate| lowered.push(gate));
+    }
+    let output_gates = output_gates.into_iter().map(|o| lowering.wire(o)).collect();
+    (lowered, output_gates)
+}
+
+/// Lowers composite gates one gate at a time.
+#[derive(Clone, Debug, Default)]
+struct Lowering {
+    /// maps the index of each original gate to the index of its output wire after lowering
+    wires: Vec<GateIndex>,
+    /// the number of gates after lowering
+    lowered: GateIndex,
+}
+
+impl Lowering {
+    fn wire(&self, w: GateIndex) -> GateIndex {
+        self.wires
+            .get(w as usize)
+            .copied()
+            .unwrap_or(GateIndex::MAX)
+    }
+
+    fn lower(&mut self, gate: Gate, mut push: impl FnMut(Gate)) {
+        let next = self.lowered;
+        let mut pushed = 0;
+        let mut push = |gate| {
+            pushed += 1;
+            push(gate)
+        };
+        match gate {
+            Gate::InContrib | Gate::InEval => push(gate),
+            Gate::Xor(x, y) => push(Gate::Xor(self.wire(x), self.wire(y))),
+            Gate::And(x, y) => push(Gate::And(self.wire(x), self.wire(y))),
+            Gate::Not(x) => push(Gate::Not(self.wire(x))),
+            Gate::Mux(s, x, y) => {
+                // x ^ (s & (x ^ y))
+                let (s, x, y) = (self.wire(s), self.wire(x), self.wire(y));
+                push(Gate::Xor(x, y));
+                push(Gate::And(s, next));
+                push(Gate::Xor(x, next + 1));
+            }
+            Gate::Nand(x, y) => {
+                push(Gate::And(self.wire(x), self.wire(y)));
+                push(Gate::Not(next));
+            }
+        }
+        self.lowered += pushed;
+        self.wires.push(self.lowered - 1);
+    }
+}
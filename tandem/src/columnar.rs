@@ -0,0 +1,284 @@
+//! A compact columnar on-disk layout of circuits, which can be accessed without decoding the whole
+//! circuit into memory.
+//!
+//! All numbers are stored in little-endian byte order. The layout consists of:
+//!
+//!   1. a header with the magic bytes `TNDMCOL\0`, the format version (`u32`), 4 reserved bytes,
+//!      followed by the number of gates, output gates, AND gates, evaluator inputs and contributor
+//!      inputs (each as `u64`)
+//!   2. the gate kinds, as 1 byte per gate
+//!   3. the first operand of each gate, as `u32` per gate (or `0` if the gate has no operand)
+//!   4. the second operand of each gate, as `u32` per gate (or `0` if the gate has no second
+//!      operand)
+//!   5. the output gates, as `u32` per output gate
+
+use std::io::{self, Write};
+
+use crate::{source, Circuit, CircuitSource, Gate, GateIndex};
+
+const MAGIC: &[u8; 8] = b"TNDMCOL\0";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 8 + 4 + 4 + 5 * 8;
+
+const IN_CONTRIB: u8 = 0;
+const IN_EVAL: u8 = 1;
+const XOR: u8 = 2;
+const AND: u8 = 3;
+const NOT: u8 = 4;
+
+impl Circuit {
+    /// Writes the circuit in the columnar layout that can be read using [`ColumnarCircuit`].
+    pub fn write_columnar(&self, mut writer: impl Write) -> io::Result<()> {
+        let gates = self.gates();
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&[0; 4])?;
+        for n in [
+            gates.len(),
+            self.output_gates().len(),
+            self.and_gates(),
+            self.eval_inputs(),
+            self.contrib_inputs(),
+        ] {
+            writer.write_all(&(n as u64).to_le_bytes())?;
+        }
+
+        let mut kinds = Vec::with_capacity(gates.len());
+        for gate in gates {
+            kinds.push(match gate {
+                Gate::InContrib => IN_CONTRIB,
+                Gate::InEval => IN_EVAL,
+                Gate::Xor(..) => XOR,
+                Gate::And(..) => AND,
+                Gate::Not(..) => NOT,
+                // only reachable if the gates were not lowered, which `Circuit::new` prevents:
+                Gate::Mux(..) | Gate::Nand(..) => {
+                    return Err(invalid_data(crate::Error::InvalidCircuit))
+                }
+            });
+        }
+        writer.write_all(&kinds)?;
+        let mut column = Vec::with_capacity(gates.len() * 4);
+        for gate in gates {
+            let x = match gate {
+                Gate::Xor(x, _) | Gate::And(x, _) | Gate::Not(x) => *x,
+                _ => 0,
+            };
+            column.extend_from_slice(&x.to_le_bytes());
+        }
+        writer.write_all(&column)?;
+        column.clear();
+        for gate in gates {
+            let y = match gate {
+                Gate::Xor(_, y) | Gate::And(_, y) => *y,
+                _ => 0,
+            };
+            column.extend_from_slice(&y.to_le_bytes());
+        }
+        writer.write_all(&column)?;
+        column.clear();
+        for output in self.output_gates() {
+            column.extend_from_slice(&output.to_le_bytes());
+        }
+        writer.write_all(&column)
+    }
+}
+
+/// A circuit in the columnar layout (see [`Circuit::write_columnar`]), whose gates are decoded on
+/// access instead of being loaded into memory.
+///
+/// The bytes can be provided by any buffer, such as a `Vec<u8>` or (with the `mmap` feature) a
+/// memory-mapped file, see `MappedCircuit`.
+#[derive(Debug, Clone)]
+pub struct ColumnarCircuit<B> {
+    bytes: B,
+    num_gates: usize,
+    output_gates: Vec<GateIndex>,
+    and_gates: usize,
+    eval_inputs: usize,
+    contrib_inputs: usize,
+}
+
+impl<B: AsRef<[u8]>> ColumnarCircuit<B> {
+    /// Reads the header and the output gates of the circuit and validates all gates (see
+    /// [`Circuit::validate`]), without copying the gates.
+    pub fn new(bytes: B) -> io::Result<Self> {
+        let buf = bytes.as_ref();
+        if buf.len() < HEADER_LEN || &buf[..8] != MAGIC {
+            return Err(invalid_data("not a columnar circuit"));
+        }
+        if read_u32(buf, 8) != VERSION {
+            return Err(invalid_data(
+                "unsupported version of the columnar circuit layout",
+            ));
+        }
+        let mut header = [0; 5];
+        for (i, n) in header.iter_mut().enumerate() {
+            let offset = 16 + i * 8;
+            let mut n_bytes = [0; 8];
+            n_bytes.copy_from_slice(&buf[offset..offset + 8]);
+            *n = usize::try_from(u64::from_le_bytes(n_bytes))
+                .map_err(|_| invalid_data("columnar circuit is too large"))?;
+        }
+        let [num_gates, num_outputs, and_gates, eval_inputs, contrib_inputs] = header;
+        let expected_len = num_gates
+            .checked_mul(9)
+            .zip(num_outputs.checked_mul(4))
+            .and_then(|(gates, outputs)| gates.checked_add(outputs))
+            .and_then(|len| len.checked_add(HEADER_LEN));
+        if expected_len != Some(buf.len()) {
+            return Err(invalid_data("unexpected length of the columnar circuit"));
+        }
+        let outputs_offset = HEADER_LEN + num_gates * 9;
+        let output_gates = (0..num_outputs)
+            .map(|i| read_u32(buf, outputs_offset + i * 4))
+            .collect();
+
+        let circuit = Self {
+            bytes,
+            num_gates,
+            output_gates,
+            and_gates,
+            eval_inputs,
+            contrib_inputs,
+        };
+        source::validate(&circuit).map_err(invalid_data)?;
+        Ok(circuit)
+    }
+
+    /// Decodes all gates into a [`Circuit`] that is fully loaded into memory.
+    pub fn to_circuit(&self) -> Circuit {
+        Circuit::new(self.iter_gates().collect(), self.output_gates.clone())
+    }
+}
+
+impl<B: AsRef<[u8]>> CircuitSource for ColumnarCircuit<B> {
+    fn num_gates(&self) -> usize {
+        self.num_gates
+    }
+
+    fn gate(&self, index: usize) -> Option<Gate> {
+        if index >= self.num_gates {
+            return None;
+        }
+        let buf = self.bytes.as_ref();
+        let x = || read_u32(buf, HEADER_LEN + self.num_gates + index * 4);
+        let y = || read_u32(buf, HEADER_LEN + self.num_gates * 5 + index * 4);
+        match buf[HEADER_LEN + index] {
+            IN_CONTRIB => Some(Gate::InContrib),
+            IN_EVAL => Some(Gate::InEval),
+            XOR => Some(Gate::Xor(x(), y())),
+            AND => Some(Gate::And(x(), y())),
+            NOT => Some(Gate::Not(x())),
+            // decoded as a gate that is never valid, so that the circuit is rejected:
+            _ => Some(Gate::Mux(GateIndex::MAX, GateIndex::MAX, GateIndex::MAX)),
+        }
+    }
+
+    fn output_gates(&self) -> &[GateIndex] {
+        &self.output_gates
+    }
+
+    fn and_gates(&self) -> usize {
+        self.and_gates
+    }
+
+    fn eval_inputs(&self) -> usize {
+        self.eval_inputs
+    }
+
+    fn contrib_inputs(&self) -> usize {
+        self.contrib_inputs
+    }
+}
+
+impl<B: AsRef<[u8]>> CircuitSource for &ColumnarCircuit<B> {
+    fn num_gates(&self) -> usize {
+        (*self).num_gates()
+    }
+
+    fn gate(&self, index: usize) -> Option<Gate> {
+        (*self).gate(index)
+    }
+
+    fn output_gates(&self) -> &[GateIndex] {
+        (*self).output_gates()
+    }
+
+    fn and_gates(&self) -> usize {
+        (*self).and_gates()
+    }
+
+    fn eval_inputs(&self) -> usize {
+        (*self).eval_inputs()
+    }
+
+    fn contrib_inputs(&self) -> usize {
+        (*self).contrib_inputs()
+    }
+}
+
+/// A circuit in the columnar layout that is memory-mapped from a file.
+#[cfg(feature = "mmap")]
+pub type MappedCircuit = ColumnarCircuit<memmap2::Mmap>;
+
+#[cfg(feature = "mmap")]
+impl ColumnarCircuit<memmap2::Mmap> {
+    /// Memory-maps the circuit file, which must have been written using
+    /// [`Circuit::write_columnar`] and must not be modified while it is mapped.
+    #[allow(unsafe_code)]
+    pub fn open(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        // SAFETY: the file is treated as read-only and must not be modified while it is mapped,
+        // as documented above. All gates are validated before the circuit is returned.
+        let mmap = unsafe { memmap2::Mmap::map(&file)? };
+        Self::new(mmap)
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[test]
+fn test_columnar_roundtrip() {
+    let circuit = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::Mux(0, 1, 0),
+            Gate::Nand(2, 1),
+            Gate::Not(3),
+        ],
+        vec![2, 4],
+    );
+    let mut bytes = vec![];
+    circuit.write_columnar(&mut bytes).unwrap();
+
+    let columnar = ColumnarCircuit::new(bytes.as_slice()).unwrap();
+    assert_eq!(columnar.num_gates(), circuit.gates().len());
+    assert_eq!(columnar.iter_gates().collect::<Vec<_>>(), *circuit.gates());
+    assert_eq!(
+        CircuitSource::output_gates(&columnar),
+        circuit.output_gates()
+    );
+    assert_eq!(columnar.gate(circuit.gates().len()), None);
+    assert_eq!(columnar.to_circuit().blake3_hash(), circuit.blake3_hash());
+
+    assert!(ColumnarCircuit::new(&bytes[..bytes.len() - 1]).is_err());
+    let mut invalid_kind = bytes.clone();
+    invalid_kind[HEADER_LEN + 2] = 42;
+    assert!(ColumnarCircuit::new(invalid_kind).is_err());
+    let mut cyclic = bytes.clone();
+    cyclic[HEADER_LEN + circuit.gates().len() + 2 * 4] = 9;
+    assert!(ColumnarCircuit::new(cyclic).is_err());
+    let mut wrong_count = bytes;
+    wrong_count[16 + 2 * 8] += 1;
+    assert!(ColumnarCircuit::new(wrong_count).is_err());
+}
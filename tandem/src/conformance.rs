@@ -0,0 +1,221 @@
+//! Deterministic test vectors of the wire protocol, for implementations of compatible parties.
+//!
+//! [`generate`] runs the protocol for each of the [`reference_circuits`] with both parties seeded
+//! from a fixed seed, which yields the same [`TestVector`]s (and thus the same messages, byte for
+//! byte) on every host. Changing any message in an incompatible way changes the
+//! [`TestVector::digest`] of the vectors generated from [`CONFORMANCE_SEED`], which are checked
+//! against golden digests by the tests of this crate and must only change together with the
+//! [`crate::PROTOCOL_VERSION`].
+//!
+//! Third party implementations can replay the recorded messages of the other party and compare
+//! their own messages against the recorded ones, just like [`TestVector::validate`] does.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    replay,
+    states::{Contributor, Evaluator},
+    Circuit, Error, Gate, GateIndex, Transcript, TranscriptRecorder,
+};
+
+/// The seed of the published test vectors.
+pub const CONFORMANCE_SEED: [u8; 32] = *b"tandem conformance test vectors!";
+
+/// A small circuit with fixed inputs, whose execution is recorded in a [`TestVector`].
+#[derive(Debug, Clone)]
+pub struct ReferenceCircuit {
+    /// The name of the circuit, which identifies it across releases.
+    pub name: &'static str,
+    /// The circuit.
+    pub circuit: Circuit,
+    /// The input bits of the contributor.
+    pub contributor_input: Vec<bool>,
+    /// The input bits of the evaluator.
+    pub evaluator_input: Vec<bool>,
+    /// The output bits of the circuit for these inputs.
+    pub output: Vec<bool>,
+}
+
+/// Returns the circuits recorded by [`generate`], in the order of the generated vectors.
+///
+/// The circuits cover single AND, XOR and NOT gates as well as a 4-bit ripple carry adder, whose
+/// AND gates depend on each other.
+pub fn reference_circuits() -> Vec<ReferenceCircuit> {
+    vec![
+        ReferenceCircuit {
+            name: "and",
+            circuit: Circuit::new(
+                vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+                vec![2],
+            ),
+            contributor_input: vec![true],
+            evaluator_input: vec![true],
+            output: vec![true],
+        },
+        ReferenceCircuit {
+            name: "xor_not",
+            circuit: Circuit::new(
+                vec![Gate::InContrib, Gate::InEval, Gate::Xor(0, 1), Gate::Not(2)],
+                vec![2, 3],
+            ),
+            contributor_input: vec![true],
+            evaluator_input: vec![false],
+            output: vec![true, false],
+        },
+        ReferenceCircuit {
+            name: "add_4bit",
+            circuit: ripple_carry_adder(4),
+            // 5 + 6 = 11, least significant bit first:
+            contributor_input: vec![true, false, true, false],
+            evaluator_input: vec![false, true, true, false],
+            output: vec![true, true, false, true],
+        },
+    ]
+}
+
+/// Adds the `bits`-bit numbers of contributor and evaluator, ignoring the final carry.
+fn ripple_carry_adder(bits: u32) -> Circuit {
+    let mut gates = vec![Gate::InContrib; bits as usize];
+    gates.extend(vec![Gate::InEval; bits as usize]);
+    let mut push = |gate: Gate| -> GateIndex {
+        gates.push(gate);
+        gates.len() as GateIndex - 1
+    };
+    let mut output_gates = vec![];
+    let mut carry = None;
+    for i in 0..bits {
+        let (a, b) = (i, bits + i);
+        let sum = push(Gate::Xor(a, b));
+        let and = push(Gate::And(a, b));
+        carry = Some(match carry {
+            None => {
+                output_gates.push(sum);
+                and
+            }
+            Some(carry) => {
+                output_gates.push(push(Gate::Xor(sum, carry)));
+                let propagated = push(Gate::And(sum, carry));
+                push(Gate::Xor(and, propagated))
+            }
+        });
+    }
+    Circuit::new(gates, output_gates)
+}
+
+/// The recorded execution of a [`ReferenceCircuit`] by both parties.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TestVector {
+    /// The name of the [`ReferenceCircuit`].
+    pub name: String,
+    /// The input bits of the contributor.
+    pub contributor_input: Vec<bool>,
+    /// The input bits of the evaluator.
+    pub evaluator_input: Vec<bool>,
+    /// The output bits learned by the evaluator.
+    pub output: Vec<bool>,
+    /// The transcript of the contributor.
+    pub contributor: Transcript,
+    /// The transcript of the evaluator.
+    pub evaluator: Transcript,
+}
+
+/// Runs the protocol for all [`reference_circuits`], seeding the RNG of both parties from the
+/// seed (the contributor using stream 0, the evaluator using stream 1).
+pub fn generate(seed: [u8; 32]) -> Result<Vec<TestVector>, Error> {
+    reference_circuits()
+        .into_iter()
+        .map(|reference| record(reference, seed))
+        .collect()
+}
+
+fn record(reference: ReferenceCircuit, seed: [u8; 32]) -> Result<TestVector, Error> {
+    let rng = |stream| {
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        rng.set_stream(stream);
+        rng
+    };
+    let contrib_recorder = TranscriptRecorder::new();
+    let eval_recorder = TranscriptRecorder::new();
+    let circuit = &reference.circuit;
+    let (mut contrib, mut msg) = Contributor::new_with_transcript(
+        circuit,
+        &reference.contributor_input[..],
+        rng(0),
+        &contrib_recorder,
+    )?;
+    let mut eval = Evaluator::new_with_transcript(
+        circuit,
+        &reference.evaluator_input[..],
+        rng(1),
+        &eval_recorder,
+    )?;
+    for _ in 0..eval.steps() {
+        let (next_state, reply) = eval.run(&msg)?;
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&reply)?;
+        contrib = next_state;
+        msg = reply;
+    }
+    let output = eval.output(&msg)?;
+    if output != reference.output {
+        return Err(Error::TranscriptMismatch);
+    }
+    match (contrib_recorder.transcript(), eval_recorder.transcript()) {
+        (Some(contributor), Some(evaluator)) => Ok(TestVector {
+            name: reference.name.to_string(),
+            contributor_input: reference.contributor_input,
+            evaluator_input: reference.evaluator_input,
+            output,
+            contributor,
+            evaluator,
+        }),
+        _ => Err(Error::TranscriptMismatch),
+    }
+}
+
+impl TestVector {
+    /// Returns the [`ReferenceCircuit`] of the vector, if it is known to this version of the crate.
+    pub fn reference_circuit(&self) -> Option<ReferenceCircuit> {
+        reference_circuits()
+            .into_iter()
+            .find(|reference| reference.name == self.name)
+    }
+
+    /// Checks that the messages of both transcripts match each other and that both parties send
+    /// exactly the recorded messages when they are replayed, see [`crate::replay`].
+    pub fn validate(&self) -> Result<(), Error> {
+        let reference = self.reference_circuit().ok_or(Error::TranscriptMismatch)?;
+        if self.contributor.sent != self.evaluator.received
+            || self.evaluator.sent != self.contributor.received
+        {
+            return Err(Error::TranscriptMismatch);
+        }
+        replay(
+            &reference.circuit,
+            &self.contributor_input,
+            &self.contributor,
+        )?;
+        match replay(&reference.circuit, &self.evaluator_input, &self.evaluator)? {
+            Some(output) if output == self.output => Ok(()),
+            _ => Err(Error::TranscriptMismatch),
+        }
+    }
+
+    /// Returns the hex-encoded blake3 hash of all messages, sent by the contributor first and by
+    /// the evaluator second, each one prefixed with its length as a little-endian `u64`.
+    pub fn digest(&self) -> String {
+        let mut hasher = blake3::Hasher::new();
+        for msg in self
+            .contributor
+            .sent
+            .iter()
+            .chain(self.evaluator.sent.iter())
+        {
+            hasher.update(&(msg.len() as u64).to_le_bytes());
+            hasher.update(msg);
+        }
+        hasher.finalize().to_hex().to_string()
+    }
+}
@@ -0,0 +1,113 @@
+//! Comparisons of secret values and an audit of their timing behavior.
+//!
+//! The comparisons run in constant time (using the `subtle` crate) if the crate is compiled with
+//! the `constant-time` feature and fall back to the regular (possibly short-circuiting)
+//! comparisons otherwise.
+
+use serde::{Deserialize, Serialize};
+
+/// Compares two 128-bit values, such as MACs and keys.
+pub(crate) fn eq_u128(a: u128, b: u128) -> bool {
+    #[cfg(feature = "constant-time")]
+    {
+        subtle::ConstantTimeEq::ct_eq(&a, &b).into()
+    }
+    #[cfg(not(feature = "constant-time"))]
+    {
+        a == b
+    }
+}
+
+/// Compares two byte strings, such as hashes, only leaking whether their lengths differ.
+pub(crate) fn eq_bytes(a: &[u8], b: &[u8]) -> bool {
+    #[cfg(feature = "constant-time")]
+    {
+        subtle::ConstantTimeEq::ct_eq(a, b).into()
+    }
+    #[cfg(not(feature = "constant-time"))]
+    {
+        a == b
+    }
+}
+
+/// An operation of the protocol on secret values, listed by [`timing_audit()`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimingAuditEntry {
+    /// Name of the operation.
+    pub operation: String,
+    /// Whether the running time of the operation is independent of the secret values in this
+    /// build of the crate.
+    pub constant_time: bool,
+    /// What the operation does and why it is (or is not) constant-time.
+    pub description: String,
+}
+
+/// Lists the operations of the protocol on secret values and whether they run in constant time.
+///
+/// MAC checks, the equality check of the leaky AND triples and the verification of the coin
+/// commitments are only constant-time if the crate is compiled with the `constant-time` feature
+/// (see [`crate::CONSTANT_TIME`]). The remaining entries stay variable-time in all builds and
+/// document where the timing of the protocol depends on the messages of the other party.
+pub fn timing_audit() -> Vec<TimingAuditEntry> {
+    let ct = cfg!(feature = "constant-time");
+    let entries = [
+        (
+            "mac_verification",
+            ct,
+            "Checks the MAC of an authenticated bit received from the other party.",
+        ),
+        (
+            "leaky_and_check",
+            ct,
+            "Compares the hashes and keys opened during the equality check of the leaky AND \
+             triples, accumulating the result over all triples.",
+        ),
+        (
+            "coin_commitment",
+            ct,
+            "Compares the coin share of the other party against its commitment.",
+        ),
+        (
+            "mac_failure_abort",
+            false,
+            "Aborts at the first input share whose MAC check fails, which reveals the position \
+             of the share to a party measuring the time until the abort.",
+        ),
+        (
+            "message_deserialization",
+            false,
+            "Deserializes the messages of the other party, depending only on their (public) \
+             lengths and structure.",
+        ),
+    ];
+    entries
+        .into_iter()
+        .map(|(operation, constant_time, description)| TimingAuditEntry {
+            operation: operation.to_string(),
+            constant_time,
+            description: description.to_string(),
+        })
+        .collect()
+}
+
+/// Checks that the comparisons of secret values return the same results as regular comparisons.
+pub(crate) fn matches_regular_comparisons() -> bool {
+    let values = [0, 1, 1 << 127, u128::MAX];
+    let u128_ok = values
+        .iter()
+        .all(|&a| values.iter().all(|&b| eq_u128(a, b) == (a == b)));
+    let bytes: [&[u8]; 4] = [b"", b"tandem", b"tandem!", b"tandeM"];
+    let bytes_ok = bytes
+        .iter()
+        .all(|a| bytes.iter().all(|b| eq_bytes(a, b) == (a == b)));
+    u128_ok && bytes_ok
+}
+
+#[test]
+fn test_timing_audit() {
+    let audit = timing_audit();
+    assert_eq!(audit.len(), 5);
+    let mac = audit.iter().find(|e| e.operation == "mac_verification");
+    assert_eq!(mac.map(|e| e.constant_time), Some(crate::CONSTANT_TIME));
+    assert!(matches_regular_comparisons());
+}
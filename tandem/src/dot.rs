@@ -0,0 +1,97 @@
+//! Export of circuits in the [DOT](https://graphviz.org/doc/info/lang.html) format of Graphviz.
+
+use std::fmt::Write;
+
+use crate::{Circuit, Gate, GateIndex};
+
+impl Circuit {
+    /// Renders the circuit as a Graphviz DOT graph, for visualization and debugging.
+    ///
+    /// Only the first `max_gates` gates (and the outputs connected to them) are rendered, as the
+    /// layout of larger graphs quickly becomes unreadable. Input gates are drawn as boxes, outputs
+    /// as double circles.
+    pub fn to_dot(&self, max_gates: usize) -> String {
+        self.to_dot_with_labels(max_gates, &[], &[])
+    }
+
+    /// Renders the circuit as a Graphviz DOT graph like [`Circuit::to_dot`], additionally
+    /// labelling the `i`-th input gate (of either party, in the order of the gates) with
+    /// `input_labels[i]` and the `i`-th output with `output_labels[i]`.
+    ///
+    /// Inputs and outputs without a label are labelled with their party and index.
+    pub fn to_dot_with_labels(
+        &self,
+        max_gates: usize,
+        input_labels: &[String],
+        output_labels: &[String],
+    ) -> String {
+        let shown = self.gates().len().min(max_gates);
+        let mut dot = String::new();
+        dot.push_str("digraph circuit {\n");
+        dot.push_str("  rankdir=LR;\n");
+        dot.push_str("  node [fontname=\"monospace\"];\n");
+
+        let mut inputs = 0;
+        let mut contrib_inputs = 0;
+        let mut eval_inputs = 0;
+        for (i, gate) in self.gates().iter().take(shown).enumerate() {
+            match *gate {
+                Gate::InContrib | Gate::InEval => {
+                    let (party, color, party_index) = if gate == &Gate::InContrib {
+                        ("contributor", "lightblue", &mut contrib_inputs)
+                    } else {
+                        ("evaluator", "lightyellow", &mut eval_inputs)
+                    };
+                    let label = match input_labels.get(inputs) {
+                        Some(label) => escape(label),
+                        None => format!("{party} {party_index}"),
+                    };
+                    let _ = writeln!(
+                        dot,
+                        "  g{i} [shape=box, style=filled, fillcolor={color}, label=\"{label}\"];"
+                    );
+                    inputs += 1;
+                    *party_index += 1;
+                }
+                Gate::Xor(x, y) => write_gate(&mut dot, i, "XOR", &[x, y], shown),
+                Gate::And(x, y) => write_gate(&mut dot, i, "AND", &[x, y], shown),
+                Gate::Not(x) => write_gate(&mut dot, i, "NOT", &[x], shown),
+                Gate::Mux(s, x, y) => write_gate(&mut dot, i, "MUX", &[s, x, y], shown),
+                Gate::Nand(x, y) => write_gate(&mut dot, i, "NAND", &[x, y], shown),
+            }
+        }
+
+        for (o, &w) in self.output_gates().iter().enumerate() {
+            if (w as usize) >= shown {
+                continue;
+            }
+            let label = match output_labels.get(o) {
+                Some(label) => escape(label),
+                None => format!("output {o}"),
+            };
+            let _ = writeln!(dot, "  out{o} [shape=doublecircle, label=\"{label}\"];");
+            let _ = writeln!(dot, "  g{w} -> out{o};");
+        }
+
+        if shown < self.gates().len() {
+            let omitted = self.gates().len() - shown;
+            let _ = writeln!(
+                dot,
+                "  omitted [shape=plaintext, label=\"... {omitted} more gates\"];"
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+fn write_gate(dot: &mut String, i: usize, label: &str, wires: &[GateIndex], shown: usize) {
+    let _ = writeln!(dot, "  g{i} [label=\"{label}\"];");
+    for w in wires.iter().filter(|&&w| (w as usize) < shown) {
+        let _ = writeln!(dot, "  g{w} -> g{i};");
+    }
+}
+
+fn escape(label: &str) -> String {
+    label.replace('\\', "\\\\").replace('"', "\\\"")
+}
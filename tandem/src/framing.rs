@@ -0,0 +1,77 @@
+//! Framed messages, which combine the messages of several sub-protocols in a single buffer.
+//!
+//! A framed message is a sequence of sections, each one prefixed with its length as a
+//! little-endian `u64`. This is byte for byte the bincode serialization of a tuple of byte vectors
+//! (such as `(Msg, Msg)`), so framed messages are compatible with peers that still nest bincode
+//! blobs, but the sections of a received message are borrowed from the message instead of being
+//! copied into freshly allocated vectors.
+
+use crate::{states::Msg, Error};
+
+const LEN_PREFIX: usize = std::mem::size_of::<u64>();
+
+/// Concatenates the sections into a single framed message.
+pub(crate) fn frame(sections: &[&[u8]]) -> Msg {
+    let len = sections.iter().map(|s| LEN_PREFIX + s.len()).sum();
+    let mut msg = Vec::with_capacity(len);
+    for section in sections {
+        msg.extend_from_slice(&(section.len() as u64).to_le_bytes());
+        msg.extend_from_slice(section);
+    }
+    msg
+}
+
+/// Splits a framed message into its sections, without copying them.
+///
+/// Like bincode, trailing bytes after the last expected section are ignored.
+pub(crate) struct Frames<'a> {
+    msg: &'a [u8],
+}
+
+impl<'a> Frames<'a> {
+    pub(crate) fn new(msg: &'a [u8]) -> Self {
+        Self { msg }
+    }
+
+    /// Returns the next section, or an error if the message is truncated.
+    pub(crate) fn section(&mut self) -> Result<&'a [u8], Error> {
+        if self.msg.len() < LEN_PREFIX {
+            return Err(Error::BincodeError);
+        }
+        let (prefix, rest) = self.msg.split_at(LEN_PREFIX);
+        let mut len = [0; LEN_PREFIX];
+        len.copy_from_slice(prefix);
+        let len = usize::try_from(u64::from_le_bytes(len)).map_err(|_| Error::BincodeError)?;
+        if rest.len() < len {
+            return Err(Error::BincodeError);
+        }
+        let (section, rest) = rest.split_at(len);
+        self.msg = rest;
+        Ok(section)
+    }
+}
+
+/// Splits a framed message of two sections, the framed equivalent of deserializing `(Msg, Msg)`.
+pub(crate) fn sections2(msg: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    let mut frames = Frames::new(msg);
+    Ok((frames.section()?, frames.section()?))
+}
+
+#[test]
+fn test_framing_is_compatible_with_bincode() {
+    let (a, b) = (vec![1, 2, 3], vec![]);
+    let framed = frame(&[&a, &b]);
+    assert_eq!(framed, bincode::serialize(&(&a, &b)).unwrap());
+    assert_eq!(sections2(&framed).unwrap(), (&a[..], &b[..]));
+
+    let nested: (Msg, Msg, Msg) = bincode::deserialize(&frame(&[&b, &a, &a])).unwrap();
+    assert_eq!(nested, (b.clone(), a.clone(), a.clone()));
+
+    assert_eq!(
+        sections2(&framed[..framed.len() - 1]),
+        Err(Error::BincodeError)
+    );
+    let mut huge = framed.clone();
+    huge[7] = 0xff;
+    assert_eq!(sections2(&huge), Err(Error::BincodeError));
+}
@@ -0,0 +1,198 @@
+//! Abstraction over the storage of input bits, so that very wide inputs do not need to be stored
+//! as one `bool` per byte.
+
+use std::borrow::Borrow;
+
+/// Read access to the input bits of a party, as required by [`crate::states::Contributor`],
+/// [`crate::states::Evaluator`] and the [`crate::semi_honest`] parties.
+///
+/// Implemented for every type that borrows a slice of bools (such as `Vec<bool>`, `&[bool]` or
+/// `[bool; N]`) and for [`InputBits`], which packs 8 input bits into each byte. Custom
+/// implementations can compute or load the bits lazily, the protocol only reads each bit once,
+/// in order.
+pub trait InputSource {
+    /// The number of input bits.
+    fn len(&self) -> usize;
+
+    /// The input bit at the specified index, or `None` if the index is out of bounds.
+    fn bit(&self, index: usize) -> Option<bool>;
+
+    /// Returns `true` if there are no input bits.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Iterates over all input bits, in order.
+    fn bits(&self) -> Bits<'_, Self> {
+        Bits {
+            source: self,
+            index: 0,
+        }
+    }
+}
+
+impl<T: Borrow<[bool]> + ?Sized> InputSource for T {
+    fn len(&self) -> usize {
+        self.borrow().len()
+    }
+
+    fn bit(&self, index: usize) -> Option<bool> {
+        self.borrow().get(index).copied()
+    }
+}
+
+/// Iterator over the bits of an [`InputSource`], see [`InputSource::bits`].
+pub struct Bits<'a, I: ?Sized> {
+    source: &'a I,
+    index: usize,
+}
+
+impl<'a, I: InputSource + ?Sized> Iterator for Bits<'a, I> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        let bit = self.source.bit(self.index)?;
+        self.index += 1;
+        Some(bit)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.source.len().saturating_sub(self.index);
+        (remaining, Some(remaining))
+    }
+}
+
+/// Input bits packed into bytes (least significant bit first), using 1/8 of the memory of a
+/// `Vec<bool>`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct InputBits {
+    bytes: Vec<u8>,
+    len: usize,
+}
+
+impl InputBits {
+    /// Creates an empty sequence of input bits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates an empty sequence of input bits with space for at least `bits` bits.
+    pub fn with_capacity(bits: usize) -> Self {
+        Self {
+            bytes: Vec::with_capacity((bits + 7) / 8),
+            len: 0,
+        }
+    }
+
+    /// Uses the first `len` bits of the bytes as input bits, least significant bit first.
+    ///
+    /// Returns `None` if the bytes contain less than `len` bits.
+    pub fn from_bytes(mut bytes: Vec<u8>, len: usize) -> Option<Self> {
+        if bytes.len() < (len + 7) / 8 {
+            return None;
+        }
+        bytes.truncate((len + 7) / 8);
+        if len % 8 != 0 {
+            if let Some(last) = bytes.last_mut() {
+                *last &= (1 << (len % 8)) - 1;
+            }
+        }
+        Some(Self { bytes, len })
+    }
+
+    /// The packed bytes, with unused bits of the last byte set to 0.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Appends a bit at the end.
+    pub fn push(&mut self, bit: bool) {
+        if self.len % 8 == 0 {
+            self.bytes.push(0);
+        }
+        if bit {
+            self.bytes[self.len / 8] |= 1 << (self.len % 8);
+        }
+        self.len += 1;
+    }
+
+    /// The number of input bits.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if there are no input bits.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The bit at the specified index, or `None` if the index is out of bounds.
+    pub fn get(&self, index: usize) -> Option<bool> {
+        if index < self.len {
+            Some(self.bytes[index / 8] & (1 << (index % 8)) != 0)
+        } else {
+            None
+        }
+    }
+}
+
+impl InputSource for InputBits {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn bit(&self, index: usize) -> Option<bool> {
+        self.get(index)
+    }
+}
+
+impl InputSource for &InputBits {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn bit(&self, index: usize) -> Option<bool> {
+        self.get(index)
+    }
+}
+
+impl FromIterator<bool> for InputBits {
+    fn from_iter<T: IntoIterator<Item = bool>>(iter: T) -> Self {
+        let iter = iter.into_iter();
+        let mut bits = Self::with_capacity(iter.size_hint().0);
+        for bit in iter {
+            bits.push(bit);
+        }
+        bits
+    }
+}
+
+impl From<&[bool]> for InputBits {
+    fn from(bits: &[bool]) -> Self {
+        bits.iter().copied().collect()
+    }
+}
+
+impl From<Vec<bool>> for InputBits {
+    fn from(bits: Vec<bool>) -> Self {
+        bits.into_iter().collect()
+    }
+}
+
+#[test]
+fn test_input_bits() {
+    let bools = [
+        true, false, true, true, false, false, false, false, true, true,
+    ];
+    let bits = InputBits::from(&bools[..]);
+    assert_eq!(bits.len(), 10);
+    assert_eq!(bits.as_bytes(), &[0b0000_1101, 0b11]);
+    assert_eq!(bits.bits().collect::<Vec<_>>(), bools);
+    assert_eq!(bits.get(10), None);
+    assert_eq!(
+        InputBits::from_bytes(vec![0b0000_1101, 0xff], 10),
+        Some(bits)
+    );
+    assert_eq!(InputBits::from_bytes(vec![0xff], 10), None);
+    assert_eq!(bools[..].bits().collect::<Vec<_>>(), bools);
+}
@@ -85,6 +85,7 @@ fn test_leaky_and_hashes() {
 
 /// Generates [`K`]-many authenticated bits.
 #[cfg(test)]
+#[allow(clippy::type_complexity)]
 fn gen_abits() -> (
     Delta,
     KeyType,
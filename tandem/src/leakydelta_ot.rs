@@ -1,124 +1,278 @@
 //! Implements Correlated OT protocols from [ALSZ13].
 //!
 //! Correlated OT uses Base OT to initialize [`K`] many RNGs. Implements WRK17-compatible
-//! optimizations from [ALSZ13] (chapter 5.4)
+//! optimizations from [ALSZ13] (chapter 5.4) and optionally the correlation check from [KOS15].
 //!
 //! [ALSZ13]: <https://eprint.iacr.org/2013/552.pdf>
+//! [KOS15]: <https://eprint.iacr.org/2015/546.pdf>
 
+#[cfg(feature = "post-quantum")]
+use crate::ot_base::pq;
 use crate::{
     ot_base::message::Init as BaseOTInit,
     ot_base::{OtMessage, Receiver as BaseReceiver, Sender as BaseSender},
+    protocol::cointossing::CoinResult,
     types::{Delta, KeyType, MacType, K},
+    BaseOt, Error,
 };
-use rand::{Rng, SeedableRng};
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
 use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
 
 /// Number of bits authenticated in a single run of the OT protocol (i.e. "batch size").
 pub(crate) const BLOCK_SIZE: usize = K;
 
+/// Number of additional batches required by the [KOS15] correlation check.
+///
+/// The additional authenticated bits mask the values disclosed by the receiver during the check
+/// (which requires at least κ + ρ = 168 bits) and are discarded afterwards.
+///
+/// [KOS15]: <https://eprint.iacr.org/2015/546.pdf>
+pub(crate) const CHECK_BLOCKS: usize = 2;
+
 /// Collection of messages exchanged between OT sender and receiver.
 pub(crate) mod message {
     use serde::{Deserialize, Serialize};
 
-    use crate::{ot_base::message::Init, ot_base::message::InitReply, Error};
+    #[cfg(feature = "post-quantum")]
+    use crate::ot_base::pq;
+    use crate::{ot_base::message::Init, ot_base::message::InitReply, BaseOt, Error};
 
+    /// The init messages of the [`BaseOt`] protocol, sent in both directions.
     #[derive(Debug, Clone, PartialEq)]
-    pub struct OtInit(pub(super) Box<[Init; super::K]>);
+    pub enum OtInit {
+        ChouOrlandi(Box<[Init; super::K]>),
+        /// The seeds sent by the senders of [`BaseOt::PostQuantum`].
+        #[cfg(feature = "post-quantum")]
+        PostQuantumSeeds(Box<[pq::message::Init; super::K]>),
+        /// The keys sent by the receivers of [`BaseOt::PostQuantum`] in reply to the seeds.
+        #[cfg(feature = "post-quantum")]
+        PostQuantumKeys(Vec<pq::message::Keys>),
+    }
 
     #[derive(Debug, Clone, PartialEq)]
-    pub struct OtInitReply(pub(super) Box<[InitReply; super::K]>);
+    pub enum OtInitReply {
+        ChouOrlandi(Box<[InitReply; super::K]>),
+        #[cfg(feature = "post-quantum")]
+        PostQuantum(Vec<pq::message::InitReply>),
+    }
 
     #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-    pub struct SerializedOtInit(Vec<u8>);
+    pub enum SerializedOtInit {
+        ChouOrlandi(Vec<u8>),
+        #[cfg(feature = "post-quantum")]
+        PostQuantumSeeds(Vec<u8>),
+        #[cfg(feature = "post-quantum")]
+        PostQuantumKeys(Vec<u8>),
+    }
 
     impl OtInit {
         pub fn serialize(&self) -> SerializedOtInit {
-            let mut buffer = Vec::with_capacity(32 * super::K);
-            for init in self.0.iter() {
-                init.serialize_to_buffer(&mut buffer);
+            match self {
+                OtInit::ChouOrlandi(inits) => {
+                    let mut buffer = Vec::with_capacity(32 * super::K);
+                    for init in inits.iter() {
+                        init.serialize_to_buffer(&mut buffer);
+                    }
+                    SerializedOtInit::ChouOrlandi(buffer)
+                }
+                #[cfg(feature = "post-quantum")]
+                OtInit::PostQuantumSeeds(seeds) => {
+                    let mut buffer = Vec::with_capacity(32 * super::K);
+                    for seed in seeds.iter() {
+                        seed.serialize_to_buffer(&mut buffer);
+                    }
+                    SerializedOtInit::PostQuantumSeeds(buffer)
+                }
+                #[cfg(feature = "post-quantum")]
+                OtInit::PostQuantumKeys(keys) => {
+                    let mut buffer = Vec::with_capacity(pq::message::KEYS_LEN * super::K);
+                    for keys in keys.iter() {
+                        keys.serialize_to_buffer(&mut buffer);
+                    }
+                    SerializedOtInit::PostQuantumKeys(buffer)
+                }
             }
-            SerializedOtInit(buffer)
         }
     }
 
     impl SerializedOtInit {
         pub fn deserialize(&self) -> Result<OtInit, Error> {
-            let mut buffer = self.0.iter();
-            let mut init = Box::new([Init::default(); super::K]);
-            for init in init.iter_mut().take(super::K) {
-                *init = Init::deserialize_from_buffer(&mut buffer)?;
+            match self {
+                SerializedOtInit::ChouOrlandi(buffer) => {
+                    let mut buffer = buffer.iter();
+                    let mut init = Box::new([Init::default(); super::K]);
+                    for init in init.iter_mut().take(super::K) {
+                        *init = Init::deserialize_from_buffer(&mut buffer)?;
+                    }
+                    Ok(OtInit::ChouOrlandi(init))
+                }
+                #[cfg(feature = "post-quantum")]
+                SerializedOtInit::PostQuantumSeeds(buffer) => {
+                    let mut buffer = buffer.iter();
+                    let mut seeds = Box::new([pq::message::Init::default(); super::K]);
+                    for seed in seeds.iter_mut() {
+                        *seed = pq::message::Init::deserialize_from_buffer(&mut buffer)?;
+                    }
+                    Ok(OtInit::PostQuantumSeeds(seeds))
+                }
+                #[cfg(feature = "post-quantum")]
+                SerializedOtInit::PostQuantumKeys(buffer) => {
+                    let mut buffer = buffer.iter();
+                    let keys = (0..super::K)
+                        .map(|_| pq::message::Keys::deserialize_from_buffer(&mut buffer))
+                        .collect::<Result<_, _>>()?;
+                    Ok(OtInit::PostQuantumKeys(keys))
+                }
+            }
+        }
+
+        /// Returns the base OT protocol that the message belongs to.
+        pub fn base_ot(&self) -> BaseOt {
+            match self {
+                SerializedOtInit::ChouOrlandi(_) => BaseOt::ChouOrlandi,
+                #[cfg(feature = "post-quantum")]
+                SerializedOtInit::PostQuantumSeeds(_) | SerializedOtInit::PostQuantumKeys(_) => {
+                    BaseOt::PostQuantum
+                }
             }
-            Ok(OtInit(init))
         }
     }
 
     impl OtInitReply {
         pub fn serialize(&self) -> Vec<u8> {
-            let mut buffer = Vec::with_capacity(crate::ot_base::MSG_LEN * 2 * super::K);
-            for init_reply in self.0.iter() {
-                init_reply.serialize_to_buffer(&mut buffer);
+            match self {
+                OtInitReply::ChouOrlandi(replies) => {
+                    let mut buffer = Vec::with_capacity(crate::ot_base::MSG_LEN * 2 * super::K);
+                    for init_reply in replies.iter() {
+                        init_reply.serialize_to_buffer(&mut buffer);
+                    }
+                    buffer
+                }
+                #[cfg(feature = "post-quantum")]
+                OtInitReply::PostQuantum(replies) => {
+                    let mut buffer = Vec::with_capacity(pq::message::INIT_REPLY_LEN * super::K);
+                    for init_reply in replies.iter() {
+                        init_reply.serialize_to_buffer(&mut buffer);
+                    }
+                    buffer
+                }
             }
-            buffer
         }
 
-        pub fn deserialize(buffer: Vec<u8>) -> Result<Self, Error> {
-            if buffer.len() != crate::ot_base::MSG_LEN * 2 * super::K {
-                return Err(Error::OtInitDeserializationError);
-            }
-
-            let mut buffer = buffer.iter();
-            let mut init_reply =
-                Box::new([crate::ot_base::message::InitReply::default(); super::K]);
-
-            for init in init_reply.iter_mut().take(super::K) {
-                *init = crate::ot_base::message::InitReply::deserialize_from_buffer(&mut buffer)?;
+        pub fn deserialize(buffer: Vec<u8>, base_ot: BaseOt) -> Result<Self, Error> {
+            match base_ot {
+                BaseOt::ChouOrlandi => {
+                    if buffer.len() != crate::ot_base::MSG_LEN * 2 * super::K {
+                        return Err(Error::OtInitDeserializationError);
+                    }
+
+                    let mut buffer = buffer.iter();
+                    let mut init_reply = Box::new([InitReply::default(); super::K]);
+
+                    for init in init_reply.iter_mut().take(super::K) {
+                        *init = InitReply::deserialize_from_buffer(&mut buffer)?;
+                    }
+
+                    Ok(OtInitReply::ChouOrlandi(init_reply))
+                }
+                #[cfg(feature = "post-quantum")]
+                BaseOt::PostQuantum => {
+                    if buffer.len() != pq::message::INIT_REPLY_LEN * super::K {
+                        return Err(Error::OtInitDeserializationError);
+                    }
+
+                    let mut buffer = buffer.iter();
+                    let replies = (0..super::K)
+                        .map(|_| pq::message::InitReply::deserialize_from_buffer(&mut buffer))
+                        .collect::<Result<_, _>>()?;
+                    Ok(OtInitReply::PostQuantum(replies))
+                }
             }
-
-            Ok(OtInitReply(init_reply))
         }
     }
 }
 
 /// Initial state of a Receiver in Leaky Delta OT protocol terms.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct ReceiverInitializer {
-    senders: Box<[BaseSender; K]>,
+    senders: BaseSenders,
+    #[serde(with = "crate::types::boxed_array")]
     ot_messages: Box<[[OtMessage; 2]; K]>,
 }
 
+/// The base OT senders of a [`ReceiverInitializer`].
+#[derive(Clone, Serialize, Deserialize)]
+enum BaseSenders {
+    ChouOrlandi(#[serde(with = "crate::types::boxed_array")] Box<[BaseSender; K]>),
+    #[cfg(feature = "post-quantum")]
+    PostQuantum(Vec<pq::Sender>),
+}
+
 /// Initial state of a Sender in Leaky Delta OT protocol terms.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct SenderInitializer {
     delta: Delta,
-    receivers: Box<[BaseReceiver; K]>,
+    receivers: BaseReceivers,
+}
+
+/// The base OT receivers of a [`SenderInitializer`].
+#[derive(Clone, Serialize, Deserialize)]
+enum BaseReceivers {
+    ChouOrlandi(#[serde(with = "crate::types::boxed_array")] Box<[BaseReceiver; K]>),
+    #[cfg(feature = "post-quantum")]
+    PostQuantum(Vec<pq::Receiver>),
 }
 
 // A Receiver in Leaky Delta OT protocol terms.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct LeakyOtReceiver {
+    #[serde(with = "crate::types::boxed_array")]
     otg0: Box<[ChaCha20Rng; K]>,
+    #[serde(with = "crate::types::boxed_array")]
     otg1: Box<[ChaCha20Rng; K]>,
 }
 
 /// A Sender in Leaky Delta OT protocol terms.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct LeakyOtSender {
     delta: Delta,
+    #[serde(with = "crate::types::boxed_array")]
     otg: Box<[ChaCha20Rng; K]>,
 }
 
 impl ReceiverInitializer {
-    /// Starts a new OT extension receiver session.
+    /// Starts a new OT extension receiver session, using the base OT protocol `base_ot`.
     ///
     /// Returns the message to be sent upstream plus an intermediate struct to create a
     /// [`LeakyOtReceiver`].
-    pub(crate) fn init(rng: &mut ChaCha20Rng) -> (Self, message::OtInit) {
-        let senders = Box::new([(); K].map(|_| BaseSender::new(rng)));
+    pub(crate) fn init<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        base_ot: BaseOt,
+    ) -> (Self, message::OtInit) {
         let mut idxs = [0; K];
         for (i, idx) in idxs.iter_mut().enumerate().take(K) {
             *idx = i;
         }
-        let msgs = Box::new(idxs.map(|i| BaseSender::init_message(&senders[i])));
+        let (senders, msgs) = match base_ot {
+            BaseOt::ChouOrlandi => {
+                let senders = Box::new([(); K].map(|_| BaseSender::new(rng)));
+                let msgs = Box::new(idxs.map(|i| BaseSender::init_message(&senders[i])));
+                (
+                    BaseSenders::ChouOrlandi(senders),
+                    message::OtInit::ChouOrlandi(msgs),
+                )
+            }
+            #[cfg(feature = "post-quantum")]
+            BaseOt::PostQuantum => {
+                let senders: Vec<pq::Sender> = (0..K).map(|_| pq::Sender::new(rng)).collect();
+                let seeds = Box::new(idxs.map(|i| senders[i].init_message()));
+                (
+                    BaseSenders::PostQuantum(senders),
+                    message::OtInit::PostQuantumSeeds(seeds),
+                )
+            }
+        };
 
         // create K * 2 many random messages which we will later use as seeds for LeakyOtReceiver's RNGs
         let ot_messages = Box::new({
@@ -135,79 +289,153 @@ impl ReceiverInitializer {
             ot_messages,
         };
 
-        (s, message::OtInit(msgs))
+        (s, msgs)
+    }
+
+    /// Returns the base OT protocol used by the session.
+    pub(crate) fn base_ot(&self) -> BaseOt {
+        match self.senders {
+            BaseSenders::ChouOrlandi(_) => BaseOt::ChouOrlandi,
+            #[cfg(feature = "post-quantum")]
+            BaseSenders::PostQuantum(_) => BaseOt::PostQuantum,
+        }
     }
 
     /// Called after the respective [`message::OtInit`] message was received from an upstream
     /// [`LeakyOtSender`] leaking a new OT receiver session.
-    pub(crate) fn recv(&self, m: &message::OtInit) -> (LeakyOtReceiver, message::OtInitReply) {
+    pub(crate) fn recv(
+        &self,
+        m: &message::OtInit,
+    ) -> Result<(LeakyOtReceiver, message::OtInitReply), Error> {
         let mut idxs = [0; K];
         for (i, idx) in idxs.iter_mut().enumerate().take(K) {
             *idx = i;
         }
 
-        let replies =
-            Box::new(idxs.map(|idx| {
-                BaseSender::send(&self.senders[idx], &m.0[idx], &self.ot_messages[idx])
-            }));
+        let replies = match (&self.senders, m) {
+            (BaseSenders::ChouOrlandi(senders), message::OtInit::ChouOrlandi(inits)) => {
+                message::OtInitReply::ChouOrlandi(Box::new(idxs.map(|idx| {
+                    BaseSender::send(&senders[idx], &inits[idx], &self.ot_messages[idx])
+                })))
+            }
+            #[cfg(feature = "post-quantum")]
+            (BaseSenders::PostQuantum(senders), message::OtInit::PostQuantumKeys(keys)) => {
+                message::OtInitReply::PostQuantum(
+                    senders
+                        .iter()
+                        .zip(keys.iter())
+                        .zip(self.ot_messages.iter())
+                        .map(|((sender, keys), ot_messages)| sender.send(keys, ot_messages))
+                        .collect(),
+                )
+            }
+            #[cfg(feature = "post-quantum")]
+            _ => return Err(Error::UnexpectedMessageType),
+        };
 
         let otg0: Box<[ChaCha20Rng; K]> =
             Box::new(idxs.map(|idx| ChaCha20Rng::from_seed(self.ot_messages[idx][0])));
         let otg1: Box<[ChaCha20Rng; K]> =
             Box::new(idxs.map(|idx| ChaCha20Rng::from_seed(self.ot_messages[idx][1])));
 
-        (
-            LeakyOtReceiver { otg0, otg1 },
-            message::OtInitReply(replies),
-        )
+        Ok((LeakyOtReceiver { otg0, otg1 }, replies))
     }
 }
 
 impl SenderInitializer {
-    /// Starts a new OT extension sender session.
+    /// Starts a new OT extension sender session, using the base OT protocol of the upstream
+    /// [`message::OtInit`].
     ///
     /// Returns the message to be sent upstream plus an intermediate struct to create a
     /// [`LeakyOtSender`].
-    pub(crate) fn init(
-        rng: &mut ChaCha20Rng,
+    pub(crate) fn init<R: RngCore + CryptoRng>(
+        rng: &mut R,
         delta: Delta,
         m: &message::OtInit,
-    ) -> (Self, message::OtInit) {
+    ) -> Result<(Self, message::OtInit), Error> {
         let mut idxs: [usize; K] = [0; K];
         for (i, idx) in idxs.iter_mut().enumerate().take(K) {
             *idx = i;
         }
 
-        let mut msgs: Box<[BaseOTInit; K]> = Box::new([BaseOTInit::default(); K]);
-        let receivers: Box<[BaseReceiver; K]> = Box::new(idxs.map(|i| {
-            let chosen = (delta.0 & (1 << i)) != 0;
-            let (msg, r) = BaseReceiver::init(rng, &m.0[i], chosen);
-            msgs[i] = msg;
-            r
-        }));
+        match m {
+            message::OtInit::ChouOrlandi(inits) => {
+                let mut msgs: Box<[BaseOTInit; K]> = Box::new([BaseOTInit::default(); K]);
+                let receivers: Box<[BaseReceiver; K]> = Box::new(idxs.map(|i| {
+                    let chosen = (delta.0 & (1 << i)) != 0;
+                    let (msg, r) = BaseReceiver::init(rng, &inits[i], chosen);
+                    msgs[i] = msg;
+                    r
+                }));
+                let receivers = BaseReceivers::ChouOrlandi(receivers);
+                Ok((
+                    Self { delta, receivers },
+                    message::OtInit::ChouOrlandi(msgs),
+                ))
+            }
+            #[cfg(feature = "post-quantum")]
+            message::OtInit::PostQuantumSeeds(seeds) => {
+                let mut keys = Vec::with_capacity(K);
+                let receivers = idxs
+                    .iter()
+                    .map(|&i| {
+                        let chosen = (delta.0 & (1 << i)) != 0;
+                        let (msg, r) = pq::Receiver::init(rng, &seeds[i], chosen);
+                        keys.push(msg);
+                        r
+                    })
+                    .collect();
+                let receivers = BaseReceivers::PostQuantum(receivers);
+                Ok((
+                    Self { delta, receivers },
+                    message::OtInit::PostQuantumKeys(keys),
+                ))
+            }
+            #[cfg(feature = "post-quantum")]
+            message::OtInit::PostQuantumKeys(_) => Err(Error::UnexpectedMessageType),
+        }
+    }
 
-        (Self { delta, receivers }, message::OtInit(msgs))
+    /// Returns the base OT protocol used by the session.
+    pub(crate) fn base_ot(&self) -> BaseOt {
+        match self.receivers {
+            BaseReceivers::ChouOrlandi(_) => BaseOt::ChouOrlandi,
+            #[cfg(feature = "post-quantum")]
+            BaseReceivers::PostQuantum(_) => BaseOt::PostQuantum,
+        }
     }
 
     /// Called after the respective [`message::OtInitReply`] message was received from an upstream
     /// [`ReceiverInitializer`], returning a new [`LeakyOtSender`].
-    pub(crate) fn recv(self, m: &message::OtInitReply) -> LeakyOtSender {
-        let mut idxs = [0; K];
-        for (i, idx) in idxs.iter_mut().enumerate().take(K) {
-            *idx = i;
-        }
-
-        let mut idx = 0;
-        let otg = Box::new(self.receivers.map(|r| {
-            let seed = r.recv(m.0[idx]);
-            idx += 1;
-            ChaCha20Rng::from_seed(seed)
-        }));
+    pub(crate) fn recv(self, m: &message::OtInitReply) -> Result<LeakyOtSender, Error> {
+        let otg = match (self.receivers, m) {
+            (BaseReceivers::ChouOrlandi(receivers), message::OtInitReply::ChouOrlandi(replies)) => {
+                let mut idx = 0;
+                Box::new(receivers.map(|r| {
+                    let seed = r.recv(replies[idx]);
+                    idx += 1;
+                    ChaCha20Rng::from_seed(seed)
+                }))
+            }
+            #[cfg(feature = "post-quantum")]
+            (BaseReceivers::PostQuantum(receivers), message::OtInitReply::PostQuantum(replies)) => {
+                let otg: Vec<ChaCha20Rng> = receivers
+                    .into_iter()
+                    .zip(replies.iter())
+                    .map(|(r, reply)| ChaCha20Rng::from_seed(r.recv(reply)))
+                    .collect();
+                otg.into_boxed_slice()
+                    .try_into()
+                    .map_err(|_| Error::UnexpectedMessageType)?
+            }
+            #[cfg(feature = "post-quantum")]
+            _ => return Err(Error::UnexpectedMessageType),
+        };
 
-        LeakyOtSender {
+        Ok(LeakyOtSender {
             delta: self.delta,
             otg,
-        }
+        })
     }
 }
 
@@ -262,6 +490,88 @@ impl LeakyOtReceiver {
     }
 }
 
+/// The values disclosed by a [`LeakyOtReceiver`] in the [KOS15] correlation check.
+///
+/// The check ensures that the receiver used the same choice bits for all [`K`] base OTs, so that
+/// a malicious receiver cannot learn individual bits of the sender's [`Delta`] without being
+/// caught with high probability.
+///
+/// [KOS15]: <https://eprint.iacr.org/2015/546.pdf>
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct CorrelationCheck {
+    x: u128,
+    t: u128,
+}
+
+impl CorrelationCheck {
+    /// Computes the check values from the authenticated bits (as pairs of bit and MAC) of the
+    /// receiver, which were derived from the `batches` sent to the sender.
+    pub(crate) fn new(
+        coin: &CoinResult,
+        batches: &[Vec<MacType>],
+        abits: impl Iterator<Item = (bool, MacType)>,
+    ) -> Self {
+        let mut chi = challenges(coin, batches);
+        let mut x = 0;
+        let mut t = 0;
+        for (bit, mac) in abits {
+            let chi_j: u128 = chi.gen();
+            if bit {
+                x ^= chi_j;
+            }
+            t ^= gf128_mul(chi_j, mac.0);
+        }
+        Self { x, t }
+    }
+
+    /// Verifies the check values using the keys of the sender, which must be in the same order as
+    /// the authenticated bits of the receiver.
+    pub(crate) fn verify(
+        &self,
+        coin: &CoinResult,
+        batches: &[Vec<MacType>],
+        delta: &Delta,
+        keys: impl Iterator<Item = KeyType>,
+    ) -> bool {
+        let mut chi = challenges(coin, batches);
+        let mut q = 0;
+        for key in keys {
+            q ^= gf128_mul(chi.gen(), key.0);
+        }
+        q == self.t ^ gf128_mul(self.x, delta.0)
+    }
+}
+
+/// Derives the random challenges of the correlation check from the coin and the batches sent by
+/// the receiver, so that the receiver cannot choose its batches depending on the challenges.
+fn challenges(coin: &CoinResult, batches: &[Vec<MacType>]) -> ChaCha20Rng {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(coin);
+    for batch in batches {
+        for u in batch {
+            hasher.update(&u.0.to_le_bytes());
+        }
+    }
+    ChaCha20Rng::from_seed(*hasher.finalize().as_bytes())
+}
+
+/// Multiplication in GF(2^128), using the reduction polynomial `x^128 + x^7 + x^2 + x + 1`.
+pub(crate) fn gf128_mul(mut a: u128, mut b: u128) -> u128 {
+    let mut result = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            result ^= a;
+        }
+        let overflow = a >> 127 != 0;
+        a <<= 1;
+        if overflow {
+            a ^= 0x87;
+        }
+        b >>= 1;
+    }
+    result
+}
+
 #[inline]
 fn matrix_transpose(macs_out: &mut [MacType], t_i: &[KeyType]) {
     for (i, mac_out) in macs_out.iter_mut().enumerate().take(BLOCK_SIZE) {
@@ -287,14 +597,101 @@ fn test_serialization() {
     let mut rng_send = ChaCha20Rng::from_seed([42; 32]);
     let delta = Delta(rng_send.gen());
 
-    let (r, r_msg) = ReceiverInitializer::init(&mut rng_send);
-    let (_, s_msg) = SenderInitializer::init(&mut rng_send, delta, &r_msg);
-    let (_, reply) = r.recv(&s_msg);
+    let (r, r_msg) = ReceiverInitializer::init(&mut rng_send, BaseOt::ChouOrlandi);
+    let (_, s_msg) = SenderInitializer::init(&mut rng_send, delta, &r_msg).unwrap();
+    let (_, reply) = r.recv(&s_msg).unwrap();
 
     assert_eq!(r_msg, r_msg.serialize().deserialize().unwrap());
     assert_eq!(s_msg, s_msg.serialize().deserialize().unwrap());
     assert_eq!(
         reply,
-        message::OtInitReply::deserialize(reply.serialize()).unwrap()
+        message::OtInitReply::deserialize(reply.serialize(), BaseOt::ChouOrlandi).unwrap()
     );
 }
+
+#[cfg(feature = "post-quantum")]
+#[test]
+fn test_post_quantum_base_ot() {
+    use rand::SeedableRng;
+
+    let mut rng = ChaCha20Rng::from_seed([42; 32]);
+    let delta = Delta(rng.gen());
+
+    let (r_init, r_msg) = ReceiverInitializer::init(&mut rng, BaseOt::PostQuantum);
+    let (s_init, s_msg) = SenderInitializer::init(&mut rng, delta.clone(), &r_msg).unwrap();
+    assert_eq!(r_init.base_ot(), BaseOt::PostQuantum);
+    assert_eq!(s_init.base_ot(), BaseOt::PostQuantum);
+    assert_eq!(r_msg, r_msg.serialize().deserialize().unwrap());
+    assert_eq!(s_msg, s_msg.serialize().deserialize().unwrap());
+
+    // the seeds of the senders cannot be answered with seeds:
+    assert!(r_init.recv(&r_msg).is_err());
+    let (mut r, reply) = r_init.recv(&s_msg).unwrap();
+    let serialized = reply.serialize();
+    assert!(message::OtInitReply::deserialize(serialized.clone(), BaseOt::ChouOrlandi).is_err());
+    let reply = message::OtInitReply::deserialize(serialized, BaseOt::PostQuantum).unwrap();
+    let mut s = s_init.recv(&reply).unwrap();
+
+    // the base OTs yield the same correlation as the Chou-Orlandi OT:
+    let bits: u128 = rng.gen();
+    let mut macs = [MacType(0); BLOCK_SIZE];
+    let mut ot_out = [MacType(0); BLOCK_SIZE];
+    r.new_batch(bits, &mut macs, &mut ot_out);
+    let mut keys = [MacType(0); BLOCK_SIZE];
+    s.send(&ot_out, &mut keys);
+    for i in 0..BLOCK_SIZE {
+        let bit = bits & (1 << i) != 0;
+        let expected = if bit { keys[i].0 ^ delta.0 } else { keys[i].0 };
+        assert_eq!(macs[i].0, expected);
+    }
+}
+
+#[test]
+fn test_correlation_check() {
+    let mut rng = ChaCha20Rng::from_seed([42; 32]);
+    let delta = Delta(rng.gen());
+    let coin = [7; 32];
+
+    let (r_init, r_msg) = ReceiverInitializer::init(&mut rng, BaseOt::ChouOrlandi);
+    let (s_init, s_msg) = SenderInitializer::init(&mut rng, delta.clone(), &r_msg).unwrap();
+    let (mut r, reply) = r_init.recv(&s_msg).unwrap();
+    let s = s_init.recv(&reply).unwrap();
+
+    let mut abits = vec![];
+    let mut batches = vec![];
+    for _ in 0..3 {
+        let bits: u128 = rng.gen();
+        let mut macs = [MacType(0); BLOCK_SIZE];
+        let mut ot_out = [MacType(0); BLOCK_SIZE];
+        r.new_batch(bits, &mut macs, &mut ot_out);
+        abits.extend((0..BLOCK_SIZE).map(|i| (bits & (1 << i) != 0, macs[i])));
+        batches.push(ot_out.to_vec());
+    }
+    let check = CorrelationCheck::new(&coin, &batches, abits.iter().copied());
+
+    let keys = |batches: &[Vec<MacType>]| {
+        let mut s = s.clone();
+        let mut keys = vec![];
+        for batch in batches {
+            let mut keys_out = [MacType(0); BLOCK_SIZE];
+            s.send(batch, &mut keys_out);
+            keys.extend(keys_out.iter().map(|k| KeyType(k.0)));
+        }
+        keys
+    };
+    assert!(check.verify(&coin, &batches, &delta, keys(&batches).into_iter()));
+    assert!(!check.verify(&[8; 32], &batches, &delta, keys(&batches).into_iter()));
+
+    // a receiver using inconsistent choice bits in some of the base OTs:
+    let mut inconsistent = batches.clone();
+    for u in inconsistent[1].iter_mut().take(K / 2) {
+        u.0 ^= 1;
+    }
+    let check = CorrelationCheck::new(&coin, &inconsistent, abits.iter().copied());
+    assert!(!check.verify(
+        &coin,
+        &inconsistent,
+        &delta,
+        keys(&inconsistent).into_iter()
+    ));
+}
@@ -12,6 +12,32 @@
 //! messages needs to be handled by the user of this crate, which allows the MPC protocol to be used
 //! both in sync and async environments.
 //!
+//! Parties that only need security against semi-honest adversaries can opt into the faster
+//! [`semi_honest`] protocol mode instead, which uses the same [`Circuit`] type.
+//!
+//! The function-independent preprocessing can also be run ahead of time, see
+//! [`PreprocessedTriples`]. For research on alternative garbling schemes, the `research` feature
+//! exposes the generated triples, wire masks and labels (which breaks the security of the protocol
+//! and must never be enabled outside of a local sandbox).
+//!
+//! Deployments that need to withstand quantum adversaries can enable the `post-quantum` feature,
+//! which adds a base OT based on ML-KEM that is negotiated using [`BaseOt`].
+//!
+//! Sessions can be suspended between two steps and continued later, possibly on another machine,
+//! see [`states::Contributor::serialize_state`] and [`states::Contributor::restore`].
+//!
+//! Implementations of compatible parties can check their messages against the deterministic test
+//! vectors of the [`conformance`] module.
+//!
+//! Deployments that cannot rely on TLS end-to-end can enable the `encrypted-channel` feature,
+//! which encrypts the messages of the protocol using keys established by a handshake, see
+//! `tandem::channel`.
+//!
+//! Before running the protocol on an untested host, [`self_test()`] can be used to check that the
+//! cryptographic primitives behave as expected. The `constant-time` feature compares MACs, keys
+//! and commitments in constant time, [`timing_audit()`] lists which operations on secret values
+//! are constant-time in the current build.
+//!
 //! # Examples
 //!
 //! ```
@@ -92,18 +118,67 @@
 #![deny(missing_docs)]
 #![deny(rustdoc::broken_intra_doc_links)]
 
+mod abort;
+mod bristol;
+#[cfg(feature = "encrypted-channel")]
+pub mod channel;
 mod circuit;
+mod columnar;
+pub mod conformance;
+mod constant_time;
+mod dot;
+mod framing;
 mod hash;
+mod input;
 mod leakyand;
 mod leakydelta_ot;
+mod options;
 mod ot_base;
+mod plan;
+mod preprocessed;
 mod protocol;
+#[cfg(feature = "research")]
+pub mod research;
+mod rng;
+mod self_test;
+pub mod semi_honest;
+mod silent_ot;
 mod simulator;
+mod source;
 pub mod states;
+mod transcript;
 mod types;
+mod wires;
 
+pub use abort::{abort_message, AbortReason};
 pub use circuit::*;
+pub use columnar::*;
+pub use constant_time::{timing_audit, TimingAuditEntry};
+pub use input::{Bits, InputBits, InputSource};
+pub use options::*;
+pub use plan::*;
+pub use preprocessed::{preprocessing_circuit, PreprocessedTriples};
+pub use self_test::{self_test, SelfTestCheck, SelfTestReport};
 pub use simulator::*;
+pub use source::{CircuitSource, Gates};
+pub use transcript::*;
+
+/// Version of the MPC protocol implemented by this crate.
+///
+/// Parties can only interoperate if they speak the same protocol version. Unlike the crate
+/// version, the protocol version is only incremented when the messages exchanged between
+/// [`states::Contributor`] and [`states::Evaluator`] change in an incompatible way.
+pub const PROTOCOL_VERSION: u32 = 6;
+
+/// Whether the crate was compiled with the `research` feature, which exposes the secret
+/// intermediate values of the protocol, see `tandem::research`.
+///
+/// Services handling real inputs should refuse to run if this is `true`.
+pub const RESEARCH_ACCESSORS: bool = cfg!(feature = "research");
+
+/// Whether the crate was compiled with the `constant-time` feature, which compares MACs, keys and
+/// commitments in constant time, see [`timing_audit()`].
+pub const CONSTANT_TIME: bool = cfg!(feature = "constant-time");
 
 /// Errors occurring during the validation or the execution of the MPC protocol.
 #[derive(Debug, PartialEq, Eq)]
@@ -120,6 +195,9 @@ pub enum Error {
     MacError,
     /// The Leaky Authenticated AND Triples did not pass the equality check.
     LeakyAndNotEqual,
+    /// The OT extension did not pass the correlation check, see [`OtExtension::Kos15`], or the
+    /// silent OT did not pass the consistency check, see [`OtBackend::Silent`].
+    OtCorrelationCheckFailed,
     /// The provided circuit contains invalid gate connections.
     InvalidCircuit,
     /// The provided circuit has too many gates to be processed.
@@ -134,6 +212,38 @@ pub enum Error {
     ProtocolEnded,
     /// The protocol is still in progress and does not yet have any output.
     ProtocolStillInProgress,
+    /// No message from the other party arrived, although the protocol has not ended yet.
+    ProtocolStalled,
+    /// The other party aborted the protocol for the specified reason.
+    PeerAborted(AbortReason),
+    /// The replayed messages differ from the messages recorded in the transcript, or the parties
+    /// did not send and receive the same messages, see
+    /// [`states::Contributor::confirm_transcript_hash`].
+    TranscriptMismatch,
+    /// The data was produced by an incompatible version of the protocol.
+    IncompatibleProtocolVersion {
+        /// The protocol version of the data.
+        version: u32,
+    },
+    /// The wiring used to compose two circuits does not connect outputs to (distinct) inputs.
+    InvalidCompositionWiring,
+    /// The Bristol netlist could not be parsed, due to a malformed or unsupported line.
+    InvalidBristolNetlist {
+        /// The (1-based) line number at which the netlist is malformed.
+        line: usize,
+    },
+    /// The preprocessed triples do not fit the circuit or the party, or belong to a different
+    /// preprocessing session than the triples of the other party.
+    InvalidPreprocessedTriples,
+    /// The base OTs of the other party do not match the negotiated [`BaseOt`], for example because
+    /// only the evaluator proposed the post-quantum base OT.
+    IncompatibleBaseOt,
+    /// A key of the encrypted channel is invalid or does not match the pinned key, or a message
+    /// could not be decrypted because it was modified in transit.
+    InvalidChannelMessage,
+    /// The serialized session state was modified, was authenticated using a different key or
+    /// does not match the party, the circuit or the input it is restored with.
+    InvalidSessionState,
 }
 
 impl std::error::Error for Error {}
@@ -153,6 +263,9 @@ impl std::fmt::Display for Error {
             Error::LeakyAndNotEqual => {
                 f.write_str("The equality check of the leaky AND step failed")
             }
+            Error::OtCorrelationCheckFailed => {
+                f.write_str("The correlation check of the OT extension failed")
+            }
             Error::InvalidCircuit => {
                 f.write_str("The provided circuit is invalid and cannot be executed")
             }
@@ -174,6 +287,40 @@ impl std::fmt::Display for Error {
             Error::ProtocolStillInProgress => {
                 f.write_str("The protocol is still in progress and does not yet have any output.")
             }
+            Error::ProtocolStalled => {
+                f.write_str("No message arrived, although the protocol has not ended yet")
+            }
+            Error::PeerAborted(reason) => {
+                write!(f, "The other party aborted the protocol: {reason}")
+            }
+            Error::TranscriptMismatch => {
+                f.write_str("The exchanged messages do not match the transcript")
+            }
+            Error::IncompatibleProtocolVersion { version } => write!(
+                f,
+                "Protocol version {version} is incompatible with version {PROTOCOL_VERSION}"
+            ),
+            Error::InvalidCompositionWiring => f.write_str(
+                "The circuits cannot be composed, the wiring does not connect outputs to inputs",
+            ),
+            Error::InvalidBristolNetlist { line } => {
+                write!(
+                    f,
+                    "The Bristol netlist is malformed or unsupported at line {line}"
+                )
+            }
+            Error::InvalidPreprocessedTriples => f.write_str(
+                "The preprocessed triples do not fit the circuit or the other party's triples",
+            ),
+            Error::IncompatibleBaseOt => {
+                f.write_str("The base OT of the other party does not match the negotiated base OT")
+            }
+            Error::InvalidChannelMessage => {
+                f.write_str("The message could not be authenticated by the encrypted channel")
+            }
+            Error::InvalidSessionState => f.write_str(
+                "The session state is corrupted or does not match the party, circuit or input",
+            ),
         }
     }
 }
@@ -0,0 +1,158 @@
+//! Protocol options, which are negotiated between the parties at the start of the protocol.
+
+use serde::{Deserialize, Serialize};
+
+/// The correlated OT extension used to generate authenticated bits during preprocessing.
+///
+/// Variants are ordered from the least to the most conservative choice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum OtExtension {
+    /// The OT extension of [ALSZ13], as used by WRK17, which relies on the leakage tolerance of
+    /// the subsequent protocol steps instead of checking the consistency of the receiver.
+    ///
+    /// [ALSZ13]: <https://eprint.iacr.org/2013/552.pdf>
+    Alsz13,
+    /// The OT extension of [ALSZ13] hardened with the correlation check of [KOS15], which
+    /// detects a receiver using inconsistent choice bits at the cost of 2 additional OT batches
+    /// and a slightly longer preprocessing.
+    ///
+    /// [ALSZ13]: <https://eprint.iacr.org/2013/552.pdf>
+    /// [KOS15]: <https://eprint.iacr.org/2015/546.pdf>
+    Kos15,
+}
+
+impl Default for OtExtension {
+    fn default() -> Self {
+        OtExtension::Alsz13
+    }
+}
+
+/// The generator of the correlated OTs that are turned into authenticated bits during preprocessing.
+///
+/// Variants are ordered from the least to the most conservative choice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum OtBackend {
+    /// A silent OT extension in the style of [Ferret], which expands a comparatively small number
+    /// of correlated OTs from the [`OtExtension`] into all required correlated OTs using a
+    /// pseudorandom correlation generator, relying on the additional LPN assumption.
+    ///
+    /// Reduces the preprocessing traffic of large circuits several times over, but requires a few
+    /// thousand base OTs and is therefore slower and more expensive for small circuits.
+    ///
+    /// [Ferret]: <https://eprint.iacr.org/2020/924.pdf>
+    Silent,
+    /// All correlated OTs are generated directly by the [`OtExtension`].
+    Extension,
+}
+
+impl Default for OtBackend {
+    fn default() -> Self {
+        OtBackend::Extension
+    }
+}
+
+/// The base OT protocol used to set up the [`OtExtension`].
+///
+/// Variants are ordered from the least to the most conservative choice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum BaseOt {
+    /// The Chou-Orlandi OT as described in [ABKLX21], relying on the hardness of the discrete
+    /// logarithm problem over Ristretto, which is not secure against quantum computers.
+    ///
+    /// [ABKLX21]: <https://eprint.iacr.org/2021/1218.pdf>
+    ChouOrlandi,
+    /// The Masny-Rindal OT of [MR19], instantiated with the key encapsulation of ML-KEM-768 (as
+    /// implemented by the `ml-kem` crate), relying on the hardness of the Module-LWE problem, which
+    /// is believed to be secure against quantum computers.
+    ///
+    /// Requires the `post-quantum` feature and sends about 35 times more bytes during the setup of
+    /// the OT extension (around 590 kB instead of 16 kB per party). Because the silent OT relies on
+    /// additional Chou-Orlandi base OTs, negotiating this option also negotiates
+    /// [`OtBackend::Extension`]. The [`crate::semi_honest`] protocol mode always uses the
+    /// Chou-Orlandi OT.
+    ///
+    /// The [`crate::states::Evaluator`] switches to this option if the
+    /// [`crate::states::Contributor`] proposes it, but a contributor cannot switch after its initial
+    /// message: if only the evaluator proposes this option, the protocol fails with
+    /// [`crate::Error::IncompatibleBaseOt`] instead of falling back to the Chou-Orlandi OT.
+    ///
+    /// [MR19]: <https://eprint.iacr.org/2019/706.pdf>
+    #[cfg(feature = "post-quantum")]
+    PostQuantum,
+}
+
+impl Default for BaseOt {
+    fn default() -> Self {
+        BaseOt::ChouOrlandi
+    }
+}
+
+/// Options proposed by a party, see [`crate::states::Contributor::new_with_options`] and
+/// [`crate::states::Evaluator::new_with_options`].
+///
+/// The options of both parties are committed to during the coin tossing at the start of the
+/// protocol, after which both parties switch to the more conservative choice of each option.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProtocolOptions {
+    /// The OT extension used during preprocessing.
+    pub ot_extension: OtExtension,
+    /// The generator of correlated OTs used during preprocessing.
+    pub ot_backend: OtBackend,
+    /// The base OT protocol used to set up the OT extension.
+    pub base_ot: BaseOt,
+}
+
+impl ProtocolOptions {
+    /// Combines the options proposed by both parties into the options used by both parties.
+    pub fn negotiate(&self, other: &ProtocolOptions) -> ProtocolOptions {
+        let base_ot = self.base_ot.max(other.base_ot);
+        let ot_backend = match base_ot {
+            BaseOt::ChouOrlandi => self.ot_backend.max(other.ot_backend),
+            #[cfg(feature = "post-quantum")]
+            BaseOt::PostQuantum => OtBackend::Extension,
+        };
+        ProtocolOptions {
+            ot_extension: self.ot_extension.max(other.ot_extension),
+            ot_backend,
+            base_ot,
+        }
+    }
+}
+
+#[test]
+fn test_negotiate() {
+    let alsz13 = ProtocolOptions::default();
+    let kos15 = ProtocolOptions {
+        ot_extension: OtExtension::Kos15,
+        ..Default::default()
+    };
+    assert_eq!(alsz13.negotiate(&alsz13), alsz13);
+    assert_eq!(alsz13.negotiate(&kos15), kos15);
+    assert_eq!(kos15.negotiate(&alsz13), kos15);
+
+    let silent = ProtocolOptions {
+        ot_backend: OtBackend::Silent,
+        ..Default::default()
+    };
+    assert_eq!(silent.negotiate(&silent), silent);
+    assert_eq!(silent.negotiate(&alsz13), alsz13);
+    assert_eq!(
+        silent.negotiate(&kos15),
+        ProtocolOptions {
+            ot_extension: OtExtension::Kos15,
+            ot_backend: OtBackend::Extension,
+            base_ot: BaseOt::ChouOrlandi,
+        }
+    );
+
+    #[cfg(feature = "post-quantum")]
+    {
+        let pq = ProtocolOptions {
+            base_ot: BaseOt::PostQuantum,
+            ..Default::default()
+        };
+        assert_eq!(pq.negotiate(&alsz13), pq);
+        assert_eq!(alsz13.negotiate(&pq), pq);
+        assert_eq!(silent.negotiate(&pq), pq);
+    }
+}
@@ -1,10 +1,13 @@
 //! Chou Orlandi Simplest OT protocol based on a version from [ABKLX21].
 //!
+//! With the `post-quantum` feature, the `pq` module additionally implements a base OT that does
+//! not rely on the hardness of discrete logarithms, see [`crate::BaseOt::PostQuantum`].
 //!
 //! [ABKLX21]: https://eprint.iacr.org/2021/1218.pdf
 use curve25519_dalek_ng::constants::RISTRETTO_BASEPOINT_TABLE;
 use curve25519_dalek_ng::ristretto::RistrettoPoint;
 use curve25519_dalek_ng::scalar::Scalar;
+use serde::{Deserialize, Serialize};
 
 pub(crate) const MSG_LEN: usize = 32;
 
@@ -15,7 +18,7 @@ pub(crate) type OtMessage = [u8; MSG_LEN];
 ///
 /// I.e. the logical actor offering 2 pieces of data of which the [`Receiver`] will be able to
 /// recover only 1.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct Sender {
     private_key: Scalar,
     pub_key: RistrettoPoint,
@@ -23,7 +26,7 @@ pub(crate) struct Sender {
 }
 
 /// The party choosing 1-out-of-2 pieces of data w/o the [`Sender`] knowing which it was.
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub(crate) struct Receiver {
     private_key: Scalar,
     upstream_pub_key: RistrettoPoint,
@@ -124,6 +127,19 @@ impl Sender {
         &self,
         upstream_init: &message::Init,
         messages: &[OtMessage; 2],
+    ) -> message::InitReply {
+        self.send_tweaked(upstream_init, messages, &[])
+    }
+
+    /// Like [`Sender::send`], but additionally hashes the `tweak` into the keys.
+    ///
+    /// Required if the same [`Sender`] is used for multiple OTs, so that the keys of different OTs
+    /// are independent even if a malicious [`Receiver`] reuses or relates its init messages.
+    pub(crate) fn send_tweaked(
+        &self,
+        upstream_init: &message::Init,
+        messages: &[OtMessage; 2],
+        tweak: &[u8],
     ) -> message::InitReply {
         let upstream_pub_key = upstream_init.0;
         let my_pub_key_bytes = self.pub_key.compress().to_bytes();
@@ -135,6 +151,7 @@ impl Sender {
             hasher.update(&my_pub_key_bytes);
             let upstream_bytes = (upstream_pub_key * self.private_key).compress().to_bytes();
             hasher.update(&upstream_bytes);
+            hasher.update(tweak);
             let hash = hasher.finalize();
             Self::xor_keys(hash.as_bytes(), &messages[0])
         };
@@ -147,6 +164,7 @@ impl Sender {
                 .compress()
                 .to_bytes();
             hasher.update(&upstream_bytes);
+            hasher.update(tweak);
             let hash = hasher.finalize();
             Self::xor_keys(hash.as_bytes(), &messages[1])
         };
@@ -209,6 +227,15 @@ impl Receiver {
     ///   - `x := self.private_key`
     ///   - `m_b := message.0[self.choice]`
     pub(crate) fn recv(self, upstream_init_reply: message::InitReply) -> OtMessage {
+        self.recv_tweaked(upstream_init_reply, &[])
+    }
+
+    /// Like [`Receiver::recv`], for messages sent using [`Sender::send_tweaked`].
+    pub(crate) fn recv_tweaked(
+        self,
+        upstream_init_reply: message::InitReply,
+        tweak: &[u8],
+    ) -> OtMessage {
         // step 1 from above
         let mut hasher = blake3::Hasher::new();
         hasher.update(&self.upstream_pub_key.compress().to_bytes());
@@ -217,6 +244,7 @@ impl Receiver {
                 .compress()
                 .to_bytes(),
         );
+        hasher.update(tweak);
         let hash = hasher.finalize();
 
         // step 2 of above
@@ -231,6 +259,406 @@ impl Receiver {
     }
 }
 
+/// Masny-Rindal OT of [MR19] (figure 8), instantiated with the key encapsulation of ML-KEM-768
+/// ([FIPS 203]), as implemented by the `ml-kem` crate.
+///
+/// The [`Receiver`] generates an ML-KEM key pair and replies with 2 values `r_0, r_1` such that
+/// only `r_c + H(r_{1 - c})` is an encapsulation key that it knows the decapsulation key of. The
+/// [`Sender`] then encapsulates a shared key under `r_b + H(r_{1 - b})` for both `b` and uses it to
+/// mask `m_b`. Since `H` is modeled as a random oracle, the receiver cannot choose `r_0, r_1` such
+/// that it knows the decapsulation keys of both encapsulation keys.
+///
+/// The values `r_b` only replace the vector `t` of the encapsulation key, both keys share the seed
+/// `rho` of the public matrix chosen by the receiver. The addition of `r_b` and `H(r_{1 - b})` only
+/// involves public values, the secret values are only ever handled by the `ml-kem` crate.
+///
+/// [MR19]: <https://eprint.iacr.org/2019/706.pdf>
+/// [FIPS 203]: <https://csrc.nist.gov/pubs/fips/203/final>
+#[cfg(feature = "post-quantum")]
+pub(crate) mod pq {
+    use ml_kem::{
+        kem::Decapsulate, Ciphertext, EncapsulateDeterministic, Encoded, EncodedSizeUser, KemCore,
+        MlKem768, B32,
+    };
+    use rand::{CryptoRng, RngCore};
+    use serde::{Deserialize, Serialize};
+
+    use super::{OtMessage, MSG_LEN};
+
+    type EncapsulationKey = <MlKem768 as KemCore>::EncapsulationKey;
+
+    /// The modulus of the coefficients of ML-KEM.
+    const Q: u16 = 3329;
+    /// Number of coefficients of the vector `t` of an ML-KEM-768 encapsulation key.
+    const COEFFS: usize = 3 * 256;
+    /// Number of bytes of the vector `t` of an encoded ML-KEM-768 encapsulation key.
+    const T_BYTES: usize = COEFFS * 12 / 8;
+    /// Number of bytes of an ML-KEM-768 ciphertext.
+    const CIPHERTEXT_BYTES: usize = 1088;
+
+    /// The party sending data to a [`Receiver`], see [`super::Sender`].
+    #[derive(Clone, Serialize, Deserialize)]
+    pub(crate) struct Sender {
+        seed: [u8; 32],
+    }
+
+    /// The party choosing 1-out-of-2 pieces of data, see [`super::Receiver`].
+    ///
+    /// Only stores the seeds of its ML-KEM key pair, which is regenerated to decapsulate.
+    #[derive(Clone, Serialize, Deserialize)]
+    pub(crate) struct Receiver {
+        key_seeds: ([u8; 32], [u8; 32]),
+        choice: bool,
+    }
+
+    /// The kind of messages exchanged between a [`Sender`] and a [`Receiver`].
+    pub(crate) mod message {
+        use std::slice;
+
+        use super::{CIPHERTEXT_BYTES, MSG_LEN, T_BYTES};
+        use crate::Error;
+
+        /// Message to initiate the protocol, containing the seed that the [`super::Sender`] uses to
+        /// domain-separate the random oracle `H` of this OT.
+        #[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+        pub(crate) struct Init(pub(super) [u8; 32]);
+
+        /// Reply to the [`Init`] message, sent by the [`super::Receiver`] to the
+        /// [`super::Sender`], containing the seed `rho` and the values `r_0, r_1`.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub(crate) struct Keys {
+            pub(super) rho: [u8; 32],
+            pub(super) r: Box<[[u8; T_BYTES]; 2]>,
+        }
+
+        /// Reply to the [`Keys`] message, sent by the [`super::Sender`] to the
+        /// [`super::Receiver`], containing the ML-KEM ciphertexts and the masked messages.
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        pub(crate) struct InitReply {
+            pub(super) ciphertexts: Box<[[u8; CIPHERTEXT_BYTES]; 2]>,
+            pub(super) masked: [[u8; MSG_LEN]; 2],
+        }
+
+        /// Number of bytes of a serialized [`Keys`] message.
+        pub(crate) const KEYS_LEN: usize = 32 + 2 * T_BYTES;
+        /// Number of bytes of a serialized [`InitReply`] message.
+        pub(crate) const INIT_REPLY_LEN: usize = 2 * (CIPHERTEXT_BYTES + MSG_LEN);
+
+        impl Init {
+            pub(crate) fn serialize_to_buffer(&self, buffer: &mut Vec<u8>) {
+                buffer.extend(self.0);
+            }
+
+            pub(crate) fn deserialize_from_buffer(
+                buffer: &mut slice::Iter<u8>,
+            ) -> Result<Self, Error> {
+                Ok(Self(read_from_buffer(buffer)?))
+            }
+        }
+
+        impl Keys {
+            pub(crate) fn serialize_to_buffer(&self, buffer: &mut Vec<u8>) {
+                buffer.extend(self.rho);
+                buffer.extend(self.r[0]);
+                buffer.extend(self.r[1]);
+            }
+
+            /// Fails unless all coefficients of `r_0, r_1` are reduced, as required for the
+            /// encapsulation keys of ML-KEM.
+            pub(crate) fn deserialize_from_buffer(
+                buffer: &mut slice::Iter<u8>,
+            ) -> Result<Self, Error> {
+                let rho = read_from_buffer(buffer)?;
+                let r = Box::new([read_from_buffer(buffer)?, read_from_buffer(buffer)?]);
+                if !r.iter().all(|r| super::decode(r).is_some()) {
+                    return Err(Error::OtInitDeserializationError);
+                }
+                Ok(Self { rho, r })
+            }
+        }
+
+        impl InitReply {
+            pub(crate) fn serialize_to_buffer(&self, buffer: &mut Vec<u8>) {
+                for b in 0..2 {
+                    buffer.extend(self.ciphertexts[b]);
+                    buffer.extend(self.masked[b]);
+                }
+            }
+
+            pub(crate) fn deserialize_from_buffer(
+                buffer: &mut slice::Iter<u8>,
+            ) -> Result<Self, Error> {
+                let ct0 = read_from_buffer(buffer)?;
+                let masked0 = read_from_buffer(buffer)?;
+                let ct1 = read_from_buffer(buffer)?;
+                let masked1 = read_from_buffer(buffer)?;
+                Ok(Self {
+                    ciphertexts: Box::new([ct0, ct1]),
+                    masked: [masked0, masked1],
+                })
+            }
+        }
+
+        fn read_from_buffer<const LEN: usize>(
+            buffer: &mut slice::Iter<u8>,
+        ) -> Result<[u8; LEN], Error> {
+            let mut bytes = [0u8; LEN];
+            for b in &mut bytes {
+                *b = *buffer.next().ok_or(Error::OtInitDeserializationError)?;
+            }
+            Ok(bytes)
+        }
+    }
+
+    /// Decodes the 12-bit coefficients of an encoded vector, or `None` if any of them is not
+    /// reduced modulo `q`.
+    fn decode(bytes: &[u8; T_BYTES]) -> Option<Vec<u16>> {
+        let mut coeffs = Vec::with_capacity(COEFFS);
+        for chunk in bytes.chunks_exact(3) {
+            let (b0, b1, b2) = (chunk[0] as u16, chunk[1] as u16, chunk[2] as u16);
+            coeffs.push(b0 | ((b1 & 0x0f) << 8));
+            coeffs.push((b1 >> 4) | (b2 << 4));
+        }
+        if coeffs.iter().all(|&c| c < Q) {
+            Some(coeffs)
+        } else {
+            None
+        }
+    }
+
+    /// Encodes the coefficients of a vector using 12 bits each, like ML-KEM.
+    fn encode(coeffs: &[u16]) -> [u8; T_BYTES] {
+        let mut bytes = [0u8; T_BYTES];
+        for (chunk, c) in bytes.chunks_exact_mut(3).zip(coeffs.chunks_exact(2)) {
+            chunk[0] = c[0] as u8;
+            chunk[1] = ((c[0] >> 8) | (c[1] << 4)) as u8;
+            chunk[2] = (c[1] >> 4) as u8;
+        }
+        bytes
+    }
+
+    /// Samples a vector with uniformly random coefficients modulo `q` from the output of a XOF.
+    fn sample_uniform(xof: &mut blake3::OutputReader) -> [u8; T_BYTES] {
+        let mut coeffs = Vec::with_capacity(COEFFS);
+        let mut chunk = [0u8; 3];
+        while coeffs.len() < COEFFS {
+            xof.fill(&mut chunk);
+            let d1 = chunk[0] as u16 | ((chunk[1] as u16 & 0x0f) << 8);
+            let d2 = (chunk[1] as u16 >> 4) | ((chunk[2] as u16) << 4);
+            for d in [d1, d2] {
+                if d < Q && coeffs.len() < COEFFS {
+                    coeffs.push(d);
+                }
+            }
+        }
+        encode(&coeffs)
+    }
+
+    /// Adds (or subtracts) two encoded vectors coefficient-wise modulo `q`.
+    ///
+    /// Only ever applied to public values, so it does not need to run in constant time.
+    fn add(a: &[u8; T_BYTES], b: &[u8; T_BYTES], subtract: bool) -> [u8; T_BYTES] {
+        let a = decode(a).expect("vectors are always reduced");
+        let b = decode(b).expect("vectors are always reduced");
+        let sum: Vec<u16> = a
+            .iter()
+            .zip(b.iter())
+            .map(|(&a, &b)| {
+                if subtract {
+                    (a + Q - b) % Q
+                } else {
+                    (a + b) % Q
+                }
+            })
+            .collect();
+        encode(&sum)
+    }
+
+    /// Hashes `r` to a vector of uniformly random coefficients, the random oracle `H` of [MR19].
+    ///
+    /// [MR19]: <https://eprint.iacr.org/2019/706.pdf>
+    fn hash_key(seed: &[u8; 32], rho: &[u8; 32], r: &[u8; T_BYTES]) -> [u8; T_BYTES] {
+        let mut hasher = blake3::Hasher::new_derive_key("tandem masny-rindal ot");
+        hasher.update(seed);
+        hasher.update(rho);
+        hasher.update(r);
+        sample_uniform(&mut hasher.finalize_xof())
+    }
+
+    /// Derives the pad that masks `m_b` from the shared key of the encapsulation.
+    fn pad(shared_key: &[u8], b: u8) -> [u8; MSG_LEN] {
+        let mut hasher = blake3::Hasher::new_derive_key("tandem masny-rindal ot pad");
+        hasher.update(&[b]);
+        hasher.update(shared_key);
+        *hasher.finalize().as_bytes()
+    }
+
+    fn xor(a: &[u8; MSG_LEN], b: &[u8; MSG_LEN]) -> [u8; MSG_LEN] {
+        let mut result = [0u8; MSG_LEN];
+        for idx in 0..MSG_LEN {
+            result[idx] = a[idx] ^ b[idx];
+        }
+        result
+    }
+
+    impl Sender {
+        /// Creates a new sender, choosing a random seed for the random oracle.
+        pub(crate) fn new<RNG: RngCore + CryptoRng>(rng: &mut RNG) -> Self {
+            let mut seed = [0u8; 32];
+            rng.fill_bytes(&mut seed);
+            Self { seed }
+        }
+
+        /// Creates an [`message::Init`] message suitable for exchange with a [`Receiver`].
+        pub(crate) fn init_message(&self) -> message::Init {
+            message::Init(self.seed)
+        }
+
+        /// The logical "send" part of the MR protocol, masking `m_b` with a key encapsulated under
+        /// the encapsulation key `r_b + H(r_{1 - b})`.
+        ///
+        /// The randomness of the encapsulation is derived from the (secret and random) messages.
+        pub(crate) fn send(
+            &self,
+            upstream_keys: &message::Keys,
+            messages: &[OtMessage; 2],
+        ) -> message::InitReply {
+            let rho = &upstream_keys.rho;
+            let [r0, r1] = &*upstream_keys.r;
+            let t = [
+                add(r0, &hash_key(&self.seed, rho, r1), false),
+                add(r1, &hash_key(&self.seed, rho, r0), false),
+            ];
+            let mut ciphertexts = Box::new([[0u8; CIPHERTEXT_BYTES]; 2]);
+            let mut masked = [[0u8; MSG_LEN]; 2];
+            for b in 0..2 {
+                let mut key = Vec::with_capacity(T_BYTES + 32);
+                key.extend(t[b]);
+                key.extend(rho);
+                let key = Encoded::<EncapsulationKey>::try_from(&key[..])
+                    .expect("encapsulation keys have a fixed size");
+                let key = EncapsulationKey::from_bytes(&key);
+
+                let mut hasher = blake3::Hasher::new_derive_key("tandem masny-rindal ot coins");
+                hasher.update(&self.seed);
+                hasher.update(&[b as u8]);
+                hasher.update(&messages[b]);
+                let coins = B32::from(*hasher.finalize().as_bytes());
+                let (ciphertext, shared_key) = key
+                    .encapsulate_deterministic(&coins)
+                    .expect("encapsulation is infallible");
+
+                ciphertexts[b].copy_from_slice(&ciphertext);
+                masked[b] = xor(&messages[b], &pad(&shared_key, b as u8));
+            }
+            message::InitReply {
+                ciphertexts,
+                masked,
+            }
+        }
+    }
+
+    impl Receiver {
+        /// Generates an ML-KEM key pair and hides the vector `t` of its encapsulation key as `r_c`.
+        pub(crate) fn init<RNG: RngCore + CryptoRng>(
+            rng: &mut RNG,
+            upstream_init: &message::Init,
+            choice: bool,
+        ) -> (message::Keys, Receiver) {
+            let seed = upstream_init.0;
+            let mut key_seeds = ([0u8; 32], [0u8; 32]);
+            rng.fill_bytes(&mut key_seeds.0);
+            rng.fill_bytes(&mut key_seeds.1);
+            let (_, key) =
+                MlKem768::generate_deterministic(&key_seeds.0.into(), &key_seeds.1.into());
+            let key = key.as_bytes();
+            let mut t = [0u8; T_BYTES];
+            t.copy_from_slice(&key[..T_BYTES]);
+            let mut rho = [0u8; 32];
+            rho.copy_from_slice(&key[T_BYTES..]);
+
+            let mut other_seed = [0u8; 32];
+            rng.fill_bytes(&mut other_seed);
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(&other_seed);
+            let r_other = sample_uniform(&mut hasher.finalize_xof());
+            let r_chosen = add(&t, &hash_key(&seed, &rho, &r_other), true);
+
+            let r = if choice {
+                [r_other, r_chosen]
+            } else {
+                [r_chosen, r_other]
+            };
+            let keys = message::Keys {
+                rho,
+                r: Box::new(r),
+            };
+            (keys, Receiver { key_seeds, choice })
+        }
+
+        /// The logical "receive" part of the MR protocol, decapsulating the key that masks the
+        /// chosen message.
+        pub(crate) fn recv(self, upstream_init_reply: &message::InitReply) -> OtMessage {
+            let b = self.choice as usize;
+            let (key, _) = MlKem768::generate_deterministic(
+                &self.key_seeds.0.into(),
+                &self.key_seeds.1.into(),
+            );
+            let ciphertext =
+                Ciphertext::<MlKem768>::try_from(&upstream_init_reply.ciphertexts[b][..])
+                    .expect("ciphertexts have a fixed size");
+            let shared_key = key
+                .decapsulate(&ciphertext)
+                .expect("decapsulation is infallible");
+            xor(&upstream_init_reply.masked[b], &pad(&shared_key, b as u8))
+        }
+    }
+
+    #[test]
+    fn test_mr19() {
+        use rand::SeedableRng;
+        use rand_chacha::ChaCha20Rng;
+
+        let mut rng_send = ChaCha20Rng::from_entropy();
+        let mut rng_recv = ChaCha20Rng::from_entropy();
+
+        for choice in [false, true] {
+            let mut messages = [OtMessage::default(); 2];
+            rng_send.fill_bytes(&mut messages[0]);
+            rng_send.fill_bytes(&mut messages[1]);
+
+            let s = Sender::new(&mut rng_send);
+            let (keys, r) = Receiver::init(&mut rng_recv, &s.init_message(), choice);
+
+            let mut buffer = vec![];
+            keys.serialize_to_buffer(&mut buffer);
+            assert_eq!(buffer.len(), message::KEYS_LEN);
+            let keys = message::Keys::deserialize_from_buffer(&mut buffer.iter()).unwrap();
+
+            let reply = s.send(&keys, &messages);
+            let mut buffer = vec![];
+            reply.serialize_to_buffer(&mut buffer);
+            assert_eq!(buffer.len(), message::INIT_REPLY_LEN);
+            let reply = message::InitReply::deserialize_from_buffer(&mut buffer.iter()).unwrap();
+
+            let other = Receiver {
+                choice: !choice,
+                ..r.clone()
+            };
+            assert_eq!(r.recv(&reply), messages[choice as usize]);
+            assert_ne!(other.recv(&reply), messages[!choice as usize]);
+        }
+    }
+
+    #[test]
+    fn test_unreduced_keys() {
+        let mut buffer = vec![0u8; message::KEYS_LEN];
+        buffer[32] = 0xff;
+        buffer[33] = 0x0f;
+        assert!(message::Keys::deserialize_from_buffer(&mut buffer.iter()).is_err());
+    }
+}
+
 #[test]
 fn test_abklx21() {
     use rand::RngCore;
@@ -254,5 +682,13 @@ fn test_abklx21() {
 
         assert_eq!(key, messages[choice as usize]);
         assert_ne!(key, messages[if choice { 0 } else { 1 }]);
+
+        let (msg, r) = Receiver::init(&mut rng_recv, &init, choice);
+        let reply = s.send_tweaked(&msg, &messages, &[1]);
+        assert_eq!(
+            r.clone().recv_tweaked(reply, &[1]),
+            messages[choice as usize]
+        );
+        assert_ne!(r.recv_tweaked(reply, &[2]), messages[choice as usize]);
     }
 }
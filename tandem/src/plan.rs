@@ -0,0 +1,260 @@
+//! Size estimates of the messages exchanged during the MPC protocol.
+//!
+//! The sizes of all messages are fully determined by the shape of the circuit (the number of AND
+//! gates, input and output bits), which allows transports to preallocate buffers and to reject
+//! oversized messages before reading them into memory.
+
+#[cfg(feature = "post-quantum")]
+use crate::ot_base::pq::message::{INIT_REPLY_LEN, KEYS_LEN};
+use crate::{
+    leakydelta_ot::BLOCK_SIZE, silent_ot::SilentParams, states::STEPS, BaseOt, CircuitSource,
+    OtBackend, OtExtension, ProtocolOptions,
+};
+
+/// Number of bytes used by bincode to encode the length of a `Vec`.
+const LEN: usize = 8;
+/// Number of bytes of an encoded `(GateIndex, PartialBitShare)`.
+const MASK_SHARE: usize = 4 + 16 + 1;
+/// Number of bytes of an encoded `(GateIndex, WireLabel, bool)`.
+const INPUT_LABEL: usize = 4 + 16 + 1;
+/// Number of bytes of an encoded `(GateIndex, [BitShare; 4])`.
+const TABLE_SHARE: usize = 4 + 4 * (16 + 16 + 1);
+/// Number of bytes of the serialized OT init message of the base OT protocol, including the tag of
+/// the base OT protocol.
+const OT_INIT: usize = 4 + LEN + 32 * 128;
+/// Number of bytes of the serialized OT init reply of the base OT protocol.
+const OT_INIT_REPLY: usize = 2 * 32 * 128;
+/// Number of bytes of a coin tossing commitment or coin share.
+const COIN: usize = 32;
+/// Number of bytes of the encoded [`ProtocolOptions`].
+const OPTIONS: usize = 4 + 4 + 4;
+/// Number of bytes of the encoded values of the OT correlation check.
+const CORRELATION_CHECK: usize = 2 * 16;
+/// Number of bytes of a single serialized message of the base OT protocol.
+const BASE_OT: usize = 32;
+/// Number of bytes of the hash of the silent OT consistency check.
+const SILENT_CHECK: usize = 32;
+
+/// The expected size of the messages exchanged at a single step of the protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageSizeHint {
+    /// Size in bytes of the message sent by the [`crate::states::Contributor`] at this step.
+    pub contributor: usize,
+    /// Size in bytes of the reply sent by the [`crate::states::Evaluator`] at this step.
+    ///
+    /// The final message of the contributor does not have a reply, its size is always `0`.
+    pub evaluator: usize,
+}
+
+/// Describes the messages exchanged during an MPC execution of a specific [`crate::Circuit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolPlan {
+    hints: Vec<MessageSizeHint>,
+}
+
+impl ProtocolPlan {
+    /// Derives the expected message sizes of each protocol step from the shape of the circuit,
+    /// using the default [`ProtocolOptions`].
+    pub fn new(circuit: &impl CircuitSource) -> Self {
+        Self::with_options(circuit, &ProtocolOptions::default())
+    }
+
+    /// Derives the expected message sizes like [`ProtocolPlan::new`], using the options negotiated
+    /// by both parties (see [`ProtocolOptions::negotiate`]).
+    ///
+    /// The sizes assume that both parties proposed the negotiated options. A party proposing
+    /// [`OtBackend::Silent`] that is not negotiated sends `8 + 32` additional bytes in its first
+    /// message.
+    pub fn with_options(circuit: &impl CircuitSource, options: &ProtocolOptions) -> Self {
+        let ands = circuit.and_gates();
+        let contrib_inputs = circuit.contrib_inputs();
+        let eval_inputs = circuit.eval_inputs();
+        let outputs = circuit.output_gates().len();
+        let bucket_size = crate::states::bucket_size(circuit);
+
+        let (blocks, triple_blocks) = crate::states::abit_blocks(circuit);
+        // number of authenticated AND triples before bucketing:
+        let triples = triple_blocks * 128;
+        // number of bucketed triples that need to be checked:
+        let bucketed = ands * bucket_size;
+
+        // the `Option`s of the silent OT messages are encoded as 1 byte, followed by the value:
+        let (silent_init, silent_choices, silent_reply, silent_correction, silent_check) =
+            match options.ot_backend {
+                OtBackend::Extension => (1, 1, 1, 1, 1),
+                OtBackend::Silent => {
+                    let params = SilentParams::new(blocks * BLOCK_SIZE);
+                    (
+                        1 + LEN + BASE_OT,
+                        1 + LEN + params.base_ots() * BASE_OT,
+                        1 + LEN + params.base_ots() * 2 * BASE_OT + LEN + params.trees() * 16,
+                        1 + 16,
+                        1 + SILENT_CHECK,
+                    )
+                }
+            };
+
+        // the first base OT message is the same for both base OTs, only the replies differ:
+        let (base_ot_keys, base_ot_reply) = match options.base_ot {
+            BaseOt::ChouOrlandi => (OT_INIT, OT_INIT_REPLY),
+            #[cfg(feature = "post-quantum")]
+            BaseOt::PostQuantum => (4 + LEN + 128 * KEYS_LEN, 128 * INIT_REPLY_LEN),
+        };
+
+        let ot_init = OT_INIT + LEN + COIN + silent_init;
+        let coin_share = base_ot_keys + LEN + COIN + OPTIONS + silent_choices;
+        let ot_init_reply = LEN + base_ot_reply + silent_reply;
+        let ot_blocks = LEN
+            + crate::states::ot_blocks(blocks, options) * (LEN + 128 * 16)
+            + match options.ot_extension {
+                OtExtension::Alsz13 => 1,
+                OtExtension::Kos15 => 1 + CORRELATION_CHECK,
+            }
+            + silent_correction;
+        let and_hashes = LEN + triples * 2 * 16 + silent_check;
+        let u = LEN + triples * 16;
+        let w = LEN + triples * 16 + LEN + triples * 2 * 16;
+        let bucketing = LEN + bucketed + LEN + bucketed * 16;
+        let and_bits = LEN + ands + LEN + ands;
+        let tables = LEN + ands * TABLE_SHARE + LEN + eval_inputs * MASK_SHARE;
+
+        let contributor = [
+            ot_init,
+            LEN + coin_share + LEN + ot_init_reply,
+            LEN + ot_blocks + LEN + and_hashes,
+            LEN + triple_blocks * 16,
+            LEN + u + LEN + w,
+            LEN + w + LEN + bucketing,
+            LEN + and_bits + LEN + tables,
+            LEN + (contrib_inputs + eval_inputs) * INPUT_LABEL + LEN + outputs * MASK_SHARE,
+        ];
+        let evaluator = [
+            LEN + ot_init + LEN + coin_share,
+            LEN + ot_init_reply + LEN + ot_blocks,
+            and_hashes,
+            LEN + LEN + triple_blocks * 16 + LEN + u,
+            LEN + w + LEN + w,
+            LEN + bucketing + LEN + and_bits,
+            LEN + contrib_inputs * MASK_SHARE + LEN + eval_inputs * 5,
+        ];
+        debug_assert_eq!(evaluator.len(), STEPS as usize);
+
+        let hints = contributor
+            .iter()
+            .enumerate()
+            .map(|(i, &contributor)| MessageSizeHint {
+                contributor,
+                evaluator: evaluator.get(i).copied().unwrap_or(0),
+            })
+            .collect();
+        Self { hints }
+    }
+
+    /// Returns the expected message sizes for each step of the protocol.
+    ///
+    /// The hint at index `i` describes the `i`-th message of the contributor (starting with the
+    /// initial message returned by [`crate::states::Contributor::new`]) and the evaluator's reply
+    /// to it.
+    pub fn message_size_hints(&self) -> &[MessageSizeHint] {
+        &self.hints
+    }
+
+    /// Returns the total number of bytes sent by the contributor during the protocol.
+    pub fn total_contributor_bytes(&self) -> usize {
+        self.hints.iter().map(|h| h.contributor).sum()
+    }
+
+    /// Returns the total number of bytes sent by the evaluator during the protocol.
+    pub fn total_evaluator_bytes(&self) -> usize {
+        self.hints.iter().map(|h| h.evaluator).sum()
+    }
+}
+
+#[test]
+fn test_message_size_hints() {
+    use crate::{
+        states::{Contributor, Evaluator},
+        Circuit, Gate,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    let circuits = [
+        Circuit::new(
+            vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+            vec![2],
+        ),
+        Circuit::new(
+            vec![
+                Gate::InContrib,
+                Gate::InContrib,
+                Gate::InEval,
+                Gate::Xor(0, 2),
+                Gate::And(1, 3),
+                Gate::Not(4),
+                Gate::And(5, 2),
+            ],
+            vec![3, 5, 6],
+        ),
+    ];
+
+    let options = [
+        ProtocolOptions::default(),
+        ProtocolOptions {
+            ot_extension: OtExtension::Kos15,
+            ..Default::default()
+        },
+    ];
+    // the silent OT needs thousands of base OTs, which are slow without optimizations:
+    let silent = ProtocolOptions {
+        ot_extension: OtExtension::Kos15,
+        ot_backend: OtBackend::Silent,
+        ..Default::default()
+    };
+    #[allow(unused_mut, clippy::useless_vec)]
+    let mut other_options = vec![silent];
+    #[cfg(feature = "post-quantum")]
+    other_options.push(ProtocolOptions {
+        base_ot: BaseOt::PostQuantum,
+        ..Default::default()
+    });
+    for (circuit, options) in circuits
+        .iter()
+        .flat_map(|c| options.iter().map(move |o| (c, o)))
+        .chain(other_options.iter().map(|o| (&circuits[0], o)))
+    {
+        let plan = ProtocolPlan::with_options(circuit, options);
+        let hints = plan.message_size_hints();
+        let input_contrib = vec![true; circuit.contrib_inputs()];
+        let input_eval = vec![false; circuit.eval_inputs()];
+
+        let mut eval = Evaluator::new_with_options(
+            circuit,
+            input_eval.as_slice(),
+            ChaCha20Rng::from_entropy(),
+            *options,
+        )
+        .unwrap();
+        let (mut contrib, mut msg_for_eval) = Contributor::new_with_options(
+            circuit,
+            input_contrib.as_slice(),
+            ChaCha20Rng::from_entropy(),
+            *options,
+        )
+        .unwrap();
+
+        for hint in hints.iter().take(eval.steps() as usize) {
+            assert_eq!(hint.contributor, msg_for_eval.len());
+            let (next_state, msg_for_contrib) = eval.run(&msg_for_eval).unwrap();
+            eval = next_state;
+            assert_eq!(hint.evaluator, msg_for_contrib.len());
+
+            let (next_state, reply) = contrib.run(&msg_for_contrib).unwrap();
+            contrib = next_state;
+            msg_for_eval = reply;
+        }
+        assert_eq!(hints.last().unwrap().contributor, msg_for_eval.len());
+        assert_eq!(hints.last().unwrap().evaluator, 0);
+        eval.output(&msg_for_eval).unwrap();
+    }
+}
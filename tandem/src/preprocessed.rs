@@ -0,0 +1,293 @@
+//! Export and import of the authenticated AND triples and wire bits generated during preprocessing.
+
+use bincode::{deserialize, serialize};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    types::{BitShare, Delta},
+    Circuit, CircuitSource, Error, Gate, Party, PROTOCOL_VERSION,
+};
+
+/// The key derivation context of the id of the triples that remain after
+/// [`PreprocessedTriples::split_off`], which must match the other party's.
+const REMAINING_TRIPLES_CONTEXT: &str = "tandem 2025-01-01 remaining triples v1";
+
+/// The authenticated AND triples and authenticated wire bits of a single party, as generated by
+/// the function-independent preprocessing of WRK17.
+///
+/// The triples are generated ahead of time by a session started using
+/// [`crate::states::Contributor::new_preprocessing`] and
+/// [`crate::states::Evaluator::new_preprocessing`]. They can later be loaded into a session
+/// using [`crate::states::Contributor::from_preprocessed`] and
+/// [`crate::states::Evaluator::from_preprocessed`], which skips the preprocessing and starts
+/// with the evaluation of the circuit. The bits opened by both parties at the start of such a
+/// session are MAC-checked, so that corrupted triples or triples of different preprocessing
+/// sessions are rejected before any input is processed.
+///
+/// The triples contain the secret MAC keys of the party and must be stored confidentially. They
+/// must never be loaded into more than a single session, since reusing them leaks the inputs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreprocessedTriples {
+    pub(crate) party: Party,
+    pub(crate) id: [u8; 32],
+    pub(crate) delta: Delta,
+    pub(crate) and_triples: Vec<BitShare>,
+    pub(crate) wire_abits: Vec<BitShare>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SerializedTriples {
+    protocol_version: u32,
+    party: Party,
+    id: [u8; 32],
+    delta: u128,
+    and_triples: Vec<BitShare>,
+    wire_abits: Vec<BitShare>,
+}
+
+impl PreprocessedTriples {
+    /// Returns the party that generated the triples.
+    pub fn party(&self) -> Party {
+        self.party
+    }
+
+    /// Returns the maximum number of AND gates of circuits that can be evaluated using the triples.
+    pub fn and_gates(&self) -> usize {
+        self.and_triples.len() / 3
+    }
+
+    /// Returns `true` if the triples suffice for the AND gates and input bits of the circuit.
+    pub fn fits(&self, circuit: &impl CircuitSource) -> bool {
+        let and_gates = circuit.and_gates();
+        let wire_abits = and_gates + circuit.contrib_inputs() + circuit.eval_inputs();
+        self.and_gates() >= and_gates && self.wire_abits.len() >= wire_abits
+    }
+
+    /// Splits off the triples needed by the circuit, keeping the remaining triples for other
+    /// circuits.
+    ///
+    /// Both parties need to split their triples using the same circuits in the same order, so
+    /// that the triples of both parties still belong together. The remaining triples are assigned
+    /// a new id, so that they are never confused with the triples that were split off. Fails with
+    /// [`Error::InvalidPreprocessedTriples`] if the triples do not fit the circuit.
+    pub fn split_off(&mut self, circuit: &impl CircuitSource) -> Result<Self, Error> {
+        if !self.fits(circuit) {
+            return Err(Error::InvalidPreprocessedTriples);
+        }
+        let and_gates = circuit.and_gates();
+        let wire_abits = and_gates + circuit.contrib_inputs() + circuit.eval_inputs();
+        let remaining_and_triples = self.and_triples.split_off(and_gates * 3);
+        let remaining_wire_abits = self.wire_abits.split_off(wire_abits);
+        let split = Self {
+            party: self.party,
+            id: self.id,
+            delta: self.delta.clone(),
+            and_triples: std::mem::replace(&mut self.and_triples, remaining_and_triples),
+            wire_abits: std::mem::replace(&mut self.wire_abits, remaining_wire_abits),
+        };
+        self.id = blake3::derive_key(REMAINING_TRIPLES_CONTEXT, &self.id);
+        Ok(split)
+    }
+
+    /// Serializes the triples, including the [`crate::PROTOCOL_VERSION`] that generated them.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        Ok(serialize(&SerializedTriples {
+            protocol_version: PROTOCOL_VERSION,
+            party: self.party,
+            id: self.id,
+            delta: self.delta.0,
+            and_triples: self.and_triples.clone(),
+            wire_abits: self.wire_abits.clone(),
+        })?)
+    }
+
+    /// Deserializes triples serialized using [`PreprocessedTriples::to_bytes`].
+    ///
+    /// Fails with [`Error::IncompatibleProtocolVersion`] if the triples were generated by a
+    /// different [`crate::PROTOCOL_VERSION`] and with [`Error::InvalidPreprocessedTriples`] if
+    /// the number of triples and wire bits is inconsistent.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let version: u32 = deserialize(bytes)?;
+        if version != PROTOCOL_VERSION {
+            return Err(Error::IncompatibleProtocolVersion { version });
+        }
+        let triples: SerializedTriples = deserialize(bytes)?;
+        if triples.and_triples.len() % 3 != 0
+            || triples.wire_abits.len() < triples.and_triples.len() / 3
+        {
+            return Err(Error::InvalidPreprocessedTriples);
+        }
+        Ok(Self {
+            party: triples.party,
+            id: triples.id,
+            delta: Delta(triples.delta),
+            and_triples: triples.and_triples,
+            wire_abits: triples.wire_abits,
+        })
+    }
+}
+
+/// Returns a circuit that only determines the number of triples generated by
+/// [`crate::states::Contributor::new_preprocessing`] and
+/// [`crate::states::Evaluator::new_preprocessing`], which then suffice for circuits with at most
+/// `and_gates` AND gates and `input_bits` input bits (of both parties) combined.
+///
+/// The circuit is never evaluated, it only consists of contributor inputs and AND gates.
+pub fn preprocessing_circuit(and_gates: usize, input_bits: usize) -> Circuit {
+    let input_bits = input_bits.max(1);
+    let mut gates = vec![Gate::InContrib; input_bits];
+    gates.extend((input_bits..input_bits + and_gates).map(|i| {
+        let prev = i as u32 - 1;
+        Gate::And(prev, prev)
+    }));
+    let output = gates.len() as u32 - 1;
+    Circuit::new(gates, vec![output])
+}
+
+#[test]
+fn test_serialization() {
+    use crate::types::{KeyType, MacType};
+
+    let bit = |i: u128| BitShare {
+        key: KeyType(i),
+        mac: MacType(i << 64),
+        bit: i % 2 == 0,
+    };
+    let triples = PreprocessedTriples {
+        party: Party::Evaluator,
+        id: [3; 32],
+        delta: Delta(42),
+        and_triples: (0..6).map(bit).collect(),
+        wire_abits: (0..4).map(bit).collect(),
+    };
+    let bytes = triples.to_bytes().unwrap();
+    assert_eq!(PreprocessedTriples::from_bytes(&bytes), Ok(triples.clone()));
+    assert_eq!(
+        PreprocessedTriples::from_bytes(&bytes[..bytes.len() - 1]),
+        Err(Error::BincodeError)
+    );
+
+    let mut outdated = bytes.clone();
+    outdated[..4].copy_from_slice(&(PROTOCOL_VERSION - 1).to_le_bytes());
+    assert_eq!(
+        PreprocessedTriples::from_bytes(&outdated),
+        Err(Error::IncompatibleProtocolVersion {
+            version: PROTOCOL_VERSION - 1
+        })
+    );
+
+    let incomplete = PreprocessedTriples {
+        and_triples: triples.and_triples[..5].to_vec(),
+        ..triples
+    };
+    assert_eq!(
+        PreprocessedTriples::from_bytes(&incomplete.to_bytes().unwrap()),
+        Err(Error::InvalidPreprocessedTriples)
+    );
+}
+
+#[test]
+fn test_corrupted_triples() -> Result<(), Error> {
+    use crate::{
+        states::{Contributor, Evaluator},
+        Circuit, Gate, ProtocolOptions,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    let circuit = Circuit::new(
+        vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+        vec![2],
+    );
+    let options = ProtocolOptions::default();
+    let mut eval = Evaluator::new_preprocessing(&circuit, ChaCha20Rng::from_entropy(), options)?;
+    let (mut contrib, mut msg) =
+        Contributor::new_preprocessing(&circuit, ChaCha20Rng::from_entropy(), options)?;
+    for _ in 0..eval.steps() {
+        let (next_state, reply) = eval.run(&msg)?;
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&reply)?;
+        contrib = next_state;
+        msg = reply;
+    }
+    let mut contrib = contrib.preprocessed()?;
+    let eval = eval.preprocessed()?;
+    contrib.and_triples[0].mac.0 ^= 1;
+
+    let eval = Evaluator::from_preprocessed(&circuit, [true], ChaCha20Rng::from_entropy(), eval)?;
+    let (_, msg) =
+        Contributor::from_preprocessed(&circuit, [true], ChaCha20Rng::from_entropy(), contrib)?;
+    assert!(matches!(eval.run(&msg), Err(Error::MacError)));
+    Ok(())
+}
+
+#[test]
+fn test_split_off() -> Result<(), Error> {
+    use crate::states::{Contributor, Evaluator};
+    use crate::ProtocolOptions;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+
+    let and = Circuit::new(
+        vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+        vec![2],
+    );
+    let xor_and = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::Xor(0, 1),
+            Gate::And(0, 1),
+            Gate::And(2, 3),
+        ],
+        vec![2, 4],
+    );
+    let capacity = preprocessing_circuit(3, 4);
+    assert_eq!(capacity.and_gates(), 3);
+    assert_eq!(capacity.contrib_inputs(), 4);
+
+    let options = ProtocolOptions::default();
+    let mut eval = Evaluator::new_preprocessing(&capacity, ChaCha20Rng::from_entropy(), options)?;
+    let (mut contrib, mut msg) =
+        Contributor::new_preprocessing(&capacity, ChaCha20Rng::from_entropy(), options)?;
+    for _ in 0..eval.steps() {
+        let (next_state, reply) = eval.run(&msg)?;
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&reply)?;
+        contrib = next_state;
+        msg = reply;
+    }
+    let mut contrib_triples = contrib.preprocessed()?;
+    let mut eval_triples = eval.preprocessed()?;
+
+    // each split of the triples runs its own session:
+    for (circuit, input, expected) in [
+        (&and, [true, true], vec![true]),
+        (&xor_and, [true, false], vec![true, false]),
+    ] {
+        let contrib = contrib_triples.split_off(circuit)?;
+        let eval = eval_triples.split_off(circuit)?;
+        assert_ne!(contrib.id, contrib_triples.id);
+        let rng = ChaCha20Rng::from_entropy();
+        let mut eval = Evaluator::from_preprocessed(circuit, [input[1]], rng, eval)?;
+        let rng = ChaCha20Rng::from_entropy();
+        let (mut contrib, mut msg) =
+            Contributor::from_preprocessed(circuit, [input[0]], rng, contrib)?;
+        for _ in 0..eval.steps() {
+            let (next_state, reply) = eval.run(&msg)?;
+            eval = next_state;
+            let (next_state, reply) = contrib.run(&reply)?;
+            contrib = next_state;
+            msg = reply;
+        }
+        assert_eq!(eval.output(&msg)?, expected);
+    }
+
+    // the remaining triples do not suffice for another session with 2 AND gates:
+    assert_eq!(contrib_triples.and_gates(), 0);
+    assert_eq!(
+        contrib_triples.split_off(&xor_and),
+        Err(Error::InvalidPreprocessedTriples)
+    );
+    Ok(())
+}
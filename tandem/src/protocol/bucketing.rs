@@ -0,0 +1,136 @@
+//! Derives the permutation of the leaky AND triples that assigns them to buckets in `Π_{aAND}`.
+//!
+//! Both parties must derive the identical permutation from the result of the coin tossing. To
+//! make this easy to verify independently, the derivation is a textbook Fisher–Yates shuffle:
+//!
+//!   1. A [`ChaCha20Rng`] is seeded with the 32 bytes of the coin (stream 0, word position 0).
+//!   2. The permutation starts out as the identity `[0, 1, ..., n - 1]`.
+//!   3. For `i` from `n - 1` down to `1`, an index `j` is sampled uniformly from `[0, i]` and the
+//!      elements at `i` and `j` are swapped.
+//!
+//! The index `j` is sampled by drawing little-endian `u32` values using [`RngCore::next_u32`],
+//! rejecting all values `x >= 2^32 - (2^32 mod (i + 1))` and returning `x mod (i + 1)` for the
+//! first value that is accepted. Rejecting the incomplete last interval ensures that all indices
+//! are equally likely, so that the resulting permutation is uniformly distributed.
+//!
+//! Apart from the permutation itself, no memory proportional to `n` is allocated.
+
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+
+use super::cointossing::CoinResult;
+
+/// Returns a uniformly random permutation of `0..len`, derived from the coin.
+pub(crate) fn bucket_permutation(coin: CoinResult, len: usize) -> Vec<u32> {
+    assert!(len <= u32::MAX as usize, "too many AND triples to permute");
+    let mut rng = ChaCha20Rng::from_seed(coin);
+    let mut permutation: Vec<u32> = (0..len as u32).collect();
+    for i in (1..len).rev() {
+        let j = sample_index(&mut rng, i as u32 + 1);
+        permutation.swap(i, j as usize);
+    }
+    permutation
+}
+
+/// Samples an index uniformly from `0..bound` by rejection sampling.
+fn sample_index(rng: &mut impl RngCore, bound: u32) -> u32 {
+    debug_assert!(bound > 0);
+    let zone = (1u64 << 32) - (1u64 << 32) % u64::from(bound);
+    loop {
+        let x = rng.next_u32();
+        if u64::from(x) < zone {
+            return x % bound;
+        }
+    }
+}
+
+#[test]
+fn test_permutation_is_deterministic() {
+    for len in [0, 1, 2, 17, 1000] {
+        let permutation = bucket_permutation([7; 32], len);
+        assert_eq!(permutation, bucket_permutation([7; 32], len));
+
+        let mut sorted = permutation.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, (0..len as u32).collect::<Vec<_>>());
+    }
+    assert_ne!(
+        bucket_permutation([7; 32], 1000),
+        bucket_permutation([8; 32], 1000)
+    );
+}
+
+#[test]
+fn test_permutation_known_answer() {
+    // Allows implementations in other languages to check that they derive the same permutation.
+    assert_eq!(
+        bucket_permutation([0; 32], 10),
+        vec![6, 8, 2, 7, 9, 5, 1, 0, 3, 4]
+    );
+}
+
+#[test]
+fn test_sample_index_rejects_incomplete_interval() {
+    struct Fixed(Vec<u32>);
+
+    impl RngCore for Fixed {
+        fn next_u32(&mut self) -> u32 {
+            self.0.remove(0)
+        }
+        fn next_u64(&mut self) -> u64 {
+            unimplemented!()
+        }
+        fn fill_bytes(&mut self, _: &mut [u8]) {
+            unimplemented!()
+        }
+        fn try_fill_bytes(&mut self, _: &mut [u8]) -> Result<(), rand::Error> {
+            unimplemented!()
+        }
+    }
+
+    // 2^32 mod 3 = 1, so only u32::MAX lies in the incomplete interval and is rejected:
+    let mut rng = Fixed(vec![u32::MAX, u32::MAX - 1]);
+    assert_eq!(sample_index(&mut rng, 3), (u32::MAX - 1) % 3);
+    let mut rng = Fixed(vec![u32::MAX, 7]);
+    assert_eq!(sample_index(&mut rng, 3), 1);
+    let mut rng = Fixed(vec![u32::MAX]);
+    assert_eq!(sample_index(&mut rng, 1 << 31), (1 << 31) - 1);
+}
+
+#[test]
+fn test_permutation_is_uniform() {
+    // Chi-squared test over all 4! = 24 permutations of 4 elements, using distinct coins. With
+    // 23 degrees of freedom, a uniform distribution exceeds 70 with a probability below 10^-6.
+    const SAMPLES: usize = 24_000;
+    let mut counts = std::collections::HashMap::new();
+    for i in 0..SAMPLES {
+        let mut coin = [0; 32];
+        coin[..8].copy_from_slice(&(i as u64).to_le_bytes());
+        *counts.entry(bucket_permutation(coin, 4)).or_insert(0usize) += 1;
+    }
+    assert_eq!(counts.len(), 24);
+    let expected = (SAMPLES / 24) as f64;
+    let chi_squared: f64 = counts
+        .values()
+        .map(|&c| (c as f64 - expected).powi(2) / expected)
+        .sum();
+    assert!(chi_squared < 70.0, "chi-squared statistic {chi_squared}");
+}
+
+#[test]
+fn test_sample_index_is_uniform() {
+    // Chi-squared test for a bound that is not a power of 2, with 6 degrees of freedom a uniform
+    // distribution exceeds 40 with a probability below 10^-6.
+    const SAMPLES: usize = 70_000;
+    let mut rng = ChaCha20Rng::from_seed([1; 32]);
+    let mut counts = [0usize; 7];
+    for _ in 0..SAMPLES {
+        counts[sample_index(&mut rng, 7) as usize] += 1;
+    }
+    let expected = (SAMPLES / 7) as f64;
+    let chi_squared: f64 = counts
+        .iter()
+        .map(|&c| (c as f64 - expected).powi(2) / expected)
+        .sum();
+    assert!(chi_squared < 40.0, "chi-squared statistic {chi_squared}");
+}
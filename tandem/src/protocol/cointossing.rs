@@ -9,57 +9,72 @@
 //!   2. calling [`serialize`] on the return coin share (tuple item #1 from [`init`])
 //!   3. finishing the protocol by calling [`finish`] with the other party's coin commitment and
 //!      coin share messages
-use crate::Error;
+//!
+//! Each party also commits to the [`ProtocolOptions`] it proposes, which are disclosed together with
+//! its coin share, so that neither party can adapt its options to the options of the other party.
+use serde::{Deserialize, Serialize};
+
+use crate::{constant_time::eq_bytes, Error, ProtocolOptions};
 
 /// Number of bits for a coin.
 pub(crate) const COIN_LEN: usize = 32;
 /// Number of bits for a commitment.
 const HASH_LEN: usize = blake3::OUT_LEN;
 
-#[derive(Clone)]
-pub(crate) struct CoinShare([u8; COIN_LEN]);
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct CoinShare([u8; COIN_LEN], ProtocolOptions);
 
 /// Result of the coin tossing protocol.
 pub(crate) type CoinResult = [u8; COIN_LEN];
 
 /// Creates a new coinshare and a message to be shared with another party.
-pub(crate) fn init(coin: [u8; COIN_LEN]) -> Result<(CoinShare, Vec<u8>), Error> {
-    let hash = hash_coinshare(&coin);
+pub(crate) fn init(
+    coin: [u8; COIN_LEN],
+    options: ProtocolOptions,
+) -> Result<(CoinShare, Vec<u8>), Error> {
+    let hash = hash_coinshare(&coin, &options)?;
     let msg = bincode::serialize(&hash)?;
-    let coin_share = CoinShare(coin);
+    let coin_share = CoinShare(coin, options);
     Ok((coin_share, msg))
 }
 
 /// Serializes a CoinShare to be disclosed to another party at the 2nd protocol step.
 pub(crate) fn serialize(cs: &CoinShare) -> Result<Vec<u8>, Error> {
-    let msg = bincode::serialize(&cs.0)?;
+    let msg = bincode::serialize(&(&cs.0, &cs.1))?;
     Ok(msg)
 }
 
-/// Verifies the upstream coinshare and returns the resulting coin.
+/// Verifies the upstream coinshare and returns the resulting coin, together with the negotiated
+/// options.
 pub(crate) fn finish(
     coin_share: CoinShare,
     upstream_hash_msg: Vec<u8>,
     upstream_coin: Vec<u8>,
-) -> Result<CoinResult, Error> {
+) -> Result<(CoinResult, ProtocolOptions), Error> {
     let upstream_hash: [u8; HASH_LEN] = bincode::deserialize(&upstream_hash_msg)?;
-    let upstream_coin: [u8; COIN_LEN] = bincode::deserialize(&upstream_coin)?;
+    let (upstream_coin, upstream_options): ([u8; COIN_LEN], ProtocolOptions) =
+        bincode::deserialize(&upstream_coin)?;
 
-    if upstream_hash != hash_coinshare(&upstream_coin) {
+    if !eq_bytes(
+        &upstream_hash,
+        &hash_coinshare(&upstream_coin, &upstream_options)?,
+    ) {
         return Err(Error::MacError);
     }
 
-    Ok(xor(coin_share.0, upstream_coin))
+    let options = coin_share.1.negotiate(&upstream_options);
+    Ok((xor(coin_share.0, upstream_coin), options))
 }
 
-fn hash_coinshare(s: &[u8; COIN_LEN]) -> [u8; HASH_LEN] {
+fn hash_coinshare(s: &[u8; COIN_LEN], options: &ProtocolOptions) -> Result<[u8; HASH_LEN], Error> {
     let mut hasher = blake3::Hasher::new();
     hasher.update(s);
+    hasher.update(&bincode::serialize(options)?);
     let mut output_reader = hasher.finalize_xof();
     let mut result = [0u8; HASH_LEN];
     output_reader.fill(&mut result);
 
-    result
+    Ok(result)
 }
 
 fn xor(lhs: [u8; COIN_LEN], rhs: [u8; COIN_LEN]) -> CoinResult {
@@ -81,18 +96,18 @@ fn test_coinshare() {
     let coin2 = [!test_val; COIN_LEN];
     let expected = [255u8; COIN_LEN];
 
-    let (coin_share1, commitment_msg1) = init(coin1).unwrap();
+    let (coin_share1, commitment_msg1) = init(coin1, ProtocolOptions::default()).unwrap();
     let coin_msg1 = serialize(&coin_share1).unwrap();
 
-    let (coin_share2, commitment_msg2) = init(coin2).unwrap();
+    let (coin_share2, commitment_msg2) = init(coin2, ProtocolOptions::default()).unwrap();
     let coin_msg2 = serialize(&coin_share2).unwrap();
 
     assert_eq!(
-        expected,
+        (expected, ProtocolOptions::default()),
         finish(coin_share1, commitment_msg2, coin_msg2).unwrap()
     );
     assert_eq!(
-        expected,
+        (expected, ProtocolOptions::default()),
         finish(coin_share2, commitment_msg1, coin_msg1).unwrap()
     );
 }
@@ -111,16 +126,17 @@ fn test_coinshare_fail() {
 
     let corruption_index = rng.gen_range(0..COIN_LEN * 8);
 
-    let (coin_share1, _) = init(coin1).unwrap();
-    let (coin_share2_ok, commitment_msg2_ok) = init(coin2.clone()).unwrap();
+    let (coin_share1, _) = init(coin1, ProtocolOptions::default()).unwrap();
+    let (coin_share2_ok, commitment_msg2_ok) = init(coin2, ProtocolOptions::default()).unwrap();
     let coin_msg2_ok = serialize(&coin_share2_ok).unwrap();
 
     // randomly corrupt the coin value by 1 bit and check that the protocol fails
     {
-        let mut coin2 = coin2.clone();
+        let mut coin2 = coin2;
         coin2[corruption_index / 8] ^= 1 << (corruption_index % 8);
 
-        let (coin_share2_nok, commitment_msg2_nok) = init(coin2).unwrap();
+        let (coin_share2_nok, commitment_msg2_nok) =
+            init(coin2, ProtocolOptions::default()).unwrap();
         let coin_msg2_nok = serialize(&coin_share2_nok).unwrap();
 
         assert_eq!(
@@ -142,3 +158,34 @@ fn test_coinshare_fail() {
         );
     }
 }
+
+#[test]
+fn test_coinshare_options() {
+    use crate::OtExtension;
+
+    let kos15 = ProtocolOptions {
+        ot_extension: OtExtension::Kos15,
+        ..Default::default()
+    };
+    let (coin_share1, commitment_msg1) = init([1; COIN_LEN], ProtocolOptions::default()).unwrap();
+    let coin_msg1 = serialize(&coin_share1).unwrap();
+    let (coin_share2, commitment_msg2) = init([2; COIN_LEN], kos15).unwrap();
+    let coin_msg2 = serialize(&coin_share2).unwrap();
+
+    assert_eq!(
+        finish(coin_share1.clone(), commitment_msg2.clone(), coin_msg2).unwrap(),
+        ([3; COIN_LEN], kos15)
+    );
+    assert_eq!(
+        finish(coin_share2, commitment_msg1, coin_msg1).unwrap(),
+        ([3; COIN_LEN], kos15)
+    );
+
+    // the options cannot be changed after committing to them:
+    let (coin_share2_nok, _) = init([2; COIN_LEN], ProtocolOptions::default()).unwrap();
+    let coin_msg2_nok = serialize(&coin_share2_nok).unwrap();
+    assert_eq!(
+        Err(Error::MacError),
+        finish(coin_share1, commitment_msg2, coin_msg2_nok)
+    );
+}
@@ -1 +1,2 @@
+pub(crate) mod bucketing;
 pub(crate) mod cointossing;
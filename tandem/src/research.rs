@@ -0,0 +1,125 @@
+//! Read-only access to the secret intermediate values of the protocol, for research only.
+//!
+//! Only available with the `research` feature. The accessors in this module expose the
+//! authenticated AND triples and wire bits generated during preprocessing (see
+//! [`PreprocessedTriples`]) and the wire masks and labels of a running session, so that
+//! alternative garbling schemes can be benchmarked on the same preprocessing outputs.
+//!
+//! # Security
+//!
+//! The values returned here include the party's global MAC key, its MAC keys and its wire labels.
+//! Anyone who learns them can forge MACs and decrypt the garbled circuit, which breaks the
+//! security of the protocol completely and reveals the inputs of both parties. The accessors must
+//! therefore only ever be used in a local sandbox, never in a deployment handling real inputs.
+//! The `research` feature should never be enabled in release builds: the Tandem HTTP server
+//! refuses to start if it was compiled in release mode with this feature, see
+//! [`crate::RESEARCH_ACCESSORS`].
+
+use crate::{
+    states::{Contributor, Evaluator},
+    types::{BitShare, Delta, WireMask as WireMaskShare},
+    CircuitSource, InputSource, PreprocessedTriples,
+};
+
+/// A party's share of a bit that is authenticated using information-theoretic MACs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthenticatedBit {
+    /// The party's share of the bit.
+    pub bit: bool,
+    /// The MAC of the party's share, which the other party can check using its key.
+    pub mac: u128,
+    /// The party's MAC key for the other party's share of the bit.
+    pub key: u128,
+}
+
+impl From<&BitShare> for AuthenticatedBit {
+    fn from(share: &BitShare) -> Self {
+        Self {
+            bit: share.bit,
+            mac: share.mac.0,
+            key: share.key.0,
+        }
+    }
+}
+
+/// A party's share of an authenticated AND triple, with `z = x & y` for the bits shared between
+/// both parties.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AndTriple {
+    /// The share of the first input bit.
+    pub x: AuthenticatedBit,
+    /// The share of the second input bit.
+    pub y: AuthenticatedBit,
+    /// The share of the AND of both input bits.
+    pub z: AuthenticatedBit,
+}
+
+/// A party's share of the mask of a wire, together with the party's labels for the wire.
+///
+/// Only the labels of the [`Contributor`] (the garbler) are used by the protocol, the labels of
+/// the [`Evaluator`] are random but unused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WireMask {
+    /// The party's share of the mask of the wire.
+    pub mask: AuthenticatedBit,
+    /// The label of the wire if its masked value is `false`.
+    pub label_0: u128,
+    /// The label of the wire if its masked value is `true`, which differs from `label_0` by the
+    /// global key.
+    pub label_1: u128,
+}
+
+fn wire_masks(delta: &Delta, masks: &[WireMaskShare]) -> Vec<WireMask> {
+    masks
+        .iter()
+        .map(|mask| WireMask {
+            mask: AuthenticatedBit::from(&mask.bit),
+            label_0: mask.label_0.0,
+            label_1: mask.label_0.0 ^ delta.0,
+        })
+        .collect()
+}
+
+impl PreprocessedTriples {
+    /// Returns the party's global MAC key, which is also the offset between its wire labels.
+    pub fn global_key(&self) -> u128 {
+        self.delta.0
+    }
+
+    /// Returns the party's shares of the authenticated AND triples.
+    pub fn and_triples(&self) -> Vec<AndTriple> {
+        self.and_triples
+            .chunks_exact(3)
+            .map(|triple| AndTriple {
+                x: AuthenticatedBit::from(&triple[0]),
+                y: AuthenticatedBit::from(&triple[1]),
+                z: AuthenticatedBit::from(&triple[2]),
+            })
+            .collect()
+    }
+
+    /// Returns the party's shares of the authenticated bits used to mask the input wires and the
+    /// outputs of AND gates.
+    pub fn wire_bits(&self) -> Vec<AuthenticatedBit> {
+        self.wire_abits.iter().map(AuthenticatedBit::from).collect()
+    }
+}
+
+impl<C: CircuitSource, I: InputSource> Contributor<C, I> {
+    /// Returns the contributor's wire masks and labels for every gate of the circuit, indexed by
+    /// gate, or `None` if the masks have not been assigned yet (during the preprocessing) or the
+    /// circuit has already been garbled.
+    pub fn wire_masks(&self) -> Option<Vec<WireMask>> {
+        let (delta, masks) = self.wire_masks_state()?;
+        Some(wire_masks(delta, masks))
+    }
+}
+
+impl<C: CircuitSource, I: InputSource> Evaluator<C, I> {
+    /// Returns the evaluator's wire masks for every gate of the circuit, indexed by gate, or `None`
+    /// if the masks have not been assigned yet (during the preprocessing).
+    pub fn wire_masks(&self) -> Option<Vec<WireMask>> {
+        let (delta, masks) = self.wire_masks_state()?;
+        Some(wire_masks(delta, masks))
+    }
+}
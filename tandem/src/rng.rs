@@ -0,0 +1,150 @@
+//! Instrumentation of the randomness consumed by each party.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use rand::{CryptoRng, RngCore};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// A [`ChaCha20Rng`] that counts the random bytes drawn from it.
+///
+/// Clones share the counter, which allows detecting (in debug builds) that a cloned RNG stream is
+/// advanced independently of the original, which would reuse randomness.
+#[derive(Clone)]
+pub(crate) struct PartyRng {
+    rng: ChaCha20Rng,
+    /// Bytes drawn from this stream, by any clone.
+    drawn: Arc<AtomicU64>,
+    /// Bytes drawn by this particular instance (including draws before it was cloned).
+    local: u64,
+}
+
+impl PartyRng {
+    pub(crate) fn new(rng: ChaCha20Rng) -> Self {
+        Self {
+            rng,
+            drawn: Arc::new(AtomicU64::new(0)),
+            local: 0,
+        }
+    }
+
+    #[inline]
+    fn count(&mut self, bytes: usize) {
+        let drawn = self.drawn.fetch_add(bytes as u64, Ordering::Relaxed);
+        debug_assert_eq!(
+            drawn, self.local,
+            "a cloned RNG stream was advanced independently of the original"
+        );
+        self.local += bytes as u64;
+    }
+}
+
+impl RngCore for PartyRng {
+    fn next_u32(&mut self) -> u32 {
+        self.count(4);
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.count(8);
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.count(dest.len());
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.count(dest.len());
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+impl CryptoRng for PartyRng {}
+
+/// The serialized state of a [`PartyRng`], including its counters.
+#[derive(Serialize, Deserialize)]
+struct SerializedRng {
+    rng: ChaCha20Rng,
+    drawn: u64,
+    local: u64,
+}
+
+impl Serialize for PartyRng {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedRng {
+            rng: self.rng.clone(),
+            drawn: self.drawn.load(Ordering::Relaxed),
+            local: self.local,
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Deserializes the RNG with a counter of its own, which is no longer shared with any clones or
+/// with an [`RngUsage`], see [`RngUsage::restore`].
+impl<'de> Deserialize<'de> for PartyRng {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let SerializedRng { rng, drawn, local } = SerializedRng::deserialize(deserializer)?;
+        Ok(Self {
+            rng,
+            drawn: Arc::new(AtomicU64::new(drawn)),
+            local,
+        })
+    }
+}
+
+/// Tracks the random bytes drawn by a party during each phase of the protocol.
+#[derive(Clone)]
+pub(crate) struct RngUsage {
+    counter: Arc<AtomicU64>,
+    per_phase: Vec<u64>,
+}
+
+impl RngUsage {
+    /// Starts tracking the specified RNG, before the initialization phase of the party.
+    pub(crate) fn new(rng: &PartyRng) -> Self {
+        Self {
+            counter: Arc::clone(&rng.drawn),
+            per_phase: vec![],
+        }
+    }
+
+    /// Continues tracking the phases recorded so far, using the restored RNG of the party, or
+    /// assuming that no more bytes will be drawn if the party no longer has an RNG.
+    pub(crate) fn restore(per_phase: Vec<u64>, rng: Option<&PartyRng>) -> Self {
+        let counter = match rng {
+            Some(rng) => Arc::clone(&rng.drawn),
+            None => Arc::new(AtomicU64::new(per_phase.iter().sum())),
+        };
+        Self { counter, per_phase }
+    }
+
+    /// Records the bytes drawn since the end of the last phase.
+    pub(crate) fn end_phase(&mut self) {
+        let total = self.counter.load(Ordering::Relaxed);
+        let previous: u64 = self.per_phase.iter().sum();
+        self.per_phase.push(total - previous);
+    }
+
+    pub(crate) fn per_phase(&self) -> &[u64] {
+        &self.per_phase
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "a cloned RNG stream was advanced independently of the original")]
+fn test_cloned_rng_advanced_independently() {
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = PartyRng::new(ChaCha20Rng::from_entropy());
+    let _: u64 = rng.gen();
+    let mut cloned = rng.clone();
+    let _: u64 = cloned.gen();
+    let _: u64 = rng.gen();
+}
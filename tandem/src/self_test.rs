@@ -0,0 +1,160 @@
+//! Runtime checks of the cryptographic primitives on the host.
+//!
+//! Miscompilations or broken intrinsics on a particular host do not necessarily crash the
+//! process, but can silently corrupt the values exchanged during the protocol, which then only
+//! surfaces as failed MAC checks. The self test runs the primitives against known values so that
+//! such a host can be detected before it accepts any sessions.
+
+use crate::{
+    constant_time, hash,
+    ot_base::{OtMessage, Receiver, Sender},
+    protocol::cointossing::{self, COIN_LEN},
+    simulate, Circuit, Gate, ProtocolOptions,
+};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    panic::catch_unwind,
+    time::{Duration, Instant},
+};
+
+type Check = fn() -> bool;
+
+/// The result of a single check of the self test.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelfTestCheck {
+    /// Name of the checked primitive.
+    pub name: String,
+    /// Whether the primitive behaved as expected (without panicking).
+    pub passed: bool,
+    /// Time spent on the check.
+    pub duration: Duration,
+}
+
+/// The results of all checks run by [`self_test()`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SelfTestReport {
+    /// The individual checks, in the order in which they were run.
+    pub checks: Vec<SelfTestCheck>,
+}
+
+impl SelfTestReport {
+    /// Returns `true` if all checks passed.
+    pub fn passed(&self) -> bool {
+        self.checks.iter().all(|c| c.passed)
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in self.checks.iter() {
+            let result = if check.passed { "ok" } else { "FAILED" };
+            writeln!(f, "{}: {result} ({:?})", check.name, check.duration)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks the cryptographic primitives used by the protocol on the current host.
+///
+/// The self test compares the hash functions against reference values, checks the comparisons of
+/// secret values (see [`crate::timing_audit`]), runs a base OT round trip and a coin tossing
+/// between two local parties and finally simulates a tiny circuit for all of its inputs. It is
+/// intended to be run at startup or as part of a health check, a failed check indicates that the
+/// host cannot be trusted to run the protocol.
+pub fn self_test() -> SelfTestReport {
+    let checks: [(&str, Check); 5] = [
+        ("hash_vectors", hash::matches_reference_values),
+        (
+            "constant_time_eq",
+            constant_time::matches_regular_comparisons,
+        ),
+        ("base_ot", base_ot_round_trip),
+        ("coin_tossing", coin_tossing),
+        ("simulate", simulate_tiny_circuit),
+    ];
+    let checks = checks
+        .into_iter()
+        .map(|(name, check)| {
+            let start = Instant::now();
+            let passed = catch_unwind(check).unwrap_or(false);
+            SelfTestCheck {
+                name: name.to_string(),
+                passed,
+                duration: start.elapsed(),
+            }
+        })
+        .collect();
+    SelfTestReport { checks }
+}
+
+fn base_ot_round_trip() -> bool {
+    let mut rng = ChaCha20Rng::from_entropy();
+    let mut messages = [OtMessage::default(); 2];
+    rng.fill_bytes(&mut messages[0]);
+    rng.fill_bytes(&mut messages[1]);
+    if messages[0] == messages[1] {
+        return false;
+    }
+
+    let sender = Sender::new(&mut rng);
+    let init = sender.init_message();
+    [false, true].into_iter().all(|choice| {
+        let (msg, receiver) = Receiver::init(&mut rng, &init, choice);
+        let reply = sender.send(&msg, &messages);
+        receiver.recv(reply) == messages[choice as usize]
+    })
+}
+
+fn coin_tossing() -> bool {
+    let mut rng = ChaCha20Rng::from_entropy();
+    let mut coin_a = [0; COIN_LEN];
+    let mut coin_b = [0; COIN_LEN];
+    rng.fill_bytes(&mut coin_a);
+    rng.fill_bytes(&mut coin_b);
+
+    let toss = || -> Result<bool, crate::Error> {
+        let options = ProtocolOptions::default();
+        let (share_a, commitment_a) = cointossing::init(coin_a, options)?;
+        let (share_b, commitment_b) = cointossing::init(coin_b, options)?;
+        let msg_a = cointossing::serialize(&share_a)?;
+        let msg_b = cointossing::serialize(&share_b)?;
+        let (result_a, _) = cointossing::finish(share_a, commitment_b, msg_b)?;
+        let (result_b, _) = cointossing::finish(share_b, commitment_a, msg_a)?;
+
+        let mut expected = [0; COIN_LEN];
+        for i in 0..COIN_LEN {
+            expected[i] = coin_a[i] ^ coin_b[i];
+        }
+        Ok(result_a == expected && result_b == expected)
+    };
+    toss().unwrap_or(false)
+}
+
+fn simulate_tiny_circuit() -> bool {
+    let circuit = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::And(0, 1),
+            Gate::Xor(0, 1),
+            Gate::Not(2),
+        ],
+        vec![2, 3, 4],
+    );
+    [(false, false), (false, true), (true, false), (true, true)]
+        .into_iter()
+        .all(|(x, y)| match simulate(&circuit, &[x], &[y]) {
+            Ok(output) => output == vec![x & y, x ^ y, !(x & y)],
+            Err(_) => false,
+        })
+}
+
+#[test]
+fn test_self_test() {
+    let report = self_test();
+    assert_eq!(report.checks.len(), 5);
+    assert!(report.passed(), "{report}");
+}
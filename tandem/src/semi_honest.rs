@@ -0,0 +1,317 @@
+//! A lightweight protocol mode that is only secure against semi-honest parties.
+//!
+//! Implements classic Yao garbling with point-and-permute, free XOR and the half-gates
+//! optimization of [ZRE15], with the labels of the evaluator's inputs transferred via the base OT
+//! protocol of [ABKLX21]. The [`Garbler`] takes the role of the [`crate::states::Contributor`],
+//! the [`Evaluator`] learns the output.
+//!
+//! Compared to the maliciously secure protocol of [`crate::states`], the protocol skips the
+//! preprocessing of authenticated bits entirely and needs only 3 messages, but a party deviating
+//! from the protocol can learn the other party's input or cause an incorrect output without being
+//! detected. It should only be used if both parties trust each other to follow the protocol.
+//!
+//! [ZRE15]: <https://eprint.iacr.org/2014/756.pdf>
+//! [ABKLX21]: <https://eprint.iacr.org/2021/1218.pdf>
+//!
+//! # Examples
+//!
+//! ```
+//! use tandem::{
+//!     semi_honest::{Evaluator, Garbler},
+//!     Circuit, Error, Gate,
+//! };
+//! use rand::SeedableRng;
+//! use rand_chacha::ChaCha20Rng;
+//!
+//! # fn main() -> Result<(), Error> {
+//! let circuit = Circuit::new(
+//!     vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+//!     vec![2],
+//! );
+//!
+//! let (garbler, msg) = Garbler::new(&circuit, &[true], ChaCha20Rng::from_entropy())?;
+//! let evaluator = Evaluator::new(&circuit, vec![true], ChaCha20Rng::from_entropy())?;
+//!
+//! let (evaluator, reply) = evaluator.run(&msg)?;
+//! let final_msg = garbler.run(&reply)?;
+//! assert_eq!(evaluator.output(&final_msg)?, vec![true]);
+//! # Ok(())
+//! # }
+//! ```
+
+use bincode::{deserialize, serialize};
+use rand::Rng;
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    abort::check_abort,
+    ot_base::{
+        message::{Init, InitReply},
+        OtMessage, Receiver as OtReceiver, Sender as OtSender, MSG_LEN,
+    },
+    states::Msg,
+    CircuitSource, Error, Gate, InputSource,
+};
+
+/// The first message of the [`Garbler`], containing the garbled circuit.
+#[derive(Serialize, Deserialize)]
+struct GarbledCircuit {
+    ot_init: Vec<u8>,
+    tables: Vec<[u128; 2]>,
+    inputs: Vec<u128>,
+    decoding: Vec<bool>,
+}
+
+/// The party that garbles the circuit and contributes its input.
+pub struct Garbler {
+    ot: OtSender,
+    delta: u128,
+    eval_labels: Vec<u128>,
+}
+
+/// The party that evaluates the garbled circuit and learns the output.
+pub struct Evaluator<C: CircuitSource, I: InputSource> {
+    circuit: C,
+    input: I,
+    rng: ChaCha20Rng,
+    state: Option<(GarbledCircuit, Vec<OtReceiver>)>,
+}
+
+impl Garbler {
+    /// Garbles the circuit, returning a state and the garbled circuit as the initial message for
+    /// the [`Evaluator`].
+    pub fn new(
+        circuit: &impl CircuitSource,
+        input: &[bool],
+        mut rng: ChaCha20Rng,
+    ) -> Result<(Self, Msg), Error> {
+        crate::source::check(circuit)?;
+        if circuit.contrib_inputs() != input.len() {
+            return Err(Error::InsufficientInput);
+        }
+        let ot = OtSender::new(&mut rng);
+        // the lowest bit of the labels is used as the permute bit, so it must differ between the 2
+        // labels of a wire:
+        let delta = rng.gen::<u128>() | 1;
+
+        let mut labels = Vec::with_capacity(circuit.num_gates());
+        let mut tables = Vec::with_capacity(circuit.and_gates());
+        let mut inputs = Vec::with_capacity(circuit.contrib_inputs());
+        let mut eval_labels = Vec::with_capacity(circuit.eval_inputs());
+        let mut contrib_input = input.iter();
+        for (w, gate) in circuit.iter_gates().enumerate() {
+            let label = match gate {
+                Gate::InContrib => {
+                    let label = rng.gen();
+                    let bit = *contrib_input.next().ok_or(Error::InsufficientInput)?;
+                    inputs.push(if bit { label ^ delta } else { label });
+                    label
+                }
+                Gate::InEval => {
+                    let label = rng.gen();
+                    eval_labels.push(label);
+                    label
+                }
+                Gate::Xor(x, y) => labels[x as usize] ^ labels[y as usize],
+                Gate::Not(x) => labels[x as usize] ^ delta,
+                Gate::And(x, y) => {
+                    let (label, table) =
+                        garble_and(labels[x as usize], labels[y as usize], delta, w);
+                    tables.push(table);
+                    label
+                }
+                Gate::Mux(..) | Gate::Nand(..) => return Err(Error::InvalidCircuit),
+            };
+            labels.push(label);
+        }
+        let decoding = circuit
+            .output_gates()
+            .iter()
+            .map(|&w| labels[w as usize] & 1 == 1)
+            .collect();
+
+        let mut ot_init = vec![];
+        ot.init_message().serialize_to_buffer(&mut ot_init);
+        let msg = serialize(&GarbledCircuit {
+            ot_init,
+            tables,
+            inputs,
+            decoding,
+        })?;
+        let garbler = Self {
+            ot,
+            delta,
+            eval_labels,
+        };
+        Ok((garbler, msg))
+    }
+
+    /// Sends the labels of the evaluator's inputs via OT, based on the choices received from the
+    /// [`Evaluator`], returning the final message of the protocol.
+    pub fn run(self, msg: &[u8]) -> Result<Msg, Error> {
+        check_abort(msg)?;
+        let choices: Vec<u8> = deserialize(msg)?;
+        if choices.len() != self.eval_labels.len() * MSG_LEN {
+            return Err(Error::UnexpectedMessageType);
+        }
+        let mut choices = choices.iter();
+        let mut replies = Vec::with_capacity(self.eval_labels.len() * 2 * MSG_LEN);
+        for (i, &label) in self.eval_labels.iter().enumerate() {
+            let choice = Init::deserialize_from_buffer(&mut choices)?;
+            let messages = [ot_message(label), ot_message(label ^ self.delta)];
+            let reply = self
+                .ot
+                .send_tweaked(&choice, &messages, &(i as u64).to_le_bytes());
+            reply.serialize_to_buffer(&mut replies);
+        }
+        Ok(serialize(&replies)?)
+    }
+}
+
+impl<C: CircuitSource, I: InputSource> Evaluator<C, I> {
+    /// Initializes the evaluator, which waits for the garbled circuit of the [`Garbler`].
+    pub fn new(circuit: C, input: I, rng: ChaCha20Rng) -> Result<Self, Error> {
+        crate::source::check(&circuit)?;
+        if circuit.eval_inputs() != input.len() {
+            return Err(Error::InsufficientInput);
+        }
+        Ok(Self {
+            circuit,
+            input,
+            rng,
+            state: None,
+        })
+    }
+
+    /// Receives the garbled circuit, returning the OT choices for the labels of the evaluator's
+    /// inputs as the reply for the [`Garbler`].
+    pub fn run(mut self, msg: &[u8]) -> Result<(Self, Msg), Error> {
+        check_abort(msg)?;
+        if self.state.is_some() {
+            return Err(Error::ProtocolEnded);
+        }
+        let garbled: GarbledCircuit = deserialize(msg)?;
+        if garbled.tables.len() != self.circuit.and_gates()
+            || garbled.inputs.len() != self.circuit.contrib_inputs()
+            || garbled.decoding.len() != self.circuit.output_gates().len()
+        {
+            return Err(Error::UnexpectedMessageType);
+        }
+        let ot_init = Init::deserialize_from_buffer(&mut garbled.ot_init.iter())?;
+
+        let mut choices = Vec::with_capacity(self.circuit.eval_inputs() * MSG_LEN);
+        let mut receivers = Vec::with_capacity(self.circuit.eval_inputs());
+        for bit in self.input.bits() {
+            let (choice, receiver) = OtReceiver::init(&mut self.rng, &ot_init, bit);
+            choice.serialize_to_buffer(&mut choices);
+            receivers.push(receiver);
+        }
+        self.state = Some((garbled, receivers));
+        let reply = serialize(&choices)?;
+        Ok((self, reply))
+    }
+
+    /// Receives the labels of the evaluator's inputs and evaluates the garbled circuit, returning
+    /// the output of the computation.
+    pub fn output(self, msg: &[u8]) -> Result<Vec<bool>, Error> {
+        check_abort(msg)?;
+        let (garbled, receivers) = self.state.ok_or(Error::ProtocolStillInProgress)?;
+        let replies: Vec<u8> = deserialize(msg)?;
+        if replies.len() != receivers.len() * 2 * MSG_LEN {
+            return Err(Error::UnexpectedMessageType);
+        }
+        let mut replies = replies.iter();
+        let mut eval_inputs = Vec::with_capacity(receivers.len());
+        for (i, receiver) in receivers.into_iter().enumerate() {
+            let reply = InitReply::deserialize_from_buffer(&mut replies)?;
+            let label = receiver.recv_tweaked(reply, &(i as u64).to_le_bytes());
+            let mut bytes = [0; 16];
+            bytes.copy_from_slice(&label[..16]);
+            eval_inputs.push(u128::from_le_bytes(bytes));
+        }
+
+        let mut labels = Vec::with_capacity(self.circuit.num_gates());
+        let mut contrib_inputs = garbled.inputs.iter();
+        let mut eval_inputs = eval_inputs.iter();
+        let mut tables = garbled.tables.iter();
+        for (w, gate) in self.circuit.iter_gates().enumerate() {
+            let label = match gate {
+                Gate::InContrib => *contrib_inputs.next().ok_or(Error::InsufficientInput)?,
+                Gate::InEval => *eval_inputs.next().ok_or(Error::InsufficientInput)?,
+                Gate::Xor(x, y) => labels[x as usize] ^ labels[y as usize],
+                Gate::Not(x) => labels[x as usize],
+                Gate::And(x, y) => {
+                    let table = tables.next().ok_or(Error::UnexpectedMessageType)?;
+                    eval_and(labels[x as usize], labels[y as usize], table, w)
+                }
+                Gate::Mux(..) | Gate::Nand(..) => return Err(Error::InvalidCircuit),
+            };
+            labels.push(label);
+        }
+        let output = self
+            .circuit
+            .output_gates()
+            .iter()
+            .zip(garbled.decoding.iter())
+            .map(|(&w, &d)| (labels[w as usize] & 1 == 1) ^ d)
+            .collect();
+        Ok(output)
+    }
+}
+
+/// Garbles an AND gate using half gates, returning the `false` label of the output wire.
+fn garble_and(a0: u128, b0: u128, delta: u128, w: usize) -> (u128, [u128; 2]) {
+    let (pa, pb) = (a0 & 1 == 1, b0 & 1 == 1);
+    let (ha0, ha1) = (hash_label(a0, 2 * w), hash_label(a0 ^ delta, 2 * w));
+    let (hb0, hb1) = (hash_label(b0, 2 * w + 1), hash_label(b0 ^ delta, 2 * w + 1));
+
+    // garbler half gate:
+    let tg = ha0 ^ ha1 ^ if pb { delta } else { 0 };
+    let wg = ha0 ^ if pa { tg } else { 0 };
+    // evaluator half gate:
+    let te = hb0 ^ hb1 ^ a0;
+    let we = hb0 ^ if pb { te ^ a0 } else { 0 };
+    (wg ^ we, [tg, te])
+}
+
+/// Evaluates an AND gate garbled by [`garble_and`].
+fn eval_and(a: u128, b: u128, table: &[u128; 2], w: usize) -> u128 {
+    let [tg, te] = *table;
+    let wg = hash_label(a, 2 * w) ^ if a & 1 == 1 { tg } else { 0 };
+    let we = hash_label(b, 2 * w + 1) ^ if b & 1 == 1 { te ^ a } else { 0 };
+    wg ^ we
+}
+
+fn hash_label(label: u128, tweak: usize) -> u128 {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&label.to_le_bytes());
+    hasher.update(&(tweak as u64).to_le_bytes());
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&hasher.finalize().as_bytes()[..16]);
+    u128::from_le_bytes(bytes)
+}
+
+fn ot_message(label: u128) -> OtMessage {
+    let mut msg = [0; MSG_LEN];
+    msg[..16].copy_from_slice(&label.to_le_bytes());
+    msg
+}
+
+#[test]
+fn test_half_gates() {
+    use rand::SeedableRng;
+
+    let mut rng = ChaCha20Rng::from_entropy();
+    let delta = rng.gen::<u128>() | 1;
+    let (a0, b0): (u128, u128) = (rng.gen(), rng.gen());
+    let (c0, table) = garble_and(a0, b0, delta, 7);
+    for a in [false, true] {
+        for b in [false, true] {
+            let label_a = if a { a0 ^ delta } else { a0 };
+            let label_b = if b { b0 ^ delta } else { b0 };
+            let expected = if a && b { c0 ^ delta } else { c0 };
+            assert_eq!(eval_and(label_a, label_b, &table, 7), expected);
+        }
+    }
+}
@@ -0,0 +1,511 @@
+//! Implements a silent OT extension in the style of [Ferret], see [`crate::OtBackend::Silent`].
+//!
+//! For each PCG instance, the receiver obtains `t` single-point correlated OTs by puncturing GGM
+//! trees of the sender with the help of `t * h` base OTs. These are expanded together with `k`
+//! correlated OTs from the OT extension into `n = t * 2^h` correlated OTs, using primal LPN with
+//! regular noise. The consistency check of [Ferret] (figure 6) ensures that the sender punctured
+//! its trees consistently.
+//!
+//! [Ferret]: <https://eprint.iacr.org/2020/924.pdf>
+
+use crate::{
+    leakydelta_ot::gf128_mul,
+    ot_base::{
+        message::{Init, InitReply},
+        OtMessage, Receiver as BaseReceiver, Sender as BaseSender,
+    },
+    protocol::cointossing::CoinResult,
+    types::{Delta, KeyType, MacType, K},
+    Error,
+};
+use rand::{CryptoRng, Rng, RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+/// Number of non-zero entries in each column of the LPN matrix.
+const LPN_WEIGHT: usize = 10;
+
+/// Parameters of a single PCG instance, producing `n = t * 2^h` correlated OTs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct LpnParams {
+    n: usize,
+    k: usize,
+    t: usize,
+    h: usize,
+}
+
+/// LPN parameters for 128 bits of security, as used by the Ferret implementation of [emp-ot].
+///
+/// [emp-ot]: <https://github.com/emp-toolkit/emp-ot>
+const LPN_PARAMS: [LpnParams; 3] = [
+    LpnParams {
+        n: 178_944,
+        k: 17_384,
+        t: 699,
+        h: 8,
+    },
+    LpnParams {
+        n: 470_016,
+        k: 32_768,
+        t: 918,
+        h: 9,
+    },
+    LpnParams {
+        n: 10_485_760,
+        k: 452_000,
+        t: 1_280,
+        h: 13,
+    },
+];
+
+/// The size of the silent OT extension for a specific number of correlated OTs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SilentParams {
+    lpn: LpnParams,
+    instances: usize,
+    cots: usize,
+}
+
+impl SilentParams {
+    /// Chooses the LPN parameters that minimize the communication for the number of correlated
+    /// OTs.
+    pub(crate) fn new(cots: usize) -> Self {
+        LPN_PARAMS
+            .iter()
+            .map(|&lpn| Self {
+                lpn,
+                instances: (cots + lpn.n - 1) / lpn.n,
+                cots,
+            })
+            .min_by_key(|params| params.base_cots() * 16 + params.base_ots() * 96)
+            .expect("at least one parameter set")
+    }
+
+    /// Number of GGM trees, i.e. single-point correlated OTs.
+    pub(crate) fn trees(&self) -> usize {
+        self.instances * self.lpn.t
+    }
+
+    /// Number of base OTs used to puncture the GGM trees.
+    pub(crate) fn base_ots(&self) -> usize {
+        self.trees() * self.lpn.h
+    }
+
+    /// Number of correlated OTs of the OT extension that are expanded, including the [`K`]
+    /// correlated OTs consumed by the consistency check.
+    pub(crate) fn base_cots(&self) -> usize {
+        self.instances * self.lpn.k + K
+    }
+
+    fn leaves(&self) -> usize {
+        self.instances * self.lpn.n
+    }
+}
+
+/// Collection of messages exchanged between silent OT sender and receiver.
+pub(crate) mod message {
+    use serde::{Deserialize, Serialize};
+
+    /// The base OT init message of the sender, used for all base OTs.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub(crate) struct SilentInit(pub(super) Vec<u8>);
+
+    /// The base OT init messages of the receiver, encoding its choice of punctured leaves.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub(crate) struct SilentChoices(pub(super) Vec<u8>);
+
+    /// The level sums of all GGM trees of the sender, encrypted using the base OTs, and the sum of
+    /// the leaves of each tree masked with the sender's global key.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub(crate) struct SilentReply {
+        pub(super) ots: Vec<u8>,
+        pub(super) sums: Vec<u128>,
+    }
+}
+
+/// The party holding the global key, obtaining the keys of the correlated OTs.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct SilentSender {
+    params: SilentParams,
+    ot: BaseSender,
+    leaves: Vec<u128>,
+    transcript: [u8; 32],
+}
+
+/// The party choosing the punctured leaves, obtaining the bits and MACs of the correlated OTs.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct SilentReceiver {
+    params: SilentParams,
+    alphas: Vec<usize>,
+    receivers: Vec<BaseReceiver>,
+}
+
+impl SilentSender {
+    /// Starts a new silent OT sender session.
+    pub(crate) fn init<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        params: SilentParams,
+    ) -> (Self, message::SilentInit) {
+        let ot = BaseSender::new(rng);
+        let mut msg = vec![];
+        ot.init_message().serialize_to_buffer(&mut msg);
+        let sender = Self {
+            params,
+            ot,
+            leaves: vec![],
+            transcript: [0; 32],
+        };
+        (sender, message::SilentInit(msg))
+    }
+
+    /// Expands the GGM trees and sends their level sums via the base OTs chosen by the receiver.
+    pub(crate) fn send<R: RngCore + CryptoRng>(
+        &mut self,
+        rng: &mut R,
+        delta: &Delta,
+        m: &message::SilentChoices,
+    ) -> Result<message::SilentReply, Error> {
+        let LpnParams { h, .. } = self.params.lpn;
+        if m.0.len() != self.params.base_ots() * 32 {
+            return Err(Error::OtInitDeserializationError);
+        }
+        let mut choices = m.0.iter();
+        let mut ots = Vec::with_capacity(self.params.base_ots() * 64);
+        let mut sums = Vec::with_capacity(self.params.trees());
+        self.leaves = vec![0; self.params.leaves()];
+        for (tree, leaves) in self.leaves.chunks_exact_mut(1 << h).enumerate() {
+            leaves[0] = rng.gen();
+            for level in 0..h {
+                let nodes = 1 << level;
+                for j in (0..nodes).rev() {
+                    let [left, right] = prg(leaves[j]);
+                    leaves[2 * j] = left;
+                    leaves[2 * j + 1] = right;
+                }
+                let [mut sum0, mut sum1] = [0, 0];
+                for pair in leaves[..2 * nodes].chunks_exact(2) {
+                    sum0 ^= pair[0];
+                    sum1 ^= pair[1];
+                }
+                let index = tree * h + level;
+                let choice = Init::deserialize_from_buffer(&mut choices)?;
+                let reply = self.ot.send_tweaked(
+                    &choice,
+                    &[ot_message(sum0), ot_message(sum1)],
+                    &(index as u64).to_le_bytes(),
+                );
+                reply.serialize_to_buffer(&mut ots);
+            }
+            sums.push(leaves.iter().fold(delta.0, |acc, leaf| acc ^ leaf));
+        }
+        let reply = message::SilentReply { ots, sums };
+        self.transcript = transcript(&reply);
+        Ok(reply)
+    }
+
+    /// Expands the keys of the correlated OTs of the OT extension into the keys of all correlated
+    /// OTs, returning them together with the hash that the receiver needs to verify.
+    ///
+    /// `correction` is the value sent by the receiver to derandomize the correlated OTs of the
+    /// consistency check.
+    pub(crate) fn expand(
+        self,
+        coin: &CoinResult,
+        delta: &Delta,
+        base_keys: &[KeyType],
+        correction: u128,
+    ) -> (Vec<KeyType>, [u8; 32]) {
+        let LpnParams { n, k, .. } = self.params.lpn;
+        assert_eq!(base_keys.len(), self.params.base_cots());
+
+        let mut chi = challenges(coin, &self.transcript);
+        let mut v = 0;
+        for leaf in self.leaves.iter() {
+            v ^= gf128_mul(chi.gen(), *leaf);
+        }
+        let check_keys = &base_keys[self.params.instances * k..];
+        for (i, key) in check_keys.iter().enumerate() {
+            let key = if correction & (1 << i) != 0 {
+                key.0 ^ delta.0
+            } else {
+                key.0
+            };
+            v ^= gf128_mul(1 << i, key);
+        }
+
+        let mut keys = Vec::with_capacity(self.params.cots);
+        for instance in 0..self.params.instances {
+            let mut matrix = lpn_matrix(coin, instance);
+            let base_keys = &base_keys[instance * k..(instance + 1) * k];
+            let leaves = &self.leaves[instance * n..(instance + 1) * n];
+            for leaf in leaves.iter().take(self.params.cots - keys.len()) {
+                let mut key = *leaf;
+                for _ in 0..LPN_WEIGHT {
+                    key ^= base_keys[matrix.gen_range(0..k)].0;
+                }
+                keys.push(KeyType(key));
+            }
+        }
+        (keys, *blake3::hash(&v.to_le_bytes()).as_bytes())
+    }
+}
+
+impl SilentReceiver {
+    /// Starts a new silent OT receiver session, choosing a random leaf to puncture in each tree.
+    pub(crate) fn init<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        params: SilentParams,
+        m: &message::SilentInit,
+    ) -> Result<(Self, message::SilentChoices), Error> {
+        let LpnParams { h, .. } = params.lpn;
+        let mut buffer = m.0.iter();
+        let sender_init = Init::deserialize_from_buffer(&mut buffer)?;
+        if buffer.next().is_some() {
+            return Err(Error::OtInitDeserializationError);
+        }
+        let mut alphas = Vec::with_capacity(params.trees());
+        let mut receivers = Vec::with_capacity(params.base_ots());
+        let mut msg = Vec::with_capacity(params.base_ots() * 32);
+        for _ in 0..params.trees() {
+            let alpha = rng.gen_range(0..1 << h);
+            for level in 0..h {
+                // the receiver learns the sum of the nodes that are not on the path to its leaf:
+                let choice = alpha & (1 << (h - 1 - level)) == 0;
+                let (init, receiver) = BaseReceiver::init(rng, &sender_init, choice);
+                init.serialize_to_buffer(&mut msg);
+                receivers.push(receiver);
+            }
+            alphas.push(alpha);
+        }
+        let receiver = Self {
+            params,
+            alphas,
+            receivers,
+        };
+        Ok((receiver, message::SilentChoices(msg)))
+    }
+
+    /// Punctures the GGM trees of the sender and expands the correlated OTs of the OT extension
+    /// (as pairs of bit and MAC) into all correlated OTs.
+    ///
+    /// Returns the correlated OTs, the correction that the sender needs for the consistency check
+    /// and the hash that the sender is expected to send back.
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn expand(
+        self,
+        coin: &CoinResult,
+        m: &message::SilentReply,
+        base: &[(bool, MacType)],
+    ) -> Result<(Vec<(bool, MacType)>, u128, [u8; 32]), Error> {
+        let LpnParams { n, k, h, .. } = self.params.lpn;
+        assert_eq!(base.len(), self.params.base_cots());
+        if m.ots.len() != self.params.base_ots() * 64 || m.sums.len() != self.params.trees() {
+            return Err(Error::OtBlockDeserializationError);
+        }
+
+        let mut replies = m.ots.iter();
+        let mut receivers = self.receivers.into_iter();
+        let mut leaves = vec![0; self.params.leaves()];
+        for (tree, leaves) in leaves.chunks_exact_mut(1 << h).enumerate() {
+            let alpha = self.alphas[tree];
+            // the position of the unknown node on the path to the punctured leaf:
+            let mut path = 0;
+            for level in 0..h {
+                let nodes = 1 << level;
+                for j in (0..nodes).rev() {
+                    let children = if j == path { [0, 0] } else { prg(leaves[j]) };
+                    leaves[2 * j] = children[0];
+                    leaves[2 * j + 1] = children[1];
+                }
+                let index = tree * h + level;
+                let reply = InitReply::deserialize_from_buffer(&mut replies)?;
+                let receiver = receivers.next().ok_or(Error::OtBlockDeserializationError)?;
+                let sum = receiver.recv_tweaked(reply, &(index as u64).to_le_bytes());
+                let mut sum = from_ot_message(&sum);
+
+                let on_path = (alpha >> (h - 1 - level)) & 1;
+                let off_path = 1 - on_path;
+                for (j, node) in leaves[..2 * nodes].iter().enumerate() {
+                    if j % 2 == off_path {
+                        sum ^= node;
+                    }
+                }
+                leaves[2 * path + off_path] = sum;
+                path = 2 * path + on_path;
+            }
+            leaves[path] = leaves.iter().fold(m.sums[tree], |acc, leaf| acc ^ leaf);
+        }
+
+        let mut chi = challenges(coin, &transcript(m));
+        let mut v = 0;
+        let mut x = 0;
+        for (i, leaf) in leaves.iter().enumerate() {
+            let chi_i: u128 = chi.gen();
+            if i % (1 << h) == self.alphas[i >> h] {
+                x ^= chi_i;
+            }
+            v ^= gf128_mul(chi_i, *leaf);
+        }
+        let mut correction = x;
+        for (i, (bit, mac)) in base[self.params.instances * k..].iter().enumerate() {
+            if *bit {
+                correction ^= 1 << i;
+            }
+            v ^= gf128_mul(1 << i, mac.0);
+        }
+
+        let mut cots = Vec::with_capacity(self.params.cots);
+        for instance in 0..self.params.instances {
+            let mut matrix = lpn_matrix(coin, instance);
+            let base = &base[instance * k..(instance + 1) * k];
+            let leaves = leaves.iter().enumerate().skip(instance * n).take(n);
+            for (i, leaf) in leaves.take(self.params.cots - cots.len()) {
+                let mut bit = i % (1 << h) == self.alphas[i >> h];
+                let mut mac = *leaf;
+                for _ in 0..LPN_WEIGHT {
+                    let (b, m) = base[matrix.gen_range(0..k)];
+                    bit ^= b;
+                    mac ^= m.0;
+                }
+                cots.push((bit, MacType(mac)));
+            }
+        }
+        Ok((cots, correction, *blake3::hash(&v.to_le_bytes()).as_bytes()))
+    }
+}
+
+/// The length-doubling PRG used to expand the GGM trees.
+fn prg(seed: u128) -> [u128; 2] {
+    let hash = blake3::hash(&seed.to_le_bytes());
+    let bytes = hash.as_bytes();
+    let mut left = [0; 16];
+    let mut right = [0; 16];
+    left.copy_from_slice(&bytes[..16]);
+    right.copy_from_slice(&bytes[16..]);
+    [u128::from_le_bytes(left), u128::from_le_bytes(right)]
+}
+
+fn ot_message(sum: u128) -> OtMessage {
+    let mut msg = OtMessage::default();
+    msg[..16].copy_from_slice(&sum.to_le_bytes());
+    msg
+}
+
+fn from_ot_message(msg: &OtMessage) -> u128 {
+    let mut sum = [0; 16];
+    sum.copy_from_slice(&msg[..16]);
+    u128::from_le_bytes(sum)
+}
+
+/// Hashes the messages of the sender, so that the challenges of the consistency check are only
+/// determined after the sender has committed to its trees.
+fn transcript(m: &message::SilentReply) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&m.ots);
+    for sum in m.sums.iter() {
+        hasher.update(&sum.to_le_bytes());
+    }
+    *hasher.finalize().as_bytes()
+}
+
+fn challenges(coin: &CoinResult, transcript: &[u8; 32]) -> ChaCha20Rng {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"tandem silent ot challenges");
+    hasher.update(coin);
+    hasher.update(transcript);
+    ChaCha20Rng::from_seed(*hasher.finalize().as_bytes())
+}
+
+/// The public LPN matrix of an instance, as a generator of the row indexes of each column.
+fn lpn_matrix(coin: &CoinResult, instance: usize) -> ChaCha20Rng {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"tandem silent ot lpn matrix");
+    hasher.update(coin);
+    hasher.update(&(instance as u64).to_le_bytes());
+    ChaCha20Rng::from_seed(*hasher.finalize().as_bytes())
+}
+
+#[cfg(test)]
+fn test_base_cots(
+    rng: &mut ChaCha20Rng,
+    delta: &Delta,
+    count: usize,
+) -> (Vec<(bool, MacType)>, Vec<KeyType>) {
+    let mut cots = vec![];
+    let mut keys = vec![];
+    for _ in 0..count {
+        let key: u128 = rng.gen();
+        let bit: bool = rng.gen();
+        let mac = if bit { key ^ delta.0 } else { key };
+        cots.push((bit, MacType(mac)));
+        keys.push(KeyType(key));
+    }
+    (cots, keys)
+}
+
+#[test]
+fn test_silent_ot() {
+    let mut rng = ChaCha20Rng::from_entropy();
+    let params = SilentParams {
+        lpn: LpnParams {
+            n: 64,
+            k: 40,
+            t: 4,
+            h: 4,
+        },
+        instances: 2,
+        cots: 100,
+    };
+    let coin = [7; 32];
+    let delta = Delta::gen_random(&mut rng);
+    let (base, base_keys) = test_base_cots(&mut rng, &delta, params.base_cots());
+
+    let (mut sender, init) = SilentSender::init(&mut rng, params);
+    let (receiver, choices) = SilentReceiver::init(&mut rng, params, &init).unwrap();
+    let reply = sender.send(&mut rng, &delta, &choices).unwrap();
+    let (cots, correction, expected) = receiver.clone().expand(&coin, &reply, &base).unwrap();
+    let (keys, check) = sender.clone().expand(&coin, &delta, &base_keys, correction);
+
+    assert_eq!(check, expected);
+    assert_eq!(cots.len(), params.cots);
+    assert_eq!(keys.len(), params.cots);
+    for ((bit, mac), key) in cots.iter().zip(keys.iter()) {
+        let expected_mac = if *bit { key.0 ^ delta.0 } else { key.0 };
+        assert_eq!(mac.0, expected_mac);
+    }
+    let ones = cots.iter().filter(|(bit, _)| *bit).count();
+    assert!(ones > 20 && ones < 80);
+
+    // an inconsistent level sum or leaf sum is detected by the receiver:
+    let mut tampered = reply.clone();
+    tampered.ots[64 + 3] ^= 1;
+    tampered.ots[64 + 32 + 3] ^= 1;
+    let (_, correction, expected) = receiver.clone().expand(&coin, &tampered, &base).unwrap();
+    let (_, check) = sender.clone().expand(&coin, &delta, &base_keys, correction);
+    assert_ne!(check, expected);
+
+    let mut tampered = reply;
+    tampered.sums[1] ^= 1;
+    let (_, correction, expected) = receiver.expand(&coin, &tampered, &base).unwrap();
+    let (_, check) = sender.expand(&coin, &delta, &base_keys, correction);
+    assert_ne!(check, expected);
+}
+
+#[test]
+fn test_silent_params() {
+    let small = SilentParams::new(1000);
+    assert_eq!(small.lpn, LPN_PARAMS[0]);
+    assert_eq!(small.instances, 1);
+    assert_eq!(small.base_cots(), LPN_PARAMS[0].k + K);
+
+    let medium = SilentParams::new(300_000);
+    assert_eq!(medium.lpn, LPN_PARAMS[1]);
+    assert_eq!(medium.instances, 1);
+
+    let large = SilentParams::new(20_000_000);
+    assert_eq!(large.lpn, LPN_PARAMS[2]);
+    assert_eq!(large.instances, 2);
+    for params in LPN_PARAMS {
+        assert_eq!(params.n, params.t << params.h);
+    }
+}
@@ -1,11 +1,15 @@
 //! SMPC engine simulation environment under ideal functionality
 
 use crate::{
-    states::{Contributor, Evaluator},
-    Circuit, Error,
+    states::{Contributor, Evaluator, Msg},
+    Circuit, Error, Party, Transcript,
 };
-use rand::SeedableRng;
+use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha20Rng;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
 
 /// Simulates the local execution of the circuit using a 2 Party MPC protocol.
 ///
@@ -39,3 +43,578 @@ pub fn simulate(
     }
     eval.output(&msg_for_eval)
 }
+
+/// Statistics collected during a simulated MPC execution.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimulationReport {
+    /// Time spent by both parties on each step of the protocol, including the initialization.
+    pub step_durations: Vec<Duration>,
+    /// Random bytes drawn by the contributor during each phase, see [`Contributor::rng_usage`].
+    pub contributor_rng_usage: Vec<u64>,
+    /// Random bytes drawn by the evaluator during each phase, see [`Evaluator::rng_usage`].
+    pub evaluator_rng_usage: Vec<u64>,
+}
+
+/// Simulates the local execution of the circuit like [`simulate`], but additionally returns the
+/// timing and randomness statistics of the execution.
+pub fn simulate_with_report(
+    circuit: &Circuit,
+    input_contributor: &[bool],
+    input_evaluator: &[bool],
+) -> Result<(Vec<bool>, SimulationReport), Error> {
+    let mut step_durations = vec![];
+
+    let start = Instant::now();
+    let mut eval = Evaluator::new(
+        circuit.clone(),
+        input_evaluator,
+        ChaCha20Rng::from_entropy(),
+    )?;
+    let (mut contrib, mut msg_for_eval) =
+        Contributor::new(circuit, input_contributor, ChaCha20Rng::from_entropy())?;
+    step_durations.push(start.elapsed());
+
+    for _ in 0..eval.steps() {
+        let start = Instant::now();
+        let (next_state, msg_for_contrib) = eval.run(&msg_for_eval)?;
+        eval = next_state;
+
+        let (next_state, reply) = contrib.run(&msg_for_contrib)?;
+        contrib = next_state;
+        step_durations.push(start.elapsed());
+
+        msg_for_eval = reply;
+    }
+    let contributor_rng_usage = contrib.rng_usage().to_vec();
+    let evaluator_rng_usage = eval.rng_usage().to_vec();
+
+    let start = Instant::now();
+    let output = eval.output(&msg_for_eval)?;
+    step_durations.push(start.elapsed());
+
+    let report = SimulationReport {
+        step_durations,
+        contributor_rng_usage,
+        evaluator_rng_usage,
+    };
+    Ok((output, report))
+}
+
+/// The conditions of the (simulated) network between contributor and evaluator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NetworkProfile {
+    /// One-way delay of each message.
+    pub latency: Duration,
+    /// Bandwidth of the link in bytes per second, or `None` if the bandwidth is unlimited.
+    pub bandwidth: Option<u64>,
+    /// Probability in `[0, 1)` that a transmission is dropped and needs to be retransmitted.
+    ///
+    /// Values of `1` or above are treated as `0.99`, to guarantee that messages eventually arrive.
+    pub drop_rate: f64,
+}
+
+/// The transmission of a single message in a simulated network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageReport {
+    /// The party that sent the message.
+    pub sender: Party,
+    /// The size of the message in bytes.
+    pub bytes: usize,
+    /// The number of times the message was dropped before it arrived.
+    pub retransmissions: u32,
+    /// The (simulated) time it took for the message to arrive, including all retransmissions.
+    pub delay: Duration,
+}
+
+/// Statistics about an MPC execution in a simulated network, see [`simulate_with_network`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkReport {
+    /// All messages exchanged between the parties, in order.
+    pub messages: Vec<MessageReport>,
+    /// Bytes sent by the contributor, excluding retransmissions.
+    pub contributor_bytes: usize,
+    /// Bytes sent by the evaluator, excluding retransmissions.
+    pub evaluator_bytes: usize,
+    /// The (simulated) time spent transmitting messages.
+    pub network_time: Duration,
+    /// The (measured) time spent by both parties computing their messages and the output.
+    pub compute_time: Duration,
+}
+
+impl NetworkReport {
+    /// The total time of the execution, the sum of network and compute time.
+    pub fn total_time(&self) -> Duration {
+        self.network_time + self.compute_time
+    }
+}
+
+/// Simulates the local execution of the circuit like [`simulate`], but under the specified network
+/// conditions.
+///
+/// Network delays are not actually waited for, but calculated for each message (as latency plus
+/// transfer time, with dropped transmissions being retransmitted after a timeout of twice the
+/// latency) and reported alongside the output.
+pub fn simulate_with_network(
+    circuit: &Circuit,
+    input_contributor: &[bool],
+    input_evaluator: &[bool],
+    network: NetworkProfile,
+) -> Result<(Vec<bool>, NetworkReport), Error> {
+    let mut drops = ChaCha20Rng::from_entropy();
+    let drop_rate = network.drop_rate.clamp(0.0, 0.99);
+    let mut messages = vec![];
+    let mut transmit = |sender: Party, msg: &[u8]| {
+        let transfer = match network.bandwidth {
+            Some(bandwidth) => Duration::from_secs_f64(msg.len() as f64 / bandwidth.max(1) as f64),
+            None => Duration::ZERO,
+        };
+        let mut retransmissions = 0;
+        let mut delay = network.latency + transfer;
+        while drops.gen_bool(drop_rate) {
+            retransmissions += 1;
+            delay += network.latency * 2 + transfer;
+        }
+        messages.push(MessageReport {
+            sender,
+            bytes: msg.len(),
+            retransmissions,
+            delay,
+        });
+    };
+
+    let start = Instant::now();
+    let mut eval = Evaluator::new(
+        circuit.clone(),
+        input_evaluator,
+        ChaCha20Rng::from_entropy(),
+    )?;
+    let (mut contrib, mut msg_for_eval) =
+        Contributor::new(circuit, input_contributor, ChaCha20Rng::from_entropy())?;
+    transmit(Party::Contributor, &msg_for_eval);
+
+    for _ in 0..eval.steps() {
+        let (next_state, msg_for_contrib) = eval.run(&msg_for_eval)?;
+        eval = next_state;
+        transmit(Party::Evaluator, &msg_for_contrib);
+
+        let (next_state, reply) = contrib.run(&msg_for_contrib)?;
+        contrib = next_state;
+        transmit(Party::Contributor, &reply);
+
+        msg_for_eval = reply;
+    }
+    let output = eval.output(&msg_for_eval)?;
+    let compute_time = start.elapsed();
+
+    let bytes_sent_by = |party| {
+        messages
+            .iter()
+            .filter(|m| m.sender == party)
+            .map(|m| m.bytes)
+            .sum()
+    };
+    let report = NetworkReport {
+        contributor_bytes: bytes_sent_by(Party::Contributor),
+        evaluator_bytes: bytes_sent_by(Party::Evaluator),
+        network_time: messages.iter().map(|m| m.delay).sum(),
+        compute_time,
+        messages,
+    };
+    Ok((output, report))
+}
+
+/// A message intercepted by the adversary in [`simulate_with_adversary`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Intercepted {
+    /// The party that sent the message.
+    pub sender: Party,
+    /// The index of the message among all messages sent by the party, starting at `0`.
+    pub index: usize,
+    /// The message as sent by the party.
+    pub msg: Msg,
+}
+
+/// Simulates the local execution of the circuit like [`simulate`], but with an adversary
+/// controlling the channel between the parties.
+///
+/// Each message sent by a party is passed to the adversary, which returns the messages that are
+/// delivered to the other party instead. The adversary can thus corrupt messages, drop them (by
+/// returning no message) or replay and reorder them (by keeping copies of earlier messages).
+/// Delivered messages are queued and processed in order. If a party is waiting for a message but
+/// the queue is empty, the simulation fails with [`Error::ProtocolStalled`].
+pub fn simulate_with_adversary(
+    circuit: &Circuit,
+    input_contributor: &[bool],
+    input_evaluator: &[bool],
+    mut adversary: impl FnMut(Intercepted) -> Vec<Msg>,
+) -> Result<Vec<bool>, Error> {
+    let mut for_contrib = VecDeque::new();
+    let mut for_eval = VecDeque::new();
+
+    let mut eval = Evaluator::new(
+        circuit.clone(),
+        input_evaluator,
+        ChaCha20Rng::from_entropy(),
+    )?;
+    let (mut contrib, msg) =
+        Contributor::new(circuit, input_contributor, ChaCha20Rng::from_entropy())?;
+    for_eval.extend(adversary(Intercepted {
+        sender: Party::Contributor,
+        index: 0,
+        msg,
+    }));
+
+    for step in 0..eval.steps() as usize {
+        let msg = for_eval.pop_front().ok_or(Error::ProtocolStalled)?;
+        let (next_state, msg) = eval.run(&msg)?;
+        eval = next_state;
+        for_contrib.extend(adversary(Intercepted {
+            sender: Party::Evaluator,
+            index: step,
+            msg,
+        }));
+
+        let msg = for_contrib.pop_front().ok_or(Error::ProtocolStalled)?;
+        let (next_state, msg) = contrib.run(&msg)?;
+        contrib = next_state;
+        for_eval.extend(adversary(Intercepted {
+            sender: Party::Contributor,
+            index: step + 1,
+            msg,
+        }));
+    }
+    let msg = for_eval.pop_front().ok_or(Error::ProtocolStalled)?;
+    eval.output(&msg)
+}
+
+/// Re-executes the recorded [`Transcript`] of a single party, using the specified input.
+///
+/// The party is re-initialized with the recorded RNG state and processes all recorded messages of
+/// the other party, which includes all MAC checks. Fails with [`Error::TranscriptMismatch`] if any
+/// message of the party differs from the recorded one and with
+/// [`Error::IncompatibleProtocolVersion`] if the transcript was recorded using a different
+/// [`crate::PROTOCOL_VERSION`]. Returns the output if the transcript was
+/// recorded by an [`Evaluator`] that received the final message of the [`Contributor`].
+pub fn replay(
+    circuit: &Circuit,
+    input: &[bool],
+    transcript: &Transcript,
+) -> Result<Option<Vec<bool>>, Error> {
+    if transcript.protocol_version != crate::PROTOCOL_VERSION {
+        return Err(Error::IncompatibleProtocolVersion {
+            version: transcript.protocol_version,
+        });
+    }
+    let rng = transcript.rng();
+    let mut sent = transcript.sent.iter();
+    let mut check_sent = |msg: &[u8]| match sent.next() {
+        Some(recorded) if recorded == msg => Ok(()),
+        _ => Err(Error::TranscriptMismatch),
+    };
+
+    match transcript.party {
+        Party::Contributor => {
+            let (mut contrib, msg) =
+                Contributor::init(circuit, input, rng, None, transcript.options)?;
+            check_sent(&msg)?;
+            for msg in transcript.received.iter() {
+                let (next_state, reply) = contrib.run(msg)?;
+                check_sent(&reply)?;
+                contrib = next_state;
+            }
+            Ok(None)
+        }
+        Party::Evaluator => {
+            let mut eval = Evaluator::init(circuit, input, rng, None, transcript.options)?;
+            for (i, msg) in transcript.received.iter().enumerate() {
+                if i == eval.steps() as usize {
+                    return eval.output(msg).map(Some);
+                }
+                let (next_state, reply) = eval.run(msg)?;
+                check_sent(&reply)?;
+                eval = next_state;
+            }
+            Ok(None)
+        }
+    }
+}
+
+#[test]
+fn test_simulate_with_report() -> Result<(), Error> {
+    use crate::{states::STEPS, Gate};
+
+    let circuit = Circuit::new(
+        vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+        vec![2],
+    );
+    let (output, report) = simulate_with_report(&circuit, &[true], &[true])?;
+    assert_eq!(output, vec![true]);
+    assert_eq!(report.step_durations.len(), STEPS as usize + 2);
+    assert_eq!(report.contributor_rng_usage.len(), STEPS as usize + 1);
+    assert_eq!(report.evaluator_rng_usage.len(), STEPS as usize + 1);
+    // the contributor draws its delta and base OT randomness during the initialization:
+    assert!(report.contributor_rng_usage[0] > 0);
+    // the evaluator does not draw any randomness before receiving the first message:
+    assert_eq!(report.evaluator_rng_usage[0], 0);
+    assert!(report.evaluator_rng_usage[1] > 0);
+    Ok(())
+}
+
+#[test]
+fn test_simulate_with_network() -> Result<(), Error> {
+    use crate::{states::STEPS, Gate, ProtocolPlan};
+
+    let circuit = Circuit::new(
+        vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+        vec![2],
+    );
+    let plan = ProtocolPlan::new(&circuit);
+
+    let network = NetworkProfile {
+        latency: Duration::from_millis(10),
+        bandwidth: None,
+        drop_rate: 0.0,
+    };
+    let (output, report) = simulate_with_network(&circuit, &[true], &[false], network)?;
+    assert_eq!(output, vec![false]);
+    assert_eq!(report.messages.len(), 2 * STEPS as usize + 1);
+    assert_eq!(report.contributor_bytes, plan.total_contributor_bytes());
+    assert_eq!(report.evaluator_bytes, plan.total_evaluator_bytes());
+    assert_eq!(
+        report.network_time,
+        Duration::from_millis(10) * (2 * STEPS + 1)
+    );
+    assert!(report.messages.iter().all(|m| m.retransmissions == 0));
+
+    let network = NetworkProfile {
+        latency: Duration::ZERO,
+        bandwidth: Some(1000),
+        drop_rate: 0.5,
+    };
+    let (output, report) = simulate_with_network(&circuit, &[true], &[true], network)?;
+    assert_eq!(output, vec![true]);
+    for m in report.messages {
+        let transfer = Duration::from_secs_f64(m.bytes as f64 / 1000.0);
+        assert_eq!(m.delay, transfer * (m.retransmissions + 1));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_replay() -> Result<(), Error> {
+    use crate::{Gate, TranscriptRecorder};
+
+    let circuit = Circuit::new(
+        vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+        vec![2],
+    );
+    let contrib_recorder = TranscriptRecorder::new();
+    let eval_recorder = TranscriptRecorder::new();
+
+    let mut eval = Evaluator::new_with_transcript(
+        &circuit,
+        [true].as_slice(),
+        ChaCha20Rng::from_entropy(),
+        &eval_recorder,
+    )?;
+    let (mut contrib, mut msg_for_eval) = Contributor::new_with_transcript(
+        &circuit,
+        [true].as_slice(),
+        ChaCha20Rng::from_entropy(),
+        &contrib_recorder,
+    )?;
+    for _ in 0..eval.steps() {
+        let (next_state, msg_for_contrib) = eval.run(&msg_for_eval)?;
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&msg_for_contrib)?;
+        contrib = next_state;
+        msg_for_eval = reply;
+    }
+    assert_eq!(eval.output(&msg_for_eval)?, vec![true]);
+
+    let contrib_transcript = contrib_recorder.transcript().unwrap();
+    let eval_transcript = eval_recorder.transcript().unwrap();
+    assert_eq!(contrib_transcript.sent, eval_transcript.received);
+    assert_eq!(contrib_transcript.received, eval_transcript.sent);
+
+    assert_eq!(replay(&circuit, &[true], &contrib_transcript)?, None);
+    assert_eq!(
+        replay(&circuit, &[true], &eval_transcript)?,
+        Some(vec![true])
+    );
+    assert_eq!(
+        replay(&circuit, &[false], &contrib_transcript),
+        Err(Error::TranscriptMismatch)
+    );
+
+    let mut outdated = contrib_transcript;
+    outdated.protocol_version += 1;
+    assert_eq!(
+        replay(&circuit, &[true], &outdated),
+        Err(Error::IncompatibleProtocolVersion {
+            version: crate::PROTOCOL_VERSION + 1
+        })
+    );
+
+    let mut tampered = eval_transcript;
+    let last_msg = tampered.received.last_mut().unwrap();
+    let last_byte = last_msg.len() - 1;
+    last_msg[last_byte] ^= 1;
+    assert!(replay(&circuit, &[true], &tampered).is_err());
+    Ok(())
+}
+
+#[test]
+fn test_negotiated_ot_extension() -> Result<(), Error> {
+    use crate::{Gate, OtExtension, ProtocolOptions};
+
+    let circuit = Circuit::new(
+        vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+        vec![2],
+    );
+    let alsz13 = ProtocolOptions::default();
+    let kos15 = ProtocolOptions {
+        ot_extension: OtExtension::Kos15,
+        ..Default::default()
+    };
+    let mut bytes_sent = vec![];
+    for (contrib_options, eval_options) in [
+        (alsz13, alsz13),
+        (kos15, alsz13),
+        (alsz13, kos15),
+        (kos15, kos15),
+    ] {
+        let mut eval = Evaluator::new_with_options(
+            &circuit,
+            [true].as_slice(),
+            ChaCha20Rng::from_entropy(),
+            eval_options,
+        )?;
+        let (mut contrib, mut msg_for_eval) = Contributor::new_with_options(
+            &circuit,
+            [true].as_slice(),
+            ChaCha20Rng::from_entropy(),
+            contrib_options,
+        )?;
+        let mut bytes = msg_for_eval.len();
+        for _ in 0..eval.steps() {
+            let (next_state, msg_for_contrib) = eval.run(&msg_for_eval)?;
+            eval = next_state;
+            let (next_state, reply) = contrib.run(&msg_for_contrib)?;
+            contrib = next_state;
+            msg_for_eval = reply;
+            bytes += msg_for_contrib.len() + msg_for_eval.len();
+        }
+        assert_eq!(eval.output(&msg_for_eval)?, vec![true]);
+        bytes_sent.push(bytes);
+    }
+    // the additional OT batches of the correlation check are sent if either party proposes it:
+    assert!(bytes_sent[0] < bytes_sent[1]);
+    assert_eq!(bytes_sent[1], bytes_sent[2]);
+    assert_eq!(bytes_sent[1], bytes_sent[3]);
+    Ok(())
+}
+
+#[test]
+fn test_silent_ot_backend() -> Result<(), Error> {
+    use crate::{Gate, OtBackend, ProtocolOptions};
+
+    let circuit = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::And(0, 1),
+            Gate::Xor(0, 1),
+        ],
+        vec![2, 3],
+    );
+    let extension = ProtocolOptions::default();
+    let silent = ProtocolOptions {
+        ot_backend: OtBackend::Silent,
+        ..Default::default()
+    };
+    for (contrib_options, eval_options) in [(silent, extension), (silent, silent)] {
+        let mut eval = Evaluator::new_with_options(
+            &circuit,
+            [true].as_slice(),
+            ChaCha20Rng::from_entropy(),
+            eval_options,
+        )?;
+        let (mut contrib, mut msg_for_eval) = Contributor::new_with_options(
+            &circuit,
+            [true].as_slice(),
+            ChaCha20Rng::from_entropy(),
+            contrib_options,
+        )?;
+        for _ in 0..eval.steps() {
+            let (next_state, msg_for_contrib) = eval.run(&msg_for_eval)?;
+            eval = next_state;
+            let (next_state, reply) = contrib.run(&msg_for_contrib)?;
+            contrib = next_state;
+            msg_for_eval = reply;
+        }
+        assert_eq!(eval.output(&msg_for_eval)?, vec![true, false]);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "post-quantum")]
+#[test]
+fn test_post_quantum_base_ot() -> Result<(), Error> {
+    use crate::{BaseOt, Gate, OtBackend, ProtocolOptions};
+
+    let circuit = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::And(0, 1),
+            Gate::Xor(0, 1),
+        ],
+        vec![2, 3],
+    );
+    let classical = ProtocolOptions::default();
+    let pq = ProtocolOptions {
+        base_ot: BaseOt::PostQuantum,
+        ..Default::default()
+    };
+    let pq_silent = ProtocolOptions {
+        ot_backend: OtBackend::Silent,
+        ..pq
+    };
+    let run = |contrib_options, eval_options| -> Result<(Vec<bool>, usize), Error> {
+        let mut eval = Evaluator::new_with_options(
+            &circuit,
+            [true].as_slice(),
+            ChaCha20Rng::from_entropy(),
+            eval_options,
+        )?;
+        let (mut contrib, mut msg_for_eval) = Contributor::new_with_options(
+            &circuit,
+            [true].as_slice(),
+            ChaCha20Rng::from_entropy(),
+            contrib_options,
+        )?;
+        let mut bytes = msg_for_eval.len();
+        for _ in 0..eval.steps() {
+            let (next_state, msg_for_contrib) = eval.run(&msg_for_eval)?;
+            eval = next_state;
+            let (next_state, reply) = contrib.run(&msg_for_contrib)?;
+            contrib = next_state;
+            msg_for_eval = reply;
+            bytes += msg_for_contrib.len() + msg_for_eval.len();
+        }
+        Ok((eval.output(&msg_for_eval)?, bytes))
+    };
+
+    let (output, classical_bytes) = run(classical, classical)?;
+    assert_eq!(output, vec![true, false]);
+    // the evaluator switches to the post-quantum base OT proposed by the contributor:
+    for (contrib_options, eval_options) in [(pq, pq), (pq, classical), (pq_silent, pq_silent)] {
+        let (output, bytes) = run(contrib_options, eval_options)?;
+        assert_eq!(output, vec![true, false]);
+        assert!(bytes > classical_bytes);
+    }
+    // ...but the contributor cannot switch after its first message, so the protocol fails:
+    assert_eq!(run(classical, pq), Err(Error::IncompatibleBaseOt));
+    Ok(())
+}
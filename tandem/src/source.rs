@@ -0,0 +1,235 @@
+//! Abstraction over the storage of circuits, so that the protocol can run on circuits that are not
+//! fully loaded into memory.
+
+use std::borrow::Borrow;
+
+use crate::{
+    circuit::MAX_AND_GATES, circuit::MAX_GATES, Circuit, Error, Gate, GateIndex, ValidatedCircuit,
+};
+
+/// Read access to the gates of a circuit, as required by [`crate::states::Contributor`] and
+/// [`crate::states::Evaluator`].
+///
+/// Implemented for every type that borrows a [`Circuit`] (such as `Circuit`, `&Circuit` or
+/// `Arc<Circuit>`) and for circuits in the [`crate::ColumnarCircuit`] layout, which can be
+/// memory-mapped from a file with the `mmap` feature. A [`ValidatedCircuit`] (or a reference to it)
+/// is not validated again by the protocol.
+///
+/// Implementations only need to store gates that were already lowered by [`Circuit::new`], i.e.
+/// [`Gate::Mux`] and [`Gate::Nand`] are rejected during validation.
+pub trait CircuitSource {
+    /// The number of gates in the circuit.
+    fn num_gates(&self) -> usize;
+
+    /// The gate at the specified index, or `None` if the index is out of bounds.
+    fn gate(&self, index: usize) -> Option<Gate>;
+
+    /// Indexes of the gates that are exposed as outputs of the circuit.
+    fn output_gates(&self) -> &[GateIndex];
+
+    /// Number of AND gates in the circuit.
+    fn and_gates(&self) -> usize;
+
+    /// Number of input bits by the evaluator party.
+    fn eval_inputs(&self) -> usize;
+
+    /// Number of input bits by the contributor party.
+    fn contrib_inputs(&self) -> usize;
+
+    /// Iterates over all gates of the circuit, in order.
+    fn iter_gates(&self) -> Gates<'_, Self> {
+        Gates {
+            source: self,
+            index: 0,
+        }
+    }
+
+    /// Whether the circuit was validated when it was constructed, see [`ValidatedCircuit`].
+    ///
+    /// Can only be overridden by this crate, which guarantees that validated circuits are never
+    /// skipped by mistake.
+    #[doc(hidden)]
+    fn is_validated(&self, _: sealed::Token) -> bool {
+        false
+    }
+}
+
+mod sealed {
+    /// Cannot be named outside of this crate, which prevents implementations of
+    /// [`super::CircuitSource::is_validated`] elsewhere.
+    pub struct Token;
+}
+
+impl<T: Borrow<Circuit>> CircuitSource for T {
+    fn num_gates(&self) -> usize {
+        self.borrow().gates().len()
+    }
+
+    fn gate(&self, index: usize) -> Option<Gate> {
+        self.borrow().gates().get(index).cloned()
+    }
+
+    fn output_gates(&self) -> &[GateIndex] {
+        self.borrow().output_gates()
+    }
+
+    fn and_gates(&self) -> usize {
+        self.borrow().and_gates()
+    }
+
+    fn eval_inputs(&self) -> usize {
+        self.borrow().eval_inputs()
+    }
+
+    fn contrib_inputs(&self) -> usize {
+        self.borrow().contrib_inputs()
+    }
+}
+
+impl CircuitSource for ValidatedCircuit {
+    fn num_gates(&self) -> usize {
+        self.gates().len()
+    }
+
+    fn gate(&self, index: usize) -> Option<Gate> {
+        self.gates().get(index).cloned()
+    }
+
+    fn output_gates(&self) -> &[GateIndex] {
+        Circuit::output_gates(self)
+    }
+
+    fn and_gates(&self) -> usize {
+        Circuit::and_gates(self)
+    }
+
+    fn eval_inputs(&self) -> usize {
+        Circuit::eval_inputs(self)
+    }
+
+    fn contrib_inputs(&self) -> usize {
+        Circuit::contrib_inputs(self)
+    }
+
+    fn is_validated(&self, _: sealed::Token) -> bool {
+        true
+    }
+}
+
+impl CircuitSource for &ValidatedCircuit {
+    fn num_gates(&self) -> usize {
+        (*self).num_gates()
+    }
+
+    fn gate(&self, index: usize) -> Option<Gate> {
+        (*self).gate(index)
+    }
+
+    fn output_gates(&self) -> &[GateIndex] {
+        CircuitSource::output_gates(*self)
+    }
+
+    fn and_gates(&self) -> usize {
+        CircuitSource::and_gates(*self)
+    }
+
+    fn eval_inputs(&self) -> usize {
+        CircuitSource::eval_inputs(*self)
+    }
+
+    fn contrib_inputs(&self) -> usize {
+        CircuitSource::contrib_inputs(*self)
+    }
+
+    fn is_validated(&self, _: sealed::Token) -> bool {
+        true
+    }
+}
+
+/// Iterator over the gates of a [`CircuitSource`], see [`CircuitSource::iter_gates`].
+pub struct Gates<'a, S: ?Sized> {
+    source: &'a S,
+    index: usize,
+}
+
+impl<'a, S: CircuitSource + ?Sized> Iterator for Gates<'a, S> {
+    type Item = Gate;
+
+    fn next(&mut self) -> Option<Gate> {
+        let gate = self.source.gate(self.index)?;
+        self.index += 1;
+        Some(gate)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.source.num_gates().saturating_sub(self.index);
+        (remaining, Some(remaining))
+    }
+}
+
+/// Validates the circuit like [`validate`], unless it is a [`ValidatedCircuit`].
+pub(crate) fn check(circuit: &(impl CircuitSource + ?Sized)) -> Result<(), Error> {
+    if circuit.is_validated(sealed::Token) {
+        Ok(())
+    } else {
+        validate(circuit)
+    }
+}
+
+/// Performs a syntax check of the circuit, see [`Circuit::validate`].
+///
+/// Additionally checks that the gate counts reported by the source match its gates.
+pub(crate) fn validate(circuit: &(impl CircuitSource + ?Sized)) -> Result<(), Error> {
+    let mut num_and_gates = 0;
+    let mut num_eval_inputs = 0;
+    let mut num_contrib_inputs = 0;
+    let mut num_gates = 0;
+    for (i, g) in circuit.iter_gates().enumerate() {
+        let i = i as u32;
+        match g {
+            Gate::InContrib => num_contrib_inputs += 1,
+            Gate::InEval => num_eval_inputs += 1,
+            Gate::Xor(x, y) => {
+                if x >= i || y >= i {
+                    return Err(Error::InvalidCircuit);
+                }
+            }
+            Gate::And(x, y) => {
+                if x >= i || y >= i {
+                    return Err(Error::InvalidCircuit);
+                }
+                num_and_gates += 1;
+            }
+            Gate::Not(x) => {
+                if x >= i {
+                    return Err(Error::InvalidCircuit);
+                }
+            }
+            // only reachable if the gates were not lowered, which `Circuit::new` prevents:
+            Gate::Mux(..) | Gate::Nand(..) => return Err(Error::InvalidCircuit),
+        }
+        num_gates += 1;
+    }
+    if num_gates != circuit.num_gates()
+        || num_and_gates != circuit.and_gates()
+        || num_eval_inputs != circuit.eval_inputs()
+        || num_contrib_inputs != circuit.contrib_inputs()
+    {
+        return Err(Error::InvalidCircuit);
+    }
+    if circuit.output_gates().is_empty() {
+        return Err(Error::InvalidCircuit);
+    }
+    for &o in circuit.output_gates().iter() {
+        if o >= num_gates as u32 {
+            return Err(Error::InvalidCircuit);
+        }
+    }
+    if num_and_gates > MAX_AND_GATES {
+        return Err(Error::MaxCircuitSizeExceeded);
+    }
+    if num_gates > MAX_GATES {
+        return Err(Error::MaxCircuitSizeExceeded);
+    }
+    Ok(())
+}
@@ -8,60 +8,203 @@
 //! messages to be handled by the user of this crate. As a result, the crate works both in sync and
 //! async environments.
 
-use std::borrow::Borrow;
-
 use crate::{
+    abort::{abort_message, check_abort, AbortReason},
+    constant_time::{eq_bytes, eq_u128},
+    framing::{frame, sections2},
     hash::{garbling_hash, hash, hash_key, hash_keys},
     leakyand::{compute_leaky_and_hashes, derive_and_shares},
     leakydelta_ot::{
         message::{OtInitReply, SerializedOtInit},
-        LeakyOtReceiver, LeakyOtSender, ReceiverInitializer, SenderInitializer, BLOCK_SIZE,
+        CorrelationCheck, LeakyOtReceiver, LeakyOtSender, ReceiverInitializer, SenderInitializer,
+        BLOCK_SIZE, CHECK_BLOCKS,
     },
     protocol::{
         self,
         cointossing::{CoinResult, CoinShare},
     },
+    rng::{PartyRng, RngUsage},
+    silent_ot::{
+        message::{SilentChoices, SilentInit, SilentReply},
+        SilentParams, SilentReceiver, SilentSender,
+    },
+    transcript::{Party, TranscriptHash, TranscriptRecorder},
     types::{
-        AndTableShare, BitShare, Delta, InputMaskShare, KeyType, MacType, PartialBitShare,
-        TableShare, WireLabel, WireMask, WireState, K,
+        AndTableShare, AndTables, BitShare, Delta, InputMaskShare, KeyType, MacType,
+        PartialBitShare, TableShare, WireLabel, WireMask, WireState, K,
     },
-    Circuit,
+    wires::LiveWires,
+    BaseOt, CircuitSource,
     Error::{self, *},
-    Gate, GateIndex,
+    Gate, GateIndex, InputSource, OtBackend, OtExtension, PreprocessedTriples, ProtocolOptions,
 };
 use bincode::{deserialize, serialize};
 use rand::Rng;
 use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// The type of messages exchanged between [`Contributor`] and [`Evaluator`].
 pub type Msg = Vec<u8>;
 
 const TRIPLES: usize = BLOCK_SIZE * 3;
 
+/// The number of messages each party needs to process before the protocol is completed.
+pub(crate) const STEPS: u32 = 7;
+
+/// The number of messages each party needs to process to generate [`PreprocessedTriples`].
+const PREPROCESSING_STEPS: u32 = 6;
+
+/// The number of messages each party needs to process when starting from [`PreprocessedTriples`].
+const LOADED_STEPS: u32 = 2;
+
+/// Version of the encoding of serialized session states, see [`Contributor::serialize_state`].
+///
+/// Incremented whenever the states of the parties change without a change of the messages (which
+/// would increment the [`crate::PROTOCOL_VERSION`] instead).
+const STATE_VERSION: u32 = 1;
+
+/// The key derivation context of the MAC of serialized session states.
+const SESSION_STATE_CONTEXT: &str = "tandem 2025-01-01 session state v1";
+
+/// Distinguishes full sessions from sessions that only run or skip the preprocessing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Mode {
+    Full,
+    Preprocessing,
+    Loaded,
+}
+
+impl Mode {
+    fn steps(self) -> u32 {
+        match self {
+            Mode::Full => STEPS,
+            Mode::Preprocessing => PREPROCESSING_STEPS,
+            Mode::Loaded => LOADED_STEPS,
+        }
+    }
+}
+
 /// The party that contributes its input to the MPC protocol.
-pub struct Contributor<C: Borrow<Circuit>, I: Borrow<[bool]>> {
+pub struct Contributor<C: CircuitSource, I: InputSource> {
     state: Box<ContribState>,
     circuit: C,
     input: I,
+    rng_usage: RngUsage,
+    transcript: Option<TranscriptRecorder>,
+    hash: TranscriptHash,
+    mode: Mode,
 }
 
 /// The party that evaluates the circuit and the output.
 ///
 /// Upon successful circuit evaluation, the evaluator can access the plain text output.
-pub struct Evaluator<C: Borrow<Circuit>, I: Borrow<[bool]>> {
+pub struct Evaluator<C: CircuitSource, I: InputSource> {
     state: Box<EvalState>,
     circuit: C,
     input: I,
+    rng_usage: RngUsage,
+    transcript: Option<TranscriptRecorder>,
+    hash: TranscriptHash,
+    /// The transcript hash of the contributor, see [`Evaluator::expect_transcript_hash`].
+    expected_hash: Option<Msg>,
+    mode: Mode,
 }
 
-impl<C: Borrow<Circuit>, I: Borrow<[bool]>> Contributor<C, I> {
+impl<C: CircuitSource, I: InputSource> Contributor<C, I> {
     /// Initializes the contributor, returning a state and an initial message for the [`Evaluator`].
     pub fn new(circuit: C, input: I, rng: ChaCha20Rng) -> Result<(Self, Msg), Error> {
-        let (state, msg) = ContribStep1::init(circuit.borrow(), input.borrow(), rng)?;
+        Self::init(circuit, input, rng, None, ProtocolOptions::default())
+    }
+
+    /// Initializes the contributor like [`Contributor::new`], proposing the specified options to
+    /// the [`Evaluator`].
+    pub fn new_with_options(
+        circuit: C,
+        input: I,
+        rng: ChaCha20Rng,
+        options: ProtocolOptions,
+    ) -> Result<(Self, Msg), Error> {
+        Self::init(circuit, input, rng, None, options)
+    }
+
+    /// Initializes the contributor like [`Contributor::new`], recording all exchanged messages and
+    /// the RNG state in the specified recorder.
+    pub fn new_with_transcript(
+        circuit: C,
+        input: I,
+        rng: ChaCha20Rng,
+        recorder: &TranscriptRecorder,
+    ) -> Result<(Self, Msg), Error> {
+        Self::init(
+            circuit,
+            input,
+            rng,
+            Some(recorder.clone()),
+            ProtocolOptions::default(),
+        )
+    }
+
+    /// Initializes the contributor from triples generated by [`Contributor::new_preprocessing`],
+    /// returning a state and an initial message for an [`Evaluator`] initialized using
+    /// [`Evaluator::from_preprocessed`].
+    ///
+    /// The session skips the preprocessing and thus only needs 2 steps. The triples must fit the
+    /// circuit, see [`PreprocessedTriples::fits`], and must not be used again.
+    pub fn from_preprocessed(
+        circuit: C,
+        input: I,
+        rng: ChaCha20Rng,
+        triples: PreprocessedTriples,
+    ) -> Result<(Self, Msg), Error> {
+        if circuit.contrib_inputs() != input.len() {
+            return Err(InsufficientInput);
+        }
+        let mut rng = PartyRng::new(rng);
+        let mut rng_usage = RngUsage::new(&rng);
+        let (state, msg) = LoadedStep::init(triples, Party::Contributor, &mut rng, &circuit)?;
+        rng_usage.end_phase();
+        let mut hash = TranscriptHash::new();
+        hash.update(Party::Contributor, &msg);
+        let contrib = Self {
+            state: Box::new(ContribState::Loaded(state)),
+            circuit,
+            input,
+            rng_usage,
+            transcript: None,
+            hash,
+            mode: Mode::Loaded,
+        };
+        Ok((contrib, msg))
+    }
+
+    pub(crate) fn init(
+        circuit: C,
+        input: I,
+        rng: ChaCha20Rng,
+        transcript: Option<TranscriptRecorder>,
+        options: ProtocolOptions,
+    ) -> Result<(Self, Msg), Error> {
+        if let Some(t) = &transcript {
+            t.start(Party::Contributor, &rng, options);
+        }
+        let rng = PartyRng::new(rng);
+        let mut rng_usage = RngUsage::new(&rng);
+        let (state, msg) = ContribStep1::init(&circuit, &input, rng, options)?;
+        rng_usage.end_phase();
+        if let Some(t) = &transcript {
+            t.sent(&msg);
+        }
+        let mut hash = TranscriptHash::new();
+        hash.update(Party::Contributor, &msg);
         let contrib = Self {
             state: Box::new(ContribState::Step1(state)),
             circuit,
             input,
+            rng_usage,
+            transcript,
+            hash,
+            mode: Mode::Full,
         };
         Ok((contrib, msg))
     }
@@ -71,20 +214,27 @@ impl<C: Borrow<Circuit>, I: Borrow<[bool]>> Contributor<C, I> {
     /// When the end state is reached, the contributor's last message will enable the [`Evaluator`]
     /// to compute the final output.
     pub fn steps(&self) -> u32 {
-        7
+        self.mode.steps()
     }
 
     /// Executes a single step in the protocol, based on the message received from the [`Evaluator`].
     pub fn run(self, msg: &[u8]) -> Result<(Contributor<C, I>, Msg), Error> {
         use ContribState::*;
 
+        if let Some(t) = &self.transcript {
+            t.received(msg);
+        }
+        check_abort(msg)?;
+        let mut hash = self.hash;
+        hash.update(Party::Evaluator, msg);
+
         let (state, msg) = match *self.state {
             Step1(s) => {
                 let (state, msg) = s.run(msg)?;
                 (Box::new(Step1a(state)), msg)
             }
             Step1a(s) => {
-                let (state, msg) = s.run(msg, self.circuit.borrow())?;
+                let (state, msg) = s.run(msg, &self.circuit)?;
                 (Box::new(Step2(state)), msg)
             }
             Step2(s) => {
@@ -96,36 +246,241 @@ impl<C: Borrow<Circuit>, I: Borrow<[bool]>> Contributor<C, I> {
                 (Box::new(Step4(state)), msg)
             }
             Step4(s) => {
-                let (state, msg) = s.run(msg, self.circuit.borrow())?;
+                let (state, msg) = s.run(msg, &self.circuit)?;
                 (Box::new(Step5(ContribBucketingStep(state))), msg)
             }
+            Step5(s) if self.mode == Mode::Preprocessing => {
+                let (triples, msg) = s.run_preprocessing(msg)?;
+                (Box::new(Preprocessed(triples)), msg)
+            }
             Step5(s) => {
-                let (state, msg) = s.run(msg, self.circuit.borrow(), self.input.borrow())?;
+                let (state, msg) = s.run(msg, &self.circuit, &self.input)?;
+                (Box::new(Step6(state)), msg)
+            }
+            Loaded(s) => {
+                let upstream = s.verify(msg)?;
+                let (state, msg) =
+                    ot_ands8_contrib(s.state, &upstream, &self.circuit, &self.input)?;
                 (Box::new(Step6(state)), msg)
             }
             Step6(s) => {
-                let ((), msg) = s.run(msg, self.circuit.borrow(), self.input.borrow())?;
-                (Box::new(Done), msg)
+                let (keys, msg) = s.run(msg, &self.circuit, &self.input)?;
+                (Box::new(Done(keys)), msg)
             }
-            Done => return Err(Error::ProtocolEnded),
+            Preprocessed(_) | Done(_) => return Err(Error::ProtocolEnded),
         };
+        let mut rng_usage = self.rng_usage;
+        rng_usage.end_phase();
+        if let Some(t) = &self.transcript {
+            t.sent(&msg);
+        }
+        hash.update(Party::Contributor, &msg);
         let next_state = Contributor {
             state,
             circuit: self.circuit,
             input: self.input,
+            rng_usage,
+            transcript: self.transcript,
+            hash,
+            mode: self.mode,
         };
         Ok((next_state, msg))
     }
+
+    /// Returns the triples generated by a contributor initialized using
+    /// [`Contributor::new_preprocessing`], after all steps have been run.
+    pub fn preprocessed(self) -> Result<PreprocessedTriples, Error> {
+        match *self.state {
+            ContribState::Preprocessed(triples) => Ok(triples),
+            _ => Err(Error::ProtocolStillInProgress),
+        }
+    }
+
+    /// Aborts the protocol, returning a message that informs the other party about the reason.
+    pub fn abort(self, reason: AbortReason) -> Msg {
+        let msg = abort_message(reason);
+        if let Some(t) = &self.transcript {
+            t.sent(&msg);
+        }
+        msg
+    }
+
+    /// Returns the number of random bytes drawn by this party during each phase so far.
+    ///
+    /// The first entry corresponds to the initialization of the party, each subsequent entry to a
+    /// single call of `run`.
+    pub fn rng_usage(&self) -> &[u64] {
+        self.rng_usage.per_phase()
+    }
+
+    /// Returns the running hash of all messages exchanged so far.
+    ///
+    /// Once all steps have been run, the hash can be sent to the [`Evaluator`] (together with or
+    /// after the final message) for an optional confirmation round, see
+    /// [`Evaluator::expect_transcript_hash`].
+    pub fn transcript_hash(&self) -> [u8; 32] {
+        self.hash.digest()
+    }
+
+    /// Checks the transcript hash of the [`Evaluator`] (see [`OutputReport::transcript_hash`])
+    /// against the hash of the contributor after all steps have been run.
+    ///
+    /// Fails with [`Error::TranscriptMismatch`] if the parties did not see the same messages, for
+    /// example because the messages were corrupted in transit, and with [`Error::PeerAborted`] if
+    /// the evaluator sent an abort message instead.
+    pub fn confirm_transcript_hash(&self, msg: &[u8]) -> Result<(), Error> {
+        check_abort(msg)?;
+        match *self.state {
+            ContribState::Done(_) => self.hash.confirm(msg),
+            _ => Err(Error::ProtocolStillInProgress),
+        }
+    }
+
+    /// Verifies the output disclosed by the [`Evaluator`] (see [`OutputReport::disclosure`])
+    /// after all steps have been run and returns it.
+    ///
+    /// The evaluator only knows the labels of the output wires for the values it computed, so it
+    /// cannot disclose any other output without failing the checks. Fails with
+    /// [`Error::MacError`] if a check fails and with [`Error::PeerAborted`] if the evaluator sent
+    /// an abort message instead.
+    pub fn disclosed_output(&self, msg: &[u8]) -> Result<Vec<bool>, Error> {
+        check_abort(msg)?;
+        match &*self.state {
+            ContribState::Done(keys) => keys.verify(msg),
+            _ => Err(Error::ProtocolStillInProgress),
+        }
+    }
+}
+
+impl<C: CircuitSource> Contributor<C, Vec<bool>> {
+    /// Initializes a contributor that only runs the preprocessing, returning a state and an
+    /// initial message for an [`Evaluator`] initialized using [`Evaluator::new_preprocessing`].
+    ///
+    /// The circuit only determines the number of generated triples, which suffice for any circuit
+    /// with at most as many AND gates and input bits. Once all steps have been run, the triples
+    /// can be obtained using [`Contributor::preprocessed`], the last message of the contributor
+    /// does not need to be sent to the evaluator.
+    pub fn new_preprocessing(
+        circuit: C,
+        rng: ChaCha20Rng,
+        options: ProtocolOptions,
+    ) -> Result<(Self, Msg), Error> {
+        let input = vec![false; circuit.contrib_inputs()];
+        let (contrib, msg) = Self::init(circuit, input, rng, None, options)?;
+        let contrib = Self {
+            mode: Mode::Preprocessing,
+            ..contrib
+        };
+        Ok((contrib, msg))
+    }
+}
+
+impl<C: CircuitSource> Evaluator<C, Vec<bool>> {
+    /// Initializes an evaluator that only runs the preprocessing, returning its initial state.
+    ///
+    /// Once all steps have been run, the triples can be obtained using [`Evaluator::preprocessed`],
+    /// see [`Contributor::new_preprocessing`].
+    pub fn new_preprocessing(
+        circuit: C,
+        rng: ChaCha20Rng,
+        options: ProtocolOptions,
+    ) -> Result<Self, Error> {
+        let input = vec![false; circuit.eval_inputs()];
+        let eval = Self::init(circuit, input, rng, None, options)?;
+        Ok(Self {
+            mode: Mode::Preprocessing,
+            ..eval
+        })
+    }
 }
 
-impl<C: Borrow<Circuit>, I: Borrow<[bool]>> Evaluator<C, I> {
+impl<C: CircuitSource, I: InputSource> Evaluator<C, I> {
     /// Initializes the evaluator, returning its initial state.
     pub fn new(circuit: C, input: I, rng: ChaCha20Rng) -> Result<Self, Error> {
-        let state = EvalStep1::init(circuit.borrow(), input.borrow(), rng)?;
+        Self::init(circuit, input, rng, None, ProtocolOptions::default())
+    }
+
+    /// Initializes the evaluator like [`Evaluator::new`], proposing the specified options to the
+    /// [`Contributor`].
+    pub fn new_with_options(
+        circuit: C,
+        input: I,
+        rng: ChaCha20Rng,
+        options: ProtocolOptions,
+    ) -> Result<Self, Error> {
+        Self::init(circuit, input, rng, None, options)
+    }
+
+    /// Initializes the evaluator like [`Evaluator::new`], recording all exchanged messages and the
+    /// RNG state in the specified recorder.
+    pub fn new_with_transcript(
+        circuit: C,
+        input: I,
+        rng: ChaCha20Rng,
+        recorder: &TranscriptRecorder,
+    ) -> Result<Self, Error> {
+        Self::init(
+            circuit,
+            input,
+            rng,
+            Some(recorder.clone()),
+            ProtocolOptions::default(),
+        )
+    }
+
+    /// Initializes the evaluator from triples generated by [`Evaluator::new_preprocessing`],
+    /// returning its initial state.
+    ///
+    /// The session skips the preprocessing and thus only needs 2 steps. The triples must fit the
+    /// circuit, see [`PreprocessedTriples::fits`], and must not be used again.
+    pub fn from_preprocessed(
+        circuit: C,
+        input: I,
+        rng: ChaCha20Rng,
+        triples: PreprocessedTriples,
+    ) -> Result<Self, Error> {
+        if circuit.eval_inputs() != input.len() {
+            return Err(InsufficientInput);
+        }
+        let mut rng = PartyRng::new(rng);
+        let mut rng_usage = RngUsage::new(&rng);
+        let (state, msg) = LoadedStep::init(triples, Party::Evaluator, &mut rng, &circuit)?;
+        rng_usage.end_phase();
+        Ok(Self {
+            state: Box::new(EvalState::Loaded(state, msg)),
+            circuit,
+            input,
+            rng_usage,
+            transcript: None,
+            hash: TranscriptHash::new(),
+            expected_hash: None,
+            mode: Mode::Loaded,
+        })
+    }
+
+    pub(crate) fn init(
+        circuit: C,
+        input: I,
+        rng: ChaCha20Rng,
+        transcript: Option<TranscriptRecorder>,
+        options: ProtocolOptions,
+    ) -> Result<Self, Error> {
+        if let Some(t) = &transcript {
+            t.start(Party::Evaluator, &rng, options);
+        }
+        let rng = PartyRng::new(rng);
+        let mut rng_usage = RngUsage::new(&rng);
+        let state = EvalStep1::init(&circuit, &input, rng, options)?;
+        rng_usage.end_phase();
         Ok(Self {
             state: Box::new(EvalState::Step1(state)),
             circuit,
             input,
+            rng_usage,
+            transcript,
+            hash: TranscriptHash::new(),
+            expected_hash: None,
+            mode: Mode::Full,
         })
     }
 
@@ -134,16 +489,23 @@ impl<C: Borrow<Circuit>, I: Borrow<[bool]>> Evaluator<C, I> {
     /// After the end state is reached, the evaluator expects one last message from the
     /// [`Contributor`] to compute the final output.
     pub fn steps(&self) -> u32 {
-        7
+        self.mode.steps()
     }
 
     /// Executes a single step in the protocol, based on the message received from the [`Contributor`].
     pub fn run(self, msg: &[u8]) -> Result<(Evaluator<C, I>, Msg), Error> {
         use EvalState::*;
 
+        if let Some(t) = &self.transcript {
+            t.received(msg);
+        }
+        check_abort(msg)?;
+        let mut hash = self.hash;
+        hash.update(Party::Contributor, msg);
+
         let (state, msg) = match *self.state {
             Step1(s) => {
-                let (state, msg) = s.run(msg, self.circuit.borrow())?;
+                let (state, msg) = s.run(msg, &self.circuit)?;
                 (Box::new(Step2(state)), msg)
             }
             Step2(s) => {
@@ -151,7 +513,7 @@ impl<C: Borrow<Circuit>, I: Borrow<[bool]>> Evaluator<C, I> {
                 (Box::new(Step2a(state)), msg)
             }
             Step2a(s) => {
-                let (state, msg) = s.run(msg, self.circuit.borrow())?;
+                let (state, msg) = s.run(msg, &self.circuit)?;
                 (Box::new(Step3(state)), msg)
             }
             Step3(s) => {
@@ -162,42 +524,409 @@ impl<C: Borrow<Circuit>, I: Borrow<[bool]>> Evaluator<C, I> {
                 let (state, msg) = s.run(msg)?;
                 (Box::new(Step5(state)), msg)
             }
+            Step5(s) if self.mode == Mode::Preprocessing => {
+                let (triples, msg) = s.run_preprocessing(msg, &self.circuit)?;
+                (Box::new(Preprocessed(triples)), msg)
+            }
             Step5(s) => {
-                let (state, msg) = s.run(msg, self.circuit.borrow())?;
+                let (state, msg) = s.run(msg, &self.circuit)?;
                 (Box::new(Step6(state)), msg)
             }
             Step6(s) => {
-                let (state, msg) = s.run(msg, self.circuit.borrow(), self.input.borrow())?;
+                let (state, msg) = s.run(msg, &self.circuit, &self.input)?;
+                (Box::new(Step8(state)), msg)
+            }
+            Loaded(s, reply) => {
+                let upstream = s.verify(msg)?;
+                (Box::new(LoadedStep6(EvalStep6(s.state), upstream)), reply)
+            }
+            LoadedStep6(s, upstream) => {
+                let (state, msg) = ot_ands8_eval(s.0, &upstream, msg, &self.circuit, &self.input)?;
                 (Box::new(Step8(state)), msg)
             }
             Step8(s) => {
-                let (_, _) = s.run(msg, self.circuit.borrow())?;
+                s.run(msg, &self.circuit)?.output.ok_or(MacError)?;
                 (Box::new(Done()), vec![])
             }
-            Done() => return Err(Error::ProtocolEnded),
+            Preprocessed(_) | Done() => return Err(Error::ProtocolEnded),
         };
+        let mut rng_usage = self.rng_usage;
+        rng_usage.end_phase();
+        if let Some(t) = &self.transcript {
+            t.sent(&msg);
+        }
+        // the empty reply after the final message is never sent to the contributor:
+        if !matches!(*state, Done()) {
+            hash.update(Party::Evaluator, &msg);
+        }
         let next_state = Evaluator {
             state,
             circuit: self.circuit,
             input: self.input,
+            rng_usage,
+            transcript: self.transcript,
+            hash,
+            expected_hash: self.expected_hash,
+            mode: self.mode,
         };
         Ok((next_state, msg))
     }
 
+    /// Aborts the protocol, returning a message that informs the other party about the reason.
+    pub fn abort(self, reason: AbortReason) -> Msg {
+        let msg = abort_message(reason);
+        if let Some(t) = &self.transcript {
+            t.sent(&msg);
+        }
+        msg
+    }
+
+    /// Returns the number of random bytes drawn by this party during each phase so far.
+    ///
+    /// The first entry corresponds to the initialization of the party, each subsequent entry to a
+    /// single call of `run`.
+    pub fn rng_usage(&self) -> &[u64] {
+        self.rng_usage.per_phase()
+    }
+
+    /// Returns the triples generated by an evaluator initialized using
+    /// [`Evaluator::new_preprocessing`], after all steps have been run.
+    pub fn preprocessed(self) -> Result<PreprocessedTriples, Error> {
+        match *self.state {
+            EvalState::Preprocessed(triples) => Ok(triples),
+            _ => Err(Error::ProtocolStillInProgress),
+        }
+    }
+
+    /// Expects the [`Contributor`] to have sent and received the same messages, as confirmed by
+    /// its transcript hash (see [`Contributor::transcript_hash`]).
+    ///
+    /// If set, [`Evaluator::output`] checks the hash after receiving the final message and fails
+    /// with [`Error::TranscriptMismatch`] instead of returning an output if the hashes differ.
+    pub fn expect_transcript_hash(&mut self, contributor_hash: &[u8]) {
+        self.expected_hash = Some(contributor_hash.to_vec());
+    }
+
     /// Returns the output of the computation or `None` if the protocol has not ended.
+    ///
+    /// Fails with [`Error::TranscriptMismatch`] if the transcript hash of the contributor was
+    /// provided using [`Evaluator::expect_transcript_hash`] and does not match.
     pub fn output(self, msg: &[u8]) -> Result<Vec<bool>, Error> {
-        match *self.state {
-            EvalState::Step8(s) => {
-                let (output, _) = s.run(msg, self.circuit.borrow())?;
-                Ok(output)
+        self.output_with_report(msg)?.output.ok_or(MacError)
+    }
+
+    /// Computes the output of the computation like [`Evaluator::output`], but reports the result
+    /// of the MAC checks instead of failing if a check fails.
+    ///
+    /// The output is only returned if all MAC checks succeeded, otherwise it cannot be trusted.
+    pub fn output_with_report(self, msg: &[u8]) -> Result<OutputReport, Error> {
+        if let Some(t) = &self.transcript {
+            t.received(msg);
+        }
+        check_abort(msg)?;
+        let mut hash = self.hash;
+        hash.update(Party::Contributor, msg);
+        let s = match *self.state {
+            EvalState::Step8(s) => s,
+            _ => return Err(Error::ProtocolStillInProgress),
+        };
+        if let Some(expected) = &self.expected_hash {
+            hash.confirm(expected)?;
+        }
+        let mut report = s.run(msg, &self.circuit)?;
+        report.transcript_hash = hash.digest();
+        Ok(report)
+    }
+}
+
+/// The MAC checks that were performed to verify the output, see
+/// [`Evaluator::output_with_report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputReport {
+    /// The output of the computation, `None` if any MAC check failed.
+    pub output: Option<Vec<bool>>,
+    /// Whether all MAC checks succeeded, i.e. whether the output is authentic.
+    pub verified: bool,
+    /// Number of MAC checks of the contributor's shares of the garbled AND gates.
+    pub and_gate_mac_checks: usize,
+    /// Number of MAC checks of the contributor's shares of the output masks, which are skipped if
+    /// any check of the AND gates failed.
+    pub output_mac_checks: usize,
+    /// The running hash of all messages exchanged by the evaluator, which can be sent to the
+    /// contributor to confirm the transcript, see [`Contributor::confirm_transcript_hash`].
+    pub transcript_hash: [u8; 32],
+    /// A message that discloses the output to the contributor, see
+    /// [`Contributor::disclosed_output`]. Empty if any MAC check failed.
+    ///
+    /// The message must only be sent if the evaluator agrees to reveal the output.
+    pub disclosure: Msg,
+}
+
+impl OutputReport {
+    /// Returns the total number of MAC checks that were performed.
+    pub fn mac_checks(&self) -> usize {
+        self.and_gate_mac_checks + self.output_mac_checks
+    }
+}
+
+impl<C: CircuitSource, I: InputSource> Contributor<C, I> {
+    /// Serializes the state of the contributor between two steps, so that the session can be
+    /// continued by [`Contributor::restore`] after a restart or on another machine.
+    ///
+    /// The serialized state contains the secret keys and MACs of the contributor (but neither the
+    /// circuit nor the input) and must be stored confidentially. It is authenticated using a MAC
+    /// under the specified key, which must be kept secret as well, so that modified states are
+    /// rejected instead of corrupting the session. Attached transcript recorders are not part of
+    /// the state.
+    ///
+    /// Each serialized state must be restored at most once and only if the original contributor
+    /// is discarded, since running the same state twice reuses its randomness.
+    pub fn serialize_state(&self, key: &[u8; 32]) -> Result<Vec<u8>, Error> {
+        seal_state(
+            key,
+            &SerializedParty {
+                protocol_version: crate::PROTOCOL_VERSION,
+                state_version: STATE_VERSION,
+                party: Party::Contributor,
+                circuit: circuit_digest(&self.circuit),
+                inputs: self.input.len(),
+                mode: self.mode,
+                rng_usage: self.rng_usage.per_phase().to_vec(),
+                hash: self.hash.clone(),
+                expected_hash: None,
+                state: &*self.state,
+            },
+        )
+    }
+
+    /// Restores a contributor serialized using [`Contributor::serialize_state`], which continues
+    /// the session with the next step.
+    ///
+    /// The circuit and the input must be the same as the ones of the serialized contributor. Fails
+    /// with [`Error::IncompatibleProtocolVersion`] if the state was serialized by a different
+    /// [`crate::PROTOCOL_VERSION`] and with [`Error::InvalidSessionState`] if the state was
+    /// modified, was authenticated using a different key, belongs to an [`Evaluator`] or does not
+    /// match the circuit or the length of the input.
+    pub fn restore(circuit: C, input: I, bytes: &[u8], key: &[u8; 32]) -> Result<Self, Error> {
+        let restored: SerializedParty<ContribState> =
+            open_state(key, bytes, Party::Contributor, &circuit, input.len())?;
+        let rng_usage = RngUsage::restore(restored.rng_usage, restored.state.rng());
+        Ok(Self {
+            state: Box::new(restored.state),
+            circuit,
+            input,
+            rng_usage,
+            transcript: None,
+            hash: restored.hash,
+            mode: restored.mode,
+        })
+    }
+}
+
+impl<C: CircuitSource, I: InputSource> Evaluator<C, I> {
+    /// Serializes the state of the evaluator between two steps, so that the session can be
+    /// continued by [`Evaluator::restore`], see [`Contributor::serialize_state`].
+    ///
+    /// The transcript hash expected using [`Evaluator::expect_transcript_hash`] is part of the
+    /// state.
+    pub fn serialize_state(&self, key: &[u8; 32]) -> Result<Vec<u8>, Error> {
+        seal_state(
+            key,
+            &SerializedParty {
+                protocol_version: crate::PROTOCOL_VERSION,
+                state_version: STATE_VERSION,
+                party: Party::Evaluator,
+                circuit: circuit_digest(&self.circuit),
+                inputs: self.input.len(),
+                mode: self.mode,
+                rng_usage: self.rng_usage.per_phase().to_vec(),
+                hash: self.hash.clone(),
+                expected_hash: self.expected_hash.clone(),
+                state: &*self.state,
+            },
+        )
+    }
+
+    /// Restores an evaluator serialized using [`Evaluator::serialize_state`], see
+    /// [`Contributor::restore`].
+    pub fn restore(circuit: C, input: I, bytes: &[u8], key: &[u8; 32]) -> Result<Self, Error> {
+        let restored: SerializedParty<EvalState> =
+            open_state(key, bytes, Party::Evaluator, &circuit, input.len())?;
+        let rng_usage = RngUsage::restore(restored.rng_usage, restored.state.rng());
+        Ok(Self {
+            state: Box::new(restored.state),
+            circuit,
+            input,
+            rng_usage,
+            transcript: None,
+            hash: restored.hash,
+            expected_hash: restored.expected_hash,
+            mode: restored.mode,
+        })
+    }
+}
+
+/// The state of a party as serialized by [`Contributor::serialize_state`] and
+/// [`Evaluator::serialize_state`], followed by its MAC.
+#[derive(Serialize, Deserialize)]
+struct SerializedParty<S> {
+    protocol_version: u32,
+    state_version: u32,
+    party: Party,
+    circuit: [u8; 32], //< digest of the gates of the circuit, see `circuit_digest`
+    inputs: usize,
+    mode: Mode,
+    rng_usage: Vec<u64>,
+    hash: TranscriptHash,
+    expected_hash: Option<Msg>,
+    state: S,
+}
+
+fn circuit_digest(circuit: &impl CircuitSource) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    for gate in circuit.iter_gates() {
+        gate.update_hash(&mut hasher);
+    }
+    for output_gate in circuit.output_gates() {
+        hasher.update(&output_gate.to_be_bytes());
+    }
+    *hasher.finalize().as_bytes()
+}
+
+fn state_mac(key: &[u8; 32], bytes: &[u8]) -> [u8; 32] {
+    let key = blake3::derive_key(SESSION_STATE_CONTEXT, key);
+    *blake3::keyed_hash(&key, bytes).as_bytes()
+}
+
+fn seal_state<S: Serialize>(key: &[u8; 32], party: &SerializedParty<S>) -> Result<Vec<u8>, Error> {
+    let mut bytes = serialize(party)?;
+    let mac = state_mac(key, &bytes);
+    bytes.extend(mac);
+    Ok(bytes)
+}
+
+fn open_state<S: for<'de> Deserialize<'de>>(
+    key: &[u8; 32],
+    bytes: &[u8],
+    party: Party,
+    circuit: &impl CircuitSource,
+    inputs: usize,
+) -> Result<SerializedParty<S>, Error> {
+    let version: u32 = deserialize(bytes)?;
+    if version != crate::PROTOCOL_VERSION {
+        return Err(IncompatibleProtocolVersion { version });
+    }
+    if bytes.len() < blake3::OUT_LEN {
+        return Err(InvalidSessionState);
+    }
+    let (bytes, mac) = bytes.split_at(bytes.len() - blake3::OUT_LEN);
+    if !eq_bytes(&state_mac(key, bytes), mac) {
+        return Err(InvalidSessionState);
+    }
+    // the state is only deserialized once it is known to belong to the party:
+    let header: SerializedParty<()> = deserialize(bytes)?;
+    if header.state_version != STATE_VERSION
+        || header.party != party
+        || header.circuit != circuit_digest(circuit)
+        || header.inputs != inputs
+    {
+        return Err(InvalidSessionState);
+    }
+    Ok(deserialize(bytes)?)
+}
+
+/// (De-)serializes [`PreprocessedTriples`] using [`PreprocessedTriples::to_bytes`].
+mod triples_bytes {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    use crate::PreprocessedTriples;
+
+    pub(super) fn serialize<S: Serializer>(
+        triples: &PreprocessedTriples,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let bytes = triples.to_bytes().map_err(serde::ser::Error::custom)?;
+        serializer.serialize_bytes(&bytes)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<PreprocessedTriples, D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        PreprocessedTriples::from_bytes(&bytes).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(feature = "research")]
+impl<C: CircuitSource, I: InputSource> Contributor<C, I> {
+    /// Returns the global key and the wire masks, if they have been assigned but the circuit has
+    /// not been garbled yet.
+    pub(crate) fn wire_masks_state(&self) -> Option<(&Delta, &[WireMask])> {
+        match self.state.as_ref() {
+            ContribState::Step6(s) => Some((&s.delta, &s.masks)),
+            ContribState::Loaded(s) => Some((&s.state.delta, &s.state.masks)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "research")]
+impl<C: CircuitSource, I: InputSource> Evaluator<C, I> {
+    /// Returns the global key and the wire masks, if they have been assigned but the output has
+    /// not been computed yet.
+    pub(crate) fn wire_masks_state(&self) -> Option<(&Delta, &[WireMask])> {
+        match self.state.as_ref() {
+            EvalState::Step6(EvalStep6(s)) | EvalState::LoadedStep6(EvalStep6(s), _) => {
+                Some((&s.delta, &s.masks))
             }
-            _ => Err(Error::ProtocolStillInProgress),
+            EvalState::Loaded(s, _) => Some((&s.state.delta, &s.state.masks)),
+            EvalState::Step8(s) => Some((&s.delta, &s.masks)),
+            _ => None,
         }
     }
 }
 
 type TandemResult<S> = Result<(S, Msg), Error>;
 
+impl ContribState {
+    /// Returns the RNG of the state, if the contributor still draws random bytes.
+    fn rng(&self) -> Option<&PartyRng> {
+        match self {
+            ContribState::Step1(ContribStep1(s)) => Some(&s.rng),
+            ContribState::Step1a(ContribStep1a(s)) => Some(&s.rng),
+            ContribState::Step2(ContribStep2(s)) => Some(&s.rng),
+            ContribState::Step3(ContribStep3(s)) => Some(&s.rng),
+            ContribState::Step4(ContribStep4(s)) => Some(&s.rng),
+            ContribState::Step5(ContribBucketingStep(s)) => Some(&s.rng),
+            ContribState::Step6(_)
+            | ContribState::Loaded(_)
+            | ContribState::Preprocessed(_)
+            | ContribState::Done(_) => None,
+        }
+    }
+}
+
+impl EvalState {
+    /// Returns the RNG of the state, if the evaluator still draws random bytes.
+    fn rng(&self) -> Option<&PartyRng> {
+        match self {
+            EvalState::Step1(EvalStep1(s)) => Some(&s.rng),
+            EvalState::Step2(EvalStep2(s)) => Some(&s.rng),
+            EvalState::Step2a(EvalStep2a(s)) => Some(&s.rng),
+            EvalState::Step3(EvalStep3(s)) => Some(&s.rng),
+            EvalState::Step4(EvalStep4(s)) => Some(&s.rng),
+            EvalState::Step5(EvalStep5(s)) => Some(&s.rng),
+            EvalState::Step6(_)
+            | EvalState::Step8(_)
+            | EvalState::Loaded(..)
+            | EvalState::LoadedStep6(..)
+            | EvalState::Preprocessed(_)
+            | EvalState::Done() => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 enum ContribState {
     Step1(ContribStep1),
     Step1a(ContribStep1a),
@@ -206,9 +935,12 @@ enum ContribState {
     Step4(ContribStep4),
     Step5(ContribBucketingStep),
     Step6(InputProcContrib),
-    Done,
+    Loaded(LoadedStep),
+    Preprocessed(#[serde(with = "triples_bytes")] PreprocessedTriples),
+    Done(OutputKeys),
 }
 
+#[derive(Serialize, Deserialize)]
 enum EvalState {
     Step1(EvalStep1),
     Step2(EvalStep2),
@@ -218,95 +950,118 @@ enum EvalState {
     Step5(EvalStep5),
     Step6(EvalStep6),
     Step8(InputProcEval),
+    Loaded(LoadedStep, Msg),
+    LoadedStep6(EvalStep6, Msg),
+    Preprocessed(#[serde(with = "triples_bytes")] PreprocessedTriples),
     Done(),
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct EvalStep1(OtPreInitState);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct ContribStep1(OtInitState1);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct ContribStep1a(OtInitState3);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct EvalStep2(OtInitState2);
 
+#[derive(Serialize, Deserialize)]
 struct EvalStep2a(OtInitState4);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct ContribStep2(OtAndsState1);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct EvalStep3(OtAndsState2);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct ContribStep3(OtAndsState2);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct EvalStep4(OtAndsState3);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct ContribStep4(OtAndsState4);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct ContribBucketingStep(AndsBucketingState);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct EvalStep5(OtAndsState5);
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct EvalStep6(OtAndsState6);
 
-#[derive(Clone)]
+/// A session started from [`PreprocessedTriples`], before the AND gate bits are opened.
+#[derive(Clone, Serialize, Deserialize)]
+struct LoadedStep {
+    state: OtAndsState6,
+    id: [u8; 32],
+    keys: Vec<(KeyType, KeyType)>, //< keys of the opened bits of the other party
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 struct OtPreInitState {
-    rng: ChaCha20Rng,
+    rng: PartyRng,
+    options: ProtocolOptions,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct OtInitState1 {
-    rng: ChaCha20Rng,
+    rng: PartyRng,
     delta: Delta,
     r_init: ReceiverInitializer,
     coin_share: CoinShare,
+    options: ProtocolOptions, //< proposed options
+    silent_s: Option<SilentSender>,
     blocks: usize,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct OtInitState2 {
-    rng: ChaCha20Rng,
+    rng: PartyRng,
     delta: Delta,
     r_init: ReceiverInitializer,
     s: SenderInitializer,
     coin_share: CoinShare,
     coin_commitment: Vec<u8>, //< upstream coin commitment message
+    silent_s: Option<SilentSender>,
+    silent_r: Option<SilentReceiver>,
     blocks: usize,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct OtInitState3 {
-    rng: ChaCha20Rng,
+    rng: PartyRng,
     delta: Delta,
     s: SenderInitializer,
     r: LeakyOtReceiver,
     coin: CoinResult,
+    options: ProtocolOptions,
+    silent_s: Option<SilentSender>,
+    silent_r: Option<SilentReceiver>,
     blocks: usize,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct OtInitState4 {
-    rng: ChaCha20Rng,
+    rng: PartyRng,
     delta: Delta,
     s: LeakyOtSender,
     coin: CoinResult,
+    options: ProtocolOptions,
+    silent_s: Option<SilentSender>,
+    silent_check: Option<[u8; 32]>, //< expected hash of the silent OT consistency check
     blocks: usize,
     abits: Vec<BitShare>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct OtAndsState1 {
-    rng: ChaCha20Rng,
+    rng: PartyRng,
     delta: Delta,
     coin: CoinResult,
     random_bits: Vec<MacType>,
@@ -316,11 +1071,12 @@ struct OtAndsState1 {
     r_and_rand_key: Vec<(MacType, KeyType)>,
     r_and_rand_hash: Vec<MacType>,
     r_prime: Vec<MacType>,
+    silent_check: Option<[u8; 32]>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct OtAndsState2 {
-    rng: ChaCha20Rng,
+    rng: PartyRng,
     delta: Delta,
     coin: CoinResult,
     and_triples: Vec<BitShare>,
@@ -331,9 +1087,9 @@ struct OtAndsState2 {
     r_prime: Vec<MacType>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct OtAndsState3 {
-    rng: ChaCha20Rng,
+    rng: PartyRng,
     delta: Delta,
     coin: CoinResult,
     and_triples: Vec<BitShare>,
@@ -343,9 +1099,9 @@ struct OtAndsState3 {
     r_prime: Vec<MacType>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct OtAndsState4 {
-    rng: ChaCha20Rng,
+    rng: PartyRng,
     delta: Delta,
     coin: CoinResult,
     and_triples: Vec<BitShare>,
@@ -355,9 +1111,9 @@ struct OtAndsState4 {
     r_prime: Vec<MacType>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct OtAndsState5 {
-    rng: ChaCha20Rng,
+    rng: PartyRng,
     delta: Delta,
     coin: CoinResult,
     and_triples: Vec<BitShare>,
@@ -367,10 +1123,11 @@ struct OtAndsState5 {
     r_prime: Vec<MacType>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct AndsBucketingState {
-    rng: ChaCha20Rng,
+    rng: PartyRng,
     delta: Delta,
+    id: [u8; 32], //< identifies the preprocessing session, derived from the coin
     bucketing_bits: Vec<bool>,
     wire_abits: Vec<BitShare>,
     and_triples: Vec<BitShare>,
@@ -379,7 +1136,7 @@ struct AndsBucketingState {
     bucket_size: usize,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct OtAndsState6 {
     delta: Delta,
     and_triples: Vec<BitShare>,
@@ -389,7 +1146,7 @@ struct OtAndsState6 {
 }
 
 /// WRK17 "input processing phase".
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 struct InputProcContrib {
     delta: Delta,
     pending_from_b: usize,
@@ -397,80 +1154,134 @@ struct InputProcContrib {
     masks: Vec<WireMask>,
 }
 
+/// What the contributor needs to verify an output disclosed by the evaluator.
+#[derive(Serialize, Deserialize)]
+struct OutputKeys {
+    delta: Delta,
+    /// The masks of the output wires, in the order of the output gates.
+    masks: Vec<WireMask>,
+}
+
+/// The label, the masked value and the evaluator's share of the mask of an output wire.
+type DisclosedOutput = (WireLabel, bool, PartialBitShare);
+
+impl OutputKeys {
+    fn verify(&self, msg: &[u8]) -> Result<Vec<bool>, Error> {
+        let disclosed: Vec<DisclosedOutput> = deserialize(msg)?;
+        if disclosed.len() != self.masks.len() {
+            return Err(UnexpectedMessageType);
+        }
+        let mut mac_checks_success = true;
+        let mut output = Vec::with_capacity(disclosed.len());
+        for ((label, masked_value, share), mask) in disclosed.into_iter().zip(&self.masks) {
+            // the label proves the masked value, the MAC the evaluator's share of the mask:
+            let expected = mask.label(masked_value, &self.delta);
+            mac_checks_success &= eq_u128(label.0, expected.0);
+            mac_checks_success &= share.verify(&mask.bit.key, &self.delta);
+            output.push(masked_value ^ share.bit ^ mask.bit.bit);
+        }
+        if mac_checks_success {
+            Ok(output)
+        } else {
+            Err(MacError)
+        }
+    }
+}
+
 /// WRK17 "input processing phase" / "circuit evaluation phase".
+#[derive(Serialize, Deserialize)]
 struct InputProcEval {
     delta: Delta,
     pending_input: usize,
     masks: Vec<WireMask>,
-    wires: Vec<WireState>,
+    /// The shares of the garbled tables, indexed by the position of the AND gate among all AND
+    /// gates of the circuit.
+    and_tables: Vec<AndTables>,
 }
 
 impl EvalStep1 {
-    pub(crate) fn init(circuit: &Circuit, input: &[bool], rng: ChaCha20Rng) -> Result<Self, Error> {
-        circuit.validate_evaluator_input(input)?;
-        let state = OtPreInitState { rng };
+    pub(crate) fn init(
+        circuit: &impl CircuitSource,
+        input: &(impl InputSource + ?Sized),
+        rng: PartyRng,
+        options: ProtocolOptions,
+    ) -> Result<Self, Error> {
+        if circuit.eval_inputs() != input.len() {
+            return Err(InsufficientInput);
+        }
+        let state = OtPreInitState { rng, options };
         Ok(Self(state))
     }
 }
 
 impl ContribStep1 {
     pub(crate) fn init(
-        circuit: &Circuit,
-        input: &[bool],
-        mut rng: ChaCha20Rng,
+        circuit: &impl CircuitSource,
+        input: &(impl InputSource + ?Sized),
+        mut rng: PartyRng,
+        options: ProtocolOptions,
     ) -> Result<(Self, Msg), Error> {
-        circuit.validate_contributor_input(input)?;
-        let (state, msg) = init_ot1(Delta::gen_random(&mut rng), rng, circuit)?;
+        if circuit.contrib_inputs() != input.len() {
+            return Err(InsufficientInput);
+        }
+        let delta = Delta::gen_random(&mut rng);
+        let (state, msg) = init_ot1(delta, rng, circuit, options, options.base_ot)?;
         Ok((Self(state), msg))
     }
 }
 
 impl EvalStep1 {
-    fn run(mut self, msg: &[u8], circuit: &Circuit) -> TandemResult<EvalStep2> {
-        let (state, reply1) = init_ot1(Delta::gen_random(&mut self.0.rng), self.0.rng, circuit)?;
+    fn run(mut self, msg: &[u8], circuit: &impl CircuitSource) -> TandemResult<EvalStep2> {
+        let (upstream_ot_init, _, _): (SerializedOtInit, Vec<u8>, Option<SilentInit>) =
+            deserialize(msg)?;
+        // the evaluator switches to the base OT of the contributor if it is more conservative:
+        let base_ot = self.0.options.base_ot.max(upstream_ot_init.base_ot());
+        let delta = Delta::gen_random(&mut self.0.rng);
+        let (state, reply1) = init_ot1(delta, self.0.rng, circuit, self.0.options, base_ot)?;
         let (state, reply2) = init_ot2(state, msg)?;
-        let reply = serialize(&(reply1, reply2))?;
+        let reply = frame(&[&reply1, &reply2]);
         Ok((EvalStep2(state), reply))
     }
 }
 
 impl ContribStep1 {
     fn run(self, msg: &[u8]) -> TandemResult<ContribStep1a> {
-        let (msg1, msg2): (Msg, Msg) = deserialize(msg)?;
-        let (state, reply1) = init_ot2(self.0, &msg1)?;
-        let (state, reply2) = init_ot3(state, &msg2)?;
-        let reply = serialize(&(reply1, reply2))?;
+        let (msg1, msg2) = sections2(msg)?;
+        let (state, reply1) = init_ot2(self.0, msg1)?;
+        let (state, reply2) = init_ot3(state, msg2)?;
+        let reply = frame(&[&reply1, &reply2]);
         Ok((ContribStep1a(state), reply))
     }
 }
 
 impl EvalStep2 {
     fn run(self, msg: &[u8]) -> TandemResult<EvalStep2a> {
-        let (msg1, msg2): (Msg, Msg) = deserialize(msg)?;
-        let (state, reply1) = init_ot3(self.0, &msg1)?;
+        let (msg1, msg2) = sections2(msg)?;
+        let (state, reply1) = init_ot3(self.0, msg1)?;
         let (state, reply2) = init_ot4(state, msg2)?;
-        let reply = serialize(&(reply1, reply2))?;
+        let reply = frame(&[&reply1, &reply2]);
         Ok((EvalStep2a(state), reply))
     }
 }
 
 impl ContribStep1a {
-    fn run(self, msg: &[u8], circuit: &Circuit) -> TandemResult<ContribStep2> {
-        let (msg1, msg2): (Msg, Msg) = deserialize(msg)?;
+    fn run(self, msg: &[u8], circuit: &impl CircuitSource) -> TandemResult<ContribStep2> {
+        let (msg1, msg2) = sections2(msg)?;
         let (state, reply1) = init_ot4(self.0, msg1)?;
-        let (state, reply2) = ot_ands1(state, &msg2, circuit)?;
-        let reply = serialize(&(reply1, reply2))?;
+        let (state, reply2) = ot_ands1(state, msg2, circuit)?;
+        let reply = frame(&[&reply1, &reply2]);
         Ok((ContribStep2(state), reply))
     }
 }
 
 impl EvalStep2a {
-    fn run(self, msg: &[u8], circuit: &Circuit) -> TandemResult<EvalStep3> {
-        let (msg1, msg2): (Msg, Msg) = deserialize(msg)?;
-        let (state, reply) = ot_ands1(self.0, &msg1, circuit)?;
+    fn run(self, msg: &[u8], circuit: &impl CircuitSource) -> TandemResult<EvalStep3> {
+        let (msg1, msg2) = sections2(msg)?;
+        let (state, reply) = ot_ands1(self.0, msg1, circuit)?;
 
         // Step 2 of `Π_{LaAND}`
-        let and_hashes: Vec<[MacType; 2]> = deserialize(&msg2)?;
+        let (and_hashes, silent_check): (Vec<[MacType; 2]>, Option<[u8; 32]>) = deserialize(msg2)?;
+        verify_silent_check(state.silent_check, silent_check)?;
         let and_shares = state.compute_and_shares(&and_hashes, Role::Evaluator)?;
         let state = OtAndsState2 {
             rng: state.rng,
@@ -490,8 +1301,9 @@ impl EvalStep2a {
 impl ContribStep2 {
     // Implements Step 2 of `Π_{LaAND}` of WRK17a
     fn run(self, msg: &[u8]) -> TandemResult<ContribStep3> {
-        let and_hashes: Vec<[MacType; 2]> = deserialize(msg)?;
+        let (and_hashes, silent_check): (Vec<[MacType; 2]>, Option<[u8; 32]>) = deserialize(msg)?;
         let state = self.0;
+        verify_silent_check(state.silent_check, silent_check)?;
         let and_shares = state.compute_and_shares(&and_hashes, Role::Contributor)?;
         let reply = serialize(&and_shares)?;
         let state = OtAndsState2 {
@@ -512,68 +1324,101 @@ impl ContribStep2 {
 /// Receives its message from [`ContribStep2`] which is a (large) vector of `AND` shares.
 impl EvalStep3 {
     fn run(self, msg: &[u8]) -> TandemResult<EvalStep4> {
-        let (state, replies) = ot_ands3_update_z2_eval(self.0, msg)?;
-        let reply = serialize(&replies)?;
+        let (state, (reply1, reply2)) = ot_ands3_update_z2_eval(self.0, msg)?;
+        let reply = frame(&[&reply1, &reply2]);
         Ok((EvalStep4(state), reply))
     }
 }
 
 impl ContribStep3 {
     fn run(self, msg: &[u8]) -> TandemResult<ContribStep4> {
-        let (msg1, msg2): (Msg, Msg) = deserialize(msg)?;
-        let (state, reply1) = ot_ands3_update_z2_contrib(self.0, &msg1)?;
-        let (state, reply2) = ot_ands4(state, &msg2)?;
-        let reply = serialize(&(reply1, reply2))?;
+        let (msg1, msg2) = sections2(msg)?;
+        let (state, reply1) = ot_ands3_update_z2_contrib(self.0, msg1)?;
+        let (state, reply2) = ot_ands4(state, msg2)?;
+        let reply = frame(&[&reply1, &reply2]);
         Ok((ContribStep4(state), reply))
     }
 }
 
 impl EvalStep4 {
     fn run(self, msg: &[u8]) -> TandemResult<EvalStep5> {
-        let (msg1, msg2): (Msg, Msg) = deserialize(msg)?;
-        let (state, reply1) = ot_ands4(self.0, &msg1)?;
-        let (state, reply2) = ot_ands5(state, &msg2)?;
-        let reply = serialize(&(reply1, reply2))?;
+        let (msg1, msg2) = sections2(msg)?;
+        let (state, reply1) = ot_ands4(self.0, msg1)?;
+        let (state, reply2) = ot_ands5(state, msg2)?;
+        let reply = frame(&[&reply1, &reply2]);
         Ok((EvalStep5(state), reply))
     }
 }
 
 impl ContribStep4 {
-    fn run(self, msg: &[u8], circuit: &Circuit) -> TandemResult<AndsBucketingState> {
-        let (msg1, msg2): (Msg, Msg) = deserialize(msg)?;
-        let (state, reply1) = ot_ands5(self.0, &msg1)?;
-        let (state, reply2) = ot_ands6(state, &msg2, circuit)?;
-        let reply = serialize(&(reply1, reply2))?;
+    fn run(self, msg: &[u8], circuit: &impl CircuitSource) -> TandemResult<AndsBucketingState> {
+        let (msg1, msg2) = sections2(msg)?;
+        let (state, reply1) = ot_ands5(self.0, msg1)?;
+        let (state, reply2) = ot_ands6(state, msg2, circuit)?;
+        let reply = frame(&[&reply1, &reply2]);
         Ok((state, reply))
     }
 }
 
 impl EvalStep5 {
-    fn run(self, msg: &[u8], circuit: &Circuit) -> TandemResult<EvalStep6> {
-        let (msg1, msg2): (Msg, Msg) = deserialize(msg)?;
-        let (state, reply1) = ot_ands6(self.0, &msg1, circuit)?;
-        let (state, reply2) = state.finish(&msg2, circuit)?;
+    fn run(self, msg: &[u8], circuit: &impl CircuitSource) -> TandemResult<EvalStep6> {
+        let (msg1, msg2) = sections2(msg)?;
+        let (state, reply1) = ot_ands6(self.0, msg1, circuit)?;
+        let (state, reply2) = state.finish(msg2, circuit)?;
 
-        let msg = serialize(&(reply1, reply2))?;
+        let msg = frame(&[&reply1, &reply2]);
         Ok((EvalStep6(state), msg))
     }
 }
 
 impl ContribBucketingStep {
-    fn run(self, msg: &[u8], circuit: &Circuit, input: &[bool]) -> TandemResult<InputProcContrib> {
-        let (msg1, msg2): (Msg, Msg) = deserialize(msg)?;
-        let (state, reply1) = self.0.finish(&msg1, circuit)?;
-        let (state, reply2) = ot_ands8_contrib(state, &msg2, circuit, input)?;
-
-        let msg = serialize(&(reply1, reply2))?;
+    fn run(
+        self,
+        msg: &[u8],
+        circuit: &impl CircuitSource,
+        input: &(impl InputSource + ?Sized),
+    ) -> TandemResult<InputProcContrib> {
+        let (msg1, msg2) = sections2(msg)?;
+        let (state, reply1) = self.0.finish(msg1, circuit)?;
+        let (state, reply2) = ot_ands8_contrib(state, msg2, circuit, input)?;
+
+        let msg = frame(&[&reply1, &reply2]);
         Ok((state, msg))
     }
 }
 
+impl EvalStep5 {
+    fn run_preprocessing(
+        self,
+        msg: &[u8],
+        circuit: &impl CircuitSource,
+    ) -> TandemResult<PreprocessedTriples> {
+        let (msg1, msg2) = sections2(msg)?;
+        let (state, reply1) = ot_ands6(self.0, msg1, circuit)?;
+        let state = state.update_triples(msg2)?;
+
+        let msg = frame(&[&reply1, &[]]);
+        Ok((state.preprocessed(Party::Evaluator), msg))
+    }
+}
+
+impl ContribBucketingStep {
+    fn run_preprocessing(self, msg: &[u8]) -> TandemResult<PreprocessedTriples> {
+        let (msg1, _) = sections2(msg)?;
+        let state = self.0.update_triples(msg1)?;
+        Ok((state.preprocessed(Party::Contributor), vec![]))
+    }
+}
+
 impl EvalStep6 {
-    fn run(self, msg: &[u8], circuit: &Circuit, input: &[bool]) -> TandemResult<InputProcEval> {
-        let (msg1, msg2): (Msg, Msg) = deserialize(msg)?;
-        let (state, reply) = ot_ands8_eval(self.0, &msg1, &msg2, circuit, input)?;
+    fn run(
+        self,
+        msg: &[u8],
+        circuit: &impl CircuitSource,
+        input: &(impl InputSource + ?Sized),
+    ) -> TandemResult<InputProcEval> {
+        let (msg1, msg2) = sections2(msg)?;
+        let (state, reply) = ot_ands8_eval(self.0, msg1, msg2, circuit, input)?;
         Ok((state, reply))
     }
 }
@@ -581,7 +1426,7 @@ impl EvalStep6 {
 type StateResult<S> = Result<(S, Msg), Error>;
 
 /// Calculates the bucket size according to WRK17a, Table 4 for statistical security ρ = 40 (rho).
-fn bucket_size(circuit: &Circuit) -> usize {
+pub(crate) fn bucket_size(circuit: &impl CircuitSource) -> usize {
     match circuit.and_gates() {
         n if n >= 280_000 => 3,
         n if n >= 3_100 => 4,
@@ -594,9 +1439,11 @@ enum Role {
     Evaluator,
 }
 
-fn init_ot1(delta: Delta, mut rng: ChaCha20Rng, p: &Circuit) -> StateResult<OtInitState1> {
-    p.validate()?;
-
+/// Returns the number of OT blocks of authenticated bits and the number of blocks of AND triples.
+///
+/// Each OT block consists of [`BLOCK_SIZE`] authenticated bits, each block of AND triples of 3 OT
+/// blocks.
+pub(crate) fn abit_blocks(p: &impl CircuitSource) -> (usize, usize) {
     // the number of authenticated bits we need for wires
     let wire_abits = p.and_gates() + p.eval_inputs() + p.contrib_inputs();
 
@@ -605,30 +1452,72 @@ fn init_ot1(delta: Delta, mut rng: ChaCha20Rng, p: &Circuit) -> StateResult<OtIn
     let triples_bits_aligned = (triples_bits + TRIPLES - 1) / TRIPLES * TRIPLES;
     let total_abits = wire_abits + triples_bits_aligned;
     let num_abits_aligned = (total_abits + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE;
-    let (r_init, ot_msg) = ReceiverInitializer::init(&mut rng);
+    (
+        num_abits_aligned / BLOCK_SIZE,
+        triples_bits_aligned / TRIPLES,
+    )
+}
+
+fn init_ot1(
+    delta: Delta,
+    mut rng: PartyRng,
+    p: &impl CircuitSource,
+    options: ProtocolOptions,
+    base_ot: BaseOt,
+) -> StateResult<OtInitState1> {
+    crate::source::check(p)?;
+
+    let (blocks, _) = abit_blocks(p);
+    let (r_init, ot_msg) = ReceiverInitializer::init(&mut rng, base_ot);
     let (coin_share, coin_msg) = {
         let mut coin = [0u8; protocol::cointossing::COIN_LEN];
         rng.fill(&mut coin);
-        protocol::cointossing::init(coin)?
+        protocol::cointossing::init(coin, options)?
     };
 
-    let msg = serialize(&(&ot_msg.serialize(), &coin_msg))?;
+    let (silent_s, silent_msg) = match options.ot_backend {
+        OtBackend::Extension => (None, None),
+        OtBackend::Silent => {
+            let params = SilentParams::new(blocks * BLOCK_SIZE);
+            let (silent_s, silent_msg) = SilentSender::init(&mut rng, params);
+            (Some(silent_s), Some(silent_msg))
+        }
+    };
+
+    let msg = serialize(&(&ot_msg.serialize(), &coin_msg, &silent_msg))?;
     let state = OtInitState1 {
         rng,
         delta,
         r_init,
         coin_share,
-        blocks: num_abits_aligned / BLOCK_SIZE,
+        options,
+        silent_s,
+        blocks,
     };
     Ok((state, msg))
 }
 
 fn init_ot2(mut state: OtInitState1, msg: &[u8]) -> StateResult<OtInitState2> {
-    let (serialized_ot_init, coin_commitment): (SerializedOtInit, Vec<u8>) = deserialize(msg)?;
+    let (serialized_ot_init, coin_commitment, silent_init): (
+        SerializedOtInit,
+        Vec<u8>,
+        Option<SilentInit>,
+    ) = deserialize(msg)?;
     let ot_init = serialized_ot_init.deserialize()?;
-    let sender = SenderInitializer::init(&mut state.rng, state.delta.clone(), &ot_init);
+    let sender = SenderInitializer::init(&mut state.rng, state.delta.clone(), &ot_init)?;
     let coin_msg = protocol::cointossing::serialize(&state.coin_share)?;
-    let msg = serialize(&(sender.1.serialize(), coin_msg))?;
+    // the options of the other party are only revealed by the coin tossing, so the silent OT is
+    // prepared whenever both parties propose it (otherwise it cannot be negotiated anyway):
+    let (silent_r, silent_msg) = match (state.options.ot_backend, silent_init) {
+        (OtBackend::Silent, Some(silent_init)) => {
+            let params = SilentParams::new(state.blocks * BLOCK_SIZE);
+            let (silent_r, silent_msg) =
+                SilentReceiver::init(&mut state.rng, params, &silent_init)?;
+            (Some(silent_r), Some(silent_msg))
+        }
+        _ => (None, None),
+    };
+    let msg = serialize(&(sender.1.serialize(), coin_msg, silent_msg))?;
     let state = OtInitState2 {
         rng: state.rng,
         delta: state.delta,
@@ -636,37 +1525,96 @@ fn init_ot2(mut state: OtInitState1, msg: &[u8]) -> StateResult<OtInitState2> {
         s: sender.0,
         coin_share: state.coin_share,
         coin_commitment,
+        silent_s: state.silent_s,
+        silent_r,
         blocks: state.blocks,
     };
     Ok((state, msg))
 }
 
-fn init_ot3(state: OtInitState2, msg: &[u8]) -> StateResult<OtInitState3> {
-    let (serialized_ot_init, upstream_coin): (SerializedOtInit, Vec<u8>) = deserialize(msg)?;
-    let coin =
+fn init_ot3(mut state: OtInitState2, msg: &[u8]) -> StateResult<OtInitState3> {
+    let (serialized_ot_init, upstream_coin, silent_choices): (
+        SerializedOtInit,
+        Vec<u8>,
+        Option<SilentChoices>,
+    ) = deserialize(msg)?;
+    let (coin, options) =
         protocol::cointossing::finish(state.coin_share, state.coin_commitment, upstream_coin)?;
+    // a party proposing a more conservative base OT must never fall back to a weaker one:
+    if state.r_init.base_ot() != options.base_ot || state.s.base_ot() != options.base_ot {
+        return Err(IncompatibleBaseOt);
+    }
     let ot_init = serialized_ot_init.deserialize()?;
-    let (r, reply) = state.r_init.recv(&ot_init);
-    let reply = reply.serialize();
+    let (r, reply) = state.r_init.recv(&ot_init)?;
+    let (silent_s, silent_r, silent_reply) = match options.ot_backend {
+        OtBackend::Extension => (None, None, None),
+        OtBackend::Silent => match (state.silent_s, state.silent_r, silent_choices) {
+            (Some(mut silent_s), Some(silent_r), Some(silent_choices)) => {
+                let silent_reply = silent_s.send(&mut state.rng, &state.delta, &silent_choices)?;
+                (Some(silent_s), Some(silent_r), Some(silent_reply))
+            }
+            _ => return Err(UnexpectedMessageType),
+        },
+    };
+    let reply = serialize(&(reply.serialize(), silent_reply))?;
     let state = OtInitState3 {
         rng: state.rng,
         delta: state.delta,
         s: state.s,
         r,
         coin,
+        options,
+        silent_s,
+        silent_r,
         blocks: state.blocks,
     };
     Ok((state, reply))
 }
 
-fn init_ot4(mut state: OtInitState3, msg: Vec<u8>) -> StateResult<OtInitState4> {
-    let init_msg = OtInitReply::deserialize(msg)?;
-    let s = state.s.recv(&init_msg);
+/// Returns the number of OT blocks that are sent by the OT extension for `blocks` blocks of
+/// authenticated bits, including the blocks of the correlation check.
+///
+/// With [`OtBackend::Silent`], the OT extension only generates the base correlated OTs that are
+/// expanded by the silent OT.
+pub(crate) fn ot_blocks(blocks: usize, options: &ProtocolOptions) -> usize {
+    let blocks = match options.ot_backend {
+        OtBackend::Extension => blocks,
+        OtBackend::Silent => {
+            let base_cots = SilentParams::new(blocks * BLOCK_SIZE).base_cots();
+            (base_cots + BLOCK_SIZE - 1) / BLOCK_SIZE
+        }
+    };
+    let check_blocks = match options.ot_extension {
+        OtExtension::Alsz13 => 0,
+        OtExtension::Kos15 => CHECK_BLOCKS,
+    };
+    blocks + check_blocks
+}
+
+/// Compares the hash of the silent OT consistency check sent by the other party to the expected
+/// hash.
+fn verify_silent_check(
+    expected: Option<[u8; 32]>,
+    received: Option<[u8; 32]>,
+) -> Result<(), Error> {
+    match (expected, received) {
+        (None, None) => Ok(()),
+        (Some(expected), Some(received)) if expected == received => Ok(()),
+        (Some(_), Some(_)) => Err(OtCorrelationCheckFailed),
+        _ => Err(UnexpectedMessageType),
+    }
+}
+
+fn init_ot4(mut state: OtInitState3, msg: &[u8]) -> StateResult<OtInitState4> {
+    let (init_msg, silent_reply): (Vec<u8>, Option<SilentReply>) = deserialize(msg)?;
+    let init_msg = OtInitReply::deserialize(init_msg, state.options.base_ot)?;
+    let s = state.s.recv(&init_msg)?;
 
     let mut r = state.r;
     let mut blocks = Vec::new();
-    let mut abits = vec![BitShare::default(); state.blocks * BLOCK_SIZE];
-    for block_id in 0..state.blocks {
+    let total_blocks = ot_blocks(state.blocks, &state.options);
+    let mut abits = vec![BitShare::default(); total_blocks * BLOCK_SIZE];
+    for block_id in 0..total_blocks {
         let mut macs_out = [MacType(0); BLOCK_SIZE];
         let mut ot_out = Box::new([MacType(0); BLOCK_SIZE]);
         let bits: u128 = state.rng.gen();
@@ -679,34 +1627,104 @@ fn init_ot4(mut state: OtInitState3, msg: Vec<u8>) -> StateResult<OtInitState4>
         }
         blocks.push(ot_out.to_vec());
     }
-    let reply = serialize(&blocks)?;
+    let check = match state.options.ot_extension {
+        OtExtension::Alsz13 => None,
+        OtExtension::Kos15 => Some(CorrelationCheck::new(
+            &state.coin,
+            &blocks,
+            abits.iter().map(|abit| (abit.bit, abit.mac)),
+        )),
+    };
+    let (abits, silent_correction, silent_check) = match (state.silent_r, silent_reply) {
+        (None, None) => {
+            abits.truncate(state.blocks * BLOCK_SIZE);
+            (abits, None, None)
+        }
+        (Some(silent_r), Some(silent_reply)) => {
+            let base_cots = SilentParams::new(state.blocks * BLOCK_SIZE).base_cots();
+            let base: Vec<(bool, MacType)> = abits[..base_cots]
+                .iter()
+                .map(|abit| (abit.bit, abit.mac))
+                .collect();
+            let (cots, correction, check) = silent_r.expand(&state.coin, &silent_reply, &base)?;
+            let abits = cots
+                .into_iter()
+                .map(|(bit, mac)| BitShare {
+                    bit,
+                    mac,
+                    ..Default::default()
+                })
+                .collect();
+            (abits, Some(correction), Some(check))
+        }
+        _ => return Err(UnexpectedMessageType),
+    };
+    let reply = serialize(&(blocks, check, silent_correction))?;
 
     let state = OtInitState4 {
         rng: state.rng,
         delta: state.delta,
         blocks: state.blocks,
         coin: state.coin,
+        options: state.options,
+        silent_s: state.silent_s,
+        silent_check,
         abits,
         s,
     };
     Ok((state, reply))
 }
 
-fn ot_ands1(mut state: OtInitState4, msg: &[u8], circuit: &Circuit) -> StateResult<OtAndsState1> {
-    let blocks: Vec<Vec<MacType>> = deserialize(msg)?;
-    for (block_id, block) in blocks.into_iter().enumerate() {
-        let ot_rx: [MacType; BLOCK_SIZE] = block
+fn ot_ands1(
+    mut state: OtInitState4,
+    msg: &[u8],
+    circuit: &impl CircuitSource,
+) -> StateResult<OtAndsState1> {
+    let (blocks, check, silent_correction): (
+        Vec<Vec<MacType>>,
+        Option<CorrelationCheck>,
+        Option<u128>,
+    ) = deserialize(msg)?;
+    let total_blocks = ot_blocks(state.blocks, &state.options);
+    if blocks.len() != total_blocks {
+        return Err(Error::OtBlockDeserializationError);
+    }
+    let mut keys = vec![KeyType(0); total_blocks * BLOCK_SIZE];
+    for (block_id, block) in blocks.iter().enumerate() {
+        let ot_rx: &[MacType; BLOCK_SIZE] = block
+            .as_slice()
             .try_into()
             .map_err(|_| Error::OtBlockDeserializationError)?;
-        let ot_rx = Box::new(ot_rx);
         let mut keys_out = [MacType(0); BLOCK_SIZE];
-        state.s.send(ot_rx.as_ref(), &mut keys_out);
+        state.s.send(ot_rx, &mut keys_out);
 
-        let abits = &mut state.abits[block_id * BLOCK_SIZE..];
+        let keys = &mut keys[block_id * BLOCK_SIZE..];
         for i in 0..BLOCK_SIZE {
-            abits[i].key = KeyType(keys_out[i].0);
+            keys[i] = KeyType(keys_out[i].0);
         }
     }
+    match (state.options.ot_extension, check) {
+        (OtExtension::Alsz13, None) => {}
+        (OtExtension::Kos15, Some(check)) => {
+            if !check.verify(&state.coin, &blocks, &state.delta, keys.iter().copied()) {
+                return Err(OtCorrelationCheckFailed);
+            }
+        }
+        _ => return Err(UnexpectedMessageType),
+    }
+    let (keys, silent_check) = match (state.silent_s, silent_correction) {
+        (None, None) => (keys, None),
+        (Some(silent_s), Some(correction)) => {
+            let base_cots = SilentParams::new(state.blocks * BLOCK_SIZE).base_cots();
+            let (keys, check) =
+                silent_s.expand(&state.coin, &state.delta, &keys[..base_cots], correction);
+            (keys, Some(check))
+        }
+        _ => return Err(UnexpectedMessageType),
+    };
+    for (abit, key) in state.abits.iter_mut().zip(keys) {
+        abit.key = key;
+    }
 
     // the number of authenticated bits we need for wires
     let n_and_gates = circuit.and_gates();
@@ -740,11 +1758,12 @@ fn ot_ands1(mut state: OtInitState4, msg: &[u8], circuit: &Circuit) -> StateResu
         r_and_rand_key: vec![],
         r_and_rand_hash: vec![],
         r_prime: vec![],
+        silent_check: state.silent_check,
     };
 
     // Step 1 of `Π_{LaAND}`
     let and_hashes = state.compute_and_ot_data();
-    let msg = serialize(&and_hashes)?;
+    let msg = serialize(&(and_hashes, silent_check))?;
 
     Ok((state, msg))
 }
@@ -891,7 +1910,7 @@ fn ot_ands3_update_z2_eval(
     let and_bits = &mut state.and_triples[0..];
     let num_blocks = and_bits.len() / BLOCK_SIZE / 3;
 
-    if and_bits.len() != num_blocks * BLOCK_SIZE * 3 {
+    if and_bits.len() != num_blocks * BLOCK_SIZE * 3 || upstream_ands.len() != num_blocks {
         return Err(InsufficientAndShares);
     }
 
@@ -1051,14 +2070,14 @@ fn check_hash(
     for (i, (r, rand_key)) in r_and_rand.iter().enumerate() {
         let hashed = hash_keys(KeyType(r.0), KeyType(rand_key.0));
         // check that the hash received previously matches the r + rand received now:
-        let hash_ok = state.r_and_rand_hash[i] == hashed;
+        let hash_ok = eq_u128(state.r_and_rand_hash[i].0, hashed.0);
         // check that the r received now matches own r':
-        let r_equal = *r == state.r_prime[i];
+        let r_equal = eq_u128(r.0, state.r_prime[i].0);
         success &= hash_ok & r_equal;
     }
     for (i, r_prime) in r_prime.iter().enumerate() {
         // check that the r' received now from the other party matches own r:
-        let r_prime_check = state.r_and_rand_key[i].0 == *r_prime;
+        let r_prime_check = eq_u128(state.r_and_rand_key[i].0 .0, r_prime.0);
 
         success &= r_prime_check;
     }
@@ -1111,15 +2130,15 @@ fn mac(delta: &Delta, value: u128, bit: bool) -> u128 {
 /// Implements Step 2 + 3 + 4a of Π_{2pc}.
 fn preprocessing_assign_masks(
     abits: Vec<BitShare>,
-    rng: &mut ChaCha20Rng,
+    rng: &mut PartyRng,
     delta: &Delta,
-    circuit: &Circuit,
+    circuit: &impl CircuitSource,
 ) -> Vec<WireMask> {
-    let mut masks = vec![WireMask::default(); circuit.gates().len()];
+    let mut masks = vec![WireMask::default(); circuit.num_gates()];
 
     // assign output masks to each wire
     let mut abit_offset = 0;
-    for (idx, gate) in circuit.gates().iter().enumerate() {
+    for (idx, gate) in circuit.iter_gates().enumerate() {
         match gate {
             Gate::InContrib | Gate::InEval | Gate::And { .. } => {
                 // Step 2 `Π_{2pc}`
@@ -1131,16 +2150,16 @@ fn preprocessing_assign_masks(
         }
     }
 
-    for (idx, gate) in circuit.gates().iter().enumerate() {
+    for (idx, gate) in circuit.iter_gates().enumerate() {
         match gate {
             Gate::Xor(input_lhs, input_rhs) => {
                 // Step 3 `Π_{2pc}`
-                let lhs = &masks[*input_lhs as usize];
-                let rhs = &masks[*input_rhs as usize];
+                let lhs = &masks[input_lhs as usize];
+                let rhs = &masks[input_rhs as usize];
                 masks[idx] = lhs.xor(rhs);
             }
             Gate::Not(input) => {
-                let lhs = &masks[*input as usize];
+                let lhs = &masks[input as usize];
                 masks[idx] = lhs.not(delta);
             }
             _ => {}
@@ -1156,7 +2175,7 @@ fn preprocessing_assign_masks(
 ///   - Tuple #1: XOR of authenticated bits of left-hand side input
 ///   - Tuple #2: like #1 but for right-hand side
 fn preprocessing_and_gate_bits(
-    circuit: &Circuit,
+    circuit: &impl CircuitSource,
     masks: &[WireMask],
     and_triples: &[BitShare],
 ) -> (Vec<bool>, Vec<bool>) {
@@ -1164,10 +2183,10 @@ fn preprocessing_and_gate_bits(
     let mut rhs_bits = Vec::new();
 
     let mut ands = 0;
-    for gate in circuit.gates() {
+    for gate in circuit.iter_gates() {
         if let Gate::And(input_lhs, input_rhs) = gate {
-            lhs_bits.push(masks[*input_lhs as usize].bit.bit ^ and_triples[3 * ands].bit);
-            rhs_bits.push(masks[*input_rhs as usize].bit.bit ^ and_triples[3 * ands + 1].bit);
+            lhs_bits.push(masks[input_lhs as usize].bit.bit ^ and_triples[3 * ands].bit);
+            rhs_bits.push(masks[input_rhs as usize].bit.bit ^ and_triples[3 * ands + 1].bit);
             ands += 1;
         }
     }
@@ -1185,27 +2204,7 @@ fn preprocessing_and_gate_bits(
 ///   - Function `finish`: Upon receiving upstream bits, computes the final authenticated AND
 ///     triples.
 impl AndsBucketingState {
-    fn init(state: OtAndsState5, circuit: &Circuit) -> StateResult<AndsBucketingState> {
-        fn new_permutation(mut rng: ChaCha20Rng, total_abits: usize) -> Vec<u32> {
-            let mut permutation = vec![0; total_abits];
-            for (i, item) in permutation.iter_mut().enumerate().take(total_abits) {
-                *item = i as u32;
-            }
-
-            let mut idx: Vec<i32> = vec![0; total_abits];
-            rng.fill(&mut idx[0..]);
-            let idx = idx;
-
-            for i in (0..total_abits as i32).rev() {
-                let idx = idx[i as usize] % (i + 1);
-                let idx = if idx < 0 { -idx } else { idx };
-
-                permutation.swap(i as usize, idx as usize);
-            }
-
-            permutation
-        }
-
+    fn init(state: OtAndsState5, circuit: &impl CircuitSource) -> StateResult<AndsBucketingState> {
         let bucket_size = bucket_size(circuit);
         let length = circuit.and_gates();
 
@@ -1214,10 +2213,7 @@ impl AndsBucketingState {
         let mut bits = vec![false; length * bucket_size];
         let mut macs = vec![MacType::default(); length * bucket_size];
 
-        let permutation = {
-            let rng = <ChaCha20Rng as rand::SeedableRng>::from_seed(state.coin);
-            new_permutation(rng, length * bucket_size)
-        };
+        let permutation = protocol::bucketing::bucket_permutation(state.coin, length * bucket_size);
 
         for i in 0..length {
             let lhs = permutation[i * bucket_size] as usize;
@@ -1236,6 +2232,7 @@ impl AndsBucketingState {
         let state = AndsBucketingState {
             rng: state.rng,
             delta: state.delta,
+            id: blake3::derive_key("tandem preprocessed triples", &state.coin),
             bucketing_bits: bits,
             wire_abits: state.wire_abits,
             permutation,
@@ -1247,7 +2244,7 @@ impl AndsBucketingState {
         Ok((state, msg))
     }
 
-    fn finish(self, msg: &[u8], circuit: &Circuit) -> StateResult<OtAndsState6> {
+    fn finish(self, msg: &[u8], circuit: &impl CircuitSource) -> StateResult<OtAndsState6> {
         let mut state = self.update_triples(msg)?;
 
         let wire_abits = state.wire_abits;
@@ -1267,6 +2264,16 @@ impl AndsBucketingState {
         Ok((state, msg))
     }
 
+    fn preprocessed(self, party: Party) -> PreprocessedTriples {
+        PreprocessedTriples {
+            party,
+            id: self.id,
+            delta: self.delta,
+            and_triples: self.and_triples,
+            wire_abits: self.wire_abits,
+        }
+    }
+
     /// Implements sub-protocol `Π_{aAND}` Step 3.a (checking step), and 3.b.
     fn update_triples(self, msg: &[u8]) -> Result<AndsBucketingState, Error> {
         assert!(self.bucketing_bits.len() == self.length * self.bucket_size);
@@ -1339,7 +2346,98 @@ impl AndsBucketingState {
     }
 }
 
-fn ot_ands6(state: OtAndsState5, msg: &[u8], circuit: &Circuit) -> StateResult<AndsBucketingState> {
+/// Implements Step 4b of `Π_{2pc}` for [`PreprocessedTriples`], authenticating the opened bits.
+///
+/// Unlike [`AndsBucketingState::finish`], the bits are sent together with a hash of their MACs,
+/// which rejects triples that were corrupted or belong to a different preprocessing session.
+impl LoadedStep {
+    fn init(
+        triples: PreprocessedTriples,
+        party: Party,
+        rng: &mut PartyRng,
+        circuit: &impl CircuitSource,
+    ) -> StateResult<LoadedStep> {
+        crate::source::check(circuit)?;
+        if triples.party != party || !triples.fits(circuit) {
+            return Err(InvalidPreprocessedTriples);
+        }
+        let PreprocessedTriples {
+            id,
+            delta,
+            mut and_triples,
+            mut wire_abits,
+            ..
+        } = triples;
+        let and_gates = circuit.and_gates();
+        and_triples.truncate(and_gates * 3);
+        wire_abits.truncate(and_gates + circuit.contrib_inputs() + circuit.eval_inputs());
+
+        let masks = preprocessing_assign_masks(wire_abits, rng, &delta, circuit);
+        let mut lhs_and_bits = Vec::with_capacity(and_gates);
+        let mut rhs_and_bits = Vec::with_capacity(and_gates);
+        let mut keys = Vec::with_capacity(and_gates);
+        let mut macs = Vec::with_capacity(and_gates * 2);
+        let mut ands = 0;
+        for gate in circuit.iter_gates() {
+            if let Gate::And(input_lhs, input_rhs) = gate {
+                let lhs = masks[input_lhs as usize].bit.xor(&and_triples[3 * ands]);
+                let rhs = masks[input_rhs as usize]
+                    .bit
+                    .xor(&and_triples[3 * ands + 1]);
+                lhs_and_bits.push(lhs.bit);
+                rhs_and_bits.push(rhs.bit);
+                keys.push((lhs.key, rhs.key));
+                macs.extend([lhs.mac.0, rhs.mac.0]);
+                ands += 1;
+            }
+        }
+        let checksum = opened_bits_checksum(&id, macs);
+        let msg = serialize(&(id, &lhs_and_bits, &rhs_and_bits, checksum))?;
+
+        let state = OtAndsState6 {
+            delta,
+            and_triples,
+            masks,
+            lhs_and_bits,
+            rhs_and_bits,
+        };
+        Ok((LoadedStep { state, id, keys }, msg))
+    }
+
+    /// Checks the bits opened by the other party, returning them as expected by `ot_ands8`.
+    fn verify(&self, msg: &[u8]) -> Result<Msg, Error> {
+        let (id, x2, y2, checksum): ([u8; 32], Vec<bool>, Vec<bool>, [u8; 32]) = deserialize(msg)?;
+        if id != self.id {
+            return Err(InvalidPreprocessedTriples);
+        }
+        if x2.len() != self.keys.len() || y2.len() != self.keys.len() {
+            return Err(InsufficientAndShares);
+        }
+        let delta = &self.state.delta;
+        let macs = self.keys.iter().zip(x2.iter().zip(y2.iter())).flat_map(
+            |((key_lhs, key_rhs), (&x, &y))| [mac(delta, key_lhs.0, x), mac(delta, key_rhs.0, y)],
+        );
+        if opened_bits_checksum(&self.id, macs) != checksum {
+            return Err(MacError);
+        }
+        Ok(serialize(&(x2, y2))?)
+    }
+}
+
+fn opened_bits_checksum(id: &[u8; 32], macs: impl IntoIterator<Item = u128>) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(id);
+    for mac in macs {
+        hasher.update(&mac.to_le_bytes());
+    }
+    *hasher.finalize().as_bytes()
+}
+
+fn ot_ands6(
+    state: OtAndsState5,
+    msg: &[u8],
+    circuit: &impl CircuitSource,
+) -> StateResult<AndsBucketingState> {
     // 2nd part of Step 4e/5e of `Π_{LaAND}`
     let (r_prime, r_and_rand): (Vec<MacType>, Vec<(MacType, KeyType)>) = deserialize(msg)?;
     check_hash(&state, &r_prime, &r_and_rand)?;
@@ -1350,8 +2448,8 @@ fn ot_ands6(state: OtAndsState5, msg: &[u8], circuit: &Circuit) -> StateResult<A
 fn ot_ands8_contrib(
     mut state: OtAndsState6,
     msg1: &[u8],
-    circuit: &Circuit,
-    input: &[bool],
+    circuit: &impl CircuitSource,
+    input: &(impl InputSource + ?Sized),
 ) -> StateResult<InputProcContrib> {
     let (x2, y2): (Vec<bool>, Vec<bool>) = deserialize(msg1)?;
     if state.lhs_and_bits.len() != x2.len()
@@ -1370,7 +2468,7 @@ fn ot_ands8_contrib(
     let mut ands = 0_usize;
     let mut garbled_table_shares = Vec::new();
 
-    for (index, gate) in circuit.gates().iter().enumerate() {
+    for (index, gate) in circuit.iter_gates().enumerate() {
         if let Gate::And(input_lhs, input_rhs) = gate {
             let input_mask = &state.sigma_mac(ands, Role::Contributor);
             ands += 1;
@@ -1379,8 +2477,8 @@ fn ot_ands8_contrib(
                 &state,
                 index,
                 &masks[index],
-                &masks[*input_lhs as usize],
-                &masks[*input_rhs as usize],
+                &masks[input_lhs as usize],
+                &masks[input_rhs as usize],
                 input_mask,
             );
             garbled_table_shares.push((index as u32, values));
@@ -1399,8 +2497,8 @@ fn ot_ands8_contrib(
 
     // generate message for each input bit and continue
     let mut input_mask_shares = Vec::with_capacity(pending_from_b);
-    for (index, gate) in circuit.gates().iter().enumerate() {
-        if gate == &Gate::InEval {
+    for (index, gate) in circuit.iter_gates().enumerate() {
+        if gate == Gate::InEval {
             input_mask_shares.push((
                 index as GateIndex,
                 PartialBitShare {
@@ -1443,47 +2541,65 @@ impl OtAndsState6 {
     }
 }
 
+/// The shares of the garbled tables and of the evaluator's input masks sent by the contributor,
+/// serialized like a tuple of both vectors.
+#[derive(Deserialize)]
+struct InputProcMsg {
+    table_shares: Vec<TableShare>,
+    input_mask_shares: Vec<InputMaskShare>,
+}
+
 fn ot_ands8_eval(
     mut state: OtAndsState6,
     msg1: &[u8],
     msg2: &[u8],
-    circuit: &Circuit,
-    input: &[bool],
+    circuit: &impl CircuitSource,
+    input: &(impl InputSource + ?Sized),
 ) -> StateResult<InputProcEval> {
     let (upstream_lhs_bits, upstream_rhs_bits): (Vec<bool>, Vec<bool>) = deserialize(msg1)?;
+    if upstream_lhs_bits.len() != state.lhs_and_bits.len()
+        || upstream_rhs_bits.len() != state.rhs_and_bits.len()
+    {
+        return Err(InsufficientAndShares);
+    }
 
     for i in 0..state.lhs_and_bits.len() {
         state.lhs_and_bits[i] ^= upstream_lhs_bits[i];
         state.rhs_and_bits[i] ^= upstream_rhs_bits[i];
     }
 
-    let mut ands = 0_usize;
-    let mut wires = vec![WireState::default(); circuit.gates().len()];
-    for (index, gate) in circuit.gates().iter().enumerate() {
+    let mut and_gates = Vec::with_capacity(circuit.and_gates());
+    let mut and_tables = Vec::with_capacity(circuit.and_gates());
+    for (index, gate) in circuit.iter_gates().enumerate() {
         if let Gate::And(input_lhs, input_rhs) = gate {
-            let input_mask = &state.sigma_mac(ands, Role::Evaluator);
-            ands += 1;
-
-            wires[index].my_and_table = compute_hashes(
-                &state.masks[index],
-                &state.masks[*input_lhs as usize],
-                &state.masks[*input_rhs as usize],
-                input_mask,
-            );
+            let input_mask = &state.sigma_mac(and_gates.len(), Role::Evaluator);
+            and_gates.push(index as GateIndex);
+            and_tables.push(AndTables {
+                my_and_table: compute_hashes(
+                    &state.masks[index],
+                    &state.masks[input_lhs as usize],
+                    &state.masks[input_rhs as usize],
+                    input_mask,
+                ),
+                other_and_table: AndTableShare::default(),
+            });
         }
     }
+    let ands = and_gates.len();
 
     // input processing:
-    let (garbled_table_shares, input_mask_shares): (Vec<TableShare>, Vec<InputMaskShare>) =
-        deserialize(msg2)?;
-    if ands != garbled_table_shares.len() {
+    let InputProcMsg {
+        table_shares,
+        input_mask_shares,
+    } = deserialize(msg2)?;
+    if ands != table_shares.len() {
         return Err(UnexpectedGarbledTableShare);
     }
-    for (gate, and_share) in garbled_table_shares {
-        if !circuit.gates()[gate as usize].is_and() {
-            return Err(UnexpectedGarbledTableShare);
-        }
-        wires[gate as usize].other_and_table = and_share;
+    for (gate, and_share) in table_shares {
+        let ordinal = and_gates
+            .binary_search(&gate)
+            .map_err(|_| UnexpectedGarbledTableShare)?;
+        and_tables[ordinal].other_and_table = and_share;
     }
 
     if circuit.eval_inputs() > input.len() {
@@ -1491,9 +2607,9 @@ fn ot_ands8_eval(
     }
 
     // generate message for each input bit and continue
-    let mut mask_shares = Vec::new();
-    for (index, gate) in circuit.gates().iter().enumerate() {
-        if gate == &Gate::InContrib {
+    let mut mask_shares = Vec::with_capacity(circuit.contrib_inputs());
+    for (index, gate) in circuit.iter_gates().enumerate() {
+        if gate == Gate::InContrib {
             mask_shares.push((
                 index as GateIndex,
                 PartialBitShare {
@@ -1505,71 +2621,25 @@ fn ot_ands8_eval(
     }
 
     let mut masked_inputs = Vec::with_capacity(input_mask_shares.len());
-    for ((index, bit_share), input) in input_mask_shares.iter().zip(input.iter()) {
-        if circuit.gates()[*index as usize] != Gate::InEval {
+    for ((index, bit_share), input) in input_mask_shares.into_iter().zip(input.bits()) {
+        if circuit.gate(index as usize) != Some(Gate::InEval) {
             return Err(UnexpectedMessageType);
         }
 
-        let mask = &state.masks[*index as usize];
-        assert!(bit_share.verify(&mask.bit.key, &state.delta));
-
-        let masked_input = mask.bit.bit ^ bit_share.bit ^ input;
-        masked_inputs.push((*index, masked_input));
-    }
-
-    let (garbled_table_shares, input_mask_shares): (Vec<TableShare>, Vec<InputMaskShare>) =
-        deserialize(msg2)?;
-    if ands != garbled_table_shares.len() {
-        return Err(UnexpectedGarbledTableShare);
-    }
-    for (gate, and_share) in garbled_table_shares {
-        if !circuit.gates()[gate as usize].is_and() {
-            return Err(UnexpectedGarbledTableShare);
-        }
-        wires[gate as usize].other_and_table = and_share;
-    }
-
-    let input_gates = circuit
-        .gates()
-        .iter()
-        .filter(|g| *g == &Gate::InEval)
-        .count();
-    if input_gates > input.len() {
-        return Err(InsufficientInput);
-    }
-
-    // generate message for each input bit and continue
-    let mut mask_shares = Vec::new();
-    for (index, gate) in circuit.gates().iter().enumerate() {
-        if gate == &Gate::InContrib {
-            mask_shares.push((
-                index as GateIndex,
-                PartialBitShare {
-                    bit: state.masks[index].bit.bit,
-                    mac: state.masks[index].bit.mac,
-                },
-            ))
-        }
-    }
-
-    let mut masked_inputs = Vec::with_capacity(input_mask_shares.len());
-    for ((index, bit_share), input) in input_mask_shares.iter().zip(input.iter()) {
-        if circuit.gates()[*index as usize] != Gate::InEval {
-            return Err(UnexpectedMessageType);
+        let mask = &state.masks[index as usize];
+        if !bit_share.verify(&mask.bit.key, &state.delta) {
+            return Err(MacError);
         }
 
-        let mask = &state.masks[*index as usize];
-        assert!(bit_share.verify(&mask.bit.key, &state.delta));
-
         let masked_input = mask.bit.bit ^ bit_share.bit ^ input;
-        masked_inputs.push((*index, masked_input));
+        masked_inputs.push((index, masked_input));
     }
     let reply = serialize(&(mask_shares, masked_inputs))?;
     let state = InputProcEval {
         delta: state.delta,
         pending_input: circuit.eval_inputs() + circuit.contrib_inputs(),
         masks: state.masks,
-        wires,
+        and_tables,
     };
 
     Ok((state, reply))
@@ -1647,13 +2717,18 @@ fn compute_hashes(
 }
 
 impl InputProcContrib {
-    fn run(mut self, msg: &[u8], circuit: &Circuit, input: &[bool]) -> TandemResult<()> {
+    fn run(
+        mut self,
+        msg: &[u8],
+        circuit: &impl CircuitSource,
+        input: &(impl InputSource + ?Sized),
+    ) -> TandemResult<OutputKeys> {
         // P_B sends its mask to P_A which then returns masked input plus label to P_B for final
         // circuit evaluation
         let (shares, inputs): (Vec<InputMaskShare>, Vec<(u32, bool)>) = deserialize(msg)?;
         let mut evaluation_inputs = Vec::with_capacity(shares.len());
-        for ((index, bit_share), input) in shares.iter().zip(input.iter()) {
-            if circuit.gates()[*index as usize] != Gate::InContrib {
+        for ((index, bit_share), input) in shares.iter().zip(input.bits()) {
+            if circuit.gate(*index as usize) != Some(Gate::InContrib) {
                 return Err(UnexpectedMessageType);
             }
             let mask = &self.masks[*index as usize];
@@ -1666,7 +2741,7 @@ impl InputProcContrib {
 
         // P_B sends masked bit to P_A so P_A can return its label
         for (index, bit) in inputs {
-            if circuit.gates()[index as usize] != Gate::InEval {
+            if circuit.gate(index as usize) != Some(Gate::InEval) {
                 return Err(UnexpectedMessageType);
             }
             if self.pending_from_b == 0 {
@@ -1691,7 +2766,16 @@ impl InputProcContrib {
                 ));
             }
             let reply = serialize(&(evaluation_inputs, mask_shares))?;
-            Ok(((), reply))
+            let masks = circuit
+                .output_gates()
+                .iter()
+                .map(|index| self.masks[*index as usize].clone())
+                .collect();
+            let keys = OutputKeys {
+                delta: self.delta,
+                masks,
+            };
+            Ok((keys, reply))
         } else {
             Err(MacError)
         }
@@ -1699,82 +2783,127 @@ impl InputProcContrib {
 }
 
 impl InputProcEval {
-    fn run(mut self, msg: &[u8], circuit: &Circuit) -> TandemResult<Vec<bool>> {
+    fn run(self, msg: &[u8], circuit: &impl CircuitSource) -> Result<OutputReport, Error> {
         let (inputs, shares): (Vec<(u32, WireLabel, bool)>, Vec<InputMaskShare>) =
             deserialize(msg)?;
+        let mut pending_input = self.pending_input;
+        let mut input_wires = HashMap::with_capacity(inputs.len());
         for (index, label, masked_value) in inputs {
-            if circuit.gates()[index as usize] != Gate::InEval
-                && circuit.gates()[index as usize] != Gate::InContrib
-            {
+            if !matches!(
+                circuit.gate(index as usize),
+                Some(Gate::InEval | Gate::InContrib)
+            ) {
                 return Err(UnexpectedMessageType);
             }
-            if self.pending_input == 0 {
+            if pending_input == 0 {
                 return Err(UnexpectedMessageType);
             }
 
-            self.wires[index as usize].label = label.clone();
-            self.wires[index as usize].masked_value = masked_value;
+            input_wires.insert(
+                index,
+                WireState {
+                    label,
+                    masked_value,
+                },
+            );
 
-            self.pending_input -= 1;
+            pending_input -= 1;
         }
 
-        assert_eq!(self.pending_input, 0);
-        let mut wires = self.wires;
+        if pending_input != 0 {
+            return Err(UnexpectedMessageType);
+        }
+        let mut wires = LiveWires::new(circuit);
+        let mut and_tables = self.and_tables.iter();
         let mut mac_checks_success = true;
-        for (index, gate) in circuit.gates().iter().enumerate() {
-            if let Gate::Xor(input_lhs, input_rhs) = gate {
-                wires[index].masked_value = wires[*input_lhs as usize].masked_value
-                    ^ wires[*input_rhs as usize].masked_value;
-                wires[index].label = wires[*input_lhs as usize]
-                    .label
-                    .xor(&wires[*input_rhs as usize].label);
-            } else if let Gate::Not(input) = gate {
-                wires[index].masked_value = !wires[*input as usize].masked_value;
-                wires[index].label = wires[*input as usize].label.clone();
-            } else if let Gate::And(input_lhs, input_rhs) = gate {
-                let lhs = &wires[*input_lhs as usize];
-                let rhs = &wires[*input_rhs as usize];
-
-                let row: u8 = 2 * u8::from(lhs.masked_value) + u8::from(rhs.masked_value);
-                let result = wires[index].other_and_table[row as usize].xor(&garbling_hash::new(
-                    &lhs.label,
-                    &rhs.label,
-                    index as u32,
-                    row,
-                ));
-
-                mac_checks_success &= PartialBitShare::from(&result)
-                    .verify(&wires[index].my_and_table[row as usize].key, &self.delta);
-
-                wires[index].masked_value =
-                    wires[index].my_and_table[row as usize].bit ^ result.bit;
-                wires[index].label =
-                    WireLabel(result.key.0 ^ wires[index].my_and_table[row as usize].mac.0);
-            }
+        let mut and_gate_mac_checks = 0;
+        for (index, gate) in circuit.iter_gates().enumerate() {
+            let wire = match gate {
+                Gate::InContrib | Gate::InEval => input_wires
+                    .remove(&(index as GateIndex))
+                    .unwrap_or_default(),
+                Gate::Xor(input_lhs, input_rhs) => {
+                    let (lhs, rhs) = (wires.get(input_lhs), wires.get(input_rhs));
+                    WireState {
+                        masked_value: lhs.masked_value ^ rhs.masked_value,
+                        label: lhs.label.xor(&rhs.label),
+                    }
+                }
+                Gate::Not(input) => {
+                    let input = wires.get(input);
+                    WireState {
+                        masked_value: !input.masked_value,
+                        label: input.label.clone(),
+                    }
+                }
+                Gate::And(input_lhs, input_rhs) => {
+                    let (lhs, rhs) = (wires.get(input_lhs), wires.get(input_rhs));
+                    let tables = and_tables.next().ok_or(InsufficientAndShares)?;
+
+                    let row: u8 = 2 * u8::from(lhs.masked_value) + u8::from(rhs.masked_value);
+                    let result = tables.other_and_table[row as usize].xor(&garbling_hash::new(
+                        &lhs.label,
+                        &rhs.label,
+                        index as u32,
+                        row,
+                    ));
+
+                    let my_share = &tables.my_and_table[row as usize];
+                    mac_checks_success &=
+                        PartialBitShare::from(&result).verify(&my_share.key, &self.delta);
+                    and_gate_mac_checks += 1;
+
+                    WireState {
+                        masked_value: my_share.bit ^ result.bit,
+                        label: WireLabel(result.key.0 ^ my_share.mac.0),
+                    }
+                }
+                // lowered by `Circuit::new` and rejected during validation:
+                Gate::Mux(..) | Gate::Nand(..) => return Err(InvalidCircuit),
+            };
+            wires.set(index, &gate, wire);
         }
+        let mut report = OutputReport {
+            output: None,
+            verified: false,
+            and_gate_mac_checks,
+            output_mac_checks: 0,
+            transcript_hash: [0; 32],
+            disclosure: vec![],
+        };
         if !mac_checks_success {
-            return Err(MacError);
+            return Ok(report);
         }
 
         let mut output = Vec::with_capacity(circuit.output_gates().len());
+        let mut disclosure: Vec<DisclosedOutput> = Vec::with_capacity(output.capacity());
         if circuit.output_gates().len() != shares.len() {
             return Err(UnexpectedMessageType);
         }
-        for (index, bit_share) in shares {
+        for ((index, bit_share), output_gate) in shares.into_iter().zip(circuit.output_gates()) {
+            if index != *output_gate {
+                return Err(UnexpectedMessageType);
+            }
             mac_checks_success &=
                 bit_share.verify(&self.masks[index as usize].bit.key, &self.delta);
+            report.output_mac_checks += 1;
 
-            let result = wires[index as usize].masked_value
-                ^ bit_share.bit
-                ^ self.masks[index as usize].bit.bit;
+            let wire = wires.get(index);
+            let mask = &self.masks[index as usize].bit;
+            let result = wire.masked_value ^ bit_share.bit ^ mask.bit;
 
             output.push(result);
+            let share = PartialBitShare {
+                mac: mask.mac,
+                bit: mask.bit,
+            };
+            disclosure.push((wire.label.clone(), wire.masked_value, share));
         }
         if mac_checks_success {
-            let empty_reply = vec![];
-            Ok((output, empty_reply))
-        } else {
-            Err(MacError)
+            report.output = Some(output);
+            report.verified = true;
+            report.disclosure = serialize(&disclosure)?;
         }
+        Ok(report)
     }
 }
@@ -0,0 +1,140 @@
+//! Recording of protocol transcripts, which can be replayed using [`crate::replay`], and the
+//! running transcript hashes used to confirm that both parties saw the same messages.
+
+use std::sync::{Arc, Mutex};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{constant_time::eq_bytes, states::Msg, Error, ProtocolOptions, PROTOCOL_VERSION};
+
+/// The key derivation context of the transcript hash, which must match the other party's.
+const TRANSCRIPT_HASH_CONTEXT: &str = "tandem 2025-01-01 transcript hash v2";
+
+/// The party whose view of the protocol is recorded in a [`Transcript`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Party {
+    /// The [`crate::states::Contributor`].
+    Contributor,
+    /// The [`crate::states::Evaluator`].
+    Evaluator,
+}
+
+/// All messages exchanged by a single party, together with the state of its RNG.
+///
+/// The transcript does not include the input of the party, which needs to be supplied separately
+/// when the transcript is replayed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transcript {
+    /// The [`crate::PROTOCOL_VERSION`] of the party that recorded the transcript.
+    pub protocol_version: u32,
+    /// The party that recorded the transcript.
+    pub party: Party,
+    /// The options proposed by the party.
+    pub options: ProtocolOptions,
+    /// The seed of the party's RNG.
+    pub seed: [u8; 32],
+    /// The stream of the party's RNG.
+    pub stream: u64,
+    /// The word position of the party's RNG when the party was initialized.
+    pub word_pos: u128,
+    /// The messages received from the other party, in order.
+    pub received: Vec<Msg>,
+    /// The messages sent to the other party, in order.
+    pub sent: Vec<Msg>,
+}
+
+impl Transcript {
+    /// Returns the RNG of the party in the state in which the party was initialized.
+    pub fn rng(&self) -> ChaCha20Rng {
+        let mut rng = ChaCha20Rng::from_seed(self.seed);
+        rng.set_stream(self.stream);
+        rng.set_word_pos(self.word_pos);
+        rng
+    }
+}
+
+/// A handle that records the [`Transcript`] of the party it is attached to.
+///
+/// The handle can be cloned and queried at any point, including after the protocol failed.
+#[derive(Debug, Clone, Default)]
+pub struct TranscriptRecorder(Arc<Mutex<Option<Transcript>>>);
+
+impl TranscriptRecorder {
+    /// Creates a new recorder, which needs to be attached to a party to record anything.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the transcript recorded so far, or `None` if the recorder was never attached.
+    pub fn transcript(&self) -> Option<Transcript> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub(crate) fn start(&self, party: Party, rng: &ChaCha20Rng, options: ProtocolOptions) {
+        *self.0.lock().unwrap() = Some(Transcript {
+            protocol_version: PROTOCOL_VERSION,
+            party,
+            options,
+            seed: rng.get_seed(),
+            stream: rng.get_stream(),
+            word_pos: rng.get_word_pos(),
+            received: vec![],
+            sent: vec![],
+        });
+    }
+
+    pub(crate) fn received(&self, msg: &[u8]) {
+        if let Some(t) = self.0.lock().unwrap().as_mut() {
+            t.received.push(msg.to_vec());
+        }
+    }
+
+    pub(crate) fn sent(&self, msg: &[u8]) {
+        if let Some(t) = self.0.lock().unwrap().as_mut() {
+            t.sent.push(msg.to_vec());
+        }
+    }
+}
+
+/// A running hash of all messages exchanged by both parties, in the order in which they were sent.
+///
+/// Each message is hashed together with its sender, its length and the hash of all previous
+/// messages, so that both parties arrive at the same hash if and only if they sent and received the
+/// same messages. Chaining the hashes keeps the running hash as small as its digest, which allows
+/// it to be serialized together with the state of the party.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct TranscriptHash([u8; 32]);
+
+impl TranscriptHash {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn update(&mut self, sender: Party, msg: &[u8]) {
+        let sender = match sender {
+            Party::Contributor => 0u8,
+            Party::Evaluator => 1u8,
+        };
+        let mut hasher = blake3::Hasher::new_derive_key(TRANSCRIPT_HASH_CONTEXT);
+        hasher.update(&self.0);
+        hasher.update(&[sender]);
+        hasher.update(&(msg.len() as u64).to_le_bytes());
+        hasher.update(msg);
+        self.0 = hasher.finalize().into();
+    }
+
+    pub(crate) fn digest(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Compares the hash of the other party against the hash of this party.
+    pub(crate) fn confirm(&self, other: &[u8]) -> Result<(), Error> {
+        if eq_bytes(&self.digest(), other) {
+            Ok(())
+        } else {
+            Err(Error::TranscriptMismatch)
+        }
+    }
+}
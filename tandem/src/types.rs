@@ -5,6 +5,8 @@ use std::ops::BitXor;
 use rand::{CryptoRng, Rng, RngCore};
 use serde::{Deserialize, Serialize};
 
+use crate::constant_time::eq_u128;
+
 /// The number bits of computational security.
 pub(crate) const K: usize = SecurityBits::BITS as usize;
 
@@ -57,7 +59,7 @@ impl From<MacType> for KeyType {
 }
 
 /// A wire mask generated during preprocessing. Foundation for garbled circuit computation.
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub(crate) struct WireMask {
     /// The wire label if {bit.bit} is `false`.
     pub(crate) label_0: WireLabel,
@@ -65,13 +67,19 @@ pub(crate) struct WireMask {
     pub(crate) bit: BitShare,
 }
 
-/// Evaluation state derived at function-dependant preprocessing stage.
+/// Evaluation state of a single wire, see [`crate::wires::LiveWires`].
 #[derive(Default, Debug, Clone)]
 pub(crate) struct WireState {
     /// The label for this wire, computed during preprocessing.
     pub(crate) label: WireLabel,
     /// The value of the wire after masking it with {bit.bit}.
     pub(crate) masked_value: bool,
+}
+
+/// The shares of the garbled table of a single AND gate, stored by the evaluator per AND gate
+/// (instead of per wire, as most gates are XOR or NOT gates).
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct AndTables {
     /// The AND table derived at preprocessing time, representing the local share.
     pub(crate) my_and_table: AndTableShare,
     /// The AND table from a contributing party, representing their share.
@@ -108,7 +116,7 @@ pub(crate) struct PartialBitShare {
 pub(crate) struct WireLabel(pub(crate) SecurityBits);
 
 /// The processing node-global hiding key AKA **THE DELTA**.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct Delta(pub(crate) SecurityBits);
 
 /// Share of an AND table.
@@ -209,7 +217,32 @@ impl BitShare {
 impl PartialBitShare {
     /// MAC verification of an authenticated bit.
     pub(crate) fn verify(&self, key: &KeyType, delta: &Delta) -> bool {
-        (if self.bit { delta.0 } else { 0 }) ^ key.0 == self.mac.0
+        let delta = delta.0 & SecurityBits::from(self.bit).wrapping_neg();
+        eq_u128(delta ^ key.0, self.mac.0)
+    }
+}
+
+/// (De-)serializes boxed arrays longer than the 32 elements supported by serde, as a sequence.
+pub(crate) mod boxed_array {
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    #[allow(clippy::borrowed_box)]
+    pub(crate) fn serialize<S: Serializer, T: Serialize, const N: usize>(
+        array: &Box<[T; N]>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(array.iter())
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<Box<[T; N]>, D::Error> {
+        let elements = Vec::<T>::deserialize(deserializer)?;
+        let len = elements.len();
+        elements
+            .into_boxed_slice()
+            .try_into()
+            .map_err(|_| D::Error::invalid_length(len, &"an array of fixed length"))
     }
 }
 
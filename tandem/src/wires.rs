@@ -0,0 +1,116 @@
+//! Storage of the evaluator's wires during the circuit evaluation phase.
+
+use crate::{types::WireState, CircuitSource, Gate, GateIndex};
+
+/// The labels and masked values of the wires that are still read by gates yet to be evaluated.
+///
+/// Each wire is stored in a slot that is released (and reused by later wires) as soon as the last
+/// gate reading the wire has been evaluated, so that only the wires crossing the current position
+/// in the circuit are kept in memory instead of one wire per gate. Output wires are kept until the
+/// end of the evaluation.
+pub(crate) struct LiveWires {
+    /// The index of the last gate reading each wire, `GateIndex::MAX` for output wires.
+    last_use: Vec<GateIndex>,
+    /// The slot of each wire, only meaningful while the wire is live.
+    slots: Vec<u32>,
+    values: Vec<WireState>,
+    free: Vec<u32>,
+}
+
+impl LiveWires {
+    pub(crate) fn new(circuit: &impl CircuitSource) -> Self {
+        // wires that are never read are released right after they were evaluated:
+        let mut last_use: Vec<GateIndex> = (0..circuit.num_gates() as GateIndex).collect();
+        for (index, gate) in circuit.iter_gates().enumerate() {
+            for input in inputs(&gate).into_iter().flatten() {
+                last_use[input as usize] = index as GateIndex;
+            }
+        }
+        for &output in circuit.output_gates() {
+            last_use[output as usize] = GateIndex::MAX;
+        }
+        Self {
+            slots: vec![0; last_use.len()],
+            last_use,
+            values: vec![],
+            free: vec![],
+        }
+    }
+
+    /// Returns the wire, which must have been evaluated and must still be live.
+    pub(crate) fn get(&self, index: GateIndex) -> &WireState {
+        &self.values[self.slots[index as usize] as usize]
+    }
+
+    /// Stores the evaluated wire of the gate, releasing the input wires that are no longer read.
+    pub(crate) fn set(&mut self, index: usize, gate: &Gate, wire: WireState) {
+        let [lhs, rhs] = inputs(gate);
+        for input in [lhs, rhs.filter(|&rhs| Some(rhs) != lhs)]
+            .into_iter()
+            .flatten()
+        {
+            if self.last_use[input as usize] as usize == index {
+                self.free.push(self.slots[input as usize]);
+            }
+        }
+        if self.last_use[index] as usize == index {
+            return;
+        }
+        let slot = match self.free.pop() {
+            Some(slot) => {
+                self.values[slot as usize] = wire;
+                slot
+            }
+            None => {
+                self.values.push(wire);
+                self.values.len() as u32 - 1
+            }
+        };
+        self.slots[index] = slot;
+    }
+
+    /// The number of slots allocated so far, i.e. the maximum number of live wires.
+    #[cfg(test)]
+    fn allocated(&self) -> usize {
+        self.values.len()
+    }
+}
+
+fn inputs(gate: &Gate) -> [Option<GateIndex>; 2] {
+    match *gate {
+        Gate::InContrib | Gate::InEval => [None, None],
+        Gate::Xor(x, y) | Gate::And(x, y) | Gate::Nand(x, y) => [Some(x), Some(y)],
+        Gate::Not(x) => [Some(x), None],
+        // lowered by `Circuit::new` and rejected during validation:
+        Gate::Mux(..) => [None, None],
+    }
+}
+
+#[test]
+fn test_live_wires() {
+    use crate::{types::WireLabel, Circuit};
+
+    // a chain of XOR gates only needs the wires of the current link:
+    let mut gates = vec![Gate::InContrib, Gate::InEval];
+    for i in 2..100 {
+        gates.push(Gate::Xor(i - 1, i - 2));
+    }
+    let circuit = Circuit::new(gates, vec![2, 99]);
+    let mut wires = LiveWires::new(&circuit);
+    for (index, gate) in circuit.iter_gates().enumerate() {
+        let wire = WireState {
+            label: WireLabel(index as u128),
+            masked_value: index % 3 == 0,
+        };
+        if let Some(input) = inputs(&gate)[0] {
+            assert_eq!(wires.get(input).label, WireLabel(input as u128));
+        }
+        wires.set(index, &gate, wire);
+    }
+    assert_eq!(wires.get(2).label, WireLabel(2));
+    assert_eq!(wires.get(99).label, WireLabel(99));
+    assert!(wires.get(99).masked_value);
+    // the 2 wires of the current link (the slot of the older one is reused by the next wire) and
+    // the output wire 2:
+    assert_eq!(wires.allocated(), 3);
+}
@@ -0,0 +1,180 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use tandem::{
+    simulate_with_adversary,
+    states::{Contributor, Evaluator},
+    Circuit, Error, Gate, Intercepted, Party,
+};
+
+fn and_xor_circuit() -> Circuit {
+    Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::InEval,
+            Gate::And(0, 2),
+            Gate::And(1, 3),
+            Gate::Xor(4, 5),
+            Gate::And(4, 6),
+        ],
+        vec![4, 5, 6, 7],
+    )
+}
+
+#[test]
+fn corrupted_messages_never_cause_wrong_output() {
+    let circuit = and_xor_circuit();
+    let input_contributor = [true, false];
+    let input_evaluator = [true, true];
+    let expected = vec![true, false, true, true];
+
+    let mut failed_checks = 0;
+    for sender in [Party::Contributor, Party::Evaluator] {
+        for index in 0..8 {
+            for pos in [0, 17, usize::MAX] {
+                let result = simulate_with_adversary(
+                    &circuit,
+                    &input_contributor,
+                    &input_evaluator,
+                    |mut i: Intercepted| {
+                        if i.sender == sender && i.index == index && !i.msg.is_empty() {
+                            let pos = pos.min(i.msg.len() - 1);
+                            i.msg[pos] ^= 1;
+                        }
+                        vec![i.msg]
+                    },
+                );
+                match result {
+                    Ok(output) => assert_eq!(output, expected, "{sender:?} {index} {pos}"),
+                    Err(Error::MacError | Error::LeakyAndNotEqual) => failed_checks += 1,
+                    Err(_) => {}
+                }
+            }
+        }
+    }
+    assert!(failed_checks > 0);
+}
+
+#[test]
+fn dropped_message_stalls_protocol() {
+    let circuit = and_xor_circuit();
+    let result = simulate_with_adversary(&circuit, &[true, false], &[true, true], |i| {
+        if i.sender == Party::Evaluator && i.index == 2 {
+            vec![]
+        } else {
+            vec![i.msg]
+        }
+    });
+    assert_eq!(result, Err(Error::ProtocolStalled));
+}
+
+#[test]
+fn replayed_message_is_rejected() {
+    let circuit = and_xor_circuit();
+    let mut previous: Option<Vec<u8>> = None;
+    let result = simulate_with_adversary(&circuit, &[true, false], &[true, true], |i| {
+        let msgs = match (&previous, i.sender, i.index) {
+            (Some(previous), Party::Contributor, 3) => vec![previous.clone()],
+            _ => vec![i.msg.clone()],
+        };
+        if i.sender == Party::Contributor {
+            previous = Some(i.msg);
+        }
+        msgs
+    });
+    assert!(result.is_err());
+}
+
+type Eval = Evaluator<Circuit, [bool; 2]>;
+type Contrib = Contributor<Circuit, [bool; 2]>;
+
+/// Runs the protocol until the evaluator receives the final message of the contributor.
+fn run_until_output(circuit: &Circuit) -> Result<(Eval, Contrib, Vec<u8>), Error> {
+    let mut eval = Evaluator::new(circuit.clone(), [true, true], ChaCha20Rng::from_entropy())?;
+    let (mut contrib, mut msg) =
+        Contributor::new(circuit.clone(), [true, false], ChaCha20Rng::from_entropy())?;
+    for _ in 0..eval.steps() {
+        let (next_state, reply) = eval.run(&msg)?;
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&reply)?;
+        contrib = next_state;
+        msg = reply;
+    }
+    Ok((eval, contrib, msg))
+}
+
+#[test]
+fn flipped_output_mask_share_is_reported() -> Result<(), Error> {
+    let circuit = and_xor_circuit();
+    let (eval, _, msg) = run_until_output(&circuit)?;
+    let report = eval.output_with_report(&msg)?;
+    assert!(report.verified);
+    assert_eq!(report.output, Some(vec![true, false, true, true]));
+    assert_eq!(report.and_gate_mac_checks, 3);
+    assert_eq!(report.output_mac_checks, 4);
+    assert_eq!(report.mac_checks(), 7);
+
+    // the final message ends with the bit of the contributor's share of the last output mask:
+    let (eval, _, mut msg) = run_until_output(&circuit)?;
+    *msg.last_mut().unwrap() ^= 1;
+    let report = eval.output_with_report(&msg)?;
+    assert!(!report.verified);
+    assert_eq!(report.output, None);
+    assert_eq!(report.output_mac_checks, 4);
+
+    let (eval, _, mut msg) = run_until_output(&circuit)?;
+    *msg.last_mut().unwrap() ^= 1;
+    assert_eq!(eval.output(&msg), Err(Error::MacError));
+    Ok(())
+}
+
+#[test]
+fn corrupted_final_message_fails_transcript_confirmation() -> Result<(), Error> {
+    let circuit = and_xor_circuit();
+    let (mut eval, contrib, msg) = run_until_output(&circuit)?;
+    eval.expect_transcript_hash(&contrib.transcript_hash());
+    let report = eval.output_with_report(&msg)?;
+    assert_eq!(report.output, Some(vec![true, false, true, true]));
+    assert_eq!(report.transcript_hash, contrib.transcript_hash());
+    contrib.confirm_transcript_hash(&report.transcript_hash)?;
+    assert_eq!(
+        contrib.confirm_transcript_hash(&[0; 32]),
+        Err(Error::TranscriptMismatch)
+    );
+
+    let (mut eval, contrib, mut msg) = run_until_output(&circuit)?;
+    eval.expect_transcript_hash(&contrib.transcript_hash());
+    *msg.last_mut().unwrap() ^= 1;
+    assert_eq!(eval.output(&msg), Err(Error::TranscriptMismatch));
+    Ok(())
+}
+
+#[test]
+fn forged_output_disclosure_is_rejected() -> Result<(), Error> {
+    let mut gates = and_xor_circuit().gates().to_vec();
+    gates.extend([Gate::Not(6), Gate::Xor(0, 3)]);
+    let circuit = Circuit::new(gates, vec![4, 5, 6, 7, 8, 9]);
+    let (eval, contrib, msg) = run_until_output(&circuit)?;
+    let report = eval.output_with_report(&msg)?;
+    let expected = vec![true, false, true, true, false, false];
+    assert_eq!(report.output, Some(expected.clone()));
+    assert_eq!(contrib.disclosed_output(&report.disclosure)?, expected);
+
+    // the disclosure consists of the label, the masked value and the evaluator's share of the
+    // mask (with its MAC) of each output wire, after the length of the vector:
+    for pos in [8, 8 + 16, 8 + 16 + 1, report.disclosure.len() - 1] {
+        let mut forged = report.disclosure.clone();
+        forged[pos] ^= 1;
+        assert_eq!(
+            contrib.disclosed_output(&forged),
+            Err(Error::MacError),
+            "{pos}"
+        );
+    }
+    let mut truncated = report.disclosure.clone();
+    truncated[0] -= 1;
+    truncated.truncate(truncated.len() - 34);
+    assert!(contrib.disclosed_output(&truncated).is_err());
+    Ok(())
+}
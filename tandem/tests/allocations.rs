@@ -0,0 +1,83 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use tandem::{
+    states::{Contributor, Evaluator},
+    Circuit, Error, Gate, GateIndex,
+};
+
+/// Counts the bytes currently allocated and the peak since the last reset.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let allocated = ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+            PEAK.fetch_max(allocated, Ordering::SeqCst);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the result of the function and the peak of the bytes allocated while it ran.
+fn peak_allocation<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    PEAK.store(before, Ordering::SeqCst);
+    let result = f();
+    (result, PEAK.load(Ordering::SeqCst) - before)
+}
+
+/// The evaluator must process the garbled table shares and input mask shares of the last message
+/// in a single pass. Processing it once keeps the peak at about 4.1 times the size of the message
+/// for this circuit (most of which are the AND tables kept by the evaluator), while each
+/// deserialization of the message takes about 1.6 times its size, so that deserializing it twice
+/// raises the peak to about 5.7 times its size. The bound lies halfway in between.
+#[test]
+fn test_peak_allocations_of_input_processing() -> Result<(), Error> {
+    let n = 2048;
+    let mut gates = vec![Gate::InContrib; n];
+    gates.extend(vec![Gate::InEval; n]);
+    for i in 0..n {
+        gates.push(Gate::And(i as GateIndex, (n + i) as GateIndex));
+    }
+    let output_gates = (2 * n..3 * n).map(|i| i as GateIndex).collect();
+    let circuit = Circuit::new(gates, output_gates);
+
+    let (mut contrib, mut msg) =
+        Contributor::new(&circuit, vec![true; n], ChaCha20Rng::seed_from_u64(0))?;
+    let mut eval = Evaluator::new(&circuit, vec![true; n], ChaCha20Rng::seed_from_u64(1))?;
+    let steps = eval.steps();
+    for step in 0..steps {
+        let (result, peak) = peak_allocation(|| eval.run(&msg));
+        let (next_state, reply) = result?;
+        if step == steps - 1 {
+            assert!(
+                peak * 5 < msg.len() * 24,
+                "peak of {peak} bytes for a message of {} bytes",
+                msg.len()
+            );
+        }
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&reply)?;
+        contrib = next_state;
+        msg = reply;
+    }
+    assert_eq!(eval.output(&msg)?, vec![true; n]);
+    Ok(())
+}
@@ -1,11 +1,17 @@
-use tandem::{Circuit, Error, Gate};
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use tandem::{
+    states::{Contributor, Evaluator},
+    Circuit, CircuitHasher, CircuitStats, ColumnarCircuit, Error, Gate, InputBits,
+    ValidatedCircuit,
+};
 
 #[test]
 fn test_missing_output_gates() -> Result<(), Error> {
     let program = Circuit::new(vec![Gate::InContrib, Gate::InEval, Gate::Xor(0, 1)], vec![]);
 
-    for in_a in vec![true, false] {
-        for in_b in vec![true, false] {
+    for in_a in [true, false] {
+        for in_b in [true, false] {
             let input_a = vec![in_a];
             let input_b = vec![in_b];
 
@@ -31,8 +37,8 @@ fn test_invalid_gates() -> Result<(), Error> {
     let invalid_not = Circuit::new(vec![Gate::InContrib, Gate::InEval, Gate::Not(500)], vec![2]);
 
     for program in [invalid_xor, invalid_and, invalid_not] {
-        for in_a in vec![true, false] {
-            for in_b in vec![true, false] {
+        for in_a in [true, false] {
+            for in_b in [true, false] {
                 let input_a = vec![in_a];
                 let input_b = vec![in_b];
 
@@ -52,8 +58,8 @@ fn test_invalid_output_gates() -> Result<(), Error> {
         vec![3],
     );
 
-    for in_a in vec![true, false] {
-        for in_b in vec![true, false] {
+    for in_a in [true, false] {
+        for in_b in [true, false] {
             let input_a = vec![in_a];
             let input_b = vec![in_b];
 
@@ -123,8 +129,8 @@ fn test_xor() -> Result<(), Error> {
         vec![2],
     );
 
-    for in_a in vec![true, false] {
-        for in_b in vec![true, false] {
+    for in_a in [true, false] {
+        for in_b in [true, false] {
             let input_a = vec![in_a];
             let input_b = vec![in_b];
 
@@ -151,8 +157,8 @@ fn test_not_simple() -> Result<(), Error> {
         vec![2, 3, 4, 5],
     );
 
-    for in_a in vec![true, false] {
-        for in_b in vec![true, false] {
+    for in_a in [true, false] {
+        for in_b in [true, false] {
             let input_a = vec![in_a];
             let input_b = vec![in_b];
 
@@ -188,8 +194,8 @@ fn test_not() -> Result<(), Error> {
         vec![2, 3, 6, 7, 8, 10],
     );
 
-    for in_a in vec![true, false] {
-        for in_b in vec![true, false] {
+    for in_a in [true, false] {
+        for in_b in [true, false] {
             let input_a = vec![in_a];
             let input_b = vec![in_b];
 
@@ -224,8 +230,8 @@ fn test_and_xor() -> Result<(), Error> {
         vec![2, 3],
     );
 
-    for in_a in vec![true, false] {
-        for in_b in vec![true, false] {
+    for in_a in [true, false] {
+        for in_b in [true, false] {
             let input_a = vec![in_a];
             let input_b = vec![in_b];
 
@@ -343,6 +349,361 @@ fn test_unsupported_program() {
     );
 }
 
+#[test]
+fn test_validated_circuit() -> Result<(), Error> {
+    let invalid = Circuit::new(vec![Gate::InContrib, Gate::InEval, Gate::Xor(0, 1)], vec![]);
+    assert_eq!(
+        ValidatedCircuit::new(invalid).unwrap_err(),
+        Error::InvalidCircuit
+    );
+
+    let program = ValidatedCircuit::try_from(Circuit::new(
+        vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+        vec![2],
+    ))?;
+    assert_eq!(program.and_gates(), 1);
+    let (mut contrib, mut msg) =
+        Contributor::new(&program, vec![true], ChaCha20Rng::from_entropy())?;
+    let mut eval = Evaluator::new(program.clone(), vec![true], ChaCha20Rng::from_entropy())?;
+    for _ in 0..eval.steps() {
+        let (next_state, reply) = eval.run(&msg)?;
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&reply)?;
+        contrib = next_state;
+        msg = reply;
+    }
+    assert_eq!(eval.output(&msg)?, vec![true]);
+
+    // the inputs are still checked against the validated circuit:
+    assert!(matches!(
+        Evaluator::new(&program, vec![], ChaCha20Rng::from_entropy()),
+        Err(Error::InsufficientInput)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_mux_nand() -> Result<(), Error> {
+    let program = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::InEval,
+            Gate::Mux(0, 1, 2),
+            Gate::Nand(0, 1),
+            Gate::Xor(3, 4),
+        ],
+        vec![3, 4, 5],
+    );
+    assert_eq!(program.and_gates(), 2);
+    program.validate()?;
+
+    for bitvec in 0..8 {
+        let s = test_bit(bitvec, 0);
+        let a = test_bit(bitvec, 1);
+        let b = test_bit(bitvec, 2);
+
+        let result = tandem::simulate(&program, &[s], &[a, b])?;
+
+        let mux = if s { b } else { a };
+        let nand = !(s & a);
+        assert_eq!(result, vec![mux, nand, mux ^ nand]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_mux_nand_invalid_wires() {
+    let program = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::Mux(0, 1, 3),
+            Gate::Not(2),
+        ],
+        vec![3],
+    );
+    assert_eq!(program.validate(), Err(Error::InvalidCircuit));
+
+    let program = Circuit::new(vec![Gate::InContrib, Gate::Nand(0, 0)], vec![2]);
+    assert_eq!(program.validate(), Err(Error::InvalidCircuit));
+}
+
+#[test]
+fn test_circuit_hasher() {
+    let gates = vec![
+        Gate::InContrib,
+        Gate::InEval,
+        Gate::InEval,
+        Gate::Mux(0, 1, 2),
+        Gate::Nand(0, 1),
+        Gate::Xor(3, 4),
+    ];
+    let program = Circuit::new(gates.clone(), vec![3, 4, 5]);
+    let lowered = Circuit::new(program.gates().clone(), program.output_gates().clone());
+    assert_eq!(program.blake3_hash(), lowered.blake3_hash());
+
+    let mut hasher = CircuitHasher::new();
+    for gate in gates.iter() {
+        hasher.update(gate);
+    }
+    assert_eq!(hasher.clone().finalize(&[3, 4, 5]), program.blake3_hash());
+    assert_ne!(hasher.finalize(&[3, 4]), program.blake3_hash());
+
+    let mut hasher = CircuitHasher::new();
+    for gate in lowered.gates() {
+        hasher.update(gate);
+    }
+    assert_eq!(
+        hasher.finalize(lowered.output_gates()),
+        program.blake3_hash()
+    );
+    assert_ne!(
+        Circuit::new(gates, vec![5]).blake3_hash(),
+        program.blake3_hash()
+    );
+}
+
+#[test]
+fn test_compose() -> Result<(), Error> {
+    let first = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::And(0, 1),
+            Gate::Xor(0, 1),
+        ],
+        vec![2, 3],
+    );
+    let second = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::InEval,
+            Gate::And(1, 0),
+            Gate::Xor(3, 2),
+        ],
+        vec![4],
+    );
+    let program = first.compose(&second, &[(0, 1), (1, 2)])?;
+    assert_eq!(program.contrib_inputs(), 2);
+    assert_eq!(program.eval_inputs(), 1);
+    assert_eq!(program.and_gates(), 2);
+
+    for bitvec in 0..8 {
+        let a = test_bit(bitvec, 0);
+        let b = test_bit(bitvec, 1);
+        let c = test_bit(bitvec, 2);
+
+        let result = tandem::simulate(&program, &[a, c], &[b])?;
+
+        assert_eq!(result, vec![(a & b & c) ^ (a ^ b)]);
+    }
+
+    for wiring in [
+        vec![(2, 1)],
+        vec![(0, 3)],
+        vec![(0, 1), (1, 1)],
+        vec![(0, 5)],
+    ] {
+        assert_eq!(
+            first.compose(&second, &wiring).unwrap_err(),
+            Error::InvalidCompositionWiring
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bristol_fashion() -> Result<(), Error> {
+    let netlist = "\
+8 13
+2 2 1
+1 3
+
+2 1 0 2 5 XOR
+2 1 1 2 6 NAND
+3 1 2 0 1 7 MUX
+1 1 5 8 INV
+1 1 8 9 EQW
+4 2 5 6 7 9 11 12 MAND
+1 1 1 3 EQ
+2 1 3 11 10 XOR
+";
+    let program = Circuit::from_bristol_fashion(netlist)?;
+    assert_eq!(program.contrib_inputs(), 2);
+    assert_eq!(program.eval_inputs(), 1);
+
+    for bitvec in 0..8 {
+        let a0 = test_bit(bitvec, 0);
+        let a1 = test_bit(bitvec, 1);
+        let b = test_bit(bitvec, 2);
+
+        let result = tandem::simulate(&program, &[a0, a1], &[b])?;
+
+        let xor = a0 ^ b;
+        let nand = !(a1 & b);
+        let mux = if b { a1 } else { a0 };
+        assert_eq!(result, vec![true ^ (xor & mux), xor & mux, nand & !xor]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_bristol_fashion_invalid() {
+    for (netlist, line) in [
+        ("", 1),
+        ("1 3\n2 1 1\n1 1\n2 1 0 1 2 FOO", 4),
+        ("1 3\n3 1 1 1\n1 1\n2 1 0 1 2 AND", 2),
+        ("1 3\n2 1 1\n1 1\n2 1 0 1 AND", 4),
+        ("1 3\n2 1 1\n1 1\n2 1 0 3 2 AND", 4),
+        ("2 3\n2 1 1\n1 1\n2 1 0 1 2 AND", 4),
+        // oversized headers are rejected without allocating memory for them:
+        ("1 999999999999\n2 99999999999 1\n1 1\n2 1 0 1 2 AND", 3),
+        ("1 3\n2 18446744073709551615 1\n1 1\n2 1 0 1 2 AND", 3),
+        ("1 3\n2 1 1\n2 18446744073709551615 1\n2 1 0 1 2 AND", 3),
+        ("1 3\n2 1 1\n1 1\n18446744073709551615 1 0 1 2 AND", 4),
+    ] {
+        assert_eq!(
+            Circuit::from_bristol_fashion(netlist).unwrap_err(),
+            Error::InvalidBristolNetlist { line },
+            "{netlist}"
+        );
+    }
+
+    // the output wires of an oversized header are never computed:
+    let netlist = "1 99999999999999999\n2 1 1\n1 1\n2 1 0 1 2 AND";
+    assert_eq!(
+        Circuit::from_bristol_fashion(netlist).unwrap_err(),
+        Error::InvalidCircuit
+    );
+}
+
+#[test]
+fn test_stats() {
+    let program = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::InEval,
+            Gate::Mux(0, 1, 2),
+            Gate::Not(3),
+        ],
+        vec![3, 4],
+    );
+    let stats = program.stats();
+    assert_eq!(
+        stats,
+        CircuitStats {
+            and_gates: 1,
+            xor_gates: 2,
+            not_gates: 1,
+            contrib_inputs: 1,
+            eval_inputs: 2,
+            outputs: 2,
+        }
+    );
+    // the MUX is lowered to an XOR, an AND and another XOR:
+    assert_eq!(program.depth(), 4);
+    assert_eq!(program.and_depth(), 1);
+}
+
+#[test]
+fn test_to_dot() {
+    let program = Circuit::new(
+        vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1), Gate::Not(2)],
+        vec![2, 3],
+    );
+
+    let dot = program.to_dot(10);
+    assert!(dot.starts_with("digraph circuit {"));
+    assert!(
+        dot.contains("g0 [shape=box, style=filled, fillcolor=lightblue, label=\"contributor 0\"];")
+    );
+    assert!(
+        dot.contains("g1 [shape=box, style=filled, fillcolor=lightyellow, label=\"evaluator 0\"];")
+    );
+    assert!(dot.contains("g2 [label=\"AND\"];\n  g0 -> g2;\n  g1 -> g2;"));
+    assert!(dot.contains("g2 -> g3;"));
+    assert!(dot.contains("out1 [shape=doublecircle, label=\"output 1\"];\n  g3 -> out1;"));
+    assert!(!dot.contains("omitted"));
+
+    let dot = program.to_dot_with_labels(3, &["x".to_string(), "y\"".to_string()], &[]);
+    assert!(dot.contains("label=\"x\""));
+    assert!(dot.contains("label=\"y\\\"\""));
+    assert!(dot.contains("g2 -> out0;"));
+    assert!(!dot.contains("g3"));
+    assert!(!dot.contains("out1"));
+    assert!(dot.contains("label=\"... 1 more gates\""));
+}
+
 fn test_bit(value: i32, idx: u8) -> bool {
     (value & (1 << idx)) != 0
 }
+
+#[test]
+fn test_columnar_circuit() -> Result<(), Error> {
+    let program = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::And(0, 1),
+            Gate::Xor(0, 2),
+            Gate::Not(3),
+        ],
+        vec![2, 3, 4],
+    );
+    let mut bytes = vec![];
+    program.write_columnar(&mut bytes).unwrap();
+    let columnar = ColumnarCircuit::new(bytes).unwrap();
+
+    let mut eval = Evaluator::new(&columnar, vec![true], ChaCha20Rng::from_entropy())?;
+    let (mut contrib, mut msg_for_eval) =
+        Contributor::new(&program, vec![true], ChaCha20Rng::from_entropy())?;
+    for _ in 0..eval.steps() {
+        let (next_state, msg_for_contrib) = eval.run(&msg_for_eval)?;
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&msg_for_contrib)?;
+        contrib = next_state;
+        msg_for_eval = reply;
+    }
+    assert_eq!(eval.output(&msg_for_eval)?, vec![true, false, true]);
+
+    Ok(())
+}
+
+#[test]
+fn test_input_bits() -> Result<(), Error> {
+    // XORs 20 bits of each party, so that the packed input spans multiple bytes:
+    let mut gates = vec![Gate::InContrib; 20];
+    gates.extend(vec![Gate::InEval; 20]);
+    gates.extend((0..20).map(|i| Gate::Xor(i, i + 20)));
+    let program = Circuit::new(gates, (40..60).collect());
+
+    let input_contrib: Vec<bool> = (0..20).map(|i| i % 3 == 0).collect();
+    let input_eval: InputBits = (0..20).map(|i| i % 2 == 0).collect();
+    let expected: Vec<bool> = (0..20).map(|i| (i % 3 == 0) ^ (i % 2 == 0)).collect();
+
+    let mut eval = Evaluator::new(&program, &input_eval, ChaCha20Rng::from_entropy())?;
+    let (mut contrib, mut msg_for_eval) =
+        Contributor::new(&program, input_contrib, ChaCha20Rng::from_entropy())?;
+    for _ in 0..eval.steps() {
+        let (next_state, msg_for_contrib) = eval.run(&msg_for_eval)?;
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&msg_for_contrib)?;
+        contrib = next_state;
+        msg_for_eval = reply;
+    }
+    assert_eq!(eval.output(&msg_for_eval)?, expected);
+
+    let too_short: InputBits = vec![true; 19].into();
+    assert!(matches!(
+        Evaluator::new(&program, too_short, ChaCha20Rng::from_entropy()),
+        Err(Error::InsufficientInput)
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use tandem::{
+    conformance::{generate, reference_circuits, CONFORMANCE_SEED},
+    Error,
+};
+
+/// The digests of the published test vectors. If they change, the wire format changed and the
+/// `PROTOCOL_VERSION` must be incremented together with the digests.
+const GOLDEN_DIGESTS: [(&str, &str); 3] = [
+    (
+        "and",
+        "387f85946d71e24218730fa5da216e8c80b1f5f52052efd9665a798316af20c3",
+    ),
+    (
+        "xor_not",
+        "63b976f8484996a122374589f29556e9d09369b170d0552e53119f86e59b0a68",
+    ),
+    (
+        "add_4bit",
+        "3b5f0450b4cbe2d27dedbc26911e713ec64155bf30dc386c23ca1cd4366e86a5",
+    ),
+];
+
+#[test]
+fn test_golden_digests() -> Result<(), Error> {
+    let vectors = generate(CONFORMANCE_SEED)?;
+    assert_eq!(vectors.len(), GOLDEN_DIGESTS.len());
+    for (vector, (name, digest)) in vectors.iter().zip(GOLDEN_DIGESTS) {
+        assert_eq!(vector.name, name);
+        assert_eq!(vector.digest(), digest, "wire format of '{name}' changed");
+        vector.validate()?;
+    }
+    assert_eq!(generate(CONFORMANCE_SEED)?, vectors);
+    Ok(())
+}
+
+#[test]
+fn test_reference_circuits() -> Result<(), Error> {
+    for reference in reference_circuits() {
+        let output = tandem::simulate(
+            &reference.circuit,
+            &reference.contributor_input,
+            &reference.evaluator_input,
+        )?;
+        assert_eq!(output, reference.output, "{}", reference.name);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_tampered_vectors() -> Result<(), Error> {
+    let mut vectors = generate([7; 32])?;
+    let mut vector = vectors.remove(0);
+    vector.validate()?;
+    assert_ne!(
+        vector.digest(),
+        generate(CONFORMANCE_SEED)?.remove(0).digest()
+    );
+
+    let mut tampered = vector.clone();
+    tampered.contributor.sent[1][0] ^= 1;
+    tampered.evaluator.received[1][0] ^= 1;
+    assert!(tampered.validate().is_err());
+
+    let mut tampered = vector.clone();
+    tampered.evaluator.sent.pop();
+    assert_eq!(tampered.validate(), Err(Error::TranscriptMismatch));
+
+    vector.output[0] = !vector.output[0];
+    assert_eq!(vector.validate(), Err(Error::TranscriptMismatch));
+    Ok(())
+}
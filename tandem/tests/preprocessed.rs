@@ -0,0 +1,159 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use tandem::{
+    states::{Contributor, Evaluator},
+    Circuit, Error, Gate, Party, PreprocessedTriples, ProtocolOptions,
+};
+
+fn preprocess(circuit: &Circuit) -> Result<(PreprocessedTriples, PreprocessedTriples), Error> {
+    let options = ProtocolOptions::default();
+    let mut eval = Evaluator::new_preprocessing(circuit, ChaCha20Rng::from_entropy(), options)?;
+    let (mut contrib, mut msg_for_eval) =
+        Contributor::new_preprocessing(circuit, ChaCha20Rng::from_entropy(), options)?;
+
+    assert_eq!(contrib.steps(), eval.steps());
+
+    for _ in 0..eval.steps() {
+        let (next_state, msg_for_contrib) = eval.run(&msg_for_eval)?;
+        eval = next_state;
+
+        let (next_state, reply) = contrib.run(&msg_for_contrib)?;
+        contrib = next_state;
+
+        msg_for_eval = reply;
+    }
+    Ok((contrib.preprocessed()?, eval.preprocessed()?))
+}
+
+fn run(
+    circuit: &Circuit,
+    input_contributor: &[bool],
+    input_evaluator: &[bool],
+    triples_contributor: PreprocessedTriples,
+    triples_evaluator: PreprocessedTriples,
+) -> Result<Vec<bool>, Error> {
+    let mut eval = Evaluator::from_preprocessed(
+        circuit,
+        input_evaluator,
+        ChaCha20Rng::from_entropy(),
+        triples_evaluator,
+    )?;
+    let (mut contrib, mut msg_for_eval) = Contributor::from_preprocessed(
+        circuit,
+        input_contributor,
+        ChaCha20Rng::from_entropy(),
+        triples_contributor,
+    )?;
+
+    assert_eq!(contrib.steps(), eval.steps());
+
+    for _ in 0..eval.steps() {
+        let (next_state, msg_for_contrib) = eval.run(&msg_for_eval)?;
+        eval = next_state;
+
+        let (next_state, reply) = contrib.run(&msg_for_contrib)?;
+        contrib = next_state;
+
+        msg_for_eval = reply;
+    }
+    eval.output(&msg_for_eval)
+}
+
+fn test_circuit() -> Circuit {
+    Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::InEval,
+            Gate::And(0, 2),
+            Gate::And(1, 3),
+            Gate::And(4, 5),
+            Gate::Xor(4, 5),
+            Gate::Not(7),
+        ],
+        vec![4, 5, 6, 7, 8],
+    )
+}
+
+#[test]
+fn test_preprocessed_triples() -> Result<(), Error> {
+    let circuit = test_circuit();
+    let (contrib, eval) = preprocess(&circuit)?;
+    assert_eq!(contrib.party(), Party::Contributor);
+    assert_eq!(eval.party(), Party::Evaluator);
+    assert_eq!(eval.and_gates(), 3);
+
+    // the triples also fit smaller circuits:
+    let smaller = Circuit::new(
+        vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1), Gate::Not(2)],
+        vec![3],
+    );
+    assert!(contrib.fits(&smaller) && eval.fits(&smaller));
+
+    for (a, b) in [(false, true), (true, true)] {
+        let contrib = PreprocessedTriples::from_bytes(&contrib.to_bytes()?)?;
+        let eval = PreprocessedTriples::from_bytes(&eval.to_bytes()?)?;
+        let output = run(&smaller, &[a], &[b], contrib, eval)?;
+        assert_eq!(output, vec![!(a & b)]);
+    }
+
+    for bitvec in [0b0000u8, 0b0101, 0b1011, 0b1111] {
+        let [a0, a1, b0, b1] = [0, 1, 2, 3].map(|i| (bitvec >> i) & 1 == 1);
+        let (contrib, eval) = preprocess(&circuit)?;
+        let output = run(&circuit, &[a0, a1], &[b0, b1], contrib, eval)?;
+        assert_eq!(
+            output,
+            vec![
+                a0 & b0,
+                a1 & b1,
+                a0 & b0 & a1 & b1,
+                (a0 & b0) ^ (a1 & b1),
+                !((a0 & b0) ^ (a1 & b1)),
+            ]
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_preprocessed_triples() -> Result<(), Error> {
+    let circuit = test_circuit();
+    let (contrib, eval) = preprocess(&circuit)?;
+
+    let larger = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::And(0, 1),
+            Gate::And(0, 2),
+            Gate::And(1, 3),
+            Gate::And(3, 4),
+        ],
+        vec![5],
+    );
+    assert!(!contrib.fits(&larger));
+    assert!(matches!(
+        Evaluator::from_preprocessed(&larger, [true], ChaCha20Rng::from_entropy(), eval.clone()),
+        Err(Error::InvalidPreprocessedTriples)
+    ));
+    assert!(matches!(
+        Evaluator::from_preprocessed(
+            &circuit,
+            [true, true],
+            ChaCha20Rng::from_entropy(),
+            contrib.clone()
+        ),
+        Err(Error::InvalidPreprocessedTriples)
+    ));
+
+    // triples of different preprocessing sessions are rejected:
+    let (_, other_eval) = preprocess(&circuit)?;
+    assert_eq!(
+        run(&circuit, &[true, true], &[true, true], contrib, other_eval),
+        Err(Error::InvalidPreprocessedTriples)
+    );
+
+    Ok(())
+}
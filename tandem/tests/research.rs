@@ -0,0 +1,103 @@
+#![cfg(feature = "research")]
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use tandem::{
+    research::AuthenticatedBit,
+    states::{Contributor, Evaluator},
+    Circuit, Error, Gate, ProtocolOptions,
+};
+
+fn test_circuit() -> Circuit {
+    Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::And(0, 1),
+            Gate::Xor(0, 2),
+            Gate::And(2, 3),
+        ],
+        vec![3, 4],
+    )
+}
+
+/// Checks that the MAC of one party's share matches the key of the other party.
+fn mac_matches(share: &AuthenticatedBit, other: &AuthenticatedBit, other_global_key: u128) -> bool {
+    share.mac == other.key ^ if share.bit { other_global_key } else { 0 }
+}
+
+#[test]
+fn test_preprocessed_triples() -> Result<(), Error> {
+    let circuit = test_circuit();
+    let options = ProtocolOptions::default();
+    let mut eval = Evaluator::new_preprocessing(&circuit, ChaCha20Rng::from_entropy(), options)?;
+    let (mut contrib, mut msg) =
+        Contributor::new_preprocessing(&circuit, ChaCha20Rng::from_entropy(), options)?;
+    for _ in 0..eval.steps() {
+        let (next_state, reply) = eval.run(&msg)?;
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&reply)?;
+        contrib = next_state;
+        msg = reply;
+    }
+    let contrib = contrib.preprocessed()?;
+    let eval = eval.preprocessed()?;
+
+    let (contrib_triples, eval_triples) = (contrib.and_triples(), eval.and_triples());
+    assert_eq!(contrib_triples.len(), contrib.and_gates());
+    assert_eq!(contrib_triples.len(), eval_triples.len());
+    for (c, e) in contrib_triples.iter().zip(eval_triples.iter()) {
+        assert_eq!((c.x.bit ^ e.x.bit) & (c.y.bit ^ e.y.bit), c.z.bit ^ e.z.bit);
+        for (c, e) in [(c.x, e.x), (c.y, e.y), (c.z, e.z)] {
+            assert!(mac_matches(&c, &e, eval.global_key()));
+            assert!(mac_matches(&e, &c, contrib.global_key()));
+        }
+    }
+
+    let (contrib_bits, eval_bits) = (contrib.wire_bits(), eval.wire_bits());
+    assert_eq!(contrib_bits.len(), eval_bits.len());
+    for (c, e) in contrib_bits.iter().zip(eval_bits.iter()) {
+        assert!(mac_matches(c, e, eval.global_key()));
+        assert!(mac_matches(e, c, contrib.global_key()));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_wire_masks() -> Result<(), Error> {
+    let circuit = test_circuit();
+    let mut eval = Evaluator::new(&circuit, [true], ChaCha20Rng::from_entropy())?;
+    let (mut contrib, mut msg) = Contributor::new(&circuit, [true], ChaCha20Rng::from_entropy())?;
+    assert_eq!(contrib.wire_masks(), None);
+    assert_eq!(eval.wire_masks(), None);
+
+    let mut masks = None;
+    for _ in 0..eval.steps() {
+        let (next_state, reply) = eval.run(&msg)?;
+        eval = next_state;
+        masks = masks.or_else(|| Some((contrib.wire_masks()?, eval.wire_masks()?)));
+        let (next_state, reply) = contrib.run(&reply)?;
+        contrib = next_state;
+        msg = reply;
+    }
+    let (contrib_masks, eval_masks) = masks.expect("the wire masks were never assigned");
+    assert_eq!(contrib_masks.len(), circuit.gates().len());
+    assert_eq!(eval_masks.len(), circuit.gates().len());
+    let delta = contrib_masks[0].label_0 ^ contrib_masks[0].label_1;
+    for (c, e) in contrib_masks.iter().zip(eval_masks.iter()) {
+        assert_eq!(c.label_0 ^ c.label_1, delta);
+        assert!(mac_matches(&e.mask, &c.mask, delta));
+    }
+    // XOR gates are masked with the XOR of the masks of their inputs (using free XOR):
+    assert_eq!(
+        contrib_masks[3].mask.bit,
+        contrib_masks[0].mask.bit ^ contrib_masks[2].mask.bit
+    );
+    assert_eq!(
+        contrib_masks[3].label_0,
+        contrib_masks[0].label_0 ^ contrib_masks[2].label_0
+    );
+
+    assert_eq!(eval.output(&msg)?, vec![false, false]);
+    Ok(())
+}
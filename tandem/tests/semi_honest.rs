@@ -0,0 +1,88 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use tandem::{
+    semi_honest::{Evaluator, Garbler},
+    Circuit, Error, Gate,
+};
+
+fn run(program: &Circuit, input_a: &[bool], input_b: &[bool]) -> Result<Vec<bool>, Error> {
+    let (garbler, msg) = Garbler::new(program, input_a, ChaCha20Rng::from_entropy())?;
+    let evaluator = Evaluator::new(program, input_b, ChaCha20Rng::from_entropy())?;
+    let (evaluator, reply) = evaluator.run(&msg)?;
+    let final_msg = garbler.run(&reply)?;
+    evaluator.output(&final_msg)
+}
+
+fn test_bit(bitvec: u32, i: u32) -> bool {
+    (bitvec >> i) & 1 == 1
+}
+
+#[test]
+fn test_semi_honest_gates() -> Result<(), Error> {
+    let program = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::InEval,
+            Gate::And(0, 2),
+            Gate::And(1, 3),
+            Gate::And(4, 5),
+            Gate::Xor(4, 5),
+            Gate::Not(7),
+            Gate::Mux(0, 2, 3),
+        ],
+        vec![4, 5, 6, 7, 8, 9],
+    );
+
+    for bitvec in 0..16 {
+        let a0 = test_bit(bitvec, 0);
+        let a1 = test_bit(bitvec, 1);
+        let b0 = test_bit(bitvec, 2);
+        let b1 = test_bit(bitvec, 3);
+
+        let result = run(&program, &[a0, a1], &[b0, b1])?;
+
+        assert_eq!(
+            result,
+            vec![
+                a0 & b0,
+                a1 & b1,
+                a0 & b0 & a1 & b1,
+                (a0 & b0) ^ (a1 & b1),
+                !((a0 & b0) ^ (a1 & b1)),
+                if a0 { b1 } else { b0 },
+            ],
+            "a0={}, a1={}, b0={}, b1={}",
+            a0,
+            a1,
+            b0,
+            b1
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_semi_honest_invalid_input() {
+    let program = Circuit::new(
+        vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+        vec![2],
+    );
+    assert!(matches!(
+        Garbler::new(&program, &[], ChaCha20Rng::from_entropy()),
+        Err(Error::InsufficientInput)
+    ));
+    assert!(matches!(
+        Evaluator::new(&program, vec![true, false], ChaCha20Rng::from_entropy()),
+        Err(Error::InsufficientInput)
+    ));
+
+    let (_, msg) = Garbler::new(&program, &[true], ChaCha20Rng::from_entropy()).unwrap();
+    let evaluator = Evaluator::new(&program, vec![true], ChaCha20Rng::from_entropy()).unwrap();
+    assert!(matches!(
+        evaluator.run(&msg[..msg.len() - 1]),
+        Err(Error::BincodeError)
+    ));
+}
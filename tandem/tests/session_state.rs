@@ -0,0 +1,201 @@
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+use tandem::{
+    states::{Contributor, Evaluator},
+    Circuit, Error, Gate, OtBackend, OtExtension, ProtocolOptions, PROTOCOL_VERSION,
+};
+
+const KEY: [u8; 32] = [7; 32];
+
+fn circuit() -> Circuit {
+    Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::InEval,
+            Gate::Xor(0, 2),
+            Gate::And(1, 3),
+            Gate::And(4, 5),
+            Gate::Not(6),
+        ],
+        vec![4, 5, 7],
+    )
+}
+
+/// Runs a session, restoring both parties from their serialized states before each step.
+fn run_restored(circuit: &Circuit, options: ProtocolOptions) -> Result<Vec<bool>, Error> {
+    let contrib_input = [true, true];
+    let eval_input = [false, true];
+    let rng = ChaCha20Rng::from_entropy();
+    let mut eval = Evaluator::new_with_options(circuit, eval_input, rng, options)?;
+    let rng = ChaCha20Rng::from_entropy();
+    let (mut contrib, mut msg) =
+        Contributor::new_with_options(circuit, contrib_input, rng, options)?;
+    assert_eq!(contrib.steps(), eval.steps());
+
+    for _ in 0..eval.steps() {
+        let rng_usage = contrib.rng_usage().to_vec();
+        let bytes = contrib.serialize_state(&KEY)?;
+        contrib = Contributor::restore(circuit, contrib_input, &bytes, &KEY)?;
+        assert_eq!(contrib.rng_usage(), rng_usage);
+
+        let bytes = eval.serialize_state(&KEY)?;
+        eval = Evaluator::restore(circuit, eval_input, &bytes, &KEY)?;
+
+        let (next_state, reply) = eval.run(&msg)?;
+        eval = next_state;
+        let (next_state, reply) = contrib.run(&reply)?;
+        contrib = next_state;
+        msg = reply;
+    }
+    let bytes = contrib.serialize_state(&KEY)?;
+    let contrib = Contributor::restore(circuit, contrib_input, &bytes, &KEY)?;
+    eval.expect_transcript_hash(&contrib.transcript_hash());
+
+    let report = eval.output_with_report(&msg)?;
+    contrib.confirm_transcript_hash(&report.transcript_hash)?;
+    assert_eq!(
+        contrib.disclosed_output(&report.disclosure)?,
+        [true, true, false]
+    );
+    report.output.ok_or(Error::MacError)
+}
+
+#[test]
+fn test_restore_at_each_step() -> Result<(), Error> {
+    let circuit = circuit();
+    let all_options = [
+        ProtocolOptions::default(),
+        ProtocolOptions {
+            ot_extension: OtExtension::Kos15,
+            ..Default::default()
+        },
+        ProtocolOptions {
+            ot_backend: OtBackend::Silent,
+            ..Default::default()
+        },
+        #[cfg(feature = "post-quantum")]
+        ProtocolOptions {
+            base_ot: tandem::BaseOt::PostQuantum,
+            ..Default::default()
+        },
+    ];
+    for options in all_options {
+        assert_eq!(run_restored(&circuit, options)?, [true, true, false]);
+    }
+    Ok(())
+}
+
+#[test]
+fn test_restore_preprocessing() -> Result<(), Error> {
+    let circuit = circuit();
+    let options = ProtocolOptions::default();
+    let mut eval = Evaluator::new_preprocessing(&circuit, ChaCha20Rng::from_entropy(), options)?;
+    let (mut contrib, mut msg) =
+        Contributor::new_preprocessing(&circuit, ChaCha20Rng::from_entropy(), options)?;
+    let contrib_input = vec![false; 2];
+    let eval_input = vec![false; 2];
+    for _ in 0..eval.steps() {
+        let (next_state, reply) = eval.run(&msg)?;
+        eval = Evaluator::restore(
+            &circuit,
+            eval_input.clone(),
+            &next_state.serialize_state(&KEY)?,
+            &KEY,
+        )?;
+        let (next_state, reply) = contrib.run(&reply)?;
+        let bytes = next_state.serialize_state(&KEY)?;
+        contrib = Contributor::restore(&circuit, contrib_input.clone(), &bytes, &KEY)?;
+        msg = reply;
+    }
+    let contrib_triples = contrib.preprocessed()?;
+    let eval_triples = eval.preprocessed()?;
+
+    let rng = ChaCha20Rng::from_entropy();
+    let eval = Evaluator::from_preprocessed(&circuit, [true, true], rng, eval_triples)?;
+    let rng = ChaCha20Rng::from_entropy();
+    let (contrib, msg) =
+        Contributor::from_preprocessed(&circuit, [true, false], rng, contrib_triples)?;
+    let mut eval = Evaluator::restore(&circuit, [true, true], &eval.serialize_state(&KEY)?, &KEY)?;
+    let bytes = contrib.serialize_state(&KEY)?;
+    let mut contrib = Contributor::restore(&circuit, [true, false], &bytes, &KEY)?;
+    let mut msg = msg;
+    for _ in 0..eval.steps() {
+        let (next_state, reply) = eval.run(&msg)?;
+        eval = Evaluator::restore(
+            &circuit,
+            [true, true],
+            &next_state.serialize_state(&KEY)?,
+            &KEY,
+        )?;
+        let (next_state, reply) = contrib.run(&reply)?;
+        let bytes = next_state.serialize_state(&KEY)?;
+        contrib = Contributor::restore(&circuit, [true, false], &bytes, &KEY)?;
+        msg = reply;
+    }
+    assert_eq!(eval.output(&msg)?, [false, false, true]);
+    Ok(())
+}
+
+#[test]
+fn test_invalid_states() -> Result<(), Error> {
+    let circuit = circuit();
+    let eval = Evaluator::new(&circuit, [false, true], ChaCha20Rng::from_entropy())?;
+    let (contrib, msg) = Contributor::new(&circuit, [true, true], ChaCha20Rng::from_entropy())?;
+    let (eval, _) = eval.run(&msg)?;
+    let bytes = contrib.serialize_state(&KEY)?;
+    let restore = |bytes: &[u8], key: &[u8; 32]| {
+        Contributor::restore(&circuit, [true, true], bytes, key).map(|_| ())
+    };
+    assert_eq!(restore(&bytes, &KEY), Ok(()));
+
+    let mut corrupted = bytes.clone();
+    corrupted[100] ^= 1;
+    assert_eq!(restore(&corrupted, &KEY), Err(Error::InvalidSessionState));
+    assert_eq!(restore(&bytes, &[8; 32]), Err(Error::InvalidSessionState));
+    assert_eq!(
+        restore(&bytes[..bytes.len() - 1], &KEY),
+        Err(Error::InvalidSessionState)
+    );
+
+    let mut outdated = bytes.clone();
+    outdated[..4].copy_from_slice(&(PROTOCOL_VERSION - 1).to_le_bytes());
+    assert_eq!(
+        restore(&outdated, &KEY),
+        Err(Error::IncompatibleProtocolVersion {
+            version: PROTOCOL_VERSION - 1
+        })
+    );
+
+    // the state must be restored as the same party, with the same circuit and input length:
+    assert!(matches!(
+        Evaluator::restore(&circuit, [false, true], &bytes, &KEY),
+        Err(Error::InvalidSessionState)
+    ));
+    let other = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::InEval,
+            Gate::And(0, 2),
+        ],
+        vec![4],
+    );
+    assert!(matches!(
+        Contributor::restore(&other, [true, true], &bytes, &KEY),
+        Err(Error::InvalidSessionState)
+    ));
+    assert!(matches!(
+        Contributor::restore(&circuit, [true], &bytes, &KEY),
+        Err(Error::InvalidSessionState)
+    ));
+
+    let bytes = eval.serialize_state(&KEY)?;
+    assert!(matches!(
+        Contributor::restore(&circuit, [true, true], &bytes, &KEY),
+        Err(Error::InvalidSessionState)
+    ));
+    Ok(())
+}
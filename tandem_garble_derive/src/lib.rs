@@ -0,0 +1,229 @@
+//! Derive macros for converting between Rust types and Garble literals.
+//!
+//! The macros implement the `ToGarble` and `FromGarble` traits of `tandem_garble_interop`, which
+//! re-exports them and should be used instead of depending on this crate directly.
+//!
+//! Structs with named fields are mapped to Garble structs of the same name, enums with unit and
+//! tuple variants to Garble enums of the same name. Garble has neither tuple structs nor enum
+//! variants with named fields, so deriving the traits for these is a compile error.
+
+#![deny(unsafe_code)]
+#![deny(missing_docs)]
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    ext::IdentExt, parse_macro_input, parse_quote, Data, DeriveInput, Error, Fields, Generics,
+    Ident, Path,
+};
+
+/// Derives `ToGarble`, converting the value into a Garble literal.
+#[proc_macro_derive(ToGarble)]
+pub fn derive_to_garble(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_to_garble(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// Derives `FromGarble`, converting a Garble literal back into the value.
+#[proc_macro_derive(FromGarble)]
+pub fn derive_from_garble(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_from_garble(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+/// The shape of a type that can be mapped to a Garble literal.
+enum Shape<'a> {
+    Struct(Vec<&'a Ident>),
+    Enum(Vec<(&'a Ident, Variant)>),
+}
+
+/// The fields of an enum variant, Garble only supports unit and tuple variants.
+enum Variant {
+    Unit,
+    Tuple(usize),
+}
+
+fn shape(input: &DeriveInput) -> syn::Result<Shape<'_>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(Shape::Struct(
+                fields
+                    .named
+                    .iter()
+                    .filter_map(|f| f.ident.as_ref())
+                    .collect(),
+            )),
+            _ => Err(Error::new_spanned(
+                &input.ident,
+                "Garble structs must have named fields",
+            )),
+        },
+        Data::Enum(data) if data.variants.is_empty() => Err(Error::new_spanned(
+            &input.ident,
+            "Garble enums must have at least one variant",
+        )),
+        Data::Enum(data) => {
+            let mut variants = Vec::with_capacity(data.variants.len());
+            for variant in data.variants.iter() {
+                let fields = match &variant.fields {
+                    Fields::Unit => Variant::Unit,
+                    Fields::Unnamed(fields) => Variant::Tuple(fields.unnamed.len()),
+                    Fields::Named(_) => {
+                        return Err(Error::new_spanned(
+                            &variant.ident,
+                            "Garble enum variants cannot have named fields",
+                        ))
+                    }
+                };
+                variants.push((&variant.ident, fields));
+            }
+            Ok(Shape::Enum(variants))
+        }
+        Data::Union(_) => Err(Error::new_spanned(
+            &input.ident,
+            "Garble literals cannot be derived for unions",
+        )),
+    }
+}
+
+/// Adds a bound on the trait for each type parameter.
+fn with_bounds(generics: &Generics, bound: &Path) -> Generics {
+    let mut generics = generics.clone();
+    let params: Vec<Ident> = generics.type_params().map(|p| p.ident.clone()).collect();
+    let where_clause = generics.make_where_clause();
+    for param in params {
+        where_clause.predicates.push(parse_quote!(#param: #bound));
+    }
+    generics
+}
+
+fn expand_to_garble(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let krate = quote!(::tandem_garble_interop);
+    let name = &input.ident;
+    let name_str = name.unraw().to_string();
+    let body = match shape(input)? {
+        Shape::Struct(fields) => {
+            let field_strs = fields.iter().map(|f| f.unraw().to_string());
+            quote! {
+                #krate::Literal::Struct(
+                    #name_str.to_string(),
+                    vec![#((
+                        #field_strs.to_string(),
+                        #krate::ToGarble::to_garble(&self.#fields),
+                    )),*],
+                )
+            }
+        }
+        Shape::Enum(variants) => {
+            let arms = variants.iter().map(|(variant, fields)| {
+                let variant_str = variant.unraw().to_string();
+                match fields {
+                    Variant::Unit => quote! {
+                        Self::#variant => #krate::Literal::Enum(
+                            #name_str.to_string(),
+                            #variant_str.to_string(),
+                            #krate::VariantLiteral::Unit,
+                        )
+                    },
+                    Variant::Tuple(n) => {
+                        let bindings: Vec<Ident> =
+                            (0..*n).map(|i| format_ident!("f{}", i)).collect();
+                        quote! {
+                            Self::#variant(#(#bindings),*) => #krate::Literal::Enum(
+                                #name_str.to_string(),
+                                #variant_str.to_string(),
+                                #krate::VariantLiteral::Tuple(
+                                    vec![#(#krate::ToGarble::to_garble(#bindings)),*],
+                                ),
+                            )
+                        }
+                    }
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms,)*
+                }
+            }
+        }
+    };
+    let generics = with_bounds(&input.generics, &parse_quote!(#krate::ToGarble));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #krate::ToGarble for #name #ty_generics #where_clause {
+            fn to_garble(&self) -> #krate::Literal {
+                #body
+            }
+        }
+    })
+}
+
+fn expand_from_garble(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let krate = quote!(::tandem_garble_interop);
+    let name = &input.ident;
+    let name_str = name.unraw().to_string();
+    let literal = Ident::new("literal", Span::call_site());
+    let body = match shape(input)? {
+        Shape::Struct(fields) => {
+            let n = fields.len();
+            let field_strs = fields.iter().map(|f| f.unraw().to_string());
+            quote! {
+                match #literal {
+                    #krate::Literal::Struct(name, fields) if name == #name_str && fields.len() == #n => {
+                        Ok(Self {
+                            #(#fields: #krate::FromGarble::from_garble(
+                                #krate::__private::struct_field(fields, #field_strs, #literal)?,
+                            )?,)*
+                        })
+                    }
+                    _ => Err(#krate::__private::unexpected(#name_str, #literal)),
+                }
+            }
+        }
+        Shape::Enum(variants) => {
+            let arms = variants.iter().map(|(variant, fields)| {
+                let variant_str = variant.unraw().to_string();
+                match fields {
+                    Variant::Unit => quote! {
+                        (#variant_str, #krate::VariantLiteral::Unit) => Ok(Self::#variant)
+                    },
+                    Variant::Tuple(n) => {
+                        let indices = 0..*n;
+                        quote! {
+                            (#variant_str, #krate::VariantLiteral::Tuple(fields)) if fields.len() == #n => {
+                                Ok(Self::#variant(
+                                    #(#krate::FromGarble::from_garble(&fields[#indices])?),*
+                                ))
+                            }
+                        }
+                    }
+                }
+            });
+            quote! {
+                match #literal {
+                    #krate::Literal::Enum(name, variant, fields) if name == #name_str => {
+                        match (variant.as_str(), fields) {
+                            #(#arms,)*
+                            _ => Err(#krate::__private::unexpected(#name_str, #literal)),
+                        }
+                    }
+                    _ => Err(#krate::__private::unexpected(#name_str, #literal)),
+                }
+            }
+        }
+    };
+    let generics = with_bounds(&input.generics, &parse_quote!(#krate::FromGarble));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #krate::FromGarble for #name #ty_generics #where_clause {
+            fn from_garble(#literal: &#krate::Literal) -> ::std::result::Result<Self, ::std::string::String> {
+                #body
+            }
+        }
+    })
+}
@@ -0,0 +1,205 @@
+//! Fluent construction of Garble literals, type-checked against a program once they are built.
+
+use garble_lang::{
+    ast::Type,
+    literal::{Literal, VariantLiteral},
+    TypedProgram,
+};
+
+use crate::{convert::in_field_order, Result, ToGarble};
+
+/// Builds a (possibly nested) Garble literal without spelling out the [`Literal`] enum:
+///
+/// ```
+/// # use tandem_garble_interop::{check_program, LiteralBuilder, Type};
+/// let prg = check_program(
+///     "pub fn main(x: Card, y: u8) -> u8 { y }
+///     struct Card { suit: Suit, value: u8 }
+///     enum Suit { Hearts, Diamonds }",
+/// )?;
+/// let card = LiteralBuilder::struct_("Card")
+///     .field("suit", LiteralBuilder::enum_("Suit", "Diamonds"))
+///     .field("value", 7u8)
+///     .build(&prg, &Type::Struct("Card".to_string()))?;
+/// assert_eq!(card.to_string(), "Card {suit: Suit::Diamonds, value: 7u8}");
+/// # Ok::<(), String>(())
+/// ```
+///
+/// Numbers, booleans and all other values implementing [`ToGarble`] can be used wherever a builder
+/// is expected. Adding fields or elements to a literal that has none (such as a field to a tuple)
+/// is reported as an error by [`LiteralBuilder::build`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiteralBuilder {
+    literal: Literal,
+    error: Option<String>,
+}
+
+impl LiteralBuilder {
+    /// Starts a struct literal without fields, see [`LiteralBuilder::field`].
+    pub fn struct_(name: &str) -> Self {
+        Self::from(Literal::Struct(name.to_string(), vec![]))
+    }
+
+    /// Starts an enum literal of a variant without fields, see [`LiteralBuilder::arg`].
+    pub fn enum_(name: &str, variant: &str) -> Self {
+        Self::from(Literal::Enum(
+            name.to_string(),
+            variant.to_string(),
+            VariantLiteral::Unit,
+        ))
+    }
+
+    /// Starts a tuple literal without fields, see [`LiteralBuilder::elem`].
+    pub fn tuple() -> Self {
+        Self::from(Literal::Tuple(vec![]))
+    }
+
+    /// Starts an array literal without elements, see [`LiteralBuilder::elem`].
+    pub fn array() -> Self {
+        Self::from(Literal::Array(vec![]))
+    }
+
+    /// Returns an array literal repeating the element `size` times.
+    pub fn array_repeat(elem: impl Into<LiteralBuilder>, size: usize) -> Self {
+        let elem = elem.into();
+        Self {
+            literal: Literal::ArrayRepeat(Box::new(elem.literal), size),
+            error: elem.error,
+        }
+    }
+
+    /// Adds a named field to a struct literal.
+    pub fn field(mut self, name: &str, value: impl Into<LiteralBuilder>) -> Self {
+        let value = self.nested(value.into());
+        match &mut self.literal {
+            Literal::Struct(_, fields) => fields.push((name.to_string(), value)),
+            _ => self.fail(format!("the field '{name}' can only be added to a struct")),
+        }
+        self
+    }
+
+    /// Adds a field to the variant of an enum literal, turning a unit variant into a tuple variant.
+    pub fn arg(mut self, value: impl Into<LiteralBuilder>) -> Self {
+        let value = self.nested(value.into());
+        match &mut self.literal {
+            Literal::Enum(_, _, fields @ VariantLiteral::Unit) => {
+                *fields = VariantLiteral::Tuple(vec![value])
+            }
+            Literal::Enum(_, _, VariantLiteral::Tuple(fields)) => fields.push(value),
+            _ => self.fail("arguments can only be added to an enum variant".to_string()),
+        }
+        self
+    }
+
+    /// Adds an element to a tuple or array literal.
+    pub fn elem(mut self, value: impl Into<LiteralBuilder>) -> Self {
+        let value = self.nested(value.into());
+        match &mut self.literal {
+            Literal::Tuple(elems) | Literal::Array(elems) => elems.push(value),
+            _ => self.fail("elements can only be added to a tuple or an array".to_string()),
+        }
+        self
+    }
+
+    /// Returns the literal if it is of the specified type, with the fields of all struct literals
+    /// ordered like the struct definitions of the program.
+    pub fn build(self, prg: &TypedProgram, ty: &Type) -> Result<Literal> {
+        if let Some(e) = self.error {
+            return Err(format!("Invalid literal {}: {e}", self.literal));
+        }
+        if self.literal.is_of_type(prg, ty) {
+            Ok(in_field_order(prg, self.literal))
+        } else {
+            Err(format!("Literal {} is not of the type {ty}", self.literal))
+        }
+    }
+
+    /// Returns the literal of a nested builder, keeping the first error of both builders.
+    fn nested(&mut self, builder: LiteralBuilder) -> Literal {
+        if let Some(e) = builder.error {
+            self.fail(e);
+        }
+        builder.literal
+    }
+
+    fn fail(&mut self, e: String) {
+        self.error.get_or_insert(e);
+    }
+}
+
+impl From<Literal> for LiteralBuilder {
+    fn from(literal: Literal) -> Self {
+        Self {
+            literal,
+            error: None,
+        }
+    }
+}
+
+impl<T: ToGarble> From<T> for LiteralBuilder {
+    fn from(value: T) -> Self {
+        Self::from(value.to_garble())
+    }
+}
+
+#[test]
+fn test_literal_builder() {
+    use crate::check_program;
+
+    let prg = check_program(
+        "pub fn main(x: (Card, [bool; 2]), y: Result) -> u8 { 0u8 }
+        struct Card { value: u8, suit: Suit }
+        enum Suit { Hearts, Diamonds }
+        enum Result { Ok(u8, i32), Err }",
+    )
+    .unwrap();
+    let ty = Type::Tuple(vec![
+        Type::Struct("Card".to_string()),
+        Type::Array(Box::new(Type::Bool), 2),
+    ]);
+    let literal = LiteralBuilder::tuple()
+        .elem(
+            LiteralBuilder::struct_("Card")
+                .field("value", 7u8)
+                .field("suit", LiteralBuilder::enum_("Suit", "Hearts")),
+        )
+        .elem(LiteralBuilder::array_repeat(true, 2))
+        .build(&prg, &ty)
+        .unwrap();
+    assert_eq!(
+        literal,
+        Literal::Tuple(vec![
+            Literal::Struct(
+                "Card".to_string(),
+                vec![
+                    (
+                        "suit".to_string(),
+                        Literal::Enum(
+                            "Suit".to_string(),
+                            "Hearts".to_string(),
+                            VariantLiteral::Unit
+                        )
+                    ),
+                    ("value".to_string(), 7u8.to_garble()),
+                ]
+            ),
+            Literal::ArrayRepeat(Box::new(Literal::True), 2),
+        ])
+    );
+
+    let ty = Type::Enum("Result".to_string());
+    let ok = LiteralBuilder::enum_("Result", "Ok").arg(1u8);
+    assert!(ok.clone().arg(-2i32).build(&prg, &ty).is_ok());
+    assert!(ok.arg(2u8).build(&prg, &ty).is_err());
+
+    let e = LiteralBuilder::tuple().field("x", 1u8).build(&prg, &ty);
+    assert!(e
+        .unwrap_err()
+        .contains("the field 'x' can only be added to a struct"));
+    let e = LiteralBuilder::struct_("Card")
+        .field("value", LiteralBuilder::array().arg(true))
+        .build(&prg, &Type::Struct("Card".to_string()));
+    assert!(e
+        .unwrap_err()
+        .contains("can only be added to an enum variant"));
+}
@@ -0,0 +1,247 @@
+//! A persistent cache of compiled circuits, stored in a directory on disk.
+//!
+//! Each entry is stored as a single file named after its key, consisting of:
+//!
+//!   1. the length of the JSON header (`u64`, little-endian)
+//!   2. the JSON header, containing the Garble function definition and the gate report
+//!   3. the circuit in the columnar layout, see [`tandem::ColumnarCircuit`]
+//!
+//! The least recently used entries are tracked in an `index.json` file in the same directory and
+//! are evicted once the size of all entries exceeds the configured bound.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use tandem::{ColumnarCircuit, ValidatedCircuit};
+
+use crate::{compile_program, two_party_fns, Result, TypedCircuit, TypedFnDef, TypedProgram};
+
+const INDEX_FILE: &str = "index.json";
+const ENTRY_EXTENSION: &str = "circuit";
+
+/// Compiled circuits persisted on disk and keyed by the hash of their program and function.
+///
+/// Failures to read or write the cache are never reported to the caller, the circuit is then
+/// simply compiled (again). The index is only kept consistent within a single process, so a
+/// directory must not be used by multiple caches at the same time.
+pub struct CircuitCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Keys and sizes of the cached entries, from least to most recently used.
+    index: Mutex<Vec<(String, u64)>>,
+}
+
+impl CircuitCache {
+    /// The default bound of the total size of all cached entries, 256 MiB.
+    pub const DEFAULT_MAX_BYTES: u64 = 256 * 1024 * 1024;
+
+    /// Opens the cache in the specified directory, which is created if it does not exist yet.
+    ///
+    /// Once the entries exceed `max_bytes` in total, the least recently used entries are evicted.
+    pub fn open(dir: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let mut index: Vec<(String, u64)> = match fs::read(dir.join(INDEX_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_default(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        index.retain(|(key, _)| entry_path(&dir, key).is_file());
+        Ok(Self {
+            dir,
+            max_bytes,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// Returns the cached circuit of the function, or compiles it using [`compile_program`] and
+    /// caches the result.
+    ///
+    /// The `source_code` must be the source code that `prg` was type-checked from, as it is used
+    /// (together with `fn_name`) as the key of the cache entry.
+    pub fn compile(
+        &self,
+        source_code: &str,
+        prg: &TypedProgram,
+        fn_name: &str,
+    ) -> Result<TypedCircuit> {
+        let key = key(source_code, fn_name);
+        let mut index = self.index.lock().unwrap();
+        if let Some(circuit) = self.load(&mut index, &key) {
+            return Ok(circuit);
+        }
+        let circuit = compile_program(prg, fn_name)?;
+        let _ = self.store(&mut index, &key, &circuit);
+        Ok(circuit)
+    }
+
+    /// Returns the cached circuit of the function, if any, without compiling it.
+    pub fn get(&self, source_code: &str, fn_name: &str) -> Option<TypedCircuit> {
+        let mut index = self.index.lock().unwrap();
+        self.load(&mut index, &key(source_code, fn_name))
+    }
+
+    /// Caches the circuit of the function, which must have been compiled from `source_code`.
+    pub fn insert(&self, source_code: &str, fn_name: &str, circuit: &TypedCircuit) {
+        let mut index = self.index.lock().unwrap();
+        let _ = self.store(&mut index, &key(source_code, fn_name), circuit);
+    }
+
+    /// Returns the circuits of all public 2-party functions like [`crate::compile_all`], using
+    /// the cached circuits where available.
+    pub fn compile_all(
+        &self,
+        source_code: &str,
+        prg: &TypedProgram,
+    ) -> Result<HashMap<String, TypedCircuit>> {
+        two_party_fns(prg)
+            .into_iter()
+            .map(|fn_name| {
+                let circuit = self
+                    .compile(source_code, prg, fn_name)
+                    .map_err(|e| format!("{fn_name}: {e}"))?;
+                Ok((fn_name.to_string(), circuit))
+            })
+            .collect()
+    }
+
+    fn load(&self, index: &mut Vec<(String, u64)>, key: &str) -> Option<TypedCircuit> {
+        let path = entry_path(&self.dir, key);
+        let bytes = fs::read(&path).ok()?;
+        index.retain(|(k, _)| k != key);
+        match TypedCircuit::from_bytes(&bytes) {
+            Some(circuit) => {
+                index.push((key.to_string(), bytes.len() as u64));
+                let _ = self.write_index(index);
+                Some(circuit)
+            }
+            None => {
+                let _ = fs::remove_file(path);
+                None
+            }
+        }
+    }
+
+    fn store(
+        &self,
+        index: &mut Vec<(String, u64)>,
+        key: &str,
+        circuit: &TypedCircuit,
+    ) -> io::Result<()> {
+        let bytes = circuit.to_bytes()?;
+        let size = bytes.len() as u64;
+        if size > self.max_bytes {
+            return Ok(());
+        }
+
+        let path = entry_path(&self.dir, key);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, bytes)?;
+        fs::rename(tmp, path)?;
+
+        index.retain(|(k, _)| k != key);
+        index.push((key.to_string(), size));
+        let mut total: u64 = index.iter().map(|(_, size)| size).sum();
+        while total > self.max_bytes {
+            let (evicted, size) = index.remove(0);
+            let _ = fs::remove_file(entry_path(&self.dir, &evicted));
+            total -= size;
+        }
+        self.write_index(index)
+    }
+
+    fn write_index(&self, index: &[(String, u64)]) -> io::Result<()> {
+        let path = self.dir.join(INDEX_FILE);
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(index)?)?;
+        fs::rename(tmp, path)
+    }
+}
+
+/// The key of a cache entry, which also covers the crate version, as circuits compiled by a
+/// different version of the Garble compiler might differ.
+fn key(source_code: &str, fn_name: &str) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(&[0]);
+    hasher.update(&(source_code.len() as u64).to_le_bytes());
+    hasher.update(source_code.as_bytes());
+    hasher.update(fn_name.as_bytes());
+    hasher.finalize().to_hex().to_string()
+}
+
+fn entry_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(key).with_extension(ENTRY_EXTENSION)
+}
+
+impl TypedCircuit {
+    /// Serializes the circuit in the format of the entries of a [`CircuitCache`], which allows
+    /// other caches (such as the browser storage of the wasm client) to persist circuits as well.
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        let header = serde_json::to_vec(&(&self.fn_def, &self.info_about_gates))?;
+        let mut bytes = Vec::with_capacity(8 + header.len());
+        bytes.extend((header.len() as u64).to_le_bytes());
+        bytes.extend(header);
+        self.gates.write_columnar(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Deserializes a circuit serialized using [`TypedCircuit::to_bytes`], returning `None` if
+    /// the bytes are corrupted or the circuit is invalid.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let mut header_len = [0; 8];
+        header_len.copy_from_slice(bytes.get(..8)?);
+        let header_end = usize::try_from(u64::from_le_bytes(header_len))
+            .ok()?
+            .checked_add(8)?;
+        let header = bytes.get(8..header_end)?;
+        let (fn_def, info_about_gates): (TypedFnDef, String) =
+            serde_json::from_slice(header).ok()?;
+        let gates = ColumnarCircuit::new(bytes.get(header_end..)?).ok()?;
+        Some(Self {
+            gates: ValidatedCircuit::new(gates.to_circuit()).ok()?,
+            fn_def,
+            info_about_gates,
+        })
+    }
+}
+
+#[test]
+fn test_circuit_cache() {
+    use crate::check_program;
+
+    let dir = std::env::temp_dir().join(format!("tandem_circuit_cache_{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let source_code = "pub fn main(x: u8, y: u8) -> u8 { x + y }
+        pub fn sub(x: u8, y: u8) -> u8 { x - y }";
+    let prg = check_program(source_code).unwrap();
+
+    let cache = CircuitCache::open(&dir, CircuitCache::DEFAULT_MAX_BYTES).unwrap();
+    let compiled = cache.compile(source_code, &prg, "main").unwrap();
+    assert!(entry_path(&dir, &key(source_code, "main")).is_file());
+
+    let cache = CircuitCache::open(&dir, CircuitCache::DEFAULT_MAX_BYTES).unwrap();
+    let cached = cache.compile(source_code, &prg, "main").unwrap();
+    assert_eq!(cached.gates.gates(), compiled.gates.gates());
+    assert_eq!(cached.gates.output_gates(), compiled.gates.output_gates());
+    assert_eq!(cached.fn_def.ty, compiled.fn_def.ty);
+    assert_eq!(cached.info_about_gates, compiled.info_about_gates);
+    assert!(cache.compile(source_code, &prg, "missing").is_err());
+    assert!(cache.get(source_code, "main").is_some());
+    assert!(cache.get(source_code, "sub").is_none());
+
+    // a bound that only fits one of the entries evicts the least recently used entry:
+    let size = fs::metadata(entry_path(&dir, &key(source_code, "main")))
+        .unwrap()
+        .len();
+    let cache = CircuitCache::open(&dir, size + size / 2).unwrap();
+    cache.compile(source_code, &prg, "sub").unwrap();
+    assert!(!entry_path(&dir, &key(source_code, "main")).is_file());
+    assert!(entry_path(&dir, &key(source_code, "sub")).is_file());
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,119 @@
+//! Comparison of programs that ignores whitespace and comments.
+
+use garble_lang::{
+    scan::scan,
+    token::{MetaInfo, Token},
+    CompileTimeError,
+};
+
+use crate::Result;
+
+/// The first token in which two programs differ, see [`diff_programs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramDifference {
+    /// The differing token of the first program.
+    pub left: DifferingToken,
+    /// The differing token of the second program.
+    pub right: DifferingToken,
+}
+
+/// The location and text of a token in which two programs differ.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DifferingToken {
+    /// The line of the token, starting at 1.
+    pub line: usize,
+    /// The column of the token, starting at 1.
+    pub column: usize,
+    /// The token, or `None` if the program ends before the other program.
+    pub token: Option<String>,
+}
+
+impl std::fmt::Display for DifferingToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.token {
+            Some(token) => write!(f, "'{token}' at {}:{}", self.line, self.column),
+            None => write!(f, "end of program at {}:{}", self.line, self.column),
+        }
+    }
+}
+
+/// Returns a hash of the tokens of the program, which is the same for all programs that only
+/// differ in whitespace and comments.
+pub fn normalized_program_hash(program: &str) -> Result<blake3::Hash> {
+    let mut hasher = blake3::Hasher::new();
+    for Token(token, _) in tokens(program)? {
+        // the debug representation distinguishes identifiers from keywords and number suffixes:
+        hasher.update(format!("{token:?}").as_bytes());
+        hasher.update(&[0]);
+    }
+    Ok(hasher.finalize())
+}
+
+/// Compares the tokens of both programs and returns the first difference, or `None` if the
+/// programs only differ in whitespace and comments.
+pub fn diff_programs(left: &str, right: &str) -> Result<Option<ProgramDifference>> {
+    let left = tokens(left)?;
+    let right = tokens(right)?;
+    for i in 0..left.len().max(right.len()) {
+        let (l, r) = (left.get(i), right.get(i));
+        if l.map(|t| &t.0) != r.map(|t| &t.0) {
+            return Ok(Some(ProgramDifference {
+                left: differing_token(&left, i),
+                right: differing_token(&right, i),
+            }));
+        }
+    }
+    Ok(None)
+}
+
+fn tokens(program: &str) -> Result<Vec<Token>> {
+    match scan(program) {
+        Ok(tokens) => Ok(tokens.0),
+        Err(e) => Err(CompileTimeError::from(e).prettify(program)),
+    }
+}
+
+fn differing_token(tokens: &[Token], i: usize) -> DifferingToken {
+    let (token, (line, column)) = match (tokens.get(i), tokens.last()) {
+        (Some(Token(token, meta)), _) => (Some(token.to_string()), meta.start),
+        (None, Some(Token(_, MetaInfo { end, .. }))) => (None, (end.0, end.1 + 1)),
+        (None, None) => (None, (0, 0)),
+    };
+    DifferingToken {
+        line: line + 1,
+        column: column + 1,
+        token,
+    }
+}
+
+#[test]
+fn test_diff_programs() {
+    let server = "pub fn main(x: u8, y: u8) -> u8 {\n    x + y\n}\n";
+    let client = "// adds both inputs\npub fn main(x: u8,y: u8) -> u8 { x + y } // done";
+    assert_eq!(diff_programs(client, server).unwrap(), None);
+    assert_eq!(
+        normalized_program_hash(client).unwrap(),
+        normalized_program_hash(server).unwrap()
+    );
+
+    let client = "pub fn main(x: u8, y: u8) -> u8 {\n    x * y\n}\n";
+    let difference = diff_programs(client, server).unwrap().unwrap();
+    assert_eq!(difference.left.to_string(), "'*' at 2:7");
+    assert_eq!(difference.right.to_string(), "'+' at 2:7");
+    assert_ne!(
+        normalized_program_hash(client).unwrap(),
+        normalized_program_hash(server).unwrap()
+    );
+
+    let difference = diff_programs("pub fn main(x: u8) -> u8 { x }", server).unwrap();
+    assert_eq!(difference.unwrap().left.token, Some(")".to_string()));
+    let difference = diff_programs("pub fn", server).unwrap().unwrap();
+    assert_eq!(difference.left.to_string(), "end of program at 1:7");
+
+    // numbers with different types are distinguished:
+    assert_ne!(
+        normalized_program_hash("1u8").unwrap(),
+        normalized_program_hash("1u16").unwrap()
+    );
+    assert!(diff_programs("pub fn main(x: u8) -> u8 { x $ }", server).is_err());
+}
@@ -0,0 +1,267 @@
+//! Conversions between Rust values and Garble literals.
+
+use std::convert::TryFrom;
+
+use garble_lang::{
+    ast::Type,
+    literal::{Literal, VariantLiteral},
+    token::{SignedNumType, UnsignedNumType},
+    TypedProgram,
+};
+
+use crate::Result;
+
+/// Converts a Rust value into the corresponding Garble literal.
+///
+/// Can be derived using `#[derive(ToGarble)]` for structs with named fields (which become Garble
+/// structs of the same name) and for enums with unit and tuple variants (which become Garble
+/// enums of the same name).
+pub trait ToGarble {
+    /// Returns the Garble literal representing the value.
+    fn to_garble(&self) -> Literal;
+
+    /// Returns the Garble literal representing the value, if it is of the specified type.
+    ///
+    /// The fields of struct literals are reordered to match the struct definitions of the
+    /// program, which determine how the literal is encoded as input bits.
+    fn to_garble_checked(&self, prg: &TypedProgram, ty: &Type) -> Result<Literal> {
+        let literal = self.to_garble();
+        if literal.is_of_type(prg, ty) {
+            Ok(in_field_order(prg, literal))
+        } else {
+            Err(format!("Literal {literal} is not of the type {ty}"))
+        }
+    }
+}
+
+/// Converts a Garble literal into the corresponding Rust value.
+///
+/// Can be derived using `#[derive(FromGarble)]`, see [`ToGarble`].
+pub trait FromGarble: Sized {
+    /// Returns the value represented by the literal, or an error if the literal does not match.
+    fn from_garble(literal: &Literal) -> Result<Self>;
+}
+
+/// Sorts the fields of all struct literals in the order of their struct definitions.
+pub(crate) fn in_field_order(prg: &TypedProgram, literal: Literal) -> Literal {
+    match literal {
+        Literal::Struct(name, mut fields) => {
+            if let Some(struct_def) = prg.struct_defs.get(&name) {
+                fields.sort_by_key(|(field, _)| {
+                    struct_def.fields.iter().position(|(f, _)| f == field)
+                });
+            }
+            let fields = fields
+                .into_iter()
+                .map(|(field, value)| (field, in_field_order(prg, value)))
+                .collect();
+            Literal::Struct(name, fields)
+        }
+        Literal::Enum(name, variant, VariantLiteral::Tuple(fields)) => {
+            let fields = fields.into_iter().map(|f| in_field_order(prg, f)).collect();
+            Literal::Enum(name, variant, VariantLiteral::Tuple(fields))
+        }
+        Literal::Array(elems) => {
+            Literal::Array(elems.into_iter().map(|e| in_field_order(prg, e)).collect())
+        }
+        Literal::ArrayRepeat(elem, size) => {
+            Literal::ArrayRepeat(Box::new(in_field_order(prg, *elem)), size)
+        }
+        Literal::Tuple(fields) => {
+            Literal::Tuple(fields.into_iter().map(|f| in_field_order(prg, f)).collect())
+        }
+        literal => literal,
+    }
+}
+
+impl ToGarble for bool {
+    fn to_garble(&self) -> Literal {
+        if *self {
+            Literal::True
+        } else {
+            Literal::False
+        }
+    }
+}
+
+impl FromGarble for bool {
+    fn from_garble(literal: &Literal) -> Result<Self> {
+        match literal {
+            Literal::True => Ok(true),
+            Literal::False => Ok(false),
+            _ => Err(__private::unexpected("bool", literal)),
+        }
+    }
+}
+
+macro_rules! impl_unsigned {
+    ($($t:ty => $num_ty:ident),*) => {$(
+        impl ToGarble for $t {
+            fn to_garble(&self) -> Literal {
+                Literal::NumUnsigned(*self as u64, UnsignedNumType::$num_ty)
+            }
+        }
+
+        impl FromGarble for $t {
+            fn from_garble(literal: &Literal) -> Result<Self> {
+                match literal {
+                    Literal::NumUnsigned(n, UnsignedNumType::$num_ty) => <$t>::try_from(*n)
+                        .map_err(|_| __private::unexpected(stringify!($t), literal)),
+                    _ => Err(__private::unexpected(stringify!($t), literal)),
+                }
+            }
+        }
+    )*};
+}
+
+macro_rules! impl_signed {
+    ($($t:ty => $num_ty:ident),*) => {$(
+        impl ToGarble for $t {
+            fn to_garble(&self) -> Literal {
+                Literal::NumSigned(*self as i64, SignedNumType::$num_ty)
+            }
+        }
+
+        impl FromGarble for $t {
+            fn from_garble(literal: &Literal) -> Result<Self> {
+                match literal {
+                    Literal::NumSigned(n, SignedNumType::$num_ty) => <$t>::try_from(*n)
+                        .map_err(|_| __private::unexpected(stringify!($t), literal)),
+                    _ => Err(__private::unexpected(stringify!($t), literal)),
+                }
+            }
+        }
+    )*};
+}
+
+impl_unsigned!(usize => Usize, u8 => U8, u16 => U16, u32 => U32, u64 => U64);
+impl_signed!(i8 => I8, i16 => I16, i32 => I32, i64 => I64);
+
+impl<T: ToGarble> ToGarble for [T] {
+    fn to_garble(&self) -> Literal {
+        Literal::Array(self.iter().map(T::to_garble).collect())
+    }
+}
+
+impl<T: ToGarble, const N: usize> ToGarble for [T; N] {
+    fn to_garble(&self) -> Literal {
+        self[..].to_garble()
+    }
+}
+
+impl<T: ToGarble> ToGarble for Vec<T> {
+    fn to_garble(&self) -> Literal {
+        self[..].to_garble()
+    }
+}
+
+impl<T: FromGarble> FromGarble for Vec<T> {
+    fn from_garble(literal: &Literal) -> Result<Self> {
+        match literal {
+            Literal::Array(elems) => elems.iter().map(T::from_garble).collect(),
+            Literal::ArrayRepeat(elem, size) => (0..*size).map(|_| T::from_garble(elem)).collect(),
+            Literal::Range((min, min_ty), (max, _)) => (*min..*max)
+                .map(|n| T::from_garble(&Literal::NumUnsigned(n, *min_ty)))
+                .collect(),
+            _ => Err(__private::unexpected("array", literal)),
+        }
+    }
+}
+
+impl<T: FromGarble, const N: usize> FromGarble for [T; N] {
+    fn from_garble(literal: &Literal) -> Result<Self> {
+        let elems: Vec<T> = Vec::from_garble(literal)?;
+        <[T; N]>::try_from(elems).map_err(|_| __private::unexpected("array", literal))
+    }
+}
+
+impl ToGarble for () {
+    fn to_garble(&self) -> Literal {
+        Literal::Tuple(vec![])
+    }
+}
+
+impl FromGarble for () {
+    fn from_garble(literal: &Literal) -> Result<Self> {
+        match literal {
+            Literal::Tuple(fields) if fields.is_empty() => Ok(()),
+            _ => Err(__private::unexpected("()", literal)),
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($(($($t:ident $i:tt),+)),*) => {$(
+        impl<$($t: ToGarble),+> ToGarble for ($($t,)+) {
+            fn to_garble(&self) -> Literal {
+                Literal::Tuple(vec![$(self.$i.to_garble()),+])
+            }
+        }
+
+        impl<$($t: FromGarble),+> FromGarble for ($($t,)+) {
+            fn from_garble(literal: &Literal) -> Result<Self> {
+                match literal {
+                    Literal::Tuple(fields) if fields.len() == [$($i),+].len() => {
+                        Ok(($($t::from_garble(&fields[$i])?,)+))
+                    }
+                    _ => Err(__private::unexpected("tuple", literal)),
+                }
+            }
+        }
+    )*};
+}
+
+impl_tuple!(
+    (A 0),
+    (A 0, B 1),
+    (A 0, B 1, C 2),
+    (A 0, B 1, C 2, D 3),
+    (A 0, B 1, C 2, D 3, E 4),
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+);
+
+/// Helpers used by the code generated by the derive macros, not part of the public API.
+#[doc(hidden)]
+pub mod __private {
+    use crate::{Literal, Result};
+
+    pub fn struct_field<'a>(
+        fields: &'a [(String, Literal)],
+        name: &str,
+        literal: &Literal,
+    ) -> Result<&'a Literal> {
+        fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+            .ok_or_else(|| format!("Literal {literal} is missing the field '{name}'"))
+    }
+
+    pub fn unexpected(expected: &str, literal: &Literal) -> String {
+        format!("Literal {literal} cannot be converted to {expected}")
+    }
+}
+
+#[test]
+fn test_primitive_conversions() {
+    fn round_trip<T: ToGarble + FromGarble + PartialEq + std::fmt::Debug>(value: T) {
+        assert_eq!(T::from_garble(&value.to_garble()), Ok(value));
+    }
+
+    round_trip(true);
+    round_trip(200u8);
+    round_trip(usize::MAX);
+    round_trip(-5i16);
+    round_trip([1u32, 2, 3]);
+    round_trip(vec![(false, -1i64), (true, 7)]);
+    round_trip(((), (1u8,), (2u16, 3u64, 4i8)));
+
+    assert_eq!(3u8.to_garble().to_string(), "3u8");
+    assert!(u8::from_garble(&3u16.to_garble()).is_err());
+    assert!(u8::from_garble(&Literal::NumUnsigned(256, UnsignedNumType::U8)).is_err());
+    assert!(<[bool; 2]>::from_garble(&[true; 3].to_garble()).is_err());
+    assert_eq!(
+        <[bool; 3]>::from_garble(&Literal::ArrayRepeat(Box::new(Literal::True), 3)),
+        Ok([true; 3])
+    );
+}
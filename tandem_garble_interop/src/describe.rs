@@ -0,0 +1,190 @@
+//! Serializable descriptions of Garble types, for generating input forms and validating inputs.
+
+use garble_lang::{
+    ast::{Type, Variant},
+    literal::Literal,
+    TypedProgram,
+};
+use serde::{Deserialize, Serialize};
+
+/// A Garble type with the fields of its structs and the variants of its enums resolved using the
+/// definitions of the program, serialized with its `kind` as the tag.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum TypeDescription {
+    /// The type `bool`.
+    Bool,
+    /// An unsigned number type such as `u8` or `usize`.
+    Unsigned {
+        /// The name of the type, such as `u8`.
+        name: String,
+        /// The number of bits of a value of the type.
+        bits: usize,
+    },
+    /// A signed number type such as `i32`.
+    Signed {
+        /// The name of the type, such as `i32`.
+        name: String,
+        /// The number of bits of a value of the type.
+        bits: usize,
+    },
+    /// An array of a fixed size.
+    Array {
+        /// The type of the elements.
+        elem: Box<TypeDescription>,
+        /// The number of elements.
+        size: usize,
+    },
+    /// A tuple, whose fields are identified by their position.
+    Tuple {
+        /// The types of the fields.
+        fields: Vec<TypeDescription>,
+    },
+    /// A struct with named fields.
+    Struct {
+        /// The name of the struct.
+        name: String,
+        /// The fields of the struct, in the order in which the compiler lays out their bits (which
+        /// is not necessarily the order of their definition).
+        fields: Vec<FieldDescription>,
+    },
+    /// An enum, whose variants can have (unnamed) fields.
+    Enum {
+        /// The name of the enum.
+        name: String,
+        /// The variants of the enum, in the order of their definition.
+        variants: Vec<VariantDescription>,
+    },
+}
+
+/// A named field of a struct, see [`TypeDescription::Struct`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldDescription {
+    /// The name of the field.
+    pub name: String,
+    /// The type of the field.
+    #[serde(rename = "type")]
+    pub ty: TypeDescription,
+}
+
+/// A variant of an enum, see [`TypeDescription::Enum`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VariantDescription {
+    /// The name of the variant.
+    pub name: String,
+    /// The types of the fields of the variant, empty for unit variants.
+    pub fields: Vec<TypeDescription>,
+}
+
+/// Describes the type, returning `None` for function types and undefined structs or enums, which
+/// cannot occur as the parameter types of a type-checked program.
+pub fn describe_type(prg: &TypedProgram, ty: &Type) -> Option<TypeDescription> {
+    Some(match ty {
+        Type::Bool => TypeDescription::Bool,
+        Type::Unsigned(num_ty) => TypeDescription::Unsigned {
+            name: num_ty.to_string(),
+            bits: Literal::NumUnsigned(0, *num_ty).as_bits(prg).len(),
+        },
+        Type::Signed(num_ty) => TypeDescription::Signed {
+            name: num_ty.to_string(),
+            bits: Literal::NumSigned(0, *num_ty).as_bits(prg).len(),
+        },
+        Type::Array(elem, size) => TypeDescription::Array {
+            elem: Box::new(describe_type(prg, elem)?),
+            size: *size,
+        },
+        Type::Tuple(tys) => TypeDescription::Tuple {
+            fields: tys
+                .iter()
+                .map(|ty| describe_type(prg, ty))
+                .collect::<Option<_>>()?,
+        },
+        Type::Struct(name) => {
+            let fields = prg.struct_defs.get(name)?.fields.iter();
+            let fields = fields
+                .map(|(field, ty)| {
+                    Some(FieldDescription {
+                        name: field.clone(),
+                        ty: describe_type(prg, ty)?,
+                    })
+                })
+                .collect::<Option<_>>()?;
+            TypeDescription::Struct {
+                name: name.clone(),
+                fields,
+            }
+        }
+        Type::Enum(name) => {
+            let variants = prg.enum_defs.get(name)?.variants.iter();
+            let variants = variants
+                .map(|variant| {
+                    let (name, tys) = match variant {
+                        Variant::Unit(name) => (name, &[][..]),
+                        Variant::Tuple(name, tys) => (name, &tys[..]),
+                    };
+                    Some(VariantDescription {
+                        name: name.clone(),
+                        fields: tys
+                            .iter()
+                            .map(|ty| describe_type(prg, ty))
+                            .collect::<Option<_>>()?,
+                    })
+                })
+                .collect::<Option<_>>()?;
+            TypeDescription::Enum {
+                name: name.clone(),
+                variants,
+            }
+        }
+        Type::Fn(..) | Type::UntypedTopLevelDefinition(..) => return None,
+    })
+}
+
+#[test]
+fn test_describe_type() {
+    use crate::{check_program, compile_program, input_type, Role};
+
+    let prg = check_program(
+        "pub fn main(x: (u8, [bool; 2]), p: Point) -> u8 { x.0 }
+        struct Point { x: i32, suit: Suit }
+        enum Suit { Hearts, Other(usize) }",
+    )
+    .unwrap();
+    let circuit = compile_program(&prg, "main").unwrap();
+    let contributor = input_type(Role::Contributor, &circuit.fn_def);
+    assert_eq!(
+        describe_type(&prg, contributor),
+        Some(TypeDescription::Tuple {
+            fields: vec![
+                TypeDescription::Unsigned {
+                    name: "u8".to_string(),
+                    bits: 8
+                },
+                TypeDescription::Array {
+                    elem: Box::new(TypeDescription::Bool),
+                    size: 2
+                },
+            ]
+        })
+    );
+    let evaluator = describe_type(&prg, input_type(Role::Evaluator, &circuit.fn_def)).unwrap();
+    let json = serde_json::to_value(&evaluator).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "kind": "struct",
+            "name": "Point",
+            "fields": [
+                {"name": "suit", "type": {"kind": "enum", "name": "Suit", "variants": [
+                    {"name": "Hearts", "fields": []},
+                    {"name": "Other", "fields": [{"kind": "unsigned", "name": "usize", "bits": 32}]},
+                ]}},
+                {"name": "x", "type": {"kind": "signed", "name": "i32", "bits": 32}},
+            ]
+        })
+    );
+    assert_eq!(
+        serde_json::from_value::<TypeDescription>(json).unwrap(),
+        evaluator
+    );
+}
@@ -0,0 +1,128 @@
+//! Graphviz export of compiled circuits, annotated with the names of their Garble parameters.
+
+use garble_lang::{
+    ast::{Type, Variant},
+    literal::{Literal, VariantLiteral},
+    TypedProgram,
+};
+
+use crate::TypedCircuit;
+
+/// Renders the circuit as a Graphviz DOT graph like [`tandem::Circuit::to_dot`], labelling each
+/// input and output wire with the Garble parameter it belongs to.
+///
+/// Wires of tuples, arrays and structs are labelled by their path (e.g. `x.0`, `x[2]` or
+/// `point.x`), wires of numbers and enums additionally by their bit index (e.g. `x.0#7`). The
+/// output wires are labelled as `return`, preceded by the wires of a potential panic (labelled as
+/// `panic`) that the compiler adds to every circuit.
+pub fn circuit_to_dot(prg: &TypedProgram, circuit: &TypedCircuit, max_gates: usize) -> String {
+    let params = &circuit.fn_def.params;
+    let widths = [circuit.gates.contrib_inputs(), circuit.gates.eval_inputs()];
+    let mut input_labels = vec![];
+    for (param, width) in params.iter().zip(widths) {
+        label_bits(prg, &param.ty, &param.name, width, &mut input_labels);
+    }
+    let mut output_labels = vec![];
+    let width = circuit.gates.output_gates().len();
+    let ty = &circuit.fn_def.ty;
+    let panic_width = bit_width(prg, ty).map_or(0, |w| width.saturating_sub(w));
+    output_labels.extend((0..panic_width).map(|i| format!("panic#{i}")));
+    label_bits(prg, ty, "return", width - panic_width, &mut output_labels);
+    circuit
+        .gates
+        .to_dot_with_labels(max_gates, &input_labels, &output_labels)
+}
+
+/// Appends a label for each of the `width` bits of a value of the specified type.
+fn label_bits(prg: &TypedProgram, ty: &Type, path: &str, width: usize, labels: &mut Vec<String>) {
+    let parts: Option<Vec<(String, &Type)>> = match ty {
+        Type::Tuple(tys) => Some(
+            tys.iter()
+                .enumerate()
+                .map(|(i, ty)| (format!("{path}.{i}"), ty))
+                .collect(),
+        ),
+        Type::Array(elem, size) => Some(
+            (0..*size)
+                .map(|i| (format!("{path}[{i}]"), &**elem))
+                .collect(),
+        ),
+        Type::Struct(name) => prg.struct_defs.get(name).map(|struct_def| {
+            struct_def
+                .fields
+                .iter()
+                .map(|(field, ty)| (format!("{path}.{field}"), ty))
+                .collect()
+        }),
+        _ => None,
+    };
+    let parts = parts.and_then(|parts| {
+        let widths: Option<Vec<usize>> = parts.iter().map(|(_, ty)| bit_width(prg, ty)).collect();
+        widths
+            .filter(|widths| widths.iter().sum::<usize>() == width)
+            .map(|widths| parts.into_iter().zip(widths))
+    });
+    match parts {
+        Some(parts) => {
+            for ((path, ty), width) in parts {
+                label_bits(prg, ty, &path, width, labels);
+            }
+        }
+        None if width == 1 => labels.push(path.to_string()),
+        None => labels.extend((0..width).map(|i| format!("{path}#{i}"))),
+    }
+}
+
+/// Returns the number of bits of a value of the specified type, if it can be determined.
+fn bit_width(prg: &TypedProgram, ty: &Type) -> Option<usize> {
+    Some(zero(prg, ty)?.as_bits(prg).len())
+}
+
+/// Returns an arbitrary literal of the specified type, which has the same size as all others.
+fn zero(prg: &TypedProgram, ty: &Type) -> Option<Literal> {
+    Some(match ty {
+        Type::Bool => Literal::False,
+        Type::Unsigned(num_ty) => Literal::NumUnsigned(0, *num_ty),
+        Type::Signed(num_ty) => Literal::NumSigned(0, *num_ty),
+        Type::Array(elem, size) => Literal::ArrayRepeat(Box::new(zero(prg, elem)?), *size),
+        Type::Tuple(tys) => {
+            Literal::Tuple(tys.iter().map(|ty| zero(prg, ty)).collect::<Option<_>>()?)
+        }
+        Type::Struct(name) => {
+            let fields = prg.struct_defs.get(name)?.fields.iter();
+            let fields = fields
+                .map(|(field, ty)| Some((field.clone(), zero(prg, ty)?)))
+                .collect::<Option<_>>()?;
+            Literal::Struct(name.clone(), fields)
+        }
+        Type::Enum(name) => match prg.enum_defs.get(name)?.variants.first()? {
+            Variant::Unit(variant) => {
+                Literal::Enum(name.clone(), variant.clone(), VariantLiteral::Unit)
+            }
+            Variant::Tuple(variant, tys) => {
+                let fields = tys.iter().map(|ty| zero(prg, ty)).collect::<Option<_>>()?;
+                Literal::Enum(name.clone(), variant.clone(), VariantLiteral::Tuple(fields))
+            }
+        },
+        _ => return None,
+    })
+}
+
+#[test]
+fn test_circuit_to_dot() {
+    use crate::{check_program, compile_program};
+
+    let prg = check_program(
+        "pub fn main(x: (u8, bool), p: Point) -> [bool; 2] { [x.1, p.y] }
+        struct Point { x: u8, y: bool }",
+    )
+    .unwrap();
+    let circuit = compile_program(&prg, "main").unwrap();
+    let dot = circuit_to_dot(&prg, &circuit, 100);
+    assert!(dot.contains("label=\"x.0#7\""));
+    assert!(dot.contains("label=\"x.1\""));
+    assert!(dot.contains("label=\"p.x#0\""));
+    assert!(dot.contains("label=\"p.y\""));
+    assert!(dot.contains("label=\"panic#0\""));
+    assert!(dot.contains("label=\"return[1]\""));
+}
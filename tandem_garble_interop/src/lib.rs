@@ -2,18 +2,64 @@
 //!
 //! This crate provides helper functions for translating between the Tandem MPC engine circuit
 //! representation and the Garble language circuit representation and types.
+//!
+//! Rust values can be converted to Garble literals and back using the [`ToGarble`] and
+//! [`FromGarble`] traits, which can be derived for structs and enums.
+//!
+//! Compiled circuits can be persisted across runs using a [`CircuitCache`].
+//!
+//! Programs that only differ in whitespace and comments can be recognized using
+//! [`normalized_program_hash`], and [`diff_programs`] locates the first difference otherwise.
+//!
+//! Compiled circuits can be visualized using [`circuit_to_dot`], which labels the wires of the
+//! circuit with the names of the Garble parameters.
+//!
+//! Programs can declare server-side constants as `const NAME: Type;` (on a line of their own),
+//! which can be used by all public functions with 2 parameters. Their values are not part of the
+//! program text, but are supplied by the contributor together with its input using
+//! [`serialize_input_with_constants`], so that the compiled circuit only depends on the names and
+//! types of the constants (see [`program_constants`]).
 
 #![deny(unsafe_code)]
 #![deny(missing_docs)]
 #![deny(rustdoc::broken_intra_doc_links)]
 
-pub use garble_lang::{ast::Type, literal::*, TypedFnDef, TypedProgram};
+use std::collections::HashMap;
+
+mod builder;
+mod cache;
+mod compare;
+mod convert;
+mod describe;
+mod dot;
+mod template;
+
+pub use builder::LiteralBuilder;
+pub use cache::CircuitCache;
+pub use compare::{diff_programs, normalized_program_hash, DifferingToken, ProgramDifference};
+pub use convert::{__private, FromGarble, ToGarble};
+pub use describe::{describe_type, FieldDescription, TypeDescription, VariantDescription};
+pub use dot::circuit_to_dot;
+pub use garble_lang::{
+    ast::Type,
+    literal::*,
+    token::{SignedNumType, UnsignedNumType},
+    TypedFnDef, TypedProgram,
+};
+pub use tandem_garble_derive::{FromGarble, ToGarble};
+pub use template::program_constants;
+
+/// Version of the Garble compiler used to compile circuits.
+///
+/// Circuits compiled from the same program by different versions of the compiler can differ, so
+/// the version is pinned and reported to help diagnose mismatching circuits of client and server.
+pub const GARBLE_VERSION: &str = "0.1.8";
 
 /// A Tandem circuit together with its associated Garble types.
 #[derive(Debug, Clone)]
 pub struct TypedCircuit {
-    /// Boolean circuit executable by the Tandem engine.
-    pub gates: tandem::Circuit,
+    /// Boolean circuit executable by the Tandem engine, validated once during compilation.
+    pub gates: tandem::ValidatedCircuit,
     /// Typed Garble function corresponding to the Tandem circuit.
     pub fn_def: TypedFnDef,
     /// Number of gates in the circuit as a formatted string.
@@ -33,9 +79,9 @@ pub enum Role {
 
 type Result<T> = std::result::Result<T, String>;
 
-/// Scans, parses and type-checks a Garble program.
+/// Scans, parses and type-checks a Garble program, which may declare server-side constants.
 pub fn check_program(program: &str) -> Result<TypedProgram> {
-    garble_lang::check(program).map_err(|e| e.prettify(program))
+    template::check_template(program)
 }
 
 /// Compiles the (type-checked) program, producing a circuit of gates.
@@ -82,7 +128,8 @@ pub fn compile_program(prg: &TypedProgram, fn_name: &str) -> Result<TypedCircuit
         .iter()
         .map(|i| *i as tandem::GateIndex)
         .collect();
-    let program = tandem::Circuit::new(gates, output_gates);
+    let program = tandem::ValidatedCircuit::new(tandem::Circuit::new(gates, output_gates))
+        .map_err(|e| format!("The compiled circuit is invalid: {e}"))?;
 
     Ok(TypedCircuit {
         gates: program,
@@ -91,14 +138,43 @@ pub fn compile_program(prg: &TypedProgram, fn_name: &str) -> Result<TypedCircuit
     })
 }
 
+/// Compiles all public functions of the (type-checked) program that take the inputs of 2 parties,
+/// returning their circuits by function name.
+///
+/// This is meant for servers that offer several functions of the same program, which then only
+/// need to type-check the program once. Functions that cannot be called by 2 parties are skipped.
+pub fn compile_all(prg: &TypedProgram) -> Result<HashMap<String, TypedCircuit>> {
+    two_party_fns(prg)
+        .into_iter()
+        .map(|fn_name| {
+            let circuit = compile_program(prg, fn_name).map_err(|e| format!("{fn_name}: {e}"))?;
+            Ok((fn_name.to_string(), circuit))
+        })
+        .collect()
+}
+
+/// Returns the names of all public functions with 2 parameters, in alphabetical order.
+fn two_party_fns(prg: &TypedProgram) -> Vec<&str> {
+    let mut fn_names: Vec<&str> = prg
+        .fn_defs
+        .iter()
+        .filter(|(_, fn_def)| fn_def.is_pub && fn_def.params.len() == 2)
+        .map(|(fn_name, _)| fn_name.as_str())
+        .collect();
+    fn_names.sort_unstable();
+    fn_names
+}
+
 /// Returns the Garble type of the input associated with the specified role.
 ///
-/// In the case of the contributor, the result will be the type of the _first_ function parameter.
-/// In the case of the evaluator, the result will be the type of the _second_ function parameter.
+/// In the case of the contributor, the result will be the type of the _first_ function parameter
+/// (without any server-side constants). In the case of the evaluator, the result will be the type
+/// of the _second_ function parameter.
 pub fn input_type(role: Role, fn_def: &TypedFnDef) -> &'_ Type {
-    match role {
-        Role::Contributor => &fn_def.params[0].ty,
-        Role::Evaluator => &fn_def.params[1].ty,
+    match (role, template::constant_types(fn_def)) {
+        (Role::Contributor, Some(types)) => &types[0],
+        (Role::Contributor, None) => &fn_def.params[0].ty,
+        (Role::Evaluator, _) => &fn_def.params[1].ty,
     }
 }
 
@@ -113,6 +189,34 @@ pub fn parse_input(
     Literal::parse(prg, input_ty, input).map_err(|e| e.prettify(input))
 }
 
+/// Parses a Garble literal in its JSON representation and checks that it is of the specified type.
+///
+/// The JSON representation is the serde representation of [`Literal`], as produced by
+/// [`literal_to_json`].
+pub fn literal_from_json(prg: &TypedProgram, ty: &Type, json: &str) -> Result<Literal> {
+    let literal: Literal = serde_json::from_str(json).map_err(|e| format!("Invalid JSON: {e}"))?;
+    if literal.is_of_type(prg, ty) {
+        Ok(literal)
+    } else {
+        Err(format!("Literal is not of the type {ty}"))
+    }
+}
+
+/// Returns the JSON representation of a Garble literal, see [`literal_from_json`].
+pub fn literal_to_json(literal: &Literal) -> Result<String> {
+    serde_json::to_string(literal).map_err(|e| format!("{e}"))
+}
+
+/// Parses an input in the JSON representation of [`literal_from_json`] as a Garble literal.
+pub fn parse_json_input(
+    role: Role,
+    prg: &TypedProgram,
+    fn_def: &TypedFnDef,
+    input: &str,
+) -> Result<Literal> {
+    literal_from_json(prg, input_type(role, fn_def), input)
+}
+
 /// Parses an input string as a Garble literal and encodes it as input bits for the Tandem engine.
 pub fn serialize_input(
     role: Role,
@@ -120,8 +224,27 @@ pub fn serialize_input(
     fn_def: &TypedFnDef,
     input: &str,
 ) -> Result<Vec<bool>> {
-    let input_ty = input_type(role, fn_def);
-    let input = Literal::parse(prg, input_ty, input).map_err(|e| e.prettify(input))?;
+    match role {
+        Role::Contributor => serialize_input_with_constants(prg, fn_def, input, &HashMap::new()),
+        Role::Evaluator => {
+            let input = parse_input(role, prg, fn_def, input)?;
+            Ok(input.as_bits(prg))
+        }
+    }
+}
+
+/// Parses the contributor's input string and the literals of the server-side constants (by name)
+/// and encodes them as input bits for the Tandem engine.
+///
+/// Fails if any constant declared by the program is not bound or if a constant is not declared.
+pub fn serialize_input_with_constants(
+    prg: &TypedProgram,
+    fn_def: &TypedFnDef,
+    input: &str,
+    constants: &HashMap<String, String>,
+) -> Result<Vec<bool>> {
+    let input = parse_input(Role::Contributor, prg, fn_def, input)?;
+    let input = template::with_constants(prg, fn_def, input, constants)?;
     Ok(input.as_bits(prg))
 }
 
@@ -134,3 +257,51 @@ pub fn deserialize_output(
     let output_ty = &fn_def.ty;
     Literal::from_result_bits(prg, output_ty, output).map_err(|e| e.prettify(""))
 }
+
+#[test]
+fn test_json_literals() {
+    let prg = check_program(
+        "pub fn main(x: (u8, bool), y: [Op; 2]) -> u8 { x.0 }
+        enum Op { Add(u8), Nop }",
+    )
+    .unwrap();
+    let TypedCircuit { fn_def, .. } = compile_program(&prg, "main").unwrap();
+
+    let input = parse_input(Role::Evaluator, &prg, &fn_def, "[Op::Add(3u8), Op::Nop]").unwrap();
+    let json = literal_to_json(&input).unwrap();
+    assert_eq!(
+        parse_json_input(Role::Evaluator, &prg, &fn_def, &json),
+        Ok(input)
+    );
+    assert!(parse_json_input(Role::Contributor, &prg, &fn_def, &json).is_err());
+    assert!(parse_json_input(Role::Evaluator, &prg, &fn_def, "[1, 2]").is_err());
+
+    let json = r#"{"Tuple": [{"NumUnsigned": [7, "U8"]}, "True"]}"#;
+    let input = parse_json_input(Role::Contributor, &prg, &fn_def, json).unwrap();
+    assert_eq!(input.to_string(), "(7u8, true)");
+}
+
+#[test]
+fn test_garble_version() {
+    let requirement = format!("garble_lang = {{ version = \"={GARBLE_VERSION}\"");
+    assert!(include_str!("../Cargo.toml").contains(&requirement));
+}
+
+#[test]
+fn test_compile_all() {
+    let prg = check_program(
+        "pub fn add(x: u8, y: u8) -> u8 { inc(x) + y - 1u8 }
+        pub fn mul(x: u8, y: u8) -> u8 { sub(x * y, 0u8) }
+        pub fn inc(x: u8) -> u8 { x + 1u8 }
+        fn sub(x: u8, y: u8) -> u8 { x - y }",
+    )
+    .unwrap();
+    let circuits = compile_all(&prg).unwrap();
+    let mut fn_names: Vec<&String> = circuits.keys().collect();
+    fn_names.sort();
+    assert_eq!(fn_names, vec!["add", "mul"]);
+    assert_eq!(
+        circuits["add"].gates.gates(),
+        compile_program(&prg, "add").unwrap().gates.gates()
+    );
+}
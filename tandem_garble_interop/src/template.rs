@@ -0,0 +1,236 @@
+//! Programs with server-side constants, which are declared in the program but bound by the server.
+
+use std::collections::HashMap;
+
+use garble_lang::{
+    ast::{Expr, ExprEnum, Mutability, ParamDef, Pattern, PatternEnum, Stmt, StmtEnum, Type},
+    literal::Literal,
+    scan::scan,
+    CompileTimeError, TypedFnDef, TypedProgram, UntypedProgram,
+};
+
+use crate::Result;
+
+/// The name of the contributor's parameter of functions that use server-side constants, which
+/// receives the contributor's input together with the values of the constants.
+const CONSTANTS_PARAM: &str = "__tandem_constants";
+
+/// The prefix of the functions that the declarations of constants are parsed as.
+const DECLARATION_PREFIX: &str = "__tandem_const_";
+
+/// Scans, parses and type-checks a program, binding its declared constants in all public
+/// functions with 2 parameters.
+///
+/// Each declaration of the form `const NAME: Type;` (on a line of its own) becomes an additional
+/// input of the contributor, so that the circuit only depends on the names and types of the
+/// constants, but not on their values.
+pub(crate) fn check_template(program: &str) -> Result<TypedProgram> {
+    let mut constants = vec![];
+    let mut source = String::with_capacity(program.len());
+    for line in program.split_inclusive('\n') {
+        match parse_declaration(line) {
+            Some((name, _)) if constants.contains(&name) => {
+                return Err(format!("The constant `{name}` is declared more than once"));
+            }
+            // declarations are parsed as functions on the same line, to keep the error locations:
+            Some((name, ty)) => {
+                let indent = &line[..line.len() - line.trim_start().len()];
+                source += &format!(
+                    "{indent}fn {DECLARATION_PREFIX}{name}({name}: {ty}) -> bool {{ true }}\n"
+                );
+                constants.push(name);
+            }
+            None => source += line,
+        }
+    }
+    let checked = scan(&source)
+        .map_err(CompileTimeError::from)
+        .and_then(|tokens| tokens.parse().map_err(CompileTimeError::from))
+        .and_then(|mut prg| {
+            bind_constants(&mut prg, &constants);
+            prg.type_check().map_err(CompileTimeError::from)
+        });
+    checked.map_err(|e| e.prettify(&source))
+}
+
+/// Parses a line of the form `const NAME: Type;` as the name and the type of the constant.
+fn parse_declaration(line: &str) -> Option<(&str, &str)> {
+    let declaration = line.trim().strip_prefix("const ")?.strip_suffix(';')?;
+    let (name, ty) = declaration.split_once(':')?;
+    Some((name.trim(), ty.trim()))
+}
+
+/// Replaces the contributor's parameter of all public functions with 2 parameters by a tuple of
+/// the parameter and the constants, which are bound at the start of the function.
+fn bind_constants(prg: &mut UntypedProgram, constants: &[&str]) {
+    let mut types = Vec::with_capacity(constants.len());
+    for name in constants {
+        if let Some(declaration) = prg.fn_defs.remove(&format!("{DECLARATION_PREFIX}{name}")) {
+            types.extend(declaration.params.into_iter().map(|p| p.ty));
+        }
+    }
+    if types.is_empty() {
+        return;
+    }
+    for fn_def in prg.fn_defs.values_mut() {
+        if !fn_def.is_pub || fn_def.params.len() != 2 {
+            continue;
+        }
+        let meta = fn_def.meta;
+        let param = fn_def.params.remove(0);
+        let mut fields = vec![Pattern::untyped(
+            PatternEnum::Identifier(param.name.clone()),
+            meta,
+        )];
+        for name in constants {
+            let name = PatternEnum::Identifier(name.to_string());
+            fields.push(Pattern::untyped(name, meta));
+        }
+        let tuple = Expr::untyped(ExprEnum::Identifier(CONSTANTS_PARAM.to_string()), meta);
+        let binding = StmtEnum::Let(Pattern::untyped(PatternEnum::Tuple(fields), meta), tuple);
+        let mut body = vec![Stmt::new(binding, meta)];
+        if param.mutability == Mutability::Mutable {
+            let value = Expr::untyped(ExprEnum::Identifier(param.name.clone()), meta);
+            body.push(Stmt::new(StmtEnum::LetMut(param.name.clone(), value), meta));
+        }
+        body.append(&mut fn_def.body);
+        fn_def.body = body;
+        let mut fields = vec![param.ty];
+        fields.extend(types.iter().cloned());
+        let param = ParamDef {
+            mutability: Mutability::Immutable,
+            name: CONSTANTS_PARAM.to_string(),
+            ty: Type::Tuple(fields),
+        };
+        fn_def.params.insert(0, param);
+    }
+}
+
+/// Returns the names and types of the server-side constants used by the function, in the order of
+/// their declaration.
+///
+/// The values of the constants need to be supplied by the contributor together with its input,
+/// see [`serialize_input_with_constants`](crate::serialize_input_with_constants).
+pub fn program_constants(fn_def: &TypedFnDef) -> Vec<(&str, &Type)> {
+    let binding = fn_def.body.first().map(|stmt| &stmt.inner);
+    match (constant_types(fn_def), binding) {
+        (Some(types), Some(StmtEnum::Let(Pattern(PatternEnum::Tuple(fields), _, _), _))) => fields
+            .iter()
+            .zip(types)
+            .skip(1)
+            .filter_map(|(field, ty)| match &field.0 {
+                PatternEnum::Identifier(name) => Some((name.as_str(), ty)),
+                _ => None,
+            })
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// Returns the types of the contributor's input and the constants, if the function uses
+/// server-side constants.
+pub(crate) fn constant_types(fn_def: &TypedFnDef) -> Option<&[Type]> {
+    match fn_def.params.first() {
+        Some(ParamDef {
+            name,
+            ty: Type::Tuple(types),
+            ..
+        }) if name == CONSTANTS_PARAM => Some(types),
+        _ => None,
+    }
+}
+
+/// Combines the contributor's input with the literals of the server-side constants.
+pub(crate) fn with_constants(
+    prg: &TypedProgram,
+    fn_def: &TypedFnDef,
+    input: Literal,
+    constants: &HashMap<String, String>,
+) -> Result<Literal> {
+    let declared = program_constants(fn_def);
+    if let Some(name) = constants
+        .keys()
+        .find(|name| !declared.iter().any(|(n, _)| n == name))
+    {
+        return Err(format!("The function does not declare a constant `{name}`"));
+    }
+    if declared.is_empty() {
+        return Ok(input);
+    }
+    let mut fields = vec![input];
+    for (name, ty) in declared {
+        let literal = match constants.get(name) {
+            Some(literal) => literal,
+            None => return Err(format!("The server-side constant `{name}` is not bound")),
+        };
+        let literal = Literal::parse(prg, ty, literal)
+            .map_err(|e| format!("Invalid literal for `{name}`:\n{}", e.prettify(literal)))?;
+        fields.push(literal);
+    }
+    Ok(Literal::Tuple(fields))
+}
+
+#[test]
+fn test_program_constants() {
+    use crate::{
+        check_program, compile_program, deserialize_output, serialize_input,
+        serialize_input_with_constants, Role,
+    };
+    use garble_lang::token::UnsignedNumType;
+
+    let program = "const LIMIT: u32;
+const BONUS: u32;
+
+pub fn main(mut score: u32, applicant: u32) -> bool {
+    score = score + BONUS;
+    applicant + score > LIMIT
+}";
+    let prg = check_program(program).unwrap();
+    let circuit = compile_program(&prg, "main").unwrap();
+    let u32_type = Type::Unsigned(UnsignedNumType::U32);
+    assert_eq!(
+        program_constants(&circuit.fn_def),
+        vec![("LIMIT", &u32_type), ("BONUS", &u32_type)]
+    );
+    assert_eq!(
+        crate::input_type(Role::Contributor, &circuit.fn_def),
+        &u32_type
+    );
+
+    let run = |limit: &str| {
+        let constants = HashMap::from([
+            ("LIMIT".to_string(), limit.to_string()),
+            ("BONUS".to_string(), "5u32".to_string()),
+        ]);
+        let contributor =
+            serialize_input_with_constants(&prg, &circuit.fn_def, "10u32", &constants).unwrap();
+        let evaluator = serialize_input(Role::Evaluator, &prg, &circuit.fn_def, "20u32").unwrap();
+        let output = tandem::simulate(&circuit.gates, &contributor, &evaluator).unwrap();
+        deserialize_output(&prg, &circuit.fn_def, &output)
+            .unwrap()
+            .to_string()
+    };
+    // the values of the constants are not compiled into the circuit:
+    assert_eq!(run("34u32"), "true");
+    assert_eq!(run("35u32"), "false");
+
+    // all declared constants need to be bound, using valid literals:
+    let result = serialize_input(Role::Contributor, &prg, &circuit.fn_def, "10u32");
+    assert!(result.unwrap_err().contains("`LIMIT` is not bound"));
+    let constants = HashMap::from([
+        ("LIMIT".to_string(), "true".to_string()),
+        ("BONUS".to_string(), "5u32".to_string()),
+    ]);
+    assert!(serialize_input_with_constants(&prg, &circuit.fn_def, "10u32", &constants).is_err());
+    let constants = HashMap::from([("OTHER".to_string(), "5u32".to_string())]);
+    assert!(serialize_input_with_constants(&prg, &circuit.fn_def, "10u32", &constants).is_err());
+
+    // programs without constants are not changed:
+    let prg = check_program("pub fn main(x: u8, y: u8) -> u8 { x + y }").unwrap();
+    let circuit = compile_program(&prg, "main").unwrap();
+    assert!(program_constants(&circuit.fn_def).is_empty());
+    assert_eq!(circuit.fn_def.params[0].name, "x");
+    assert!(
+        check_program("const A: u8;\nconst A: u8;\npub fn main(x: u8, y: u8) -> u8 { A }").is_err()
+    );
+}
@@ -0,0 +1,112 @@
+use tandem_garble_interop::{
+    check_program, compile_program, input_type, FromGarble, Literal, Role, ToGarble, TypedCircuit,
+};
+
+const PROGRAM: &str = "
+pub fn main(order: Order, book: [Quote; 2]) -> (bool, Side) {
+    (order.amount > book[0].price, order.side)
+}
+
+struct Order {
+    side: Side,
+    amount: u32,
+}
+
+struct Quote {
+    price: u32,
+    urgent: bool,
+}
+
+enum Side {
+    Buy,
+    Sell(u8),
+    Limit(u32, bool),
+}
+";
+
+#[derive(Debug, Clone, PartialEq, ToGarble, FromGarble)]
+struct Order {
+    side: Side,
+    amount: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ToGarble, FromGarble)]
+struct Quote<T> {
+    urgent: bool,
+    price: T,
+}
+
+#[derive(Debug, Clone, PartialEq, ToGarble, FromGarble)]
+enum Side {
+    Buy,
+    Sell(u8),
+    Limit(u32, bool),
+}
+
+#[test]
+fn test_derive_round_trip() {
+    let order = Order {
+        side: Side::Limit(10, true),
+        amount: 5,
+    };
+    let literal = order.to_garble();
+    assert_eq!(
+        literal.to_string(),
+        "Order {side: Side::Limit(10u32, true), amount: 5u32}"
+    );
+    assert_eq!(Order::from_garble(&literal), Ok(order));
+
+    let book = [
+        Quote {
+            urgent: false,
+            price: 3u32,
+        },
+        Quote {
+            urgent: true,
+            price: 4,
+        },
+    ];
+    assert_eq!(<[Quote<u32>; 2]>::from_garble(&book.to_garble()), Ok(book));
+
+    for side in [Side::Buy, Side::Sell(7), Side::Limit(1, false)] {
+        assert_eq!(Side::from_garble(&side.to_garble()), Ok(side));
+    }
+}
+
+#[test]
+fn test_derive_type_check() {
+    let prg = check_program(PROGRAM).unwrap();
+    let TypedCircuit { fn_def, .. } = compile_program(&prg, "main").unwrap();
+
+    let order = Order {
+        side: Side::Sell(2),
+        amount: 5,
+    };
+    let ty = input_type(Role::Contributor, &fn_def);
+    let literal = order.to_garble_checked(&prg, ty).unwrap();
+    let parsed = Literal::parse(&prg, ty, "Order {side: Side::Sell(2u8), amount: 5u32}").unwrap();
+    assert_eq!(literal.as_bits(&prg), parsed.as_bits(&prg));
+
+    // the fields are encoded in the order of the Garble struct, not the order of the Rust struct:
+    let book = [Quote {
+        urgent: true,
+        price: 1u32,
+    }; 2];
+    let ty = input_type(Role::Evaluator, &fn_def);
+    let literal = book.to_garble_checked(&prg, ty).unwrap();
+    let parsed = Literal::parse(&prg, ty, "[Quote {price: 1u32, urgent: true}; 2]").unwrap();
+    assert_eq!(literal.as_bits(&prg), parsed.as_bits(&prg));
+    assert_ne!(book.to_garble().as_bits(&prg), parsed.as_bits(&prg));
+    assert_eq!(<[Quote<u32>; 2]>::from_garble(&parsed), Ok(book));
+
+    let wrong_price = [Quote {
+        urgent: true,
+        price: 1u8,
+    }; 2];
+    assert!(wrong_price.to_garble_checked(&prg, ty).is_err());
+
+    let output = Literal::parse(&prg, &fn_def.ty, "(true, Side::Buy)").unwrap();
+    assert_eq!(<(bool, Side)>::from_garble(&output), Ok((true, Side::Buy)));
+    assert!(<(bool, Order)>::from_garble(&output).is_err());
+    assert!(Side::from_garble(&parsed).is_err());
+}
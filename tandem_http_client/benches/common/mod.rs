@@ -31,7 +31,7 @@ where
 fn start_server(path: &str) -> Result<(Child, String), Box<dyn std::error::Error>> {
     let port: u16 = thread_rng().gen_range(8001..=9000);
     let port_str = port.to_string();
-    let mut cmd = Command::cargo_bin(&SERVER_CRATE)?;
+    let mut cmd = Command::cargo_bin(SERVER_CRATE)?;
     let mut proc = cmd
         .current_dir(path)
         .env("ROCKET_PORT", port_str)
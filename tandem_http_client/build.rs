@@ -0,0 +1,10 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(feature = "grpc")]
+    tonic_build::configure()
+        .build_server(false)
+        .compile_protos(&["proto/tandem.proto"], &["proto"])?;
+    #[cfg(feature = "ffi")]
+    cbindgen::generate(std::env::var("CARGO_MANIFEST_DIR")?)?
+        .write_to_file("include/tandem_http_client.h");
+    Ok(())
+}
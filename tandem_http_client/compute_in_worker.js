@@ -0,0 +1,83 @@
+// Runs Tandem computations in a Web Worker (see `worker.js`), so that neither the compilation of
+// the program nor the evaluation of the circuit blocks the main thread.
+
+/**
+ * Computes the function of the Garble program in a new Web Worker, which is terminated once the
+ * computation has finished.
+ *
+ * The input is either a Garble literal string or an `ArrayBuffer` returned by
+ * `MpcData.toBytes()`, which is transferred to the worker (and thus detached). Aborting the
+ * `signal` aborts the computation before its next request to the server.
+ *
+ * @param {object} request
+ * @param {string} request.source The source code of the Garble program.
+ * @param {string} request.function The name of the function to compute.
+ * @param {string | ArrayBuffer} request.input The input of the client.
+ * @param {string} request.url The url of the Tandem server.
+ * @param {string | unknown} request.metadata Plaintext metadata sent to the server.
+ * @param {Record<string, string>} [request.headers] Headers sent with every request.
+ * @param {number} [request.timeout] Timeout of the whole computation in milliseconds.
+ * @param {(progress: {step: number, steps: number}) => void} [request.onProgress]
+ * @param {AbortSignal} [request.signal]
+ * @param {string | URL} [request.workerUrl] The url of `worker.js`, if it was moved.
+ * @returns {Promise<{literal: string, output: unknown, bytes: ArrayBuffer}>} The output as a
+ *   Garble literal string, as a plain value and as the bytes of `MpcData.toBytes()`.
+ */
+export function computeInWorker({
+  source,
+  function: functionName,
+  input,
+  url,
+  metadata,
+  headers,
+  timeout,
+  onProgress,
+  signal,
+  workerUrl = new URL("./worker.js", import.meta.url),
+}) {
+  const worker = new Worker(workerUrl, { type: "module" });
+  return new Promise((resolve, reject) => {
+    const abort = () => worker.postMessage({ type: "abort" });
+    const done = () => {
+      signal?.removeEventListener("abort", abort);
+      worker.terminate();
+    };
+    worker.onmessage = ({ data }) => {
+      switch (data.type) {
+        case "progress":
+          onProgress?.({ step: data.step, steps: data.steps });
+          break;
+        case "result":
+          done();
+          resolve({ literal: data.literal, output: data.output, bytes: data.bytes });
+          break;
+        case "error":
+          done();
+          reject(new Error(data.message));
+          break;
+      }
+    };
+    worker.onerror = (e) => {
+      done();
+      reject(new Error(e.message));
+    };
+    const transfer = input instanceof ArrayBuffer ? [input] : [];
+    worker.postMessage(
+      {
+        type: "compute",
+        source,
+        function: functionName,
+        input,
+        url,
+        metadata,
+        headers,
+        timeout,
+      },
+      transfer,
+    );
+    signal?.addEventListener("abort", abort);
+    if (signal?.aborted) {
+      abort();
+    }
+  });
+}
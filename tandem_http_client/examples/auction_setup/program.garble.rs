@@ -0,0 +1,38 @@
+pub fn auction(reserve_price: u32, bids: [u32; 4]) -> (BidderOutcome, SellerOutcome) {
+    let mut highest = 0u32;
+    let mut second_highest = 0u32;
+    let mut winner = 0u8;
+    let mut i = 0u8;
+    for bid in bids {
+        if bid > highest {
+            second_highest = highest;
+            highest = bid;
+            winner = i;
+        } else {
+            if bid > second_highest {
+                second_highest = bid;
+            }
+        }
+        i = i + 1u8;
+    }
+    if highest > 0u32 && highest >= reserve_price {
+        let price = if second_highest > reserve_price {
+            second_highest
+        } else {
+            reserve_price
+        };
+        (BidderOutcome::Sold(winner, price), SellerOutcome::Sold(price))
+    } else {
+        (BidderOutcome::NotSold, SellerOutcome::NotSold)
+    }
+}
+
+enum BidderOutcome {
+    NotSold,
+    Sold(u8, u32),
+}
+
+enum SellerOutcome {
+    NotSold,
+    Sold(u32),
+}
@@ -0,0 +1,52 @@
+//! Runs a sealed-bid auction against a Tandem server acting as the auctioneer.
+//!
+//! Start the server with the configuration in `examples/auction_setup`:
+//!
+//! ```sh
+//! cd tandem_http_client/examples/auction_setup
+//! cargo run -p tandem_http_server --features=bin
+//! ```
+//!
+//! Then run the auction for the lot `lot1` (with a reserve price of 100) using 3 bids:
+//!
+//! ```sh
+//! cargo run -p tandem_http_client --features=auction --example sealed_bid_auction -- \
+//!     http://127.0.0.1:8000 lot1 80 150 120
+//! ```
+
+#[cfg(not(target_arch = "wasm32"))]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    use tandem_http_client::auction::{run_auction, BidderOutcome, SellerOutcome};
+
+    let mut args = std::env::args().skip(1);
+    let usage = "usage: sealed_bid_auction <url> <lot> <bid>...";
+    let url = args.next().ok_or(usage)?;
+    let lot = args.next().ok_or(usage)?;
+    let bids = args
+        .map(|bid| bid.parse())
+        .collect::<Result<Vec<u32>, _>>()?;
+
+    let result = run_auction(url, lot, &bids).await?;
+    for (i, receipt) in result.receipts.iter().enumerate() {
+        let commitment: String = receipt
+            .commitment
+            .0
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        println!("Bid #{i}: commitment {commitment}");
+    }
+    match result.bidder_outcome {
+        BidderOutcome::NotSold => println!("No bid reached the reserve price"),
+        BidderOutcome::Sold { winner, price } => println!("Bid #{winner} wins, paying {price}"),
+    }
+    match result.seller_outcome {
+        SellerOutcome::NotSold => println!("Disclosed to the seller: not sold"),
+        SellerOutcome::Sold(price) => println!("Disclosed to the seller: sold for {price}"),
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn main() {}
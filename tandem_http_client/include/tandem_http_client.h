@@ -0,0 +1,85 @@
+#ifndef TANDEM_HTTP_CLIENT_H
+#define TANDEM_HTTP_CLIENT_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit. */
+
+#include <stdlib.h>
+
+// Stores data (either inputs or output) in an Tandem-compatible format.
+typedef struct MpcData MpcData;
+
+// An MPC program that was type-checked and can be executed by the Tandem engine.
+typedef struct MpcProgram MpcProgram;
+
+// Type-checks and compiles the function of the Garble program.
+//
+// # Safety
+//
+// `source_code` and `function_name` must be valid NUL-terminated strings, `error` must either be
+// `NULL` or point to a `char *` that can be written.
+struct MpcProgram *tandem_program_new(const char *source_code,
+                                      const char *function_name,
+                                      char **error);
+
+// Releases the program.
+//
+// # Safety
+//
+// `program` must either be `NULL` or a program returned by [`tandem_program_new`] that has not
+// been released yet.
+void tandem_program_free(struct MpcProgram *program);
+
+// Parses and type-checks a Garble literal as the input of the program.
+//
+// # Safety
+//
+// `program` must be a valid program, `literal` a valid NUL-terminated string and `error` must
+// either be `NULL` or point to a `char *` that can be written.
+struct MpcData *tandem_data_from_string(const struct MpcProgram *program,
+                                        const char *literal,
+                                        char **error);
+
+// Returns the data as a Garble literal, which must be released using [`tandem_string_free`], or
+// `NULL` if `data` is `NULL`.
+//
+// # Safety
+//
+// `data` must be valid data returned by this API that has not been released yet.
+char *tandem_data_to_string(const struct MpcData *data);
+
+// Releases the data.
+//
+// # Safety
+//
+// `data` must either be `NULL` or data returned by this API that has not been released yet.
+void tandem_data_free(struct MpcData *data);
+
+// Computes the program with the Tandem server at the url like `compute`, blocking the calling
+// thread until the output is known.
+//
+// The program and the input are not consumed and can be reused by later computations.
+//
+// # Safety
+//
+// `url` and `plaintext_metadata` must be valid NUL-terminated strings, `program` and `input` must
+// be valid handles and `error` must either be `NULL` or point to a `char *` that can be written.
+struct MpcData *tandem_compute(const char *url,
+                               const char *plaintext_metadata,
+                               const struct MpcProgram *program,
+                               const struct MpcData *input,
+                               char **error);
+
+// Releases a string returned by this API.
+//
+// # Safety
+//
+// `string` must either be `NULL` or a string returned by this API that has not been released yet.
+void tandem_string_free(char *string);
+
+extern Promise idb_get(const str *key);
+
+extern Promise idb_put(const str *key, Uint8Array bytes);
+
+extern Promise idb_clear(void);
+
+#endif /* TANDEM_HTTP_CLIENT_H */
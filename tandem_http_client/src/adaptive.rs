@@ -0,0 +1,145 @@
+//! Adaptive sizing of dialog batches based on the measured round trip time and throughput.
+
+use std::time::Duration;
+
+use crate::Capabilities;
+
+/// Name of the server limit (see [`Capabilities::limits`]) bounding the size of a dialog batch.
+pub const MAX_BATCH_BYTES_LIMIT: &str = "max_batch_bytes";
+
+/// Adjusts the number of bytes sent per dialog round in an AIMD (additive increase,
+/// multiplicative decrease) fashion.
+///
+/// While the round trip time stays close to the fastest observed round trip, the batch size grows
+/// by a constant step after each round, unless the last increase did not improve the throughput
+/// (indicating that the link is saturated). As soon as the round trip time indicates queueing on
+/// the link, the batch size is halved. The batch size always stays within the configured bounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchSizeController {
+    min: usize,
+    max: usize,
+    size: usize,
+    min_rtt: Option<Duration>,
+    /// Bytes and throughput (in bytes/sec) of the last round.
+    last_round: Option<(usize, f64)>,
+}
+
+impl Default for BatchSizeController {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MIN, Self::DEFAULT_MAX)
+    }
+}
+
+impl BatchSizeController {
+    /// The default lower bound of the batch size, in bytes.
+    pub const DEFAULT_MIN: usize = 16 * 1024;
+    /// The default upper bound of the batch size, in bytes.
+    pub const DEFAULT_MAX: usize = 4 * 1024 * 1024;
+
+    /// Creates a controller with batches of at least `min` and at most `max` bytes.
+    ///
+    /// The controller starts with the smallest batch size, which also serves as the step size of
+    /// the additive increase.
+    pub fn new(min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        Self {
+            min,
+            max: max.max(min),
+            size: min,
+            min_rtt: None,
+            last_round: None,
+        }
+    }
+
+    /// Restricts the batch size to the limit advertised by the server, if there is one.
+    pub fn bounded_by(mut self, capabilities: &Capabilities) -> Self {
+        if let Some(&limit) = capabilities.limits.get(MAX_BATCH_BYTES_LIMIT) {
+            let limit = usize::try_from(limit).unwrap_or(usize::MAX);
+            self.max = self.max.min(limit).max(1);
+            self.min = self.min.min(self.max);
+            self.size = self.size.clamp(self.min, self.max);
+        }
+        self
+    }
+
+    /// The number of bytes that should be sent in the next dialog round.
+    pub fn batch_size(&self) -> usize {
+        self.size
+    }
+
+    /// Adjusts the batch size after a dialog round that transferred `bytes` in the specified time.
+    pub fn observe(&mut self, bytes: usize, rtt: Duration) {
+        let min_rtt = match self.min_rtt {
+            Some(min_rtt) if min_rtt <= rtt => min_rtt,
+            _ => rtt,
+        };
+        self.min_rtt = Some(min_rtt);
+        let throughput = bytes as f64 / rtt.as_secs_f64().max(f64::EPSILON);
+        let saturated = match self.last_round {
+            Some((last_bytes, last_throughput)) => {
+                bytes > last_bytes && throughput <= last_throughput
+            }
+            None => false,
+        };
+        self.last_round = Some((bytes, throughput));
+
+        self.size = if rtt > min_rtt * 2 {
+            self.size / 2
+        } else if saturated {
+            self.size
+        } else {
+            self.size.saturating_add(self.min)
+        }
+        .clamp(self.min, self.max);
+    }
+}
+
+#[test]
+fn test_batch_size_controller() {
+    let ms = Duration::from_millis;
+    let mut c = BatchSizeController::new(10, 45);
+    assert_eq!(c.batch_size(), 10);
+
+    // additive increase while the link is fast:
+    c.observe(10, ms(10));
+    assert_eq!(c.batch_size(), 20);
+    c.observe(20, ms(12));
+    assert_eq!(c.batch_size(), 30);
+    c.observe(30, ms(15));
+    assert_eq!(c.batch_size(), 40);
+    c.observe(40, ms(18));
+    assert_eq!(c.batch_size(), 45);
+
+    // multiplicative decrease as soon as the round trip time indicates queueing:
+    c.observe(45, ms(50));
+    assert_eq!(c.batch_size(), 22);
+    c.observe(22, ms(100));
+    assert_eq!(c.batch_size(), 11);
+    c.observe(11, ms(100));
+    assert_eq!(c.batch_size(), 10);
+
+    // recovery once the link is fast again:
+    c.observe(10, ms(10));
+    assert_eq!(c.batch_size(), 20);
+
+    // no further increase if a larger batch does not improve the throughput:
+    c.observe(20, ms(20));
+    assert_eq!(c.batch_size(), 20);
+    c.observe(20, ms(10));
+    assert_eq!(c.batch_size(), 30);
+}
+
+#[test]
+fn test_batch_size_bounded_by_server_limit() {
+    let mut capabilities = Capabilities::default();
+    let c = BatchSizeController::new(10, 100).bounded_by(&capabilities);
+    assert_eq!(c, BatchSizeController::new(10, 100));
+
+    capabilities
+        .limits
+        .insert(MAX_BATCH_BYTES_LIMIT.to_string(), 5);
+    let mut c = BatchSizeController::new(10, 100).bounded_by(&capabilities);
+    assert_eq!(c.batch_size(), 5);
+    c.observe(5, Duration::from_millis(1));
+    assert_eq!(c.batch_size(), 5);
+}
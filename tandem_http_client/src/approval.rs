@@ -0,0 +1,165 @@
+//! Helper API for servers that require sessions to be approved before they can be created.
+//!
+//! The approval happens in two phases:
+//!
+//!   1. The client submits the hash of the program, the function, the plaintext metadata and a
+//!      commitment to its input using [`request_approval`], which is stored as pending by the
+//!      server.
+//!   2. Once the request was approved by an admin or an approval service (using the token
+//!      configured on the server), the client runs the computation using [`compute_approved`].
+//!
+//! The input commitment never reveals the input to the server, but allows the client to prove
+//! later which input was used, by opening the commitment with the nonce of the
+//! [`ApprovalReceipt`].
+
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaCha20Rng,
+};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    compute_session, resp_or_err, timeouts, ComputeOptions, Error, MpcData, MpcProgram, Transport,
+};
+
+/// A commitment to the input of the client, which hides the input until it is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct InputCommitment(pub [u8; 32]);
+
+impl InputCommitment {
+    /// Commits to the input bits using the specified random nonce.
+    pub fn new(input: &[bool], nonce: &[u8; 32]) -> Self {
+        let mut hasher = blake3::Hasher::new_derive_key("tandem input commitment");
+        hasher.update(&(input.len() as u64).to_le_bytes());
+        for byte in input.chunks(8).map(|bits| {
+            bits.iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, &bit)| byte | ((bit as u8) << i))
+        }) {
+            hasher.update(&[byte]);
+        }
+        hasher.update(nonce);
+        Self(*hasher.finalize().as_bytes())
+    }
+
+    /// Returns `true` if the commitment was created for the input and the nonce.
+    pub fn verify(&self, input: &[bool], nonce: &[u8; 32]) -> bool {
+        Self::new(input, nonce) == *self
+    }
+}
+
+/// The state of an [`Approval`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApprovalStatus {
+    /// The request has neither been approved nor rejected yet.
+    Pending,
+    /// A session can be created for the request (once).
+    Approved,
+    /// The request was rejected, no session can be created for it.
+    Rejected,
+}
+
+/// A request to run a computation, as stored by the server.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Approval {
+    /// The id that needs to be passed to [`compute_approved`].
+    pub approval_id: String,
+    /// Whether the request was approved.
+    pub status: ApprovalStatus,
+    /// The blake3 hash of the program source code.
+    pub program_hash: [u8; 32],
+    /// The name of the function to be computed.
+    pub function: String,
+    /// The plaintext metadata that will be sent to the server.
+    pub plaintext_metadata: String,
+    /// The commitment to the input of the client.
+    pub input_commitment: InputCommitment,
+}
+
+/// The result of [`request_approval`], including the opening of the input commitment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApprovalReceipt {
+    /// The pending approval, as stored by the server.
+    pub approval: Approval,
+    /// The nonce that was used to create the input commitment.
+    pub nonce: [u8; 32],
+}
+
+#[derive(Serialize)]
+struct NewApproval<'a> {
+    program_hash: [u8; 32],
+    function: &'a str,
+    plaintext_metadata: &'a str,
+    input_commitment: InputCommitment,
+}
+
+/// Submits a request to compute the program with the input, to be approved by the server at the
+/// specified url.
+pub async fn request_approval(
+    url: &Url,
+    plaintext_metadata: &str,
+    program: &MpcProgram,
+    input: &MpcData,
+) -> Result<ApprovalReceipt, Error> {
+    let mut nonce = [0; 32];
+    ChaCha20Rng::from_entropy().fill_bytes(&mut nonce);
+    let input = input.literal.as_bits(&program.ast);
+    let request = NewApproval {
+        program_hash: *blake3::hash(program.source_code.as_bytes()).as_bytes(),
+        function: &program.function_name,
+        plaintext_metadata,
+        input_commitment: InputCommitment::new(&input, &nonce),
+    };
+    let resp = reqwest::Client::new()
+        .post(url.join("approvals")?)
+        .json(&request)
+        .send()
+        .await?;
+    let approval = resp_or_err(resp).await?.json::<Approval>().await?;
+    Ok(ApprovalReceipt { approval, nonce })
+}
+
+/// Returns the current state of the approval with the specified id.
+pub async fn approval_status(url: &Url, approval_id: &str) -> Result<Approval, Error> {
+    let url = url.join("approvals/")?.join(approval_id)?;
+    let resp = reqwest::Client::new().get(url).send().await?;
+    Ok(resp_or_err(resp).await?.json::<Approval>().await?)
+}
+
+/// Computes the program like [`crate::compute`], in a session that was approved by the server.
+///
+/// The plaintext metadata, the program and the function must match the approved request.
+pub async fn compute_approved(
+    url: String,
+    approval_id: String,
+    plaintext_metadata: String,
+    program: MpcProgram,
+    input: MpcData,
+) -> Result<MpcData, Error> {
+    let url = Url::parse(&url)?;
+    let approval_id = Some(approval_id);
+    let options = ComputeOptions::default();
+    compute_session(
+        &Transport::Http(timeouts::client(&options)?),
+        url,
+        &options,
+        plaintext_metadata.into(),
+        program,
+        input,
+        approval_id,
+        &(),
+    )
+    .await
+}
+
+#[test]
+fn test_input_commitment() {
+    let input = [true, false, true, true, false, false, false, false, true];
+    let nonce = [1; 32];
+    let commitment = InputCommitment::new(&input, &nonce);
+    assert!(commitment.verify(&input, &nonce));
+    assert!(!commitment.verify(&input[..8], &nonce));
+    assert!(!commitment.verify(&[false; 9], &nonce));
+    assert!(!commitment.verify(&input, &[2; 32]));
+}
@@ -0,0 +1,202 @@
+//! Helper API for sealed-bid auctions, see `examples/sealed_bid_auction.rs`.
+//!
+//! The server acts as the auctioneer and contributes the confidential reserve price of a lot, the
+//! client collects the sealed bids and learns the outcome of a second-price auction. The output
+//! of the program consists of two parts:
+//!
+//!   - the [`BidderOutcome`], which reveals the winning bid and the price to the client
+//!   - the [`SellerOutcome`], which only reveals the price and is meant to be disclosed to the
+//!     seller, who thus learns neither the bids nor the identity of the winner
+//!
+//! Before the computation, the client commits to each bid (see [`BidCommitment`]). The
+//! commitments can be published without revealing the bids, so that each bidder can later prove
+//! which bid was submitted by opening its commitment.
+
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaCha20Rng,
+};
+use tandem_garble_interop::{Literal, VariantLiteral};
+
+use crate::{compute, Error, MpcData, MpcProgram, ValidationError};
+
+/// The Garble program computing the auction, as deployed on the server.
+pub const AUCTION_PROGRAM: &str = include_str!("../examples/auction_setup/program.garble.rs");
+
+/// The name of the function in [`AUCTION_PROGRAM`] that computes the auction.
+pub const AUCTION_FUNCTION: &str = "auction";
+
+/// The maximum number of bids per auction, unused slots are filled with bids of `0`.
+pub const MAX_BIDS: usize = 4;
+
+/// A commitment to a single bid, which hides the bid until it is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BidCommitment(pub [u8; 32]);
+
+impl BidCommitment {
+    /// Commits to the bid using the specified random nonce.
+    pub fn new(bid: u32, nonce: &[u8; 32]) -> Self {
+        let mut hasher = blake3::Hasher::new_derive_key("tandem sealed-bid auction commitment");
+        hasher.update(&bid.to_le_bytes());
+        hasher.update(nonce);
+        Self(*hasher.finalize().as_bytes())
+    }
+
+    /// Returns `true` if the commitment was created for the bid and the nonce.
+    pub fn verify(&self, bid: u32, nonce: &[u8; 32]) -> bool {
+        Self::new(bid, nonce) == *self
+    }
+}
+
+/// The opening of a [`BidCommitment`], to be handed to the bidder.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BidReceipt {
+    /// The bid that was submitted.
+    pub bid: u32,
+    /// The nonce that was used to create the commitment.
+    pub nonce: [u8; 32],
+    /// The commitment that can be published before the auction.
+    pub commitment: BidCommitment,
+}
+
+/// The outcome of the auction, as learned by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BidderOutcome {
+    /// No bid reached the reserve price.
+    NotSold,
+    /// The bid at the specified index won and pays the specified price.
+    Sold {
+        /// Index of the winning bid, in the order passed to [`run_auction`].
+        winner: usize,
+        /// The price paid, i.e. the second-highest bid or the reserve price, whichever is higher.
+        price: u32,
+    },
+}
+
+/// The outcome of the auction that is disclosed to the seller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SellerOutcome {
+    /// No bid reached the reserve price.
+    NotSold,
+    /// The lot was sold for the specified price.
+    Sold(u32),
+}
+
+/// The result of [`run_auction`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuctionResult {
+    /// The outcome of the auction for the bidders.
+    pub bidder_outcome: BidderOutcome,
+    /// The outcome of the auction for the seller.
+    pub seller_outcome: SellerOutcome,
+    /// The openings of the commitments to each bid, in the order of the bids.
+    pub receipts: Vec<BidReceipt>,
+}
+
+impl AuctionResult {
+    /// Returns the commitments to all bids, which can be published without revealing the bids.
+    pub fn commitments(&self) -> Vec<BidCommitment> {
+        self.receipts.iter().map(|r| r.commitment).collect()
+    }
+}
+
+/// Runs a sealed-bid second-price auction for the specified lot, keeping the bids private.
+///
+/// A Tandem server configured with the `examples/auction_setup` must be running at the specified
+/// url, providing the reserve price of the lot.
+pub async fn run_auction(url: String, lot: String, bids: &[u32]) -> Result<AuctionResult, Error> {
+    if bids.is_empty() || bids.len() > MAX_BIDS {
+        return Err(ValidationError::InvalidInput.into());
+    }
+    let mut rng = ChaCha20Rng::from_entropy();
+    let receipts: Vec<BidReceipt> = bids
+        .iter()
+        .map(|&bid| {
+            let mut nonce = [0; 32];
+            rng.fill_bytes(&mut nonce);
+            BidReceipt {
+                bid,
+                nonce,
+                commitment: BidCommitment::new(bid, &nonce),
+            }
+        })
+        .collect();
+
+    let program = MpcProgram::new(AUCTION_PROGRAM.to_string(), AUCTION_FUNCTION.to_string())?;
+    let mut slots = [0; MAX_BIDS];
+    slots[..bids.len()].copy_from_slice(bids);
+    let input: Vec<String> = slots.iter().map(|bid| format!("{bid}u32")).collect();
+    let input = MpcData::from_string(&program, format!("[{}]", input.join(", ")))?;
+
+    let output = compute(url, lot, program, input).await?;
+    let (bidder_outcome, seller_outcome) = parse_outcome(&output.literal)
+        .filter(|(bidder, _)| match bidder {
+            BidderOutcome::Sold { winner, .. } => *winner < bids.len(),
+            BidderOutcome::NotSold => true,
+        })
+        .ok_or_else(|| {
+            ValidationError::GarbleCompileTimeError(format!(
+                "Unexpected output of the auction program: {}",
+                output.literal
+            ))
+        })?;
+    Ok(AuctionResult {
+        bidder_outcome,
+        seller_outcome,
+        receipts,
+    })
+}
+
+fn parse_outcome(literal: &Literal) -> Option<(BidderOutcome, SellerOutcome)> {
+    let (bidder, seller) = match literal {
+        Literal::Tuple(fields) if fields.len() == 2 => (&fields[0], &fields[1]),
+        _ => return None,
+    };
+    let bidder = match bidder {
+        Literal::Enum(_, variant, VariantLiteral::Unit) if variant == "NotSold" => {
+            BidderOutcome::NotSold
+        }
+        Literal::Enum(_, variant, VariantLiteral::Tuple(fields)) if variant == "Sold" => {
+            match fields.as_slice() {
+                [Literal::NumUnsigned(winner, _), Literal::NumUnsigned(price, _)] => {
+                    BidderOutcome::Sold {
+                        winner: *winner as usize,
+                        price: *price as u32,
+                    }
+                }
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    let seller = match seller {
+        Literal::Enum(_, variant, VariantLiteral::Unit) if variant == "NotSold" => {
+            SellerOutcome::NotSold
+        }
+        Literal::Enum(_, variant, VariantLiteral::Tuple(fields)) if variant == "Sold" => {
+            match fields.as_slice() {
+                [Literal::NumUnsigned(price, _)] => SellerOutcome::Sold(*price as u32),
+                _ => return None,
+            }
+        }
+        _ => return None,
+    };
+    Some((bidder, seller))
+}
+
+#[test]
+fn test_bid_commitment() {
+    let nonce = [7; 32];
+    let commitment = BidCommitment::new(42, &nonce);
+    assert!(commitment.verify(42, &nonce));
+    assert!(!commitment.verify(43, &nonce));
+    assert!(!commitment.verify(42, &[8; 32]));
+}
+
+#[test]
+fn test_auction_program() {
+    let program =
+        MpcProgram::new(AUCTION_PROGRAM.to_string(), AUCTION_FUNCTION.to_string()).unwrap();
+    let input = MpcData::from_string(&program, "[30u32, 0u32, 0u32, 0u32]".to_string());
+    assert!(input.is_ok());
+}
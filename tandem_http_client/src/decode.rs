@@ -0,0 +1,353 @@
+//! Decoding of Garble literals into Rust values using serde.
+
+use std::fmt;
+
+use serde::{
+    de::{
+        self,
+        value::{MapDeserializer, SeqDeserializer, StrDeserializer},
+        DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, Unexpected, VariantAccess,
+        Visitor,
+    },
+    forward_to_deserialize_any, Deserializer,
+};
+use tandem_garble_interop::{Literal, VariantLiteral};
+
+use crate::{Error, MpcData};
+
+impl MpcData {
+    /// Decodes the Garble literal into any type implementing [`serde::Deserialize`].
+    ///
+    /// Booleans and numbers are decoded as Rust booleans and numbers, arrays and tuples as
+    /// sequences, structs as maps of their fields and enums as enums with unit or tuple variants
+    /// of the same name. The names of structs and enums are not compared to the Rust types.
+    /// ```
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Debug, PartialEq, Deserialize)]
+    /// struct Card {
+    ///     suit: Suit,
+    ///     value: u8,
+    /// }
+    ///
+    /// #[derive(Debug, PartialEq, Deserialize)]
+    /// enum Suit {
+    ///     Diamonds,
+    ///     Clubs,
+    /// }
+    ///
+    /// let source_code = "pub fn higher(house: Card, player: Card) -> bool {
+    ///     house.value < player.value
+    /// }
+    ///
+    /// struct Card {
+    ///     suit: Suit,
+    ///     value: u8,
+    /// }
+    ///
+    /// enum Suit {
+    ///     Diamonds,
+    ///     Clubs,
+    /// }";
+    ///
+    /// let program =
+    ///     tandem_http_client::MpcProgram::new(source_code.to_string(), "higher".to_string()).unwrap();
+    ///
+    /// let card = "Card {suit: Suit::Clubs, value: 7u8}";
+    /// let card = tandem_http_client::MpcData::from_string(&program, card.to_string()).unwrap();
+    ///
+    /// assert_eq!(
+    ///     card.decode::<Card>().unwrap(),
+    ///     Card {
+    ///         suit: Suit::Clubs,
+    ///         value: 7
+    ///     }
+    /// );
+    /// assert!(card.decode::<bool>().is_err());
+    /// ```
+    pub fn decode<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        T::deserialize(LiteralDeserializer(&self.literal)).map_err(|e| Error::DecodeError(e.0))
+    }
+}
+
+/// An error that occurred while decoding a literal, see [`MpcData::decode`].
+#[derive(Debug)]
+struct DecodeError(String);
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl de::Error for DecodeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+#[derive(Clone, Copy)]
+struct LiteralDeserializer<'de>(&'de Literal);
+
+impl<'de> IntoDeserializer<'de, DecodeError> for LiteralDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+fn visit_seq<'de, V: Visitor<'de>, T: IntoDeserializer<'de, DecodeError>>(
+    elems: impl Iterator<Item = T>,
+    visitor: V,
+) -> Result<V::Value, DecodeError> {
+    let mut seq = SeqDeserializer::new(elems);
+    let value = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(value)
+}
+
+impl<'de> Deserializer<'de> for LiteralDeserializer<'de> {
+    type Error = DecodeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DecodeError> {
+        match self.0 {
+            Literal::True => visitor.visit_bool(true),
+            Literal::False => visitor.visit_bool(false),
+            Literal::NumUnsigned(n, _) => visitor.visit_u64(*n),
+            Literal::NumSigned(n, _) => visitor.visit_i64(*n),
+            Literal::Array(elems) => visit_seq(elems.iter().map(LiteralDeserializer), visitor),
+            Literal::ArrayRepeat(elem, size) => {
+                visit_seq((0..*size).map(|_| LiteralDeserializer(elem)), visitor)
+            }
+            Literal::Range((min, _), (max, _)) => visit_seq(*min..*max, visitor),
+            Literal::Tuple(fields) if fields.is_empty() => visitor.visit_unit(),
+            Literal::Tuple(fields) => visit_seq(fields.iter().map(LiteralDeserializer), visitor),
+            Literal::Struct(_, fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), LiteralDeserializer(value)));
+                let mut map = MapDeserializer::new(fields);
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+            // without a hint, enums are represented like in JSON, as the name of a unit variant or
+            // as a map from the name of a tuple variant to its fields:
+            Literal::Enum(_, variant, VariantLiteral::Unit) => visitor.visit_str(variant),
+            Literal::Enum(_, variant, VariantLiteral::Tuple(fields)) => {
+                let entry = (variant.as_str(), FieldsDeserializer(fields));
+                let mut map = MapDeserializer::new(std::iter::once(entry));
+                let value = visitor.visit_map(&mut map)?;
+                map.end()?;
+                Ok(value)
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DecodeError> {
+        // Garble has no optional values, every literal is present:
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        match self.0 {
+            Literal::Enum(_, variant, fields) => {
+                visitor.visit_enum(EnumDeserializer { variant, fields })
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// The fields of a tuple variant, which are represented like a newtype variant if there is only a
+/// single field.
+struct FieldsDeserializer<'de>(&'de [Literal]);
+
+impl<'de> IntoDeserializer<'de, DecodeError> for FieldsDeserializer<'de> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'de> Deserializer<'de> for FieldsDeserializer<'de> {
+    type Error = DecodeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DecodeError> {
+        match self.0 {
+            [field] => LiteralDeserializer(field).deserialize_any(visitor),
+            fields => visit_seq(fields.iter().map(LiteralDeserializer), visitor),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+        unit unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier
+        ignored_any
+    }
+}
+
+struct EnumDeserializer<'de> {
+    variant: &'de str,
+    fields: &'de VariantLiteral,
+}
+
+impl<'de> EnumAccess<'de> for EnumDeserializer<'de> {
+    type Error = DecodeError;
+    type Variant = Self;
+
+    fn variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<(S::Value, Self), DecodeError> {
+        let variant = StrDeserializer::<DecodeError>::new(self.variant);
+        Ok((seed.deserialize(variant)?, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for EnumDeserializer<'de> {
+    type Error = DecodeError;
+
+    fn unit_variant(self) -> Result<(), DecodeError> {
+        match self.fields {
+            VariantLiteral::Unit => Ok(()),
+            VariantLiteral::Tuple(_) => Err(de::Error::invalid_type(
+                Unexpected::TupleVariant,
+                &"unit variant",
+            )),
+        }
+    }
+
+    fn newtype_variant_seed<S: DeserializeSeed<'de>>(
+        self,
+        seed: S,
+    ) -> Result<S::Value, DecodeError> {
+        match self.fields {
+            VariantLiteral::Tuple(fields) if fields.len() == 1 => {
+                seed.deserialize(LiteralDeserializer(&fields[0]))
+            }
+            VariantLiteral::Tuple(fields) => Err(de::Error::invalid_length(
+                fields.len(),
+                &"tuple variant with 1 field",
+            )),
+            VariantLiteral::Unit => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"newtype variant",
+            )),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        match self.fields {
+            VariantLiteral::Tuple(fields) => {
+                visit_seq(fields.iter().map(LiteralDeserializer), visitor)
+            }
+            VariantLiteral::Unit => Err(de::Error::invalid_type(
+                Unexpected::UnitVariant,
+                &"tuple variant",
+            )),
+        }
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, DecodeError> {
+        // Garble enums only have unit and tuple variants:
+        let unexpected = match self.fields {
+            VariantLiteral::Unit => Unexpected::UnitVariant,
+            VariantLiteral::Tuple(_) => Unexpected::TupleVariant,
+        };
+        Err(de::Error::invalid_type(unexpected, &"struct variant"))
+    }
+}
+
+#[test]
+fn test_decode() {
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Loan {
+        amount: u32,
+        rates: [i8; 3],
+        history: (bool, Vec<u16>),
+        status: Status,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    enum Status {
+        Open,
+        Late(u8),
+        Closed(bool, u8),
+    }
+
+    let program = crate::MpcProgram::new(
+        "pub fn main(x: u8, loans: [Loan; 2]) -> u8 { x }
+        struct Loan { amount: u32, rates: [i8; 3], history: (bool, [u16; 2]), status: Status }
+        enum Status { Open, Late(u8), Closed(bool, u8) }"
+            .to_string(),
+        "main".to_string(),
+    )
+    .unwrap();
+    let loans = "[
+        Loan { amount: 500u32, rates: [-1i8; 3], history: (true, [1u16, 2u16]), status: Status::Open },
+        Loan { amount: 9u32, rates: [1i8, 2i8, 3i8], history: (false, [0u16; 2]), status: Status::Late(3u8) }
+    ]";
+    let loans = MpcData::from_string(&program, loans.to_string()).unwrap();
+    assert_eq!(
+        loans.decode::<Vec<Loan>>().unwrap(),
+        vec![
+            Loan {
+                amount: 500,
+                rates: [-1; 3],
+                history: (true, vec![1, 2]),
+                status: Status::Open,
+            },
+            Loan {
+                amount: 9,
+                rates: [1, 2, 3],
+                history: (false, vec![0, 0]),
+                status: Status::Late(3),
+            },
+        ]
+    );
+
+    let json = loans.decode::<serde_json::Value>().unwrap();
+    assert_eq!(json[1]["status"], serde_json::json!({ "Late": 3 }));
+
+    // type mismatches are reported instead of being silently converted:
+    assert!(matches!(
+        loans.decode::<[Loan; 3]>(),
+        Err(Error::DecodeError(_))
+    ));
+    assert!(loans.decode::<Vec<u32>>().is_err());
+    let err = loans.decode::<Vec<(u8, bool)>>().unwrap_err();
+    assert!(err.to_string().contains("invalid type"), "{err}");
+}
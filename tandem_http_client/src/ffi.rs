@@ -0,0 +1,226 @@
+//! C API of the client, for embedding the evaluator in apps outside of Rust and JavaScript.
+//!
+//! Programs and data are passed as opaque handles, which are owned by the caller and must be
+//! released using [`tandem_program_free`] and [`tandem_data_free`]. Strings returned by the API are
+//! allocated by Rust and must be released using [`tandem_string_free`].
+//!
+//! Functions that can fail return `NULL` on failure and, unless `error` is `NULL`, store the
+//! message of the error as a newly allocated string in `*error` (and set it to `NULL` otherwise).
+//! The header `include/tandem_http_client.h` is generated by `cbindgen` when building the crate
+//! with the `ffi` feature.
+#![allow(unsafe_code)]
+
+use std::{
+    ffi::{CStr, CString},
+    os::raw::c_char,
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr,
+};
+
+use crate::{compute, Error, MpcData, MpcProgram};
+
+/// Type-checks and compiles the function of the Garble program.
+///
+/// # Safety
+///
+/// `source_code` and `function_name` must be valid NUL-terminated strings, `error` must either be
+/// `NULL` or point to a `char *` that can be written.
+#[no_mangle]
+pub unsafe extern "C" fn tandem_program_new(
+    source_code: *const c_char,
+    function_name: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut MpcProgram {
+    ffi_result(error, || {
+        let source_code = read_str(source_code, "source_code")?;
+        let function_name = read_str(function_name, "function_name")?;
+        MpcProgram::new(source_code, function_name).map_err(message)
+    })
+}
+
+/// Releases the program.
+///
+/// # Safety
+///
+/// `program` must either be `NULL` or a program returned by [`tandem_program_new`] that has not
+/// been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn tandem_program_free(program: *mut MpcProgram) {
+    if !program.is_null() {
+        drop(Box::from_raw(program));
+    }
+}
+
+/// Parses and type-checks a Garble literal as the input of the program.
+///
+/// # Safety
+///
+/// `program` must be a valid program, `literal` a valid NUL-terminated string and `error` must
+/// either be `NULL` or point to a `char *` that can be written.
+#[no_mangle]
+pub unsafe extern "C" fn tandem_data_from_string(
+    program: *const MpcProgram,
+    literal: *const c_char,
+    error: *mut *mut c_char,
+) -> *mut MpcData {
+    ffi_result(error, || {
+        let program = read_handle(program, "program")?;
+        let literal = read_str(literal, "literal")?;
+        MpcData::from_string(program, literal).map_err(message)
+    })
+}
+
+/// Returns the data as a Garble literal, which must be released using [`tandem_string_free`], or
+/// `NULL` if `data` is `NULL`.
+///
+/// # Safety
+///
+/// `data` must be valid data returned by this API that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn tandem_data_to_string(data: *const MpcData) -> *mut c_char {
+    ffi_call(
+        ptr::null_mut(),
+        || Ok(read_handle(data, "data")?.to_literal_string()),
+        to_c_string,
+    )
+}
+
+/// Releases the data.
+///
+/// # Safety
+///
+/// `data` must either be `NULL` or data returned by this API that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn tandem_data_free(data: *mut MpcData) {
+    if !data.is_null() {
+        drop(Box::from_raw(data));
+    }
+}
+
+/// Computes the program with the Tandem server at the url like `compute`, blocking the calling
+/// thread until the output is known.
+///
+/// The program and the input are not consumed and can be reused by later computations.
+///
+/// # Safety
+///
+/// `url` and `plaintext_metadata` must be valid NUL-terminated strings, `program` and `input` must
+/// be valid handles and `error` must either be `NULL` or point to a `char *` that can be written.
+#[no_mangle]
+pub unsafe extern "C" fn tandem_compute(
+    url: *const c_char,
+    plaintext_metadata: *const c_char,
+    program: *const MpcProgram,
+    input: *const MpcData,
+    error: *mut *mut c_char,
+) -> *mut MpcData {
+    ffi_result(error, || {
+        let url = read_str(url, "url")?;
+        let plaintext_metadata = read_str(plaintext_metadata, "plaintext_metadata")?;
+        let program = read_handle(program, "program")?.clone();
+        let input = read_handle(input, "input")?.clone();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| format!("Could not start the runtime: {e}"))?;
+        runtime
+            .block_on(compute(url, plaintext_metadata, program, input))
+            .map_err(message)
+    })
+}
+
+/// Releases a string returned by this API.
+///
+/// # Safety
+///
+/// `string` must either be `NULL` or a string returned by this API that has not been released yet.
+#[no_mangle]
+pub unsafe extern "C" fn tandem_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+/// Runs the function, returning its result as a handle or `NULL` after storing the error.
+unsafe fn ffi_result<T>(error: *mut *mut c_char, f: impl FnOnce() -> Result<T, String>) -> *mut T {
+    ffi_call(error, f, |value| Box::into_raw(Box::new(value)))
+}
+
+/// Runs the function, returning its result converted by `into_raw` or `NULL` after storing the
+/// error, see [`ffi_result`].
+unsafe fn ffi_call<T, R>(
+    error: *mut *mut c_char,
+    f: impl FnOnce() -> Result<T, String>,
+    into_raw: impl FnOnce(T) -> *mut R,
+) -> *mut R {
+    // panics must not unwind into the caller:
+    let result = match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(_) => Err("The client panicked".to_string()),
+    };
+    let (handle, message) = match result {
+        Ok(value) => (into_raw(value), ptr::null_mut()),
+        Err(e) => (ptr::null_mut(), to_c_string(e)),
+    };
+    if let Some(error) = error.as_mut() {
+        *error = message;
+    } else {
+        tandem_string_free(message);
+    }
+    handle
+}
+
+fn message(e: Error) -> String {
+    e.to_string()
+}
+
+unsafe fn read_str(string: *const c_char, name: &str) -> Result<String, String> {
+    if string.is_null() {
+        return Err(format!("`{name}` must not be NULL"));
+    }
+    CStr::from_ptr(string)
+        .to_str()
+        .map(str::to_string)
+        .map_err(|_| format!("`{name}` is not valid UTF-8"))
+}
+
+unsafe fn read_handle<'a, T>(handle: *const T, name: &str) -> Result<&'a T, String> {
+    handle
+        .as_ref()
+        .ok_or_else(|| format!("`{name}` must not be NULL"))
+}
+
+fn to_c_string(string: String) -> *mut c_char {
+    // messages and literals never contain NUL bytes, but must not fail if they do:
+    let string = CString::new(string.replace('\0', "")).unwrap_or_default();
+    string.into_raw()
+}
+
+#[test]
+fn test_ffi() {
+    let cstr = |s: &str| CString::new(s).unwrap();
+    let source_code = cstr("pub fn main(x: u8, y: u8) -> u8 { x + y }");
+    let mut error = ptr::null_mut();
+    unsafe {
+        let program = tandem_program_new(source_code.as_ptr(), cstr("main").as_ptr(), &mut error);
+        assert!(!program.is_null() && error.is_null());
+
+        let data = tandem_data_from_string(program, cstr("2u8").as_ptr(), &mut error);
+        assert!(!data.is_null() && error.is_null());
+        let literal = tandem_data_to_string(data);
+        assert_eq!(CStr::from_ptr(literal).to_str().unwrap(), "2u8");
+        tandem_string_free(literal);
+        tandem_data_free(data);
+        assert!(tandem_data_to_string(ptr::null()).is_null());
+
+        let data = tandem_data_from_string(program, cstr("2i32").as_ptr(), &mut error);
+        assert!(data.is_null() && !error.is_null());
+        let message = CStr::from_ptr(error).to_str().unwrap();
+        assert!(message.starts_with("The MPC program or the input is invalid"));
+        tandem_string_free(error);
+
+        let program2 = tandem_program_new(source_code.as_ptr(), ptr::null(), ptr::null_mut());
+        assert!(program2.is_null());
+        tandem_program_free(program);
+    }
+}
@@ -0,0 +1,128 @@
+//! Incremental parsing of the length-prefixed frames of dialog responses.
+//!
+//! A response consists of a header frame followed by one frame per message. Each frame starts
+//! with its length as a little-endian `u64`. The header frame contains the bincode-encoded offset
+//! of the last durably received message of the client, each message frame contains the message id
+//! as a little-endian `u32`, followed by the message.
+
+use std::collections::VecDeque;
+
+use crate::{Error, MessageId};
+
+/// Number of bytes of the length prefix of a frame.
+const LEN: usize = 8;
+/// Number of bytes of the message id at the start of a message frame.
+const ID: usize = 4;
+
+/// Parses the frames of a dialog response as soon as they are complete, so that each message can
+/// be processed before the full response has arrived.
+pub(crate) struct FrameDecoder {
+    buf: Vec<u8>,
+    last_durably_received_offset: Option<Option<MessageId>>,
+    msgs: VecDeque<(Vec<u8>, MessageId)>,
+}
+
+impl FrameDecoder {
+    /// Creates a decoder whose buffer is preallocated for `size_hint` bytes.
+    pub(crate) fn with_capacity(size_hint: usize) -> Self {
+        Self {
+            buf: Vec::with_capacity(size_hint),
+            last_durably_received_offset: None,
+            msgs: VecDeque::new(),
+        }
+    }
+
+    /// Appends the chunk of the response and parses all frames that are complete.
+    pub(crate) fn push(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        self.buf.extend_from_slice(chunk);
+        let mut start = 0;
+        while let Some(len) = self.buf.get(start..start + LEN) {
+            let len = u64::from_le_bytes(len.try_into().unwrap()) as usize;
+            let end = (start + LEN)
+                .checked_add(len)
+                .ok_or(Error::InvalidResponseFrame)?;
+            if self.buf.len() < end {
+                break;
+            }
+            let frame = &self.buf[start + LEN..end];
+            match self.last_durably_received_offset {
+                None => {
+                    let offset =
+                        bincode::deserialize(frame).map_err(|_| Error::InvalidResponseFrame)?;
+                    self.last_durably_received_offset = Some(offset);
+                }
+                Some(_) if frame.len() < ID => return Err(Error::InvalidResponseFrame),
+                Some(_) => {
+                    let id = MessageId::from_le_bytes(frame[..ID].try_into().unwrap());
+                    self.msgs.push_back((frame[ID..].to_vec(), id));
+                }
+            }
+            start = end;
+        }
+        self.buf.drain(..start);
+        Ok(())
+    }
+
+    /// The last durably received offset of the client, once the header frame has been parsed.
+    pub(crate) fn header(&self) -> Option<Option<MessageId>> {
+        self.last_durably_received_offset
+    }
+
+    /// Removes the next parsed message, if any.
+    pub(crate) fn next_msg(&mut self) -> Option<(Vec<u8>, MessageId)> {
+        self.msgs.pop_front()
+    }
+
+    /// Checks that the response did not end in the middle of a frame or before its header.
+    pub(crate) fn finish(&self) -> Result<(), Error> {
+        match self.last_durably_received_offset {
+            Some(_) if self.buf.is_empty() => Ok(()),
+            _ => Err(Error::InvalidResponseFrame),
+        }
+    }
+}
+
+/// Parses a complete response, returning its messages and the last durably received offset of the
+/// client.
+#[cfg(any(test, feature = "local", target_arch = "wasm32"))]
+pub(crate) fn decode(body: &[u8]) -> Result<(crate::MessageLog, Option<MessageId>), Error> {
+    let mut decoder = FrameDecoder::with_capacity(0);
+    decoder.push(body)?;
+    decoder.finish()?;
+    let offset = decoder.header().unwrap_or_default();
+    Ok((decoder.msgs.into(), offset))
+}
+
+#[test]
+fn test_frame_decoder() {
+    let frame = |bytes: &[u8]| [&(bytes.len() as u64).to_le_bytes(), bytes].concat();
+    let body = [
+        frame(&bincode::serialize(&Some(3u32)).unwrap()),
+        frame(&[4, 0, 0, 0, 1, 2, 3]),
+        frame(&[5, 0, 0, 0]),
+    ]
+    .concat();
+    let expected = vec![(vec![1, 2, 3], 4), (vec![], 5)];
+
+    // the frames are parsed regardless of how the response is split into chunks:
+    for chunk_size in 1..=body.len() {
+        let mut decoder = FrameDecoder::with_capacity(0);
+        let mut msgs = vec![];
+        for chunk in body.chunks(chunk_size) {
+            decoder.push(chunk).unwrap();
+            msgs.extend(std::iter::from_fn(|| decoder.next_msg()));
+        }
+        decoder.finish().unwrap();
+        assert_eq!(decoder.header(), Some(Some(3)));
+        assert_eq!(msgs, expected);
+    }
+    assert_eq!(decode(&body).unwrap(), (expected, Some(3)));
+
+    // each message is available as soon as its frame is complete:
+    let mut decoder = FrameDecoder::with_capacity(0);
+    decoder.push(&body[..body.len() - 1]).unwrap();
+    assert_eq!(decoder.next_msg(), Some((vec![1, 2, 3], 4)));
+    assert_eq!(decoder.next_msg(), None);
+    assert!(matches!(decoder.finish(), Err(Error::InvalidResponseFrame)));
+    assert!(decode(&[]).is_err());
+}
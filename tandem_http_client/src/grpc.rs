@@ -0,0 +1,298 @@
+//! gRPC transport, connecting the client to the gRPC service of a Tandem server.
+//!
+//! Each computation exchanges its messages over a single bidirectional stream, which is opened
+//! with the first dialog round and reused for all following rounds.
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use futures::{channel::mpsc, SinkExt};
+use tandem::{states::Msg, Circuit};
+use tokio::sync::Mutex;
+use tonic::{
+    metadata::{MetadataKey, MetadataMap, MetadataValue},
+    transport::Channel,
+    Code, Request, Status, Streaming,
+};
+use url::Url;
+
+use crate::{
+    compute_session, new_session_error, response_error, ComputeOptions, EngineCreationResult,
+    Error, MessageId, MessageLog, MpcData, MpcProgram, NewSession, Transport,
+};
+
+mod proto {
+    tonic::include_proto!("tandem");
+}
+
+use proto::{
+    tandem_client::TandemClient as Client, CreateSessionRequest, DeleteSessionRequest,
+    DialogRequest, DialogResponse, Message,
+};
+
+/// The gRPC service of a Tandem server, see [`connect_grpc`].
+#[derive(Clone)]
+pub struct GrpcConnection {
+    client: Client<Channel>,
+    url: Url,
+    /// The dialog stream of the current computation, opened by its first dialog round.
+    dialog: Arc<Mutex<Option<Dialog>>>,
+}
+
+struct Dialog {
+    requests: mpsc::Sender<DialogRequest>,
+    responses: Streaming<DialogResponse>,
+}
+
+impl fmt::Debug for GrpcConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GrpcConnection")
+            .field("url", &self.url)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Connects to the gRPC service of a Tandem server (compiled with its `grpc` feature), returning a
+/// connection that computes programs over gRPC instead of HTTP requests.
+pub async fn connect_grpc(url: Url) -> Result<GrpcConnection, Error> {
+    let client = Client::connect(url.to_string())
+        .await
+        .map_err(|e| Error::ServerError(format!("The gRPC server could not be reached: {e}")))?
+        .max_decoding_message_size(usize::MAX)
+        .max_encoding_message_size(usize::MAX);
+    Ok(GrpcConnection {
+        client,
+        url,
+        dialog: Arc::new(Mutex::new(None)),
+    })
+}
+
+impl GrpcConnection {
+    /// Computes the program like [`crate::compute`], using the gRPC service as the contributor.
+    pub async fn compute(
+        &self,
+        plaintext_metadata: String,
+        program: MpcProgram,
+        input: MpcData,
+    ) -> Result<MpcData, Error> {
+        let options = ComputeOptions::default();
+        self.compute_with_options(plaintext_metadata, program, input, options)
+            .await
+    }
+
+    /// Computes the program like [`crate::compute_with_options`], using the gRPC service as the
+    /// contributor. The headers of the options are sent as gRPC metadata.
+    pub async fn compute_with_options(
+        &self,
+        plaintext_metadata: String,
+        program: MpcProgram,
+        input: MpcData,
+        options: ComputeOptions,
+    ) -> Result<MpcData, Error> {
+        // concurrent computations must not share their dialog stream:
+        let transport = Transport::Grpc(GrpcConnection {
+            client: self.client.clone(),
+            url: self.url.clone(),
+            dialog: Arc::new(Mutex::new(None)),
+        });
+        let plaintext_metadata = serde_json::Value::String(plaintext_metadata);
+        compute_session(
+            &transport,
+            self.url.clone(),
+            &options,
+            plaintext_metadata,
+            program,
+            input,
+            None,
+            &(),
+        )
+        .await
+    }
+
+    pub(crate) async fn send_new_session(
+        &self,
+        headers: &HashMap<String, String>,
+        session: &NewSession,
+        circuit: &Circuit,
+    ) -> Result<EngineCreationResult, Error> {
+        let req = CreateSessionRequest {
+            plaintext_metadata_json: serde_json::to_string(&session.plaintext_metadata)
+                .map_err(|e| Error::JsonError(e.to_string()))?,
+            program: session.program.clone(),
+            function: session.function.clone(),
+            circuit_hash: session.circuit_hash.to_vec(),
+            client_version: session.client_version.clone(),
+            protocol_version: session.protocol_version,
+            wire_versions: session.wire_versions.clone(),
+            capabilities: session.capabilities.clone(),
+            approval_id: session.approval_id.clone(),
+            idempotency_key: session.idempotency_key.clone(),
+        };
+        let resp = self
+            .client
+            .clone()
+            .create_session(with_metadata(req, headers)?)
+            .await;
+        let resp = match resp {
+            Ok(resp) => resp.into_inner(),
+            Err(status) if status.code() == Code::InvalidArgument => {
+                return Err(new_session_error(status.message().to_string(), circuit));
+            }
+            Err(status) => return Err(status_error(status)),
+        };
+        Ok(EngineCreationResult {
+            engine_id: resp.engine_id,
+            request_headers: resp.request_headers,
+            server_version: resp.server_version,
+            // final messages are always part of the dialog stream:
+            final_url: None,
+            messages: resp
+                .messages
+                .into_iter()
+                .map(|m| (m.payload, m.id))
+                .collect(),
+            capabilities: resp.capabilities,
+            wire_version: Some(resp.wire_version),
+            // gRPC sessions do not support commitments yet:
+            session_commitment: None,
+            // the gRPC service does not support encrypted channels:
+            channel: None,
+        })
+    }
+
+    pub(crate) async fn send_msgs(
+        &self,
+        url: &Url,
+        request_headers: &HashMap<String, String>,
+        last_durably_received_offset: Option<u32>,
+        msgs: &[(&Msg, MessageId)],
+    ) -> Result<(MessageLog, Option<MessageId>), Error> {
+        let req = DialogRequest {
+            engine_id: engine_id(url)?,
+            last_durably_received_offset,
+            messages: msgs
+                .iter()
+                .map(|(msg, id)| Message {
+                    payload: msg.to_vec(),
+                    id: *id,
+                })
+                .collect(),
+        };
+        let mut dialog = self.dialog.lock().await;
+        let resp = match dialog.as_mut() {
+            Some(dialog) => exchange(dialog, req).await,
+            None => match self.open_dialog(request_headers, req).await {
+                Ok((opened, resp)) => {
+                    *dialog = Some(opened);
+                    Ok(resp)
+                }
+                Err(e) => Err(e),
+            },
+        };
+        let resp = match resp {
+            Ok(resp) => resp,
+            Err(e) => {
+                // the stream is closed after an error, the next round opens a new stream:
+                *dialog = None;
+                return Err(e);
+            }
+        };
+        let msgs = resp.messages.into_iter().map(|m| (m.payload, m.id));
+        Ok((msgs.collect(), resp.last_durably_received_offset))
+    }
+
+    pub(crate) async fn delete_session(
+        &self,
+        url: &Url,
+        request_headers: &HashMap<String, String>,
+    ) -> Result<(), Error> {
+        let req = DeleteSessionRequest {
+            engine_id: engine_id(url)?,
+        };
+        self.client
+            .clone()
+            .delete_session(with_metadata(req, request_headers)?)
+            .await
+            .map_err(status_error)?;
+        // the dialog stream belongs to the deleted session:
+        *self.dialog.lock().await = None;
+        Ok(())
+    }
+
+    /// Opens the dialog stream with the first request, returning the stream and the response.
+    async fn open_dialog(
+        &self,
+        request_headers: &HashMap<String, String>,
+        req: DialogRequest,
+    ) -> Result<(Dialog, DialogResponse), Error> {
+        let (mut requests, stream) = mpsc::channel(1);
+        // the first request is buffered before the stream is opened, so that it is sent
+        // immediately:
+        requests.send(req).await.map_err(closed)?;
+        let responses = self
+            .client
+            .clone()
+            .dialog(with_metadata(stream, request_headers)?)
+            .await
+            .map_err(status_error)?
+            .into_inner();
+        let mut dialog = Dialog {
+            requests,
+            responses,
+        };
+        let resp = next_response(&mut dialog).await?;
+        Ok((dialog, resp))
+    }
+}
+
+async fn exchange(dialog: &mut Dialog, req: DialogRequest) -> Result<DialogResponse, Error> {
+    dialog.requests.send(req).await.map_err(closed)?;
+    next_response(dialog).await
+}
+
+async fn next_response(dialog: &mut Dialog) -> Result<DialogResponse, Error> {
+    match dialog.responses.message().await.map_err(status_error)? {
+        Some(resp) => Ok(resp),
+        None => Err(Error::ServerError(
+            "The gRPC server closed the dialog stream".to_string(),
+        )),
+    }
+}
+
+/// The engine id of a session, which is the last path segment of its url.
+fn engine_id(url: &Url) -> Result<String, Error> {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .map(|segment| segment.to_string())
+        .ok_or_else(|| Error::ServerError(format!("Invalid session url {url}")))
+}
+
+/// Sends the headers as gRPC metadata, whose keys are always lowercase.
+fn with_metadata<T>(message: T, headers: &HashMap<String, String>) -> Result<Request<T>, Error> {
+    let mut metadata = MetadataMap::new();
+    for (k, v) in headers.iter() {
+        let key = MetadataKey::from_bytes(k.to_lowercase().as_bytes());
+        let value = MetadataValue::try_from(v.as_str());
+        match (key, value) {
+            (Ok(key), Ok(value)) => {
+                metadata.insert(key, value);
+            }
+            _ => return Err(Error::ServerError(format!("Invalid gRPC metadata `{k}`"))),
+        }
+    }
+    let mut req = Request::new(message);
+    *req.metadata_mut() = metadata;
+    Ok(req)
+}
+
+/// Converts the status into an error, the message of which is the JSON-encoded server error.
+fn status_error(status: Status) -> Error {
+    let server_failed = matches!(
+        status.code(),
+        Code::Unavailable | Code::Internal | Code::Unknown
+    );
+    response_error(server_failed, status.message().to_string())
+}
+
+fn closed(_: mpsc::SendError) -> Error {
+    Error::ServerError("The gRPC dialog stream is closed".to_string())
+}
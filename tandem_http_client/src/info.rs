@@ -0,0 +1,175 @@
+//! Discovery of the health, capabilities and published programs of a remote server.
+
+use std::{collections::BTreeMap, fmt};
+
+use reqwest::{Client, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use url::Url;
+
+use crate::{resp_or_err, timeouts, timeouts::Timeouts, ComputeOptions, Error};
+
+/// Health of a server, as reported by its `/healthz` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Health {
+    /// Status of the server, `"ok"` if the server is ready to accept sessions.
+    pub status: String,
+    /// Version of the server.
+    #[serde(default)]
+    pub server_version: Option<String>,
+    /// Version of the MPC protocol spoken by the server, see [`tandem::PROTOCOL_VERSION`].
+    #[serde(default)]
+    pub protocol_version: Option<u32>,
+    /// Version of the HTTP wire protocol spoken by the server, see [`crate::WIRE_VERSION`].
+    #[serde(default)]
+    pub wire_version: Option<u32>,
+}
+
+/// Features and limits of a server, as reported by its `/capabilities` endpoint.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities {
+    /// Version of the server.
+    #[serde(default)]
+    pub server_version: Option<String>,
+    /// Newest version of the HTTP wire protocol supported by the server.
+    #[serde(default)]
+    pub wire_version: Option<u32>,
+    /// Oldest version of the HTTP wire protocol supported by the server.
+    #[serde(default)]
+    pub min_wire_version: Option<u32>,
+    /// Optional features supported by the server.
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Limits enforced by the server, such as the maximum number of AND gates per session.
+    #[serde(default)]
+    pub limits: BTreeMap<String, u64>,
+}
+
+/// A function published by a server, as reported by its `/programs` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublishedFunction {
+    /// Name of the Garble function.
+    pub function: String,
+    /// Signature of the Garble function, e.g. `fn main(x: u8, y: u8) -> u8`.
+    #[serde(default)]
+    pub signature: Option<String>,
+    /// Hash of the Garble program containing the function.
+    #[serde(default)]
+    pub program_hash: Option<String>,
+}
+
+/// Summary of everything a server exposes about itself.
+///
+/// Each part is `None` if the server does not (yet) provide the corresponding endpoint.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    /// Health of the server.
+    pub health: Option<Health>,
+    /// Features and limits of the server.
+    pub capabilities: Option<Capabilities>,
+    /// Functions published by the server.
+    pub programs: Option<Vec<PublishedFunction>>,
+}
+
+/// Queries the health, capabilities and published programs of the server at the specified url.
+pub async fn server_info(url: &Url) -> Result<ServerInfo, Error> {
+    server_info_with_options(url, &ComputeOptions::default()).await
+}
+
+/// Queries the server like [`server_info`], sending the headers and applying the timeouts of the
+/// options to every request.
+pub async fn server_info_with_options(
+    url: &Url,
+    options: &ComputeOptions,
+) -> Result<ServerInfo, Error> {
+    let client = timeouts::client(options)?;
+    let timeouts = Timeouts::start(options);
+    let (client, timeouts) = (&client, &timeouts);
+    Ok(ServerInfo {
+        health: fetch_optional(client, url.join("healthz")?, options, timeouts).await?,
+        capabilities: fetch_optional(client, url.join("capabilities")?, options, timeouts).await?,
+        programs: fetch_optional(client, url.join("programs")?, options, timeouts).await?,
+    })
+}
+
+async fn fetch_optional<T: DeserializeOwned>(
+    client: &Client,
+    url: Url,
+    options: &ComputeOptions,
+    timeouts: &Timeouts,
+) -> Result<Option<T>, Error> {
+    let mut req = client.get(url);
+    for (k, v) in options.headers.iter() {
+        req = req.header(k, v);
+    }
+    let resp = timeouts.apply(req)?.send().await?;
+    if resp.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let resp = resp_or_err(resp).await?;
+    Ok(Some(resp.json::<T>().await?))
+}
+
+impl fmt::Display for ServerInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Client version: {}", env!("CARGO_PKG_VERSION"))?;
+        match &self.health {
+            Some(Health {
+                status,
+                server_version,
+                protocol_version,
+                wire_version,
+            }) => {
+                writeln!(f, "Health: {status}")?;
+                if let Some(v) = server_version {
+                    writeln!(f, "Server version: {v}")?;
+                }
+                if let (Some(p), Some(w)) = (protocol_version, wire_version) {
+                    writeln!(f, "Protocol version: {p} (wire version {w})")?;
+                }
+            }
+            None => writeln!(f, "Health: not available")?,
+        }
+        match &self.capabilities {
+            Some(Capabilities {
+                wire_version,
+                min_wire_version,
+                features,
+                limits,
+                ..
+            }) => {
+                if let (Some(min), Some(max)) = (min_wire_version, wire_version) {
+                    writeln!(f, "Wire versions: {min} to {max}")?;
+                }
+                if features.is_empty() {
+                    writeln!(f, "Features: none")?;
+                } else {
+                    writeln!(f, "Features: {}", features.join(", "))?;
+                }
+                if limits.is_empty() {
+                    writeln!(f, "Limits: none")?;
+                } else {
+                    writeln!(f, "Limits:")?;
+                    for (limit, value) in limits {
+                        writeln!(f, "  {limit}: {value}")?;
+                    }
+                }
+            }
+            None => writeln!(f, "Capabilities: not available")?,
+        }
+        match &self.programs {
+            Some(programs) if programs.is_empty() => writeln!(f, "Published functions: none")?,
+            Some(programs) => {
+                writeln!(f, "Published functions:")?;
+                for p in programs {
+                    let signature = p.signature.as_deref().unwrap_or(&p.function);
+                    match &p.program_hash {
+                        Some(hash) => writeln!(f, "  {signature} (program {hash})")?,
+                        None => writeln!(f, "  {signature}")?,
+                    }
+                }
+            }
+            None => writeln!(f, "Published functions: not available")?,
+        }
+        Ok(())
+    }
+}
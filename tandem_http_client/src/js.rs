@@ -0,0 +1,489 @@
+//! JavaScript API of the wasm build, accepting options as plain objects and returning outputs as
+//! plain values, see the TypeScript definitions below.
+
+use std::{collections::HashMap, future::Future, time::Duration};
+
+use js_sys::{Function, Promise, Reflect, TypeError, Uint8Array};
+use serde::Serialize;
+use tandem_garble_interop::{check_program, compile_program, TypedCircuit};
+use wasm_bindgen::{prelude::wasm_bindgen, JsCast, JsValue};
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+
+use crate::{
+    plain::Plain,
+    program_cache::{self, ProgramKey},
+    progress::{Monitor, Progress},
+    ComputeOptions, ComputeReport, Error, MpcData, MpcProgram, PhaseTimings, Role, TandemClient,
+    ValidationError,
+};
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_DEFINITIONS: &'static str = r#"
+/**
+ * A Garble value as a plain JavaScript value: booleans, numbers (or bigints for 64-bit numbers),
+ * arrays (for arrays and tuples), objects (for structs) and enums as `{enum, variant, fields}`.
+ */
+export type PlainValue =
+  | boolean
+  | number
+  | bigint
+  | PlainValue[]
+  | { enum: string; variant: string; fields: PlainValue[] }
+  | { [field: string]: PlainValue };
+
+/** A Garble type, as returned by `MpcProgram.inputType`. */
+export type TypeDescription =
+  | { kind: "bool" }
+  | { kind: "unsigned" | "signed"; name: string; bits: number }
+  | { kind: "array"; elem: TypeDescription; size: number }
+  | { kind: "tuple"; fields: TypeDescription[] }
+  | { kind: "struct"; name: string; fields: { name: string; type: TypeDescription }[] }
+  | { kind: "enum"; name: string; variants: { name: string; fields: TypeDescription[] }[] };
+
+/** How far a computation has progressed, `step` equals `steps` once the output is known. */
+export interface Progress {
+  step: number;
+  steps: number;
+}
+
+/** Round trips, bandwidth and timing of a computation, with all timings in milliseconds. */
+export interface ComputeReport {
+  rounds: number;
+  bytesSent: number;
+  bytesReceived: number;
+  phaseTimings: { session: number; evaluation: number; output: number };
+}
+
+/** The output of a computation together with its report, see `computeWithReport`. */
+export interface ReportedOutput {
+  output: PlainValue;
+  report: ComputeReport;
+}
+
+/** The server and options of a computation, see `computeWithOptions`. */
+export interface ComputeRequest {
+  /** The url of the Tandem server. */
+  url: string;
+  /** Plaintext metadata sent to the server, either a string or any JSON value. */
+  metadata: string | unknown;
+  /** Headers sent with every request of the computation, such as `Authorization`. */
+  headers?: Record<string, string>;
+  /** Fails the computation if it does not complete within the timeout (in milliseconds). */
+  timeout?: number;
+  /** Fails the computation if the server does not commit to the created session. */
+  requireSessionCommitment?: boolean;
+  /** Compares the hashes of all exchanged messages with the server after the final message. */
+  confirmTranscript?: boolean;
+  /** Encrypts all messages of the protocol end-to-end, independent of TLS. */
+  encryptedChannel?: boolean;
+  /** The hex-encoded public key authenticating the encrypted channel of the server. */
+  serverChannelKey?: string;
+  /** Discloses the output to servers whose handlers process it, such sessions fail otherwise. */
+  discloseOutput?: boolean;
+  /** Pauses the computation until the server acknowledges the queued messages beyond this size. */
+  maxQueueBytes?: number;
+  /** Called whenever the computation has progressed. */
+  onProgress?: (progress: Progress) => void;
+  /** Aborts the computation before its next request to the server. */
+  signal?: AbortSignal;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// See the TypeScript definition of `ComputeRequest`.
+    #[wasm_bindgen(typescript_type = "ComputeRequest")]
+    pub type ComputeRequest;
+
+    /// A promise that resolves to the output of a computation.
+    #[wasm_bindgen(typescript_type = "Promise<PlainValue>")]
+    pub type PlainValuePromise;
+
+    /// A promise that resolves to the output of a computation together with its report.
+    #[wasm_bindgen(typescript_type = "Promise<ReportedOutput>")]
+    pub type ReportedOutputPromise;
+
+    /// A promise that resolves to the output of a computation as `MpcData`.
+    #[wasm_bindgen(typescript_type = "Promise<MpcData>")]
+    pub type MpcDataPromise;
+
+    /// A promise that resolves to a compiled program.
+    #[wasm_bindgen(typescript_type = "Promise<MpcProgram>")]
+    pub type MpcProgramPromise;
+
+    /// See the TypeScript definition of `PlainValue`.
+    #[wasm_bindgen(typescript_type = "PlainValue")]
+    pub type PlainValue;
+
+    /// See the TypeScript definition of `TypeDescription`.
+    #[wasm_bindgen(typescript_type = "TypeDescription")]
+    pub type JsTypeDescription;
+}
+
+// Compiled circuits are persisted in IndexedDB (if available) so that they survive page loads.
+// Failures of IndexedDB are ignored, the circuit is then simply compiled (again).
+#[wasm_bindgen(inline_js = r#"
+const STORE = "circuits";
+
+function request(mode, f) {
+  return new Promise((resolve, reject) => {
+    const open = indexedDB.open("tandem_http_client", 1);
+    open.onupgradeneeded = () => open.result.createObjectStore(STORE);
+    open.onerror = () => reject(open.error);
+    open.onsuccess = () => {
+      const req = f(open.result.transaction(STORE, mode).objectStore(STORE));
+      req.onsuccess = () => resolve(req.result);
+      req.onerror = () => reject(req.error);
+    };
+  }).catch(() => undefined);
+}
+
+export function idbGet(key) {
+  return request("readonly", (store) => store.get(key));
+}
+
+export function idbPut(key, bytes) {
+  return request("readwrite", (store) => store.put(bytes, key));
+}
+
+export function idbClear() {
+  return request("readwrite", (store) => store.clear());
+}
+"#)]
+extern "C" {
+    #[wasm_bindgen(js_name = idbGet)]
+    fn idb_get(key: &str) -> Promise;
+
+    #[wasm_bindgen(js_name = idbPut)]
+    fn idb_put(key: &str, bytes: Uint8Array) -> Promise;
+
+    #[wasm_bindgen(js_name = idbClear)]
+    fn idb_clear() -> Promise;
+}
+
+/// Computes the program like `compute`, using the server and options of the request and
+/// resolving to the output as a plain value.
+///
+/// The program and the input are not consumed and can be reused by later computations. Once the
+/// `signal` of the request is aborted, the computation is aborted before its next request and the
+/// promise is rejected with a `Cancelled` error.
+#[wasm_bindgen(js_name = computeWithOptions)]
+pub fn compute_with_options_js(
+    program: &MpcProgram,
+    input: &MpcData,
+    request: ComputeRequest,
+) -> Result<PlainValuePromise, JsValue> {
+    let computation = compute_js(program, input, request)?;
+    let promise = future_to_promise(async move { computation.await?.0.to_plain().map(Into::into) });
+    Ok(JsValue::from(promise).unchecked_into())
+}
+
+/// Computes the program like `computeWithOptions`, but resolves to the output together with the
+/// round trips, bandwidth and timing of the computation, see the TypeScript definition of
+/// `ComputeReport`.
+#[wasm_bindgen(js_name = computeWithReport)]
+pub fn compute_with_report_js(
+    program: &MpcProgram,
+    input: &MpcData,
+    request: ComputeRequest,
+) -> Result<ReportedOutputPromise, JsValue> {
+    let computation = compute_js(program, input, request)?;
+    let promise = future_to_promise(async move {
+        let (output, report) = computation.await?;
+        to_js(&ReportedOutput {
+            output: Plain(&output.literal),
+            report: JsComputeReport::from(&report),
+        })
+    });
+    Ok(JsValue::from(promise).unchecked_into())
+}
+
+/// Computes the program like `computeWithOptions`, but resolves to the output as `MpcData`, which
+/// can be used as the input of another computation or be transferred as bytes.
+#[wasm_bindgen(js_name = computeDataWithOptions)]
+pub fn compute_data_with_options_js(
+    program: &MpcProgram,
+    input: &MpcData,
+    request: ComputeRequest,
+) -> Result<MpcDataPromise, JsValue> {
+    let computation = compute_js(program, input, request)?;
+    let promise = future_to_promise(async move { Ok(computation.await?.0.into()) });
+    Ok(JsValue::from(promise).unchecked_into())
+}
+
+/// Parses the request, returning the computation as a future that can be turned into a promise.
+fn compute_js(
+    program: &MpcProgram,
+    input: &MpcData,
+    request: ComputeRequest,
+) -> Result<impl Future<Output = Result<(MpcData, ComputeReport), JsValue>>, JsValue> {
+    let request: JsValue = request.into();
+    let url = get(&request, "url")?
+        .as_string()
+        .ok_or_else(|| TypeError::new("`url` must be a string"))?;
+    let metadata = get(&request, "metadata")?;
+    let metadata = match metadata.as_string() {
+        Some(metadata) => serde_json::Value::String(metadata),
+        None => serde_wasm_bindgen::from_value(metadata)
+            .map_err(|e| TypeError::new(&format!("`metadata` is not a JSON value: {e}")))?,
+    };
+    let mut options = ComputeOptions::new();
+    let headers = get(&request, "headers")?;
+    if !headers.is_undefined() {
+        let headers: HashMap<String, String> = serde_wasm_bindgen::from_value(headers)
+            .map_err(|e| TypeError::new(&format!("`headers` must map names to strings: {e}")))?;
+        options = options.headers(headers);
+    }
+    let timeout = get(&request, "timeout")?;
+    if !timeout.is_undefined() {
+        let millis = timeout
+            .as_f64()
+            .filter(|millis| *millis >= 0.0)
+            .ok_or_else(|| TypeError::new("`timeout` must be a number of milliseconds"))?;
+        options = options.timeout(Duration::from_secs_f64(millis / 1000.0));
+    }
+    let require_session_commitment = get(&request, "requireSessionCommitment")?;
+    if !require_session_commitment.is_undefined() {
+        let require = require_session_commitment
+            .as_bool()
+            .ok_or_else(|| TypeError::new("`requireSessionCommitment` must be a boolean"))?;
+        if require {
+            options = options.require_session_commitment();
+        }
+    }
+    let confirm_transcript = get(&request, "confirmTranscript")?;
+    if !confirm_transcript.is_undefined() {
+        let confirm = confirm_transcript
+            .as_bool()
+            .ok_or_else(|| TypeError::new("`confirmTranscript` must be a boolean"))?;
+        if confirm {
+            options = options.confirm_transcript();
+        }
+    }
+    let encrypted_channel = get(&request, "encryptedChannel")?;
+    if !encrypted_channel.is_undefined() {
+        let encrypt = encrypted_channel
+            .as_bool()
+            .ok_or_else(|| TypeError::new("`encryptedChannel` must be a boolean"))?;
+        if encrypt {
+            options = options.encrypted_channel();
+        }
+    }
+    let server_channel_key = get(&request, "serverChannelKey")?;
+    if !server_channel_key.is_undefined() {
+        let key = server_channel_key
+            .as_string()
+            .and_then(|key| blake3::Hash::from_hex(key).ok())
+            .ok_or_else(|| {
+                TypeError::new("`serverChannelKey` must be a hex-encoded key of 32 bytes")
+            })?;
+        options = options.server_channel_key(*key.as_bytes());
+    }
+    let disclose_output = get(&request, "discloseOutput")?;
+    if !disclose_output.is_undefined() {
+        let disclose = disclose_output
+            .as_bool()
+            .ok_or_else(|| TypeError::new("`discloseOutput` must be a boolean"))?;
+        if disclose {
+            options = options.disclose_output();
+        }
+    }
+    let max_queue_bytes = get(&request, "maxQueueBytes")?;
+    if !max_queue_bytes.is_undefined() {
+        let bytes = max_queue_bytes
+            .as_f64()
+            .filter(|bytes| *bytes >= 0.0)
+            .ok_or_else(|| TypeError::new("`maxQueueBytes` must be a number of bytes"))?;
+        options = options.max_queue_bytes(bytes as usize);
+    }
+    let on_progress = get(&request, "onProgress")?;
+    let on_progress = if on_progress.is_undefined() {
+        None
+    } else {
+        let on_progress = on_progress
+            .dyn_into::<Function>()
+            .map_err(|_| TypeError::new("`onProgress` must be a function"))?;
+        Some(on_progress)
+    };
+    let signal = get(&request, "signal")?;
+    let signal = if signal.is_undefined() {
+        None
+    } else {
+        Some(signal)
+    };
+    let monitor = JsMonitor {
+        on_progress,
+        signal,
+    };
+
+    let client = TandemClient::new(&url, options)?;
+    let program = program.clone();
+    let input = input.clone();
+    Ok(async move {
+        if monitor.is_cancelled() {
+            return Err(Error::Cancelled.into());
+        }
+        let output = client
+            .compute_monitored(program, input, metadata, &monitor)
+            .await?;
+        Ok(output)
+    })
+}
+
+#[wasm_bindgen]
+impl MpcProgram {
+    /// Returns the type of the input of the party (either `"contributor"` or `"evaluator"`), see
+    /// the TypeScript definition of `TypeDescription`.
+    #[wasm_bindgen(js_name = inputType)]
+    pub fn input_type_js(&self, role: &str) -> Result<JsTypeDescription, JsValue> {
+        Ok(to_js(self.input_type(parse_role(role)?))?.unchecked_into())
+    }
+
+    /// Returns the number of bits that the input of the party (either `"contributor"` or
+    /// `"evaluator"`) is encoded as.
+    #[wasm_bindgen(js_name = expectedInputBits)]
+    pub fn expected_input_bits_js(&self, role: &str) -> Result<usize, JsValue> {
+        Ok(self.expected_input_bits(parse_role(role)?))
+    }
+
+    /// Type-checks the specified function like `new MpcProgram`, but resolves to the program as
+    /// soon as it is cached in memory, and otherwise reuses the circuit persisted in IndexedDB by
+    /// an earlier page load (or persists the circuit after compiling it).
+    #[wasm_bindgen(js_name = loadCached)]
+    pub fn load_cached_js(source_code: String, function_name: String) -> MpcProgramPromise {
+        let promise =
+            future_to_promise(
+                async move { Ok(load_cached(source_code, function_name).await?.into()) },
+            );
+        JsValue::from(promise).unchecked_into()
+    }
+}
+
+async fn load_cached(source_code: String, function_name: String) -> Result<MpcProgram, Error> {
+    let source_code = source_code.trim().to_string();
+    let key = program_cache::key(&source_code, &function_name);
+    if let Some(program) = program_cache::get(&key) {
+        return Ok(program);
+    }
+    let compile_error = |e| Error::from(ValidationError::GarbleCompileTimeError(e));
+    let ast = check_program(&source_code).map_err(compile_error)?;
+    let circuit = match load_circuit(&key).await {
+        Some(circuit) => circuit,
+        None => {
+            let circuit = compile_program(&ast, &function_name).map_err(compile_error)?;
+            store_circuit(&key, &circuit).await;
+            circuit
+        }
+    };
+    let program = MpcProgram::from_circuit(source_code, function_name, ast, circuit)?;
+    program_cache::insert(key, &program);
+    Ok(program)
+}
+
+async fn load_circuit(key: &ProgramKey) -> Option<TypedCircuit> {
+    let key = blake3::Hash::from(*key).to_hex();
+    let bytes = JsFuture::from(idb_get(&key)).await.ok()?;
+    TypedCircuit::from_bytes(&bytes.dyn_into::<Uint8Array>().ok()?.to_vec())
+}
+
+async fn store_circuit(key: &ProgramKey, circuit: &TypedCircuit) {
+    let key = blake3::Hash::from(*key).to_hex();
+    if let Ok(bytes) = circuit.to_bytes() {
+        let _ = JsFuture::from(idb_put(&key, Uint8Array::from(bytes.as_slice()))).await;
+    }
+}
+
+/// Removes all programs from the in-memory cache and all circuits persisted in IndexedDB, so that
+/// `MpcProgram.loadCached` compiles them again.
+#[wasm_bindgen(js_name = clearCache)]
+pub fn clear_cache_js() -> Promise {
+    program_cache::clear_cache();
+    idb_clear()
+}
+
+#[wasm_bindgen]
+impl MpcData {
+    /// Returns MpcData as a plain value, see the TypeScript definition of `PlainValue`.
+    #[wasm_bindgen(js_name = toPlain)]
+    pub fn to_plain(&self) -> Result<PlainValue, JsValue> {
+        Ok(to_js(&Plain(&self.literal))?.unchecked_into())
+    }
+}
+
+/// See the TypeScript definition of `ReportedOutput`.
+#[derive(Serialize)]
+struct ReportedOutput<'a> {
+    output: Plain<'a>,
+    report: JsComputeReport,
+}
+
+/// A [`ComputeReport`] with camel-cased fields, see the TypeScript definition of `ComputeReport`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsComputeReport {
+    rounds: usize,
+    bytes_sent: usize,
+    bytes_received: usize,
+    phase_timings: PhaseTimings,
+}
+
+impl From<&ComputeReport> for JsComputeReport {
+    fn from(report: &ComputeReport) -> Self {
+        Self {
+            rounds: report.rounds,
+            bytes_sent: report.bytes_sent,
+            bytes_received: report.bytes_received,
+            phase_timings: report.phase_timings,
+        }
+    }
+}
+
+/// Reports progress to the `onProgress` callback and cancels the computation once its `signal` is
+/// aborted.
+struct JsMonitor {
+    on_progress: Option<Function>,
+    signal: Option<JsValue>,
+}
+
+impl Monitor for JsMonitor {
+    fn progress(&self, progress: Progress) {
+        if let Some(on_progress) = &self.on_progress {
+            // errors thrown by the callback must not fail the computation:
+            if let Ok(progress) = to_js(&progress) {
+                let _ = on_progress.call1(&JsValue::NULL, &progress);
+            }
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        match &self.signal {
+            Some(signal) => Reflect::get(signal, &JsValue::from_str("aborted"))
+                .map_or(false, |aborted| aborted.is_truthy()),
+            None => false,
+        }
+    }
+}
+
+fn parse_role(role: &str) -> Result<Role, JsValue> {
+    match role {
+        "contributor" => Ok(Role::Contributor),
+        "evaluator" => Ok(Role::Evaluator),
+        _ => Err(TypeError::new("The role must be either \"contributor\" or \"evaluator\"").into()),
+    }
+}
+
+/// Returns the property of the object, `undefined` if the property is missing.
+fn get(object: &JsValue, key: &str) -> Result<JsValue, JsValue> {
+    if !object.is_object() {
+        return Err(TypeError::new("The request must be an object").into());
+    }
+    Reflect::get(object, &JsValue::from_str(key))
+}
+
+/// Converts the value to JavaScript, using plain objects for maps and bigints for 64-bit numbers.
+fn to_js(value: &impl Serialize) -> Result<JsValue, JsValue> {
+    let serializer = serde_wasm_bindgen::Serializer::new()
+        .serialize_maps_as_objects(true)
+        .serialize_large_number_types_as_bigints(true);
+    Ok(value.serialize(&serializer)?)
+}
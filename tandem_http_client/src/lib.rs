@@ -16,16 +16,40 @@
 // https://github.com/rustwasm/wasm-bindgen/issues/2774
 #![allow(clippy::unused_unit)]
 
+use base64::{engine::general_purpose::STANDARD, Engine};
+#[cfg(not(target_arch = "wasm32"))]
+use frames::FrameDecoder;
 use msg_queue::{MessageId, MsgQueue};
-use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use progress::Monitor;
+use rand_chacha::{
+    rand_core::{RngCore, SeedableRng},
+    ChaCha20Rng,
+};
 use reqwest::Response;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt};
-use tandem::{states::Msg, Circuit, CircuitBlake3Hash};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tandem::{
+    abort_message,
+    channel::{ChannelKey, Handshake, HandshakeReply, SecureChannel},
+    preprocessing_circuit,
+    states::{Evaluator, Msg, OutputReport},
+    AbortReason, Circuit, CircuitBlake3Hash, PreprocessedTriples, ProtocolOptions, ProtocolPlan,
+    ValidatedCircuit,
+};
 use tandem_garble_interop::{
-    check_program, compile_program, deserialize_output, parse_input, Role, TypedCircuit,
+    check_program, circuit_to_dot, compile_program, describe_type, deserialize_output, parse_input,
+    TypedCircuit,
+};
+pub use tandem_garble_interop::{
+    CircuitCache, FieldDescription, Literal, LiteralBuilder, Role, TypeDescription,
+    VariantDescription, VariantLiteral,
 };
-pub use tandem_garble_interop::{Literal, VariantLiteral};
+use timeouts::{Clock, Timeouts};
 use url::Url;
 
 #[cfg(target_arch = "wasm32")]
@@ -33,7 +57,104 @@ use wasm_bindgen::{prelude::wasm_bindgen, JsValue};
 
 use self::ValidationError::*;
 
+pub use adaptive::{BatchSizeController, MAX_BATCH_BYTES_LIMIT};
+#[cfg(all(feature = "grpc", not(target_arch = "wasm32")))]
+pub use grpc::{connect_grpc, GrpcConnection};
+pub use info::{
+    server_info, server_info_with_options, Capabilities, Health, PublishedFunction, ServerInfo,
+};
+#[cfg(all(feature = "local", not(target_arch = "wasm32")))]
+pub use local::{connect_local, LocalConnection};
+pub use mismatch::CircuitMismatch;
+pub use program_cache::clear_cache;
+pub use progress::Progress;
+pub use report::{CircuitReport, ComputeReport, PhaseTimings};
+
+mod adaptive;
+pub mod approval;
+#[cfg(feature = "auction")]
+pub mod auction;
+mod decode;
+#[cfg(all(feature = "ffi", not(target_arch = "wasm32")))]
+pub mod ffi;
+mod frames;
+#[cfg(all(feature = "grpc", not(target_arch = "wasm32")))]
+mod grpc;
+mod info;
+#[cfg(target_arch = "wasm32")]
+mod js;
+#[cfg(all(feature = "local", not(target_arch = "wasm32")))]
+mod local;
+mod mismatch;
 mod msg_queue;
+mod plain;
+mod program_cache;
+mod progress;
+#[cfg(all(feature = "python", not(target_arch = "wasm32")))]
+mod python;
+mod report;
+mod timeouts;
+mod wire_error;
+
+/// Version of the HTTP wire protocol spoken between client and server.
+///
+/// Client and server can interoperate regardless of their crate versions as long as they agree on
+/// both the wire version and the [`tandem::PROTOCOL_VERSION`]. The client advertises all versions
+/// between [`MIN_WIRE_VERSION`] and this version, the server picks the newest one it supports.
+pub const WIRE_VERSION: u32 = 2;
+
+/// Oldest version of the HTTP wire protocol that the client still supports, see [`WIRE_VERSION`].
+pub const MIN_WIRE_VERSION: u32 = 1;
+
+/// Optional features of the HTTP wire protocol that the client supports, enabled for a session if
+/// the server supports them as well.
+///
+/// - `streaming`: dialog responses are parsed frame by frame while they arrive.
+/// - `transcript_confirmation`: the hashes of all exchanged messages are compared after the final
+///   message, only requested if enabled using [`ComputeOptions::confirm_transcript`].
+/// - `encrypted_channel`: all messages of the protocol are encrypted end-to-end, only requested if
+///   enabled using [`ComputeOptions::encrypted_channel`].
+/// - `output_disclosure`: the output is disclosed to the server after it has been verified, only
+///   requested if enabled using [`ComputeOptions::disclose_output`].
+/// - `multi_round`: the session runs follow-up rounds on preprocessing reserved when the session
+///   is created, only requested if enabled using [`ComputeOptions::reserve_follow_up`].
+/// - `program_store`: the server stores the program, so that later sessions only send its circuit
+///   hash, only requested if enabled using [`ComputeOptions::reference_stored_programs`].
+///
+/// Servers ignore capabilities that they do not know, so that clients can advertise new
+/// capabilities without breaking older servers. There is no capability for compressing the
+/// messages, which consist of garbled tables and labels that do not compress, or for reversing
+/// the roles of client and server.
+pub const CAPABILITIES: &[&str] = &[
+    "streaming",
+    "transcript_confirmation",
+    "encrypted_channel",
+    "output_disclosure",
+    "multi_round",
+    "program_store",
+];
+
+/// Header identifying all requests of a single computation.
+///
+/// A random trace id is generated for each computation unless the [`ComputeOptions`] already set
+/// this header. Servers log the trace id and include it in their error responses, which are
+/// returned as [`Error::ServerError`] together with the trace id.
+pub const TRACE_ID_HEADER: &str = "X-Tandem-Trace-Id";
+
+/// Size in bytes of the server's final message above which the client asks the server to stage
+/// the message for a separate download instead of sending it as part of the dialog.
+///
+/// Interrupted downloads are resumed, so that large outputs do not need to be sent again.
+pub const FINAL_DOWNLOAD_THRESHOLD: usize = 1024 * 1024;
+
+/// Number of attempts to complete the download of a staged final message.
+const MAX_DOWNLOAD_ATTEMPTS: usize = 3;
+
+/// Number of attempts to create a session if the request fails with a retryable error.
+const MAX_SESSION_ATTEMPTS: usize = 3;
+
+/// Number of attempts to send a dialog request if it fails with a retryable error.
+const MAX_DIALOG_ATTEMPTS: usize = 3;
 
 /// An MPC program that was type-checked and can be executed by the Tandem engine.
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -43,33 +164,113 @@ pub struct MpcProgram {
     function_name: String,
     ast: tandem_garble_interop::TypedProgram,
     circuit: tandem_garble_interop::TypedCircuit,
+    contributor_input: TypeDescription,
+    evaluator_input: TypeDescription,
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
 impl MpcProgram {
     /// Type-checks the specified function, returning a compiled program.
+    ///
+    /// Compiled programs are cached in memory and keyed by the hash of their source code and
+    /// function, so that compiling the same function again returns a clone of the cached program,
+    /// see [`clear_cache`].
     #[cfg_attr(target_arch = "wasm32", wasm_bindgen(constructor))]
     pub fn new(source_code: String, function_name: String) -> Result<MpcProgram, Error> {
+        Self::compile(source_code, function_name, None)
+    }
+
+    /// Returns the number of gates in the circuit as a formatted string.
+    ///
+    /// E.g. "79k gates (XOR: 44k, NOT: 13k, AND: 21k)"
+    pub fn report_gates(&self) -> String {
+        self.circuit.info_about_gates.to_string()
+    }
+
+    /// Returns the first `max_gates` gates of the circuit as a Graphviz DOT graph, with the input
+    /// and output wires labelled by the names of the Garble parameters.
+    pub fn to_dot(&self, max_gates: usize) -> String {
+        circuit_to_dot(&self.ast, &self.circuit, max_gates)
+    }
+}
+
+impl MpcProgram {
+    /// Type-checks the specified function like [`MpcProgram::new`], but reuses the compiled circuit
+    /// from the cache (or caches the circuit after compiling it).
+    pub fn new_cached(
+        source_code: String,
+        function_name: String,
+        cache: &CircuitCache,
+    ) -> Result<MpcProgram, Error> {
+        Self::compile(source_code, function_name, Some(cache))
+    }
+
+    fn compile(
+        source_code: String,
+        function_name: String,
+        cache: Option<&CircuitCache>,
+    ) -> Result<MpcProgram, Error> {
         let source_code = source_code.trim().to_string();
+        let key = program_cache::key(&source_code, &function_name);
+        if let Some(program) = program_cache::get(&key) {
+            return Ok(program);
+        }
         let ast = check_program(&source_code).map_err(GarbleCompileTimeError)?;
-        let circuit = compile_program(&ast, &function_name).map_err(GarbleCompileTimeError)?;
+        let circuit = match cache {
+            Some(cache) => cache.compile(&source_code, &ast, &function_name),
+            None => compile_program(&ast, &function_name),
+        }
+        .map_err(GarbleCompileTimeError)?;
+        let program = Self::from_circuit(source_code, function_name, ast, circuit)?;
+        program_cache::insert(key, &program);
+        Ok(program)
+    }
 
+    /// Builds the program from the type-checked source code and the compiled circuit of the
+    /// function, which must be a 2-party function.
+    fn from_circuit(
+        source_code: String,
+        function_name: String,
+        ast: tandem_garble_interop::TypedProgram,
+        circuit: TypedCircuit,
+    ) -> Result<MpcProgram, Error> {
         if circuit.fn_def.params.len() != 2 {
             return Err(ValidationError::GarbleProgramIsNoTwoPartyFunction.into());
         }
+        let describe = |role| {
+            let ty = tandem_garble_interop::input_type(role, &circuit.fn_def);
+            describe_type(&ast, ty).ok_or_else(|| {
+                GarbleCompileTimeError(format!("The input type {ty} cannot be described"))
+            })
+        };
+        let contributor_input = describe(Role::Contributor)?;
+        let evaluator_input = describe(Role::Evaluator)?;
         Ok(Self {
             source_code,
             function_name,
             ast,
             circuit,
+            contributor_input,
+            evaluator_input,
         })
     }
 
-    /// Returns the number of gates in the circuit as a formatted string.
-    ///
-    /// E.g. "79k gates (XOR: 44k, NOT: 13k, AND: 21k)"
-    pub fn report_gates(&self) -> String {
-        self.circuit.info_about_gates.to_string()
+    /// Returns the type of the input of the party, which can be used to generate input forms or
+    /// to validate inputs before they are parsed.
+    pub fn input_type(&self, role: Role) -> &TypeDescription {
+        match role {
+            Role::Contributor => &self.contributor_input,
+            Role::Evaluator => &self.evaluator_input,
+        }
+    }
+
+    /// Returns the number of bits that the input of the party is encoded as, which is the same for
+    /// all valid inputs.
+    pub fn expected_input_bits(&self, role: Role) -> usize {
+        match role {
+            Role::Contributor => self.circuit.gates.contrib_inputs(),
+            Role::Evaluator => self.circuit.gates.eval_inputs(),
+        }
     }
 }
 
@@ -78,6 +279,29 @@ impl MpcProgram {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MpcData {
     literal: Literal,
+    /// Only set for the output of a computation.
+    #[serde(skip)]
+    verification: Option<OutputVerification>,
+}
+
+/// How the output of a computation was verified, see [`MpcData::verification`].
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct OutputVerification {
+    /// Whether all MAC checks succeeded, which is always the case for outputs returned by
+    /// [`compute`], as the computation fails otherwise.
+    pub verified: bool,
+    /// Number of MAC checks of the server's shares of the AND gates and the output masks.
+    pub mac_checks: usize,
+}
+
+impl From<&OutputReport> for OutputVerification {
+    fn from(report: &OutputReport) -> Self {
+        Self {
+            verified: report.verified,
+            mac_checks: report.mac_checks(),
+        }
+    }
 }
 
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
@@ -129,7 +353,10 @@ impl MpcData {
             &input,
         )
         .map_err(GarbleCompileTimeError)?;
-        Ok(MpcData { literal })
+        Ok(MpcData {
+            literal,
+            verification: None,
+        })
     }
 
     /// Type-checks a Garble literal, returning it as MpcData.
@@ -204,7 +431,42 @@ impl MpcData {
                 )),
             ));
         }
-        Ok(MpcData { literal })
+        Ok(MpcData {
+            literal,
+            verification: None,
+        })
+    }
+
+    /// Builds and type-checks the literal of the builder as MpcData.
+    /// ```
+    /// use tandem_http_client::{LiteralBuilder, MpcData, MpcProgram};
+    ///
+    /// let source_code = "pub fn card_guess(house: Card, player: Card) -> bool { house == player }
+    /// struct Card { suit: Suit, value: u8 }
+    /// enum Suit { Diamonds, Hearts }";
+    /// let program = MpcProgram::new(source_code.to_string(), "card_guess".to_string()).unwrap();
+    ///
+    /// let card = LiteralBuilder::struct_("Card")
+    ///     .field("suit", LiteralBuilder::enum_("Suit", "Diamonds"))
+    ///     .field("value", 11u8);
+    /// let player_card = MpcData::from_builder(&program, card).unwrap();
+    ///
+    /// assert_eq!(
+    ///     player_card.to_literal_string(),
+    ///     "Card {suit: Suit::Diamonds, value: 11u8}"
+    /// );
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_builder(program: &MpcProgram, builder: LiteralBuilder) -> Result<MpcData, Error> {
+        let expected_type =
+            tandem_garble_interop::input_type(Role::Evaluator, &program.circuit.fn_def);
+        let literal = builder
+            .build(&program.ast, expected_type)
+            .map_err(|e| Error::ValidationError(ValidationError::GarbleCompileTimeError(e)))?;
+        Ok(MpcData {
+            literal,
+            verification: None,
+        })
     }
 
     /// Parses and type-checks a Garble literal in its JSON representation as MpcData.
@@ -277,16 +539,64 @@ impl MpcData {
     pub fn from_object(program: &MpcProgram, literal: JsValue) -> Result<MpcData, Error> {
         let literal: Literal =
             serde_wasm_bindgen::from_value(literal).map_err(|e| Error::JsonError(e.to_string()))?;
-        let expected_type =
-            tandem_garble_interop::input_type(Role::Evaluator, &program.circuit.fn_def);
-        if !literal.is_of_type(&program.ast, &expected_type) {
-            return Err(Error::ValidationError(
-                ValidationError::GarbleCompileTimeError(format!(
-                    "Input literal is not of the type {expected_type}"
-                )),
-            ));
-        }
-        Ok(MpcData { literal })
+        Self::checked_input(program, literal)
+    }
+
+    /// Deserializes and type-checks bytes returned by [`MpcData::to_bytes`] as MpcData.
+    ///
+    /// In JavaScript, the bytes are a `Uint8Array`, whose buffer can be transferred to or from a
+    /// Web Worker without copying it.
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen(js_name = fromBytes))]
+    pub fn from_bytes(program: &MpcProgram, bytes: &[u8]) -> Result<MpcData, Error> {
+        let literal: Literal = bincode::deserialize(bytes)?;
+        Self::checked_input(program, literal)
+    }
+
+    /// Serializes MpcData into bytes, which can be deserialized using [`MpcData::from_bytes`].
+    #[cfg_attr(target_arch = "wasm32", wasm_bindgen(js_name = toBytes))]
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        Ok(bincode::serialize(&self.literal)?)
+    }
+
+    /// Parses and type-checks a Garble literal in its JSON representation as MpcData.
+    ///
+    /// This is the native equivalent of `MpcData::from_object` on wasm32, see
+    /// [`tandem_garble_interop::literal_from_json`] for the JSON representation.
+    /// ```
+    /// let source_code = "pub fn add(x: u8, y: (u8, bool)) -> u8 {
+    ///     if y.1 { x + y.0 } else { x }
+    /// }";
+    ///
+    /// let program =
+    ///     tandem_http_client::MpcProgram::new(source_code.to_string(), "add".to_string()).unwrap();
+    ///
+    /// let json = r#"{"Tuple": [{"NumUnsigned": [7, "U8"]}, "True"]}"#;
+    /// let input = tandem_http_client::MpcData::from_json(&program, json).unwrap();
+    ///
+    /// assert_eq!(input.to_literal_string(), "(7u8, true)");
+    /// assert_eq!(input.to_json().unwrap(), r#"{"Tuple":[{"NumUnsigned":[7,"U8"]},"True"]}"#);
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_json(program: &MpcProgram, json: &str) -> Result<MpcData, Error> {
+        let literal = tandem_garble_interop::parse_json_input(
+            Role::Evaluator,
+            &program.ast,
+            &program.circuit.fn_def,
+            json,
+        )
+        .map_err(Error::JsonError)?;
+        Ok(MpcData {
+            literal,
+            verification: None,
+        })
+    }
+
+    /// Returns MpcData as a Garble literal in its JSON representation.
+    ///
+    /// This is the native equivalent of `MpcData::to_literal` on wasm32, see [`MpcData::from_json`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn to_json(&self) -> Result<String, Error> {
+        tandem_garble_interop::literal_to_json(&self.literal).map_err(Error::JsonError)
     }
 
     /// Returns MpcData as a Garble literal string.
@@ -296,6 +606,12 @@ impl MpcData {
         format!("{}", self.literal)
     }
 
+    /// Returns how the output of a computation was verified, or `None` if the data was not
+    /// returned by a computation.
+    pub fn verification(&self) -> Option<OutputVerification> {
+        self.verification
+    }
+
     /// Returns MpcData as a Garble literal in its JSON representation.
     ///
     /// See [`MpcData::from_object`] for the format of the JsValue returned here.
@@ -305,6 +621,25 @@ impl MpcData {
     }
 }
 
+impl MpcData {
+    /// Returns the literal as input of the program, if it is of the program's input type.
+    fn checked_input(program: &MpcProgram, literal: Literal) -> Result<MpcData, Error> {
+        let expected_type =
+            tandem_garble_interop::input_type(Role::Evaluator, &program.circuit.fn_def);
+        if !literal.is_of_type(&program.ast, expected_type) {
+            return Err(Error::ValidationError(
+                ValidationError::GarbleCompileTimeError(format!(
+                    "Input literal is not of the type {expected_type}"
+                )),
+            ));
+        }
+        Ok(MpcData {
+            literal,
+            verification: None,
+        })
+    }
+}
+
 /// Computes the specified program using Multi-Party Computation, keeping the input private.
 ///
 /// A Tandem server must be running at the specified url, to provide the contributor's input.
@@ -318,201 +653,1902 @@ pub async fn compute(
     program: MpcProgram,
     input: MpcData,
 ) -> Result<MpcData, Error> {
-    let url = Url::parse(&url)?;
-
-    let my_input = input.literal.as_bits(&program.ast);
-
-    let expected_input_len = program
-        .circuit
-        .gates
-        .gates()
-        .iter()
-        .filter(|&gate| gate == &tandem::Gate::InEval)
-        .count();
-
-    if expected_input_len != my_input.len() {
-        return Err(ValidationError::InvalidInput.into());
-    }
-
-    let client = TandemClient::new(&url);
-    let TypedCircuit { gates, fn_def, .. } = program.circuit;
-    let session = client
-        .new_session(
-            &gates,
-            program.source_code.clone(),
-            program.function_name.clone(),
-            plaintext_metadata,
-        )
-        .await?;
-    let result = session.evaluate(gates, my_input).await?;
-    let literal =
-        deserialize_output(&program.ast, &fn_def, &result).map_err(GarbleCompileTimeError)?;
-    Ok(MpcData { literal })
+    TandemClient::new(&url, ComputeOptions::default())?
+        .compute(program, input, plaintext_metadata)
+        .await
 }
 
-type MessageLog = Vec<(Msg, MessageId)>;
-
-#[derive(Debug)]
-struct TandemClient {
-    url: Url,
+/// Computes the program like [`compute`], applying the specified [`ComputeOptions`].
+///
+/// JavaScript uses `computeWithOptions` instead, which accepts the options as a plain object.
+pub async fn compute_with_options(
+    url: String,
+    plaintext_metadata: String,
+    program: MpcProgram,
+    input: MpcData,
+    options: ComputeOptions,
+) -> Result<MpcData, Error> {
+    TandemClient::new(&url, options)?
+        .compute(program, input, plaintext_metadata)
+        .await
 }
 
-struct TandemSession {
-    url: Url,
-    request_headers: HashMap<String, String>,
+/// Computes the program like [`compute_with_options`], additionally returning the round trips,
+/// bandwidth and timing of the computation, see [`ComputeReport`].
+///
+/// JavaScript uses `computeWithReport` instead, which accepts the options as a plain object.
+pub async fn compute_with_report(
+    url: String,
+    plaintext_metadata: String,
+    program: MpcProgram,
+    input: MpcData,
+    options: ComputeOptions,
+) -> Result<(MpcData, ComputeReport), Error> {
+    TandemClient::new(&url, options)?
+        .compute_with_report(program, input, plaintext_metadata)
+        .await
 }
 
-#[derive(Serialize, Debug)]
-struct NewSession {
+/// Computes a raw circuit using Multi-Party Computation, bypassing the Garble compiler, for
+/// circuits that are generated by other tools (such as [`Circuit::from_bristol_fashion`]).
+///
+/// The input bits are the evaluator's inputs of the circuit, the output bits are returned in the
+/// order of the circuit's output gates. The server's handler must accept raw circuits, which the
+/// server checks against its allowlist and limits like any compiled program.
+pub async fn compute_circuit(
+    url: String,
+    circuit: Circuit,
+    input_bits: Vec<bool>,
     plaintext_metadata: String,
-    program: String,
-    function: String,
-    circuit_hash: CircuitBlake3Hash,
-    client_version: String,
+) -> Result<Vec<bool>, Error> {
+    TandemClient::new(&url, ComputeOptions::default())?
+        .compute_circuit(circuit, input_bits, plaintext_metadata)
+        .await
 }
 
-#[derive(Deserialize, Debug, PartialEq, Eq)]
-struct EngineCreationResult {
-    engine_id: String,
-    request_headers: HashMap<String, String>,
-    server_version: String,
+/// Options of a computation, see [`compute_with_options`].
+///
+/// JavaScript passes the options to `computeWithOptions` as a plain object instead.
+#[derive(Debug, Clone, Default)]
+pub struct ComputeOptions {
+    headers: HashMap<String, String>,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    require_session_commitment: bool,
+    confirm_transcript: bool,
+    encrypted_channel: bool,
+    server_channel_key: Option<ChannelKey>,
+    disclose_output: bool,
+    follow_ups: Option<FollowUps>,
+    stored_programs: Option<StoredPrograms>,
+    max_queue_bytes: Option<usize>,
 }
 
-impl TandemClient {
-    fn new(url: &Url) -> Self {
-        Self { url: url.clone() }
+impl ComputeOptions {
+    /// Returns the default options, which send no additional headers.
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    async fn new_session<'a, 'b>(
-        &'a self,
-        circuit: &Circuit,
-        source_code: String,
-        function: String,
-        plaintext_metadata: String,
-    ) -> Result<TandemSession, Error> {
-        let client_version = env!("CARGO_PKG_VERSION").to_string();
-        let req = NewSession {
-            plaintext_metadata,
-            program: source_code,
-            function,
-            circuit_hash: circuit.blake3_hash(),
-            client_version: client_version.clone(),
-        };
-        let EngineCreationResult {
-            engine_id,
-            request_headers,
-            server_version: _server_version,
-        } = send_new_session(self.url.clone(), &req).await?;
-        let url = self.url.join(&engine_id)?;
+    /// Adds a header that is sent with every request of the session, replacing any previously
+    /// added header of the same name.
+    pub fn header(mut self, name: String, value: String) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
 
-        Ok(TandemSession {
-            url,
-            request_headers,
-        })
+    /// Authenticates every request of the session using the bearer token, by sending it as an
+    /// `Authorization: Bearer <token>` header.
+    pub fn bearer_token(self, token: String) -> Self {
+        self.header("Authorization".to_string(), format!("Bearer {token}"))
     }
-}
 
-impl TandemSession {
-    async fn evaluate(self, circuit: Circuit, input: Vec<bool>) -> Result<Vec<bool>, Error> {
-        let mut context = MsgQueue::new();
-        let mut evaluator =
-            tandem::states::Evaluator::new(circuit, input, ChaCha20Rng::from_entropy())?;
+    /// Adds headers that are sent with every request of the session, such as the `Authorization`
+    /// header required by an API gateway in front of the server.
+    ///
+    /// These headers are sent in addition to the headers that the server asks the client to
+    /// include in all requests after the session has been created (for example to route them to
+    /// the same server instance). If both use the same name, the header of the server is sent.
+    pub fn headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.headers.extend(headers);
+        self
+    }
 
-        let mut last_durably_received_offset: Option<MessageId> = None;
-        let mut steps_remaining = evaluator.steps();
-        loop {
-            let messages: Vec<(&Msg, MessageId)> = context.msgs_iter().collect();
-            let (upstream_msgs, server_commited_offset) =
-                self.dialog(last_durably_received_offset, &messages).await?;
-            if messages.last().map(|v| v.1) != server_commited_offset {
-                return Err(Error::MessageOffsetMismatch);
-            }
+    /// Fails requests that cannot connect to the server within the timeout, which is not
+    /// supported (and thus ignored) in wasm.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
 
-            if let Some(last_durably_received_offset) = server_commited_offset {
-                context.flush_queue(last_durably_received_offset);
-            }
+    /// Fails requests whose response has not been received completely within the timeout, which
+    /// is not supported (and thus ignored) in wasm.
+    pub fn request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
 
-            for (msg, server_offset) in &upstream_msgs {
-                if *server_offset != last_durably_received_offset.map(|o| o + 1).unwrap_or(0) {
-                    return Err(Error::MessageOffsetMismatch);
-                }
+    /// Fails the computation with [`Error::DeadlineExceeded`] if it does not complete within the
+    /// timeout, by limiting the time of each request to the time remaining.
+    ///
+    /// The timeouts only apply to requests sent over HTTP. In wasm, requests cannot be cancelled,
+    /// the computation thus only fails once the deadline has passed before sending a request.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
 
-                if steps_remaining > 0 {
-                    let (next_state, msg) = evaluator.run(msg)?;
-                    evaluator = next_state;
-                    steps_remaining -= 1;
-                    context.send(msg);
-                } else {
-                    return Ok(evaluator.output(msg)?);
-                }
-                last_durably_received_offset = Some(*server_offset);
-            }
-        }
+    /// Fails the computation with [`Error::SessionCommitmentMismatch`] if the server does not
+    /// commit to the created session.
+    ///
+    /// The client always sends a random nonce when it creates a session and checks the commitment
+    /// of the server to the nonce, the circuit, the function and the negotiated protocol
+    /// configuration, which detects results that were mixed up with other sessions. By default,
+    /// sessions of older servers that do not send any commitment are accepted nevertheless.
+    ///
+    /// The commitment can only be computed by the server (and not by a middlebox that replays or
+    /// alters the results, for example by stripping capabilities) if it is keyed by an encrypted
+    /// channel that authenticates the server, see [`ComputeOptions::server_channel_key`]. Without
+    /// such a channel, the commitment is computed over public values and does not protect against
+    /// an active attacker, which is still the job of TLS.
+    pub fn require_session_commitment(mut self) -> Self {
+        self.require_session_commitment = true;
+        self
     }
 
-    async fn dialog(
-        &self,
-        last_durably_received_offset: Option<u32>,
-        messages: &[(&Msg, MessageId)],
-    ) -> Result<(MessageLog, Option<MessageId>), Error> {
-        send_msgs(
-            self.url.clone(),
-            &self.request_headers,
-            last_durably_received_offset,
-            messages,
-        )
-        .await
+    /// Confirms that client and server exchanged the same messages, by comparing the hashes of
+    /// all messages after the final message of the server.
+    ///
+    /// The client only decrypts the output if the hash of the server matches, otherwise the
+    /// computation fails with a [`tandem::Error::TranscriptMismatch`], and then sends its own
+    /// hash to the server, which costs an additional request. This detects messages that were
+    /// corrupted in transit (for example by a middlebox) even where the MAC checks of the protocol
+    /// would not. Servers that do not support the confirmation skip it.
+    pub fn confirm_transcript(mut self) -> Self {
+        self.confirm_transcript = true;
+        self
     }
-}
 
-async fn send_new_session(url: Url, session: &NewSession) -> Result<EngineCreationResult, Error> {
-    let client = reqwest::Client::new();
-    let resp = client.post(url).json(session).send().await?;
-    let resp = resp_or_err(resp).await?;
-    Ok(resp.json::<EngineCreationResult>().await?)
-}
+    /// Encrypts all messages of the protocol using keys agreed on with the server when the session
+    /// is created, so that they stay confidential even if the connection to the server is not
+    /// (for example if TLS is terminated at a proxy that is not trusted).
+    ///
+    /// The computation fails with [`Error::EncryptedChannelUnavailable`] if the server does not
+    /// support encrypted channels. Without a pinned [`ComputeOptions::server_channel_key`], the
+    /// channel only protects against passive observers, not against a proxy impersonating the
+    /// server.
+    pub fn encrypted_channel(mut self) -> Self {
+        self.encrypted_channel = true;
+        self
+    }
 
-async fn send_msgs(
-    url: Url,
-    request_headers: &HashMap<String, String>,
-    last_durably_received_offset: Option<u32>,
-    msgs: &[(&Msg, MessageId)],
-) -> Result<(MessageLog, Option<MessageId>), Error> {
-    let client = reqwest::Client::new();
-    let body = bincode::serialize(&(last_durably_received_offset, msgs))?;
-    let mut req = client.post(url).body(body);
-    for (k, v) in request_headers.iter() {
-        req = req.header(k, v);
+    /// Encrypts all messages like [`ComputeOptions::encrypted_channel`] and authenticates the
+    /// server using the public key of its `channel_secret_key`.
+    ///
+    /// The computation fails with [`Error::EncryptedChannelUnavailable`] if the server does not
+    /// authenticate itself using this key.
+    pub fn server_channel_key(mut self, key: ChannelKey) -> Self {
+        self.encrypted_channel = true;
+        self.server_channel_key = Some(key);
+        self
     }
-    let resp = req.send().await?;
-    let resp = resp_or_err(resp).await?;
-    Ok(bincode::deserialize(&resp.bytes().await?)?)
-}
 
-async fn resp_or_err(resp: Response) -> Result<Response, Error> {
-    if resp.status().is_success() {
-        Ok(resp)
-    } else {
-        let e = resp.text().await?;
-        let e = match serde_json::from_str::<ErrorJson>(&e) {
-            Ok(ErrorJson { error, args }) => format!("{error}: {args}"),
-            Err(_) => e,
-        };
-        Err(Error::ServerError(e))
+    /// Agrees to disclose the output to servers whose handlers process it (for example to log or
+    /// persist the results of the computations they participate in), which servers can require.
+    ///
+    /// The output is only disclosed once it has been verified, in one additional request. The
+    /// server checks the disclosed output against its own keys, so it learns the actual output.
+    /// Without this option, sessions of servers that require the output fail with an error.
+    pub fn disclose_output(mut self) -> Self {
+        self.disclose_output = true;
+        self
     }
-}
 
-#[derive(Deserialize)]
-struct ErrorJson {
-    error: String,
-    args: String,
+    /// Reserves a follow-up round for the program, which can be run on the same session (once its
+    /// previous round is completed) using [`TandemSession::next`], without creating a new session.
+    ///
+    /// The preprocessing of all reserved rounds is run together with the first round, which makes
+    /// the first round slower but saves the setup and preprocessing of each follow-up round. The
+    /// program of each follow-up round must be a function of the program of the session, and can
+    /// be reserved more than once. Sessions of servers that do not support follow-up rounds fail
+    /// with [`Error::FollowUpUnavailable`] once the next round is requested.
+    pub fn reserve_follow_up(mut self, program: &MpcProgram) -> Self {
+        let circuit = &program.circuit.gates;
+        let follow_ups = self.follow_ups.get_or_insert_with(FollowUps::default);
+        follow_ups.and_gates += circuit.and_gates();
+        follow_ups.input_bits += circuit.contrib_inputs() + circuit.eval_inputs();
+        self
+    }
+
+    /// Sends only the circuit hash instead of the source code of programs that the server has
+    /// already stored, which saves bandwidth and does not reveal the formatting of the program.
+    ///
+    /// The first session of each program still sends the program, which servers that support it
+    /// then store for later sessions. Which programs the servers stored is tracked by the options
+    /// (shared with all their clones) per server url. If a server no longer stores the program
+    /// (for example after a restart), the session is created again using the full program.
+    pub fn reference_stored_programs(mut self) -> Self {
+        self.stored_programs
+            .get_or_insert_with(StoredPrograms::default);
+        self
+    }
+
+    /// Limits the messages that the client keeps until the server acknowledges them to the
+    /// specified number of bytes.
+    ///
+    /// Once the limit is reached, the client pauses processing the messages of the server and
+    /// sends its queued messages first, which costs additional round trips but bounds the memory
+    /// of the session if the server falls behind. A single message can still exceed the limit.
+    pub fn max_queue_bytes(mut self, bytes: usize) -> Self {
+        self.max_queue_bytes = Some(bytes);
+        self
+    }
 }
 
-/// Errors occurring during the validation or the execution of the MPC protocol.
+/// The programs stored by servers for later sessions, as the url of the server and the hash of
+/// the circuit, see [`ComputeOptions::reference_stored_programs`].
+#[derive(Debug, Clone, Default)]
+struct StoredPrograms(Arc<Mutex<HashSet<(Url, CircuitBlake3Hash)>>>);
+
+impl StoredPrograms {
+    fn contains(&self, url: &Url, circuit_hash: &CircuitBlake3Hash) -> bool {
+        let programs = self.0.lock().unwrap();
+        programs.contains(&(url.clone(), *circuit_hash))
+    }
+
+    fn insert(&self, url: &Url, circuit_hash: CircuitBlake3Hash) {
+        self.0.lock().unwrap().insert((url.clone(), circuit_hash));
+    }
+
+    fn remove(&self, url: &Url, circuit_hash: &CircuitBlake3Hash) {
+        self.0.lock().unwrap().remove(&(url.clone(), *circuit_hash));
+    }
+}
+
+/// The size of the follow-up rounds reserved by [`ComputeOptions::reserve_follow_up`].
+#[derive(Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct FollowUps {
+    and_gates: usize,
+    input_bits: usize,
+}
+
+/// Computes the program like [`compute`], sending structured plaintext metadata to the server.
+///
+/// The metadata can be any JSON value, which the server's handler receives both as a JSON value
+/// and as its (compact) JSON serialization in place of a plain string. A JSON string is sent
+/// exactly like the metadata of [`compute`], other values are rejected by older servers that only
+/// support plain strings.
+pub async fn compute_with_metadata_json(
+    url: String,
+    plaintext_metadata: serde_json::Value,
+    program: MpcProgram,
+    input: MpcData,
+) -> Result<MpcData, Error> {
+    TandemClient::new(&url, ComputeOptions::default())?
+        .compute_with_metadata_json(program, input, plaintext_metadata)
+        .await
+}
+
+/// A client computing programs with the Tandem server at a url, set up once and reused by all of
+/// its computations.
+///
+/// The [`ComputeOptions`], the pool of HTTP connections and the retry policy are shared by all
+/// computations of the client. Cloning the client is cheap, clones share the same connections, so
+/// that concurrent computations can each use a clone of the client:
+///
+/// ```no_run
+/// # use tandem_http_client::{ComputeOptions, Error, MpcData, MpcProgram, TandemClient};
+/// # async fn run(program: MpcProgram, inputs: Vec<MpcData>) -> Result<(), Error> {
+/// let client = TandemClient::new("http://localhost:8000", ComputeOptions::new())?;
+/// let computations = inputs.into_iter().map(|input| {
+///     let client = client.clone();
+///     let program = program.clone();
+///     async move { client.compute(program, input, "metadata".to_string()).await }
+/// });
+/// let outputs = futures::future::try_join_all(computations).await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct TandemClient {
+    transport: Transport,
+    url: Url,
+    options: ComputeOptions,
+}
+
+impl TandemClient {
+    /// Creates a client for the Tandem server at the url, applying the options to every
+    /// computation.
+    pub fn new(url: &str, options: ComputeOptions) -> Result<Self, Error> {
+        Ok(Self {
+            transport: Transport::Http(timeouts::client(&options)?),
+            url: Url::parse(url)?,
+            options,
+        })
+    }
+
+    /// Computes the program like [`compute`], using the connections and options of the client.
+    ///
+    /// Each computation runs in its own session with its own trace id, the overall timeout of the
+    /// options starts when the computation starts.
+    pub async fn compute(
+        &self,
+        program: MpcProgram,
+        input: MpcData,
+        plaintext_metadata: String,
+    ) -> Result<MpcData, Error> {
+        let plaintext_metadata = serde_json::Value::String(plaintext_metadata);
+        self.compute_with_metadata_json(program, input, plaintext_metadata)
+            .await
+    }
+
+    /// Computes the program like [`compute_with_metadata_json`], using the connections and
+    /// options of the client.
+    pub async fn compute_with_metadata_json(
+        &self,
+        program: MpcProgram,
+        input: MpcData,
+        plaintext_metadata: serde_json::Value,
+    ) -> Result<MpcData, Error> {
+        let (output, _) = self
+            .compute_monitored(program, input, plaintext_metadata, &())
+            .await?;
+        Ok(output)
+    }
+
+    /// Computes the program like [`TandemClient::compute`], additionally returning the round
+    /// trips, bandwidth and timing of the computation, see [`ComputeReport`].
+    pub async fn compute_with_report(
+        &self,
+        program: MpcProgram,
+        input: MpcData,
+        plaintext_metadata: String,
+    ) -> Result<(MpcData, ComputeReport), Error> {
+        let plaintext_metadata = serde_json::Value::String(plaintext_metadata);
+        self.compute_monitored(program, input, plaintext_metadata, &())
+            .await
+    }
+
+    /// Computes the raw circuit like [`compute_circuit`], using the connections and options of the
+    /// client.
+    ///
+    /// Follow-up rounds and stored programs are not available for raw circuits, the corresponding
+    /// options are ignored.
+    pub async fn compute_circuit(
+        &self,
+        circuit: Circuit,
+        input_bits: Vec<bool>,
+        plaintext_metadata: String,
+    ) -> Result<Vec<bool>, Error> {
+        compute_raw_circuit(
+            &self.transport,
+            self.url.clone(),
+            &self.options,
+            serde_json::Value::String(plaintext_metadata),
+            circuit,
+            input_bits,
+        )
+        .await
+    }
+
+    /// Creates a session for the program with the server, without evaluating it yet.
+    ///
+    /// The session is evaluated step by step using [`TandemSession::step`], which allows callers
+    /// to interleave the computation with their own control flow, or completely using
+    /// [`TandemSession::evaluate`]. A session that is no longer needed should be deleted using
+    /// [`TandemSession::delete`], otherwise the server drops it after a timeout.
+    pub async fn new_session(
+        &self,
+        program: MpcProgram,
+        input: MpcData,
+        plaintext_metadata: serde_json::Value,
+    ) -> Result<TandemSession, Error> {
+        new_session(
+            &self.transport,
+            self.url.clone(),
+            &self.options,
+            plaintext_metadata,
+            program,
+            input,
+            None,
+        )
+        .await
+    }
+
+    /// Computes the program, reporting its progress to the monitor and aborting the computation
+    /// once the monitor cancels it.
+    async fn compute_monitored(
+        &self,
+        program: MpcProgram,
+        input: MpcData,
+        plaintext_metadata: serde_json::Value,
+        monitor: &impl Monitor,
+    ) -> Result<(MpcData, ComputeReport), Error> {
+        let session = self.new_session(program, input, plaintext_metadata).await?;
+        session.evaluate_monitored(monitor).await
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn compute_session(
+    transport: &Transport,
+    url: Url,
+    options: &ComputeOptions,
+    plaintext_metadata: serde_json::Value,
+    program: MpcProgram,
+    input: MpcData,
+    approval_id: Option<String>,
+    monitor: &impl Monitor,
+) -> Result<MpcData, Error> {
+    let session = new_session(
+        transport,
+        url,
+        options,
+        plaintext_metadata,
+        program,
+        input,
+        approval_id,
+    )
+    .await?;
+    let (output, _) = session.evaluate_monitored(monitor).await?;
+    Ok(output)
+}
+
+async fn new_session(
+    transport: &Transport,
+    url: Url,
+    options: &ComputeOptions,
+    plaintext_metadata: serde_json::Value,
+    program: MpcProgram,
+    input: MpcData,
+    approval_id: Option<String>,
+) -> Result<TandemSession, Error> {
+    let my_input = input.literal.as_bits(&program.ast);
+
+    if program.expected_input_bits(Role::Evaluator) != my_input.len() {
+        return Err(ValidationError::InvalidInput.into());
+    }
+
+    let computation = Computation::new(transport, &url, options);
+    let computed = Computed::Program(Box::new(program));
+    computation
+        .new_session(computed, my_input, plaintext_metadata, approval_id)
+        .await
+}
+
+async fn compute_raw_circuit(
+    transport: &Transport,
+    url: Url,
+    options: &ComputeOptions,
+    plaintext_metadata: serde_json::Value,
+    circuit: Circuit,
+    input_bits: Vec<bool>,
+) -> Result<Vec<bool>, Error> {
+    let circuit = ValidatedCircuit::new(circuit)?;
+    if circuit.eval_inputs() != input_bits.len() {
+        return Err(ValidationError::InvalidInput.into());
+    }
+
+    let computation = Computation::new(transport, &url, options);
+    let computed = Computed::Circuit(circuit);
+    let session = computation
+        .new_session(computed, input_bits, plaintext_metadata, None)
+        .await?;
+    let output = session.evaluate().await?;
+    // the output of a raw circuit is decoded as an array of booleans, see `OutputType::Bits`:
+    match output.literal {
+        Literal::Array(bits) => Ok(bits.iter().map(|b| *b == Literal::True).collect()),
+        _ => Ok(vec![]),
+    }
+}
+
+/// What a session computes, either a function of a Garble program or a raw circuit.
+enum Computed {
+    Program(Box<MpcProgram>),
+    Circuit(ValidatedCircuit),
+}
+
+/// How the output bits of a round are decoded.
+enum OutputType {
+    /// As a value of the return type of the program's function.
+    Literal(
+        Box<(
+            tandem_garble_interop::TypedProgram,
+            tandem_garble_interop::TypedFnDef,
+        )>,
+    ),
+    /// As an array of booleans, for raw circuits that have no types.
+    Bits,
+}
+
+type MessageLog = Vec<(Msg, MessageId)>;
+
+/// How the requests of a session reach the server.
+#[derive(Debug, Clone)]
+enum Transport {
+    /// Requests are sent to a (usually remote) server over HTTP, sharing the client's connections.
+    Http(reqwest::Client),
+    /// Requests are dispatched to a server running in the same process.
+    #[cfg(all(feature = "local", not(target_arch = "wasm32")))]
+    Local(LocalConnection),
+    /// Messages are exchanged with the gRPC service of a server.
+    #[cfg(all(feature = "grpc", not(target_arch = "wasm32")))]
+    Grpc(GrpcConnection),
+}
+
+/// A single computation, from the creation of its session until its output.
+#[derive(Debug)]
+struct Computation {
+    transport: Transport,
+    url: Url,
+    /// Headers of the [`ComputeOptions`], sent with every request.
+    headers: HashMap<String, String>,
+    timeouts: Timeouts,
+    require_session_commitment: bool,
+    confirm_transcript: bool,
+    encrypted_channel: bool,
+    server_channel_key: Option<ChannelKey>,
+    disclose_output: bool,
+    follow_ups: Option<FollowUps>,
+    stored_programs: Option<StoredPrograms>,
+    max_queue_bytes: Option<usize>,
+}
+
+/// A session of a computation with the server, created by [`TandemClient::new_session`].
+///
+/// The state of the evaluator is kept in memory, the session thus needs to be evaluated by the
+/// process that created it, before the server drops the session after its timeout.
+pub struct TandemSession {
+    transport: Transport,
+    url: Url,
+    /// Headers of the [`ComputeOptions`] together with the headers requested by the server.
+    request_headers: HashMap<String, String>,
+    final_url: Option<Url>,
+    /// Whether dialog responses are sent as frames, see [`CAPABILITIES`].
+    streaming: bool,
+    /// Whether the final message is followed by the transcript hash of the server, see
+    /// [`ComputeOptions::confirm_transcript`].
+    confirm_transcript: bool,
+    /// Encrypts the messages of the dialog, see [`ComputeOptions::encrypted_channel`].
+    channel: Option<SecureChannel>,
+    /// Whether the output is disclosed to the server, see [`ComputeOptions::disclose_output`].
+    disclose_output: bool,
+    /// The state of the follow-up rounds, see [`ComputeOptions::reserve_follow_up`].
+    rounds: Option<Rounds>,
+    timeouts: Timeouts,
+    output_type: OutputType,
+    plan: ProtocolPlan,
+    /// Taken while the evaluator processes a message, `None` once the session has finished.
+    evaluator: Option<Evaluator<ValidatedCircuit, Vec<bool>>>,
+    context: MsgQueue,
+    /// Messages of the server that have not been processed yet, initially the messages
+    /// piggybacked on the creation of the session.
+    upstream_msgs: Upstream,
+    last_durably_received_offset: Option<MessageId>,
+    steps_remaining: u32,
+    step: usize,
+    steps: usize,
+    /// The round trips, bandwidth and timing of the session so far, see [`ComputeReport`].
+    report: ComputeReport,
+}
+
+/// The state of a session with follow-up rounds.
+struct Rounds {
+    /// The source code of the program, whose functions are run by all rounds.
+    program: String,
+    /// The circuit and input of the first round, until the preprocessing is done.
+    first_round: Option<(ValidatedCircuit, Vec<bool>)>,
+    /// The triples that remain for the follow-up rounds, once the preprocessing is done.
+    triples: Option<PreprocessedTriples>,
+}
+
+impl fmt::Debug for TandemSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TandemSession")
+            .field("url", &self.url)
+            .field("progress", &self.progress())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct NewSession {
+    /// Sent as a plain JSON string unless the metadata is structured.
+    plaintext_metadata: serde_json::Value,
+    program: String,
+    function: String,
+    circuit_hash: CircuitBlake3Hash,
+    client_version: String,
+    protocol_version: u32,
+    /// The oldest supported wire version, for servers that predate the negotiation of wire
+    /// versions and require an exact match.
+    wire_version: u32,
+    wire_versions: Vec<u32>,
+    capabilities: Vec<String>,
+    stage_final: bool,
+    /// Only sent for servers that require sessions to be approved.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    approval_id: Option<String>,
+    /// Identifies retries of the same request, ignored by servers that predate idempotency keys.
+    idempotency_key: Option<String>,
+    /// Asks the server to commit to the session, ignored by servers that predate commitments.
+    session_nonce: [u8; 32],
+    /// The ephemeral key of the client, only sent if the messages are encrypted.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    channel_key: Option<ChannelKey>,
+    /// The reserved follow-up rounds, only sent if any were reserved.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    follow_ups: Option<FollowUps>,
+    /// The base64-encoded columnar layout of a raw circuit, sent instead of a program.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    circuit: Option<String>,
+}
+
+/// A request for the next round of a session, see [`TandemSession::next`].
+#[derive(Serialize, Debug)]
+struct NextRound {
+    plaintext_metadata: serde_json::Value,
+    function: String,
+    circuit_hash: CircuitBlake3Hash,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+struct NextRoundResult {
+    request_headers: HashMap<String, String>,
+    #[serde(default)]
+    messages: MessageLog,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+struct EngineCreationResult {
+    engine_id: String,
+    request_headers: HashMap<String, String>,
+    server_version: String,
+    /// Only set by servers that support staging the final message.
+    #[serde(default)]
+    final_url: Option<String>,
+    /// Only sent by servers that piggyback their initial messages, older servers send them as part
+    /// of the first dialog round instead.
+    #[serde(default)]
+    messages: MessageLog,
+    /// The capabilities negotiated for the session, never sent by servers that predate the
+    /// negotiation.
+    #[serde(default)]
+    capabilities: Vec<String>,
+    /// Never sent by servers that predate the negotiation of wire versions.
+    #[serde(default)]
+    wire_version: Option<u32>,
+    /// Never sent by servers that predate session commitments.
+    #[serde(default)]
+    session_commitment: Option<SessionCommitment>,
+    /// Only sent if an encrypted channel was negotiated.
+    #[serde(default)]
+    channel: Option<HandshakeReply>,
+}
+
+/// The commitment of the server to a session, see [`ComputeOptions::require_session_commitment`].
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+struct SessionCommitment {
+    server_nonce: [u8; 32],
+    commitment: [u8; 32],
+}
+
+/// The key derivation context of a [`SessionCommitment`], which must match the server's.
+const SESSION_COMMITMENT_CONTEXT: &str = "tandem 2025-01-01 session commitment v1";
+
+impl SessionCommitment {
+    /// Checks that the server committed to the session that the client requested, with the engine
+    /// id, wire version and capabilities that the server returned, keyed using the binding key of
+    /// the session's encrypted channel (if any).
+    fn verify(
+        &self,
+        req: &NewSession,
+        engine_id: &str,
+        wire_version: u32,
+        capabilities: &[String],
+        key: Option<&[u8; 32]>,
+    ) -> Result<(), Error> {
+        let committed = bincode::serialize(&(
+            &req.session_nonce,
+            &self.server_nonce,
+            engine_id,
+            &req.circuit_hash,
+            &req.function,
+            req.protocol_version,
+            &req.wire_versions,
+            &req.capabilities,
+            wire_version,
+            capabilities,
+            req.stage_final,
+        ))?;
+        let mut hasher = match key {
+            Some(key) => blake3::Hasher::new_keyed(key),
+            None => blake3::Hasher::new_derive_key(SESSION_COMMITMENT_CONTEXT),
+        };
+        let commitment: [u8; 32] = hasher.update(&committed).finalize().into();
+        if commitment == self.commitment {
+            Ok(())
+        } else {
+            Err(Error::SessionCommitmentMismatch)
+        }
+    }
+}
+
+impl Computation {
+    fn new(transport: &Transport, url: &Url, options: &ComputeOptions) -> Self {
+        let mut headers = options.headers.clone();
+        if !headers
+            .keys()
+            .any(|k| k.eq_ignore_ascii_case(TRACE_ID_HEADER))
+        {
+            headers.insert(TRACE_ID_HEADER.to_string(), random_id());
+        }
+        Self {
+            transport: transport.clone(),
+            url: url.clone(),
+            headers,
+            timeouts: Timeouts::start(options),
+            require_session_commitment: options.require_session_commitment,
+            confirm_transcript: options.confirm_transcript,
+            encrypted_channel: options.encrypted_channel,
+            server_channel_key: options.server_channel_key,
+            disclose_output: options.disclose_output,
+            follow_ups: options.follow_ups,
+            stored_programs: options.stored_programs.clone(),
+            max_queue_bytes: options.max_queue_bytes,
+        }
+    }
+
+    async fn new_session(
+        &self,
+        computed: Computed,
+        input: Vec<bool>,
+        plaintext_metadata: serde_json::Value,
+        approval_id: Option<String>,
+    ) -> Result<TandemSession, Error> {
+        let (source_code, function_name, circuit, output_type, raw_circuit) = match computed {
+            Computed::Program(program) => {
+                let MpcProgram {
+                    source_code,
+                    function_name,
+                    ast,
+                    circuit,
+                    ..
+                } = *program;
+                let TypedCircuit {
+                    gates: circuit,
+                    fn_def,
+                    ..
+                } = circuit;
+                let output_type = OutputType::Literal(Box::new((ast, fn_def)));
+                (source_code, function_name, circuit, output_type, None)
+            }
+            Computed::Circuit(circuit) => {
+                let mut columnar = vec![];
+                circuit
+                    .write_columnar(&mut columnar)
+                    .map_err(|_| tandem::Error::InvalidCircuit)?;
+                let raw_circuit = Some(STANDARD.encode(columnar));
+                (
+                    String::new(),
+                    String::new(),
+                    circuit,
+                    OutputType::Bits,
+                    raw_circuit,
+                )
+            }
+        };
+        // raw circuits have no program to run follow-up rounds of or to store:
+        let follow_ups = self.follow_ups.filter(|_| raw_circuit.is_none());
+        let client_version = env!("CARGO_PKG_VERSION").to_string();
+        let plan = ProtocolPlan::new(&circuit);
+        let final_msg_size = plan
+            .message_size_hints()
+            .last()
+            .map_or(0, |h| h.contributor);
+        let handshake = self
+            .encrypted_channel
+            .then(|| Handshake::initiate(&mut ChaCha20Rng::from_entropy()));
+        let circuit_hash = circuit.blake3_hash();
+        let stored = self
+            .stored_programs
+            .as_ref()
+            .filter(|_| raw_circuit.is_none());
+        let is_stored = stored.map_or(false, |s| s.contains(&self.url, &circuit_hash));
+        let mut req = NewSession {
+            plaintext_metadata,
+            program: if is_stored {
+                String::new()
+            } else {
+                source_code.clone()
+            },
+            function: function_name,
+            circuit_hash,
+            client_version: client_version.clone(),
+            protocol_version: tandem::PROTOCOL_VERSION,
+            wire_version: MIN_WIRE_VERSION,
+            wire_versions: (MIN_WIRE_VERSION..=WIRE_VERSION).collect(),
+            capabilities: CAPABILITIES
+                .iter()
+                .filter(|c| **c != "transcript_confirmation" || self.confirm_transcript)
+                .filter(|c| **c != "encrypted_channel" || self.encrypted_channel)
+                .filter(|c| **c != "output_disclosure" || self.disclose_output)
+                .filter(|c| **c != "multi_round" || follow_ups.is_some())
+                .filter(|c| **c != "program_store" || stored.is_some())
+                .map(|c| c.to_string())
+                .collect(),
+            stage_final: final_msg_size > FINAL_DOWNLOAD_THRESHOLD,
+            approval_id,
+            idempotency_key: Some(random_id()),
+            session_nonce: random_nonce(),
+            channel_key: handshake.as_ref().map(Handshake::ephemeral_key),
+            follow_ups,
+            circuit: raw_circuit,
+        };
+        // retries use the same idempotency key, so that the server does not create another session
+        // if only its response was lost:
+        let mut attempts = 1;
+        let started = Clock::now();
+        let created = loop {
+            let created = send_new_session(
+                &self.transport,
+                self.url.clone(),
+                &self.headers,
+                &req,
+                &circuit,
+                &self.timeouts,
+            )
+            .await;
+            match created {
+                Err(e) if e.is_retryable() && attempts < MAX_SESSION_ATTEMPTS => {
+                    log::info!("Retrying the creation of the session (attempt {attempts}): {e}");
+                    attempts += 1;
+                }
+                // the server no longer stores the program, for example because it restarted:
+                Err(Error::UnknownProgram { .. }) if req.program.is_empty() => {
+                    log::info!("The server does not store the program, sending it again");
+                    if let Some(stored) = stored {
+                        stored.remove(&self.url, &circuit_hash);
+                    }
+                    req.program = source_code.clone();
+                }
+                created => break created?,
+            }
+        };
+        let EngineCreationResult {
+            engine_id,
+            request_headers,
+            server_version: _server_version,
+            final_url,
+            messages,
+            capabilities,
+            wire_version,
+            session_commitment,
+            channel,
+        } = created;
+        let elapsed = started.elapsed();
+        log::info!(
+            "Created session {engine_id} in {elapsed:?} (wire version {}, capabilities: {})",
+            wire_version.unwrap_or(MIN_WIRE_VERSION),
+            capabilities.join(", ")
+        );
+        let received: usize = messages.iter().map(|(msg, _)| msg.len()).sum();
+        log::debug!("Received {received} bytes with the creation of the session");
+        let report = ComputeReport {
+            rounds: 1,
+            bytes_received: received,
+            phase_timings: PhaseTimings {
+                session: elapsed,
+                ..PhaseTimings::default()
+            },
+            ..ComputeReport::default()
+        };
+        if let Some(stored) = stored {
+            if capabilities.iter().any(|c| c == "program_store") {
+                stored.insert(&self.url, circuit_hash);
+            }
+        }
+        let negotiated = capabilities.iter().any(|c| c == "encrypted_channel");
+        let channel = match (handshake, channel) {
+            (Some(handshake), Some(reply)) if negotiated => {
+                let pinned_key = self.server_channel_key.as_ref();
+                let channel = handshake
+                    .finish(&reply, pinned_key)
+                    .map_err(|_| Error::EncryptedChannelUnavailable)?;
+                Some(channel)
+            }
+            (Some(_), _) => return Err(Error::EncryptedChannelUnavailable),
+            (None, _) => None,
+        };
+        // the commitment of a session with an encrypted channel is keyed by the channel:
+        let key = channel.as_ref().map(SecureChannel::binding_key);
+        match (session_commitment, wire_version) {
+            (Some(commitment), Some(wire_version)) => {
+                commitment.verify(&req, &engine_id, wire_version, &capabilities, key)?
+            }
+            (Some(_), None) => return Err(Error::SessionCommitmentMismatch),
+            (None, _) if self.require_session_commitment => {
+                return Err(Error::SessionCommitmentMismatch)
+            }
+            (None, _) => {}
+        }
+        let url = self.url.join(&engine_id)?;
+        // the server returns an absolute path, which is resolved relative to the base url:
+        let final_url = match final_url {
+            Some(path) => Some(self.url.join(path.trim_start_matches('/'))?),
+            None => None,
+        };
+
+        // the headers of the server take precedence, they might be needed to route the requests:
+        let mut headers = self.headers.clone();
+        headers.extend(request_headers);
+
+        // the triples of all rounds are preprocessed before the first round, which then only needs
+        // the steps of a session on preprocessed triples:
+        let rng = ChaCha20Rng::from_entropy();
+        let (evaluator, rounds) = match follow_ups {
+            Some(f) if capabilities.iter().any(|c| c == "multi_round") => {
+                let and_gates = circuit.and_gates() + f.and_gates;
+                let input_bits = circuit.contrib_inputs() + circuit.eval_inputs() + f.input_bits;
+                let capacity = preprocessing_circuit(and_gates, input_bits);
+                let capacity = ValidatedCircuit::new(capacity)?;
+                let options = ProtocolOptions::default();
+                let evaluator = Evaluator::new_preprocessing(capacity, rng, options)?;
+                let rounds = Rounds {
+                    program: source_code,
+                    first_round: Some((circuit, input)),
+                    triples: None,
+                };
+                (evaluator, Some(rounds))
+            }
+            _ => (Evaluator::new(circuit, input, rng)?, None),
+        };
+        let steps_remaining = evaluator.steps();
+        Ok(TandemSession {
+            transport: self.transport.clone(),
+            url,
+            request_headers: headers,
+            final_url,
+            streaming: capabilities.iter().any(|c| c == "streaming"),
+            confirm_transcript: capabilities.iter().any(|c| c == "transcript_confirmation"),
+            channel,
+            disclose_output: capabilities.iter().any(|c| c == "output_disclosure"),
+            rounds,
+            timeouts: self.timeouts,
+            output_type,
+            plan,
+            evaluator: Some(evaluator),
+            context: MsgQueue::new(self.max_queue_bytes),
+            upstream_msgs: Upstream::Buffered(messages.into_iter()),
+            last_durably_received_offset: None,
+            steps_remaining,
+            step: 0,
+            // the last step decrypts the output using the final message of the server, the steps of
+            // the first round of a session with follow-up rounds are added once it starts:
+            steps: steps_remaining as usize + 1,
+            report,
+        })
+    }
+}
+
+impl TandemSession {
+    /// Evaluates the session until its output is known, like [`TandemClient::compute`].
+    pub async fn evaluate(self) -> Result<MpcData, Error> {
+        let (output, _) = self.evaluate_monitored(&()).await?;
+        Ok(output)
+    }
+
+    /// Evaluates the session like [`TandemSession::evaluate`], additionally returning the round
+    /// trips, bandwidth and timing of the session, see [`ComputeReport`].
+    pub async fn evaluate_with_report(self) -> Result<(MpcData, ComputeReport), Error> {
+        self.evaluate_monitored(&()).await
+    }
+
+    async fn evaluate_monitored(
+        mut self,
+        monitor: &impl Monitor,
+    ) -> Result<(MpcData, ComputeReport), Error> {
+        loop {
+            if let Some(output) = self.step_monitored(monitor).await? {
+                return Ok((output, self.report));
+            }
+        }
+    }
+
+    /// Processes the messages received from the server and exchanges the next messages with the
+    /// server, returning the output once it is known.
+    ///
+    /// Each step sends at most one request to the server (apart from the download of a staged final
+    /// message and the confirmation of the transcript or disclosure of the output). Once the step
+    /// returns the output or fails, the session has finished and further steps fail with
+    /// [`Error::SessionFinished`].
+    pub async fn step(&mut self) -> Result<Option<MpcData>, Error> {
+        self.step_monitored(&()).await
+    }
+
+    /// Returns how far the evaluation of the session has progressed.
+    pub fn progress(&self) -> Progress {
+        Progress {
+            step: self.step,
+            steps: self.steps,
+        }
+    }
+
+    /// Returns the round trips, bandwidth and timing of the session so far, which covers all
+    /// rounds of a session with follow-up rounds.
+    pub fn report(&self) -> ComputeReport {
+        self.report.clone()
+    }
+
+    /// Deletes the session on the server, which releases the session immediately instead of
+    /// waiting for its timeout.
+    ///
+    /// Fails with [`Error::NoSuchEngineId`] if the server has already dropped the session, which
+    /// is usually the case once the session has finished (unless it has follow-up rounds, which
+    /// the server keeps until they are deleted).
+    pub async fn delete(self) -> Result<(), Error> {
+        delete_session(
+            &self.transport,
+            self.url.clone(),
+            &self.request_headers,
+            &self.timeouts,
+        )
+        .await
+    }
+
+    /// Evaluates the current round of a session with follow-up rounds until its output is known,
+    /// keeping the session for the next round, see [`TandemSession::next`].
+    pub async fn evaluate_round(&mut self) -> Result<MpcData, Error> {
+        loop {
+            if let Some(output) = self.step_monitored(&()).await? {
+                return Ok(output);
+            }
+        }
+    }
+
+    /// Starts the next round of a session with follow-up rounds once the previous round has
+    /// returned its output, computing the program on the reserved preprocessing of the session,
+    /// see [`ComputeOptions::reserve_follow_up`].
+    ///
+    /// The program must be a function of the program of the session. The round is evaluated using
+    /// [`TandemSession::step`] or [`TandemSession::evaluate_round`], the session should be deleted
+    /// after its last round. Fails with [`Error::FollowUpUnavailable`] if the server does not
+    /// support follow-up rounds, the previous round is still running or the remaining reserved
+    /// preprocessing does not fit the program.
+    pub async fn next(
+        &mut self,
+        program: MpcProgram,
+        input: MpcData,
+        plaintext_metadata: serde_json::Value,
+    ) -> Result<(), Error> {
+        let round_done = self.evaluator.is_none() && self.step == self.steps;
+        let available = match &self.rounds {
+            Some(Rounds {
+                program: source_code,
+                triples: Some(triples),
+                ..
+            }) => {
+                round_done
+                    && *source_code == program.source_code
+                    && triples.fits(&program.circuit.gates)
+            }
+            _ => false,
+        };
+        if !available {
+            return Err(Error::FollowUpUnavailable);
+        }
+        let my_input = input.literal.as_bits(&program.ast);
+        if program.expected_input_bits(Role::Evaluator) != my_input.len() {
+            return Err(ValidationError::InvalidInput.into());
+        }
+        let MpcProgram {
+            function_name,
+            ast,
+            circuit,
+            ..
+        } = program;
+        let TypedCircuit {
+            gates: circuit,
+            fn_def,
+            ..
+        } = circuit;
+        let req = NextRound {
+            plaintext_metadata,
+            function: function_name,
+            circuit_hash: circuit.blake3_hash(),
+        };
+        let url = Url::parse(&format!("{}/next", self.url))?;
+        let started = Clock::now();
+        let NextRoundResult {
+            request_headers,
+            messages,
+        } = send_next_round(
+            &self.transport,
+            url,
+            &self.request_headers,
+            &req,
+            &circuit,
+            &self.timeouts,
+        )
+        .await?;
+        // the triples are only split off once the server has started the round as well:
+        let triples = match self.rounds.as_mut().and_then(|r| r.triples.as_mut()) {
+            Some(triples) => triples.split_off(&circuit)?,
+            None => return Err(Error::FollowUpUnavailable),
+        };
+        self.plan = ProtocolPlan::new(&circuit);
+        let rng = ChaCha20Rng::from_entropy();
+        let evaluator = Evaluator::from_preprocessed(circuit, my_input, rng, triples)?;
+        self.request_headers.extend(request_headers);
+        // the server has processed all messages of the previous round:
+        if let Some(last_id) = self.context.next_id().checked_sub(1) {
+            self.context.flush_queue(last_id);
+        }
+        self.output_type = OutputType::Literal(Box::new((ast, fn_def)));
+        self.report.rounds += 1;
+        self.report.bytes_received += messages.iter().map(|(msg, _)| msg.len()).sum::<usize>();
+        self.report.phase_timings.session += started.elapsed();
+        self.upstream_msgs = Upstream::Buffered(messages.into_iter());
+        self.steps_remaining = evaluator.steps();
+        self.step = 0;
+        self.steps = self.steps_remaining as usize + 1;
+        self.evaluator = Some(evaluator);
+        Ok(())
+    }
+
+    async fn step_monitored(&mut self, monitor: &impl Monitor) -> Result<Option<MpcData>, Error> {
+        let step_started = Clock::now();
+        let mut evaluator = self.evaluator.take().ok_or(Error::SessionFinished)?;
+        let mut upstream_msgs = std::mem::take(&mut self.upstream_msgs);
+        loop {
+            let received = &mut self.report.bytes_received;
+            let (msg, server_offset) = match upstream_msgs.next(received).await {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                // the server resends the remaining messages, as they are not acknowledged yet:
+                Err(e) if e.is_retryable() => {
+                    log::info!(
+                        "The response was interrupted, requesting the remaining messages: {e}"
+                    );
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            let expected_offset = self
+                .last_durably_received_offset
+                .map(|o| o + 1)
+                .unwrap_or(0);
+            // messages are delivered at least once, the server might send a message again:
+            if server_offset < expected_offset {
+                continue;
+            }
+            // the remaining messages are resent by the server, as they are not acknowledged:
+            if self.context.is_full() {
+                log::debug!(
+                    "Pausing with {} bytes queued for the server",
+                    self.context.queued_bytes()
+                );
+                break;
+            }
+            if server_offset != expected_offset {
+                return Err(Error::MessageOffsetMismatch);
+            }
+
+            // the preprocessing is followed by the first round on its share of the triples:
+            let first_round = match &mut self.rounds {
+                Some(rounds) if self.steps_remaining == 0 => rounds.first_round.take(),
+                _ => None,
+            };
+            if let Some((circuit, input)) = first_round {
+                evaluator = match self.start_first_round(evaluator, circuit, input) {
+                    Ok(evaluator) => evaluator,
+                    Err(e) => return Err(self.abort_on_error(e).await),
+                };
+            }
+
+            if self.steps_remaining > 0 {
+                let processed = match self.open(&msg, server_offset) {
+                    Ok(msg) => evaluator.run(&msg),
+                    Err(e) => Err(e),
+                };
+                match processed {
+                    Ok((next_state, msg)) => {
+                        evaluator = next_state;
+                        self.steps_remaining -= 1;
+                        self.step += 1;
+                        self.queue(msg);
+                        monitor.progress(self.progress());
+                    }
+                    Err(e) => return Err(self.abort_on_error(e).await),
+                }
+            } else {
+                self.report.phase_timings.evaluation += step_started.elapsed();
+                let output_started = Clock::now();
+                let msg = match &self.final_url {
+                    Some(url) => {
+                        let size_hint = self
+                            .plan
+                            .message_size_hints()
+                            .last()
+                            .map_or(0, |h| h.contributor);
+                        let started = Clock::now();
+                        let msg = download_final(
+                            &self.transport,
+                            url,
+                            &self.request_headers,
+                            size_hint,
+                            &self.timeouts,
+                        )
+                        .await?;
+                        let elapsed = started.elapsed();
+                        log::debug!(
+                            "Downloaded the final message ({} bytes) in {elapsed:?}",
+                            msg.len()
+                        );
+                        self.report.rounds += 1;
+                        self.report.bytes_received += msg.len();
+                        msg
+                    }
+                    None => msg,
+                };
+                // a staged final message is encrypted using the id of its placeholder:
+                let msg = match self.open(&msg, server_offset) {
+                    Ok(msg) => msg,
+                    Err(e) => return Err(self.abort_on_error(e).await),
+                };
+                // the server sends its transcript hash together with the final message:
+                let mut last_offset = server_offset;
+                if self.confirm_transcript {
+                    let hash = match upstream_msgs.next(&mut self.report.bytes_received).await? {
+                        Some((hash, offset)) if offset == server_offset + 1 => {
+                            last_offset = offset;
+                            self.open(&hash, offset)
+                        }
+                        _ => return Err(Error::MessageOffsetMismatch),
+                    };
+                    match hash {
+                        Ok(hash) => evaluator.expect_transcript_hash(&hash),
+                        Err(e) => return Err(self.abort_on_error(e).await),
+                    }
+                }
+                let report = match evaluator.output_with_report(&msg) {
+                    Ok(report) => report,
+                    Err(e) => return Err(self.abort_on_error(e).await),
+                };
+                if self.confirm_transcript || (self.disclose_output && report.verified) {
+                    self.send_last_messages(last_offset, &report).await?;
+                }
+                let verification = OutputVerification::from(&report);
+                let output = match report.output {
+                    Some(output) => output,
+                    None => return Err(self.abort_on_error(tandem::Error::MacError).await),
+                };
+                self.last_durably_received_offset = Some(last_offset);
+                self.step = self.steps;
+                self.report.phase_timings.output += output_started.elapsed();
+                monitor.progress(self.progress());
+                let literal = match &self.output_type {
+                    OutputType::Literal(typed) => {
+                        let (ast, fn_def) = &**typed;
+                        deserialize_output(ast, fn_def, &output).map_err(GarbleCompileTimeError)?
+                    }
+                    OutputType::Bits => Literal::Array(
+                        output
+                            .iter()
+                            .map(|b| if *b { Literal::True } else { Literal::False })
+                            .collect(),
+                    ),
+                };
+                return Ok(Some(MpcData {
+                    literal,
+                    verification: Some(verification),
+                }));
+            }
+            self.last_durably_received_offset = Some(server_offset);
+        }
+
+        if monitor.is_cancelled() {
+            // the abort is best-effort, the server drops the session after a timeout otherwise:
+            let _ = self.abort_with_reason(AbortReason::Cancelled).await;
+            return Err(Error::Cancelled);
+        }
+        let messages: Vec<(&Msg, MessageId)> = self.context.msgs_iter().collect();
+        let size_hint =
+            response_size_hint(&self.plan, self.last_durably_received_offset, &messages);
+        let started = Clock::now();
+        let (msgs, server_commited_offset) = self
+            .dialog(self.last_durably_received_offset, &messages, size_hint)
+            .await?;
+        let sent: usize = messages.iter().map(|(msg, _)| msg.len()).sum();
+        // streamed messages are only counted once they arrive:
+        let received = msgs.buffered_bytes();
+        log::debug!(
+            "Step {}/{}: sent {sent} bytes, received {received} bytes in {:?}",
+            self.step,
+            self.steps,
+            started.elapsed()
+        );
+        if messages.last().map(|v| v.1) != server_commited_offset {
+            return Err(Error::MessageOffsetMismatch);
+        }
+        self.report.rounds += 1;
+        self.report.bytes_sent += sent;
+        self.report.bytes_received += received;
+        self.report.phase_timings.evaluation += step_started.elapsed();
+
+        if let Some(last_durably_received_offset) = server_commited_offset {
+            self.context.flush_queue(last_durably_received_offset);
+        }
+        self.upstream_msgs = msgs;
+        self.evaluator = Some(evaluator);
+        Ok(None)
+    }
+
+    /// Splits off the triples of the first round from the finished preprocessing, returning the
+    /// evaluator of the first round.
+    fn start_first_round(
+        &mut self,
+        preprocessing: Evaluator<ValidatedCircuit, Vec<bool>>,
+        circuit: ValidatedCircuit,
+        input: Vec<bool>,
+    ) -> Result<Evaluator<ValidatedCircuit, Vec<bool>>, tandem::Error> {
+        let mut triples = preprocessing.preprocessed()?;
+        let round = triples.split_off(&circuit)?;
+        let rng = ChaCha20Rng::from_entropy();
+        let evaluator = Evaluator::from_preprocessed(circuit, input, rng, round)?;
+        if let Some(rounds) = &mut self.rounds {
+            rounds.triples = Some(triples);
+        }
+        self.steps_remaining = evaluator.steps();
+        self.steps += evaluator.steps() as usize;
+        Ok(evaluator)
+    }
+
+    /// Sends the transcript hash of the client and the disclosed output (if negotiated) to the
+    /// server in a single request, after the output has been computed.
+    ///
+    /// The output is only disclosed if it has been verified.
+    async fn send_last_messages(
+        &mut self,
+        last_durably_received_offset: MessageId,
+        report: &OutputReport,
+    ) -> Result<(), Error> {
+        self.last_durably_received_offset = Some(last_durably_received_offset);
+        if self.confirm_transcript {
+            self.queue(report.transcript_hash.to_vec());
+        }
+        if self.disclose_output && report.verified {
+            self.queue(report.disclosure.clone());
+        }
+        let messages: Vec<(&Msg, MessageId)> = self.context.msgs_iter().collect();
+        let (msgs, _) = self
+            .dialog(self.last_durably_received_offset, &messages, 0)
+            .await?;
+        let msgs = msgs.collect().await?;
+        self.report.rounds += 1;
+        self.report.bytes_sent += messages.iter().map(|(msg, _)| msg.len()).sum::<usize>();
+        self.report.bytes_received += msgs.iter().map(|(msg, _)| msg.len()).sum::<usize>();
+        // the server only responds with an abort message if its hash differs or the disclosed
+        // output fails its checks:
+        match (msgs.is_empty(), self.confirm_transcript) {
+            (true, _) => Ok(()),
+            (false, true) => Err(Error::TandemError(tandem::Error::TranscriptMismatch)),
+            (false, false) => Err(Error::TandemError(tandem::Error::MacError)),
+        }
+    }
+
+    /// Queues a message for the server, encrypted if the session uses an encrypted channel.
+    fn queue(&mut self, msg: Msg) {
+        let msg = match &self.channel {
+            Some(channel) => channel.seal(u64::from(self.context.next_id()), &msg),
+            None => msg,
+        };
+        self.context.send(msg);
+    }
+
+    /// Decrypts a message that the server sent with the specified id, if the session uses an
+    /// encrypted channel.
+    fn open(&self, msg: &[u8], id: MessageId) -> Result<Msg, tandem::Error> {
+        match &self.channel {
+            Some(channel) => channel.open(u64::from(id), msg),
+            None => Ok(msg.to_vec()),
+        }
+    }
+
+    /// Informs the server that the protocol failed (unless the server aborted the protocol itself)
+    /// and returns the error.
+    async fn abort_on_error(&mut self, e: tandem::Error) -> Error {
+        if !matches!(e, tandem::Error::PeerAborted(_)) {
+            let reason = AbortReason::from(&e);
+            // the abort is best-effort, the server drops the session after a timeout otherwise:
+            let _ = self.abort_with_reason(reason).await;
+        }
+        Error::TandemError(e)
+    }
+
+    /// Aborts the session, informing the server about the reason so that it can release the
+    /// session immediately.
+    async fn abort_with_reason(&mut self, reason: AbortReason) -> Result<(), Error> {
+        self.queue(abort_message(reason));
+        let messages: Vec<(&Msg, MessageId)> = self.context.msgs_iter().collect();
+        self.dialog(self.last_durably_received_offset, &messages, 0)
+            .await?;
+        Ok(())
+    }
+
+    async fn dialog(
+        &self,
+        last_durably_received_offset: Option<u32>,
+        messages: &[(&Msg, MessageId)],
+        response_size_hint: usize,
+    ) -> Result<(Upstream, Option<MessageId>), Error> {
+        // the server ignores the messages that it already processed if only the response was lost:
+        let mut attempts = 1;
+        loop {
+            let sent = send_msgs(
+                &self.transport,
+                self.url.clone(),
+                &self.request_headers,
+                last_durably_received_offset,
+                messages,
+                response_size_hint,
+                self.streaming,
+                &self.timeouts,
+            )
+            .await;
+            match sent {
+                Err(e) if e.is_retryable() && attempts < MAX_DIALOG_ATTEMPTS => {
+                    log::info!("Retrying the dialog request (attempt {attempts}): {e}");
+                    attempts += 1;
+                }
+                sent => return sent,
+            }
+        }
+    }
+}
+
+/// Generates a random hex-encoded id, used as the trace id of a computation (see
+/// [`TRACE_ID_HEADER`]) and as the idempotency key of a session.
+fn random_id() -> String {
+    let mut id = [0; 16];
+    ChaCha20Rng::from_entropy().fill_bytes(&mut id);
+    id.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn random_nonce() -> [u8; 32] {
+    let mut nonce = [0; 32];
+    ChaCha20Rng::from_entropy().fill_bytes(&mut nonce);
+    nonce
+}
+
+/// Estimates the size of the server's response to a dialog request, based on the protocol plan.
+///
+/// The server replies to each message of the client with a single message, the response thus
+/// contains all of the server's messages that have not been durably received yet.
+fn response_size_hint(
+    plan: &ProtocolPlan,
+    last_durably_received_offset: Option<MessageId>,
+    messages: &[(&Msg, MessageId)],
+) -> usize {
+    let hints = plan.message_size_hints();
+    let first = last_durably_received_offset
+        .map(|o| o as usize + 1)
+        .unwrap_or(0);
+    let last = messages.last().map(|m| m.1 as usize + 1).unwrap_or(0);
+    let end = (last + 1).min(hints.len());
+    let msgs: usize = hints[first.min(end)..end]
+        .iter()
+        .map(|h| h.contributor + 8 + 4)
+        .sum();
+    msgs + 8 + 5
+}
+
+/// Downloads a staged final message, resuming the download if it is interrupted.
+async fn download_final(
+    transport: &Transport,
+    url: &Url,
+    request_headers: &HashMap<String, String>,
+    size_hint: usize,
+    timeouts: &Timeouts,
+) -> Result<Msg, Error> {
+    // the other transports are only available with their features:
+    #[allow(clippy::infallible_destructuring_match)]
+    let client = match transport {
+        Transport::Http(client) => client,
+        #[cfg(all(feature = "local", not(target_arch = "wasm32")))]
+        Transport::Local(local) => {
+            return local.download_final(url, request_headers).await;
+        }
+        #[cfg(all(feature = "grpc", not(target_arch = "wasm32")))]
+        Transport::Grpc(_) => {
+            return Err(Error::ServerError(format!(
+                "Final messages cannot be downloaded via gRPC: {url}"
+            )));
+        }
+    };
+    let mut body = Vec::with_capacity(size_hint);
+    let mut error = Error::IncompleteDownload;
+    for _ in 0..MAX_DOWNLOAD_ATTEMPTS {
+        let mut req = client
+            .get(url.clone())
+            .header("Range", format!("bytes={}-", body.len()));
+        for (k, v) in request_headers.iter() {
+            req = req.header(k, v);
+        }
+        match download_range(timeouts.apply(req)?, &mut body).await {
+            Ok(total) if body.len() == total => return Ok(body),
+            Ok(_) => error = Error::IncompleteDownload,
+            Err(e) if e.is_retryable() => error = e,
+            Err(e) => return Err(e),
+        }
+        log::info!(
+            "Resuming the download of the final message after {} bytes: {error}",
+            body.len()
+        );
+    }
+    Err(error)
+}
+
+/// Appends the requested range to the body (or replaces the body if the server ignored the range),
+/// returning the total size of the download.
+async fn download_range(req: reqwest::RequestBuilder, body: &mut Vec<u8>) -> Result<usize, Error> {
+    let resp = resp_or_err(req.send().await?).await?;
+    let total = if resp.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+        let range = resp
+            .headers()
+            .get("Content-Range")
+            .and_then(|h| h.to_str().ok())
+            .and_then(parse_content_range);
+        match range {
+            Some((start, total)) if start == body.len() => total,
+            _ => return Err(Error::IncompleteDownload),
+        }
+    } else {
+        body.clear();
+        resp.content_length().map_or(0, |len| len as usize)
+    };
+    append_body(resp, body).await?;
+    Ok(total.max(body.len()))
+}
+
+/// Parses a `Content-Range: bytes <start>-<end>/<total>` header into `(start, total)`.
+fn parse_content_range(header: &str) -> Option<(usize, usize)> {
+    let (range, total) = header.strip_prefix("bytes ")?.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    Some((start.parse().ok()?, total.parse().ok()?))
+}
+
+async fn send_new_session(
+    transport: &Transport,
+    url: Url,
+    headers: &HashMap<String, String>,
+    session: &NewSession,
+    circuit: &Circuit,
+    timeouts: &Timeouts,
+) -> Result<EngineCreationResult, Error> {
+    // the other transports are only available with their features:
+    #[allow(clippy::infallible_destructuring_match)]
+    let client = match transport {
+        Transport::Http(client) => client,
+        #[cfg(all(feature = "local", not(target_arch = "wasm32")))]
+        Transport::Local(local) => {
+            return local
+                .send_new_session(&url, headers, session, circuit)
+                .await;
+        }
+        #[cfg(all(feature = "grpc", not(target_arch = "wasm32")))]
+        Transport::Grpc(grpc) => {
+            return grpc.send_new_session(headers, session, circuit).await;
+        }
+    };
+    let mut req = client.post(url).json(session);
+    for (k, v) in headers.iter() {
+        req = req.header(k, v);
+    }
+    let resp = timeouts.apply(req)?.send().await?;
+    if resp.status() == reqwest::StatusCode::BAD_REQUEST {
+        return Err(new_session_error(resp.text().await?, circuit));
+    }
+    let resp = resp_or_err(resp).await?;
+    Ok(resp.json::<EngineCreationResult>().await?)
+}
+
+/// Converts the body of a rejected session request into an error, detecting circuit mismatches.
+fn new_session_error(body: String, circuit: &Circuit) -> Error {
+    match CircuitMismatch::from_error_body(&body, circuit.stats()) {
+        Some(mismatch) => Error::CircuitHashMismatch(Box::new(mismatch)),
+        None => server_error(body),
+    }
+}
+
+async fn send_next_round(
+    transport: &Transport,
+    url: Url,
+    request_headers: &HashMap<String, String>,
+    round: &NextRound,
+    circuit: &Circuit,
+    timeouts: &Timeouts,
+) -> Result<NextRoundResult, Error> {
+    // the other transports are only available with their features:
+    #[allow(clippy::infallible_destructuring_match)]
+    let client = match transport {
+        Transport::Http(client) => client,
+        #[cfg(all(feature = "local", not(target_arch = "wasm32")))]
+        Transport::Local(local) => {
+            return local
+                .send_next_round(&url, request_headers, round, circuit)
+                .await;
+        }
+        // gRPC servers never agree to follow-up rounds:
+        #[cfg(all(feature = "grpc", not(target_arch = "wasm32")))]
+        Transport::Grpc(_) => return Err(Error::FollowUpUnavailable),
+    };
+    let mut req = client.post(url).json(round);
+    for (k, v) in request_headers.iter() {
+        req = req.header(k, v);
+    }
+    let resp = timeouts.apply(req)?.send().await?;
+    if resp.status() == reqwest::StatusCode::BAD_REQUEST {
+        return Err(new_session_error(resp.text().await?, circuit));
+    }
+    let resp = resp_or_err(resp).await?;
+    Ok(resp.json::<NextRoundResult>().await?)
+}
+
+async fn delete_session(
+    transport: &Transport,
+    url: Url,
+    request_headers: &HashMap<String, String>,
+    timeouts: &Timeouts,
+) -> Result<(), Error> {
+    // the other transports are only available with their features:
+    #[allow(clippy::infallible_destructuring_match)]
+    let client = match transport {
+        Transport::Http(client) => client,
+        #[cfg(all(feature = "local", not(target_arch = "wasm32")))]
+        Transport::Local(local) => return local.delete_session(&url, request_headers).await,
+        #[cfg(all(feature = "grpc", not(target_arch = "wasm32")))]
+        Transport::Grpc(grpc) => return grpc.delete_session(&url, request_headers).await,
+    };
+    let mut req = client.delete(url);
+    for (k, v) in request_headers.iter() {
+        req = req.header(k, v);
+    }
+    let resp = timeouts.apply(req)?.send().await?;
+    resp_or_err(resp).await?;
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn send_msgs(
+    transport: &Transport,
+    url: Url,
+    request_headers: &HashMap<String, String>,
+    last_durably_received_offset: Option<u32>,
+    msgs: &[(&Msg, MessageId)],
+    response_size_hint: usize,
+    streaming: bool,
+    timeouts: &Timeouts,
+) -> Result<(Upstream, Option<MessageId>), Error> {
+    // the other transports are only available with their features:
+    #[allow(clippy::infallible_destructuring_match)]
+    let client = match transport {
+        Transport::Http(client) => client,
+        #[cfg(all(feature = "local", not(target_arch = "wasm32")))]
+        Transport::Local(local) => {
+            let (msgs, offset) = local
+                .send_msgs(
+                    &url,
+                    request_headers,
+                    last_durably_received_offset,
+                    msgs,
+                    streaming,
+                )
+                .await?;
+            return Ok((Upstream::Buffered(msgs.into_iter()), offset));
+        }
+        #[cfg(all(feature = "grpc", not(target_arch = "wasm32")))]
+        Transport::Grpc(grpc) => {
+            let (msgs, offset) = grpc
+                .send_msgs(&url, request_headers, last_durably_received_offset, msgs)
+                .await?;
+            return Ok((Upstream::Buffered(msgs.into_iter()), offset));
+        }
+    };
+    let msgs = (last_durably_received_offset, msgs);
+    let mut body = Vec::with_capacity(bincode::serialized_size(&msgs)? as usize);
+    bincode::serialize_into(&mut body, &msgs)?;
+    let mut req = client.post(url).body(body);
+    for (k, v) in request_headers.iter() {
+        req = req.header(k, v);
+    }
+    let resp = timeouts.apply(req)?.send().await?;
+    let resp = resp_or_err(resp).await?;
+    // only sent by servers that enable their timing diagnostics:
+    if let Some(timing) = resp.headers().get("Server-Timing") {
+        log::debug!("Server timing: {}", timing.to_str().unwrap_or_default());
+    }
+    if streaming {
+        read_frames(resp, response_size_hint).await
+    } else {
+        let body = read_body(resp, response_size_hint).await?;
+        let (msgs, offset): (MessageLog, _) = bincode::deserialize(&body)?;
+        Ok((Upstream::Buffered(msgs.into_iter()), offset))
+    }
+}
+
+/// The messages of the server's response to a dialog request, in the order they were sent.
+enum Upstream {
+    Buffered(std::vec::IntoIter<(Msg, MessageId)>),
+    /// A streamed response whose messages are returned as soon as their frames have arrived.
+    #[cfg(not(target_arch = "wasm32"))]
+    Streaming {
+        resp: Response,
+        decoder: FrameDecoder,
+    },
+}
+
+impl Default for Upstream {
+    fn default() -> Self {
+        Upstream::Buffered(vec![].into_iter())
+    }
+}
+
+impl Upstream {
+    /// Returns the next message, waiting until it has arrived if the response is streamed.
+    ///
+    /// Adds the size of streamed messages to `received`, buffered messages are counted when their
+    /// response is received, see [`Upstream::buffered_bytes`].
+    async fn next(&mut self, received: &mut usize) -> Result<Option<(Msg, MessageId)>, Error> {
+        match self {
+            Upstream::Buffered(msgs) => Ok(msgs.next()),
+            #[cfg(not(target_arch = "wasm32"))]
+            Upstream::Streaming { resp, decoder } => loop {
+                if let Some((msg, id)) = decoder.next_msg() {
+                    *received += msg.len();
+                    return Ok(Some((msg, id)));
+                }
+                match resp.chunk().await? {
+                    Some(chunk) => decoder.push(&chunk)?,
+                    None => {
+                        decoder.finish()?;
+                        return Ok(None);
+                    }
+                }
+            },
+        }
+    }
+
+    /// Waits for all remaining messages.
+    async fn collect(mut self) -> Result<MessageLog, Error> {
+        let mut msgs = vec![];
+        while let Some(msg) = self.next(&mut 0).await? {
+            msgs.push(msg);
+        }
+        Ok(msgs)
+    }
+
+    /// The size of the remaining messages of a buffered response.
+    fn buffered_bytes(&self) -> usize {
+        match self {
+            Upstream::Buffered(msgs) => msgs.as_slice().iter().map(|(msg, _)| msg.len()).sum(),
+            #[cfg(not(target_arch = "wasm32"))]
+            Upstream::Streaming { .. } => 0,
+        }
+    }
+}
+
+/// Reads the response until its header frame has arrived, the messages are parsed as the caller
+/// processes them.
+#[cfg(not(target_arch = "wasm32"))]
+async fn read_frames(
+    mut resp: Response,
+    size_hint: usize,
+) -> Result<(Upstream, Option<MessageId>), Error> {
+    let mut decoder = FrameDecoder::with_capacity(size_hint);
+    let offset = loop {
+        if let Some(offset) = decoder.header() {
+            break offset;
+        }
+        match resp.chunk().await? {
+            Some(chunk) => decoder.push(&chunk)?,
+            None => return Err(Error::InvalidResponseFrame),
+        }
+    };
+    Ok((Upstream::Streaming { resp, decoder }, offset))
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn read_frames(
+    resp: Response,
+    _size_hint: usize,
+) -> Result<(Upstream, Option<MessageId>), Error> {
+    // the fetch API always buffers the full response, no need to preallocate:
+    let (msgs, offset) = frames::decode(&resp.bytes().await?)?;
+    Ok((Upstream::Buffered(msgs.into_iter()), offset))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+async fn read_body(mut resp: Response, size_hint: usize) -> Result<Vec<u8>, Error> {
+    let mut body = Vec::with_capacity(size_hint);
+    while let Some(chunk) = resp.chunk().await? {
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn read_body(resp: Response, _size_hint: usize) -> Result<Vec<u8>, Error> {
+    // the fetch API always buffers the full response, no need to preallocate:
+    Ok(resp.bytes().await?.to_vec())
+}
+
+/// Appends the response to the body, keeping the bytes received before an interruption.
+#[cfg(not(target_arch = "wasm32"))]
+async fn append_body(mut resp: Response, body: &mut Vec<u8>) -> Result<(), Error> {
+    while let Some(chunk) = resp.chunk().await? {
+        body.extend_from_slice(&chunk);
+    }
+    Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+async fn append_body(resp: Response, body: &mut Vec<u8>) -> Result<(), Error> {
+    body.extend_from_slice(&resp.bytes().await?);
+    Ok(())
+}
+
+async fn resp_or_err(resp: Response) -> Result<Response, Error> {
+    if resp.status().is_success() {
+        Ok(resp)
+    } else {
+        let server_failed = resp.status().is_server_error();
+        Err(response_error(server_failed, resp.text().await?))
+    }
+}
+
+/// Converts the body of an error response into an error, which is retryable if the server failed
+/// (with a 5xx status) instead of rejecting the request.
+fn response_error(server_failed: bool, body: String) -> Error {
+    match server_error(body) {
+        Error::ServerError(e) if server_failed => Error::ServerFailure(e),
+        e => e,
+    }
+}
+
+fn server_error(e: String) -> Error {
+    if let Some(e) = wire_error::parse(&e) {
+        return e;
+    }
+    let e = match serde_json::from_str::<ErrorJson>(&e) {
+        Ok(ErrorJson {
+            error,
+            args,
+            trace_id: Some(trace_id),
+        }) => format!("{error}: {args} (trace id {trace_id})"),
+        Ok(ErrorJson { error, args, .. }) => format!("{error}: {args}"),
+        Err(_) => e,
+    };
+    Error::ServerError(e)
+}
+
+#[derive(Deserialize)]
+struct ErrorJson {
+    error: String,
+    args: String,
+    /// Only sent by servers if the request contained a trace id.
+    #[serde(default)]
+    trace_id: Option<String>,
+}
+
+/// Errors occurring during the validation or the execution of the MPC protocol.
 #[derive(Debug)]
 pub enum Error {
     /// An error occurred on the server side.
     ServerError(String),
+    /// The server failed to handle the request (with a 5xx status), retrying might succeed.
+    ServerFailure(String),
     /// An error occurred while trying to send a request to the server.
     ReqwestError(reqwest::Error),
     /// The provided JSON is not a valid Garble literal.
@@ -525,8 +2561,78 @@ pub enum Error {
     TandemError(tandem::Error),
     /// A message could not be serialized/deserialized.
     BincodeError,
+    /// The server's streamed response to a dialog request is not a valid sequence of frames.
+    InvalidResponseFrame,
     /// The client's message id did not match the server's message id.
     MessageOffsetMismatch,
+    /// The download of the staged final message could not be completed.
+    IncompleteDownload,
+    /// The circuit compiled by the client does not match the circuit of the server.
+    CircuitHashMismatch(Box<CircuitMismatch>),
+    /// The literal could not be decoded into the requested Rust type.
+    DecodeError(String),
+    /// The computation did not complete before the timeout of its [`ComputeOptions`].
+    DeadlineExceeded,
+    /// The computation was cancelled (by the `AbortSignal` passed to `computeWithOptions`).
+    Cancelled,
+    /// The [`TandemSession`] has already returned its output or failed.
+    SessionFinished,
+    /// The server does not know the session, because it was dropped or the server restarted.
+    NoSuchEngineId {
+        /// The engine id of the session.
+        engine_id: String,
+        /// The trace id of the failed request, see [`TRACE_ID_HEADER`].
+        trace_id: Option<String>,
+    },
+    /// The server rejected the request, usually because of invalid plaintext metadata.
+    MpcRequestRejected {
+        /// Why the server's handler rejected the request.
+        reason: String,
+        /// The trace id of the failed request, see [`TRACE_ID_HEADER`].
+        trace_id: Option<String>,
+    },
+    /// The server temporarily rejects the client after too many failed requests.
+    ClientBlocked {
+        /// The trace id of the failed request, see [`TRACE_ID_HEADER`].
+        trace_id: Option<String>,
+    },
+    /// The server did not commit to the requested session, or its commitment does not match the
+    /// session that was created, see [`ComputeOptions::require_session_commitment`].
+    SessionCommitmentMismatch,
+    /// The server does not support encrypted channels or did not authenticate itself using the
+    /// pinned key, see [`ComputeOptions::encrypted_channel`].
+    EncryptedChannelUnavailable,
+    /// The session exceeds one of the resource limits of the server.
+    QuotaExceeded {
+        /// The exceeded limit, such as `session_and_gates` or `total_and_gates`.
+        quota: String,
+        /// The configured limit.
+        limit: usize,
+        /// What the session would have required.
+        requested: usize,
+        /// The trace id of the failed request, see [`TRACE_ID_HEADER`].
+        trace_id: Option<String>,
+    },
+    /// The server did not process the messages of the client, because the messages queued for
+    /// the client have not been acknowledged and exceed the limit of the server.
+    QueueFull {
+        /// The bytes queued for the client.
+        queued_bytes: usize,
+        /// The configured limit.
+        limit: usize,
+        /// The trace id of the failed request, see [`TRACE_ID_HEADER`].
+        trace_id: Option<String>,
+    },
+    /// The session cannot run a follow-up round of the program, see [`TandemSession::next`].
+    FollowUpUnavailable,
+    /// The server does not store the program that the client referenced by its circuit hash, see
+    /// [`ComputeOptions::reference_stored_programs`].
+    UnknownProgram {
+        /// The (hex-encoded) hash of the circuit.
+        circuit_hash: String,
+        /// The trace id of the failed request, see [`TRACE_ID_HEADER`].
+        trace_id: Option<String>,
+    },
 }
 
 impl From<bincode::Error> for Error {
@@ -559,11 +2665,62 @@ impl From<tandem::Error> for Error {
     }
 }
 
+impl Error {
+    /// Returns the name of the error variant, as a machine-readable identifier of the error.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::ServerError(_) => "ServerError",
+            Error::ServerFailure(_) => "ServerFailure",
+            Error::ReqwestError(_) => "ReqwestError",
+            Error::JsonError(_) => "JsonError",
+            Error::ParseError(_) => "ParseError",
+            Error::ValidationError(_) => "ValidationError",
+            Error::TandemError(_) => "TandemError",
+            Error::BincodeError => "BincodeError",
+            Error::InvalidResponseFrame => "InvalidResponseFrame",
+            Error::MessageOffsetMismatch => "MessageOffsetMismatch",
+            Error::IncompleteDownload => "IncompleteDownload",
+            Error::CircuitHashMismatch(_) => "CircuitHashMismatch",
+            Error::DecodeError(_) => "DecodeError",
+            Error::DeadlineExceeded => "DeadlineExceeded",
+            Error::Cancelled => "Cancelled",
+            Error::SessionFinished => "SessionFinished",
+            Error::NoSuchEngineId { .. } => "NoSuchEngineId",
+            Error::MpcRequestRejected { .. } => "MpcRequestRejected",
+            Error::ClientBlocked { .. } => "ClientBlocked",
+            Error::SessionCommitmentMismatch => "SessionCommitmentMismatch",
+            Error::EncryptedChannelUnavailable => "EncryptedChannelUnavailable",
+            Error::QuotaExceeded { .. } => "QuotaExceeded",
+            Error::QueueFull { .. } => "QueueFull",
+            Error::FollowUpUnavailable => "FollowUpUnavailable",
+            Error::UnknownProgram { .. } => "UnknownProgram",
+        }
+    }
+
+    /// Whether the failed request can be retried, because the network or the server failed
+    /// temporarily.
+    ///
+    /// All other errors are fatal, as the server rejected the request (such as
+    /// [`Error::CircuitHashMismatch`]) or the protocol failed, and retrying would fail again.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            // errors building the request are bugs of the client, all other errors occur in
+            // transit:
+            Error::ReqwestError(e) => !e.is_builder(),
+            Error::ServerFailure(_) | Error::IncompleteDownload => true,
+            // other sessions need to finish first, while sessions that are too large never fit:
+            Error::QuotaExceeded { quota, .. } => quota == "total_and_gates",
+            _ => false,
+        }
+    }
+}
+
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Error::ValidationError(e) => write!(f, "The MPC program or the input is invalid: {e}"),
             Error::ServerError(e) => write!(f, "An error occurred on the server side: {e}"),
+            Error::ServerFailure(e) => write!(f, "The server failed temporarily: {e}"),
             Error::ReqwestError(e) => write!(
                 f,
                 "An error occurred while trying to send a request to the server: {e}"
@@ -577,14 +2734,98 @@ impl std::fmt::Display for Error {
                 "An error occurred during the client's execution of the MPC protocol: {e}"
             ),
             Error::BincodeError => write!(f, "A message could not be serialized/deserialized."),
+            Error::InvalidResponseFrame => {
+                write!(
+                    f,
+                    "The server's response is not a valid sequence of frames."
+                )
+            }
             Error::MessageOffsetMismatch => write!(
                 f,
                 "The client's message id did not match the server's message id."
             ),
+            Error::IncompleteDownload => {
+                write!(
+                    f,
+                    "The download of the final message could not be completed."
+                )
+            }
+            Error::CircuitHashMismatch(mismatch) => write!(f, "{mismatch}"),
+            Error::DecodeError(e) => write!(f, "The literal could not be decoded: {e}"),
+            Error::DeadlineExceeded => {
+                write!(f, "The computation did not complete before its deadline.")
+            }
+            Error::Cancelled => write!(f, "The computation was cancelled."),
+            Error::SessionFinished => write!(f, "The session has already finished."),
+            Error::NoSuchEngineId {
+                engine_id,
+                trace_id,
+            } => write!(
+                f,
+                "The server does not know the session {engine_id}{}",
+                trace(trace_id)
+            ),
+            Error::MpcRequestRejected { reason, trace_id } => write!(
+                f,
+                "The server rejected the request: {reason}{}",
+                trace(trace_id)
+            ),
+            Error::ClientBlocked { trace_id } => write!(
+                f,
+                "The server temporarily rejects requests after too many failures{}",
+                trace(trace_id)
+            ),
+            Error::SessionCommitmentMismatch => write!(
+                f,
+                "The server did not commit to the requested session and protocol configuration."
+            ),
+            Error::EncryptedChannelUnavailable => write!(
+                f,
+                "The server did not establish an encrypted channel with the expected key."
+            ),
+            Error::QuotaExceeded {
+                quota,
+                limit,
+                requested,
+                trace_id,
+            } => write!(
+                f,
+                "The session exceeds the {quota} quota of the server ({requested} > {limit}){}",
+                trace(trace_id)
+            ),
+            Error::QueueFull {
+                queued_bytes,
+                limit,
+                trace_id,
+            } => write!(
+                f,
+                "The server has {queued_bytes} unacknowledged bytes queued (limit {limit}){}",
+                trace(trace_id)
+            ),
+            Error::FollowUpUnavailable => write!(
+                f,
+                "The session has no follow-up round for the program or its round is still running."
+            ),
+            Error::UnknownProgram {
+                circuit_hash,
+                trace_id,
+            } => write!(
+                f,
+                "The server does not store the program of the circuit {circuit_hash}{}",
+                trace(trace_id)
+            ),
         }
     }
 }
 
+/// Formats the trace id of a server error, if any.
+fn trace(trace_id: &Option<String>) -> String {
+    match trace_id {
+        Some(trace_id) => format!(" (trace id {trace_id})"),
+        None => String::new(),
+    }
+}
+
 impl std::error::Error for Error {}
 
 #[cfg(target_arch = "wasm32")]
@@ -617,3 +2858,190 @@ impl fmt::Display for ValidationError {
         }
     }
 }
+
+#[test]
+fn test_new_session_metadata() {
+    let session = |plaintext_metadata| NewSession {
+        plaintext_metadata,
+        program: String::new(),
+        function: "main".to_string(),
+        circuit_hash: [0; 32],
+        client_version: String::new(),
+        protocol_version: tandem::PROTOCOL_VERSION,
+        wire_version: MIN_WIRE_VERSION,
+        wire_versions: vec![MIN_WIRE_VERSION, WIRE_VERSION],
+        capabilities: vec![],
+        stage_final: false,
+        approval_id: None,
+        idempotency_key: None,
+        session_nonce: [0; 32],
+        channel_key: None,
+        follow_ups: None,
+        circuit: None,
+    };
+    // plain strings are sent as before, so that older servers still accept them:
+    let json = serde_json::to_value(session("false".into())).unwrap();
+    assert_eq!(json["plaintext_metadata"], "false");
+    let metadata = serde_json::json!({"user": 7, "tags": ["a"]});
+    let json = serde_json::to_value(session(metadata.clone())).unwrap();
+    assert_eq!(json["plaintext_metadata"], metadata);
+}
+
+/// The commitment of [`test_session_commitment`], keyed using `[4; 32]`.
+#[cfg(test)]
+const KEYED_COMMITMENT: &str = "616b1ff15be485dae8b006963dc0d75098170265b5ce70bf07555b9e0d9c6fcc";
+
+#[test]
+fn test_session_commitment() {
+    let req = NewSession {
+        plaintext_metadata: "".into(),
+        program: String::new(),
+        function: "main".to_string(),
+        circuit_hash: [3; 32],
+        client_version: String::new(),
+        protocol_version: tandem::PROTOCOL_VERSION,
+        wire_version: 1,
+        wire_versions: vec![1, 2],
+        capabilities: vec!["streaming".to_string()],
+        stage_final: true,
+        approval_id: None,
+        idempotency_key: None,
+        session_nonce: [1; 32],
+        channel_key: None,
+        follow_ups: None,
+        circuit: None,
+    };
+    // the commitment computed by the server for the same inputs:
+    let mut commitment = SessionCommitment {
+        server_nonce: [2; 32],
+        commitment: *blake3::Hash::from_hex(
+            "9055bd8004e77b4d71c88a552922097e74dbe794e545c3e2cf5364c4840f658e",
+        )
+        .unwrap()
+        .as_bytes(),
+    };
+    let capabilities = vec!["streaming".to_string()];
+    assert!(commitment
+        .verify(&req, "engine", 2, &capabilities, None)
+        .is_ok());
+    assert!(matches!(
+        commitment.verify(&req, "engine", 2, &[], None),
+        Err(Error::SessionCommitmentMismatch)
+    ));
+    assert!(commitment
+        .verify(&req, "replayed", 2, &capabilities, None)
+        .is_err());
+    // a commitment keyed by an encrypted channel cannot be computed without its key:
+    assert!(commitment
+        .verify(&req, "engine", 2, &capabilities, Some(&[4; 32]))
+        .is_err());
+    commitment.server_nonce[0] ^= 1;
+    assert!(commitment
+        .verify(&req, "engine", 2, &capabilities, None)
+        .is_err());
+
+    // the keyed commitment computed by the server for the same inputs:
+    let commitment = SessionCommitment {
+        server_nonce: [2; 32],
+        commitment: *blake3::Hash::from_hex(KEYED_COMMITMENT).unwrap().as_bytes(),
+    };
+    assert!(commitment
+        .verify(&req, "engine", 2, &capabilities, Some(&[4; 32]))
+        .is_ok());
+    assert!(commitment
+        .verify(&req, "engine", 2, &capabilities, None)
+        .is_err());
+}
+
+#[test]
+fn test_parse_content_range() {
+    assert_eq!(parse_content_range("bytes 0-9/100"), Some((0, 100)));
+    assert_eq!(parse_content_range("bytes 90-99/100"), Some((90, 100)));
+    assert_eq!(parse_content_range("bytes 90-99/*"), None);
+    assert_eq!(parse_content_range("bytes */100"), None);
+}
+
+#[test]
+fn test_server_error_trace_id() {
+    let error = r#"{"error":"UnexpectedWireFormat","args":"e1","trace_id":"t1"}"#;
+    assert!(matches!(
+        server_error(error.to_string()),
+        Error::ServerError(e) if e == "UnexpectedWireFormat: e1 (trace id t1)"
+    ));
+    let error = r#"{"error":"UnexpectedWireFormat","args":"e1"}"#;
+    assert!(matches!(
+        server_error(error.to_string()),
+        Error::ServerError(e) if e == "UnexpectedWireFormat: e1"
+    ));
+    assert_eq!(random_id().len(), 32);
+    assert_ne!(random_id(), random_id());
+}
+
+#[test]
+fn test_retryable_errors() {
+    let error = r#"{"error":"Internal","args":{"message":"oops"}}"#.to_string();
+    assert!(response_error(true, error.clone()).is_retryable());
+    assert!(!response_error(false, error).is_retryable());
+    let error = r#"{"error":"CircuitHashMismatch","args":{}}"#.to_string();
+    assert!(!response_error(false, error).is_retryable());
+    let quota = |quota: &str| Error::QuotaExceeded {
+        quota: quota.to_string(),
+        limit: 1,
+        requested: 2,
+        trace_id: None,
+    };
+    assert!(quota("total_and_gates").is_retryable());
+    assert!(!quota("session_and_gates").is_retryable());
+    assert!(Error::IncompleteDownload.is_retryable());
+    assert!(!Error::MessageOffsetMismatch.is_retryable());
+    assert!(!Error::TandemError(tandem::Error::MacError).is_retryable());
+}
+
+#[test]
+fn test_tandem_client() {
+    let client = TandemClient::new("http://localhost:8000", ComputeOptions::new()).unwrap();
+    assert_eq!(client.url.as_str(), "http://localhost:8000/");
+    assert!(matches!(
+        TandemClient::new("localhost", ComputeOptions::new()),
+        Err(Error::ParseError(_))
+    ));
+}
+
+#[test]
+fn test_mpc_data_bytes() {
+    let source_code = "pub fn main(x: (u8, bool), y: u8) -> u8 { x.0 + y }".to_string();
+    let program = MpcProgram::new(source_code, "main".to_string()).unwrap();
+    let input = MpcData::from_string(&program, "7u8".to_string()).unwrap();
+    let bytes = input.to_bytes().unwrap();
+    let decoded = MpcData::from_bytes(&program, &bytes).unwrap();
+    assert_eq!(decoded.to_literal_string(), "7u8");
+
+    let source_code = "pub fn main(x: u8, y: bool) -> bool { y }".to_string();
+    let other = MpcProgram::new(source_code, "main".to_string()).unwrap();
+    assert!(matches!(
+        MpcData::from_bytes(&other, &bytes),
+        Err(Error::ValidationError(_))
+    ));
+    assert!(matches!(
+        MpcData::from_bytes(&program, &[0xff]),
+        Err(Error::BincodeError)
+    ));
+}
+
+#[test]
+fn test_input_type() {
+    let source_code = "pub fn main(x: (u8, bool), y: [i16; 2]) -> u8 { x.0 }".to_string();
+    let program = MpcProgram::new(source_code, "main".to_string()).unwrap();
+    assert_eq!(program.expected_input_bits(Role::Contributor), 9);
+    assert_eq!(program.expected_input_bits(Role::Evaluator), 32);
+    assert_eq!(
+        program.input_type(Role::Evaluator),
+        &TypeDescription::Array {
+            elem: Box::new(TypeDescription::Signed {
+                name: "i16".to_string(),
+                bits: 16
+            }),
+            size: 2
+        }
+    );
+}
@@ -0,0 +1,254 @@
+//! In-process transport, connecting the client to a Tandem server running in the same process.
+//!
+//! Requests are dispatched to the server's Rocket instance using Rocket's local client, without
+//! binding a port or opening sockets, which is useful to test servers and clients end-to-end.
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use rocket::{
+    http::{Header, Status, StatusClass},
+    local::asynchronous::{Client, LocalRequest},
+    Build, Rocket,
+};
+use tandem::{states::Msg, Circuit};
+use url::{Position, Url};
+
+use crate::{
+    compute_raw_circuit, compute_session, frames, new_session, new_session_error, response_error,
+    ComputeOptions, EngineCreationResult, Error, MessageId, MessageLog, MpcData, MpcProgram,
+    NewSession, NextRound, NextRoundResult, TandemSession, Transport,
+};
+
+/// Base url of the local server, only used to resolve the paths returned by the server.
+const LOCAL_URL: &str = "http://localhost/";
+
+/// A Tandem server running in the same process, see [`connect_local`].
+#[derive(Clone)]
+pub struct LocalConnection {
+    client: Arc<Client>,
+}
+
+impl fmt::Debug for LocalConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LocalConnection").finish_non_exhaustive()
+    }
+}
+
+/// Launches the Rocket instance of a Tandem server (such as the one returned by
+/// `tandem_http_server::build`) in the same process, returning a connection that computes programs
+/// without sending any requests over the network.
+pub async fn connect_local(rocket: Rocket<Build>) -> Result<LocalConnection, Error> {
+    let client = Client::untracked(rocket)
+        .await
+        .map_err(|e| Error::ServerError(format!("The local server could not be launched: {e}")))?;
+    Ok(LocalConnection {
+        client: Arc::new(client),
+    })
+}
+
+impl LocalConnection {
+    /// Computes the program like [`crate::compute`], using the local server as the contributor.
+    pub async fn compute(
+        &self,
+        plaintext_metadata: String,
+        program: MpcProgram,
+        input: MpcData,
+    ) -> Result<MpcData, Error> {
+        let options = ComputeOptions::default();
+        self.compute_with_options(plaintext_metadata, program, input, options)
+            .await
+    }
+
+    /// Computes the program like [`crate::compute_with_options`], using the local server as the
+    /// contributor.
+    pub async fn compute_with_options(
+        &self,
+        plaintext_metadata: String,
+        program: MpcProgram,
+        input: MpcData,
+        options: ComputeOptions,
+    ) -> Result<MpcData, Error> {
+        let transport = Transport::Local(self.clone());
+        let url = Url::parse(LOCAL_URL)?;
+        let plaintext_metadata = serde_json::Value::String(plaintext_metadata);
+        compute_session(
+            &transport,
+            url,
+            &options,
+            plaintext_metadata,
+            program,
+            input,
+            None,
+            &(),
+        )
+        .await
+    }
+
+    /// Computes the raw circuit like [`crate::compute_circuit`], using the local server as the
+    /// contributor.
+    pub async fn compute_circuit(
+        &self,
+        plaintext_metadata: String,
+        circuit: Circuit,
+        input_bits: Vec<bool>,
+    ) -> Result<Vec<bool>, Error> {
+        let transport = Transport::Local(self.clone());
+        let url = Url::parse(LOCAL_URL)?;
+        let plaintext_metadata = serde_json::Value::String(plaintext_metadata);
+        let options = ComputeOptions::default();
+        compute_raw_circuit(
+            &transport,
+            url,
+            &options,
+            plaintext_metadata,
+            circuit,
+            input_bits,
+        )
+        .await
+    }
+
+    /// Creates a session like [`crate::TandemClient::new_session`], using the local server as the
+    /// contributor.
+    pub async fn new_session(
+        &self,
+        plaintext_metadata: String,
+        program: MpcProgram,
+        input: MpcData,
+    ) -> Result<TandemSession, Error> {
+        let options = ComputeOptions::default();
+        self.new_session_with_options(plaintext_metadata, program, input, options)
+            .await
+    }
+
+    /// Creates a session like [`LocalConnection::new_session`], applying the specified options.
+    pub async fn new_session_with_options(
+        &self,
+        plaintext_metadata: String,
+        program: MpcProgram,
+        input: MpcData,
+        options: ComputeOptions,
+    ) -> Result<TandemSession, Error> {
+        let transport = Transport::Local(self.clone());
+        let url = Url::parse(LOCAL_URL)?;
+        let plaintext_metadata = serde_json::Value::String(plaintext_metadata);
+        new_session(
+            &transport,
+            url,
+            &options,
+            plaintext_metadata,
+            program,
+            input,
+            None,
+        )
+        .await
+    }
+
+    pub(crate) async fn send_new_session(
+        &self,
+        url: &Url,
+        headers: &HashMap<String, String>,
+        session: &NewSession,
+        circuit: &Circuit,
+    ) -> Result<EngineCreationResult, Error> {
+        let req = with_headers(self.client.post(path(url)).json(session), headers);
+        let (status, body) = dispatch(req).await;
+        if status == Status::BadRequest {
+            return Err(new_session_error(into_string(body), circuit));
+        }
+        let body = success_or_err(status, body)?;
+        serde_json::from_slice(&body)
+            .map_err(|e| Error::ServerError(format!("Invalid session response: {e}")))
+    }
+
+    pub(crate) async fn send_msgs(
+        &self,
+        url: &Url,
+        request_headers: &HashMap<String, String>,
+        last_durably_received_offset: Option<u32>,
+        msgs: &[(&Msg, MessageId)],
+        streaming: bool,
+    ) -> Result<(MessageLog, Option<MessageId>), Error> {
+        let body = bincode::serialize(&(last_durably_received_offset, msgs))?;
+        let req = with_headers(self.client.post(path(url)).body(body), request_headers);
+        let (status, body) = dispatch(req).await;
+        let body = success_or_err(status, body)?;
+        if !streaming {
+            return Ok(bincode::deserialize(&body)?);
+        }
+        frames::decode(&body)
+    }
+
+    pub(crate) async fn send_next_round(
+        &self,
+        url: &Url,
+        request_headers: &HashMap<String, String>,
+        round: &NextRound,
+        circuit: &Circuit,
+    ) -> Result<NextRoundResult, Error> {
+        let req = with_headers(self.client.post(path(url)).json(round), request_headers);
+        let (status, body) = dispatch(req).await;
+        if status == Status::BadRequest {
+            return Err(new_session_error(into_string(body), circuit));
+        }
+        let body = success_or_err(status, body)?;
+        serde_json::from_slice(&body)
+            .map_err(|e| Error::ServerError(format!("Invalid round response: {e}")))
+    }
+
+    pub(crate) async fn delete_session(
+        &self,
+        url: &Url,
+        request_headers: &HashMap<String, String>,
+    ) -> Result<(), Error> {
+        let req = with_headers(self.client.delete(path(url)), request_headers);
+        let (status, body) = dispatch(req).await;
+        success_or_err(status, body)?;
+        Ok(())
+    }
+
+    /// Downloads a staged final message, which cannot be interrupted in-process and is thus always
+    /// requested in full.
+    pub(crate) async fn download_final(
+        &self,
+        url: &Url,
+        request_headers: &HashMap<String, String>,
+    ) -> Result<Msg, Error> {
+        let req = with_headers(self.client.get(path(url)), request_headers);
+        let (status, body) = dispatch(req).await;
+        success_or_err(status, body)
+    }
+}
+
+/// The path and query of the url, which is all that the local client needs.
+fn path(url: &Url) -> &str {
+    &url[Position::BeforePath..]
+}
+
+fn with_headers<'c>(
+    mut req: LocalRequest<'c>,
+    request_headers: &HashMap<String, String>,
+) -> LocalRequest<'c> {
+    for (k, v) in request_headers.iter() {
+        req.add_header(Header::new(k.clone(), v.clone()));
+    }
+    req
+}
+
+async fn dispatch(req: LocalRequest<'_>) -> (Status, Vec<u8>) {
+    let resp = req.dispatch().await;
+    let status = resp.status();
+    (status, resp.into_bytes().await.unwrap_or_default())
+}
+
+fn success_or_err(status: Status, body: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if status.class() == StatusClass::Success {
+        Ok(body)
+    } else {
+        let server_failed = status.class() == StatusClass::ServerError;
+        Err(response_error(server_failed, into_string(body)))
+    }
+}
+
+fn into_string(body: Vec<u8>) -> String {
+    String::from_utf8_lossy(&body).into_owned()
+}
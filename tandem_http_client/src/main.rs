@@ -1,13 +1,89 @@
 #![cfg(not(target_arch = "wasm32"))]
 
 use anyhow::Context;
-use clap::Parser;
-use std::{io::Read, path::PathBuf};
-use tandem_http_client::{compute, MpcData, MpcProgram};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use futures::{stream, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Read},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use tandem_http_client::{
+    compute_with_report, server_info, CircuitCache, ComputeOptions, MpcData, MpcProgram,
+    TandemClient,
+};
+
+const DEFAULT_URL: &str = "https://echo-server.sine.dev";
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    run: Option<RunArgs>,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value = "human",
+        help = "Format of the output, `json` prints results to stdout and errors to stderr as JSON"
+    )]
+    output: OutputFormat,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        action = clap::ArgAction::Count,
+        help = "Logs the compilation, session and timing to stderr, `-vv` also logs each round"
+    )]
+    verbose: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Human,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Displays the health, capabilities and published functions of a remote server
+    Info {
+        #[arg(
+            long,
+            default_value = DEFAULT_URL,
+            help = "Base URL of a remote tandem http server. "
+        )]
+        url: url::Url,
+    },
+    /// Checks that the cryptographic primitives work correctly on this host
+    SelfTest,
+    /// Type-checks and compiles a program and reports its circuit, without contacting any server
+    Check {
+        #[arg(value_parser, help = "Path to a Garble program file")]
+        program: PathBuf,
+
+        #[arg(
+            long,
+            required(true),
+            help = "Name of the Garble function to be compiled"
+        )]
+        function: String,
+    },
+    /// Runs the computation for every input of a CSV or JSONL file, printing one result per line
+    Batch(BatchArgs),
+}
+
+#[derive(Args, Debug)]
+struct BatchArgs {
     #[arg(value_parser, help = "Path to a Garble program file")]
     program: PathBuf,
 
@@ -20,7 +96,7 @@ struct Cli {
 
     #[arg(
         long,
-        default_value = "https://echo-server.sine.dev",
+        default_value = DEFAULT_URL,
         help = "Base URL of a remote tandem http server. "
     )]
     url: url::Url,
@@ -28,36 +104,446 @@ struct Cli {
     #[arg(
         long,
         required(true),
-        help = "Garble input literal for this (local) party"
+        value_name = "FILE",
+        help = "A `.csv` file with an `input` and an optional `metadata` column, or a `.jsonl` file with one `{\"input\": ..., \"metadata\": ...}` object per line"
     )]
-    input: String,
+    inputs: PathBuf,
+
+    #[arg(
+        long,
+        value_parser = ArgSource::parse,
+        help = "Metadata for all inputs without metadata of their own, `@<FILE>` to read it from a file or `-` to read it from stdin"
+    )]
+    metadata: Option<ArgSource>,
+
+    #[arg(
+        long,
+        default_value = "4",
+        help = "Maximum number of computations that run at the same time"
+    )]
+    concurrency: usize,
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    #[arg(value_parser, help = "Path to a Garble program file")]
+    program: PathBuf,
 
     #[arg(
         long,
         required(true),
-        help = "Metadata to send to the server (as plaintext) to influence the server's input"
+        help = "Name of the Garble function to be executed"
+    )]
+    function: String,
+
+    #[arg(
+        long,
+        default_value = DEFAULT_URL,
+        help = "Base URL of a remote tandem http server. "
+    )]
+    url: url::Url,
+
+    #[arg(
+        long,
+        required(true),
+        value_parser = ArgSource::parse,
+        help = "Garble input literal for this (local) party, `@<FILE>` to read it from a file or `-` to read it from stdin"
+    )]
+    input: ArgSource,
+
+    #[arg(
+        long,
+        required(true),
+        value_parser = ArgSource::parse,
+        help = "Metadata to send to the server (as plaintext) to influence the server's input, `@<FILE>` to read it from a file or `-` to read it from stdin"
+    )]
+    metadata: ArgSource,
+
+    #[arg(
+        long,
+        help = "Directory in which compiled circuits are cached across runs"
+    )]
+    circuit_cache: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Writes the circuit as a Graphviz DOT graph to the file before running it"
     )]
-    metadata: String,
+    emit_dot: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value = "1000",
+        help = "Maximum number of gates written to the DOT graph"
+    )]
+    dot_max_gates: usize,
+}
+
+/// The value of an argument, given either directly, as `@<FILE>` or as `-` for stdin.
+///
+/// A value that starts with `@` can be given directly by escaping it as `@@`.
+#[derive(Debug, Clone)]
+enum ArgSource {
+    Value(String),
+    File(PathBuf),
+    Stdin,
+}
+
+impl ArgSource {
+    fn parse(arg: &str) -> Result<Self, String> {
+        if arg == "-" {
+            Ok(ArgSource::Stdin)
+        } else if let Some(value) = arg.strip_prefix("@@") {
+            Ok(ArgSource::Value(format!("@{value}")))
+        } else if let Some(path) = arg.strip_prefix('@') {
+            if path.is_empty() {
+                return Err("expected a file name after `@`".to_string());
+            }
+            Ok(ArgSource::File(PathBuf::from(path)))
+        } else {
+            Ok(ArgSource::Value(arg.to_string()))
+        }
+    }
+
+    /// Reads the value, without the trailing newline of files or stdin.
+    fn read(self) -> anyhow::Result<String> {
+        let value = match self {
+            ArgSource::Value(value) => return Ok(value),
+            ArgSource::File(path) => read_program(&path)?,
+            ArgSource::Stdin => {
+                let mut value = String::new();
+                std::io::stdin()
+                    .read_to_string(&mut value)
+                    .with_context(|| "Could not read from stdin".to_string())?;
+                value
+            }
+        };
+        Ok(value.trim_end_matches(['\n', '\r']).to_string())
+    }
+
+    /// Describes where the value was read from (if not given directly), for error messages.
+    fn origin(&self) -> Option<String> {
+        match self {
+            ArgSource::Value(_) => None,
+            ArgSource::File(path) => Some(format!("file `{}`", path.display())),
+            ArgSource::Stdin => Some("stdin".to_string()),
+        }
+    }
 }
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<(), Box<dyn Error>> {
     let cli = Cli::parse();
+    let output = cli.output;
+    StderrLogger::init(cli.verbose);
+
+    let result = match (cli.command, cli.run) {
+        (Some(Command::Info { url }), _) => info(url, output).await,
+        (Some(Command::SelfTest), _) => self_test(output),
+        (Some(Command::Check { program, function }), _) => check(program, function, output),
+        (Some(Command::Batch(args)), _) => batch(args, output).await,
+        (None, Some(run)) => run_program(run, output).await,
+        (None, None) => {
+            use clap::CommandFactory;
+            Cli::command().print_help()?;
+            std::process::exit(2)
+        }
+    };
+    match (result, output) {
+        (Err(e), OutputFormat::Json) => {
+            eprintln!("{}", error_to_json(e.as_ref()));
+            std::process::exit(1)
+        }
+        (result, _) => result,
+    }
+}
+
+/// Logs the client's events to stderr, with the time since the start of the CLI, see `--verbose`.
+struct StderrLogger {
+    level: log::LevelFilter,
+    start: Instant,
+}
+
+impl StderrLogger {
+    fn init(verbose: u8) {
+        let level = match verbose {
+            0 => return,
+            1 => log::LevelFilter::Info,
+            _ => log::LevelFilter::Debug,
+        };
+        let logger = Box::leak(Box::new(StderrLogger {
+            level,
+            start: Instant::now(),
+        }));
+        if log::set_logger(logger).is_ok() {
+            log::set_max_level(level);
+        }
+    }
+}
+
+impl log::Log for StderrLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        // the logs of dependencies such as reqwest are not meant for users of the CLI:
+        metadata.level() <= self.level && metadata.target().starts_with("tandem")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            let elapsed = self.start.elapsed().as_secs_f64();
+            eprintln!("[{elapsed:8.3}s {:<5}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
 
-    let path = &cli.program;
+/// Describes the error as JSON, with the kind of the (first) [`tandem_http_client::Error`] in its
+/// chain of causes as a machine-readable identifier.
+fn error_to_json(e: &(dyn Error + 'static)) -> serde_json::Value {
+    let chain: Vec<&(dyn Error + 'static)> =
+        std::iter::successors(Some(e), |&e| e.source()).collect();
+    let kind = chain
+        .iter()
+        .find_map(|e| e.downcast_ref::<tandem_http_client::Error>())
+        .map_or("Error", |e| e.kind());
+    json!({
+        "error": {
+            "kind": kind,
+            "message": e.to_string(),
+            "causes": chain[1..].iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+        }
+    })
+}
+
+async fn info(url: url::Url, output: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let info = server_info(&url)
+        .await
+        .with_context(|| format!("Could not fetch server info from {url}"))?;
+    match output {
+        OutputFormat::Human => print!("{info}"),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&info)?),
+    }
+    Ok(())
+}
+
+fn self_test(output: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let report = tandem::self_test();
+    match output {
+        OutputFormat::Human => print!("{report}"),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&report)?),
+    }
+    if !report.passed() {
+        std::process::exit(1)
+    }
+    Ok(())
+}
 
+fn check(path: PathBuf, function: String, output: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let source_code = read_program(&path)?;
+    let program = MpcProgram::new(source_code, function)
+        .with_context(|| "Not a valid 2-Party Garble program".to_string())?;
+    match output {
+        OutputFormat::Human => print!("{}", program.report()),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&program.report())?),
+    }
+    Ok(())
+}
+
+async fn run_program(cli: RunArgs, output: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let start = Instant::now();
+    let source_code = read_program(&cli.program)?;
+    if let (ArgSource::Stdin, ArgSource::Stdin) = (&cli.input, &cli.metadata) {
+        return Err("Only one of `--input` and `--metadata` can be read from stdin".into());
+    }
+    let input_origin = cli.input.origin();
+    let input = cli.input.read()?;
+    let metadata = cli.metadata.read()?;
+
+    let program = match &cli.circuit_cache {
+        Some(dir) => {
+            let cache = CircuitCache::open(dir, CircuitCache::DEFAULT_MAX_BYTES)
+                .with_context(|| format!("Could not open circuit cache `{}`", dir.display()))?;
+            MpcProgram::new_cached(source_code, cli.function, &cache)
+        }
+        None => MpcProgram::new(source_code, cli.function),
+    }
+    .with_context(|| "Not a valid 2-Party Garble program".to_string())?;
+    if let Some(dot_path) = &cli.emit_dot {
+        std::fs::write(dot_path, program.to_dot(cli.dot_max_gates))
+            .with_context(|| format!("Could not write file `{}`", dot_path.display()))?;
+    }
+    let input = MpcData::from_string(&program, input).with_context(|| match &input_origin {
+        Some(origin) => format!("Not a valid Garble input (read from {origin})"),
+        None => "Not a valid Garble input".to_string(),
+    })?;
+    let report = program.report();
+    let compiled = Instant::now();
+    log::info!("Compiled the program in {:?}", compiled - start);
+    log::info!("Circuit: {}", program.report_gates());
+
+    let options = ComputeOptions::new();
+    let (result, compute_report) =
+        compute_with_report(cli.url.to_string(), metadata, program, input, options).await?;
+    let computed = Instant::now();
+    log::info!(
+        "Computed the result with {} in {:?} (total: {:?})",
+        cli.url,
+        computed - compiled,
+        computed - start
+    );
+    match output {
+        OutputFormat::Human => println!("{}", result.to_literal_string()),
+        OutputFormat::Json => {
+            let literal: serde_json::Value = serde_json::from_str(&result.to_json()?)?;
+            let json = json!({
+                "result": result.to_literal_string(),
+                "literal": literal,
+                "circuit": report,
+                "verification": result.verification(),
+                "report": compute_report,
+                "timing": {
+                    "compile_ms": (compiled - start).as_millis() as u64,
+                    "compute_ms": (computed - compiled).as_millis() as u64,
+                },
+            });
+            println!("{json}");
+        }
+    }
+    Ok(())
+}
+
+/// A single computation of a batch.
+#[derive(Debug, Deserialize)]
+struct BatchRow {
+    /// Line of the row in the batch file, for reporting the results.
+    #[serde(skip)]
+    line: u64,
+    input: String,
+    #[serde(default)]
+    metadata: Option<String>,
+}
+
+async fn batch(args: BatchArgs, output: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let start = Instant::now();
+    let source_code = read_program(&args.program)?;
+    let program = MpcProgram::new(source_code, args.function)
+        .with_context(|| "Not a valid 2-Party Garble program".to_string())?;
+    log::info!("Compiled the program in {:?}", start.elapsed());
+    log::info!("Circuit: {}", program.report_gates());
+    let metadata = args.metadata.map(ArgSource::read).transpose()?;
+    let rows = read_batch(&args.inputs)?;
+    let total = rows.len();
+    // all computations share the connections to the server:
+    let client = TandemClient::new(args.url.as_str(), ComputeOptions::new())?;
+
+    // results are printed in the order of the rows, as soon as all previous rows are done:
+    let mut results = stream::iter(rows)
+        .map(|row| {
+            let line = row.line;
+            let computation = compute_row(&client, program.clone(), metadata.clone(), row);
+            async move { (line, computation.await) }
+        })
+        .buffered(args.concurrency.max(1));
+    let mut failed = 0;
+    while let Some((line, result)) = results.next().await {
+        if result.is_err() {
+            failed += 1;
+        }
+        match (result, output) {
+            (Ok(result), OutputFormat::Human) => {
+                println!("line {line}: {}", result.to_literal_string())
+            }
+            (Err(e), OutputFormat::Human) => println!("line {line}: Error: {e:#}"),
+            (Ok(result), OutputFormat::Json) => {
+                let literal: serde_json::Value = serde_json::from_str(&result.to_json()?)?;
+                let json = json!({
+                    "line": line,
+                    "result": result.to_literal_string(),
+                    "literal": literal,
+                });
+                println!("{json}");
+            }
+            (Err(e), OutputFormat::Json) => {
+                let mut json = error_to_json(e.as_ref());
+                json["line"] = json!(line);
+                println!("{json}");
+            }
+        }
+    }
+    log::info!("Computed {total} rows in {:?}", start.elapsed());
+    if failed > 0 {
+        return Err(format!("{failed} of {total} computations failed").into());
+    }
+    Ok(())
+}
+
+async fn compute_row(
+    client: &TandemClient,
+    program: MpcProgram,
+    metadata: Option<String>,
+    row: BatchRow,
+) -> anyhow::Result<MpcData> {
+    let metadata = row
+        .metadata
+        .or(metadata)
+        .context("No metadata, neither in the row nor as `--metadata`")?;
+    let input = MpcData::from_string(&program, row.input)
+        .with_context(|| "Not a valid Garble input".to_string())?;
+    Ok(client.compute(program, input, metadata).await?)
+}
+
+/// Reads the rows of a `.csv` or `.jsonl` file, skipping empty lines of JSONL files.
+fn read_batch(path: &Path) -> anyhow::Result<Vec<BatchRow>> {
+    let file = std::fs::File::open(path)
+        .with_context(|| format!("Could not open file `{}`", path.display()))?;
+    let mut rows = vec![];
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("csv") => {
+            let mut reader = csv::Reader::from_reader(file);
+            let headers = reader
+                .headers()
+                .with_context(|| format!("Could not read the header of `{}`", path.display()))?
+                .clone();
+            for record in reader.records() {
+                let record =
+                    record.with_context(|| format!("Could not read `{}`", path.display()))?;
+                let line = record.position().map_or(0, |position| position.line());
+                let mut row: BatchRow = record.deserialize(Some(&headers)).with_context(|| {
+                    format!("Invalid row on line {line} of `{}`", path.display())
+                })?;
+                row.line = line;
+                rows.push(row);
+            }
+        }
+        Some("jsonl") => {
+            for (i, line) in BufReader::new(file).lines().enumerate() {
+                let line_number = i as u64 + 1;
+                let line = line.with_context(|| format!("Could not read `{}`", path.display()))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let mut row: BatchRow = serde_json::from_str(&line).with_context(|| {
+                    format!("Invalid JSON on line {line_number} of `{}`", path.display())
+                })?;
+                row.line = line_number;
+                rows.push(row);
+            }
+        }
+        _ => anyhow::bail!(
+            "Unsupported file `{}`, expected a `.csv` or `.jsonl` file",
+            path.display()
+        ),
+    }
+    Ok(rows)
+}
+
+/// Reads a file as a string, e.g. a Garble program.
+fn read_program(path: &Path) -> anyhow::Result<String> {
     let mut source_code = String::new();
     std::fs::File::open(path)
         .with_context(|| format!("Could not open file `{}`", path.display()))?
         .read_to_string(&mut source_code)
         .with_context(|| format!("Could not read file `{}`", path.display()))?;
-
-    let program = MpcProgram::new(source_code, cli.function)
-        .with_context(|| "Not a valid 2-Party Garble program".to_string())?;
-    let input = MpcData::from_string(&program, cli.input)
-        .with_context(|| "Not a valid Garble input".to_string())?;
-
-    let result = compute(cli.url.to_string(), cli.metadata, program, input).await?;
-    println!("{}", result.to_literal_string());
-    Ok(())
+    Ok(source_code)
 }
@@ -0,0 +1,133 @@
+//! Diagnostics for circuits of the client that do not match the circuit of the server.
+
+use std::fmt;
+
+use serde::Deserialize;
+use tandem::CircuitStats;
+use tandem_garble_interop::GARBLE_VERSION;
+
+type Stat = fn(&CircuitStats) -> usize;
+
+/// A comparison of the circuits compiled by client and server, whose hashes did not match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CircuitMismatch {
+    /// Stats of the circuit compiled by the client.
+    pub client_circuit: CircuitStats,
+    /// Stats of the circuit of the server, unless the server does not report them.
+    pub server_circuit: Option<CircuitStats>,
+    /// Version of the compiler used by the client.
+    pub client_compiler_version: String,
+    /// Version of the compiler used by the server, if reported by the server.
+    pub server_compiler_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct MismatchJson {
+    error: String,
+    #[serde(default)]
+    args: Option<MismatchArgs>,
+}
+
+#[derive(Deserialize)]
+struct MismatchArgs {
+    #[serde(default)]
+    server_circuit: Option<CircuitStats>,
+    #[serde(default)]
+    compiler_version: Option<String>,
+}
+
+impl CircuitMismatch {
+    /// Parses the error response of the server, if it reports a circuit hash mismatch.
+    ///
+    /// Older servers do not include any details, which are then reported as unknown.
+    pub(crate) fn from_error_body(body: &str, client_circuit: CircuitStats) -> Option<Self> {
+        let json: MismatchJson = serde_json::from_str(body).ok()?;
+        if json.error != "CircuitHashMismatch" {
+            return None;
+        }
+        let (server_circuit, server_compiler_version) = match json.args {
+            Some(args) => (args.server_circuit, args.compiler_version),
+            None => (None, None),
+        };
+        Some(Self {
+            client_circuit,
+            server_circuit,
+            client_compiler_version: format!("garble_lang {GARBLE_VERSION}"),
+            server_compiler_version,
+        })
+    }
+}
+
+impl fmt::Display for CircuitMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "The circuit compiled by the client does not match the circuit of the server:"
+        )?;
+        writeln!(f, "  {:<20} {:>20} {:>20}", "", "client", "server")?;
+        let client = &self.client_circuit;
+        let server = self.server_circuit.as_ref();
+        let rows: [(&str, Stat); 6] = [
+            ("AND gates", |s| s.and_gates),
+            ("XOR gates", |s| s.xor_gates),
+            ("NOT gates", |s| s.not_gates),
+            ("contributor inputs", |s| s.contrib_inputs),
+            ("evaluator inputs", |s| s.eval_inputs),
+            ("outputs", |s| s.outputs),
+        ];
+        for (name, stat) in rows {
+            let server = server.map_or("?".to_string(), |s| stat(s).to_string());
+            writeln!(f, "  {name:<20} {:>20} {server:>20}", stat(client))?;
+        }
+        let server_version = self.server_compiler_version.as_deref().unwrap_or("?");
+        writeln!(
+            f,
+            "  {:<20} {:>20} {server_version:>20}",
+            "compiler", self.client_compiler_version
+        )?;
+
+        match (server, &self.server_compiler_version) {
+            (_, Some(version)) if *version != self.client_compiler_version => write!(
+                f,
+                "Client and server use different compiler versions, which can compile the same \
+                program to different circuits."
+            ),
+            (Some(server), _) if server == client => write!(
+                f,
+                "Both circuits have the same gates, inputs and outputs, but are wired differently. \
+                Check that the program is exactly the same on both sides."
+            ),
+            (Some(_), _) => write!(
+                f,
+                "Check that client and server compile the same program and function."
+            ),
+            (None, _) => write!(
+                f,
+                "The server did not report any details about its circuit."
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_circuit_mismatch() {
+    let client = CircuitStats {
+        and_gates: 1,
+        xor_gates: 2,
+        not_gates: 0,
+        contrib_inputs: 8,
+        eval_inputs: 8,
+        outputs: 8,
+    };
+    let body = r#"{"error":"CircuitHashMismatch","args":{"server_circuit":{"and_gates":1,"xor_gates":3,"not_gates":0,"contrib_inputs":8,"eval_inputs":8,"outputs":8},"compiler_version":"garble_lang 0.0.1"}}"#;
+    let mismatch = CircuitMismatch::from_error_body(body, client).unwrap();
+    assert_eq!(mismatch.server_circuit.unwrap().xor_gates, 3);
+    let report = mismatch.to_string();
+    assert!(report.contains("different compiler versions"), "{report}");
+
+    let mismatch = CircuitMismatch::from_error_body(r#"{"error":"CircuitHashMismatch"}"#, client);
+    assert_eq!(mismatch.unwrap().server_circuit, None);
+
+    let body = r#"{"error":"MpcRequestRejected","args":"no handler"}"#;
+    assert_eq!(CircuitMismatch::from_error_body(body, client), None);
+}
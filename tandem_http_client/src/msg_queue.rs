@@ -6,13 +6,18 @@ pub(crate) type MessageId = u32;
 pub(crate) struct MsgQueue {
     send_q: VecDeque<Vec<u8>>,
     msg_counter: usize,
+    /// The budget of the queue in bytes, see [`MsgQueue::is_full`].
+    max_bytes: Option<usize>,
 }
 
 impl MsgQueue {
-    pub(crate) fn new() -> Self {
+    /// Creates a queue that is full once its unacknowledged messages reach `max_bytes` bytes,
+    /// unbounded if `None`.
+    pub(crate) fn new(max_bytes: Option<usize>) -> Self {
         Self {
             send_q: VecDeque::with_capacity(100),
             msg_counter: 0,
+            max_bytes,
         }
     }
 
@@ -34,10 +39,27 @@ impl MsgQueue {
         offset - first_offset
     }
 
+    /// The id of the next message that will be sent.
+    pub(crate) fn next_id(&self) -> MessageId {
+        self.msg_counter as MessageId
+    }
+
     pub(crate) fn send(&mut self, msg: Vec<u8>) {
         self.msg_counter += 1;
         self.send_q.push_back(msg);
     }
+
+    /// Total size in bytes of the messages that have not been acknowledged yet.
+    pub(crate) fn queued_bytes(&self) -> usize {
+        self.send_q.iter().map(Vec::len).sum()
+    }
+
+    /// Whether the unacknowledged messages have reached the budget of the queue, in which case no
+    /// further messages should be queued until earlier messages are acknowledged.
+    pub(crate) fn is_full(&self) -> bool {
+        self.max_bytes
+            .map_or(false, |max_bytes| self.queued_bytes() >= max_bytes)
+    }
 }
 
 pub struct MsgIter<'a>(vec_deque::Iter<'a, Vec<u8>>, MessageId);
@@ -62,10 +84,7 @@ impl MsgQueue {
 
 #[test]
 fn test_flush_queue() {
-    let c = MsgQueue {
-        send_q: Default::default(),
-        msg_counter: Default::default(),
-    };
+    let c = MsgQueue::new(None);
 
     {
         assert_eq!(0, c.clone().flush_queue(0));
@@ -90,3 +109,16 @@ fn test_flush_queue() {
         );
     }
 }
+
+#[test]
+fn test_queue_budget() {
+    let mut c = MsgQueue::new(Some(4));
+    c.send(vec![0; 3]);
+    assert!(!c.is_full());
+    c.send(vec![0; 3]);
+    assert_eq!(c.queued_bytes(), 6);
+    assert!(c.is_full());
+    c.flush_queue(0);
+    assert!(!c.is_full());
+    assert!(!MsgQueue::new(None).is_full());
+}
@@ -0,0 +1,107 @@
+//! Plain representation of Garble literals, as returned to JavaScript by `computeWithOptions`.
+
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use tandem_garble_interop::{Literal, SignedNumType, UnsignedNumType, VariantLiteral};
+
+/// Serializes a literal as a plain value instead of the tagged JSON representation of Garble.
+///
+/// Booleans, numbers, arrays and tuples (as arrays) and structs (as maps of their fields) are
+/// serialized as their natural counterparts. Numbers of 64 bits are serialized as 64-bit integers
+/// (which become a `BigInt` in JavaScript), all smaller numbers as 32-bit integers. Enums are
+/// serialized as a map with the `enum` name, the `variant` name and the `fields` of the variant.
+#[cfg_attr(not(target_arch = "wasm32"), allow(dead_code))]
+#[derive(Clone, Copy)]
+pub(crate) struct Plain<'a>(pub(crate) &'a Literal);
+
+impl Serialize for Plain<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Literal::True => serializer.serialize_bool(true),
+            Literal::False => serializer.serialize_bool(false),
+            Literal::NumUnsigned(n, UnsignedNumType::U64) => serializer.serialize_u64(*n),
+            Literal::NumUnsigned(n, _) => match u32::try_from(*n) {
+                Ok(n) => serializer.serialize_u32(n),
+                Err(_) => serializer.serialize_u64(*n),
+            },
+            Literal::NumSigned(n, SignedNumType::I64) => serializer.serialize_i64(*n),
+            Literal::NumSigned(n, _) => match i32::try_from(*n) {
+                Ok(n) => serializer.serialize_i32(n),
+                Err(_) => serializer.serialize_i64(*n),
+            },
+            Literal::ArrayRepeat(elem, size) => {
+                serializer.collect_seq(std::iter::repeat(Plain(elem)).take(*size))
+            }
+            Literal::Array(elems) | Literal::Tuple(elems) => {
+                serializer.collect_seq(elems.iter().map(Plain))
+            }
+            Literal::Struct(_, fields) => {
+                serializer.collect_map(fields.iter().map(|(name, value)| (name, Plain(value))))
+            }
+            Literal::Enum(name, variant, fields) => {
+                let fields = match fields {
+                    VariantLiteral::Unit => &[][..],
+                    VariantLiteral::Tuple(fields) => &fields[..],
+                };
+                let mut map = serializer.serialize_map(Some(3))?;
+                map.serialize_entry("enum", name)?;
+                map.serialize_entry("variant", variant)?;
+                map.serialize_entry("fields", &fields.iter().map(Plain).collect::<Vec<_>>())?;
+                map.end()
+            }
+            Literal::Range((min, _), (max, _)) => {
+                let mut map = serializer.serialize_map(Some(2))?;
+                map.serialize_entry("min", min)?;
+                map.serialize_entry("max", max)?;
+                map.end()
+            }
+        }
+    }
+}
+
+#[test]
+fn test_plain_literals() {
+    use serde_json::json;
+
+    let plain = |literal: &Literal| serde_json::to_value(Plain(literal)).unwrap();
+    let literal = Literal::Tuple(vec![
+        Literal::True,
+        Literal::NumUnsigned(7, UnsignedNumType::U8),
+        Literal::NumSigned(-3, SignedNumType::I64),
+        Literal::ArrayRepeat(Box::new(Literal::False), 2),
+    ]);
+    assert_eq!(plain(&literal), json!([true, 7, -3, [false, false]]));
+
+    let literal = Literal::Struct(
+        "Point".to_string(),
+        vec![
+            (
+                "x".to_string(),
+                Literal::NumUnsigned(1, UnsignedNumType::U32),
+            ),
+            (
+                "y".to_string(),
+                Literal::NumUnsigned(2, UnsignedNumType::U32),
+            ),
+        ],
+    );
+    assert_eq!(plain(&literal), json!({"x": 1, "y": 2}));
+
+    let literal = Literal::Enum(
+        "LogResult".to_string(),
+        "Ok".to_string(),
+        VariantLiteral::Tuple(vec![Literal::NumUnsigned(4, UnsignedNumType::Usize)]),
+    );
+    assert_eq!(
+        plain(&literal),
+        json!({"enum": "LogResult", "variant": "Ok", "fields": [4]})
+    );
+    let literal = Literal::Enum(
+        "Suit".to_string(),
+        "Hearts".to_string(),
+        VariantLiteral::Unit,
+    );
+    assert_eq!(
+        plain(&literal),
+        json!({"enum": "Suit", "variant": "Hearts", "fields": []})
+    );
+}
@@ -0,0 +1,59 @@
+//! In-memory cache of compiled programs, keyed by the hash of their source code and function, so
+//! that apps compiling the same program on every interaction only type-check and compile it once.
+//!
+//! The cache is local to the current thread, which in the wasm build is the page or worker.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::MpcProgram;
+
+pub(crate) type ProgramKey = [u8; 32];
+
+thread_local! {
+    static PROGRAMS: RefCell<HashMap<ProgramKey, MpcProgram>> = RefCell::new(HashMap::new());
+}
+
+/// The key of a compiled program, which also covers the crate version, as programs compiled by a
+/// different version of the Garble compiler might differ (which matters for the persistent cache
+/// of the wasm build).
+pub(crate) fn key(source_code: &str, function_name: &str) -> ProgramKey {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.update(&[0]);
+    hasher.update(&(source_code.len() as u64).to_le_bytes());
+    hasher.update(source_code.as_bytes());
+    hasher.update(function_name.as_bytes());
+    *hasher.finalize().as_bytes()
+}
+
+pub(crate) fn get(key: &ProgramKey) -> Option<MpcProgram> {
+    PROGRAMS.with(|programs| programs.borrow().get(key).cloned())
+}
+
+pub(crate) fn insert(key: ProgramKey, program: &MpcProgram) {
+    PROGRAMS.with(|programs| programs.borrow_mut().insert(key, program.clone()));
+}
+
+/// Removes all programs of the current thread from the cache used by [`MpcProgram::new`] and
+/// [`MpcProgram::new_cached`], so that they are compiled again.
+///
+/// (In the wasm build, `clearCache()` additionally clears the programs persisted in IndexedDB.)
+pub fn clear_cache() {
+    PROGRAMS.with(|programs| programs.borrow_mut().clear());
+}
+
+#[test]
+fn test_program_cache() {
+    let source_code = "pub fn main(x: u16, y: u16) -> u16 { x ^ y }";
+    let key = key(source_code, "main");
+    let program = MpcProgram::new(format!("  {source_code}\n"), "main".to_string()).unwrap();
+    let cached = get(&key).expect("program should be cached under its trimmed source code");
+    assert_eq!(
+        cached.circuit.gates.blake3_hash(),
+        program.circuit.gates.blake3_hash()
+    );
+    assert!(get(&self::key(source_code, "other")).is_none());
+
+    clear_cache();
+    assert!(get(&key).is_none());
+}
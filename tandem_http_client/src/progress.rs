@@ -0,0 +1,26 @@
+//! Progress reporting and cancellation of running computations.
+
+use serde::Serialize;
+
+/// How far the evaluation of a computation has progressed, see [`crate::TandemSession::progress`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    /// Number of protocol steps that the client has completed.
+    pub step: usize,
+    /// Total number of protocol steps, including the step that decrypts the output.
+    pub steps: usize,
+}
+
+/// Observes a computation while it is evaluated, `()` ignores all progress.
+pub(crate) trait Monitor {
+    /// Called whenever the client has processed another message of the server.
+    fn progress(&self, _progress: Progress) {}
+
+    /// Checked before each request to the server, the computation is aborted with
+    /// [`crate::Error::Cancelled`] once this returns `true`.
+    fn is_cancelled(&self) -> bool {
+        false
+    }
+}
+
+impl Monitor for () {}
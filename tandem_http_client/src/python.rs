@@ -0,0 +1,170 @@
+//! Python bindings of the client, built as the `tandem_http_client_py` extension module.
+//!
+//! The bindings wrap [`MpcProgram`], [`MpcData`] and [`TandemClient`], computations are run in
+//! the default executor of the running `asyncio` event loop and can thus be awaited.
+
+use std::{collections::HashMap, time::Duration};
+
+use pyo3::{
+    create_exception,
+    exceptions::{PyException, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+
+use crate::{ComputeOptions, Error, MpcData, MpcProgram, TandemClient};
+
+create_exception!(
+    tandem_http_client_py,
+    TandemError,
+    PyException,
+    "Raised if a program, an input or a computation fails, with the message and the kind of the \
+     error (such as `ValidationError`) as its arguments."
+);
+
+impl From<Error> for PyErr {
+    fn from(e: Error) -> Self {
+        TandemError::new_err((e.to_string(), e.kind()))
+    }
+}
+
+/// An MPC program that was type-checked and can be executed by the Tandem engine.
+#[pyclass(name = "MpcProgram", module = "tandem_http_client_py", frozen)]
+struct PyMpcProgram(MpcProgram);
+
+#[pymethods]
+impl PyMpcProgram {
+    /// Type-checks the specified function, returning a compiled program.
+    #[new]
+    fn new(source_code: String, function_name: String) -> PyResult<Self> {
+        Ok(Self(MpcProgram::new(source_code, function_name)?))
+    }
+
+    /// Returns the number of gates of the circuit as a human-readable report.
+    fn report_gates(&self) -> String {
+        self.0.report_gates()
+    }
+}
+
+/// Stores data (either inputs or output) in a Tandem-compatible format.
+#[pyclass(name = "MpcData", module = "tandem_http_client_py", frozen)]
+struct PyMpcData(MpcData);
+
+#[pymethods]
+impl PyMpcData {
+    /// Parses and type-checks a Garble literal as the input of the program.
+    #[staticmethod]
+    fn from_string(program: &PyMpcProgram, input: String) -> PyResult<Self> {
+        Ok(Self(MpcData::from_string(&program.0, input)?))
+    }
+
+    /// Parses and type-checks a Garble literal in its JSON representation.
+    #[staticmethod]
+    fn from_json(program: &PyMpcProgram, json: &str) -> PyResult<Self> {
+        Ok(Self(MpcData::from_json(&program.0, json)?))
+    }
+
+    /// Deserializes and type-checks data serialized by `to_bytes`.
+    #[staticmethod]
+    fn from_bytes(program: &PyMpcProgram, bytes: &[u8]) -> PyResult<Self> {
+        Ok(Self(MpcData::from_bytes(&program.0, bytes)?))
+    }
+
+    /// Returns the data as a Garble literal.
+    fn to_literal_string(&self) -> String {
+        self.0.to_literal_string()
+    }
+
+    /// Returns the data as a Garble literal in its JSON representation.
+    fn to_json(&self) -> PyResult<String> {
+        Ok(self.0.to_json()?)
+    }
+
+    /// Serializes the data as bytes, which can be stored and read again using `from_bytes`.
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        Ok(PyBytes::new(py, &self.0.to_bytes()?))
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_literal_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("MpcData({})", self.0.to_literal_string())
+    }
+}
+
+/// Computes the program with the Tandem server at the url, returning an awaitable of the output.
+///
+/// The headers are sent with every request and the timeout (in seconds) limits the time of the
+/// whole computation, like the `ComputeOptions` of the Rust client.
+#[pyfunction]
+#[pyo3(signature = (url, plaintext_metadata, program, input, *, headers = None, timeout = None))]
+fn compute<'py>(
+    py: Python<'py>,
+    url: String,
+    plaintext_metadata: String,
+    program: &PyMpcProgram,
+    input: &PyMpcData,
+    headers: Option<HashMap<String, String>>,
+    timeout: Option<f64>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let mut options = ComputeOptions::new();
+    if let Some(headers) = headers {
+        options = options.headers(headers);
+    }
+    if let Some(timeout) = timeout {
+        if !(timeout.is_finite() && timeout >= 0.0) {
+            return Err(PyValueError::new_err(
+                "`timeout` must be a number of seconds",
+            ));
+        }
+        options = options.timeout(Duration::from_secs_f64(timeout));
+    }
+    let computation = Computation {
+        client: TandemClient::new(&url, options)?,
+        program: program.0.clone(),
+        input: input.0.clone(),
+        plaintext_metadata,
+    };
+    // the executor runs the computation in a Python thread, which the event loop joins before the
+    // interpreter shuts down (unlike the threads of a Tokio runtime shared by all computations):
+    let event_loop = py.import("asyncio")?.call_method0("get_running_loop")?;
+    event_loop.call_method1("run_in_executor", (py.None(), computation))
+}
+
+/// A computation, run to completion on its own Tokio runtime once it is called by the executor.
+#[pyclass(module = "tandem_http_client_py", frozen)]
+struct Computation {
+    client: TandemClient,
+    program: MpcProgram,
+    input: MpcData,
+    plaintext_metadata: String,
+}
+
+#[pymethods]
+impl Computation {
+    fn __call__(&self, py: Python<'_>) -> PyResult<PyMpcData> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let output = py.allow_threads(|| {
+            runtime.block_on(self.client.compute(
+                self.program.clone(),
+                self.input.clone(),
+                self.plaintext_metadata.clone(),
+            ))
+        })?;
+        Ok(PyMpcData(output))
+    }
+}
+
+/// Python bindings of the Tandem HTTP client.
+#[pymodule]
+fn tandem_http_client_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyMpcProgram>()?;
+    m.add_class::<PyMpcData>()?;
+    m.add_function(wrap_pyfunction!(compute, m)?)?;
+    m.add("TandemError", m.py().get_type::<TandemError>())?;
+    Ok(())
+}
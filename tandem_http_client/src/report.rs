@@ -0,0 +1,175 @@
+//! Summary of a compiled program, which can be computed without contacting any server, and of
+//! the bandwidth and timing of a completed computation.
+
+use std::{fmt, time::Duration};
+
+use serde::{Serialize, Serializer};
+use tandem::{CircuitBlake3Hash, CircuitStats, ProtocolPlan};
+
+use crate::MpcProgram;
+
+/// Gate counts, depth, hash and estimated bandwidth of the circuit of an [`MpcProgram`].
+///
+/// The circuit hash is serialized as a hex string.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CircuitReport {
+    /// Name of the compiled Garble function.
+    pub function: String,
+    /// Gate counts and input and output bits of the circuit.
+    pub stats: CircuitStats,
+    /// Maximum number of gates on any path from an input to an output.
+    pub depth: usize,
+    /// Maximum number of AND gates on any path from an input to an output.
+    pub and_depth: usize,
+    /// The blake3 hash of the circuit, which must match the hash of the server's circuit.
+    #[serde(serialize_with = "serialize_hex")]
+    pub circuit_hash: CircuitBlake3Hash,
+    /// Estimated number of bytes sent by the server (as the contributor) during the protocol.
+    pub contributor_bytes: usize,
+    /// Estimated number of bytes sent by the client (as the evaluator) during the protocol.
+    pub evaluator_bytes: usize,
+}
+
+impl MpcProgram {
+    /// Summarizes the compiled circuit, see [`CircuitReport`].
+    pub fn report(&self) -> CircuitReport {
+        let circuit = &self.circuit.gates;
+        let plan = ProtocolPlan::new(circuit);
+        CircuitReport {
+            function: self.function_name.clone(),
+            stats: circuit.stats(),
+            depth: circuit.depth(),
+            and_depth: circuit.and_depth(),
+            circuit_hash: circuit.blake3_hash(),
+            contributor_bytes: plan.total_contributor_bytes(),
+            evaluator_bytes: plan.total_evaluator_bytes(),
+        }
+    }
+}
+
+impl fmt::Display for CircuitReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stats = &self.stats;
+        let hash = blake3::Hash::from(self.circuit_hash);
+        writeln!(f, "Function: {}", self.function)?;
+        writeln!(f, "  AND gates:          {}", stats.and_gates)?;
+        writeln!(f, "  XOR gates:          {}", stats.xor_gates)?;
+        writeln!(f, "  NOT gates:          {}", stats.not_gates)?;
+        writeln!(f, "  contributor inputs: {}", stats.contrib_inputs)?;
+        writeln!(f, "  evaluator inputs:   {}", stats.eval_inputs)?;
+        writeln!(f, "  outputs:            {}", stats.outputs)?;
+        writeln!(f, "  depth:              {}", self.depth)?;
+        writeln!(f, "  AND depth:          {}", self.and_depth)?;
+        writeln!(f, "  circuit hash:       {}", hash.to_hex())?;
+        writeln!(
+            f,
+            "  bandwidth:          {} sent by the server, {} sent by the client",
+            format_bytes(self.contributor_bytes),
+            format_bytes(self.evaluator_bytes)
+        )
+    }
+}
+
+/// Round trips, bandwidth and timing of a computation, returned by
+/// [`crate::TandemClient::compute_with_report`] and [`crate::TandemSession::report`].
+///
+/// The bytes count the messages of the protocol exchanged with the server (after the encryption
+/// of an encrypted channel), not the HTTP headers or the request that creates the session. The
+/// timings are serialized as (fractional) milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ComputeReport {
+    /// Number of requests exchanging messages with the server, including the creation of the
+    /// session and the download of a staged final message.
+    pub rounds: usize,
+    /// Number of bytes sent by the client.
+    pub bytes_sent: usize,
+    /// Number of bytes received from the server.
+    pub bytes_received: usize,
+    /// Time spent in each phase of the computation.
+    pub phase_timings: PhaseTimings,
+}
+
+/// Time spent in each phase of a computation, see [`ComputeReport`].
+///
+/// Sessions that are evaluated step by step only count the time spent within each step.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PhaseTimings {
+    /// Creating the session with the server, including retries.
+    #[serde(serialize_with = "serialize_millis")]
+    pub session: Duration,
+    /// Exchanging and processing the messages of the protocol, until the final message of the
+    /// server has been received.
+    #[serde(serialize_with = "serialize_millis")]
+    pub evaluation: Duration,
+    /// Downloading (if staged) and processing the final message of the server, including the
+    /// confirmation of the transcript or the disclosure of the output.
+    #[serde(serialize_with = "serialize_millis")]
+    pub output: Duration,
+}
+
+impl PhaseTimings {
+    /// The time spent in all phases.
+    pub fn total(&self) -> Duration {
+        self.session + self.evaluation + self.output
+    }
+}
+
+fn serialize_millis<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+fn serialize_hex<S: Serializer>(hash: &CircuitBlake3Hash, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&blake3::Hash::from(*hash).to_hex())
+}
+
+fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        b if b >= 1 << 30 => format!("{:.1} GiB", b as f64 / (1u64 << 30) as f64),
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1u64 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1u64 << 10) as f64),
+        b => format!("{b} B"),
+    }
+}
+
+#[test]
+fn test_circuit_report() {
+    let program = MpcProgram::new(
+        "pub fn main(x: u8, y: u8) -> u8 { x & y }".to_string(),
+        "main".to_string(),
+    )
+    .unwrap();
+    let report = program.report();
+    assert_eq!(report.stats.and_gates, 8);
+    assert_eq!(report.and_depth, 1);
+    assert!(report.evaluator_bytes > 0);
+    assert_eq!(report.circuit_hash, program.circuit.gates.blake3_hash());
+
+    let printed = report.to_string();
+    assert!(printed.contains("Function: main"), "{printed}");
+    assert!(printed.contains(&blake3::Hash::from(report.circuit_hash).to_hex().to_string()));
+    assert_eq!(format_bytes(1536), "1.5 KiB");
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["stats"]["and_gates"], 8);
+    assert_eq!(json["circuit_hash"].as_str().unwrap().len(), 64);
+}
+
+#[test]
+fn test_compute_report() {
+    let report = ComputeReport {
+        rounds: 3,
+        bytes_sent: 2048,
+        bytes_received: 512,
+        phase_timings: PhaseTimings {
+            session: Duration::from_millis(10),
+            evaluation: Duration::from_millis(25),
+            output: Duration::from_micros(500),
+        },
+    };
+    assert_eq!(report.phase_timings.total(), Duration::from_micros(35_500));
+
+    let json = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["bytes_received"], 512);
+    assert_eq!(json["phase_timings"]["evaluation"], 25.0);
+    assert_eq!(json["phase_timings"]["output"], 0.5);
+}
@@ -0,0 +1,151 @@
+//! Timeouts of the HTTP requests of a computation, see [`ComputeOptions`].
+
+use std::time::Duration;
+
+use reqwest::{Client, RequestBuilder};
+
+use crate::{ComputeOptions, Error};
+
+/// The timeouts of a single computation, whose deadline starts when the computation starts.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timeouts {
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    request: Option<Duration>,
+    deadline: Option<Clock>,
+}
+
+impl Timeouts {
+    /// Starts the computation now, failing requests after the options' overall timeout.
+    pub(crate) fn start(options: &ComputeOptions) -> Self {
+        Self {
+            request: options.request_timeout,
+            deadline: options.timeout.map(|timeout| Clock::now().add(timeout)),
+        }
+    }
+
+    /// Limits the request (including its response body) to the request timeout, or to the
+    /// remaining time until the deadline if that is shorter.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn apply(&self, req: RequestBuilder) -> Result<RequestBuilder, Error> {
+        let remaining = self.remaining()?;
+        let timeout = match (self.request, remaining) {
+            (Some(request), Some(remaining)) => Some(request.min(remaining)),
+            (timeout, None) | (None, timeout) => timeout,
+        };
+        Ok(match timeout {
+            Some(timeout) => req.timeout(timeout),
+            None => req,
+        })
+    }
+
+    /// Fails the request if the deadline has passed.
+    ///
+    /// Requests sent using the fetch API of the browser cannot be cancelled by reqwest, so that
+    /// requests in wasm are neither limited by the request timeout nor by the remaining time.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn apply(&self, req: RequestBuilder) -> Result<RequestBuilder, Error> {
+        self.remaining()?;
+        Ok(req)
+    }
+
+    /// The time remaining until the deadline, failing if the deadline has passed.
+    fn remaining(&self) -> Result<Option<Duration>, Error> {
+        match self.deadline {
+            Some(deadline) => match deadline.remaining() {
+                Some(remaining) => Ok(Some(remaining)),
+                None => Err(Error::DeadlineExceeded),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+/// Returns a client that gives up connecting after the connect timeout of the options.
+///
+/// Browsers do not support connect timeouts, the options are thus ignored in wasm.
+#[cfg_attr(target_arch = "wasm32", allow(unused_variables))]
+pub(crate) fn client(options: &ComputeOptions) -> Result<Client, Error> {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(connect) = options.connect_timeout {
+        return Ok(Client::builder().connect_timeout(connect).build()?);
+    }
+    Ok(Client::new())
+}
+
+/// A point in time, using the monotonic clock.
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Clock(std::time::Instant);
+
+/// A point in time as milliseconds since the Unix epoch, using the clock of the browser as
+/// [`std::time::Instant`] is not supported in wasm.
+#[cfg(target_arch = "wasm32")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Clock(f64);
+
+impl Clock {
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn now() -> Self {
+        Self(std::time::Instant::now())
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn now() -> Self {
+        Self(js_sys::Date::now())
+    }
+
+    /// The time since this point in time, used to log and report the latency of requests.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn elapsed(self) -> Duration {
+        self.0.elapsed()
+    }
+
+    /// The time since this point in time, used to log and report the latency of requests.
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn elapsed(self) -> Duration {
+        Duration::from_secs_f64((js_sys::Date::now() - self.0).max(0.0) / 1000.0)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn add(self, duration: Duration) -> Self {
+        Self(self.0 + duration)
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn add(self, duration: Duration) -> Self {
+        Self(self.0 + duration.as_secs_f64() * 1000.0)
+    }
+
+    /// The time until this point in time, `None` if it has passed.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn remaining(self) -> Option<Duration> {
+        let remaining = self.0.checked_duration_since(std::time::Instant::now())?;
+        if remaining.is_zero() {
+            None
+        } else {
+            Some(remaining)
+        }
+    }
+
+    /// The time until this point in time, `None` if it has passed.
+    #[cfg(target_arch = "wasm32")]
+    fn remaining(self) -> Option<Duration> {
+        let remaining = self.0 - js_sys::Date::now();
+        (remaining > 0.0).then(|| Duration::from_secs_f64(remaining / 1000.0))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[test]
+fn test_deadline() {
+    let options = ComputeOptions::new().timeout(Duration::from_secs(60));
+    let timeouts = Timeouts::start(&options);
+    let remaining = timeouts.deadline.unwrap().remaining().unwrap();
+    assert!(remaining <= Duration::from_secs(60));
+    assert!(remaining > Duration::from_secs(50));
+
+    let options = ComputeOptions::new().timeout(Duration::ZERO);
+    let timeouts = Timeouts::start(&options);
+    let req = Client::new().get("http://localhost/");
+    assert!(matches!(timeouts.apply(req), Err(Error::DeadlineExceeded)));
+}
@@ -0,0 +1,116 @@
+//! Error responses of the server, mirroring the `Error` enum of `tandem_http_server`.
+//!
+//! Only the errors that callers can sensibly react to are mirrored, all other errors are reported
+//! as [`Error::ServerError`].
+
+use serde::Deserialize;
+
+use crate::Error;
+
+/// The JSON of an error response, `{"error": <variant>, "args": <fields>}`.
+#[derive(Deserialize, Debug, PartialEq, Eq)]
+#[serde(tag = "error", content = "args")]
+enum WireError {
+    NoSuchEngineId {
+        engine_id: String,
+    },
+    MpcRequestRejected(String),
+    ClientBlocked,
+    QuotaExceeded {
+        quota: String,
+        limit: usize,
+        requested: usize,
+    },
+    QueueFull {
+        queued_bytes: usize,
+        limit: usize,
+    },
+    UnknownProgram {
+        circuit_hash: String,
+    },
+}
+
+/// The trace id included in error responses, see [`crate::TRACE_ID_HEADER`].
+#[derive(Deserialize)]
+struct TraceJson {
+    #[serde(default)]
+    trace_id: Option<String>,
+}
+
+/// Parses the error response into a typed error, if the error is mirrored by the client.
+pub(crate) fn parse(body: &str) -> Option<Error> {
+    let error = serde_json::from_str(body).ok()?;
+    let trace_id = serde_json::from_str::<TraceJson>(body).ok()?.trace_id;
+    Some(match error {
+        WireError::NoSuchEngineId { engine_id } => Error::NoSuchEngineId {
+            engine_id,
+            trace_id,
+        },
+        WireError::MpcRequestRejected(reason) => Error::MpcRequestRejected { reason, trace_id },
+        WireError::ClientBlocked => Error::ClientBlocked { trace_id },
+        WireError::QuotaExceeded {
+            quota,
+            limit,
+            requested,
+        } => Error::QuotaExceeded {
+            quota,
+            limit,
+            requested,
+            trace_id,
+        },
+        WireError::QueueFull {
+            queued_bytes,
+            limit,
+        } => Error::QueueFull {
+            queued_bytes,
+            limit,
+            trace_id,
+        },
+        WireError::UnknownProgram { circuit_hash } => Error::UnknownProgram {
+            circuit_hash,
+            trace_id,
+        },
+    })
+}
+
+#[test]
+fn test_parse_wire_error() {
+    let error = r#"{"error":"NoSuchEngineId","args":{"engine_id":"e1"},"trace_id":"t1"}"#;
+    assert!(matches!(
+        parse(error),
+        Some(Error::NoSuchEngineId { engine_id, trace_id })
+            if engine_id == "e1" && trace_id.as_deref() == Some("t1")
+    ));
+    let error = r#"{"error":"MpcRequestRejected","args":"invalid metadata"}"#;
+    assert!(matches!(
+        parse(error),
+        Some(Error::MpcRequestRejected { reason, trace_id: None }) if reason == "invalid metadata"
+    ));
+    let error = r#"{"error":"ClientBlocked"}"#;
+    assert!(matches!(
+        parse(error),
+        Some(Error::ClientBlocked { trace_id: None })
+    ));
+    let error =
+        r#"{"error":"QuotaExceeded","args":{"quota":"total_and_gates","limit":1,"requested":2}}"#;
+    assert!(matches!(
+        parse(error),
+        Some(Error::QuotaExceeded { quota, limit: 1, requested: 2, .. }) if quota == "total_and_gates"
+    ));
+    let error = r#"{"error":"QueueFull","args":{"queued_bytes":5,"limit":4}}"#;
+    assert!(matches!(
+        parse(error),
+        Some(Error::QueueFull {
+            queued_bytes: 5,
+            limit: 4,
+            trace_id: None
+        })
+    ));
+    let error = r#"{"error":"UnknownProgram","args":{"circuit_hash":"ab"}}"#;
+    assert!(matches!(
+        parse(error),
+        Some(Error::UnknownProgram { circuit_hash, trace_id: None }) if circuit_hash == "ab"
+    ));
+    assert!(parse(r#"{"error":"Bincode"}"#).is_none());
+    assert!(parse("not json").is_none());
+}
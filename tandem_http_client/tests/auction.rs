@@ -0,0 +1,88 @@
+#![cfg(all(feature = "auction", not(target_arch = "wasm32")))]
+
+use rand::prelude::*;
+use std::process::{Child, Command, Stdio};
+use tandem_http_client::auction::{run_auction, BidderOutcome, SellerOutcome};
+
+#[test]
+fn integration_test_auction() -> Result<(), Box<dyn std::error::Error>> {
+    let (server, url) = start_server()?;
+    let result = run_auctions(&url);
+    let _ = stop_server(server);
+    result
+}
+
+fn run_auctions(url: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let rt = tokio::runtime::Runtime::new()?;
+
+    let result = rt.block_on(run_auction(url.into(), "lot1".into(), &[80, 150, 120]))?;
+    assert_eq!(
+        result.bidder_outcome,
+        BidderOutcome::Sold {
+            winner: 1,
+            price: 120
+        }
+    );
+    assert_eq!(result.seller_outcome, SellerOutcome::Sold(120));
+    assert_eq!(result.receipts.len(), 3);
+    for receipt in result.receipts.iter() {
+        assert!(receipt.commitment.verify(receipt.bid, &receipt.nonce));
+    }
+
+    // the reserve price is paid if the second-highest bid is lower:
+    let result = rt.block_on(run_auction(url.into(), "lot1".into(), &[20, 110]))?;
+    assert_eq!(
+        result.bidder_outcome,
+        BidderOutcome::Sold {
+            winner: 1,
+            price: 100
+        }
+    );
+    assert_eq!(result.seller_outcome, SellerOutcome::Sold(100));
+
+    let result = rt.block_on(run_auction(url.into(), "lot2".into(), &[80, 150, 120]))?;
+    assert_eq!(result.bidder_outcome, BidderOutcome::NotSold);
+    assert_eq!(result.seller_outcome, SellerOutcome::NotSold);
+
+    assert!(rt
+        .block_on(run_auction(url.into(), "lot1".into(), &[1, 2, 3, 4, 5]))
+        .is_err());
+    assert!(rt
+        .block_on(run_auction(url.into(), "lot3".into(), &[1, 2]))
+        .is_err());
+    Ok(())
+}
+
+fn start_server() -> Result<(Child, String), Box<dyn std::error::Error>> {
+    Command::new("cargo")
+        .args(["build", "-p", "tandem_http_server", "--features=bin"])
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()?;
+    let port: u16 = thread_rng().gen_range(9001..=10000);
+    let server = env!("CARGO_MANIFEST_DIR").to_string() + "/../target/debug/tandem_http_server";
+    let mut proc = Command::new(server)
+        .current_dir(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/examples/auction_setup"
+        ))
+        .env("ROCKET_PORT", port.to_string())
+        .env("ROCKET_LOG_LEVEL", "off")
+        .spawn()?;
+
+    let connection_string = format!("127.0.0.1:{port}");
+    for _ in 0..250 {
+        if std::net::TcpStream::connect(&connection_string).is_ok() {
+            return Ok((proc, format!("http://{connection_string}")));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    let _ = proc.kill();
+    Err("Timeout while starting tandem_http_server".into())
+}
+
+fn stop_server(mut c: Child) -> Result<(), Box<dyn std::error::Error>> {
+    c.kill()?;
+    c.wait()?;
+    Ok(())
+}
@@ -1,4 +1,5 @@
 #![cfg(not(target_arch = "wasm32"))]
+#![allow(unexpected_cfgs)]
 
 use assert_cmd::prelude::*; // Add methods on commands
 use predicates::prelude::*; // Used for writing assertions
@@ -42,6 +43,199 @@ fn test_too_many_parties() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[test]
+fn test_check() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin(CRATE_NAME)?
+        .args(["check", "tests/.add.garble.rs", "--function", "main"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Function: main"))
+        .stdout(predicate::str::contains("circuit hash:"))
+        .stdout(predicate::str::contains("bandwidth:"));
+
+    Command::cargo_bin(CRATE_NAME)?
+        .args([
+            "check",
+            "tests/.manyparties.garble.rs",
+            "--function",
+            "main",
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("not a 2-Party function"));
+
+    Ok(())
+}
+
+#[test]
+fn test_json_output() -> Result<(), Box<dyn std::error::Error>> {
+    Command::cargo_bin(CRATE_NAME)?
+        .args(["check", "tests/.add.garble.rs", "--function", "main"])
+        .args(["--output", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(r#""function":"main""#))
+        .stdout(predicate::str::contains(r#""circuit_hash":""#));
+
+    new_command(SERVER_URL, "tests/.manyparties.garble.rs", "main", "", "")?
+        .args(["--output", "json"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::starts_with(r#"{"error":{"#))
+        .stderr(predicate::str::contains(r#""kind":"ValidationError""#));
+
+    Ok(())
+}
+
+#[test]
+fn test_input_sources() -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::env::temp_dir().join(format!("tandem_input_{}.garble", random::<u64>()));
+    std::fs::write(&file, "not a literal\n")?;
+    new_command(
+        SERVER_URL,
+        "tests/.add.garble.rs",
+        "main",
+        &format!("@{}", file.display()),
+        "0u8",
+    )?
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains(format!(
+        "Not a valid Garble input (read from file `{}`)",
+        file.display()
+    )));
+    std::fs::remove_file(&file)?;
+
+    new_command(SERVER_URL, "tests/.add.garble.rs", "main", "-", "0u8")?
+        .write_stdin("not a literal\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "Not a valid Garble input (read from stdin)",
+        ));
+
+    new_command(SERVER_URL, "tests/.add.garble.rs", "main", "-", "-")?
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Only one of"));
+
+    new_command(
+        SERVER_URL,
+        "tests/.add.garble.rs",
+        "main",
+        "@missing",
+        "0u8",
+    )?
+    .assert()
+    .failure()
+    .stderr(predicate::str::contains("Could not open file `missing`"));
+
+    Ok(())
+}
+
+#[test]
+fn integration_test_input_sources() -> Result<(), Box<dyn std::error::Error>> {
+    with_server(|connection_string| {
+        let metadata = std::env::temp_dir().join(format!("tandem_meta_{}.garble", random::<u64>()));
+        std::fs::write(&metadata, "3u8\n")?;
+        new_command(
+            connection_string,
+            "tests/.add.garble.rs",
+            "main",
+            "-",
+            &format!("@{}", metadata.display()),
+        )?
+        .write_stdin("2u8\n")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("5u8"));
+        std::fs::remove_file(&metadata)?;
+        Ok(())
+    })
+}
+
+#[test]
+fn test_batch_errors() -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::env::temp_dir().join(format!("tandem_batch_{}.csv", random::<u64>()));
+    std::fs::write(&file, "input,metadata\nfoo,1u8\n\"(1u8, 2u8)\",1u8\n")?;
+    let batch = |output: &str| -> Result<Command, Box<dyn std::error::Error>> {
+        let mut cmd = Command::cargo_bin(CRATE_NAME)?;
+        cmd.args(["batch", "tests/.add.garble.rs", "--function", "main"])
+            .arg("--inputs")
+            .arg(&file)
+            .args(["--output", output]);
+        Ok(cmd)
+    };
+    batch("human")?
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(
+            "line 2: Error: Not a valid Garble input",
+        ))
+        .stdout(predicate::str::contains(
+            "line 3: Error: Not a valid Garble input",
+        ))
+        .stderr(predicate::str::contains("2 of 2 computations failed"));
+    batch("json")?
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains(r#""line":3"#))
+        .stdout(predicate::str::contains(r#""kind":"ValidationError""#));
+    std::fs::remove_file(&file)?;
+
+    Command::cargo_bin(CRATE_NAME)?
+        .args(["batch", "tests/.add.garble.rs", "--function", "main"])
+        .args(["--inputs", "tests/.add.garble.rs"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "expected a `.csv` or `.jsonl` file",
+        ));
+
+    Ok(())
+}
+
+#[test]
+fn integration_test_batch() -> Result<(), Box<dyn std::error::Error>> {
+    with_server(|connection_string| {
+        let file = std::env::temp_dir().join(format!("tandem_batch_{}.jsonl", random::<u64>()));
+        let rows = [
+            r#"{"input": "1u8", "metadata": "2u8"}"#,
+            "",
+            r#"{"input": "3u8"}"#,
+            r#"{"input": "5u8", "metadata": "6u8"}"#,
+        ];
+        std::fs::write(&file, rows.join("\n"))?;
+        Command::cargo_bin(CRATE_NAME)?
+            .args(["batch", "tests/.add.garble.rs", "--function", "main"])
+            .args(["--url", connection_string, "--metadata", "10u8"])
+            .args(["--concurrency", "2"])
+            .arg("--inputs")
+            .arg(&file)
+            .assert()
+            .success()
+            .stdout("line 1: 3u8\nline 3: 13u8\nline 4: 11u8\n");
+        std::fs::remove_file(&file)?;
+        Ok(())
+    })
+}
+
+#[test]
+fn integration_test_info() -> Result<(), Box<dyn std::error::Error>> {
+    with_server(|connection_string| {
+        Command::cargo_bin(CRATE_NAME)?
+            .args(["info", "--url", connection_string])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Health: ok"))
+            .stdout(predicate::str::contains("Features: streaming"))
+            .stdout(predicate::str::contains(
+                "Published functions: not available",
+            ));
+        Ok(())
+    })
+}
+
 #[test]
 fn integration_test_and() -> Result<(), Box<dyn std::error::Error>> {
     with_server(|connection_string| {
@@ -65,6 +259,16 @@ fn integration_test_and() -> Result<(), Box<dyn std::error::Error>> {
                 cmd.assert()
                     .success()
                     .stdout(predicate::str::contains(format!("{}", party_a + party_b)));
+                cmd.args(["--output", "json"])
+                    .assert()
+                    .success()
+                    .stdout(predicate::str::contains(format!(
+                        r#""result":"{}u8""#,
+                        party_a + party_b
+                    )))
+                    .stdout(predicate::str::contains(r#""compute_ms":"#))
+                    .stdout(predicate::str::contains(r#""bytes_received":"#))
+                    .stdout(predicate::str::contains(r#""verified":true"#));
             }
         }
 
@@ -72,6 +276,31 @@ fn integration_test_and() -> Result<(), Box<dyn std::error::Error>> {
     })
 }
 
+#[test]
+fn integration_test_verbose() -> Result<(), Box<dyn std::error::Error>> {
+    with_server(|url| {
+        new_command(url, "tests/.add.garble.rs", "main", "2u8", "3u8")?
+            .arg("-v")
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("5u8"))
+            .stderr(predicate::str::contains("Compiled the program in"))
+            .stderr(predicate::str::contains("Circuit:"))
+            .stderr(predicate::str::contains("Created session"))
+            .stderr(predicate::str::contains("Computed the result"))
+            .stderr(predicate::str::contains("Step ").not());
+
+        new_command(url, "tests/.add.garble.rs", "main", "2u8", "3u8")?
+            .arg("-vv")
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("Step 1/"))
+            .stderr(predicate::str::contains("bytes in"));
+
+        Ok(())
+    })
+}
+
 #[test]
 fn integration_test_div_by_zero() -> Result<(), Box<dyn std::error::Error>> {
     with_server(|url| {
@@ -93,14 +322,41 @@ fn integration_test_div_by_zero() -> Result<(), Box<dyn std::error::Error>> {
     })
 }
 
+#[test]
+fn integration_test_max_and_gates() -> Result<(), Box<dyn std::error::Error>> {
+    with_configured_server(&[("TANDEM_MAX_AND_GATES", "1")], |url| {
+        new_command(url, "tests/.add.garble.rs", "main", "2u8", "3u8")?
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "but the server accepts at most 1 AND gates",
+            ));
+
+        Ok(())
+    })
+}
+
+#[test]
+fn integration_test_compile_limits() -> Result<(), Box<dyn std::error::Error>> {
+    // the client compiles the program as well, so only the memory limit can be exceeded here:
+    with_configured_server(&[("TANDEM_COMPILE_MEMORY_BYTES", "1000000")], |url| {
+        new_command(url, "tests/.add.garble.rs", "main", "2u8", "3u8")?
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("compilation exceeded limits"));
+
+        Ok(())
+    })
+}
+
 fn new_command(
     url: &str,
     program: &str,
     function: &str,
     input: &str,
     metadata: &str,
-) -> Result<Command, Box<dyn std::error::Error>> {
-    let mut cmd = Command::cargo_bin(CRATE_NAME)?;
+) -> Result<assert_cmd::Command, Box<dyn std::error::Error>> {
+    let mut cmd = assert_cmd::Command::cargo_bin(CRATE_NAME)?;
     cmd.arg(program)
         .args(["--function", function, "--url", url])
         .arg("--input")
@@ -111,7 +367,7 @@ fn new_command(
     Ok(cmd)
 }
 
-fn start_server() -> Result<(Child, String), Box<dyn std::error::Error>> {
+fn start_server(envs: &[(&str, &str)]) -> Result<(Child, String), Box<dyn std::error::Error>> {
     if cfg!(not(tarpaulin)) {
         println!("Compiling tandem_http_server, this might take a few minutes");
         Command::new("cargo")
@@ -129,10 +385,12 @@ fn start_server() -> Result<(Child, String), Box<dyn std::error::Error>> {
     let mut proc = cmd
         .env("ROCKET_PORT", port_str)
         .env("ROCKET_LOG_LEVEL", "off")
+        .envs(envs.iter().copied())
         .spawn()?;
 
     let connection_string = format!("127.0.0.1:{port}");
-    for _ in 0..50 {
+    // the server runs its self test before it accepts connections:
+    for _ in 0..500 {
         if std::net::TcpStream::connect(&connection_string).is_ok() {
             return Ok((proc, format!("http://127.0.0.1:{port}")));
         }
@@ -153,7 +411,18 @@ fn with_server<F>(test: F) -> Result<(), Box<dyn std::error::Error>>
 where
     F: FnOnce(&String) -> Result<(), Box<dyn std::error::Error>>,
 {
-    let (server, connection_string) = start_server()?;
+    with_configured_server(&[], test)
+}
+
+/// Runs the test against a server configured using the specified `TANDEM_*` env vars.
+fn with_configured_server<F>(
+    envs: &[(&str, &str)],
+    test: F,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    F: FnOnce(&String) -> Result<(), Box<dyn std::error::Error>>,
+{
+    let (server, connection_string) = start_server(envs)?;
     let res = test(&connection_string);
     let stop = stop_server(server);
     stop.and(res)
@@ -0,0 +1,405 @@
+#![cfg(all(feature = "local", not(target_arch = "wasm32")))]
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use rocket::fairing::AdHoc;
+use tandem::{Circuit, Gate};
+use tandem_garble_interop::{
+    check_program, compile_program, deserialize_output, serialize_input, Role,
+};
+use tandem_http_client::{connect_local, ComputeOptions, Error, MpcData, MpcProgram};
+use tandem_http_server::{
+    build, build_with_config, MpcRequest, MpcSession, OutputHandler, ServerConfig,
+};
+
+fn handler(r: MpcRequest) -> Result<MpcSession, String> {
+    let prg = check_program(&r.program)?;
+    let circuit = compile_program(&prg, &r.function)?;
+    let input = serialize_input(
+        Role::Contributor,
+        &prg,
+        &circuit.fn_def,
+        &r.plaintext_metadata,
+    )?;
+    Ok(MpcSession {
+        circuit: circuit.gates.into_inner(),
+        input_from_server: input,
+        request_headers: HashMap::new(),
+        on_output: None,
+    })
+}
+
+#[tokio::test]
+async fn test_compute_local() -> Result<(), Box<dyn std::error::Error>> {
+    let server = connect_local(build(Box::new(handler))).await?;
+
+    let source_code = "pub fn main(a: i32, b: u16) -> i32 { a + (b as i32) }".to_string();
+    let program = MpcProgram::new(source_code, "main".to_string())?;
+    let input = MpcData::from_string(&program, "2u16".to_string())?;
+    let output = server
+        .compute("3i32".to_string(), program.clone(), input.clone())
+        .await?;
+    assert_eq!(output.to_literal_string(), "5i32");
+    let verification = output.verification().unwrap();
+    assert!(verification.verified);
+    assert!(verification.mac_checks > 0);
+    assert_eq!(input.verification(), None);
+
+    // the connection can be shared by concurrent sessions:
+    let sessions = (0..4).map(|i| {
+        let server = server.clone();
+        let program = program.clone();
+        let input = input.clone();
+        tokio::spawn(async move { server.compute(format!("{i}i32"), program, input).await })
+    });
+    for (i, session) in sessions.enumerate() {
+        let output = session.await??;
+        assert_eq!(output.to_literal_string(), format!("{}i32", i + 2));
+    }
+
+    // errors of the handler are reported like for servers reached over HTTP:
+    match server.compute("true".to_string(), program, input).await {
+        Err(Error::MpcRequestRejected { .. }) => {}
+        result => panic!("expected a rejected request, got {result:?}"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_session_steps_local() -> Result<(), Box<dyn std::error::Error>> {
+    let server = connect_local(build(Box::new(handler))).await?;
+
+    let source_code = "pub fn main(a: i32, b: u16) -> i32 { a + (b as i32) }".to_string();
+    let program = MpcProgram::new(source_code, "main".to_string())?;
+    let input = MpcData::from_string(&program, "2u16".to_string())?;
+    let mut session = server
+        .new_session("3i32".to_string(), program.clone(), input.clone())
+        .await?;
+    let steps = session.progress().steps;
+    assert_eq!(session.progress().step, 0);
+    let mut last_step = 0;
+    let output = loop {
+        if let Some(output) = session.step().await? {
+            break output;
+        }
+        let progress = session.progress();
+        assert!(progress.step >= last_step && progress.step < steps);
+        last_step = progress.step;
+    };
+    assert_eq!(output.to_literal_string(), "5i32");
+    assert_eq!(session.progress().step, steps);
+    assert!(matches!(session.step().await, Err(Error::SessionFinished)));
+    let report = session.report();
+    assert!(report.rounds > 1);
+    assert!(report.bytes_sent > 0 && report.bytes_received > 0);
+
+    // sessions can also be evaluated completely or deleted before they have finished:
+    let session = server
+        .new_session("3i32".to_string(), program.clone(), input.clone())
+        .await?;
+    let (output, completed) = session.evaluate_with_report().await?;
+    assert_eq!(output.to_literal_string(), "5i32");
+    assert_eq!(completed.rounds, report.rounds);
+    assert_eq!(completed.bytes_sent, report.bytes_sent);
+    assert!(completed.phase_timings.total() > std::time::Duration::ZERO);
+    let mut session = server
+        .new_session("3i32".to_string(), program, input)
+        .await?;
+    assert!(session.step().await?.is_none());
+    session.delete().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_compute_local_with_headers() -> Result<(), Box<dyn std::error::Error>> {
+    let auth_headers = Arc::new(Mutex::new(vec![]));
+    let recorded = Arc::clone(&auth_headers);
+    let rocket = build(Box::new(handler)).attach(AdHoc::on_request("Auth", move |req, _| {
+        let auth = req.headers().get_one("Authorization").map(String::from);
+        recorded.lock().unwrap().push(auth);
+        Box::pin(async {})
+    }));
+    let server = connect_local(rocket).await?;
+
+    let source_code = "pub fn main(a: i32, b: u16) -> i32 { a + (b as i32) }".to_string();
+    let program = MpcProgram::new(source_code, "main".to_string())?;
+    let input = MpcData::from_string(&program, "2u16".to_string())?;
+    // the server commits to the session and confirms the transcript, which the client checks:
+    let options = ComputeOptions::new()
+        .bearer_token("secret".to_string())
+        .require_session_commitment()
+        .confirm_transcript();
+    let output = server
+        .compute_with_options("3i32".to_string(), program, input, options)
+        .await?;
+    assert_eq!(output.to_literal_string(), "5i32");
+
+    // the header is sent with the creation of the session and with every dialog request:
+    let auth_headers = auth_headers.lock().unwrap();
+    assert!(auth_headers.len() > 1);
+    for auth in auth_headers.iter() {
+        assert_eq!(auth.as_deref(), Some("Bearer secret"));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_encrypted_channel_local() -> Result<(), Box<dyn std::error::Error>> {
+    let secret_key = [7; 32];
+    let public_key = tandem::channel::ChannelKeyPair::from_secret_bytes(secret_key).public_key();
+    let secret_key = secret_key
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<String>();
+    let config = rocket::Config::figment().merge(("channel_secret_key", secret_key));
+    let server = connect_local(build(Box::new(handler)).configure(config)).await?;
+
+    let source_code = "pub fn main(a: i32, b: u16) -> i32 { a + (b as i32) }".to_string();
+    let program = MpcProgram::new(source_code, "main".to_string())?;
+    let input = MpcData::from_string(&program, "2u16".to_string())?;
+    for options in [
+        ComputeOptions::new().encrypted_channel(),
+        ComputeOptions::new()
+            .server_channel_key(public_key)
+            .confirm_transcript(),
+    ] {
+        let output = server
+            .compute_with_options("3i32".to_string(), program.clone(), input.clone(), options)
+            .await?;
+        assert_eq!(output.to_literal_string(), "5i32");
+    }
+
+    // the server must authenticate itself using the pinned key:
+    let options = ComputeOptions::new().server_channel_key([1; 32]);
+    match server
+        .compute_with_options("3i32".to_string(), program, input, options)
+        .await
+    {
+        Err(Error::EncryptedChannelUnavailable) => {}
+        result => panic!("expected an unavailable channel, got {result:?}"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bounded_queues_local() -> Result<(), Box<dyn std::error::Error>> {
+    let config = rocket::Config::figment().merge(("max_queue_bytes", 1 << 16));
+    let server = connect_local(build(Box::new(handler)).configure(config)).await?;
+
+    let source_code = "pub fn main(a: i32, b: u16) -> i32 { a + (b as i32) }".to_string();
+    let program = MpcProgram::new(source_code, "main".to_string())?;
+    let input = MpcData::from_string(&program, "2u16".to_string())?;
+    let session = server
+        .new_session("3i32".to_string(), program.clone(), input.clone())
+        .await?;
+    let (_, unbounded) = session.evaluate_with_report().await?;
+    // single messages can exceed the budget, which then never holds more than one message:
+    let options = ComputeOptions::new().max_queue_bytes(1);
+    let session = server
+        .new_session_with_options("3i32".to_string(), program, input, options)
+        .await?;
+    let (output, bounded) = session.evaluate_with_report().await?;
+    assert_eq!(output.to_literal_string(), "5i32");
+    assert_eq!(bounded.rounds, unbounded.rounds);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unreliable_server_local() -> Result<(), Box<dyn std::error::Error>> {
+    // the server fails after processing some dialog requests and sends other responses twice:
+    let config = rocket::Config::figment()
+        .merge(("chaos.seed", 7))
+        .merge(("chaos.routes.dialog.error_probability", 0.2))
+        .merge(("chaos.routes.dialog.duplicate_probability", 0.5));
+    let server = connect_local(build(Box::new(handler)).configure(config)).await?;
+
+    let source_code = "pub fn main(a: i32, b: u16) -> i32 { a + (b as i32) }".to_string();
+    let program = MpcProgram::new(source_code, "main".to_string())?;
+    let input = MpcData::from_string(&program, "2u16".to_string())?;
+    let output = server.compute("3i32".to_string(), program, input).await?;
+    assert_eq!(output.to_literal_string(), "5i32");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_output_disclosure_local() -> Result<(), Box<dyn std::error::Error>> {
+    let outputs = Arc::new(Mutex::new(vec![]));
+    let disclosed = Arc::clone(&outputs);
+    let handler = move |r: MpcRequest| {
+        let mut session = handler(r)?;
+        let disclosed = Arc::clone(&disclosed);
+        session.on_output = Some(OutputHandler::new(move |output| {
+            disclosed.lock().unwrap().push(output);
+        }));
+        Ok(session)
+    };
+    let server = connect_local(build(Box::new(handler))).await?;
+
+    let source_code = "pub fn main(a: i32, b: u16) -> i32 { a + (b as i32) }".to_string();
+    let program = MpcProgram::new(source_code.clone(), "main".to_string())?;
+    let input = MpcData::from_string(&program, "2u16".to_string())?;
+    for options in [
+        ComputeOptions::new().disclose_output(),
+        ComputeOptions::new().disclose_output().confirm_transcript(),
+    ] {
+        let output = server
+            .compute_with_options("3i32".to_string(), program.clone(), input.clone(), options)
+            .await?;
+        assert_eq!(output.to_literal_string(), "5i32");
+    }
+    // the server learns the verified output:
+    let prg = check_program(&source_code)?;
+    let circuit = compile_program(&prg, "main")?;
+    let outputs = outputs.lock().unwrap().clone();
+    assert_eq!(outputs.len(), 2);
+    for output in outputs {
+        assert_eq!(output.function, "main");
+        assert_eq!(output.plaintext_metadata, "3i32");
+        let literal = deserialize_output(&prg, &circuit.fn_def, &output.output)?;
+        assert_eq!(literal.to_string(), "5i32");
+    }
+
+    // the handler requires the output, so clients that do not disclose it are rejected:
+    match server.compute("3i32".to_string(), program, input).await {
+        Err(Error::ServerError(e)) => assert!(e.contains("OutputDisclosureRequired"), "{e}"),
+        result => panic!("expected a rejected session, got {result:?}"),
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_follow_up_rounds_local() -> Result<(), Box<dyn std::error::Error>> {
+    let server = connect_local(build(Box::new(handler))).await?;
+
+    let source_code = "pub fn add(a: i32, b: u16) -> i32 { a + (b as i32) }
+pub fn sub(a: i32, b: u16) -> i32 { a - (b as i32) }"
+        .to_string();
+    let add = MpcProgram::new(source_code.clone(), "add".to_string())?;
+    let sub = MpcProgram::new(source_code, "sub".to_string())?;
+    let options = ComputeOptions::new()
+        .reserve_follow_up(&sub)
+        .confirm_transcript();
+    let input = MpcData::from_string(&add, "2u16".to_string())?;
+    let mut session = server
+        .new_session_with_options("3i32".to_string(), add, input, options)
+        .await?;
+    assert_eq!(session.evaluate_round().await?.to_literal_string(), "5i32");
+
+    // the follow-up round runs on the same session, without another preprocessing:
+    let input = MpcData::from_string(&sub, "4u16".to_string())?;
+    let metadata = serde_json::Value::String("10i32".to_string());
+    session.next(sub.clone(), input.clone(), metadata).await?;
+    assert_eq!(session.progress().steps, 3);
+    assert_eq!(session.evaluate_round().await?.to_literal_string(), "6i32");
+
+    // the reserved preprocessing is used up:
+    let metadata = serde_json::Value::String("10i32".to_string());
+    match session.next(sub, input, metadata).await {
+        Err(Error::FollowUpUnavailable) => {}
+        result => panic!("expected no follow-up round, got {result:?}"),
+    }
+    session.delete().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stored_programs_local() -> Result<(), Box<dyn std::error::Error>> {
+    let source_code = "pub fn main(a: i32, b: u16) -> i32 { a + (b as i32) }";
+    let program = MpcProgram::new(source_code.to_string(), "main".to_string())?;
+    let input = MpcData::from_string(&program, "2u16".to_string())?;
+    let options = ComputeOptions::new().reference_stored_programs();
+
+    let server = connect_local(build(Box::new(handler))).await?;
+    for _ in 0..2 {
+        let output = server
+            .compute_with_options(
+                "3i32".into(),
+                program.clone(),
+                input.clone(),
+                options.clone(),
+            )
+            .await?;
+        assert_eq!(output.to_literal_string(), "5i32");
+    }
+
+    // a server that stores a different program under the hash shows that only the hash was sent:
+    let circuit = compile_program(&check_program(source_code)?, "main")?;
+    let other_program = "pub fn main(a: i32, b: u16) -> i32 { a - (b as i32) }".to_string();
+    let config = ServerConfig::default().with_program(circuit.gates.blake3_hash(), other_program);
+    let server = connect_local(build_with_config(Box::new(handler), config)).await?;
+    let computation = server
+        .compute_with_options(
+            "3i32".into(),
+            program.clone(),
+            input.clone(),
+            options.clone(),
+        )
+        .await;
+    assert!(matches!(computation, Err(Error::CircuitHashMismatch(_))));
+
+    // a restarted server no longer stores the program, which is then sent again:
+    let server = connect_local(build(Box::new(handler))).await?;
+    let output = server
+        .compute_with_options("3i32".into(), program, input, options)
+        .await?;
+    assert_eq!(output.to_literal_string(), "5i32");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_compute_circuit_local() -> Result<(), Box<dyn std::error::Error>> {
+    // the contributor's input bits are sent as metadata, like the literals of the echo server:
+    let raw_circuit_handler = |r: MpcRequest| -> Result<MpcSession, String> {
+        let circuit = r.circuit.ok_or("expected a raw circuit")?;
+        let input = r.plaintext_metadata.chars().map(|b| b == '1').collect();
+        Ok(MpcSession {
+            circuit,
+            input_from_server: input,
+            request_headers: HashMap::new(),
+            on_output: None,
+        })
+    };
+    let server = connect_local(build(Box::new(raw_circuit_handler))).await?;
+
+    // (x0 & y0, x1 ^ y1, !x0)
+    let gates = vec![
+        Gate::InContrib,
+        Gate::InContrib,
+        Gate::InEval,
+        Gate::InEval,
+        Gate::And(0, 2),
+        Gate::Xor(1, 3),
+        Gate::Not(0),
+    ];
+    let circuit = Circuit::new(gates, vec![4, 5, 6]);
+    let output = server
+        .compute_circuit("10".to_string(), circuit.clone(), vec![true, true])
+        .await?;
+    assert_eq!(output, vec![true, true, false]);
+
+    match server
+        .compute_circuit("10".to_string(), circuit, vec![true])
+        .await
+    {
+        Err(Error::ValidationError(_)) => {}
+        result => panic!("expected an invalid input, got {result:?}"),
+    }
+
+    // handlers that compile programs reject raw circuits:
+    let server = connect_local(build(Box::new(handler))).await?;
+    let circuit = Circuit::new(
+        vec![Gate::InContrib, Gate::InEval, Gate::And(0, 1)],
+        vec![2],
+    );
+    match server
+        .compute_circuit("1".to_string(), circuit, vec![true])
+        .await
+    {
+        Err(Error::MpcRequestRejected { .. }) => {}
+        result => panic!("expected a rejected request, got {result:?}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,29 @@
+#![cfg(target_arch = "wasm32")]
+
+use std::time::Duration;
+
+use tandem_http_client::{ComputeOptions, MpcData, MpcProgram, TandemClient};
+use wasm_bindgen_test::wasm_bindgen_test;
+
+// without `run_in_browser`, the tests are run in Node.js, which provides `fetch` since Node 18
+
+#[wasm_bindgen_test]
+async fn test_node() {
+    let url = "http://127.0.0.1:8000";
+    let source_code = "pub fn main(a: i32, b: u16) -> i32 { a + (b as i32) }".to_string();
+    let function = "main".to_string();
+    let program = MpcProgram::new(source_code, function).expect("Could not parse source code");
+    let options = ComputeOptions::new().timeout(Duration::from_secs(60));
+    let client = TandemClient::new(url, options).expect("Could not create client");
+    for (remote_input, expected) in [("2i32", "4i32"), ("-3i32", "-1i32")] {
+        let my_input =
+            MpcData::from_string(&program, "2u16".to_string()).expect("Could not parse input");
+        let output = client
+            .compute(program.clone(), my_input, remote_input.to_string())
+            .await;
+        match output {
+            Ok(output) => assert_eq!(output.to_literal_string(), expected),
+            Err(e) => panic!("{e:?}"),
+        }
+    }
+}
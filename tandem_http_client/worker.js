@@ -0,0 +1,65 @@
+// Web Worker running a single Tandem computation, spawned by `computeInWorker` (see
+// `compute_in_worker.js`). The worker must be started as a module worker:
+//
+//   new Worker(new URL("./worker.js", import.meta.url), { type: "module" })
+//
+// Messages received from the main thread:
+//
+// - `{type: "compute", source, function, input, url, metadata, headers, timeout}` compiles the
+//   function of the Garble program and computes it with the input, which is either a Garble
+//   literal string or an `ArrayBuffer` returned by `MpcData.toBytes()` (which can be transferred).
+// - `{type: "abort"}` aborts the computation before its next request to the server.
+//
+// Messages posted back to the main thread:
+//
+// - `{type: "progress", step, steps}` whenever the computation has progressed.
+// - `{type: "result", literal, output, bytes}` with the output as a Garble literal string, as a
+//   plain value and as the transferred `ArrayBuffer` of `MpcData.toBytes()`.
+// - `{type: "error", message}` if the computation failed.
+import init, {
+  MpcProgram,
+  MpcData,
+  computeDataWithOptions,
+} from "./pkg/tandem_http_client.js";
+
+const ready = init();
+const controller = new AbortController();
+
+self.onmessage = async ({ data }) => {
+  if (data.type === "abort") {
+    controller.abort();
+    return;
+  }
+  if (data.type !== "compute") {
+    return;
+  }
+  try {
+    await ready;
+    const program = new MpcProgram(data.source, data.function);
+    const input =
+      data.input instanceof ArrayBuffer
+        ? MpcData.fromBytes(program, new Uint8Array(data.input))
+        : MpcData.from_string(program, data.input);
+    const output = await computeDataWithOptions(program, input, {
+      url: data.url,
+      metadata: data.metadata,
+      headers: data.headers,
+      timeout: data.timeout,
+      onProgress: ({ step, steps }) =>
+        self.postMessage({ type: "progress", step, steps }),
+      signal: controller.signal,
+    });
+    const bytes = output.toBytes().buffer;
+    self.postMessage(
+      {
+        type: "result",
+        literal: output.to_literal_string(),
+        output: output.toPlain(),
+        bytes,
+      },
+      [bytes],
+    );
+  } catch (e) {
+    self.postMessage({ type: "error", message: String(e) });
+  }
+};
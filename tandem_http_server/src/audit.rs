@@ -0,0 +1,128 @@
+//! Tamper-evident records of who ran which function when, for compliance.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    net::IpAddr,
+    path::Path,
+    sync::Mutex,
+};
+
+use rocket::serde::{Deserialize, Serialize};
+
+/// Records the lifecycle of sessions, see [`crate::ServerConfig::with_audit_sink`].
+pub trait AuditSink: Send + Sync {
+    /// Records a single event.
+    ///
+    /// Events are recorded while handling the requests of clients, so this should not block for
+    /// long.
+    fn record(&self, event: &AuditEvent);
+}
+
+/// What happened to a session, see [`AuditEvent`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub enum AuditOutcome {
+    /// The session was created and the protocol started.
+    Created,
+    /// The server completed the protocol, the client can decrypt the output.
+    Completed,
+    /// The session could not be created, was aborted by either party or was dropped before the
+    /// protocol was completed.
+    Failed,
+}
+
+/// An event in the lifecycle of a session, which never contains any inputs or outputs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct AuditEvent {
+    /// What happened to the session.
+    pub outcome: AuditOutcome,
+    /// Milliseconds since the Unix epoch at which the event occurred.
+    pub timestamp_millis: u64,
+    /// Milliseconds since the client requested the session.
+    pub duration_millis: u64,
+    /// The engine id of the session, `None` if it failed before an id was assigned.
+    pub engine_id: Option<String>,
+    /// The external id of the session, if assigned by the [`crate::IdGenerator`].
+    pub external_id: Option<String>,
+    /// The hex-encoded BLAKE3 hash of the program's source code.
+    pub program_hash: String,
+    /// The name of the function executed using MPC.
+    pub function: String,
+    /// The plaintext metadata supplied by the client, see [`crate::MpcRequest`].
+    pub plaintext_metadata: String,
+    /// The IP address of the client, if known.
+    pub client: Option<IpAddr>,
+    /// Why the session failed, always `None` unless the outcome is [`AuditOutcome::Failed`].
+    pub error: Option<String>,
+    /// Whether the client confirmed that it exchanged the same messages as the server, `None`
+    /// unless the `transcript_confirmation` capability was negotiated and the client responded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transcript_confirmed: Option<bool>,
+}
+
+/// An [`AuditSink`] appending every event as a line of JSON to a file, used for the `audit_log`
+/// of the Rocket config.
+///
+/// Every line contains the hex-encoded BLAKE3 hash of the previous line (without its newline) as
+/// `prev_hash`, `null` for the first line of the file. Modifying or removing any line thus breaks
+/// the chain of hashes, unless all following lines are rewritten as well.
+pub struct JsonLinesAuditSink {
+    file: Mutex<(File, Option<String>)>,
+}
+
+impl JsonLinesAuditSink {
+    /// Opens the file for appending (creating it if necessary), continuing the chain of hashes of
+    /// the existing lines.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut prev_hash = None;
+        match File::open(path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    if !line.is_empty() {
+                        prev_hash = Some(blake3::hash(line.as_bytes()).to_hex().to_string());
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new((file, prev_hash)),
+        })
+    }
+}
+
+impl AuditSink for JsonLinesAuditSink {
+    fn record(&self, event: &AuditEvent) {
+        #[derive(Serialize)]
+        #[serde(crate = "rocket::serde")]
+        struct Line<'a> {
+            #[serde(flatten)]
+            event: &'a AuditEvent,
+            prev_hash: Option<&'a str>,
+        }
+
+        let mut file = self.file.lock().unwrap();
+        let (file, prev_hash) = &mut *file;
+        let line = Line {
+            event,
+            prev_hash: prev_hash.as_deref(),
+        };
+        let line = match serde_json::to_string(&line) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Could not serialize audit event: {e}");
+                return;
+            }
+        };
+        match file.write_all(format!("{line}\n").as_bytes()) {
+            Ok(()) => *prev_hash = Some(blake3::hash(line.as_bytes()).to_hex().to_string()),
+            Err(e) => error!("Could not write audit event: {e}"),
+        }
+    }
+}
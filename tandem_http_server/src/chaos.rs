@@ -0,0 +1,206 @@
+//! Failure injection for resilience testing of clients, only available with the `chaos` feature.
+//!
+//! The faults are configured per route as part of the Rocket config, using the name of the route's
+//! handler (such as `create_session`, `dialog` or `download_final`):
+//!
+//! ```toml
+//! [global.chaos]
+//! seed = 42
+//!
+//! [global.chaos.routes.dialog]
+//! delay_probability = 0.2
+//! delay_millis = 500
+//! drop_probability = 0.05
+//! error_probability = 0.05
+//! duplicate_probability = 0.1
+//! ```
+//!
+//! All faults are injected after the request has been handled by the server, so that the state of
+//! the server has changed even if the client never learns about the response.
+
+use std::{
+    collections::HashMap,
+    io,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use rand::Rng;
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use rocket::{
+    fairing::{self, Fairing, Info, Kind},
+    http::{ContentType, Status},
+    serde::Deserialize,
+    tokio::{
+        io::{AsyncRead, ReadBuf},
+        time::sleep,
+    },
+    Build, Request, Response, Rocket,
+};
+
+use crate::{frames, responses::Error};
+
+/// Faults injected into the responses of a route, each with an independent probability.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct ChaosRule {
+    /// Probability that the response is delayed by `delay_millis`.
+    pub delay_probability: f64,
+    /// Milliseconds by which delayed responses are delayed.
+    pub delay_millis: u64,
+    /// Probability that the connection is aborted instead of sending the response body.
+    pub drop_probability: f64,
+    /// Probability that the response is replaced by a `503 Service Unavailable` error.
+    pub error_probability: f64,
+    /// Probability that the messages of a successful `dialog` response are sent twice.
+    pub duplicate_probability: f64,
+}
+
+impl ChaosRule {
+    fn validate(&self, route: &str) -> Result<(), String> {
+        let probabilities = [
+            ("delay_probability", self.delay_probability),
+            ("drop_probability", self.drop_probability),
+            ("error_probability", self.error_probability),
+            ("duplicate_probability", self.duplicate_probability),
+        ];
+        for (name, p) in probabilities {
+            if !(0.0..=1.0).contains(&p) {
+                return Err(format!(
+                    "chaos.routes.{route}.{name} must be between 0 and 1"
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The faults of all routes, configured as the `chaos` key of the Rocket config.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct ChaosConfig {
+    /// Seed of the random decisions, to reproduce failures. Chosen randomly if not set.
+    pub seed: Option<u64>,
+    /// Faults by the name of the route's handler.
+    pub routes: HashMap<String, ChaosRule>,
+}
+
+struct ChaosState {
+    routes: HashMap<String, ChaosRule>,
+    rng: Mutex<ChaCha20Rng>,
+}
+
+impl ChaosState {
+    fn roll(&self, probability: f64) -> bool {
+        probability > 0.0 && self.rng.lock().unwrap().gen_bool(probability)
+    }
+}
+
+/// Injects the faults of the `chaos` config into the responses of the server.
+pub(crate) struct Chaos;
+
+#[rocket::async_trait]
+impl Fairing for Chaos {
+    fn info(&self) -> Info {
+        Info {
+            name: "Inject failures into responses",
+            kind: Kind::Ignite | Kind::Response,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        let config = match rocket.figment().extract_inner::<ChaosConfig>("chaos") {
+            Ok(config) => config,
+            Err(e) if e.missing() => return Ok(rocket),
+            Err(e) => {
+                error!("Invalid chaos config: {e}");
+                return Err(rocket);
+            }
+        };
+        for (route, rule) in config.routes.iter() {
+            if let Err(e) = rule.validate(route) {
+                error!("Invalid chaos config: {e}");
+                return Err(rocket);
+            }
+        }
+        warn!(
+            "Injecting failures into the responses of {:?}",
+            config.routes.keys()
+        );
+        let rng = match config.seed {
+            Some(seed) => ChaCha20Rng::seed_from_u64(seed),
+            None => ChaCha20Rng::from_entropy(),
+        };
+        Ok(rocket.manage(ChaosState {
+            routes: config.routes,
+            rng: Mutex::new(rng),
+        }))
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let state = match request.rocket().state::<ChaosState>() {
+            Some(state) => state,
+            None => return,
+        };
+        let route = match request.route().and_then(|route| route.name.as_deref()) {
+            Some(route) => route,
+            None => return,
+        };
+        let rule = match state.routes.get(route) {
+            Some(rule) => rule,
+            None => return,
+        };
+        if state.roll(rule.delay_probability) {
+            info!("Chaos: delaying the response of {route}");
+            sleep(Duration::from_millis(rule.delay_millis)).await;
+        }
+        if state.roll(rule.error_probability) {
+            info!("Chaos: replacing the response of {route} with an error");
+            let error = Error::Internal {
+                message: "Injected failure".to_string(),
+            };
+            let body = serde_json::to_string(&error).unwrap_or_default();
+            response.set_status(Status::ServiceUnavailable);
+            response.set_header(ContentType::JSON);
+            response.set_sized_body(body.len(), io::Cursor::new(body));
+        } else if state.roll(rule.drop_probability) {
+            info!("Chaos: dropping the response of {route}");
+            response.set_streamed_body(Dropped);
+        } else if route == "dialog"
+            && response.status() == Status::Ok
+            && state.roll(rule.duplicate_probability)
+        {
+            info!("Chaos: duplicating the messages of {route}");
+            let body = response.body_mut().to_bytes().await.unwrap_or_default();
+            let body = duplicate_messages(&body).unwrap_or(body);
+            response.set_sized_body(body.len(), io::Cursor::new(body));
+        }
+    }
+}
+
+/// Sends every message of a serialized dialog response twice, in the same order.
+fn duplicate_messages(body: &[u8]) -> Option<Vec<u8>> {
+    let (mut msgs, offset) = frames::decode(body)?;
+    msgs.extend(msgs.clone());
+    let msgs: Vec<_> = msgs.iter().map(|(msg, id)| (msg, *id)).collect();
+    Some(frames::encode(&msgs, offset).ok()?.concat())
+}
+
+/// A response body that fails immediately, which aborts the connection to the client.
+struct Dropped;
+
+impl AsyncRead for Dropped {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let e = io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "response dropped by chaos",
+        );
+        Poll::Ready(Err(e))
+    }
+}
@@ -1,89 +1,371 @@
 #![allow(clippy::let_unit_value)]
 
 use crate::{
-    msg_queue::MessageId,
-    requests::NewSession,
-    responses::Error,
-    state::{EngineRef, EngineRegistry},
-    types::{EngineCreationResult, HandleMpcRequestFn},
+    audit::{AuditOutcome, JsonLinesAuditSink},
+    frames,
+    msg_queue::{MessageId, MessageLog},
+    requests::{BearerToken, ByteRange, NewApproval, NewSession, NextRound, TraceId},
+    responses::{Download, Error, ServerTiming, Timed},
+    shared::{SharedRegistryPolicy, SharedRequest, SharedSessions},
+    state::{
+        ApprovalPolicy, BandwidthPolicy, ChannelPolicy, DialogEngine, EngineRef, EngineRegistry,
+        ExecutionPolicy, FailurePolicy, FollowUps, Idempotency, IdempotencyPolicy,
+        MismatchDiagnostics, ProgramAllowlist, ProgramStorePolicy, ResourceQuotas, SessionInfo,
+        TimingDiagnostics,
+    },
+    store::StorePolicy,
+    types::{
+        Approval, Capabilities, EngineCreationResult, EngineId, ExternalId, HandleMpcRequestFn,
+        Health, Metrics, MpcRequest, NextRoundResult, PublishedFunction, Readiness,
+        SessionCommitment,
+    },
+    ServerConfig, SessionStore, CAPABILITIES, MIN_WIRE_VERSION, WIRE_VERSION,
 };
 use rand::Rng;
 use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
 use rocket::{
-    data::ToByteUnit,
+    data::{Capped, Limits, ToByteUnit},
     fairing::{AdHoc, Fairing, Info, Kind},
-    http::Header,
+    http::{Header, Status},
     response::{status::Created, stream::ByteStream},
     serde::{json::Json, Deserialize},
+    tokio::{task::spawn_blocking, time::sleep_until},
     Data, Request, Response, State,
 };
 use std::{
     collections::HashSet,
+    net::IpAddr,
+    path::PathBuf,
     sync::{Arc, Mutex},
+    time::Instant,
 };
+use tandem::{channel::Handshake, preprocessing_circuit, states::Msg};
 use url::{Host, Url};
 
 #[options("/")]
 pub(crate) fn preflight_response_create_session() {}
 
+#[get("/healthz?<self_test>")]
+pub(crate) fn healthz(self_test: Option<bool>) -> (Status, Json<Health>) {
+    let report = self_test.unwrap_or_default().then(tandem::self_test);
+    let (status, health) = match &report {
+        Some(report) if !report.passed() => (Status::ServiceUnavailable, "self test failed"),
+        _ => (Status::Ok, "ok"),
+    };
+    let health = Health {
+        status: health.to_string(),
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol_version: tandem::PROTOCOL_VERSION,
+        wire_version: WIRE_VERSION,
+        self_test: report,
+    };
+    (status, Json(health))
+}
+
+#[get("/readyz")]
+pub(crate) fn readyz(r: &State<Arc<EngineRegistry>>) -> (Status, Json<Readiness>) {
+    let readiness = r.readiness();
+    let status = if readiness.ready {
+        Status::Ok
+    } else {
+        Status::ServiceUnavailable
+    };
+    (status, Json(readiness))
+}
+
+#[get("/capabilities")]
+pub(crate) fn capabilities(r: &State<Arc<EngineRegistry>>) -> Json<Capabilities> {
+    Json(Capabilities {
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        wire_version: WIRE_VERSION,
+        min_wire_version: MIN_WIRE_VERSION,
+        features: CAPABILITIES
+            .iter()
+            .filter(|c| r.supports(c))
+            .map(|c| c.to_string())
+            .collect(),
+        limits: r.limits(),
+    })
+}
+
+#[get("/programs")]
+pub(crate) fn programs(r: &State<Arc<EngineRegistry>>) -> Option<Json<Vec<PublishedFunction>>> {
+    r.catalog().map(Json)
+}
+
+#[get("/metrics")]
+pub(crate) fn metrics(r: &State<Arc<EngineRegistry>>) -> Json<Metrics> {
+    Json(r.metrics())
+}
+
 #[post("/", format = "application/json", data = "<request>")]
-pub(crate) fn create_session(
-    r: &State<EngineRegistry>,
+pub(crate) async fn create_session(
+    r: &State<Arc<EngineRegistry>>,
     request: Json<NewSession>,
+    client: Option<IpAddr>,
+    trace_id: Option<TraceId>,
 ) -> Result<Created<Json<EngineCreationResult>>, Error> {
-    let server_version = env!("CARGO_PKG_VERSION").to_string();
-    if request.client_version != server_version {
-        return Err(Error::IncompatibleVersions {
-            client_version: request.client_version.clone(),
-            server_version,
-        });
+    // the handler might compile the program, which must not stall Rocket's async workers:
+    let registry = Arc::clone(r);
+    let created = spawn_blocking(move || new_session(&registry, &request, client))
+        .await
+        .map_err(|e| {
+            error!("The handler failed while creating a session: {e}");
+            Error::Internal {
+                message: "the handler failed".to_string(),
+            }
+        })??;
+    if let Some(TraceId(trace_id)) = trace_id {
+        info!(
+            "Session {} created with trace id {trace_id}",
+            created.engine_id
+        );
     }
-    let invocation = crate::types::MpcRequest {
-        plaintext_metadata: request.plaintext_metadata.clone(),
+    let location = uri!(dialog(&created.engine_id)).to_string();
+    Ok(Created::new(location).body(Json(created)))
+}
+
+/// Creates a new session and records an audit event, regardless of the transport of the request.
+pub(crate) fn new_session(
+    r: &EngineRegistry,
+    request: &NewSession,
+    client: Option<IpAddr>,
+) -> Result<EngineCreationResult, Error> {
+    // the program might only be referenced by its circuit hash:
+    let request = &*r.resolve_program(request)?;
+    // a retried request must not create (and audit) another session, even while the first
+    // request is still creating it:
+    let reservation = match r.idempotent_session(request)? {
+        Idempotency::Created(created) => return Ok(*created),
+        Idempotency::Reserved(reservation) => reservation,
+    };
+    let mut session = SessionInfo::new(request, client);
+    let created = create(r, request, client, &mut session);
+    match &created {
+        Ok(created) => {
+            reservation.complete(created);
+            r.audit(&session, AuditOutcome::Created, None)
+        }
+        Err(e) => {
+            let error = serde_json::to_string(e).unwrap_or_default();
+            r.audit(&session, AuditOutcome::Failed, Some(error));
+        }
+    }
+    created
+}
+
+fn create(
+    r: &EngineRegistry,
+    request: &NewSession,
+    client: Option<IpAddr>,
+    session: &mut SessionInfo,
+) -> Result<EngineCreationResult, Error> {
+    r.check_client(client)?;
+    let server_version = env!("CARGO_PKG_VERSION").to_string();
+    let wire_version = match (request.protocol_version, request.supported_wire_versions()) {
+        (Some(client_protocol_version), Some(client_wire_versions)) => {
+            let wire_version = client_wire_versions
+                .iter()
+                .copied()
+                .filter(|v| (MIN_WIRE_VERSION..=WIRE_VERSION).contains(v))
+                .max();
+            match wire_version {
+                Some(wire_version) if client_protocol_version == tandem::PROTOCOL_VERSION => {
+                    wire_version
+                }
+                _ => {
+                    return Err(Error::IncompatibleProtocolVersions {
+                        client_protocol_version,
+                        client_wire_version: client_wire_versions.into_iter().max().unwrap_or(0),
+                        server_protocol_version: tandem::PROTOCOL_VERSION,
+                        server_wire_version: WIRE_VERSION,
+                        server_min_wire_version: MIN_WIRE_VERSION,
+                    });
+                }
+            }
+        }
+        // clients that do not send their protocol versions need to match the crate version and
+        // predate the negotiation of wire versions:
+        _ => {
+            if request.client_version != server_version {
+                return Err(Error::IncompatibleVersions {
+                    client_version: request.client_version.clone(),
+                    server_version,
+                });
+            }
+            MIN_WIRE_VERSION
+        }
+    };
+    // capabilities are only negotiated since wire version 2, an encrypted channel also needs the
+    // ephemeral key of the client, follow-up rounds need their reserved size:
+    let capabilities: Vec<String> = CAPABILITIES
+        .iter()
+        .filter(|c| wire_version >= 2 && request.capabilities.iter().any(|r| r == *c))
+        .filter(|c| r.supports(c))
+        .filter(|c| **c != "encrypted_channel" || request.channel_key.is_some())
+        .filter(|c| **c != "multi_round" || request.follow_ups.is_some())
+        // raw circuits have no program to run follow-up rounds of or to store:
+        .filter(|c| request.circuit.is_none() || !matches!(**c, "multi_round" | "program_store"))
+        .map(|c| c.to_string())
+        .collect();
+    let program_hash = request.program_hash();
+    r.check_program(&program_hash)?;
+    let invocation = MpcRequest {
+        plaintext_metadata: request.plaintext_metadata_string(),
+        plaintext_metadata_json: request.plaintext_metadata.clone(),
         program: request.program.clone(),
         function: request.function.clone(),
+        circuit: request.raw_circuit()?,
     };
+    // the output handler of a shared session cannot be stored, so other instances ask the handler
+    // for it again once the client discloses its output:
+    let shared_request = r.is_shared().then(|| SharedRequest {
+        program: invocation.program.clone(),
+        plaintext_metadata_json: invocation.plaintext_metadata_json.to_string(),
+        raw_circuit: invocation.circuit.is_some(),
+    });
+    let (engine_id, external_id) = r.new_ids(&invocation)?;
+    session.engine_id = Some(engine_id.clone());
+    session.external_id = external_id.clone();
     let handled = r
         .handle_input(invocation)
         .map_err(Error::MpcRequestRejected)?;
     let circuit_hash = handled.circuit.blake3_hash();
     if circuit_hash != request.circuit_hash {
-        return Err(Error::CircuitHashMismatch);
+        return Err(r.circuit_mismatch(&handled.circuit));
+    }
+    r.check_program_circuit(&program_hash, &handled.circuit)?;
+    if capabilities.iter().any(|c| c == "program_store") {
+        r.store_program(circuit_hash, &request.program);
+    }
+    // the quotas apply to the triples of all rounds, which are preprocessed upfront:
+    let follow_ups = match request.follow_ups {
+        Some(f) if capabilities.iter().any(|c| c == "multi_round") => {
+            let c = &handled.circuit;
+            let and_gates = c.and_gates().saturating_add(f.and_gates);
+            let input_bits = (c.contrib_inputs() + c.eval_inputs()).saturating_add(f.input_bits);
+            Some(FollowUps {
+                program: request.program.clone(),
+                capacity: preprocessing_circuit(and_gates, input_bits),
+            })
+        }
+        _ => None,
+    };
+    match &follow_ups {
+        Some(follow_ups) => r.check_quotas(&follow_ups.capacity)?,
+        None => r.check_quotas(&handled.circuit)?,
+    }
+    // the client only discloses its output to handlers that process it (or that might process the
+    // output of a follow-up round), if it agrees to:
+    let mut capabilities = capabilities;
+    let disclosure = capabilities.iter().any(|c| c == "output_disclosure");
+    match (&handled.on_output, disclosure) {
+        (Some(_), false) => return Err(Error::OutputDisclosureRequired),
+        (None, true) if follow_ups.is_none() => capabilities.retain(|c| c != "output_disclosure"),
+        _ => {}
     }
 
-    let mut rng = ChaCha20Rng::from_entropy();
-    let engine_id = uuid::Builder::from_random_bytes(rng.gen()).into_uuid();
-    let engine_id = engine_id.to_string();
-    let er = Arc::new(Mutex::new(EngineRef::new(
-        rng,
+    let (channel, channel_reply) = match &request.channel_key {
+        Some(key) if capabilities.iter().any(|c| c == "encrypted_channel") => {
+            let mut rng = ChaCha20Rng::from_entropy();
+            let (channel, reply) = Handshake::respond(&mut rng, key, r.channel_key())
+                .map_err(|_| Error::UnexpectedWireFormat("invalid channel key".to_string()))?;
+            (Some(channel), Some(reply))
+        }
+        _ => (None, None),
+    };
+    // only the ends of the channel can compute the commitment to the session:
+    let commitment_key = channel.as_ref().map(|channel| *channel.binding_key());
+    // shared engines are restored from their circuit and input by other instances:
+    let shared = r
+        .is_shared()
+        .then(|| (handled.circuit.clone(), handled.input_from_server.clone()));
+    let processes_output = handled.on_output.is_some();
+    let mut engine = EngineRef::new(
+        ChaCha20Rng::from_entropy(),
         handled.circuit,
         handled.input_from_server,
-    )?));
-    let inserted = r.insert_engine(engine_id.clone(), er);
-
-    if !inserted {
-        return Err(Error::DuplicateEngineId { engine_id });
+        request.stage_final,
+        r.session_bandwidth(),
+        r.max_queue_bytes(),
+        &capabilities,
+        channel,
+        handled.on_output,
+        follow_ups,
+        session.clone(),
+    )?;
+    if let Some((circuit, input)) = shared {
+        let request = shared_request.filter(|_| processes_output);
+        engine.share(&circuit, input, request)?;
     }
+    // the messages are still resent as part of the dialog until the client acknowledges them:
+    let messages = engine
+        .dump_messages()
+        .into_iter()
+        .map(|(msg, id)| (msg.clone(), id))
+        .collect();
+    let session_commitment = match &request.session_nonce {
+        Some(nonce) => Some(SessionCommitment::new(
+            request,
+            nonce,
+            &engine_id,
+            wire_version,
+            &capabilities,
+            commitment_key.as_ref(),
+        )?),
+        None => None,
+    };
+    r.take_approval(request)?;
+    let engine = Arc::new(Mutex::new(engine));
+    r.insert_engine(engine_id.clone(), external_id.clone(), Arc::clone(&engine))?;
+    r.save_engine(&engine_id, &mut engine.lock().unwrap())?;
 
-    let body = EngineCreationResult {
+    Ok(EngineCreationResult {
         engine_id: engine_id.clone(),
+        external_id,
         request_headers: handled.request_headers,
         server_version,
-    };
-
-    // Otherwise clippy complains that the uri! macro is using an unnecessary redefinition of engine_id.
-    #[allow(clippy::redundant_locals)]
-    let c = Created::new(uri!(dialog(engine_id)).to_string()).body(Json(body));
-    Ok(c)
+        protocol_version: tandem::PROTOCOL_VERSION,
+        wire_version,
+        capabilities,
+        final_url: request
+            .stage_final
+            .then(|| uri!(download_final(&engine_id)).to_string()),
+        messages,
+        session_commitment,
+        channel: channel_reply,
+    })
 }
 
 #[options("/<_engine_id>")]
 pub(crate) fn preflight_response_delete_session(_engine_id: String) {}
 
 #[delete("/<engine_id>")]
-pub(crate) fn delete_session(engine_id: String, r: &State<EngineRegistry>) -> Result<(), Error> {
+pub(crate) fn delete_session(
+    engine_id: String,
+    r: &State<Arc<EngineRegistry>>,
+) -> Result<(), Error> {
+    delete(r, engine_id)
+}
+
+/// Drops the engine of a session deleted by the client, regardless of the transport of the
+/// request.
+pub(crate) fn delete(r: &EngineRegistry, engine_id: String) -> Result<(), Error> {
+    let engine = r.lookup(&engine_id).ok();
     let removed = r.drop_engine(&engine_id);
     if removed {
+        r.remove_log(&engine_id);
+        if let Some(engine) = engine {
+            let engine = engine.lock().unwrap();
+            r.remove_shared(&engine_id, engine.session().external_id.as_deref());
+            // sessions with follow-up rounds are only dropped once the client deletes them:
+            if engine.is_round_done() {
+                r.audit(engine.session(), AuditOutcome::Completed, None);
+            } else {
+                let error = "deleted by the client".to_string();
+                r.audit(engine.session(), AuditOutcome::Failed, Some(error));
+            }
+        }
         Ok(())
     } else {
         Err(Error::NoSuchEngineId { engine_id })
@@ -91,42 +373,596 @@ pub(crate) fn delete_session(engine_id: String, r: &State<EngineRegistry>) -> Re
 }
 
 #[post("/<engine_id>", data = "<messages>")]
-pub(crate) async fn dialog<'a>(
+pub(crate) async fn dialog(
     engine_id: String,
     messages: Data<'_>,
-    registry: &State<EngineRegistry>,
-) -> Result<ByteStream![Vec<u8>], Error> {
-    let stream = messages.open(20.mebibytes());
+    registry: &State<Arc<EngineRegistry>>,
+    limits: &Limits,
+    client: Option<IpAddr>,
+) -> Result<Timed<ByteStream![Vec<u8>]>, Error> {
+    let started = Instant::now();
+    registry.check_client(client)?;
+    // shared engines and message logs are loaded from the session store, which must not stall the
+    // async workers. The engine is locked for a whole circuit step by its blocking tasks, so its
+    // request size limit is read on the blocking thread as well:
+    let (engine, mut max_request_size) = {
+        let (registry, engine_id) = (Arc::clone(registry), engine_id.clone());
+        spawn_blocking(move || {
+            let engine = registry.lookup_dialog(&engine_id)?;
+            let max_request_size = match &engine {
+                DialogEngine::Engine(engine) => engine.lock().unwrap().max_request_size(),
+                DialogEngine::Log(_) => 0,
+            };
+            Ok::<_, Error>((engine, max_request_size))
+        })
+        .await
+        .map_err(|e| {
+            error!("The engine could not be looked up: {e}");
+            Error::Internal {
+                message: "the engine could not be looked up".to_string(),
+            }
+        })??
+    };
+    let engine = match engine {
+        DialogEngine::Engine(engine) => engine,
+        DialogEngine::Log(log) => {
+            let chunks = redeliver(&engine_id, log, messages, limits).await?;
+            let now = Instant::now();
+            let chunks = chunks.into_iter().map(|chunk| (chunk, now)).collect();
+            return Ok(Timed(throttled(chunks), None));
+        }
+    };
+    // the expected size is derived from the circuit, which is chosen by the client:
+    if let Some(limit) = limits.get("dialog") {
+        max_request_size = max_request_size.min(limit.as_u64() as usize);
+    }
+
+    let body = read_dialog(messages, max_request_size).await?;
+    let read = started.elapsed();
+    if registry.throttle_requests() {
+        let (registry, engine, bytes) = (Arc::clone(registry), Arc::clone(&engine), body.len());
+        let deadline =
+            spawn_blocking(move || registry.throttle_request(&mut engine.lock().unwrap(), bytes))
+                .await
+                .map_err(|e| {
+                    error!("The request of the client could not be throttled: {e}");
+                    Error::Internal {
+                        message: "the request could not be throttled".to_string(),
+                    }
+                })?;
+        wait_until(deadline).await;
+    }
+    let throttle = started.elapsed() - read;
+
+    // the cryptography runs on a blocking thread, so that it does not stall Rocket's async workers:
+    let permit = registry.engine_permit().await;
+    let wait = started.elapsed() - read - throttle;
+    let registry = Arc::clone(registry);
+    let processed = spawn_blocking(move || {
+        let _permit = permit;
+        let mut engine = engine.lock().unwrap();
+        let processing = Instant::now();
+        dialog_round(&registry, &engine_id, &mut engine, client, |engine| {
+            process_dialog(engine, &body, max_request_size)
+        })?;
+        let process = processing.elapsed();
+
+        let msgs = engine.dump_messages();
+        let message_id = engine.last_durably_received_client_event_offset();
+        let timing = registry.server_timing().then(|| ServerTiming {
+            read,
+            throttle,
+            wait,
+            process,
+            queued_messages: msgs.len(),
+            queued_bytes: engine.queued_bytes(),
+        });
+        let mut chunks = vec![];
+        if engine.streaming() {
+            // each message is sent as a separate frame, which the client can parse while it arrives:
+            for frame in frames::encode(&msgs, message_id)? {
+                chunks.extend(registry.throttle(&mut engine, frame));
+            }
+        } else {
+            let serialized = bincode::serialize(&(msgs, message_id))?;
+            chunks = registry.throttle(&mut engine, serialized);
+        }
+        Ok::<_, Error>((chunks, timing))
+    });
+    let (chunks, timing) = processed.await.map_err(|e| {
+        error!("The engine failed while processing the messages of its client: {e}");
+        Error::Internal {
+            message: "the engine failed".to_string(),
+        }
+    })??;
+    Ok(Timed(throttled(chunks), timing))
+}
+
+/// Sends each chunk of the response once its deadline is reached.
+fn throttled(chunks: Vec<(Vec<u8>, Instant)>) -> ByteStream![Vec<u8>] {
+    ByteStream! {
+        for (chunk, deadline) in chunks {
+            wait_until(deadline).await;
+            yield chunk;
+        }
+    }
+}
+
+async fn read_dialog(
+    messages: Data<'_>,
+    max_request_size: usize,
+) -> Result<Capped<Vec<u8>>, Error> {
+    let stream = messages.open(max_request_size.bytes());
+    stream.into_bytes().await.map_err(|e| {
+        warn!("Could not read the dialog request body: {e}");
+        Error::UnexpectedWireFormat(format!("could not read the request body: {e}"))
+    })
+}
+
+/// Answers a repeated dialog request of an engine that is gone using its persisted message log,
+/// see [`EngineRegistry::save_log`], returning the chunks of the response.
+async fn redeliver(
+    engine_id: &str,
+    mut log: MessageLog,
+    messages: Data<'_>,
+    limits: &Limits,
+) -> Result<Vec<Vec<u8>>, Error> {
+    let mut max_request_size = log.max_request_size;
+    if let Some(limit) = limits.get("dialog") {
+        max_request_size = max_request_size.min(limit.as_u64() as usize);
+    }
+    let body = read_dialog(messages, max_request_size).await?;
+    if !body.is_complete() {
+        return Err(Error::UnexpectedWireFormat(format!(
+            "request body exceeds the expected maximum of {max_request_size} bytes"
+        )));
+    }
     let (last_durably_received_offset, messages): (Option<u32>, Vec<(Vec<u8>, MessageId)>) =
-        bincode::deserialize(&stream.into_bytes().await.unwrap())?;
+        bincode::deserialize(&body)?;
+    if !log.replay(last_durably_received_offset, &messages) {
+        return Err(Error::NoSuchEngineId {
+            engine_id: engine_id.to_string(),
+        });
+    }
+    let msgs: Vec<_> = log.queue.msgs_iter().collect();
+    let message_id = log.last_durably_received_client_event_offset;
+    if log.streaming {
+        Ok(frames::encode(&msgs, message_id)?)
+    } else {
+        Ok(vec![bincode::serialize(&(msgs, message_id))?])
+    }
+}
 
-    let engine = registry.lookup(&engine_id)?;
+#[post("/<engine_id>/next", format = "application/json", data = "<request>")]
+pub(crate) async fn next_round(
+    engine_id: String,
+    request: Json<NextRound>,
+    r: &State<Arc<EngineRegistry>>,
+    client: Option<IpAddr>,
+) -> Result<Json<NextRoundResult>, Error> {
+    // the handler might compile the program, which must not stall Rocket's async workers:
+    let registry = Arc::clone(r);
+    let started = spawn_blocking(move || start_next_round(&registry, &engine_id, &request, client))
+        .await
+        .map_err(|e| {
+            error!("The handler failed while starting the next round: {e}");
+            Error::Internal {
+                message: "the handler failed".to_string(),
+            }
+        })??;
+    Ok(Json(started))
+}
+
+fn start_next_round(
+    r: &EngineRegistry,
+    engine_id: &EngineId,
+    request: &NextRound,
+    client: Option<IpAddr>,
+) -> Result<NextRoundResult, Error> {
+    r.check_client(client)?;
+    let engine = r.lookup(engine_id)?;
+    let program = match engine.lock().unwrap().follow_up_program() {
+        Some(program) => program.to_string(),
+        None => {
+            return Err(Error::NoFollowUp {
+                remaining_and_gates: 0,
+            })
+        }
+    };
+    let invocation = MpcRequest {
+        plaintext_metadata: request.plaintext_metadata_string(),
+        plaintext_metadata_json: request.plaintext_metadata.clone(),
+        program: program.clone(),
+        function: request.function.clone(),
+        circuit: None,
+    };
+    let handled = r
+        .handle_input(invocation)
+        .map_err(Error::MpcRequestRejected)?;
+    if handled.circuit.blake3_hash() != request.circuit_hash {
+        return Err(r.circuit_mismatch(&handled.circuit));
+    }
+    r.check_program_circuit(
+        blake3::hash(program.as_bytes()).as_bytes(),
+        &handled.circuit,
+    )?;
     let mut engine = engine.lock().unwrap();
+    if handled.on_output.is_some() && !engine.output_disclosure() {
+        return Err(Error::OutputDisclosureRequired);
+    }
+    let first_id = engine.next_round(
+        handled.circuit,
+        handled.input_from_server,
+        handled.on_output,
+        request.function.clone(),
+        request.plaintext_metadata_string(),
+    )?;
+    let messages = engine
+        .dump_messages()
+        .into_iter()
+        .filter(|(_, id)| *id >= first_id)
+        .map(|(msg, id)| (msg.clone(), id))
+        .collect();
+    Ok(NextRoundResult {
+        request_headers: handled.request_headers,
+        messages,
+    })
+}
 
-    if let Some(offset) = last_durably_received_offset {
-        engine.flush_queue(offset);
+#[get("/<engine_id>/final")]
+pub(crate) fn download_final(
+    engine_id: String,
+    range: Option<ByteRange>,
+    registry: &State<Arc<EngineRegistry>>,
+    client: Option<IpAddr>,
+) -> Result<Download, Error> {
+    registry.check_client(client)?;
+    registry.download_final(&engine_id, range)
+}
+
+// ranked explicitly, as the route would otherwise collide with `download_final`:
+#[get("/external/<external_id>", rank = 1)]
+pub(crate) fn external_id(
+    external_id: String,
+    r: &State<Arc<EngineRegistry>>,
+) -> Result<Json<ExternalId>, Error> {
+    let engine_id = r.resolve_external_id(&external_id)?;
+    Ok(Json(ExternalId {
+        external_id,
+        engine_id,
+    }))
+}
+
+#[post("/approvals", format = "application/json", data = "<request>")]
+pub(crate) fn create_approval(
+    request: Json<NewApproval>,
+    r: &State<Arc<EngineRegistry>>,
+    client: Option<IpAddr>,
+) -> Result<Created<Json<Approval>>, Error> {
+    r.check_client(client)?;
+    let rng: [u8; 16] = ChaCha20Rng::from_entropy().gen();
+    let approval_id = uuid::Builder::from_random_bytes(rng)
+        .into_uuid()
+        .to_string();
+    let approval = r.request_approval(approval_id, request.into_inner())?;
+    let c = Created::new(uri!(approval(&approval.approval_id)).to_string()).body(Json(approval));
+    Ok(c)
+}
+
+// ranked explicitly, as the route would otherwise collide with `download_final`:
+#[get("/approvals/<approval_id>", rank = 1)]
+pub(crate) fn approval(
+    approval_id: String,
+    r: &State<Arc<EngineRegistry>>,
+) -> Result<Json<Approval>, Error> {
+    r.approval(&approval_id).map(Json)
+}
+
+#[post("/approvals/<approval_id>/approve")]
+pub(crate) fn approve(
+    approval_id: String,
+    token: Option<BearerToken>,
+    r: &State<Arc<EngineRegistry>>,
+) -> Result<Json<Approval>, Error> {
+    let token = token.ok_or(Error::Unauthorized)?;
+    r.decide_approval(&approval_id, &token.0, true).map(Json)
+}
+
+#[post("/approvals/<approval_id>/reject")]
+pub(crate) fn reject(
+    approval_id: String,
+    token: Option<BearerToken>,
+    r: &State<Arc<EngineRegistry>>,
+) -> Result<Json<Approval>, Error> {
+    let token = token.ok_or(Error::Unauthorized)?;
+    r.decide_approval(&approval_id, &token.0, false).map(Json)
+}
+
+async fn wait_until(deadline: Instant) {
+    if deadline > Instant::now() {
+        sleep_until(deadline.into()).await;
     }
-    for (msg, offset) in messages {
-        engine.process_message(&msg, offset)?;
+}
+
+/// Lets the engine process the messages of the client using `process`, then drops the engine if
+/// it failed too often or is done, regardless of the transport of the request. Done engines are
+/// kept for repeated requests of their client, see [`EngineRegistry::finish_engine`]. The queued
+/// messages are persisted before they are sent, see [`EngineRegistry::save_log`].
+///
+/// Shared engines are stored afterwards, so that any instance can process the next request, see
+/// [`EngineRegistry::save_engine`].
+///
+/// The engine's queued messages are the response for the client if the round succeeded.
+pub(crate) fn dialog_round(
+    registry: &EngineRegistry,
+    engine_id: &EngineId,
+    engine: &mut EngineRef,
+    client: Option<IpAddr>,
+    process: impl FnOnce(&mut EngineRef) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let processed = process(engine);
+    // a full queue is not the client's fault, it only needs to acknowledge the queued messages:
+    if let Err(e) = &processed {
+        if !matches!(e, Error::QueueFull { .. }) {
+            engine.record_failure();
+        }
+    }
+    registry.check_failures(engine_id, engine, client);
+    let (staged_final, finished) = match &processed {
+        Ok(()) => (
+            engine.take_staged_final(),
+            engine.is_done() && registry.finish_engine(engine_id),
+        ),
+        Err(_) => (None, false),
+    };
+    // the messages are only sent once they can be sent again, even by another instance:
+    if processed.is_ok() {
+        registry.save_log(engine_id, engine)?;
+    }
+    registry.save_engine(engine_id, engine)?;
+    processed?;
+
+    if let Some(msg) = staged_final {
+        registry.stage_final(engine_id.clone(), msg)?;
     }
 
-    let result = (
-        engine.dump_messages(),
-        engine.last_durably_received_client_event_offset(),
-    );
+    if finished {
+        let (outcome, error) = engine.outcome();
+        registry.audit(engine.session(), outcome, error);
+    }
+    Ok(())
+}
 
-    if engine.is_done() {
-        registry.drop_engine(&engine_id);
+fn process_dialog(
+    engine: &mut EngineRef,
+    body: &Capped<Vec<u8>>,
+    max_request_size: usize,
+) -> Result<(), Error> {
+    if !body.is_complete() {
+        return Err(Error::UnexpectedWireFormat(format!(
+            "request body exceeds the expected maximum of {max_request_size} bytes"
+        )));
     }
+    let (last_durably_received_offset, messages): (Option<u32>, Vec<(Vec<u8>, MessageId)>) =
+        bincode::deserialize(body)?;
+    process_messages(engine, last_durably_received_offset, messages)
+}
 
-    let (msgs, message_id) = result;
-    let serialized = bincode::serialize(&(msgs, message_id))?;
-    Ok(ByteStream! { yield serialized; })
+/// Acknowledges the messages durably received by the client and processes the client's messages,
+/// unless the engine's queue is still full after the acknowledgement.
+pub(crate) fn process_messages(
+    engine: &mut EngineRef,
+    last_durably_received_offset: Option<MessageId>,
+    messages: Vec<(Msg, MessageId)>,
+) -> Result<(), Error> {
+    if let Some(offset) = last_durably_received_offset {
+        engine.flush_queue(offset);
+    }
+    if !messages.is_empty() {
+        engine.check_queue()?;
+    }
+    for (msg, offset) in messages {
+        engine.process_message(&msg, offset)?;
+    }
+    Ok(())
 }
 
-pub fn stage(handle_input: HandleMpcRequestFn) -> AdHoc {
-    AdHoc::on_ignite("Engine Context", |rocket| async {
-        rocket
+pub fn stage(handle_input: HandleMpcRequestFn, mut config: ServerConfig) -> AdHoc {
+    AdHoc::try_on_ignite("Engine Context", |rocket| async {
+        // sessions must not run without being recorded if an audit log is configured:
+        if config.audit_sink.is_none() {
+            if let Ok(path) = rocket.figment().extract_inner::<PathBuf>("audit_log") {
+                match JsonLinesAuditSink::open(&path) {
+                    Ok(audit_sink) => config.audit_sink = Some(Box::new(audit_sink)),
+                    Err(e) => {
+                        error!("Could not open audit log `{}`: {e}", path.display());
+                        return Err(rocket);
+                    }
+                }
+            }
+        }
+        let policy = rocket
+            .figment()
+            .extract::<FailurePolicy>()
+            .unwrap_or_else(|e| {
+                warn!("Invalid failure policy, using the defaults: {e}");
+                FailurePolicy::default()
+            });
+        let approval_policy = rocket
+            .figment()
+            .extract::<ApprovalPolicy>()
+            .unwrap_or_else(|e| {
+                warn!("Invalid approval policy, sessions do not require approvals: {e}");
+                ApprovalPolicy::default()
+            });
+        // a misconfigured allowlist must not allow all programs:
+        let allowlist = rocket
+            .figment()
+            .extract::<ProgramAllowlist>()
+            .unwrap_or_else(|e| {
+                error!("Invalid program allowlist, all programs are rejected: {e}");
+                ProgramAllowlist::deny_all()
+            });
+        let diagnostics = rocket
+            .figment()
+            .extract::<MismatchDiagnostics>()
+            .unwrap_or_else(|e| {
+                warn!("Invalid circuit diagnostics config, using the defaults: {e}");
+                MismatchDiagnostics::default()
+            });
+        let timing = rocket
+            .figment()
+            .extract::<TimingDiagnostics>()
+            .unwrap_or_else(|e| {
+                warn!("Invalid timing diagnostics config, responses are not timed: {e}");
+                TimingDiagnostics::default()
+            });
+        let execution = rocket
+            .figment()
+            .extract::<ExecutionPolicy>()
+            .unwrap_or_else(|e| {
+                warn!("Invalid engine threads, using one per CPU core: {e}");
+                ExecutionPolicy::default()
+            });
+        let bandwidth = rocket
+            .figment()
+            .extract::<BandwidthPolicy>()
+            .unwrap_or_else(|e| {
+                warn!("Invalid bandwidth limits, sessions are not throttled: {e}");
+                BandwidthPolicy::default()
+            });
+        let quotas = rocket
+            .figment()
+            .extract::<ResourceQuotas>()
+            .unwrap_or_else(|e| {
+                warn!("Invalid resource quotas, sessions are not limited: {e}");
+                ResourceQuotas::default()
+            });
+        let idempotency = rocket
+            .figment()
+            .extract::<IdempotencyPolicy>()
+            .unwrap_or_else(|e| {
+                warn!("Invalid idempotency policy, using the defaults: {e}");
+                IdempotencyPolicy::default()
+            });
+        let program_store = rocket
+            .figment()
+            .extract::<ProgramStorePolicy>()
+            .unwrap_or_else(|e| {
+                warn!("Invalid program store config, using the defaults: {e}");
+                ProgramStorePolicy::default()
+            });
+        // sessions must not fall back to unauthenticated channels if the key is misconfigured:
+        let channel_policy = rocket
+            .figment()
+            .extract::<ChannelPolicy>()
+            .map_err(|e| e.to_string())
+            .and_then(|policy| policy.key_pair());
+        let channel_key = match channel_policy {
+            Ok(Some(key)) => {
+                let public_key = blake3::Hash::from(key.public_key()).to_hex();
+                info!("Encrypted channels are authenticated using the public key {public_key}");
+                Some(key)
+            }
+            Ok(None) => None,
+            Err(e) => {
+                error!("Invalid channel secret key: {e}");
+                return Err(rocket);
+            }
+        };
+        // the message logs must not be lost silently if the store is misconfigured:
+        let store_policy = match rocket.figment().extract::<StorePolicy>() {
+            Ok(policy) => policy,
+            Err(e) => {
+                error!("Invalid session store config: {e}");
+                return Err(rocket);
+            }
+        };
+        let store = match config.session_store.take() {
+            Some(store) => Some(store),
+            None => match store_policy.open() {
+                Ok(store) => store,
+                Err(e) => {
+                    error!("Could not open the session store: {e}");
+                    return Err(rocket);
+                }
+            },
+        };
+        // an instance must not keep sessions to itself if the other instances expect to share them:
+        let shared_policy = match rocket.figment().extract::<SharedRegistryPolicy>() {
+            Ok(policy) => policy,
+            Err(e) => {
+                error!("Invalid shared registry config: {e}");
+                return Err(rocket);
+            }
+        };
+        let store: Option<Arc<dyn SessionStore>> = store.map(Arc::from);
+        let shared = match (
+            shared_policy.shared_registry,
+            &store,
+            shared_policy.state_key(),
+        ) {
+            (false, ..) => None,
+            (true, None, _) => {
+                error!("The shared registry requires a `session_store`");
+                return Err(rocket);
+            }
+            // shared sessions cannot store their encrypted channels, so the server would never
+            // authenticate itself:
+            (true, Some(_), Ok(Some(_))) if channel_key.is_some() => {
+                error!("Shared sessions do not support encrypted channels, remove the `channel_secret_key` or the `shared_registry`");
+                return Err(rocket);
+            }
+            (true, Some(store), Ok(Some(key))) => {
+                info!("Sessions are shared with all instances using the same store");
+                info!("Shared sessions do not negotiate encrypted channels or follow-up rounds");
+                Some(SharedSessions::new(
+                    Arc::clone(store),
+                    key,
+                    store_policy.session_ttl(),
+                ))
+            }
+            (true, Some(_), Ok(None)) => {
+                error!("Shared sessions require a `shared_state_key`");
+                return Err(rocket);
+            }
+            (true, Some(_), Err(e)) => {
+                error!("Invalid shared state key: {e}");
+                return Err(rocket);
+            }
+        };
+        let registry = Arc::new(EngineRegistry::new(
+            handle_input,
+            config,
+            policy,
+            approval_policy,
+            allowlist,
+            diagnostics,
+            timing,
+            execution,
+            bandwidth,
+            quotas,
+            idempotency,
+            channel_key,
+            program_store,
+            store,
+            store_policy.session_ttl(),
+            shared,
+        ));
+        #[cfg(feature = "grpc")]
+        let rocket = match rocket.figment().extract::<crate::grpc::GrpcConfig>() {
+            Ok(grpc) => match grpc.grpc_address {
+                Some(address) => {
+                    rocket.attach(crate::grpc::serve(Arc::clone(&registry), address, grpc))
+                }
+                None => rocket,
+            },
+            Err(e) => {
+                error!("Invalid gRPC config: {e}");
+                return Err(rocket);
+            }
+        };
+        Ok(rocket
             .mount(
                 "/",
                 routes![
@@ -134,10 +970,49 @@ pub fn stage(handle_input: HandleMpcRequestFn) -> AdHoc {
                     preflight_response_delete_session,
                     create_session,
                     delete_session,
-                    dialog
+                    dialog,
+                    next_round,
+                    download_final,
+                    external_id,
+                    create_approval,
+                    approval,
+                    approve,
+                    reject,
+                    healthz,
+                    readyz,
+                    capabilities,
+                    programs,
+                    metrics
                 ],
             )
-            .manage(EngineRegistry::new(handle_input))
+            .manage(registry))
+    })
+}
+
+/// Runs [`tandem::self_test()`] before the server is launched, unless `self_test_on_startup` is set
+/// to `false` in the Rocket config, and aborts the launch if any check fails.
+///
+/// Release builds are never launched if `tandem` was compiled with its `research` feature.
+pub fn self_test_on_startup() -> AdHoc {
+    AdHoc::try_on_ignite("Self Test", |rocket| async {
+        if tandem::RESEARCH_ACCESSORS && !cfg!(debug_assertions) {
+            error!("Tandem was compiled with the `research` feature, which exposes the secret values of the protocol and must never be used in release builds");
+            return Err(rocket);
+        }
+        let enabled = rocket
+            .figment()
+            .extract_inner::<bool>("self_test_on_startup")
+            .unwrap_or(true);
+        if !enabled {
+            return Ok(rocket);
+        }
+        let report = tandem::self_test();
+        if report.passed() {
+            Ok(rocket)
+        } else {
+            error!("Self test failed, the host cannot run the MPC protocol:\n{report}");
+            Err(rocket)
+        }
     })
 }
 
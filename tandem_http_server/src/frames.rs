@@ -0,0 +1,80 @@
+//! Length-prefixed frames of dialog responses, which clients can parse while they arrive.
+//!
+//! A response consists of a header frame followed by one frame per message. Each frame starts
+//! with its length as a little-endian `u64`:
+//!
+//! - The header frame contains the bincode-encoded offset of the last durably received message of
+//!   the client (an `Option<u32>`).
+//! - Each message frame contains the message id as a little-endian `u32`, followed by the message.
+
+use tandem::states::Msg;
+
+use crate::msg_queue::MessageId;
+
+/// The messages of a response together with the last durably received offset of the client.
+#[cfg(any(test, feature = "chaos"))]
+pub(crate) type Response = (Vec<(Msg, MessageId)>, Option<MessageId>);
+
+/// Number of bytes of the length prefix of a frame.
+const LEN: usize = 8;
+/// Number of bytes of the message id at the start of a message frame.
+#[cfg(any(test, feature = "chaos"))]
+const ID: usize = 4;
+
+/// Encodes the messages and the last durably received offset of the client as frames.
+pub(crate) fn encode(
+    msgs: &[(&Msg, MessageId)],
+    last_durably_received_offset: Option<MessageId>,
+) -> Result<Vec<Vec<u8>>, bincode::Error> {
+    let header = bincode::serialize(&last_durably_received_offset)?;
+    let mut frames = Vec::with_capacity(msgs.len() + 1);
+    frames.push(frame(&[&header]));
+    for (msg, id) in msgs {
+        frames.push(frame(&[&id.to_le_bytes(), msg]));
+    }
+    Ok(frames)
+}
+
+fn frame(parts: &[&[u8]]) -> Vec<u8> {
+    let len: usize = parts.iter().map(|p| p.len()).sum();
+    let mut frame = Vec::with_capacity(LEN + len);
+    frame.extend_from_slice(&(len as u64).to_le_bytes());
+    for part in parts {
+        frame.extend_from_slice(part);
+    }
+    frame
+}
+
+/// Decodes a complete response, returning `None` if it is malformed.
+#[cfg(any(test, feature = "chaos"))]
+pub(crate) fn decode(mut body: &[u8]) -> Option<Response> {
+    let mut frames = vec![];
+    while !body.is_empty() {
+        let len = u64::from_le_bytes(body.get(..LEN)?.try_into().ok()?) as usize;
+        frames.push(body.get(LEN..LEN.checked_add(len)?)?);
+        body = &body[LEN + len..];
+    }
+    let (header, frames) = frames.split_first()?;
+    let offset = bincode::deserialize(header).ok()?;
+    let mut msgs = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let id = MessageId::from_le_bytes(frame.get(..ID)?.try_into().ok()?);
+        msgs.push((frame[ID..].to_vec(), id));
+    }
+    Some((msgs, offset))
+}
+
+#[test]
+fn test_frames() {
+    let (a, b) = (vec![1, 2, 3], vec![]);
+    for (msgs, offset) in [
+        (vec![], None),
+        (vec![(&a, 0)], None),
+        (vec![(&a, 4), (&b, 5)], Some(7)),
+    ] {
+        let body = encode(&msgs, offset).unwrap().concat();
+        let msgs = msgs.into_iter().map(|(m, id)| (m.clone(), id)).collect();
+        assert_eq!(decode(&body), Some((msgs, offset)));
+        assert_eq!(decode(&body[..body.len() - 1]), None);
+    }
+}
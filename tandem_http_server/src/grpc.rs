@@ -0,0 +1,253 @@
+//! gRPC transport, serving the sessions of the Rocket routes as defined in `proto/tandem.proto`.
+//!
+//! The gRPC service shares the [`EngineRegistry`] with the Rocket routes, so that all policies,
+//! quotas and audit events apply to both transports. Responses are not throttled, as the
+//! bandwidth limits only apply to the HTTP dialog.
+
+use std::{net::SocketAddr, pin::Pin, sync::Arc};
+
+use rocket::{
+    fairing::AdHoc,
+    futures::{Stream, StreamExt},
+    serde::Deserialize,
+    tokio::task::spawn_blocking,
+};
+use tandem::states::Msg;
+use tonic::{Code, Request, Response, Status, Streaming};
+
+use crate::{
+    engine::{delete, dialog_round, new_session, process_messages},
+    msg_queue::MessageId,
+    requests::NewSession,
+    responses::Error,
+    state::{DialogEngine, EngineRegistry},
+};
+
+mod proto {
+    tonic::include_proto!("tandem");
+}
+
+use proto::{
+    tandem_server::{Tandem, TandemServer},
+    CreateSessionRequest, CreateSessionResponse, DeleteSessionRequest, DeleteSessionResponse,
+    DialogRequest, DialogResponse, Message,
+};
+
+/// Where and how the gRPC service is served, configured as part of the Rocket config.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct GrpcConfig {
+    /// Address of the gRPC service, which is only served if set.
+    pub grpc_address: Option<SocketAddr>,
+    /// Maximum size in bytes of a single gRPC message sent by a client.
+    pub grpc_max_message_size: usize,
+}
+
+impl Default for GrpcConfig {
+    fn default() -> Self {
+        Self {
+            grpc_address: None,
+            grpc_max_message_size: 256 * 1024 * 1024,
+        }
+    }
+}
+
+/// Serves the gRPC service at the address once Rocket has launched, until Rocket shuts down.
+pub(crate) fn serve(
+    registry: Arc<EngineRegistry>,
+    address: SocketAddr,
+    config: GrpcConfig,
+) -> AdHoc {
+    AdHoc::on_liftoff("gRPC Service", move |rocket| {
+        Box::pin(async move {
+            let shutdown = rocket.shutdown();
+            let service = TandemServer::new(TandemService { registry })
+                .max_decoding_message_size(config.grpc_max_message_size)
+                .max_encoding_message_size(usize::MAX);
+            info!("Serving gRPC at {address}");
+            rocket::tokio::spawn(async move {
+                let served = tonic::transport::Server::builder()
+                    .add_service(service)
+                    .serve_with_shutdown(address, shutdown)
+                    .await;
+                if let Err(e) = served {
+                    error!("The gRPC service failed: {e}");
+                }
+            });
+        })
+    })
+}
+
+struct TandemService {
+    registry: Arc<EngineRegistry>,
+}
+
+#[tonic::async_trait]
+impl Tandem for TandemService {
+    async fn create_session(
+        &self,
+        request: Request<CreateSessionRequest>,
+    ) -> Result<Response<CreateSessionResponse>, Status> {
+        let client = request.remote_addr().map(|addr| addr.ip());
+        let session = new_session_request(request.into_inner()).map_err(status)?;
+        // the handler might compile the program, which must not stall the async workers:
+        let registry = Arc::clone(&self.registry);
+        let created = spawn_blocking(move || new_session(&registry, &session, client))
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .map_err(status)?;
+        Ok(Response::new(CreateSessionResponse {
+            engine_id: created.engine_id,
+            request_headers: created.request_headers,
+            server_version: created.server_version,
+            protocol_version: created.protocol_version,
+            wire_version: created.wire_version,
+            capabilities: created.capabilities,
+            external_id: created.external_id,
+            messages: created.messages.into_iter().map(message).collect(),
+        }))
+    }
+
+    type DialogStream = Pin<Box<dyn Stream<Item = Result<DialogResponse, Status>> + Send>>;
+
+    async fn dialog(
+        &self,
+        request: Request<Streaming<DialogRequest>>,
+    ) -> Result<Response<Self::DialogStream>, Status> {
+        let client = request.remote_addr().map(|addr| addr.ip());
+        let registry = Arc::clone(&self.registry);
+        let responses = request.into_inner().then(move |request| {
+            let registry = Arc::clone(&registry);
+            async move {
+                let request = request?;
+                // like the HTTP dialog, the cryptography and the session store run on a blocking
+                // thread that is bounded by the `engine_threads`:
+                let permit = registry.engine_permit().await;
+                spawn_blocking(move || {
+                    let _permit = permit;
+                    exchange(&registry, request, client)
+                })
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?
+                .map_err(status)
+            }
+        });
+        Ok(Response::new(Box::pin(responses)))
+    }
+
+    async fn delete_session(
+        &self,
+        request: Request<DeleteSessionRequest>,
+    ) -> Result<Response<DeleteSessionResponse>, Status> {
+        delete(&self.registry, request.into_inner().engine_id).map_err(status)?;
+        Ok(Response::new(DeleteSessionResponse {}))
+    }
+}
+
+fn new_session_request(request: CreateSessionRequest) -> Result<NewSession, Error> {
+    let plaintext_metadata = serde_json::from_str(&request.plaintext_metadata_json)
+        .map_err(|e| Error::UnexpectedWireFormat(format!("invalid plaintext metadata: {e}")))?;
+    let circuit_hash = request.circuit_hash.try_into().map_err(|_| {
+        Error::UnexpectedWireFormat("the circuit hash must consist of 32 bytes".to_string())
+    })?;
+    Ok(NewSession {
+        plaintext_metadata,
+        program: request.program,
+        function: request.function,
+        circuit_hash,
+        client_version: request.client_version,
+        protocol_version: Some(request.protocol_version),
+        wire_version: None,
+        wire_versions: Some(request.wire_versions),
+        capabilities: request.capabilities,
+        // gRPC messages are not limited in size, the final message is part of the dialog:
+        stage_final: false,
+        approval_id: request.approval_id,
+        idempotency_key: request.idempotency_key,
+        session_nonce: None,
+        channel_key: None,
+        // follow-up rounds need the `next` route of the HTTP API:
+        follow_ups: None,
+        circuit: None,
+    })
+}
+
+/// Processes a single dialog request, like a `POST /<engine_id>` of the client.
+fn exchange(
+    registry: &EngineRegistry,
+    request: DialogRequest,
+    client: Option<std::net::IpAddr>,
+) -> Result<DialogResponse, Error> {
+    registry.check_client(client)?;
+    let offset = request.last_durably_received_offset;
+    let messages: Vec<(Msg, MessageId)> = request
+        .messages
+        .into_iter()
+        .map(|m| (m.payload, m.id))
+        .collect();
+    let engine = match registry.lookup_dialog(&request.engine_id)? {
+        DialogEngine::Engine(engine) => engine,
+        // the engine is gone, only the messages that it already sent can be sent again:
+        DialogEngine::Log(mut log) => {
+            if !log.replay(offset, &messages) {
+                return Err(Error::NoSuchEngineId {
+                    engine_id: request.engine_id,
+                });
+            }
+            return Ok(DialogResponse {
+                last_durably_received_offset: log.last_durably_received_client_event_offset,
+                messages: log
+                    .queue
+                    .msgs_iter()
+                    .map(|(msg, id)| message((msg.clone(), id)))
+                    .collect(),
+            });
+        }
+    };
+    let mut engine = engine.lock().unwrap();
+    let max_request_size = engine.max_request_size();
+    dialog_round(
+        registry,
+        &request.engine_id,
+        &mut engine,
+        client,
+        |engine| {
+            let size: usize = messages.iter().map(|(msg, _)| msg.len()).sum();
+            if size > max_request_size {
+                return Err(Error::UnexpectedWireFormat(format!(
+                    "messages exceed the expected maximum of {max_request_size} bytes"
+                )));
+            }
+            process_messages(engine, offset, messages)
+        },
+    )?;
+    Ok(DialogResponse {
+        last_durably_received_offset: engine.last_durably_received_client_event_offset(),
+        messages: engine
+            .dump_messages()
+            .into_iter()
+            .map(|(msg, id)| message((msg.clone(), id)))
+            .collect(),
+    })
+}
+
+fn message((payload, id): (Msg, MessageId)) -> Message {
+    Message { payload, id }
+}
+
+/// Converts the error into a gRPC status, with the JSON-encoded error (as returned by the Rocket
+/// routes) as its message.
+fn status(e: Error) -> Status {
+    let code = match e.status().code {
+        400 => Code::InvalidArgument,
+        401 => Code::Unauthenticated,
+        403 => Code::PermissionDenied,
+        404 => Code::NotFound,
+        409 => Code::AlreadyExists,
+        413 | 429 => Code::ResourceExhausted,
+        416 => Code::OutOfRange,
+        503 => Code::Unavailable,
+        _ => Code::Internal,
+    };
+    Status::new(code, serde_json::to_string(&e).unwrap_or_default())
+}
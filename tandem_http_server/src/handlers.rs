@@ -0,0 +1,203 @@
+//! Typed handlers for the functions of a Garble program, see [`MpcHandlers`].
+
+use std::{collections::HashMap, fmt::Display};
+
+use rocket::serde::DeserializeOwned;
+use tandem_garble_interop::{
+    check_program, compile_program, input_type, program_constants, Literal, Role, ToGarble,
+    TypedCircuit, TypedProgram,
+};
+
+use crate::{HandleMpcRequestFn, MpcRequest, MpcSession};
+
+type TypedInputFn = Box<dyn Fn(&MpcRequest) -> Result<Literal, String> + Send + Sync>;
+
+/// Handlers for the functions of a Garble program, each choosing the server's input as a typed
+/// Rust value based on the (deserialized) metadata of the client.
+///
+/// The functions are compiled when they are registered, the inputs returned by the handlers are
+/// type-checked and serialized as input bits and errors are reported to the client as rejected
+/// requests. Requests for other programs or functions are rejected as well. Usually built using
+/// [`crate::mpc_handler!`]:
+///
+/// ```
+/// use tandem_http_server::{build, mpc_handler};
+///
+/// let program = "pub fn add(x: u8, y: u8) -> u8 { x + y }";
+/// let handlers = mpc_handler!(program, {
+///     "add" => |user: String| -> Result<u8, String> {
+///         match user.as_str() {
+///             "alice" => Ok(2),
+///             _ => Err(format!("unknown user {user}")),
+///         }
+///     },
+/// })
+/// .unwrap();
+/// let rocket = build(handlers.into_handler());
+/// ```
+pub struct MpcHandlers {
+    program: String,
+    prg: TypedProgram,
+    handlers: HashMap<String, (TypedCircuit, TypedInputFn)>,
+}
+
+impl MpcHandlers {
+    /// Type-checks the program, whose functions can then be registered.
+    pub fn new(program: &str) -> Result<Self, String> {
+        let program = program.trim().to_string();
+        let prg = check_program(&program)?;
+        Ok(Self {
+            program,
+            prg,
+            handlers: HashMap::new(),
+        })
+    }
+
+    /// Compiles the function and registers the handler choosing its input.
+    ///
+    /// The metadata is deserialized from the JSON metadata of the request (a plain string unless
+    /// the client sent structured metadata) and the input must match the type of the function's
+    /// first parameter. Functions that declare server-side constants cannot be registered.
+    pub fn register<M, I, E>(
+        mut self,
+        function: &str,
+        handler: impl Fn(M) -> Result<I, E> + Send + Sync + 'static,
+    ) -> Result<Self, String>
+    where
+        M: DeserializeOwned,
+        I: ToGarble,
+        E: Display,
+    {
+        let circuit = compile_program(&self.prg, function)?;
+        if !program_constants(&circuit.fn_def).is_empty() {
+            return Err(format!(
+                "{function} declares server-side constants, which typed handlers cannot bind"
+            ));
+        }
+        let prg = self.prg.clone();
+        let fn_def = circuit.fn_def.clone();
+        let input = move |r: &MpcRequest| {
+            let metadata = M::deserialize(&r.plaintext_metadata_json)
+                .map_err(|e| format!("invalid metadata for {}: {e}", r.function))?;
+            let input = handler(metadata).map_err(|e| e.to_string())?;
+            input.to_garble_checked(&prg, input_type(Role::Contributor, &fn_def))
+        };
+        let handler = (circuit, Box::new(input) as TypedInputFn);
+        self.handlers.insert(function.to_string(), handler);
+        Ok(self)
+    }
+
+    /// Returns the handler of the registered functions, to be passed to [`crate::build`].
+    pub fn into_handler(self) -> HandleMpcRequestFn {
+        Box::new(move |r: MpcRequest| self.handle(r))
+    }
+
+    fn handle(&self, r: MpcRequest) -> Result<MpcSession, String> {
+        if r.circuit.is_some() || r.program.trim() != self.program {
+            return Err("the program does not match the program of the handlers".to_string());
+        }
+        let (circuit, input) = self
+            .handlers
+            .get(&r.function)
+            .ok_or_else(|| format!("there is no handler for the function '{}'", r.function))?;
+        let input = input(&r)?;
+        Ok(MpcSession {
+            circuit: circuit.gates.clone().into_inner(),
+            input_from_server: input.as_bits(&self.prg),
+            request_headers: HashMap::new(),
+            on_output: None,
+        })
+    }
+}
+
+/// Builds [`MpcHandlers`] for the functions of a Garble program, each mapped to a handler that
+/// takes the deserialized metadata and returns the typed input of the server (or an error that is
+/// reported to the client), see [`MpcHandlers::register`]:
+///
+/// ```
+/// use tandem_http_server::mpc_handler;
+///
+/// fn limit(tier: u8) -> Result<u16, &'static str> {
+///     [100, 1000].get(tier as usize).copied().ok_or("unknown tier")
+/// }
+///
+/// let program = "pub fn check(limit: u16, amount: u16) -> bool { amount <= limit }";
+/// let handlers = mpc_handler!(program, { "check" => limit }).unwrap();
+/// ```
+///
+/// Evaluates to a `Result<MpcHandlers, String>`, which fails if the program or any of the
+/// functions cannot be compiled.
+#[macro_export]
+macro_rules! mpc_handler {
+    ($program:expr, { $($function:expr => $handler:expr),* $(,)? }) => {
+        (|| -> ::std::result::Result<$crate::MpcHandlers, ::std::string::String> {
+            let handlers = $crate::MpcHandlers::new($program)?;
+            $(let handlers = handlers.register($function, $handler)?;)*
+            ::std::result::Result::Ok(handlers)
+        })()
+    };
+}
+
+#[test]
+fn test_typed_handlers() {
+    use rocket::serde::Deserialize;
+    use tandem_garble_interop::serialize_input;
+
+    #[derive(Deserialize)]
+    #[serde(crate = "rocket::serde")]
+    struct Account {
+        tier: u8,
+    }
+
+    let program = "pub fn add(x: u8, y: u8) -> u8 { x + y }
+pub fn check(limit: u16, amount: u16) -> bool { amount <= limit }";
+    let request = |function: &str, metadata: serde_json::Value| MpcRequest {
+        plaintext_metadata: metadata.to_string(),
+        plaintext_metadata_json: metadata,
+        program: format!("{program}\n"),
+        function: function.to_string(),
+        circuit: None,
+    };
+    let handlers = crate::mpc_handler!(program, {
+        "add" => |user: String| match user.as_str() {
+            "alice" => Ok(2u8),
+            _ => Err(format!("unknown user {user}")),
+        },
+        "check" => |account: Account| -> Result<u16, String> { Ok(100 * account.tier as u16) },
+    })
+    .unwrap();
+
+    let prg = check_program(program).unwrap();
+    let add = compile_program(&prg, "add").unwrap();
+    let session = handlers.handle(request("add", "alice".into())).unwrap();
+    assert_eq!(session.circuit.blake3_hash(), add.gates.blake3_hash());
+    let expected = serialize_input(Role::Contributor, &prg, &add.fn_def, "2u8").unwrap();
+    assert_eq!(session.input_from_server, expected);
+    let error = handlers.handle(request("add", "bob".into())).unwrap_err();
+    assert_eq!(error, "unknown user bob");
+
+    let metadata = serde_json::json!({ "tier": 3 });
+    let session = handlers.handle(request("check", metadata)).unwrap();
+    let check = compile_program(&prg, "check").unwrap();
+    let expected = serialize_input(Role::Contributor, &prg, &check.fn_def, "300u16").unwrap();
+    assert_eq!(session.input_from_server, expected);
+    let error = handlers
+        .handle(request("check", "gold".into()))
+        .unwrap_err();
+    assert!(error.starts_with("invalid metadata for check"), "{error}");
+
+    let error = handlers.handle(request("sub", "alice".into())).unwrap_err();
+    assert_eq!(error, "there is no handler for the function 'sub'");
+    let mut other_program = request("add", "alice".into());
+    other_program.program = "pub fn add(x: u8, y: u8) -> u8 { y + x }".to_string();
+    assert!(handlers.handle(other_program).is_err());
+
+    // inputs of the wrong type are rejected:
+    let handlers = crate::mpc_handler!(program, { "add" => |_: String| Ok::<_, String>(2u16) });
+    let error = handlers.unwrap().handle(request("add", "alice".into()));
+    assert!(error.unwrap_err().contains("is not of the type u8"));
+
+    assert!(crate::mpc_handler!(program, { "sub" => |_: String| Ok::<_, String>(2u8) }).is_err());
+    let program = "const LIMIT: u8;\npub fn main(x: u8, y: u8) -> bool { x + y > LIMIT }";
+    assert!(crate::mpc_handler!(program, { "main" => |_: String| Ok::<_, String>(2u8) }).is_err());
+}
@@ -6,10 +6,13 @@
 //! This crate can be used as either a library or a binary.
 //!
 //! As a library, it provides a [`build`] function, which can be used to construct a server with
-//! custom logic for choosing its input.
+//! custom logic for choosing its input. Additional hooks, such as a custom [`IdGenerator`] for the
+//! ids of sessions, can be supplied using [`build_with_config`]. With the `handlers` feature, the
+//! input can instead be chosen by typed handlers for the functions of a Garble program, see
+//! `mpc_handler!`.
 //!
 //! In order to use this crate as a binary, the crate must be compiled with the `bin` feature. The
-//! server binary supports two modes of execution:
+//! server binary supports three modes of execution:
 //!
 //! If the server is started without any configuration, it acts as a simple 'echo server' and
 //! expects the contributor's input to be supplied by the client (as plaintext metadata). This can
@@ -19,7 +22,11 @@
 //! `Tandem.toml`, describing which MPC function and which contributor input to use based on the
 //! plaintext metadata supplied by the client. These must be stored in the directory from which the
 //! server is going to be started. The directory must also contain a file named `program.garble.rs`
-//! with the program to run on the SMPC engine.
+//! with the program to run on the SMPC engine, unless each handler references its own program file.
+//!
+//! Finally, the configuration can specify an `input_webhook` instead of handlers, an HTTP endpoint
+//! that is asked for the contributor's input of each session, based on the plaintext metadata,
+//! function and program hash of the request.
 //!
 //! As the sample server is based on the [Rocket](https://rocket.rs) framework, it is possible to
 //! configure it according to the official [Rocket
@@ -39,24 +46,208 @@
 #![deny(missing_docs)]
 #![deny(rustdoc::broken_intra_doc_links)]
 
-use engine::{stage, Cors};
+pub use audit::{AuditEvent, AuditOutcome, AuditSink, JsonLinesAuditSink};
+use engine::{self_test_on_startup, stage, Cors};
+#[cfg(feature = "handlers")]
+pub use handlers::MpcHandlers;
 use rocket::{Build, Rocket};
-pub use types::{HandleMpcRequestFn, MpcRequest, MpcSession};
+use std::collections::HashMap;
+#[cfg(feature = "redis")]
+pub use store::RedisSessionStore;
+pub use store::SessionStore;
+use tandem::CircuitBlake3Hash;
+pub use types::{
+    CatalogFn, HandleMpcRequestFn, IdGenerator, MpcOutput, MpcRequest, MpcSession, OutputHandler,
+    PublishedFunction, RandomIds, Readiness, ReadinessFn, StaticHeaders, StickinessProvider,
+};
 
 #[macro_use]
 extern crate rocket;
 
+mod audit;
+#[cfg(feature = "chaos")]
+mod chaos;
 mod engine;
+mod frames;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "handlers")]
+mod handlers;
 mod msg_queue;
 mod requests;
 mod responses;
+mod shared;
 mod state;
+mod store;
+mod throttle;
 mod types;
 
 #[cfg(test)]
 mod tests;
 
+/// Version of the HTTP wire protocol spoken between client and server.
+///
+/// Client and server can interoperate regardless of their crate versions as long as they agree on
+/// both the wire version and the [`tandem::PROTOCOL_VERSION`]. The wire version is only
+/// incremented when the HTTP requests or responses change in an incompatible way.
+///
+/// Clients advertise all wire versions that they support, the server picks the newest version
+/// between [`MIN_WIRE_VERSION`] and this version that is supported by the client.
+pub const WIRE_VERSION: u32 = 2;
+
+/// Oldest version of the HTTP wire protocol that the server still supports, see [`WIRE_VERSION`].
+pub const MIN_WIRE_VERSION: u32 = 1;
+
+/// Optional features of the HTTP wire protocol that the server supports.
+///
+/// Clients advertise the capabilities that they support (starting with wire version 2), the server
+/// enables the ones that both sides support for the session. Unknown capabilities are ignored.
+///
+/// - `streaming`: dialog responses are sent as length-prefixed frames, one per message, instead of
+///   a single bincode-encoded message log.
+/// - `transcript_confirmation`: the final message of the server is followed by the hash of all
+///   exchanged messages, which the client checks before decrypting the output and answers with its
+///   own hash in a last dialog request, see [`tandem::states::Contributor::transcript_hash`].
+/// - `encrypted_channel`: all messages of the protocol are encrypted using keys agreed on during
+///   the creation of the session, see [`tandem::channel`]. Only enabled if the client sends its
+///   `channel_key`, the server authenticates itself using its `channel_secret_key` (if configured).
+/// - `output_disclosure`: the client discloses its output to the server in a last dialog request
+///   (after the confirmation of the transcript, if any), see
+///   [`tandem::states::Contributor::disclosed_output`]. Only enabled for sessions whose handler
+///   processes the output (see [`MpcSession::on_output`]) or that run follow-up rounds.
+/// - `multi_round`: the session preprocesses the triples of follow-up rounds that the client
+///   reserves in its `follow_ups`, which run other functions of the same program once the previous
+///   round is completed, without creating a new session. Only enabled if the client sends its
+///   `follow_ups`, see [`tandem::PreprocessedTriples::split_off`].
+/// - `program_store`: the server stores the program of the session under the hash of its circuit,
+///   so that later sessions of the client can send an empty `program` and only reference the
+///   program by its `circuit_hash`. Sessions referencing a program that the server does not know
+///   (or no longer knows) fail with `UnknownProgram`, see [`ServerConfig::with_program`].
+///
+/// The messages of the protocol are not compressed, as garbled tables and labels are
+/// indistinguishable from random bytes. The roles are not negotiated either: the server always
+/// acts as the contributor and the client as the evaluator.
+pub const CAPABILITIES: &[&str] = &[
+    "streaming",
+    "transcript_confirmation",
+    "encrypted_channel",
+    "output_disclosure",
+    "multi_round",
+    "program_store",
+];
+
+/// Header identifying all requests of a single computation, generated by the client.
+///
+/// The server logs the trace id together with the engine id of the session and includes it as
+/// `trace_id` in all error responses, so that the logs of client and server can be joined. Trace
+/// ids of more than 64 characters or with characters other than ASCII letters, digits, `-` and `_`
+/// are ignored.
+pub const TRACE_ID_HEADER: &str = "X-Tandem-Trace-Id";
+
+/// Hooks to customize a server built using [`build_with_config`].
+pub struct ServerConfig {
+    id_generator: Box<dyn IdGenerator>,
+    readiness: Option<ReadinessFn>,
+    catalog: Option<CatalogFn>,
+    audit_sink: Option<Box<dyn AuditSink>>,
+    programs: HashMap<CircuitBlake3Hash, String>,
+    session_store: Option<Box<dyn SessionStore>>,
+    stickiness: Option<Box<dyn StickinessProvider>>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            id_generator: Box::new(RandomIds),
+            readiness: None,
+            catalog: None,
+            audit_sink: None,
+            programs: HashMap::new(),
+            session_store: None,
+            stickiness: None,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Uses the specified generator to choose the engine ids (and optionally the external ids) of
+    /// new sessions, instead of random UUIDs.
+    pub fn with_id_generator(mut self, id_generator: impl IdGenerator + 'static) -> Self {
+        self.id_generator = Box::new(id_generator);
+        self
+    }
+
+    /// Reports the readiness of the server via `GET /readyz` using the specified function, for
+    /// example while circuits are still being compiled in the background. Without a readiness
+    /// function, the server is always ready.
+    pub fn with_readiness(mut self, readiness: ReadinessFn) -> Self {
+        self.readiness = Some(readiness);
+        self
+    }
+
+    /// Publishes the functions listed by the specified function via `GET /programs`, so that
+    /// clients can discover them. Without a catalog, `GET /programs` responds with `404 Not
+    /// Found`.
+    pub fn with_catalog(mut self, catalog: CatalogFn) -> Self {
+        self.catalog = Some(catalog);
+        self
+    }
+
+    /// Records the creation, completion and failure of every session using the specified sink,
+    /// instead of the [`JsonLinesAuditSink`] configured as `audit_log` in the Rocket config (if
+    /// any).
+    pub fn with_audit_sink(mut self, audit_sink: impl AuditSink + 'static) -> Self {
+        self.audit_sink = Some(Box::new(audit_sink));
+        self
+    }
+
+    /// Pre-registers the program, so that clients can reference it by the hash of the circuit
+    /// compiled from it without ever sending the program, see the `program_store` capability of
+    /// [`CAPABILITIES`].
+    ///
+    /// The program is still checked against the allowlist and compiled by the handler for each
+    /// session, sessions fail if the circuit of the handler does not match the hash. Unlike the
+    /// programs stored for clients, pre-registered programs are never evicted.
+    pub fn with_program(mut self, circuit_hash: CircuitBlake3Hash, program: String) -> Self {
+        self.programs.insert(circuit_hash, program);
+        self
+    }
+
+    /// Persists the message log of every session in the specified store, instead of the
+    /// `RedisSessionStore` configured as `session_store` in the Rocket config (if any).
+    ///
+    /// The messages of each dialog response are stored before they are sent, so that a client
+    /// whose response was lost can repeat its request even if the session is no longer running on
+    /// this instance, for example after a restart. The session itself still needs to be continued
+    /// by the instance that runs it, unless the Rocket config enables the `shared_registry`: the
+    /// running sessions are then stored as well, so that any instance using the store can continue
+    /// them.
+    pub fn with_session_store(mut self, session_store: impl SessionStore + 'static) -> Self {
+        self.session_store = Some(Box::new(session_store));
+        self
+    }
+
+    /// Adds the headers chosen by the specified provider to every session, so that the client
+    /// sends all requests of the session to the instance of the server that runs it, even behind a
+    /// load balancer.
+    pub fn with_stickiness_provider(mut self, stickiness: Box<dyn StickinessProvider>) -> Self {
+        self.stickiness = Some(stickiness);
+        self
+    }
+}
+
 /// Starts a Tandem server, responding to requests using the specified custom handler logic.
 pub fn build(handler: HandleMpcRequestFn) -> Rocket<Build> {
-    rocket::build().attach(stage(handler)).attach(Cors)
+    build_with_config(handler, ServerConfig::default())
+}
+
+/// Starts a Tandem server like [`build`], using the hooks of the specified config.
+///
+/// With the `chaos` feature, failures are injected into the responses as configured by the `chaos`
+/// key of the Rocket config, to test how clients cope with misbehaving servers.
+pub fn build_with_config(handler: HandleMpcRequestFn, config: ServerConfig) -> Rocket<Build> {
+    let rocket = rocket::build().attach(self_test_on_startup());
+    #[cfg(feature = "chaos")]
+    let rocket = rocket.attach(chaos::Chaos);
+    rocket.attach(stage(handler, config)).attach(Cors)
 }
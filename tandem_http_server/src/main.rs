@@ -1,114 +1,554 @@
-use std::{collections::HashMap, fs::read_to_string, path::Path};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    fs::read_to_string,
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{self, Stdio},
+    sync::{Arc, Mutex, RwLock},
+    thread,
+    time::{Duration, Instant},
+};
 
 use figment::{
     providers::{Env, Format, Json, Toml},
     Figment,
 };
-use serde::Deserialize;
-use tandem_garble_interop::{check_program, compile_program, serialize_input, Role};
-use tandem_http_server::{build, MpcRequest, MpcSession};
-
-use std::{env, iter::zip};
+use rocket::{Build, Rocket};
+use serde::{Deserialize, Serialize};
+use tandem::Circuit;
+use tandem_garble_interop::{
+    check_program, compile_program, deserialize_output, diff_programs, normalized_program_hash,
+    program_constants, serialize_input, serialize_input_with_constants, CircuitCache, Role,
+    TypedCircuit, TypedFnDef, TypedProgram, GARBLE_VERSION,
+};
+use tandem_http_server::{
+    build_with_config, MpcOutput, MpcRequest, MpcSession, OutputHandler, PublishedFunction,
+    Readiness, ServerConfig, StaticHeaders, StickinessProvider,
+};
+use tokio::{io::AsyncWriteExt, process::Command, runtime::Handle, sync::Semaphore, time};
+use url::Url;
 
-#[macro_use]
-extern crate rocket;
+use std::env;
 
 type ProgramFilePath = String;
 type ProgramFnName = String;
+type ProgramHash = String;
+type HandlerName = String;
 type PlaintextMetadata = String;
 type OwnInput = String;
+type ConstantName = String;
 
+/// The raw configuration, as read from `Tandem.json`, `Tandem.toml` and `TANDEM_*` env vars.
 #[derive(Debug, Clone, Deserialize)]
 struct HandlerConfig {
-    handlers: HashMap<ProgramFnName, HashMap<PlaintextMetadata, OwnInput>>,
+    handlers: HashMap<HandlerName, HandlerEntry>,
+    /// URL of an endpoint that chooses the contributor's input for each session, instead of the
+    /// configured handlers.
+    #[serde(default)]
+    input_webhook: Option<String>,
+    /// Bearer token sent to the input webhook in the `Authorization` header.
+    #[serde(default)]
+    input_webhook_token: Option<String>,
+    /// Bearer token sent to the output webhooks of all handlers in the `Authorization` header.
+    #[serde(default)]
+    output_webhook_token: Option<String>,
+    /// URL of the SQL database in which handlers look up inputs, see [`InputLookup`].
+    #[cfg(feature = "database")]
+    #[serde(default)]
+    input_database: Option<String>,
+    #[serde(default)]
+    circuit_cache: Option<PathBuf>,
+    #[serde(default = "default_circuit_cache_max_bytes")]
+    circuit_cache_max_bytes: u64,
+    /// Handlers that are compiled in the background after startup, in the specified order. All
+    /// other handlers are compiled when they are first used.
+    #[serde(default)]
+    prewarm: Vec<HandlerName>,
+    /// Routes all requests of a session to the instance of the server that runs it, see
+    /// [`StickinessProvider`]. Defaults to `fly` if the server runs on fly.io.
+    #[serde(default)]
+    stickiness: Option<StickinessConfig>,
+    /// The maximum number of AND gates of the circuits compiled for clients by the echo server.
+    #[serde(default)]
+    max_and_gates: Option<usize>,
+    /// The CPU time (in seconds) that the echo server spends on compiling a client's program.
+    #[serde(default = "default_compile_cpu_secs")]
+    compile_cpu_secs: u64,
+    /// The memory (in bytes) that the echo server uses for compiling a client's program.
+    #[serde(default = "default_compile_memory_bytes")]
+    compile_memory_bytes: u64,
+    /// The maximum number of programs that the echo server compiles at the same time, defaults to
+    /// the number of available CPU cores. Further sessions wait until one of them is done.
+    #[serde(default)]
+    max_compile_workers: Option<usize>,
 }
 
-#[launch]
-fn rocket() -> _ {
-    println!(
-        "Starting server in {}...",
-        env::current_dir().unwrap().display().to_string()
-    );
+/// A single entry of the configured handlers.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+enum HandlerEntry {
+    /// The inputs of a function of its own program file, with the handler named freely.
+    Program(ProgramHandler),
+    /// The inputs of a function of `program.garble.rs`, with the handler named after the function.
+    Inputs(HashMap<PlaintextMetadata, OwnInput>),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProgramHandler {
+    program: ProgramFilePath,
+    function: ProgramFnName,
+    #[serde(default)]
+    inputs: HashMap<PlaintextMetadata, OwnInput>,
+    /// The literals of the server-side constants declared by the program, see
+    /// [`program_constants`].
+    #[serde(default)]
+    constants: HashMap<ConstantName, String>,
+    /// URL of an endpoint that receives the output of every session, see [`OutputWebhook`].
+    #[serde(default)]
+    output_webhook: Option<String>,
+    /// Looks up the inputs for all metadata that is not listed in `inputs`.
+    #[cfg(feature = "database")]
+    #[serde(default)]
+    lookup: Option<InputLookup>,
+}
 
+fn default_circuit_cache_max_bytes() -> u64 {
+    CircuitCache::DEFAULT_MAX_BYTES
+}
+
+fn default_compile_cpu_secs() -> u64 {
+    10
+}
+
+fn default_compile_memory_bytes() -> u64 {
+    2 * 1024 * 1024 * 1024
+}
+
+/// The validated configuration of the server.
+struct AppConfig {
+    handlers: HashMap<HandlerName, Handler>,
+    /// The source code and the type-checked program of all configured handlers, by the hash of
+    /// the (trimmed) source code.
+    programs: HashMap<ProgramHash, (String, TypedProgram)>,
+    webhook: Option<InputWebhook>,
+    cache: Option<CircuitCache>,
+    prewarm: Vec<HandlerName>,
+    stickiness: Option<Box<dyn StickinessProvider>>,
+    max_and_gates: Option<usize>,
+    compile_workers: CompileWorkers,
+}
+
+/// A validated handler, referring to a function of one of the configured programs.
+#[derive(Debug, Clone)]
+struct Handler {
+    path: ProgramFilePath,
+    program: ProgramHash,
+    function: ProgramFnName,
+    inputs: HashMap<PlaintextMetadata, OwnInput>,
+    constants: HashMap<ConstantName, String>,
+    output_webhook: Option<Arc<OutputWebhook>>,
+    #[cfg(feature = "database")]
+    lookup: Option<(InputLookup, Arc<InputDatabase>)>,
+}
+
+/// All problems found in the configuration, reported together instead of one at a time.
+#[derive(Debug, Default)]
+struct ConfigErrors(Vec<String>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration, found {} error(s):", self.0.len())?;
+        for e in &self.0 {
+            writeln!(f, "  - {}", e.replace('\n', "\n    "))?;
+        }
+        Ok(())
+    }
+}
+
+impl AppConfig {
+    /// Reads and validates the configuration, checking that all programs can be type-checked and
+    /// that all handlers refer to existing functions and have valid input literals.
+    fn load(figment: Figment) -> Result<Self, ConfigErrors> {
+        let config: HandlerConfig = figment
+            .extract()
+            .map_err(|e| ConfigErrors(e.into_iter().map(|e| e.to_string()).collect()))?;
+        let mut errors = ConfigErrors::default();
+
+        if config.circuit_cache_max_bytes == 0 {
+            errors
+                .0
+                .push("circuit_cache_max_bytes must be greater than 0".to_string());
+        }
+        if config.max_and_gates == Some(0) {
+            errors
+                .0
+                .push("max_and_gates must be greater than 0".to_string());
+        }
+        if config.compile_cpu_secs == 0 {
+            errors
+                .0
+                .push("compile_cpu_secs must be greater than 0".to_string());
+        }
+        if config.compile_memory_bytes == 0 {
+            errors
+                .0
+                .push("compile_memory_bytes must be greater than 0".to_string());
+        }
+        if config.max_compile_workers == Some(0) {
+            errors
+                .0
+                .push("max_compile_workers must be greater than 0".to_string());
+        }
+        let cache = match &config.circuit_cache {
+            Some(dir) if config.circuit_cache_max_bytes > 0 => {
+                match CircuitCache::open(dir, config.circuit_cache_max_bytes) {
+                    Ok(cache) => {
+                        println!("Caching compiled circuits in {}...", dir.display());
+                        Some(cache)
+                    }
+                    Err(e) => {
+                        errors.0.push(format!(
+                            "could not open circuit cache `{}`: {e}",
+                            dir.display()
+                        ));
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+
+        let webhook = match (&config.input_webhook, &config.input_webhook_token) {
+            (Some(url), token) => match Url::parse(url) {
+                Ok(url) if !config.handlers.is_empty() => {
+                    errors.0.push(format!(
+                        "input_webhook: {url} cannot be used together with configured handlers"
+                    ));
+                    None
+                }
+                Ok(url) => Some(InputWebhook::new(url, token.clone())),
+                Err(e) => {
+                    errors
+                        .0
+                        .push(format!("input_webhook: `{url}` is not a valid URL: {e}"));
+                    None
+                }
+            },
+            (None, Some(_)) => {
+                errors
+                    .0
+                    .push("input_webhook_token: there is no configured input_webhook".to_string());
+                None
+            }
+            (None, None) => None,
+        };
+
+        let stickiness = match config.stickiness.or_else(StickinessConfig::detect) {
+            Some(stickiness) => match stickiness.provider() {
+                Ok(provider) => Some(provider),
+                Err(e) => {
+                    errors.0.push(format!("stickiness: {e}"));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        #[cfg(feature = "database")]
+        let database = config.input_database.as_ref().map(|url| {
+            Arc::new(InputDatabase {
+                url: url.clone(),
+                pool: Default::default(),
+            })
+        });
+
+        for name in &config.prewarm {
+            if !config.handlers.contains_key(name) {
+                errors.0.push(format!(
+                    "prewarm: {name} cannot be prewarmed, as it has no configured handlers"
+                ));
+            }
+        }
+
+        // each program file is only read once, even if it is used by several handlers:
+        let mut loaded: HashMap<ProgramFilePath, Option<ProgramHash>> = HashMap::new();
+        let mut programs = HashMap::new();
+        let mut handlers = HashMap::new();
+        for (name, entry) in config.handlers {
+            let handler = match entry {
+                HandlerEntry::Program(handler) => handler,
+                HandlerEntry::Inputs(inputs) => ProgramHandler {
+                    program: PROGRAM_FILE.to_string(),
+                    function: name.clone(),
+                    inputs,
+                    constants: HashMap::new(),
+                    output_webhook: None,
+                    #[cfg(feature = "database")]
+                    lookup: None,
+                },
+            };
+            let ProgramHandler {
+                program: path,
+                function,
+                inputs,
+                constants,
+                output_webhook,
+                ..
+            } = handler;
+            let output_webhook = match output_webhook.as_deref().map(Url::parse) {
+                Some(Ok(url)) => {
+                    let token = config.output_webhook_token.clone();
+                    Some(Arc::new(OutputWebhook::new(url, token)))
+                }
+                Some(Err(e)) => {
+                    errors.0.push(format!(
+                        "handlers.{name}.output_webhook: `{}` is not a valid URL: {e}",
+                        output_webhook.unwrap_or_default()
+                    ));
+                    None
+                }
+                None => None,
+            };
+            let hash = loaded.entry(path.clone()).or_insert_with(|| {
+                let (source_code, program) = load_program(Path::new(&path), &mut errors)?;
+                let hash = blake3::hash(source_code.as_bytes()).to_string();
+                programs.insert(hash.clone(), (source_code, program));
+                Some(hash)
+            });
+            let hash = match hash {
+                Some(hash) => hash.clone(),
+                None => continue,
+            };
+            let (_, program) = &programs[&hash];
+            let fn_def = match program.fn_defs.get(&function) {
+                Some(fn_def) if fn_def.is_pub && fn_def.params.len() == 2 => fn_def,
+                _ => {
+                    errors.0.push(format!(
+                        "handlers.{name}: {function} in `{path}` is not a public function with 2 parameters"
+                    ));
+                    continue;
+                }
+            };
+            // the values of the constants are only checked once all of them are bound:
+            let declared = program_constants(fn_def);
+            let mut unbound = false;
+            for (constant, _) in &declared {
+                if !constants.contains_key(*constant) {
+                    errors.0.push(format!(
+                        "handlers.{name}.constants: {constant} is declared in `{path}`, but not bound"
+                    ));
+                    unbound = true;
+                }
+            }
+            for constant in constants.keys() {
+                if !declared.iter().any(|(c, _)| c == constant) {
+                    errors.0.push(format!(
+                        "handlers.{name}.constants: {constant} is not declared in `{path}`"
+                    ));
+                    unbound = true;
+                }
+            }
+            for (metadata, input) in &inputs {
+                let serialized = serialize_input_with_constants(program, fn_def, input, &constants);
+                if let (Err(e), false) = (serialized, unbound) {
+                    errors.0.push(format!(
+                        "handlers.{name}.\"{metadata}\": not a valid input literal:\n{e}"
+                    ));
+                }
+            }
+            #[cfg(feature = "database")]
+            let lookup = match (handler.lookup, &database) {
+                (Some(lookup), Some(database)) => {
+                    if let Err(e) = lookup.render(|_| Ok(String::new())) {
+                        errors.0.push(format!("handlers.{name}.lookup: {e}"));
+                    }
+                    Some((lookup, Arc::clone(database)))
+                }
+                (Some(_), None) => {
+                    errors.0.push(format!(
+                        "handlers.{name}.lookup: there is no configured input_database"
+                    ));
+                    None
+                }
+                (None, _) => None,
+            };
+            handlers.insert(
+                name,
+                Handler {
+                    path,
+                    program: hash,
+                    function,
+                    inputs,
+                    constants,
+                    output_webhook,
+                    #[cfg(feature = "database")]
+                    lookup,
+                },
+            );
+        }
+        if config.output_webhook_token.is_some()
+            && !handlers.values().any(|h| h.output_webhook.is_some())
+        {
+            errors
+                .0
+                .push("output_webhook_token: there is no configured output_webhook".to_string());
+        }
+        // requests are dispatched by program and function, which must thus be unambiguous:
+        let mut names: Vec<_> = handlers.keys().collect();
+        names.sort();
+        let mut dispatch = HashMap::new();
+        for name in names {
+            let handler = &handlers[name];
+            if let Some(other) = dispatch.insert((&handler.program, &handler.function), name) {
+                errors.0.push(format!(
+                    "handlers.{name}: {} is already handled by handlers.{other} for the same program",
+                    handler.function
+                ));
+            }
+        }
+        if errors.0.is_empty() {
+            Ok(Self {
+                handlers,
+                programs,
+                webhook,
+                cache,
+                prewarm: config.prewarm,
+                stickiness,
+                max_and_gates: config.max_and_gates,
+                compile_workers: CompileWorkers::new(
+                    CompileLimits {
+                        cpu_secs: config.compile_cpu_secs,
+                        memory_bytes: config.compile_memory_bytes,
+                    },
+                    config.max_compile_workers,
+                ),
+            })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+const PROGRAM_FILE: &str = "program.garble.rs";
+
+/// Reads and type-checks the program of the configured handlers.
+fn load_program(path: &Path, errors: &mut ConfigErrors) -> Option<(String, TypedProgram)> {
+    let source_code = match read_to_string(path) {
+        Ok(source_code) => source_code.trim().to_string(),
+        Err(e) => {
+            errors.0.push(format!(
+                "could not read `{}`, which is required for configured handlers: {e}",
+                path.display()
+            ));
+            return None;
+        }
+    };
+    match check_program(&source_code) {
+        Ok(program) => Some((source_code, program)),
+        Err(e) => {
+            errors
+                .0
+                .push(format!("`{}` is not a valid program:\n{e}", path.display()));
+            None
+        }
+    }
+}
+
+/// Reads the configuration from `Tandem.json`, `Tandem.toml` and `TANDEM_*` env vars.
+fn figment() -> Figment {
     let default = HashMap::<ProgramFilePath, HashMap<PlaintextMetadata, OwnInput>>::new();
-    let config: HandlerConfig = Figment::from(("handlers", default))
+    Figment::from(("handlers", default))
         .merge(Json::file("Tandem.json"))
         .merge(Toml::file("Tandem.toml"))
         .merge(Env::prefixed("TANDEM_"))
-        .extract()
-        .unwrap();
+}
+
+fn main() {
+    // workers are dispatched before the runtime of the server exists, which would otherwise count
+    // towards their limits:
+    let mut args = env::args().skip(1);
+    if args.next().as_deref() == Some(COMPILE_WORKER_ARG) {
+        run_compile_worker(args);
+    }
+    let _ = rocket::execute(rocket().launch());
+}
 
-    let mut request_headers = HashMap::new();
+fn rocket() -> Rocket<Build> {
+    println!(
+        "Starting server in {}...",
+        env::current_dir().unwrap().display()
+    );
 
-    // fly.io specific logic to allow reconnecting to the same instance:
-    set_fly_instance_id(&mut request_headers);
+    let config = AppConfig::load(figment()).unwrap_or_else(|errors| {
+        eprint!("{errors}");
+        std::process::exit(1)
+    });
 
-    if config.handlers.is_empty() {
-        println!("No configured handlers, starting simple echo server instead...");
-        let handler = move |r: MpcRequest| -> Result<MpcSession, String> {
-            let prg = check_program(&r.program)?;
-            let circuit = compile_program(&prg, &r.function)?;
-            let input = serialize_input(
-                Role::Contributor,
-                &prg,
-                &circuit.fn_def,
-                &r.plaintext_metadata,
-            )?;
-            Ok(MpcSession {
-                circuit: circuit.gates,
-                input_from_server: input,
-                request_headers: request_headers.clone(),
-            })
-        };
-        build(Box::new(handler))
-    } else {
+    let cache = config.cache;
+    let max_and_gates = config.max_and_gates;
+    let compile_workers = config.compile_workers;
+    let mut server_config = ServerConfig::default();
+    if let Some(stickiness) = config.stickiness {
+        server_config = server_config.with_stickiness_provider(stickiness);
+    }
+
+    if !config.handlers.is_empty() {
         println!("Starting server based on configured handlers...");
-        let path = Path::new("program.garble.rs");
-        let source_code =
-            read_to_string(&path).unwrap_or_else(|_| panic!("could not read file {path:?}"));
-        let source_code = source_code.trim().to_string();
-        let program = check_program(&source_code)
-            .unwrap_or_else(|e| panic!("{path:?} is not a valid program:\n{e}"));
-        let mut handlers_with_circuit = HashMap::with_capacity(config.handlers.capacity());
-        for (fn_name, handlers) in config.handlers {
-            let circuit = compile_program(&program, &fn_name)
-                .unwrap_or_else(|e| panic!("{fn_name} in {path:?} cannot be compiled:\n{e}"));
-            let mut inputs = HashMap::with_capacity(handlers.len());
-            for (metadata, input) in handlers {
-                let input = serialize_input(Role::Contributor, &program, &circuit.fn_def, &input)
-                    .unwrap_or_else(|e| panic!("Could not parse literal of handler {path:?}, {fn_name}, \"{metadata}\":\n{e}"));
-                inputs.insert(metadata, input);
-            }
-            handlers_with_circuit.insert(fn_name, (circuit.gates, inputs));
-        }
+        let handlers = Arc::new(LazyHandlers::new(
+            config.programs,
+            cache,
+            config.handlers,
+            config.prewarm,
+        ));
+        handlers.spawn_prewarm();
+        let current = Arc::new(RwLock::new(handlers));
+        #[cfg(feature = "hot-reload")]
+        watch_config(Arc::clone(&current));
+        let readiness = {
+            let current = Arc::clone(&current);
+            Box::new(move || current.read().unwrap().readiness())
+        };
+        let catalog = {
+            let current = Arc::clone(&current);
+            Box::new(move || current.read().unwrap().catalog())
+        };
         let handler = move |r: MpcRequest| -> Result<MpcSession, String> {
+            // sessions keep their circuit and input, even if the handlers are reloaded meanwhile:
+            let handlers = Arc::clone(&*current.read().unwrap());
+            if r.circuit.is_some() {
+                return Err("raw circuits are not supported by configured handlers".to_string());
+            }
             let hash_of_source_code = blake3::hash(r.program.trim().as_bytes());
-            let server_program = source_code.chars();
-            let client_program = r.program.chars();
-            let mut differences = zip(client_program, server_program);
-            let mismatch_index = differences.position(|(a, b)| a != b);
-
-            if let Some(mismatch_index) = mismatch_index {
-                fn extract_snippet(code: &str, index: usize) -> String {
-                    let snippet: String = code.chars().skip(index).take(10).collect();
-                    let snippet = snippet.replace('\\', "\\\\").replace('\n', "\\n");
-                    format!("'{snippet}...'")
-                }
-
-                let client = extract_snippet(&r.program, mismatch_index);
-                let server = extract_snippet(&source_code, mismatch_index);
-
-                return Err(format!(
-                    "Programs differ at character {mismatch_index}: {client}, {server}"
-                ));
+            let mut program_hash = hash_of_source_code.to_string();
+            let is_equivalent = !handlers.programs.contains_key(&program_hash);
+            if is_equivalent {
+                program_hash = handlers.find_program(&r.program)?;
             }
 
-            if let Some((circuit, handlers)) = handlers_with_circuit.get(&r.function) {
-                if let Some(input) = handlers.get(&r.plaintext_metadata) {
+            if let Some(compiled) = handlers.get(&program_hash, &r.function) {
+                let compiled = compiled?;
+                let input = match compiled.inputs.get(&r.plaintext_metadata) {
+                    Some(input) => Some(input.clone()),
+                    #[cfg(feature = "database")]
+                    None if compiled.lookup.is_some() => {
+                        let (_, program) = &handlers.programs[&program_hash];
+                        compiled.look_up(program, &r.plaintext_metadata)?
+                    }
+                    None => None,
+                };
+                if let Some(input) = input {
+                    let circuit = if is_equivalent {
+                        handlers.compile_equivalent(&r.program, &r.function)?
+                    } else {
+                        compiled.circuit.clone()
+                    };
                     Ok(MpcSession {
-                        circuit: circuit.clone(),
-                        input_from_server: input.clone(),
+                        circuit,
+                        input_from_server: input,
                         request_headers: HashMap::new(),
+                        on_output: compiled.on_output.clone(),
                     })
                 } else {
                     Err(format!(
@@ -123,22 +563,1428 @@ fn rocket() -> _ {
                     ))
             }
         };
-        build(Box::new(handler))
+        let config = server_config
+            .with_readiness(readiness)
+            .with_catalog(catalog);
+        with_compiler_version(build_with_config(Box::new(handler), config))
+    } else if let Some(webhook) = config.webhook {
+        println!("Starting server based on input webhook {}...", webhook.url);
+        let handler = move |r: MpcRequest| -> Result<MpcSession, String> {
+            if r.circuit.is_some() {
+                return Err("raw circuits are not supported by the input webhook".to_string());
+            }
+            let program_hash = blake3::hash(r.program.trim().as_bytes()).to_string();
+            let input = webhook.input(&r, &program_hash)?;
+            let prg = check_program(&r.program)?;
+            let circuit = compile(cache.as_ref(), &r.program, &prg, &r.function)?;
+            let input = serialize_input(Role::Contributor, &prg, &circuit.fn_def, &input)
+                .map_err(|e| format!("The input webhook returned an invalid literal:\n{e}"))?;
+            Ok(MpcSession {
+                circuit: circuit.gates.into_inner(),
+                input_from_server: input,
+                request_headers: HashMap::new(),
+                on_output: None,
+            })
+        };
+        with_compiler_version(build_with_config(Box::new(handler), server_config))
+    } else {
+        println!("No configured handlers, starting simple echo server instead...");
+        let handler = move |r: MpcRequest| -> Result<MpcSession, String> {
+            if let Some(circuit) = r.circuit {
+                check_and_gates(&circuit, max_and_gates)?;
+                let input = raw_circuit_input(&circuit, &r.plaintext_metadata)?;
+                return Ok(MpcSession {
+                    circuit,
+                    input_from_server: input,
+                    request_headers: HashMap::new(),
+                    on_output: None,
+                });
+            }
+            let (circuit, input) = compile_workers.compile(
+                cache.as_ref(),
+                &r.program,
+                &r.function,
+                &r.plaintext_metadata,
+            )?;
+            check_and_gates(&circuit.gates, max_and_gates)?;
+            Ok(MpcSession {
+                circuit: circuit.gates.into_inner(),
+                input_from_server: input,
+                request_headers: HashMap::new(),
+                on_output: None,
+            })
+        };
+        with_compiler_version(build_with_config(Box::new(handler), server_config))
+    }
+}
+
+/// The circuit of a configured function, together with the inputs of its handlers.
+struct CompiledHandlers {
+    circuit: Circuit,
+    inputs: HashMap<PlaintextMetadata, Vec<bool>>,
+    /// Sends the outputs to the output webhook of the handler, if configured.
+    on_output: Option<OutputHandler>,
+    #[cfg(feature = "database")]
+    lookup: Option<(InputLookup, Arc<InputDatabase>)>,
+    #[cfg(feature = "database")]
+    fn_def: TypedFnDef,
+    #[cfg(feature = "database")]
+    constants: HashMap<ConstantName, String>,
+}
+
+#[cfg(feature = "database")]
+impl CompiledHandlers {
+    /// Looks up the input for the metadata in the input database, if it exists.
+    fn look_up(&self, program: &TypedProgram, metadata: &str) -> Result<Option<Vec<bool>>, String> {
+        let (lookup, database) = match &self.lookup {
+            Some(lookup) => lookup,
+            None => return Ok(None),
+        };
+        match database.look_up(lookup, metadata)? {
+            Some(literal) => {
+                serialize_input_with_constants(program, &self.fn_def, &literal, &self.constants)
+                    .map(Some)
+                    .map_err(|e| format!("The input database returned an invalid literal:\n{e}"))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+enum Compilation {
+    Pending,
+    Compiled(Arc<CompiledHandlers>),
+    Failed(String),
+}
+
+/// The configured handlers, whose circuits are compiled when they are first used or prewarmed.
+struct LazyHandlers {
+    programs: HashMap<ProgramHash, (String, TypedProgram)>,
+    cache: Option<CircuitCache>,
+    handlers: HashMap<HandlerName, (Handler, Mutex<Compilation>)>,
+    /// The name of the handler of each function, by the hash of its program.
+    dispatch: HashMap<(ProgramHash, ProgramFnName), HandlerName>,
+    /// The hash of each program, by the hash of its tokens (see [`normalized_program_hash`]).
+    normalized: HashMap<blake3::Hash, ProgramHash>,
+    prewarm: Vec<HandlerName>,
+}
+
+impl LazyHandlers {
+    fn new(
+        programs: HashMap<ProgramHash, (String, TypedProgram)>,
+        cache: Option<CircuitCache>,
+        handlers: HashMap<HandlerName, Handler>,
+        prewarm: Vec<HandlerName>,
+    ) -> Self {
+        let dispatch = handlers
+            .iter()
+            .map(|(name, h)| ((h.program.clone(), h.function.clone()), name.clone()))
+            .collect();
+        let handlers = handlers
+            .into_iter()
+            .map(|(name, handler)| (name, (handler, Mutex::new(Compilation::Pending))))
+            .collect();
+        let normalized = programs
+            .iter()
+            .filter_map(|(hash, (source_code, _))| {
+                let normalized = normalized_program_hash(source_code).ok()?;
+                Some((normalized, hash.clone()))
+            })
+            .collect();
+        Self {
+            programs,
+            cache,
+            handlers,
+            dispatch,
+            normalized,
+            prewarm,
+        }
+    }
+
+    /// Returns the hash of the configured program that only differs from the source code in
+    /// whitespace and comments.
+    ///
+    /// If there is no such program, but only a single configured program, the client most likely
+    /// uses an outdated version of it, so the error reports where both programs differ.
+    fn find_program(&self, source_code: &str) -> Result<ProgramHash, String> {
+        if let Some(hash) = self.normalized.get(&normalized_program_hash(source_code)?) {
+            return Ok(hash.clone());
+        }
+        if let [(server_program, _)] = self.programs.values().collect::<Vec<_>>()[..] {
+            if let Some(difference) = diff_programs(source_code, server_program)? {
+                return Err(format!(
+                    "Programs differ: found {} in the client's program, but {} in the server's program",
+                    difference.left, difference.right
+                ));
+            }
+        }
+        Err(format!(
+            "could not find a configured program with hash {}:\n{source_code}",
+            blake3::hash(source_code.trim().as_bytes())
+        ))
+    }
+
+    /// Compiles the function of a program that only differs from a configured program in
+    /// whitespace and comments, as the source locations of panics are compiled into the circuit.
+    fn compile_equivalent(&self, source_code: &str, fn_name: &str) -> Result<Circuit, String> {
+        let program = check_program(source_code)?;
+        let circuit = compile(self.cache.as_ref(), source_code, &program, fn_name)?;
+        Ok(circuit.gates.into_inner())
+    }
+
+    /// Returns the compiled handler of the function in the program with the specified hash, or
+    /// `None` if there is no handler for the function.
+    fn get(&self, program: &str, fn_name: &str) -> Option<Result<Arc<CompiledHandlers>, String>> {
+        let name = self
+            .dispatch
+            .get(&(program.to_string(), fn_name.to_string()))?;
+        self.get_by_name(name)
+    }
+
+    /// Returns the compiled handler with the specified name, compiling it first if necessary, or
+    /// `None` if there is no such handler.
+    ///
+    /// Concurrent requests for a handler that is being compiled wait for the compilation.
+    fn get_by_name(&self, name: &str) -> Option<Result<Arc<CompiledHandlers>, String>> {
+        let (handler, compilation) = self.handlers.get(name)?;
+        let mut compilation = compilation.lock().unwrap();
+        if let Compilation::Pending = *compilation {
+            *compilation = match self.compile(name, handler) {
+                Ok(compiled) => Compilation::Compiled(Arc::new(compiled)),
+                Err(e) => {
+                    eprintln!("{e}");
+                    Compilation::Failed(e)
+                }
+            };
+        }
+        Some(match &*compilation {
+            Compilation::Compiled(compiled) => Ok(Arc::clone(compiled)),
+            Compilation::Failed(e) => Err(e.clone()),
+            Compilation::Pending => unreachable!("the compilation has just been attempted"),
+        })
+    }
+
+    fn compile(&self, name: &str, handler: &Handler) -> Result<CompiledHandlers, String> {
+        let (source_code, program) = &self.programs[&handler.program];
+        let fn_name = &handler.function;
+        let path = &handler.path;
+        let circuit = compile(self.cache.as_ref(), source_code, program, fn_name)
+            .map_err(|e| format!("{fn_name} in `{path}` cannot be compiled:\n{e}"))?;
+        let mut inputs = HashMap::with_capacity(handler.inputs.len());
+        for (metadata, input) in &handler.inputs {
+            let input =
+                serialize_input_with_constants(program, &circuit.fn_def, input, &handler.constants)
+                    .map_err(|e| {
+                        format!("Could not parse literal of handler {name}, \"{metadata}\":\n{e}")
+                    })?;
+            inputs.insert(metadata.clone(), input);
+        }
+        let on_output = handler.output_webhook.as_ref().map(|webhook| {
+            let webhook = Arc::clone(webhook);
+            let (program, fn_def) = (program.clone(), circuit.fn_def.clone());
+            let handler = name.to_string();
+            OutputHandler::new(move |output| {
+                let literal = match deserialize_output(&program, &fn_def, &output.output) {
+                    Ok(literal) => literal.to_string(),
+                    Err(e) => return eprintln!("Could not deserialize output of {handler}: {e}"),
+                };
+                let (webhook, handler) = (Arc::clone(&webhook), handler.clone());
+                // the session must not wait for the webhook:
+                std::thread::spawn(move || {
+                    if let Err(e) = webhook.send(&handler, &output, &literal) {
+                        eprintln!("{e}");
+                    }
+                });
+            })
+        });
+        Ok(CompiledHandlers {
+            circuit: circuit.gates.into_inner(),
+            inputs,
+            on_output,
+            #[cfg(feature = "database")]
+            lookup: handler.lookup.clone(),
+            #[cfg(feature = "database")]
+            fn_def: circuit.fn_def,
+            #[cfg(feature = "database")]
+            constants: handler.constants.clone(),
+        })
+    }
+
+    /// Compiles the handlers configured to be prewarmed in the background, if there are any.
+    fn spawn_prewarm(self: &Arc<Self>) {
+        if !self.prewarm.is_empty() {
+            let handlers = Arc::clone(self);
+            std::thread::spawn(move || handlers.prewarm());
+        }
+    }
+
+    /// Compiles the handlers configured to be prewarmed, in order.
+    fn prewarm(&self) {
+        for name in &self.prewarm {
+            let start = Instant::now();
+            if let Some(Ok(_)) = self.get_by_name(name) {
+                println!("Compiled {name} in {:.1?}", start.elapsed());
+            }
+        }
+    }
+
+    /// The server is ready once all prewarmed handlers have been compiled, unless any handler
+    /// failed to compile.
+    fn readiness(&self) -> Readiness {
+        let mut components = BTreeMap::new();
+        let mut ready = true;
+        for (name, (_, compilation)) in &self.handlers {
+            let is_prewarmed = self.prewarm.contains(name);
+            let (status, blocks_readiness) = match compilation.try_lock().as_deref() {
+                Ok(Compilation::Compiled(_)) => ("compiled".to_string(), false),
+                Ok(Compilation::Failed(e)) => (format!("failed: {e}"), true),
+                Ok(Compilation::Pending) => ("pending".to_string(), is_prewarmed),
+                Err(_) => ("compiling".to_string(), is_prewarmed),
+            };
+            ready &= !blocks_readiness;
+            components.insert(format!("circuit {name}"), status);
+        }
+        Readiness { ready, components }
+    }
+
+    /// Lists the function of each handler, as published via `GET /programs`.
+    fn catalog(&self) -> Vec<PublishedFunction> {
+        let mut catalog: Vec<PublishedFunction> = self
+            .handlers
+            .values()
+            .map(|(handler, _)| {
+                let (_, program) = &self.programs[&handler.program];
+                PublishedFunction {
+                    function: handler.function.clone(),
+                    signature: program.fn_defs.get(&handler.function).map(signature),
+                    program_hash: Some(handler.program.clone()),
+                }
+            })
+            .collect();
+        catalog.sort_by(|a, b| (&a.function, &a.program_hash).cmp(&(&b.function, &b.program_hash)));
+        catalog
+    }
+}
+
+/// Renders the signature of the function, e.g. `fn main(x: u8, y: u8) -> u8`.
+fn signature(fn_def: &TypedFnDef) -> String {
+    let params: Vec<String> = fn_def
+        .params
+        .iter()
+        .map(|param| format!("{}: {}", param.name, param.ty))
+        .collect();
+    format!(
+        "fn {}({}) -> {}",
+        fn_def.identifier,
+        params.join(", "),
+        fn_def.ty
+    )
+}
+
+/// Watches the configuration and the configured programs, replacing all handlers at once whenever
+/// any of the files change.
+///
+/// Sessions that have already started finish with the circuit and input of the old handlers. A
+/// changed configuration that is invalid (or has no handlers) is reported and ignored.
+#[cfg(feature = "hot-reload")]
+fn watch_config(current: Arc<RwLock<Arc<LazyHandlers>>>) {
+    use notify::{RecursiveMode, Watcher};
+    use std::{collections::HashSet, sync::mpsc, time::Duration};
+
+    fn is_config_file(path: &Path) -> bool {
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => {
+                name == "Tandem.toml" || name == "Tandem.json" || name.ends_with(".garble.rs")
+            }
+            None => false,
+        }
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(tx) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            eprintln!("Could not watch the configuration, hot reload is disabled: {e}");
+            return;
+        }
+    };
+    std::thread::spawn(move || {
+        let mut watched_dirs = HashSet::new();
+        loop {
+            // programs can be stored anywhere, so their directories are watched as well:
+            let handlers = Arc::clone(&*current.read().unwrap());
+            let dirs = handlers.handlers.values().map(|(handler, _)| {
+                match Path::new(&handler.path).parent() {
+                    Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+                    _ => PathBuf::from("."),
+                }
+            });
+            for dir in dirs.chain([PathBuf::from(".")]) {
+                if !watched_dirs.contains(&dir) {
+                    match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+                        Ok(()) => {
+                            watched_dirs.insert(dir);
+                        }
+                        Err(e) => eprintln!("Could not watch `{}`: {e}", dir.display()),
+                    }
+                }
+            }
+
+            loop {
+                match rx.recv() {
+                    Ok(Ok(event)) if !event.kind.is_access() => {
+                        if event.paths.iter().any(|path| is_config_file(path)) {
+                            break;
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(_) => return,
+                }
+            }
+            // editors often write files in several steps, so wait until the changes settle:
+            while rx.recv_timeout(Duration::from_millis(200)).is_ok() {}
+
+            println!("Configuration changed, reloading handlers...");
+            match AppConfig::load(figment()) {
+                Ok(config) if config.handlers.is_empty() => {
+                    eprintln!("The changed configuration has no handlers, keeping the old handlers")
+                }
+                Ok(config) => {
+                    let handlers = Arc::new(LazyHandlers::new(
+                        config.programs,
+                        config.cache,
+                        config.handlers,
+                        config.prewarm,
+                    ));
+                    handlers.spawn_prewarm();
+                    *current.write().unwrap() = handlers;
+                    println!("Reloaded handlers");
+                }
+                Err(errors) => eprint!("Keeping the old handlers. {errors}"),
+            }
+        }
+    });
+}
+
+/// An operator-provided HTTP endpoint, which chooses the contributor's input of each session.
+///
+/// The webhook receives a JSON object with the `plaintext_metadata`, `function` and `program_hash`
+/// of the request and responds with a JSON object containing the `input` literal. Any other status
+/// than `2xx` rejects the session, with the response body as the error message for the client.
+struct InputWebhook {
+    url: Url,
+    token: Option<String>,
+    agent: ureq::Agent,
+}
+
+#[derive(Debug, Serialize)]
+struct InputWebhookRequest<'a> {
+    plaintext_metadata: &'a serde_json::Value,
+    function: &'a str,
+    program_hash: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct InputWebhookResponse {
+    input: OwnInput,
+}
+
+impl InputWebhook {
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    fn new(url: Url, token: Option<String>) -> Self {
+        let agent = ureq::AgentBuilder::new().timeout(Self::TIMEOUT).build();
+        Self { url, token, agent }
+    }
+
+    /// Asks the webhook for the contributor's input literal.
+    fn input(&self, r: &MpcRequest, program_hash: &str) -> Result<OwnInput, String> {
+        let mut request = self.agent.post(self.url.as_str());
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {token}"));
+        }
+        let body = InputWebhookRequest {
+            plaintext_metadata: &r.plaintext_metadata_json,
+            function: &r.function,
+            program_hash,
+        };
+        match request.send_json(body) {
+            Ok(response) => match response.into_json::<InputWebhookResponse>() {
+                Ok(response) => Ok(response.input),
+                Err(e) => {
+                    eprintln!("Invalid response of the input webhook: {e}");
+                    Err("The input webhook returned an invalid response".to_string())
+                }
+            },
+            Err(ureq::Error::Status(status, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                Err(format!(
+                    "The input webhook rejected the request ({status}): {body}"
+                ))
+            }
+            Err(e) => {
+                // the details are only logged, as they contain the URL of the webhook:
+                eprintln!("Could not reach the input webhook: {e}");
+                Err("The input webhook could not be reached".to_string())
+            }
+        }
+    }
+}
+
+/// An operator-provided HTTP endpoint, which receives the output of every session of a handler.
+///
+/// The client needs to agree to disclose its output, sessions of clients that do not are
+/// rejected. The webhook receives a JSON object with the `handler`, `engine_id`,
+/// `plaintext_metadata`, `function`, `program_hash` and the `output` literal of the session. The
+/// session does not wait for the webhook, failed requests are only logged.
+struct OutputWebhook {
+    url: Url,
+    token: Option<String>,
+    agent: ureq::Agent,
+}
+
+impl fmt::Debug for OutputWebhook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // the token is never logged:
+        f.debug_struct("OutputWebhook")
+            .field("url", &self.url.as_str())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct OutputWebhookRequest<'a> {
+    handler: &'a str,
+    engine_id: &'a str,
+    plaintext_metadata: &'a str,
+    function: &'a str,
+    program_hash: &'a str,
+    output: &'a str,
+}
+
+impl OutputWebhook {
+    fn new(url: Url, token: Option<String>) -> Self {
+        let agent = ureq::AgentBuilder::new()
+            .timeout(InputWebhook::TIMEOUT)
+            .build();
+        Self { url, token, agent }
+    }
+
+    /// Sends the output literal of a session to the webhook.
+    fn send(&self, handler: &str, output: &MpcOutput, literal: &str) -> Result<(), String> {
+        let mut request = self.agent.post(self.url.as_str());
+        if let Some(token) = &self.token {
+            request = request.set("Authorization", &format!("Bearer {token}"));
+        }
+        let body = OutputWebhookRequest {
+            handler,
+            engine_id: &output.engine_id,
+            plaintext_metadata: &output.plaintext_metadata,
+            function: &output.function,
+            program_hash: &output.program_hash,
+            output: literal,
+        };
+        match request.send_json(body) {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, response)) => {
+                let body = response.into_string().unwrap_or_default();
+                Err(format!(
+                    "The output webhook of {handler} rejected the output ({status}): {body}"
+                ))
+            }
+            Err(e) => Err(format!(
+                "Could not reach the output webhook of {handler}: {e}"
+            )),
+        }
+    }
+}
+
+/// A query that looks up the contributor's input in the `input_database`.
+///
+/// The query is run with the plaintext metadata as its only parameter (using the placeholder
+/// syntax of the database, such as `$1`). The first returned row is rendered into the template, by
+/// replacing each `${column}` with the value of the column.
+#[cfg(feature = "database")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InputLookup {
+    query: String,
+    template: String,
+}
+
+#[cfg(feature = "database")]
+impl InputLookup {
+    /// Replaces each `${column}` of the template with the value returned for the column.
+    fn render(&self, value: impl Fn(&str) -> Result<String, String>) -> Result<OwnInput, String> {
+        let mut literal = String::new();
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find("${") {
+            literal.push_str(&rest[..start]);
+            let column = &rest[start + 2..];
+            let end = column
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in template `{}`", self.template))?;
+            literal.push_str(&value(&column[..end])?);
+            rest = &column[end + 1..];
+        }
+        literal.push_str(rest);
+        Ok(literal)
+    }
+}
+
+/// A SQL database, in which handlers look up the inputs that are not part of the configuration.
+#[cfg(feature = "database")]
+#[derive(Debug)]
+struct InputDatabase {
+    url: String,
+    /// Connected on first use, from within the runtime of the server.
+    pool: rocket::tokio::sync::OnceCell<sqlx::AnyPool>,
+}
+
+#[cfg(feature = "database")]
+impl InputDatabase {
+    /// Renders the first row returned by the query of the lookup, or returns `None` if there are
+    /// no rows for the metadata.
+    fn look_up(&self, lookup: &InputLookup, metadata: &str) -> Result<Option<OwnInput>, String> {
+        use rocket::tokio::{runtime::Handle, task::block_in_place};
+        use sqlx::Row;
+
+        // handlers are synchronous, but called from within the async runtime of the server:
+        block_in_place(|| {
+            Handle::current().block_on(async {
+                let pool = self
+                    .pool
+                    .get_or_try_init(|| async {
+                        sqlx::any::install_default_drivers();
+                        sqlx::any::AnyPoolOptions::new().connect(&self.url).await
+                    })
+                    .await
+                    .map_err(|e| {
+                        // the details are only logged, as they might contain credentials:
+                        eprintln!("Could not connect to the input database: {e}");
+                        "The input database could not be reached".to_string()
+                    })?;
+                let row = sqlx::query(&lookup.query)
+                    .bind(metadata)
+                    .fetch_optional(pool)
+                    .await
+                    .map_err(|e| {
+                        eprintln!("Could not look up the input for '{metadata}': {e}");
+                        "The input could not be looked up in the input database".to_string()
+                    })?;
+                let row = match row {
+                    Some(row) => row,
+                    None => return Ok(None),
+                };
+                lookup
+                    .render(|column| {
+                        if let Ok(value) = row.try_get::<String, _>(column) {
+                            Ok(value)
+                        } else if let Ok(value) = row.try_get::<i64, _>(column) {
+                            Ok(value.to_string())
+                        } else if let Ok(value) = row.try_get::<f64, _>(column) {
+                            Ok(value.to_string())
+                        } else if let Ok(value) = row.try_get::<bool, _>(column) {
+                            Ok(value.to_string())
+                        } else {
+                            Err(format!("The input database returned no string, number or boolean for `{column}`"))
+                        }
+                    })
+                    .map(Some)
+            })
+        })
+    }
+}
+
+/// Parses the echoed input of a raw circuit, sent as the metadata in the form of a string of `0`
+/// and `1` bits, one for each of the contributor's input bits.
+fn raw_circuit_input(circuit: &Circuit, metadata: &str) -> Result<Vec<bool>, String> {
+    let input = metadata
+        .chars()
+        .map(|bit| match bit {
+            '0' => Ok(false),
+            '1' => Ok(true),
+            _ => Err(format!("'{metadata}' is not a string of 0 and 1 bits")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if input.len() != circuit.contrib_inputs() {
+        return Err(format!(
+            "the circuit expects {} input bits, but '{metadata}' has {}",
+            circuit.contrib_inputs(),
+            input.len()
+        ));
+    }
+    Ok(input)
+}
+
+/// Rejects circuits with more AND gates than the server accepts, see `max_and_gates`.
+fn check_and_gates(circuit: &Circuit, max_and_gates: Option<usize>) -> Result<(), String> {
+    match max_and_gates {
+        Some(max) if circuit.and_gates() > max => Err(format!(
+            "the circuit has {} AND gates, but the server accepts at most {max} AND gates",
+            circuit.and_gates()
+        )),
+        _ => Ok(()),
+    }
+}
+
+const COMPILE_WORKER_ARG: &str = "--compile-worker";
+const COMPILE_WORKER_REJECTED: i32 = 2;
+
+/// Compiles the programs sent by clients of the echo server in separate processes, which are
+/// limited in their CPU time and memory (on Unix) and killed if they do not finish in time, so that
+/// adversarial programs cannot exhaust the resources of the server.
+struct CompileWorkers {
+    limits: CompileLimits,
+    /// Permits to run a worker, bounding the number of workers (and thus their total memory).
+    permits: Arc<Semaphore>,
+}
+
+/// The limits of a single worker, passed as arguments and applied by the worker itself before it
+/// reads the program.
+#[derive(Debug, Clone, Copy)]
+struct CompileLimits {
+    cpu_secs: u64,
+    memory_bytes: u64,
+}
+
+/// The program to compile, sent by the server to the worker on stdin.
+#[derive(Debug, Serialize, Deserialize)]
+struct CompileRequest {
+    program: String,
+    function: String,
+    metadata: String,
+    /// The function definition of the circuit in the cache of the server, in which case the worker
+    /// only parses the input. Workers never access the cache themselves.
+    cached: Option<TypedFnDef>,
+}
+
+impl CompileWorkers {
+    fn new(limits: CompileLimits, max_workers: Option<usize>) -> Self {
+        let cores = thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            limits,
+            permits: Arc::new(Semaphore::new(max_workers.unwrap_or(cores).max(1))),
+        }
+    }
+
+    /// Type-checks and compiles the program in a worker process (unless its circuit is cached),
+    /// returning the circuit and the input of the contributor, parsed from the metadata.
+    ///
+    /// Must be called on a blocking thread of the runtime, which waits for the worker.
+    fn compile(
+        &self,
+        cache: Option<&CircuitCache>,
+        program: &str,
+        function: &str,
+        metadata: &str,
+    ) -> Result<(TypedCircuit, Vec<bool>), String> {
+        let cached = cache.and_then(|cache| cache.get(program, function));
+        let request = CompileRequest {
+            program: program.to_string(),
+            function: function.to_string(),
+            metadata: metadata.to_string(),
+            cached: cached.as_ref().map(|circuit| circuit.fn_def.clone()),
+        };
+        let request = serde_json::to_vec(&request).map_err(|e| e.to_string())?;
+        let output = Handle::current().block_on(self.run(request))?;
+        match (decode_compiled(&output), cached) {
+            (Some((input, None)), Some(circuit)) => Ok((circuit, input)),
+            (Some((input, Some(circuit))), None) => {
+                if let Some(cache) = cache {
+                    cache.insert(program, function, &circuit);
+                }
+                Ok((circuit, input))
+            }
+            _ => Err("compilation failed".to_string()),
+        }
+    }
+
+    async fn run(&self, request: Vec<u8>) -> Result<Vec<u8>, String> {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("the semaphore of the compile workers is never closed");
+        let exe = env::current_exe().map_err(|e| format!("could not start compilation: {e}"))?;
+        let mut child = Command::new(exe)
+            .arg(COMPILE_WORKER_ARG)
+            .arg(self.limits.cpu_secs.to_string())
+            .arg(self.limits.memory_bytes.to_string())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("could not start compilation: {e}"))?;
+        let stdin = child.stdin.take();
+        let compiled = async move {
+            if let Some(mut stdin) = stdin {
+                // a worker that exceeded its limits might not read its input anymore:
+                let _ = stdin.write_all(&request).await;
+            }
+            child.wait_with_output().await
+        };
+        // the CPU time limit does not apply to a worker that is waiting, the worker is killed when
+        // the timeout drops it:
+        let timeout = Duration::from_secs(self.limits.cpu_secs.saturating_mul(2));
+        match time::timeout(timeout, compiled).await {
+            Ok(Ok(output)) if output.status.success() => Ok(output.stdout),
+            Ok(Ok(output)) if output.status.code() == Some(COMPILE_WORKER_REJECTED) => {
+                Err(String::from_utf8_lossy(&output.stdout).into_owned())
+            }
+            _ => Err("compilation exceeded limits".to_string()),
+        }
+    }
+}
+
+impl CompileLimits {
+    #[cfg(unix)]
+    fn apply_limits(&self) -> io::Result<()> {
+        let cpu = libc::rlimit {
+            rlim_cur: self.cpu_secs as libc::rlim_t,
+            // the soft limit sends SIGXCPU, the hard limit SIGKILL:
+            rlim_max: self.cpu_secs.saturating_add(1) as libc::rlim_t,
+        };
+        let memory = libc::rlimit {
+            rlim_cur: self.memory_bytes as libc::rlim_t,
+            rlim_max: self.memory_bytes as libc::rlim_t,
+        };
+        for (resource, limit) in [(libc::RLIMIT_CPU, cpu), (libc::RLIMIT_AS, memory)] {
+            // SAFETY: `setrlimit` only reads the limit, which outlives the call.
+            if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    /// Only the timeout of the server applies to workers on other platforms.
+    #[cfg(not(unix))]
+    fn apply_limits(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs the worker started by [`CompileWorkers::compile`], which applies the limits passed as its
+/// `args`, compiles a single program and writes the result to stdout, then exits.
+fn run_compile_worker(mut args: impl Iterator<Item = String>) -> ! {
+    let mut limit = || args.next().and_then(|arg| arg.parse().ok());
+    let limits = match (limit(), limit()) {
+        (Some(cpu_secs), Some(memory_bytes)) => CompileLimits {
+            cpu_secs,
+            memory_bytes,
+        },
+        _ => process::exit(1),
+    };
+    if limits.apply_limits().is_err() {
+        process::exit(1);
+    }
+    let mut request = vec![];
+    let request: CompileRequest = match io::stdin().read_to_end(&mut request) {
+        Ok(_) => serde_json::from_slice(&request).unwrap_or_else(|_| process::exit(1)),
+        Err(_) => process::exit(1),
+    };
+    let compiled = check_program(&request.program).and_then(|prg| {
+        let (fn_def, circuit) = match request.cached {
+            Some(fn_def) => (fn_def, None),
+            None => {
+                let circuit = compile_program(&prg, &request.function)?;
+                (circuit.fn_def.clone(), Some(circuit))
+            }
+        };
+        let input = serialize_input(Role::Contributor, &prg, &fn_def, &request.metadata)?;
+        encode_compiled(&input, circuit.as_ref()).map_err(|e| e.to_string())
+    });
+    let (output, code) = match compiled {
+        Ok(bytes) => (bytes, 0),
+        Err(e) => (e.into_bytes(), COMPILE_WORKER_REJECTED),
+    };
+    let mut stdout = io::stdout().lock();
+    if stdout
+        .write_all(&output)
+        .and_then(|_| stdout.flush())
+        .is_err()
+    {
+        process::exit(1);
+    }
+    process::exit(code)
+}
+
+/// Encodes the result of the worker as the number of input bits (`u64`, little-endian), the input
+/// bits (one byte each) and the circuit (if it was compiled), see [`TypedCircuit::to_bytes`].
+fn encode_compiled(input: &[bool], circuit: Option<&TypedCircuit>) -> io::Result<Vec<u8>> {
+    let mut bytes = (input.len() as u64).to_le_bytes().to_vec();
+    bytes.extend(input.iter().map(|bit| *bit as u8));
+    if let Some(circuit) = circuit {
+        bytes.extend(circuit.to_bytes()?);
     }
+    Ok(bytes)
 }
 
-fn set_fly_instance_id(request_headers: &mut HashMap<String, String>) {
-    if let Ok(fly_alloc_id) = env::var("FLY_ALLOC_ID") {
-        let fly_instance_id = fly_alloc_id.split("-").collect::<Vec<_>>()[0].to_string();
-        request_headers.insert("fly-force-instance-id".to_string(), fly_instance_id);
+fn decode_compiled(bytes: &[u8]) -> Option<(Vec<bool>, Option<TypedCircuit>)> {
+    let mut input_len = [0; 8];
+    input_len.copy_from_slice(bytes.get(..8)?);
+    let input_end = usize::try_from(u64::from_le_bytes(input_len))
+        .ok()?
+        .checked_add(8)?;
+    let input = bytes.get(8..input_end)?.iter().map(|b| *b != 0).collect();
+    let circuit = match bytes.get(input_end..)? {
+        [] => None,
+        circuit => Some(TypedCircuit::from_bytes(circuit)?),
+    };
+    Some((input, circuit))
+}
+
+/// Reports the version of the compiler to clients whose circuits do not match the server's.
+fn with_compiler_version(rocket: Rocket<Build>) -> Rocket<Build> {
+    let compiler_version = format!("garble_lang {GARBLE_VERSION}");
+    let figment = rocket
+        .figment()
+        .clone()
+        .merge(("compiler_version", compiler_version));
+    rocket.configure(figment)
+}
+
+fn compile(
+    cache: Option<&CircuitCache>,
+    source_code: &str,
+    prg: &TypedProgram,
+    fn_name: &str,
+) -> Result<TypedCircuit, String> {
+    match cache {
+        Some(cache) => cache.compile(source_code, prg, fn_name),
+        None => compile_program(prg, fn_name),
+    }
+}
+
+/// The built-in [`StickinessProvider`]s, configured as the `stickiness` table with a `provider`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "provider", rename_all = "snake_case")]
+enum StickinessConfig {
+    /// fly.io's `fly-force-instance-id` header, with the instance id taken from the allocation id.
+    Fly {
+        /// Defaults to the `FLY_ALLOC_ID` of the instance.
+        #[serde(default)]
+        alloc_id: Option<String>,
+    },
+    /// Custom headers, whose values can refer to the id of the instance as `{instance_id}`.
+    Headers {
+        headers: HashMap<String, String>,
+        /// Defaults to the `HOSTNAME` of the instance.
+        #[serde(default)]
+        instance_id: Option<String>,
+    },
+    /// A cookie containing the id of the instance, signed using the `secret` so that load
+    /// balancers can reject cookies that were not issued by the servers.
+    ///
+    /// The cookie is sent as a `cookie` request header, which only native clients can send, as
+    /// browsers do not allow scripts to set it.
+    Cookie {
+        #[serde(default = "default_cookie_name")]
+        name: String,
+        secret: String,
+        /// Defaults to the `HOSTNAME` of the instance.
+        #[serde(default)]
+        instance_id: Option<String>,
+    },
+}
+
+fn default_cookie_name() -> String {
+    "tandem_instance".to_string()
+}
+
+impl StickinessConfig {
+    /// The provider for the current hosting environment, if the server does not configure one.
+    fn detect() -> Option<Self> {
+        env::var("FLY_ALLOC_ID")
+            .ok()
+            .map(|_| StickinessConfig::Fly { alloc_id: None })
+    }
+
+    fn provider(self) -> Result<Box<dyn StickinessProvider>, String> {
+        let instance_id = |configured: Option<String>| {
+            configured
+                .or_else(|| env::var("HOSTNAME").ok())
+                .filter(|id| !id.is_empty())
+                .ok_or_else(|| "instance_id is not set and there is no HOSTNAME".to_string())
+        };
+        let headers = match self {
+            StickinessConfig::Fly { alloc_id } => {
+                let alloc_id = alloc_id
+                    .or_else(|| env::var("FLY_ALLOC_ID").ok())
+                    .ok_or("FLY_ALLOC_ID is not set, the server is not on fly.io")?;
+                let instance_id = alloc_id.split('-').next().unwrap_or_default().to_string();
+                HashMap::from([("fly-force-instance-id".to_string(), instance_id)])
+            }
+            StickinessConfig::Headers {
+                headers,
+                instance_id: id,
+            } => {
+                let id = instance_id(id)?;
+                let mut rendered = HashMap::new();
+                for (name, template) in headers {
+                    let value = template.replace("{instance_id}", &id);
+                    if value.contains('{') {
+                        return Err(format!(
+                            "headers.{name}: `{template}` contains an unknown placeholder"
+                        ));
+                    }
+                    rendered.insert(name, value);
+                }
+                rendered
+            }
+            StickinessConfig::Cookie {
+                name,
+                secret,
+                instance_id: id,
+            } => {
+                if secret.is_empty() {
+                    return Err("secret must not be empty".to_string());
+                }
+                let id = instance_id(id)?;
+                let key = blake3::hash(secret.as_bytes());
+                let signature = blake3::keyed_hash(key.as_bytes(), id.as_bytes());
+                let cookie = format!("{name}={id}.{}", signature.to_hex());
+                HashMap::from([("cookie".to_string(), cookie)])
+            }
+        };
+        // the built-in providers only identify the instance, not the session:
+        Ok(Box::new(StaticHeaders(headers)))
     }
 }
 
 #[test]
+fn test_stickiness_providers() {
+    let request = MpcRequest {
+        plaintext_metadata: String::new(),
+        plaintext_metadata_json: serde_json::Value::Null,
+        program: String::new(),
+        function: "main".to_string(),
+        circuit: None,
+    };
+    let headers = |toml: &str| {
+        let config = format!("handlers = {{}}\n[stickiness]\n{toml}");
+        AppConfig::load(Figment::from(Toml::string(&config)))
+            .map(|config| config.stickiness.unwrap().request_headers(&request))
+            .map_err(|e| e.0)
+    };
+
+    let fly = "provider = 'fly'\nalloc_id = 'b996131a-5bae-215b-d0f1-2d75d1a8812b'";
+    let fly = headers(fly).unwrap();
+    assert_eq!(fly["fly-force-instance-id"], "b996131a");
+
+    let custom =
+        "provider = 'headers'\ninstance_id = 'i-1'\nheaders = { x-pool = 'a/{instance_id}' }";
+    assert_eq!(headers(custom).unwrap()["x-pool"], "a/i-1");
+    let unknown = "provider = 'headers'\ninstance_id = 'i-1'\nheaders = { x-pool = '{host}' }";
+    assert_eq!(
+        headers(unknown).unwrap_err(),
+        vec!["stickiness: headers.x-pool: `{host}` contains an unknown placeholder"]
+    );
+
+    let cookie = |secret: &str| {
+        headers(&format!(
+            "provider = 'cookie'\ninstance_id = 'i-1'\nsecret = '{secret}'"
+        ))
+    };
+    let signed = cookie("s3cret").unwrap()["cookie"].clone();
+    assert!(signed.starts_with("tandem_instance=i-1."));
+    assert_ne!(signed, cookie("other").unwrap()["cookie"]);
+    assert!(cookie("").is_err());
+}
+
+#[test]
+fn test_raw_circuit_input() {
+    use tandem::Gate;
+
+    let circuit = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::And(0, 2),
+        ],
+        vec![3],
+    );
+    assert_eq!(
+        raw_circuit_input(&circuit, "10").unwrap(),
+        vec![true, false]
+    );
+    assert!(raw_circuit_input(&circuit, "1").is_err());
+    assert!(raw_circuit_input(&circuit, "1x").is_err());
+}
+
+#[test]
+fn test_check_and_gates() {
+    use tandem::Gate;
+
+    let circuit = Circuit::new(
+        vec![
+            Gate::InContrib,
+            Gate::InEval,
+            Gate::And(0, 1),
+            Gate::And(1, 2),
+        ],
+        vec![3],
+    );
+    assert!(check_and_gates(&circuit, None).is_ok());
+    assert!(check_and_gates(&circuit, Some(2)).is_ok());
+    assert_eq!(
+        check_and_gates(&circuit, Some(1)),
+        Err("the circuit has 2 AND gates, but the server accepts at most 1 AND gates".to_string())
+    );
+}
+
+#[test]
+fn test_encode_compiled() {
+    let source_code = "pub fn main(x: u8, y: u8) -> u8 { x + y }";
+    let prg = check_program(source_code).unwrap();
+    let circuit = compile_program(&prg, "main").unwrap();
+    let input = serialize_input(Role::Contributor, &prg, &circuit.fn_def, "5u8").unwrap();
+    let bytes = encode_compiled(&input, Some(&circuit)).unwrap();
+    let (decoded_input, decoded) = decode_compiled(&bytes).unwrap();
+    let decoded = decoded.unwrap();
+    assert_eq!(decoded.gates.gates(), circuit.gates.gates());
+    assert_eq!(decoded.gates.output_gates(), circuit.gates.output_gates());
+    assert_eq!(decoded_input, input);
+    assert!(decode_compiled(&bytes[..bytes.len() - 1]).is_none());
+    assert!(decode_compiled(&bytes[..4]).is_none());
+
+    // cached circuits are not sent back by the worker:
+    let bytes = encode_compiled(&input, None).unwrap();
+    let (decoded_input, decoded) = decode_compiled(&bytes).unwrap();
+    assert_eq!(decoded_input, input);
+    assert!(decoded.is_none());
+}
+
+#[test]
+fn test_handlers_of_multiple_programs() {
+    let dir = env::temp_dir().join(format!("tandem_programs_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let add = "pub fn add(x: u8, y: u8) -> u8 {\n    x + y\n}";
+    let mul = "pub fn mul(x: u8, y: u8) -> u8 {\n    x * y\n}";
+    std::fs::write(dir.join("add.garble.rs"), format!("{add}\n")).unwrap();
+    std::fs::write(dir.join("mul.garble.rs"), mul).unwrap();
+
+    let handlers = |function: &str, other: &str| {
+        format!(
+            "[handlers.add]\nprogram = '{}'\nfunction = 'add'\ninputs = {{ _ = '2u8' }}\n\
+            [handlers.mul]\nprogram = '{}'\nfunction = '{function}'\ninputs = {{ _ = '3u8' }}\n",
+            dir.join("add.garble.rs").display(),
+            dir.join(other).display(),
+        )
+    };
+    let config = AppConfig::load(Figment::from(Toml::string(&handlers(
+        "mul",
+        "mul.garble.rs",
+    ))))
+    .unwrap();
+    assert_eq!(config.programs.len(), 2);
+    let handlers_by_program = LazyHandlers::new(config.programs, None, config.handlers, vec![]);
+    let add_hash = blake3::hash(add.as_bytes()).to_string();
+    let mul_hash = blake3::hash(mul.as_bytes()).to_string();
+    assert!(handlers_by_program.get(&add_hash, "add").unwrap().is_ok());
+    assert!(handlers_by_program.get(&mul_hash, "mul").unwrap().is_ok());
+    assert!(handlers_by_program.get(&mul_hash, "add").is_none());
+    assert_eq!(
+        handlers_by_program.catalog(),
+        vec![
+            PublishedFunction {
+                function: "add".to_string(),
+                signature: Some("fn add(x: u8, y: u8) -> u8".to_string()),
+                program_hash: Some(add_hash),
+            },
+            PublishedFunction {
+                function: "mul".to_string(),
+                signature: Some("fn mul(x: u8, y: u8) -> u8".to_string()),
+                program_hash: Some(mul_hash),
+            },
+        ]
+    );
+
+    let errors = AppConfig::load(Figment::from(Toml::string(&handlers(
+        "add",
+        "add.garble.rs",
+    ))))
+    .err()
+    .unwrap();
+    assert_eq!(
+        errors.0,
+        vec!["handlers.mul: add is already handled by handlers.add for the same program"]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_equivalent_programs() {
+    let dir = env::temp_dir().join(format!("tandem_equivalent_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let program = "pub fn div(x: u8, y: u8) -> u8 {\n    x / y\n}";
+    let path = dir.join("div.garble.rs");
+    std::fs::write(&path, program).unwrap();
+    let config = format!(
+        "[handlers.div]\nprogram = '{}'\nfunction = 'div'\ninputs = {{ _ = '6u8' }}\n",
+        path.display()
+    );
+    let config = AppConfig::load(Figment::from(Toml::string(&config))).unwrap();
+    let handlers = LazyHandlers::new(config.programs, None, config.handlers, vec![]);
+    let hash = blake3::hash(program.as_bytes()).to_string();
+
+    let reformatted = "// divides x by y\npub fn div(x: u8, y: u8) -> u8 { x / y }";
+    assert_eq!(handlers.find_program(reformatted).unwrap(), hash);
+    // the location of the division by zero is part of the circuit:
+    let circuit = handlers.compile_equivalent(reformatted, "div").unwrap();
+    let configured = &handlers.get(&hash, "div").unwrap().unwrap().circuit;
+    assert_ne!(circuit.blake3_hash(), configured.blake3_hash());
+    let circuit = handlers.compile_equivalent(program, "div").unwrap();
+    assert_eq!(circuit.blake3_hash(), configured.blake3_hash());
+
+    let outdated = "pub fn div(x: u8, y: u8) -> u8 {\n    x % y\n}";
+    assert_eq!(
+        handlers.find_program(outdated).unwrap_err(),
+        "Programs differ: found '%' at 2:7 in the client's program, but '/' at 2:7 in the server's program"
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_handler_constants() {
+    let dir = env::temp_dir().join(format!("tandem_constants_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let program = "const LIMIT: u8;\n\npub fn check(x: u8, y: u8) -> bool {\n    x + y > LIMIT\n}";
+    std::fs::write(dir.join("check.garble.rs"), program).unwrap();
+
+    let handlers = |constants: &str| {
+        format!(
+            "[handlers.check]\nprogram = '{}'\nfunction = 'check'\ninputs = {{ _ = '2u8' }}\n\
+            constants = {{ {constants} }}\n",
+            dir.join("check.garble.rs").display(),
+        )
+    };
+    let config = AppConfig::load(Figment::from(Toml::string(&handlers("LIMIT = '9u8'")))).unwrap();
+    let handlers_by_program = LazyHandlers::new(config.programs, None, config.handlers, vec![]);
+    let hash = blake3::hash(program.as_bytes()).to_string();
+    let compiled = handlers_by_program.get(&hash, "check").unwrap().unwrap();
+    // the circuit only depends on the declaration of the constant, not on its value:
+    let prg = check_program(program).unwrap();
+    let circuit = compile_program(&prg, "check").unwrap();
+    assert_eq!(compiled.circuit.blake3_hash(), circuit.gates.blake3_hash());
+    let evaluator = serialize_input(Role::Evaluator, &prg, &circuit.fn_def, "8u8").unwrap();
+    let output = tandem::simulate(&compiled.circuit, &compiled.inputs["_"], &evaluator).unwrap();
+    let output = tandem_garble_interop::deserialize_output(&prg, &circuit.fn_def, &output);
+    assert_eq!(output.unwrap().to_string(), "true");
+
+    let errors = AppConfig::load(Figment::from(Toml::string(&handlers("OTHER = '9u8'"))))
+        .err()
+        .unwrap();
+    let path = dir.join("check.garble.rs").display().to_string();
+    assert_eq!(
+        errors.0,
+        vec![
+            format!("handlers.check.constants: LIMIT is declared in `{path}`, but not bound"),
+            format!("handlers.check.constants: OTHER is not declared in `{path}`"),
+        ]
+    );
+    let errors = AppConfig::load(Figment::from(Toml::string(&handlers("LIMIT = 'true'"))))
+        .err()
+        .unwrap();
+    assert!(errors.0[0].starts_with("handlers.check.\"_\": not a valid input literal"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+/// The headers and JSON body of a request received by [`mock_webhook`].
+#[cfg(test)]
+type MockRequest = (Vec<String>, serde_json::Value);
+
+/// Serves the responses to consecutive requests, returning the headers and JSON body of each.
+#[cfg(test)]
+fn mock_webhook(
+    path: &str,
+    responses: &'static [(&'static str, &'static str)],
+) -> (Url, std::thread::JoinHandle<Vec<MockRequest>>) {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/{path}", listener.local_addr().unwrap());
+    let server = std::thread::spawn(move || {
+        let mut requests = vec![];
+        for (status, body) in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut headers = vec![];
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let line = line.trim_end().to_string();
+                if line.is_empty() {
+                    break;
+                }
+                if let Some(len) = line.to_lowercase().strip_prefix("content-length: ") {
+                    content_length = len.parse().unwrap();
+                }
+                headers.push(line);
+            }
+            let mut request = vec![0; content_length];
+            reader.read_exact(&mut request).unwrap();
+            let request: serde_json::Value = serde_json::from_slice(&request).unwrap();
+            requests.push((headers, request));
+            let response = format!(
+                "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+        }
+        requests
+    });
+    (Url::parse(&url).unwrap(), server)
+}
+
+#[test]
+fn test_input_webhook() {
+    let (url, server) = mock_webhook(
+        "input",
+        &[("200 OK", r#"{"input":"42u8"}"#), ("403 Forbidden", "no")],
+    );
+    let webhook = InputWebhook::new(url, Some("secret".to_string()));
+    let request = MpcRequest {
+        plaintext_metadata: "{\"user\":1}".to_string(),
+        plaintext_metadata_json: serde_json::json!({ "user": 1 }),
+        program: "pub fn main(x: u8, y: u8) -> u8 { x + y }".to_string(),
+        function: "main".to_string(),
+        circuit: None,
+    };
+    assert_eq!(webhook.input(&request, "abc").unwrap(), "42u8");
+    assert_eq!(
+        webhook.input(&request, "abc").unwrap_err(),
+        "The input webhook rejected the request (403): no"
+    );
+
+    let requests = server.join().unwrap();
+    let (headers, body) = &requests[0];
+    assert!(headers.contains(&"Authorization: Bearer secret".to_string()));
+    assert_eq!(
+        body,
+        &serde_json::json!({
+            "plaintext_metadata": { "user": 1 },
+            "function": "main",
+            "program_hash": "abc",
+        })
+    );
+}
+
+#[test]
+fn test_output_webhook() {
+    let (url, server) = mock_webhook("output", &[("204 No Content", ""), ("500 Error", "down")]);
+    let webhook = OutputWebhook::new(url.clone(), Some("secret".to_string()));
+    let output = MpcOutput {
+        engine_id: "engine".to_string(),
+        program_hash: "abc".to_string(),
+        function: "main".to_string(),
+        plaintext_metadata: "alice".to_string(),
+        output: vec![true, false],
+    };
+    assert!(webhook.send("scores", &output, "true").is_ok());
+    assert_eq!(
+        webhook.send("scores", &output, "true").unwrap_err(),
+        "The output webhook of scores rejected the output (500): down"
+    );
+    // the token is never logged:
+    assert!(!format!("{webhook:?}").contains("secret"));
+
+    let requests = server.join().unwrap();
+    let (headers, body) = &requests[0];
+    assert!(headers.contains(&"Authorization: Bearer secret".to_string()));
+    assert_eq!(
+        body,
+        &serde_json::json!({
+            "handler": "scores",
+            "engine_id": "engine",
+            "plaintext_metadata": "alice",
+            "function": "main",
+            "program_hash": "abc",
+            "output": "true",
+        })
+    );
+
+    let dir = env::temp_dir().join(format!("tandem_output_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.garble.rs");
+    std::fs::write(&path, "pub fn main(x: u8, y: u8) -> bool { x > y }").unwrap();
+    let handlers = |webhook: &str| {
+        format!(
+            "output_webhook_token = 'secret'
+[handlers.scores]
+program = '{}'
+            function = 'main'
+inputs = {{ _ = '2u8' }}
+{webhook}",
+            path.display()
+        )
+    };
+    let config = handlers(&format!("output_webhook = '{url}'\n"));
+    let config = AppConfig::load(Figment::from(Toml::string(&config))).unwrap();
+    let handlers_by_program = LazyHandlers::new(config.programs, None, config.handlers, vec![]);
+    let hash = blake3::hash(std::fs::read(&path).unwrap().as_slice()).to_string();
+    let compiled = handlers_by_program.get(&hash, "main").unwrap().unwrap();
+    assert!(compiled.on_output.is_some());
+
+    let errors = AppConfig::load(Figment::from(Toml::string(&handlers(
+        "output_webhook = 'not a url'\n",
+    ))))
+    .err()
+    .unwrap();
+    assert!(errors.0[0].starts_with("handlers.scores.output_webhook: `not a url` is not a valid"));
+    let errors = AppConfig::load(Figment::from(Toml::string(&handlers(""))))
+        .err()
+        .unwrap();
+    assert_eq!(
+        errors.0,
+        vec!["output_webhook_token: there is no configured output_webhook"]
+    );
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "database")]
+#[test]
+fn test_input_lookup() {
+    let lookup = InputLookup {
+        query: "SELECT score, premium FROM (SELECT 'alice' AS id, 700 AS score, 'true' AS premium) WHERE id = $1".to_string(),
+        template: "Customer { score: ${score}u32, premium: ${premium} }".to_string(),
+    };
+    let database = Arc::new(InputDatabase {
+        url: "sqlite::memory:".to_string(),
+        pool: Default::default(),
+    });
+    let rt = rocket::tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let inputs = rt.block_on(async move {
+        rocket::tokio::spawn(async move {
+            (
+                database.look_up(&lookup, "alice"),
+                database.look_up(&lookup, "bob"),
+            )
+        })
+        .await
+        .unwrap()
+    });
+    assert_eq!(
+        inputs,
+        (
+            Ok(Some(
+                "Customer { score: 700u32, premium: true }".to_string()
+            )),
+            Ok(None)
+        )
+    );
 
-fn test_fly_instance_id() {
-    env::set_var("FLY_ALLOC_ID", "b996131a-5bae-215b-d0f1-2d75d1a8812b");
-    let mut headers = HashMap::new();
-    set_fly_instance_id(&mut headers);
-    assert_eq!(headers.get("fly-force-instance-id").unwrap(), "b996131a");
+    let unclosed = InputLookup {
+        query: String::new(),
+        template: "${score".to_string(),
+    };
+    assert!(unclosed.render(|_| Ok(String::new())).is_err());
 }
@@ -1,18 +1,39 @@
+//! The messages of an engine that have not been acknowledged by its client yet.
+//!
+//! Messages are delivered at least once: every dialog response contains all queued messages,
+//! starting after the offset that the client acknowledged as durably received, until the client
+//! acknowledges them in a later request. Clients therefore ignore messages with offsets that they
+//! already processed, while the engine ignores resent client messages in the same way (see
+//! [`crate::state::EngineRef::process_message`]), so that requests can be retried, duplicated or
+//! arrive late without breaking the protocol. Only gaps in the offsets are rejected.
+//!
+//! If a [`crate::SessionStore`] is configured, the queued messages are persisted as a
+//! [`MessageLog`] after every dialog request, so that a repeated request can still be answered if
+//! the engine is gone, for example after a restart of the server.
+
 use std::collections::{vec_deque, VecDeque};
 
+use rocket::serde::{Deserialize, Serialize};
+
 pub(crate) type MessageId = u32;
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
 pub(crate) struct MsgQueue {
     send_q: VecDeque<Vec<u8>>,
     msg_counter: usize,
+    /// The budget of the queue in bytes, see [`MsgQueue::is_full`].
+    max_bytes: Option<usize>,
 }
 
 impl MsgQueue {
-    pub(crate) fn new() -> Self {
+    /// Creates a queue that is full once its unacknowledged messages reach `max_bytes` bytes,
+    /// unbounded if `None`.
+    pub(crate) fn new(max_bytes: Option<usize>) -> Self {
         Self {
             send_q: VecDeque::with_capacity(8),
             msg_counter: 0,
+            max_bytes,
         }
     }
 
@@ -34,10 +55,34 @@ impl MsgQueue {
         offset - first_offset
     }
 
+    /// The id of the next message that will be sent.
+    pub(crate) fn next_id(&self) -> MessageId {
+        self.msg_counter as MessageId
+    }
+
     pub(crate) fn send(&mut self, msg: Vec<u8>) {
         self.msg_counter += 1;
         self.send_q.push_back(msg);
     }
+
+    /// Total size in bytes of the messages that have not been acknowledged yet.
+    pub(crate) fn queued_bytes(&self) -> usize {
+        self.send_q.iter().map(Vec::len).sum()
+    }
+
+    /// Whether the unacknowledged messages have reached the budget of the queue, in which case no
+    /// further messages should be sent until earlier messages are acknowledged.
+    ///
+    /// Messages are never rejected by the queue itself, so that the budget can be exceeded by the
+    /// messages sent after the last check.
+    pub(crate) fn is_full(&self) -> bool {
+        self.max_bytes
+            .map_or(false, |max_bytes| self.queued_bytes() >= max_bytes)
+    }
+
+    pub(crate) fn max_bytes(&self) -> Option<usize> {
+        self.max_bytes
+    }
 }
 
 pub struct MsgIter<'a>(vec_deque::Iter<'a, Vec<u8>>, MessageId);
@@ -60,12 +105,44 @@ impl MsgQueue {
     }
 }
 
+/// The queued messages of an engine as persisted after a dialog request, see
+/// [`crate::state::EngineRegistry::save_log`].
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct MessageLog {
+    /// The offset of the last message of the client that the engine processed.
+    pub last_durably_received_client_event_offset: Option<MessageId>,
+    pub queue: MsgQueue,
+    /// Whether the dialog responses are sent as frames, see [`crate::CAPABILITIES`].
+    pub streaming: bool,
+    /// See [`crate::state::EngineRef::max_request_size`].
+    pub max_request_size: usize,
+}
+
+impl MessageLog {
+    /// Acknowledges the messages durably received by the client of an engine that is gone.
+    ///
+    /// Returns `false` if the client sent a message that the engine has not processed, which can
+    /// only be processed by the engine itself.
+    pub(crate) fn replay(
+        &mut self,
+        last_durably_received_offset: Option<MessageId>,
+        messages: &[(Vec<u8>, MessageId)],
+    ) -> bool {
+        let processed = self.last_durably_received_client_event_offset;
+        if messages.iter().any(|(_, offset)| Some(*offset) > processed) {
+            return false;
+        }
+        if let Some(offset) = last_durably_received_offset {
+            self.queue.flush_queue(offset);
+        }
+        true
+    }
+}
+
 #[test]
 fn test_flush_queue() {
-    let c = MsgQueue {
-        send_q: Default::default(),
-        msg_counter: Default::default(),
-    };
+    let c = MsgQueue::new(None);
 
     {
         assert_eq!(0, c.clone().flush_queue(0));
@@ -90,3 +167,32 @@ fn test_flush_queue() {
         );
     }
 }
+
+#[test]
+fn test_queue_budget() {
+    let mut c = MsgQueue::new(Some(4));
+    c.send(vec![0; 3]);
+    assert!(!c.is_full());
+    c.send(vec![0; 3]);
+    assert!(c.is_full());
+    c.flush_queue(0);
+    assert!(!c.is_full());
+    assert!(!MsgQueue::new(None).is_full());
+}
+
+#[test]
+fn test_replay_message_log() {
+    let mut queue = MsgQueue::new(None);
+    queue.send(vec![0]);
+    queue.send(vec![1]);
+    let mut log = MessageLog {
+        last_durably_received_client_event_offset: Some(1),
+        queue,
+        streaming: false,
+        max_request_size: 0,
+    };
+    assert!(!log.replay(None, &[(vec![], 2)]));
+    assert!(log.replay(Some(0), &[(vec![], 0), (vec![], 1)]));
+    let msgs: Vec<_> = log.queue.msgs_iter().collect();
+    assert_eq!(msgs, vec![(&vec![1], 1)]);
+}
@@ -1,12 +1,256 @@
-use rocket::serde::{Deserialize, Serialize};
-use tandem::CircuitBlake3Hash;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use rocket::{
+    http::Status,
+    request::{FromRequest, Outcome},
+    serde::{Deserialize, Serialize},
+    Request,
+};
+use tandem::{channel::ChannelKey, Circuit, CircuitBlake3Hash, ColumnarCircuit};
 
-#[derive(Serialize, Deserialize, Debug)]
+use crate::responses::Error;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(crate = "rocket::serde")]
 pub struct NewSession {
-    pub plaintext_metadata: String,
+    /// Either a plain string (as sent by older clients) or any structured JSON value.
+    pub plaintext_metadata: serde_json::Value,
+    /// Empty if the client references a program stored by the server by its `circuit_hash`, see
+    /// the `program_store` capability of [`crate::CAPABILITIES`].
+    #[serde(default)]
     pub program: String,
     pub function: String,
     pub circuit_hash: CircuitBlake3Hash,
     pub client_version: String,
+    #[serde(default)]
+    pub protocol_version: Option<u32>,
+    #[serde(default)]
+    pub wire_version: Option<u32>,
+    /// All wire versions supported by the client, taking precedence over `wire_version`.
+    #[serde(default)]
+    pub wire_versions: Option<Vec<u32>>,
+    /// Optional features supported by the client, see [`crate::CAPABILITIES`].
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+    /// Stage the final message for a separate download instead of sending it in the dialog.
+    #[serde(default)]
+    pub stage_final: bool,
+    /// The approved request for this session, required if the server requires approvals.
+    #[serde(default)]
+    pub approval_id: Option<String>,
+    /// Chosen by the client so that retried requests return the session created by the first
+    /// request instead of creating another session, see [`crate::state::IdempotencyPolicy`].
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    /// A random nonce chosen by the client, which asks the server to bind the created session to
+    /// the request using a [`crate::types::SessionCommitment`].
+    #[serde(default)]
+    pub session_nonce: Option<[u8; 32]>,
+    /// The ephemeral key of the client, which starts the handshake of an encrypted channel if the
+    /// client also sent the `encrypted_channel` capability, see [`crate::CAPABILITIES`].
+    #[serde(default)]
+    pub channel_key: Option<ChannelKey>,
+    /// The preprocessing reserved for follow-up rounds if the client also sent the `multi_round`
+    /// capability, see [`crate::CAPABILITIES`].
+    #[serde(default)]
+    pub follow_ups: Option<FollowUps>,
+    /// A raw circuit that is computed instead of a Garble program (leaving `program` and
+    /// `function` empty), as the base64-encoded columnar layout of [`ColumnarCircuit`].
+    #[serde(default)]
+    pub circuit: Option<String>,
+}
+
+/// The size of the follow-up rounds of a session, which are preprocessed together with the
+/// first round.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct FollowUps {
+    /// The AND gates of all follow-up rounds combined.
+    pub and_gates: usize,
+    /// The input bits (of both parties) of all follow-up rounds combined.
+    pub input_bits: usize,
+}
+
+/// A request of the client to run another function of the program of a session with follow-up
+/// rounds, once its previous round is completed.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(crate = "rocket::serde")]
+pub struct NextRound {
+    /// Either a plain string or any structured JSON value, see [`NewSession`].
+    pub plaintext_metadata: serde_json::Value,
+    pub function: String,
+    pub circuit_hash: CircuitBlake3Hash,
+}
+
+impl NextRound {
+    /// Returns the plaintext metadata as a string, see [`NewSession::plaintext_metadata_string`].
+    pub fn plaintext_metadata_string(&self) -> String {
+        match &self.plaintext_metadata {
+            serde_json::Value::String(metadata) => metadata.clone(),
+            metadata => metadata.to_string(),
+        }
+    }
+}
+
+impl NewSession {
+    /// Returns the plaintext metadata as a string, which is the metadata itself if it is a JSON
+    /// string and the (compact) JSON serialization of the metadata otherwise.
+    pub fn plaintext_metadata_string(&self) -> String {
+        match &self.plaintext_metadata {
+            serde_json::Value::String(metadata) => metadata.clone(),
+            metadata => metadata.to_string(),
+        }
+    }
+
+    /// Returns the hash identifying the program in allowlists, approvals and audit events, which is
+    /// the blake3 hash of the program or the circuit hash of a raw circuit.
+    pub fn program_hash(&self) -> [u8; 32] {
+        match &self.circuit {
+            Some(_) => self.circuit_hash,
+            None => *blake3::hash(self.program.as_bytes()).as_bytes(),
+        }
+    }
+
+    /// Decodes and validates the raw circuit sent by the client, if any.
+    pub(crate) fn raw_circuit(&self) -> Result<Option<Circuit>, Error> {
+        let encoded = match &self.circuit {
+            Some(encoded) => encoded,
+            None => return Ok(None),
+        };
+        let invalid = |e: &dyn std::fmt::Display| {
+            Error::UnexpectedWireFormat(format!("invalid raw circuit: {e}"))
+        };
+        let bytes = STANDARD.decode(encoded).map_err(|e| invalid(&e))?;
+        let circuit = ColumnarCircuit::new(bytes).map_err(|e| invalid(&e))?;
+        Ok(Some(circuit.to_circuit()))
+    }
+
+    /// Returns all wire versions supported by the client, if the client sent any.
+    pub fn supported_wire_versions(&self) -> Option<Vec<u32>> {
+        match (&self.wire_versions, self.wire_version) {
+            (Some(versions), _) => Some(versions.clone()),
+            (None, Some(version)) => Some(vec![version]),
+            (None, None) => None,
+        }
+    }
+}
+
+/// A request of a client to run a computation, to be approved before the session is created.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct NewApproval {
+    /// The blake3 hash of the program.
+    pub program_hash: [u8; 32],
+    pub function: String,
+    pub plaintext_metadata: String,
+    /// A commitment of the client to its input, which is recorded but never opened by the server.
+    pub input_commitment: [u8; 32],
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+pub(crate) struct BearerToken(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BearerToken {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        let token = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|h| h.strip_prefix("Bearer "));
+        match token {
+            Some(token) => Outcome::Success(BearerToken(token.trim().to_string())),
+            None => Outcome::Forward(Status::Unauthorized),
+        }
+    }
+}
+
+/// A single range of bytes requested using a `Range: bytes=<start>-[<end>]` header.
+///
+/// Unsupported range headers (such as multiple ranges) are ignored, as permitted by RFC 9110.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ByteRange {
+    pub start: usize,
+    /// The (inclusive) end of the range, `None` if the range extends to the end.
+    pub end: Option<usize>,
+}
+
+impl ByteRange {
+    fn parse(header: &str) -> Option<Self> {
+        let (start, end) = header.strip_prefix("bytes=")?.split_once('-')?;
+        let start = start.trim().parse().ok()?;
+        let end = match end.trim() {
+            "" => None,
+            end => Some(end.parse().ok()?),
+        };
+        match end {
+            Some(end) if end < start => None,
+            _ => Some(Self { start, end }),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ByteRange {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        match req.headers().get_one("Range").and_then(ByteRange::parse) {
+            Some(range) => Outcome::Success(range),
+            None => Outcome::Forward(Status::Ok),
+        }
+    }
+}
+
+/// The trace id of a request, see [`crate::TRACE_ID_HEADER`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TraceId(pub String);
+
+impl TraceId {
+    /// Returns the trace id of the request, ignoring ids that could mess up the logs.
+    pub(crate) fn of(req: &Request<'_>) -> Option<Self> {
+        Self::parse(req.headers().get_one(crate::TRACE_ID_HEADER)?)
+    }
+
+    fn parse(header: &str) -> Option<Self> {
+        let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+        if header.is_empty() || header.len() > 64 || !header.chars().all(valid) {
+            return None;
+        }
+        Some(Self(header.to_string()))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for TraceId {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, ()> {
+        match TraceId::of(req) {
+            Some(trace_id) => Outcome::Success(trace_id),
+            None => Outcome::Forward(Status::Ok),
+        }
+    }
+}
+
+#[test]
+fn test_parse_byte_range() {
+    let range = |start, end| Some(ByteRange { start, end });
+    assert_eq!(ByteRange::parse("bytes=0-"), range(0, None));
+    assert_eq!(ByteRange::parse("bytes=10-19"), range(10, Some(19)));
+    assert_eq!(ByteRange::parse("bytes=19-10"), None);
+    assert_eq!(ByteRange::parse("bytes=-10"), None);
+    assert_eq!(ByteRange::parse("bytes=0-1, 5-6"), None);
+    assert_eq!(ByteRange::parse("items=0-"), None);
+}
+
+#[test]
+fn test_parse_trace_id() {
+    let trace_id = |id: &str| Some(TraceId(id.to_string()));
+    assert_eq!(TraceId::parse("3f2a-9c_01"), trace_id("3f2a-9c_01"));
+    assert_eq!(TraceId::parse(&"a".repeat(64)), trace_id(&"a".repeat(64)));
+    assert_eq!(TraceId::parse(&"a".repeat(65)), None);
+    assert_eq!(TraceId::parse(""), None);
+    assert_eq!(TraceId::parse("a b"), None);
+    assert_eq!(TraceId::parse("a\u{1b}[31m"), None);
 }
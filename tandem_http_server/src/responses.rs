@@ -1,15 +1,20 @@
+use crate::requests::TraceId;
 use rocket::{
     http::Status,
     response::{self, Responder},
     serde::{Deserialize, Serialize},
 };
-use std::io::Cursor;
+use std::{fmt, io::Cursor, time::Duration};
+use tandem::CircuitStats;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
 #[serde(crate = "rocket::serde")]
 #[serde(tag = "error", content = "args")]
 pub(crate) enum Error {
-    CircuitHashMismatch,
+    CircuitHashMismatch {
+        server_circuit: Option<CircuitStats>,
+        compiler_version: Option<String>,
+    },
     UnexpectedWireFormat(String),
     MpcRequestRejected(String),
     DuplicateEngineId {
@@ -19,21 +24,101 @@ pub(crate) enum Error {
     NoSuchEngineId {
         engine_id: String,
     },
+    DuplicateExternalId {
+        external_id: String,
+    },
+    NoSuchExternalId {
+        external_id: String,
+    },
     Internal {
         message: String,
     },
     Bincode,
     Engine,
+    ClientBlocked,
+    RangeNotSatisfiable {
+        total: usize,
+    },
     IncompatibleVersions {
         client_version: String,
         server_version: String,
     },
+    IncompatibleProtocolVersions {
+        client_protocol_version: u32,
+        client_wire_version: u32,
+        server_protocol_version: u32,
+        server_wire_version: u32,
+        server_min_wire_version: u32,
+    },
+    ApprovalsDisabled,
+    NoSuchApproval {
+        approval_id: String,
+    },
+    ApprovalRequired,
+    ApprovalNotGranted {
+        approval_id: String,
+    },
+    ApprovalMismatch,
+    ApprovalAlreadyDecided {
+        approval_id: String,
+    },
+    Unauthorized,
+    ProgramNotAllowed {
+        program_hash: String,
+        max_and_gates: Option<usize>,
+    },
+    QuotaExceeded {
+        quota: Quota,
+        limit: usize,
+        requested: usize,
+    },
+    QueueFull {
+        queued_bytes: usize,
+        limit: usize,
+    },
+    IdempotencyKeyReused {
+        idempotency_key: String,
+    },
+    OutputDisclosureRequired,
+    RoundInProgress,
+    NoFollowUp {
+        remaining_and_gates: usize,
+    },
+    UnknownProgram {
+        circuit_hash: String,
+    },
+    SessionConflict {
+        engine_id: String,
+    },
+}
+
+/// A resource limit of the server, see [`Error::QuotaExceeded`].
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "rocket::serde", rename_all = "snake_case")]
+pub(crate) enum Quota {
+    /// The number of AND gates of a single session.
+    SessionAndGates,
+    /// The number of AND gates of all running sessions combined.
+    TotalAndGates,
+    /// The bytes of the messages queued for a session until the client acknowledges them.
+    QueueBytes,
 }
 
 impl<'r, 'o: 'r> Responder<'r, 'o> for Error {
-    fn respond_to(self, _: &'r rocket::Request<'_>) -> response::Result<'o> {
-        let string =
-            serde_json::to_string(&self).map_err(|_| rocket::http::Status::InternalServerError)?;
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> response::Result<'o> {
+        let mut json =
+            serde_json::to_value(&self).map_err(|_| rocket::http::Status::InternalServerError)?;
+        // the trace id allows operators to find the client's logs of the failed request:
+        if let Some(TraceId(trace_id)) = TraceId::of(req) {
+            if let Some(json) = json.as_object_mut() {
+                json.insert("trace_id".to_string(), trace_id.clone().into());
+            }
+            info!(
+                "Request {} with trace id {trace_id} failed: {json}",
+                req.uri()
+            );
+        }
+        let string = json.to_string();
 
         rocket::Response::build()
             .header(rocket::http::ContentType::JSON)
@@ -44,19 +129,124 @@ impl<'r, 'o: 'r> Responder<'r, 'o> for Error {
 }
 
 impl Error {
-    fn status(&self) -> Status {
+    pub(crate) fn status(&self) -> Status {
         match self {
             Error::IncompatibleVersions { .. } => Status::BadRequest,
-            Error::CircuitHashMismatch => Status::BadRequest,
+            Error::IncompatibleProtocolVersions { .. } => Status::BadRequest,
+            Error::CircuitHashMismatch { .. } => Status::BadRequest,
             Error::UnexpectedWireFormat(_) => Status::BadRequest,
             Error::MpcRequestRejected(_) => Status::BadRequest,
             Error::DuplicateEngineId { .. } => Status::BadRequest,
             Error::UnexpectedMessageId => Status::BadRequest,
             Error::Bincode => Status::BadRequest,
             Error::NoSuchEngineId { .. } => Status::NotFound,
+            Error::DuplicateExternalId { .. } => Status::Conflict,
+            Error::NoSuchExternalId { .. } => Status::NotFound,
             Error::Internal { .. } => Status::InternalServerError,
-            Error::Engine { .. } => Status::InternalServerError,
+            Error::Engine => Status::InternalServerError,
+            Error::ClientBlocked => Status::TooManyRequests,
+            Error::RangeNotSatisfiable { .. } => Status::RangeNotSatisfiable,
+            Error::ApprovalsDisabled => Status::NotFound,
+            Error::NoSuchApproval { .. } => Status::NotFound,
+            Error::ApprovalRequired => Status::Forbidden,
+            Error::ApprovalNotGranted { .. } => Status::Forbidden,
+            Error::ApprovalMismatch => Status::Forbidden,
+            Error::ApprovalAlreadyDecided { .. } => Status::Conflict,
+            Error::Unauthorized => Status::Unauthorized,
+            Error::ProgramNotAllowed { .. } => Status::Forbidden,
+            Error::IdempotencyKeyReused { .. } => Status::UnprocessableEntity,
+            Error::OutputDisclosureRequired => Status::Forbidden,
+            Error::RoundInProgress => Status::Conflict,
+            Error::NoFollowUp { .. } => Status::Conflict,
+            Error::UnknownProgram { .. } => Status::NotFound,
+            // other sessions need to finish before the request can succeed:
+            Error::QuotaExceeded {
+                quota: Quota::TotalAndGates,
+                ..
+            } => Status::ServiceUnavailable,
+            Error::QuotaExceeded { .. } => Status::PayloadTooLarge,
+            // another instance processed a request of the shared session at the same time:
+            Error::SessionConflict { .. } => Status::ServiceUnavailable,
+            // the client needs to acknowledge the queued messages before retrying:
+            Error::QueueFull { .. } => Status::TooManyRequests,
+        }
+    }
+}
+
+/// A (partial) download of a staged final message.
+pub(crate) struct Download {
+    pub bytes: Vec<u8>,
+    /// The returned range as `(start, end)` with an exclusive end, `None` for the full message.
+    pub range: Option<(usize, usize)>,
+    pub total: usize,
+}
+
+impl<'r, 'o: 'r> Responder<'r, 'o> for Download {
+    fn respond_to(self, _: &'r rocket::Request<'_>) -> response::Result<'o> {
+        let mut response = rocket::Response::build();
+        response
+            .header(rocket::http::ContentType::Binary)
+            .raw_header("Accept-Ranges", "bytes");
+        if let Some((start, end)) = self.range {
+            response.status(Status::PartialContent).raw_header(
+                "Content-Range",
+                format!("bytes {start}-{}/{}", end - 1, self.total),
+            );
+        }
+        response
+            .sized_body(self.bytes.len(), Cursor::new(self.bytes))
+            .ok()
+    }
+}
+
+/// How long the server took to respond to a dialog request, see
+/// [`crate::state::TimingDiagnostics`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct ServerTiming {
+    /// Time spent reading the request body, which includes its upload by the client.
+    pub read: Duration,
+    /// Time the processing of the request body was delayed by the bandwidth limits.
+    pub throttle: Duration,
+    /// Time spent waiting for a thread to process the messages on, see
+    /// [`crate::state::ExecutionPolicy`].
+    pub wait: Duration,
+    /// Time spent processing the messages of the client.
+    pub process: Duration,
+    /// Number of messages queued for the client (until it acknowledges them) after processing.
+    pub queued_messages: usize,
+    /// Bytes of the messages queued for the client after processing.
+    pub queued_bytes: usize,
+}
+
+/// Formats the timing as the value of a `Server-Timing` header, with durations in milliseconds.
+impl fmt::Display for ServerTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let millis = |d: Duration| d.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "read;dur={:.3}, throttle;dur={:.3}, wait;dur={:.3}, process;dur={:.3}, queued-messages;desc={}, queued-bytes;desc={}",
+            millis(self.read),
+            millis(self.throttle),
+            millis(self.wait),
+            millis(self.process),
+            self.queued_messages,
+            self.queued_bytes
+        )
+    }
+}
+
+/// A response that includes its [`ServerTiming`] as a header, if the timing is enabled.
+pub(crate) struct Timed<R>(pub R, pub Option<ServerTiming>);
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Timed<R> {
+    fn respond_to(self, req: &'r rocket::Request<'_>) -> response::Result<'o> {
+        let mut response = self.0.respond_to(req)?;
+        if let Some(timing) = self.1 {
+            response.set_raw_header("Server-Timing", timing.to_string());
+            // browsers only let scripts of other origins read the header if it is exposed:
+            response.set_raw_header("Access-Control-Expose-Headers", "Server-Timing");
         }
+        Ok(response)
     }
 }
 
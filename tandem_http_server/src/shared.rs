@@ -0,0 +1,287 @@
+//! Sessions shared by all instances of a load-balanced server, so that any instance can continue
+//! a session regardless of which instance created it or processed its last request.
+//!
+//! The state of a shared session is written to the [`SessionStore`] after every dialog request
+//! (next to its message log, see [`crate::msg_queue::MessageLog`]) and read back by the instance that processes the next request, see
+//! [`tandem::states::Contributor::serialize_state`]. Each write is versioned: a session is only
+//! written if nobody else wrote it since it was read, so that two instances that process requests
+//! of the same session at the same time never both respond with messages derived from the same
+//! state.
+
+use std::{sync::Arc, time::Duration};
+
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::RngCore;
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use rocket::serde::{Deserialize, Serialize};
+use tandem::states::Msg;
+
+use crate::{
+    msg_queue::MsgQueue,
+    responses::Error,
+    store::{store_failed, SessionStore},
+    types::EngineId,
+};
+
+/// Whether sessions are shared, configured as part of the Rocket config.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct SharedRegistryPolicy {
+    /// Whether the running sessions are stored in the `session_store` and shared by all
+    /// instances, sessions are only kept by the instance that created them if not set.
+    pub shared_registry: bool,
+    /// The hex-encoded key encrypting and authenticating the shared sessions, which must be the
+    /// same for all instances.
+    pub shared_state_key: Option<String>,
+}
+
+impl SharedRegistryPolicy {
+    /// Decodes the 64 hex characters of the state key, if any.
+    pub fn state_key(&self) -> Result<Option<[u8; 32]>, String> {
+        let hex = match &self.shared_state_key {
+            Some(hex) => hex.as_bytes(),
+            None => return Ok(None),
+        };
+        let invalid = || "`shared_state_key` must consist of 64 hex characters".to_string();
+        if hex.len() != 64 {
+            return Err(invalid());
+        }
+        let mut key = [0; 32];
+        for (byte, digits) in key.iter_mut().zip(hex.chunks(2)) {
+            let digits = std::str::from_utf8(digits).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| invalid())?;
+        }
+        Ok(Some(key))
+    }
+}
+
+/// A session as written to the [`SessionStore`], see [`crate::state::EngineRef::to_shared`].
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct SharedEngine {
+    /// The circuit of the contributor, in the layout of [`tandem::ColumnarCircuit`].
+    pub circuit: Vec<u8>,
+    pub input: Vec<bool>,
+    /// The serialized contributor, `None` once the protocol was aborted.
+    pub contributor: Option<Vec<u8>>,
+    pub last_durably_received_client_event_offset: Option<u32>,
+    /// The version of the message log of the session, see [`crate::state::EngineRegistry::save_log`].
+    pub log_version: u64,
+    pub steps_remaining: u32,
+    pub context: MsgQueue,
+    pub aborted: bool,
+    pub failures: u32,
+    pub stage_final: bool,
+    pub streaming: bool,
+    pub confirm_transcript: bool,
+    pub output_disclosure: bool,
+    pub output_disclosed: bool,
+    /// The request of the session, if its handler processes the disclosed output.
+    pub request: Option<SharedRequest>,
+    pub abort_reason: Option<String>,
+    pub session: SharedSessionInfo,
+    /// The bucket limiting the bandwidth of the session, if limited, see
+    /// [`crate::throttle::TokenBucket::state`].
+    pub bandwidth: Option<SharedBucket>,
+}
+
+/// What is needed to ask the handler of a shared session again for its [`crate::OutputHandler`],
+/// which cannot be stored, see [`crate::MpcSession::on_output`].
+///
+/// The function and metadata of the request are part of the [`SharedSessionInfo`], the circuit of
+/// a raw circuit is part of the [`SharedEngine`].
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct SharedRequest {
+    pub program: String,
+    /// The JSON-encoded metadata, see [`crate::MpcRequest::plaintext_metadata_json`].
+    pub plaintext_metadata_json: String,
+    pub raw_circuit: bool,
+}
+
+/// The state of the bandwidth limit of a shared session, so that the session stays throttled on
+/// every instance that continues it.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct SharedBucket {
+    pub tokens: f64,
+    /// Milliseconds since the Unix epoch at which the bucket was last updated.
+    pub updated_millis: u64,
+}
+
+/// What is recorded about a shared session in its audit events, see
+/// [`crate::state::SessionInfo`].
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct SharedSessionInfo {
+    pub engine_id: Option<EngineId>,
+    pub external_id: Option<String>,
+    pub program_hash: String,
+    pub function: String,
+    pub plaintext_metadata: String,
+    pub client: Option<std::net::IpAddr>,
+    /// Milliseconds since the Unix epoch at which the client requested the session.
+    pub requested_millis: u64,
+    pub transcript_confirmed: Option<bool>,
+}
+
+/// The sessions shared by all instances, stored in a [`SessionStore`].
+pub(crate) struct SharedSessions {
+    store: Arc<dyn SessionStore>,
+    /// Authenticates the serialized contributors, see
+    /// [`tandem::states::Contributor::serialize_state`].
+    state_key: [u8; 32],
+    /// Encrypts the stored values, which contain the input and the secret keys of the contributor.
+    cipher: ChaCha20Poly1305,
+    /// How long sessions are stored without being used.
+    pub session_ttl: Duration,
+}
+
+impl SharedSessions {
+    pub fn new(store: Arc<dyn SessionStore>, key: [u8; 32], session_ttl: Duration) -> Self {
+        let state_key = blake3::derive_key("tandem_http_server shared session state", &key);
+        let cipher_key = blake3::derive_key("tandem_http_server shared session cipher", &key);
+        Self {
+            store,
+            state_key,
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&cipher_key)),
+            session_ttl,
+        }
+    }
+
+    /// The key authenticating the serialized contributors.
+    pub fn state_key(&self) -> &[u8; 32] {
+        &self.state_key
+    }
+
+    /// Loads the session with its version, or `None` if there is no such session.
+    pub fn load_engine(&self, engine_id: &EngineId) -> Result<Option<(u64, SharedEngine)>, Error> {
+        let key = format!("tandem:session:{engine_id}");
+        match self.load(&key)? {
+            Some((version, value)) => Ok(Some((version, bincode::deserialize(&value)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores the session as the specified version, returning `false` if another instance stored
+    /// the session in the meantime, see [`SessionStore::store`].
+    pub fn store_engine(
+        &self,
+        engine_id: &EngineId,
+        version: u64,
+        engine: &SharedEngine,
+        ttl: Duration,
+    ) -> Result<bool, Error> {
+        let key = format!("tandem:session:{engine_id}");
+        self.store(&key, version, &bincode::serialize(engine)?, ttl)
+    }
+
+    pub fn remove_engine(&self, engine_id: &EngineId) -> Result<(), Error> {
+        self.remove(&format!("tandem:session:{engine_id}"))
+    }
+
+    /// Stores the engine id of a session under its external id, failing with
+    /// [`Error::DuplicateExternalId`] if another session already uses the external id.
+    pub fn store_external_id(&self, external_id: &str, engine_id: &EngineId) -> Result<(), Error> {
+        let key = format!("tandem:external:{external_id}");
+        match self.store(&key, 1, engine_id.as_bytes(), self.session_ttl)? {
+            true => Ok(()),
+            false => Err(Error::DuplicateExternalId {
+                external_id: external_id.to_string(),
+            }),
+        }
+    }
+
+    pub fn load_external_id(&self, external_id: &str) -> Result<Option<EngineId>, Error> {
+        let key = format!("tandem:external:{external_id}");
+        let engine_id = self.load(&key)?;
+        Ok(engine_id.map(|(_, id)| String::from_utf8_lossy(&id).to_string()))
+    }
+
+    pub fn remove_external_id(&self, external_id: &str) -> Result<(), Error> {
+        self.remove(&format!("tandem:external:{external_id}"))
+    }
+
+    /// Stores the staged final message of a session until it is downloaded, failing with
+    /// [`Error::SessionConflict`] if a final message of the session is already stored.
+    pub fn store_final(&self, engine_id: &EngineId, msg: &Msg) -> Result<(), Error> {
+        let key = format!("tandem:final:{engine_id}");
+        match self.store(&key, 1, msg, self.session_ttl)? {
+            true => Ok(()),
+            false => Err(Error::SessionConflict {
+                engine_id: engine_id.clone(),
+            }),
+        }
+    }
+
+    pub fn load_final(&self, engine_id: &EngineId) -> Result<Option<Msg>, Error> {
+        let key = format!("tandem:final:{engine_id}");
+        Ok(self.load(&key)?.map(|(_, msg)| msg))
+    }
+
+    pub fn remove_final(&self, engine_id: &EngineId) -> Result<(), Error> {
+        self.remove(&format!("tandem:final:{engine_id}"))
+    }
+
+    /// Loads and decrypts the value stored under the key, which is bound to the key and version.
+    fn load(&self, key: &str) -> Result<Option<(u64, Vec<u8>)>, Error> {
+        let (version, value) = match self.store.load(key).map_err(store_failed)? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        if value.len() < 12 {
+            return Err(invalid_value(key));
+        }
+        let (nonce, ciphertext) = value.split_at(12);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &associated_data(key, version),
+        };
+        let value = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| invalid_value(key))?;
+        Ok(Some((version, value)))
+    }
+
+    /// Encrypts and stores the value, see [`SessionStore::store`].
+    fn store(&self, key: &str, version: u64, value: &[u8], ttl: Duration) -> Result<bool, Error> {
+        let mut nonce = [0; 12];
+        ChaCha20Rng::from_entropy().fill_bytes(&mut nonce);
+        let payload = Payload {
+            msg: value,
+            aad: &associated_data(key, version),
+        };
+        let ciphertext = self
+            .cipher
+            .encrypt(Nonce::from_slice(&nonce), payload)
+            .map_err(|_| Error::Internal {
+                message: "could not encrypt the session".to_string(),
+            })?;
+        let mut stored = nonce.to_vec();
+        stored.extend(ciphertext);
+        self.store
+            .store(key, version, &stored, ttl)
+            .map_err(store_failed)
+    }
+
+    fn remove(&self, key: &str) -> Result<(), Error> {
+        self.store.remove(key).map_err(store_failed)
+    }
+}
+
+fn associated_data(key: &str, version: u64) -> Vec<u8> {
+    let mut aad = version.to_le_bytes().to_vec();
+    aad.extend(key.as_bytes());
+    aad
+}
+
+fn invalid_value(key: &str) -> Error {
+    error!("The value of `{key}` in the session store could not be decrypted");
+    Error::Internal {
+        message: "invalid shared session".to_string(),
+    }
+}
@@ -1,52 +1,548 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
-    sync::{Arc, Mutex, RwLock},
+    borrow::Cow,
+    collections::{hash_map::Entry, BTreeMap, HashMap},
+    net::IpAddr,
+    sync::{Arc, Condvar, Mutex, RwLock},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use rand_chacha::ChaCha20Rng;
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
+use rocket::{
+    serde::Deserialize,
+    tokio::sync::{OwnedSemaphorePermit, Semaphore},
+};
 use tandem::{
+    abort_message,
+    channel::{ChannelKeyPair, SecureChannel},
     states::{Contributor, Msg},
-    Circuit,
+    AbortReason, Circuit, CircuitBlake3Hash, ColumnarCircuit, PreprocessedTriples, ProtocolOptions,
+    ProtocolPlan,
 };
 
 use crate::{
-    msg_queue::{MessageId, MsgQueue},
-    responses::Error,
-    types::{EngineId, HandleMpcRequestFn, MpcRequest, MpcSession},
+    audit::{AuditEvent, AuditOutcome, AuditSink},
+    msg_queue::{MessageId, MessageLog, MsgQueue},
+    requests::{ByteRange, NewApproval, NewSession},
+    responses::{Download, Error, Quota},
+    shared::{SharedBucket, SharedEngine, SharedRequest, SharedSessionInfo, SharedSessions},
+    store::{store_failed, SessionStore},
+    throttle::{Throttle, TokenBucket, THROTTLE_CHUNK_SIZE},
+    types::{
+        Approval, ApprovalStatus, CatalogFn, EngineCreationResult, EngineId, HandleMpcRequestFn,
+        IdGenerator, Metrics, MpcOutput, MpcRequest, MpcSession, OutputHandler, PublishedFunction,
+        Readiness, ReadinessFn, StickinessProvider,
+    },
+    ServerConfig,
 };
 
+/// The contributor run by an engine.
+type EngineContributor = Contributor<Circuit, Vec<bool>>;
+
 /// reference to a (running) Engine
 pub(crate) struct EngineRef {
     last_durably_received_client_event_offset: Option<MessageId>,
-    tandem: Option<Contributor<Circuit, Vec<bool>>>,
+    tandem: Option<EngineContributor>,
     steps_remaining: u32,
     context: MsgQueue,
+    plan: ProtocolPlan,
+    and_gates: usize,
+    aborted: bool,
+    failures: u32,
+    stage_final: bool,
+    staged_final: Option<Msg>,
+    bandwidth: Option<TokenBucket>,
+    /// Whether dialog responses are sent as frames, see [`crate::CAPABILITIES`].
+    streaming: bool,
+    /// Whether the transcript hashes are exchanged after the final message, see
+    /// [`crate::CAPABILITIES`].
+    confirm_transcript: bool,
+    /// Encrypts the messages of the dialog, if the client negotiated an encrypted channel.
+    channel: Option<SecureChannel>,
+    /// Whether the client discloses the output after the final message (and the confirmation of
+    /// the transcript), see [`crate::CAPABILITIES`].
+    output_disclosure: bool,
+    /// Processes the disclosed output, if the handler asked for it.
+    on_output: Option<OutputHandler>,
+    output_disclosed: bool,
+    /// The number of output bits of the circuit.
+    outputs: usize,
+    session: SessionInfo,
+    /// Why the protocol was aborted, if it was aborted.
+    abort_reason: Option<String>,
+    /// The state of the rounds that follow the first round, if the client reserved any.
+    rounds: Option<Rounds>,
+    /// The version of the message log in the [`crate::SessionStore`], `0` until it is first
+    /// stored, see [`EngineRegistry::save_log`].
+    log_version: u64,
+    /// The state of a session shared by all instances, if it is shared.
+    shared: Option<SharedState>,
+}
+
+/// The circuit and input of a session shared by all instances, which are stored together with the
+/// contributor, see [`EngineRef::to_shared`].
+struct SharedState {
+    /// The version of the session in the [`crate::SessionStore`], `0` until it is first stored.
+    version: u64,
+    /// The circuit of the contributor, in the layout of [`ColumnarCircuit`].
+    circuit: Vec<u8>,
+    input: Vec<bool>,
+    /// The request of the session, if its handler processes the disclosed output.
+    request: Option<SharedRequest>,
+}
+
+/// Decodes a circuit stored in the layout of [`ColumnarCircuit`].
+fn decode_circuit(bytes: &[u8]) -> Result<Circuit, Error> {
+    Ok(ColumnarCircuit::new(bytes)
+        .map_err(|e| Error::Internal {
+            message: format!("could not decode the circuit: {e}"),
+        })?
+        .to_circuit())
+}
+
+/// The preprocessing reserved by the client for the rounds that follow the first round of a
+/// session, see [`EngineRef::next_round`].
+pub(crate) struct FollowUps {
+    /// The program of the session, whose functions are run by all rounds.
+    pub program: String,
+    /// The circuit whose triples are preprocessed, see [`tandem::preprocessing_circuit`].
+    pub capacity: Circuit,
+}
+
+/// The state of a session with follow-up rounds.
+struct Rounds {
+    program: String,
+    /// The circuit and input of the first round, until the preprocessing is done.
+    first_round: Option<(Circuit, Vec<bool>)>,
+    /// The triples that remain for the follow-up rounds, once the preprocessing is done.
+    triples: Option<PreprocessedTriples>,
+}
+
+/// What is recorded about a session in its audit events.
+#[derive(Debug, Clone)]
+pub(crate) struct SessionInfo {
+    pub engine_id: Option<EngineId>,
+    pub external_id: Option<String>,
+    pub program_hash: String,
+    pub function: String,
+    pub plaintext_metadata: String,
+    pub client: Option<IpAddr>,
+    pub requested: Instant,
+    /// Whether the transcript hash of the client matched, `None` until it was checked.
+    pub transcript_confirmed: Option<bool>,
+}
+
+impl SessionInfo {
+    pub fn new(request: &NewSession, client: Option<IpAddr>) -> Self {
+        Self {
+            engine_id: None,
+            external_id: None,
+            program_hash: blake3::Hash::from(request.program_hash())
+                .to_hex()
+                .to_string(),
+            function: request.function.clone(),
+            plaintext_metadata: request.plaintext_metadata_string(),
+            client,
+            requested: Instant::now(),
+            transcript_confirmed: None,
+        }
+    }
+
+    /// The output of the session, as passed to [`MpcSession::on_output`].
+    fn output(&self, output: Vec<bool>) -> MpcOutput {
+        MpcOutput {
+            engine_id: self.engine_id.clone().unwrap_or_default(),
+            program_hash: self.program_hash.clone(),
+            function: self.function.clone(),
+            plaintext_metadata: self.plaintext_metadata.clone(),
+            output,
+        }
+    }
 }
 
 impl EngineRef {
-    pub fn new(rng: ChaCha20Rng, program: Circuit, input: Vec<bool>) -> Result<Self, Error> {
-        let mut context = MsgQueue::new();
-        let (contrib, initial_msg) = Contributor::new(program, input, rng)?;
+    /// Creates a new engine, which stages its final message for a separate download (instead of
+    /// sending it as part of the dialog) if `stage_final` is set, limits its bandwidth using the
+    /// `bandwidth` bucket (if any), pauses once `max_queue_bytes` (if any) are queued, uses the
+    /// negotiated `capabilities`, encrypts its messages using the `channel` (if any) and passes
+    /// the output disclosed by the client to `on_output` (if any).
+    ///
+    /// If the client reserved `follow_ups`, the engine first preprocesses the triples of all
+    /// rounds and then runs the first round on its share of the triples, see
+    /// [`EngineRef::next_round`].
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        rng: ChaCha20Rng,
+        program: Circuit,
+        input: Vec<bool>,
+        stage_final: bool,
+        bandwidth: Option<TokenBucket>,
+        max_queue_bytes: Option<usize>,
+        capabilities: &[String],
+        channel: Option<SecureChannel>,
+        on_output: Option<OutputHandler>,
+        follow_ups: Option<FollowUps>,
+        session: SessionInfo,
+    ) -> Result<Self, Error> {
+        let outputs = program.output_gates().len();
+        let (plan, and_gates, contrib, initial_msg, rounds) = match follow_ups {
+            Some(FollowUps {
+                program: source,
+                capacity,
+            }) => {
+                // the plan of the capacity circuit covers the messages of all rounds:
+                let plan = ProtocolPlan::new(&capacity);
+                let and_gates = capacity.and_gates();
+                let options = ProtocolOptions::default();
+                let (contrib, msg) = Contributor::new_preprocessing(capacity, rng, options)?;
+                let rounds = Rounds {
+                    program: source,
+                    first_round: Some((program, input)),
+                    triples: None,
+                };
+                (plan, and_gates, contrib, msg, Some(rounds))
+            }
+            None => {
+                let plan = ProtocolPlan::new(&program);
+                let and_gates = program.and_gates();
+                let (contrib, msg) = Contributor::new(program, input, rng)?;
+                (plan, and_gates, contrib, msg, None)
+            }
+        };
         let steps_remaining = contrib.steps();
-        context.send(initial_msg);
 
-        Ok(Self {
-            context,
+        let mut engine = Self {
+            context: MsgQueue::new(max_queue_bytes),
             tandem: Some(contrib),
             steps_remaining,
             last_durably_received_client_event_offset: None,
+            plan,
+            and_gates,
+            aborted: false,
+            failures: 0,
+            stage_final,
+            staged_final: None,
+            bandwidth,
+            streaming: capabilities.iter().any(|c| c == "streaming"),
+            confirm_transcript: capabilities.iter().any(|c| c == "transcript_confirmation"),
+            channel,
+            output_disclosure: capabilities.iter().any(|c| c == "output_disclosure"),
+            on_output,
+            output_disclosed: false,
+            outputs,
+            session,
+            abort_reason: None,
+            rounds,
+            log_version: 0,
+            shared: None,
+        };
+        engine.queue(initial_msg);
+        Ok(engine)
+    }
+
+    /// Shares the engine with all instances, storing its circuit and input together with its
+    /// state, see [`EngineRegistry::save_engine`]. The `request` (if any) is used to ask the
+    /// handler again for the output handler, see [`EngineRef::output_request`].
+    pub fn share(
+        &mut self,
+        circuit: &Circuit,
+        input: Vec<bool>,
+        request: Option<SharedRequest>,
+    ) -> Result<(), Error> {
+        let mut bytes = vec![];
+        circuit
+            .write_columnar(&mut bytes)
+            .map_err(|e| Error::Internal {
+                message: format!("could not encode the circuit: {e}"),
+            })?;
+        self.shared = Some(SharedState {
+            version: 0,
+            circuit: bytes,
+            input,
+            request,
+        });
+        Ok(())
+    }
+
+    /// The state of a shared engine, to be stored in the [`crate::SessionStore`]. The contributor is
+    /// serialized using the `state_key`, see [`Contributor::serialize_state`].
+    fn to_shared(&self, state_key: &[u8; 32]) -> Result<SharedEngine, Error> {
+        let shared = self.shared.as_ref().expect("shared engine");
+        let contributor = match &self.tandem {
+            Some(contrib) => Some(contrib.serialize_state(state_key)?),
+            None => None,
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let requested = now.saturating_sub(self.session.requested.elapsed());
+        let bandwidth = self.bandwidth.as_ref().map(|bucket| {
+            let (tokens, updated) = bucket.state();
+            SharedBucket {
+                tokens,
+                updated_millis: now.saturating_sub(updated.elapsed()).as_millis() as u64,
+            }
+        });
+        Ok(SharedEngine {
+            circuit: shared.circuit.clone(),
+            input: shared.input.clone(),
+            contributor,
+            last_durably_received_client_event_offset: self
+                .last_durably_received_client_event_offset,
+            log_version: self.log_version,
+            steps_remaining: self.steps_remaining,
+            context: self.context.clone(),
+            aborted: self.aborted,
+            failures: self.failures,
+            stage_final: self.stage_final,
+            streaming: self.streaming,
+            confirm_transcript: self.confirm_transcript,
+            output_disclosure: self.output_disclosure,
+            output_disclosed: self.output_disclosed,
+            request: shared.request.clone(),
+            abort_reason: self.abort_reason.clone(),
+            session: SharedSessionInfo {
+                engine_id: self.session.engine_id.clone(),
+                external_id: self.session.external_id.clone(),
+                program_hash: self.session.program_hash.clone(),
+                function: self.session.function.clone(),
+                plaintext_metadata: self.session.plaintext_metadata.clone(),
+                client: self.session.client,
+                requested_millis: requested.as_millis() as u64,
+                transcript_confirmed: self.session.transcript_confirmed,
+            },
+            bandwidth,
         })
     }
 
+    /// Restores the specified version of a shared engine, see [`EngineRef::to_shared`], limiting
+    /// its bandwidth using the `bandwidth` bucket (if any), which continues from the stored state
+    /// of the bucket.
+    fn from_shared(
+        version: u64,
+        shared: SharedEngine,
+        state_key: &[u8; 32],
+        mut bandwidth: Option<TokenBucket>,
+    ) -> Result<Self, Error> {
+        let circuit = decode_circuit(&shared.circuit)?;
+        let tandem = match &shared.contributor {
+            Some(bytes) => Some(Contributor::restore(
+                circuit.clone(),
+                shared.input.clone(),
+                bytes,
+                state_key,
+            )?),
+            None => None,
+        };
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let age = now.saturating_sub(Duration::from_millis(shared.session.requested_millis));
+        let session = SessionInfo {
+            engine_id: shared.session.engine_id,
+            external_id: shared.session.external_id,
+            program_hash: shared.session.program_hash,
+            function: shared.session.function,
+            plaintext_metadata: shared.session.plaintext_metadata,
+            client: shared.session.client,
+            requested: Instant::now().checked_sub(age).unwrap_or_else(Instant::now),
+            transcript_confirmed: shared.session.transcript_confirmed,
+        };
+        if let (Some(bucket), Some(stored)) = (&mut bandwidth, shared.bandwidth) {
+            let age = now.saturating_sub(Duration::from_millis(stored.updated_millis));
+            let updated = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+            bucket.restore(stored.tokens, updated);
+        }
+        Ok(Self {
+            last_durably_received_client_event_offset: shared
+                .last_durably_received_client_event_offset,
+            tandem,
+            steps_remaining: shared.steps_remaining,
+            context: shared.context,
+            plan: ProtocolPlan::new(&circuit),
+            and_gates: circuit.and_gates(),
+            aborted: shared.aborted,
+            failures: shared.failures,
+            stage_final: shared.stage_final,
+            staged_final: None,
+            bandwidth,
+            streaming: shared.streaming,
+            confirm_transcript: shared.confirm_transcript,
+            channel: None,
+            output_disclosure: shared.output_disclosure,
+            on_output: None,
+            output_disclosed: shared.output_disclosed,
+            outputs: circuit.output_gates().len(),
+            session,
+            abort_reason: shared.abort_reason,
+            rounds: None,
+            log_version: shared.log_version,
+            shared: Some(SharedState {
+                version,
+                circuit: shared.circuit,
+                input: shared.input,
+                request: shared.request,
+            }),
+        })
+    }
+
+    /// The request for which the handler of a restored shared engine is asked again for its
+    /// output handler, which cannot be stored. Only needed once the client is about to disclose
+    /// its output and only if the handler processes the output.
+    fn output_request(&self) -> Result<Option<MpcRequest>, Error> {
+        let (shared, request) = match &self.shared {
+            Some(
+                shared @ SharedState {
+                    request: Some(request),
+                    ..
+                },
+            ) => (shared, request),
+            _ => return Ok(None),
+        };
+        if self.steps_remaining > 0 || self.output_disclosed || self.on_output.is_some() {
+            return Ok(None);
+        }
+        let plaintext_metadata_json = serde_json::from_str(&request.plaintext_metadata_json)
+            .map_err(|e| Error::Internal {
+                message: format!("could not decode the metadata: {e}"),
+            })?;
+        let circuit = match request.raw_circuit {
+            true => Some(decode_circuit(&shared.circuit)?),
+            false => None,
+        };
+        Ok(Some(MpcRequest {
+            plaintext_metadata: self.session.plaintext_metadata.clone(),
+            plaintext_metadata_json,
+            program: request.program.clone(),
+            function: self.session.function.clone(),
+            circuit,
+        }))
+    }
+
+    /// Encrypts a message that is sent with the specified id, if the session uses an encrypted
+    /// channel.
+    fn seal(&self, msg: Msg, id: MessageId) -> Msg {
+        match &self.channel {
+            Some(channel) => channel.seal(u64::from(id), &msg),
+            None => msg,
+        }
+    }
+
+    /// Queues a message for the client, see [`EngineRef::seal`].
+    fn queue(&mut self, msg: Msg) {
+        let msg = self.seal(msg, self.context.next_id());
+        self.context.send(msg);
+    }
+
+    /// Upper bound for the size of a dialog request body sent by the client for this engine.
+    pub fn max_request_size(&self) -> usize {
+        // the client might (re-)send all of its messages at once, each one prefixed with its length
+        // and followed by its message id, plus the last durably received offset:
+        let hints = self.plan.message_size_hints();
+        let confirmation = if self.confirm_transcript {
+            32 + 8 + 4
+        } else {
+            0
+        };
+        // the disclosed output consists of a label, the masked value and a share of the mask (with
+        // its MAC) per output bit:
+        let disclosure = if self.output_disclosure {
+            8 + self.outputs * (16 + 1 + 16 + 1) + 8 + 4
+        } else {
+            0
+        };
+        // the first round of a session with follow-up rounds needs one more message, which also
+        // contains the id and checksum of the opened bits of the preprocessed triples:
+        let rounds = if self.rounds.is_some() {
+            8 + 4 + 32 + 32
+        } else {
+            0
+        };
+        let messages = hints.len()
+            + usize::from(self.confirm_transcript)
+            + usize::from(self.output_disclosure)
+            + usize::from(self.rounds.is_some());
+        // each encrypted message is followed by its authentication tag:
+        let tags = if self.channel.is_some() {
+            messages * 16
+        } else {
+            0
+        };
+        self.plan.total_evaluator_bytes()
+            + hints.len() * (8 + 4)
+            + confirmation
+            + disclosure
+            + rounds
+            + tags
+            + 8
+            + 5
+    }
+
+    /// Processes a message of the client.
+    ///
+    /// If the client aborted the protocol, the engine is done. If the engine fails to process the
+    /// message, it queues an abort message for the client and is done as well.
+    ///
+    /// If the transcripts are confirmed, the final message is followed by the transcript hash of
+    /// the engine and the engine is only done once it has checked the hash of the client. If the
+    /// output is disclosed, the engine is only done once the client has disclosed it.
+    ///
+    /// If the client reserved follow-up rounds, the last message of the preprocessing is replaced
+    /// by the initial message of the first round.
+    ///
+    /// If the session uses an encrypted channel, messages that cannot be decrypted abort the
+    /// protocol like any other invalid message.
+    ///
+    /// Messages that were already processed are ignored, as clients resend their messages if the
+    /// response to a dialog request was lost, while messages that skip an offset are rejected.
     pub fn process_message(&mut self, msg: &Msg, offset: MessageId) -> Result<(), Error> {
-        if (self.last_durably_received_client_event_offset.is_none() && offset == 0)
-            || self.last_durably_received_client_event_offset == Some(offset - 1)
-        {
+        let expected_offset = self
+            .last_durably_received_client_event_offset
+            .map_or(0, |o| o + 1);
+        if offset < expected_offset {
+            return Ok(());
+        }
+        if offset == expected_offset {
             self.last_durably_received_client_event_offset = Some(offset);
             if let Some(contrib) = self.tandem.take() {
-                let (next_state, reply) = contrib.run(msg)?;
-                self.tandem = Some(next_state);
-                self.context.send(reply);
+                let msg = match &self.channel {
+                    Some(channel) => channel.open(u64::from(offset), msg).map(Cow::Owned),
+                    None => Ok(Cow::Borrowed(msg.as_slice())),
+                };
+                let confirmed = self.session.transcript_confirmed.is_some();
+                let processed = match msg {
+                    Ok(msg)
+                        if self.steps_remaining == 0 && self.confirm_transcript && !confirmed =>
+                    {
+                        let confirmed = contrib.confirm_transcript_hash(&msg);
+                        if !matches!(confirmed, Err(tandem::Error::PeerAborted(_))) {
+                            self.session.transcript_confirmed = Some(confirmed.is_ok());
+                        }
+                        confirmed.map(|()| (contrib, None))
+                    }
+                    Ok(msg) if self.steps_remaining == 0 && self.output_disclosure => {
+                        let output = contrib.disclosed_output(&msg);
+                        if let Ok(output) = &output {
+                            self.output_disclosed = true;
+                            if let Some(on_output) = &self.on_output {
+                                on_output.call(self.session.output(output.clone()));
+                            }
+                        }
+                        output.map(|_| (contrib, None))
+                    }
+                    Ok(msg) => contrib.run(&msg).map(|(c, reply)| (c, Some(reply))),
+                    Err(e) => Err(e),
+                };
+                let preprocessed = self.steps_remaining == 1
+                    && matches!(&self.rounds, Some(r) if r.first_round.is_some());
+                match processed {
+                    // the last message of the preprocessing is not needed by the client:
+                    Ok((next_state, Some(_))) if preprocessed => {
+                        if let Err(e) = self.start_first_round(next_state) {
+                            self.fail(e);
+                        }
+                    }
+                    processed => self.process_reply(processed),
+                }
             }
             Ok(())
         } else {
@@ -54,6 +550,76 @@ impl EngineRef {
         }
     }
 
+    /// Queues the reply of the contributor (if any) or aborts the protocol if it failed.
+    fn process_reply(
+        &mut self,
+        processed: Result<(EngineContributor, Option<Msg>), tandem::Error>,
+    ) {
+        match processed {
+            Ok((next_state, None)) => self.tandem = Some(next_state),
+            Ok((next_state, Some(reply))) => {
+                self.steps_remaining = self.steps_remaining.saturating_sub(1);
+                if self.steps_remaining == 0 && self.stage_final {
+                    // an empty placeholder keeps the message ids of the dialog intact, the
+                    // staged message is encrypted using the id of the placeholder:
+                    self.staged_final = Some(self.seal(reply, self.context.next_id()));
+                    self.context.send(vec![]);
+                } else {
+                    self.queue(reply);
+                }
+                if self.steps_remaining == 0 && self.confirm_transcript {
+                    self.queue(next_state.transcript_hash().to_vec());
+                }
+                self.tandem = Some(next_state);
+            }
+            Err(e) => self.fail(e),
+        }
+    }
+
+    /// Aborts the protocol, informing the client unless the client aborted it.
+    fn fail(&mut self, e: tandem::Error) {
+        match e {
+            tandem::Error::PeerAborted(reason) => {
+                info!("Session aborted by the client: {reason}");
+                self.abort_reason = Some(format!("aborted by the client: {reason}"));
+                self.aborted = true;
+            }
+            e => {
+                warn!("Aborting session: {e}");
+                self.abort_reason = Some(format!("aborted by the server: {e}"));
+                self.queue(abort_message(AbortReason::from(&e)));
+                self.aborted = true;
+                self.failures += 1;
+            }
+        }
+    }
+
+    /// Splits off the triples of the first round from the finished preprocessing and queues the
+    /// initial message of the first round.
+    fn start_first_round(&mut self, preprocessing: EngineContributor) -> Result<(), tandem::Error> {
+        let rounds = self.rounds.as_mut().expect("multi-round session");
+        let (circuit, input) = rounds.first_round.take().expect("first round not started");
+        let mut triples = preprocessing.preprocessed()?;
+        let round = triples.split_off(&circuit)?;
+        rounds.triples = Some(triples);
+        self.start_round(circuit, input, round)
+    }
+
+    /// Starts a round of a multi-round session on the specified triples.
+    fn start_round(
+        &mut self,
+        circuit: Circuit,
+        input: Vec<bool>,
+        triples: PreprocessedTriples,
+    ) -> Result<(), tandem::Error> {
+        let rng = ChaCha20Rng::from_entropy();
+        let (contrib, msg) = Contributor::from_preprocessed(circuit, input, rng, triples)?;
+        self.steps_remaining = contrib.steps();
+        self.queue(msg);
+        self.tandem = Some(contrib);
+        Ok(())
+    }
+
     pub fn last_durably_received_client_event_offset(&self) -> Option<MessageId> {
         self.last_durably_received_client_event_offset
     }
@@ -62,44 +628,707 @@ impl EngineRef {
         self.context.flush_queue(last_durably_received_offset);
     }
 
+    /// Total size in bytes of the messages that the client has not acknowledged yet.
+    pub fn queued_bytes(&self) -> usize {
+        self.context.queued_bytes()
+    }
+
+    /// Returns an error if the client needs to acknowledge the queued messages before the engine
+    /// processes further messages, see [`Error::QueueFull`].
+    pub fn check_queue(&self) -> Result<(), Error> {
+        match self.context.max_bytes() {
+            Some(limit) if self.context.is_full() => Err(Error::QueueFull {
+                queued_bytes: self.context.queued_bytes(),
+                limit,
+            }),
+            _ => Ok(()),
+        }
+    }
+
     pub fn dump_messages(&self) -> Vec<(&Msg, MessageId)> {
         self.context.msgs_iter().map(|m| (m.0, m.1)).collect()
     }
 
+    /// The queued messages, as persisted after a dialog request, see
+    /// [`EngineRegistry::save_log`].
+    fn message_log(&self) -> MessageLog {
+        MessageLog {
+            last_durably_received_client_event_offset: self
+                .last_durably_received_client_event_offset,
+            queue: self.context.clone(),
+            streaming: self.streaming,
+            max_request_size: self.max_request_size(),
+        }
+    }
+
+    /// Takes the final message if it was staged for a separate download.
+    pub fn take_staged_final(&mut self) -> Option<Msg> {
+        self.staged_final.take()
+    }
+
+    /// Whether the engine is done and can be dropped.
+    ///
+    /// Engines with follow-up rounds are only done once they are aborted (or deleted by the
+    /// client), see [`EngineRef::is_round_done`].
     pub fn is_done(&self) -> bool {
-        self.steps_remaining == 0
+        (self.rounds.is_none() && self.is_round_done()) || self.aborted
+    }
+
+    /// Whether the current round of the engine is completed.
+    pub fn is_round_done(&self) -> bool {
+        let confirmed = !self.confirm_transcript || self.session.transcript_confirmed.is_some();
+        let disclosed = !self.output_disclosure || self.output_disclosed;
+        let preprocessing = matches!(&self.rounds, Some(r) if r.first_round.is_some());
+        self.steps_remaining == 0 && confirmed && disclosed && !preprocessing && !self.aborted
+    }
+
+    /// Whether the client discloses the output of the session.
+    pub fn output_disclosure(&self) -> bool {
+        self.output_disclosure
+    }
+
+    /// The program of a session with follow-up rounds.
+    pub fn follow_up_program(&self) -> Option<&str> {
+        self.rounds.as_ref().map(|r| r.program.as_str())
+    }
+
+    /// Starts the next round of a session with follow-up rounds, returning the id of its first
+    /// message.
+    ///
+    /// The messages of the previous round are dropped, since the client must have received all
+    /// of them to complete the round.
+    pub fn next_round(
+        &mut self,
+        circuit: Circuit,
+        input: Vec<bool>,
+        on_output: Option<OutputHandler>,
+        function: String,
+        plaintext_metadata: String,
+    ) -> Result<MessageId, Error> {
+        if !self.is_round_done() {
+            return Err(Error::RoundInProgress);
+        }
+        let triples = match self.rounds.as_mut().and_then(|r| r.triples.as_mut()) {
+            Some(triples) => triples,
+            None => {
+                return Err(Error::NoFollowUp {
+                    remaining_and_gates: 0,
+                })
+            }
+        };
+        if !triples.fits(&circuit) {
+            let remaining_and_gates = triples.and_gates();
+            return Err(Error::NoFollowUp {
+                remaining_and_gates,
+            });
+        }
+        let round = triples.split_off(&circuit)?;
+        let first_id = self.context.next_id();
+        if let Some(last_id) = first_id.checked_sub(1) {
+            self.context.flush_queue(last_id);
+        }
+        self.outputs = circuit.output_gates().len();
+        self.on_output = on_output;
+        self.output_disclosed = false;
+        self.session.transcript_confirmed = None;
+        self.session.function = function;
+        self.session.plaintext_metadata = plaintext_metadata;
+        self.start_round(circuit, input, round)?;
+        Ok(first_id)
+    }
+
+    /// Counts a request of the client that could not be processed.
+    pub fn record_failure(&mut self) {
+        self.failures += 1;
+    }
+
+    /// Number of failed requests and protocol errors so far.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn streaming(&self) -> bool {
+        self.streaming
+    }
+
+    pub fn session(&self) -> &SessionInfo {
+        &self.session
+    }
+
+    /// The outcome of a finished engine and why it failed (if it failed).
+    pub fn outcome(&self) -> (AuditOutcome, Option<String>) {
+        match &self.abort_reason {
+            Some(reason) => (AuditOutcome::Failed, Some(reason.clone())),
+            None if self.is_round_done() => (AuditOutcome::Completed, None),
+            None => (
+                AuditOutcome::Failed,
+                Some("dropped before the protocol was completed".to_string()),
+            ),
+        }
+    }
+}
+
+/// Limits for clients whose requests repeatedly fail, configured as part of the Rocket config.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct FailurePolicy {
+    /// Number of failures after which an engine is dropped, counting both failed dialog requests
+    /// and protocol errors such as MAC check failures.
+    pub engine_failure_threshold: u32,
+    /// Seconds during which a client is rejected after one of its engines was dropped due to
+    /// failures, `0` disables blocking.
+    pub engine_failure_block_secs: u64,
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self {
+        Self {
+            engine_failure_threshold: 3,
+            engine_failure_block_secs: 0,
+        }
+    }
+}
+
+/// Limits of the bandwidth used by sessions, configured as part of the Rocket config.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct BandwidthPolicy {
+    /// Bytes per second that the server may send to each session, unlimited if not set.
+    pub session_bandwidth_limit: Option<u64>,
+    /// Bytes per second that the server may send to all sessions combined, unlimited if not set.
+    pub global_bandwidth_limit: Option<u64>,
+    /// Bytes that may be sent at once before the limits apply, defaults to a second's worth.
+    pub bandwidth_burst: Option<u64>,
+    /// Whether the request bodies of clients count towards (and are delayed by) the limits.
+    pub throttle_requests: bool,
+}
+
+impl BandwidthPolicy {
+    /// The capacity of a token bucket for the specified limit.
+    pub fn burst(&self, limit: u64) -> u64 {
+        self.bandwidth_burst.unwrap_or(limit)
+    }
+}
+
+/// Limits of the resources used by sessions, configured as part of the Rocket config.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct ResourceQuotas {
+    /// Maximum number of AND gates of the circuit of a single session, unlimited if not set.
+    pub max_session_and_gates: Option<usize>,
+    /// Maximum number of AND gates of the circuits of all running sessions combined, unlimited if
+    /// not set.
+    pub max_total_and_gates: Option<usize>,
+    /// Maximum number of bytes queued for a session until its client acknowledges them, unlimited
+    /// if not set. Sessions whose queue is full do not process further messages of their client
+    /// until it acknowledges earlier messages.
+    pub max_queue_bytes: Option<usize>,
+}
+
+/// How long sessions can be looked up by the idempotency keys of their clients and how long
+/// finished sessions answer repeated requests, configured as part of the Rocket config.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct IdempotencyPolicy {
+    /// Seconds during which a repeated request with the same idempotency key returns the session
+    /// created by the first request, `0` ignores idempotency keys.
+    pub idempotency_ttl_secs: u64,
+    /// Seconds during which a finished session answers repeated dialog requests with its last
+    /// messages, so that clients whose last response was lost can retry, `0` drops sessions as
+    /// soon as they are done.
+    pub redelivery_ttl_secs: u64,
+}
+
+impl Default for IdempotencyPolicy {
+    fn default() -> Self {
+        Self {
+            idempotency_ttl_secs: 600,
+            redelivery_ttl_secs: 60,
+        }
+    }
+}
+
+/// How many programs are stored for clients that reference programs by the hash of their circuit,
+/// configured as part of the Rocket config.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct ProgramStorePolicy {
+    /// Maximum number of programs stored for clients with the `program_store` capability, the
+    /// least recently used program is evicted first. `0` stores no programs.
+    pub max_stored_programs: usize,
+}
+
+impl Default for ProgramStorePolicy {
+    fn default() -> Self {
+        Self {
+            max_stored_programs: 256,
+        }
+    }
+}
+
+/// The programs that clients can reference by the hash of a circuit compiled from them, see the
+/// `program_store` capability of [`crate::CAPABILITIES`].
+struct ProgramStore {
+    policy: ProgramStorePolicy,
+    /// Pre-registered using [`ServerConfig::with_program`], never evicted.
+    preregistered: HashMap<CircuitBlake3Hash, String>,
+    /// Stored for the sessions of clients, together with the time they were last used.
+    stored: Mutex<HashMap<CircuitBlake3Hash, (String, Instant)>>,
+}
+
+impl ProgramStore {
+    fn get(&self, circuit_hash: &CircuitBlake3Hash) -> Option<String> {
+        if let Some(program) = self.preregistered.get(circuit_hash) {
+            return Some(program.clone());
+        }
+        let mut stored = self.stored.lock().unwrap();
+        let (program, last_used) = stored.get_mut(circuit_hash)?;
+        *last_used = Instant::now();
+        Some(program.clone())
+    }
+
+    fn insert(&self, circuit_hash: CircuitBlake3Hash, program: &str) {
+        if self.policy.max_stored_programs == 0 || self.preregistered.contains_key(&circuit_hash) {
+            return;
+        }
+        let mut stored = self.stored.lock().unwrap();
+        if !stored.contains_key(&circuit_hash) && stored.len() >= self.policy.max_stored_programs {
+            let least_recently_used = stored
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(hash, _)| *hash);
+            if let Some(hash) = least_recently_used {
+                stored.remove(&hash);
+            }
+        }
+        stored.insert(circuit_hash, (program.to_string(), Instant::now()));
+    }
+}
+
+/// The static key of the encrypted channels of sessions, configured as part of the Rocket config.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct ChannelPolicy {
+    /// The hex-encoded secret key authenticating the server to clients that pinned its public
+    /// key, encrypted channels are unauthenticated if not set.
+    pub channel_secret_key: Option<String>,
+}
+
+impl ChannelPolicy {
+    /// Parses the secret key, if any.
+    pub fn key_pair(&self) -> Result<Option<ChannelKeyPair>, String> {
+        match &self.channel_secret_key {
+            Some(key) => match blake3::Hash::from_hex(key) {
+                Ok(key) => Ok(Some(ChannelKeyPair::from_secret_bytes(*key.as_bytes()))),
+                Err(e) => Err(e.to_string()),
+            },
+            None => Ok(None),
+        }
+    }
+}
+
+/// A session created for a request with an idempotency key.
+struct IdempotentSession {
+    /// The hash of the request, repeated requests must be identical.
+    request_hash: blake3::Hash,
+    /// `None` while the session of the first request is still being created.
+    created: Option<EngineCreationResult>,
+    expires: Instant,
+}
+
+/// The outcome of looking up the idempotency key of a request, see
+/// [`EngineRegistry::idempotent_session`].
+pub(crate) enum Idempotency<'a> {
+    /// The session created by an earlier request with the same key.
+    Created(Box<EngineCreationResult>),
+    /// The key is reserved for this request until the reservation is completed or dropped.
+    Reserved(IdempotencyReservation<'a>),
+}
+
+/// Reserves the idempotency key of a request (if any) while its session is being created, so
+/// that concurrent requests with the same key wait for the session instead of creating another.
+///
+/// Dropping the reservation without completing it releases the key, so that waiting requests
+/// try to create the session themselves.
+pub(crate) struct IdempotencyReservation<'a> {
+    registry: &'a EngineRegistry,
+    key: Option<String>,
+}
+
+impl IdempotencyReservation<'_> {
+    /// Stores the created session, returning it to all requests with the same key until it
+    /// expires.
+    pub(crate) fn complete(mut self, created: &EngineCreationResult) {
+        if let Some(key) = self.key.take() {
+            let ttl = Duration::from_secs(self.registry.idempotency.idempotency_ttl_secs);
+            let mut sessions = self.registry.idempotent_sessions.lock().unwrap();
+            if let Some(session) = sessions.get_mut(&key) {
+                session.created = Some(created.clone());
+                session.expires = Instant::now() + ttl;
+            }
+            self.registry.idempotent_sessions_changed.notify_all();
+        }
+    }
+}
+
+impl Drop for IdempotencyReservation<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut sessions = self.registry.idempotent_sessions.lock().unwrap();
+            sessions.remove(&key);
+            self.registry.idempotent_sessions_changed.notify_all();
+        }
+    }
+}
+
+/// A finished session, kept to answer repeated dialog requests of its client.
+struct FinishedEngine {
+    engine: Arc<Mutex<EngineRef>>,
+    expires: Instant,
+}
+
+/// The key of the message log of an engine in the [`crate::SessionStore`].
+fn log_key(engine_id: &EngineId) -> String {
+    format!("tandem:log:{engine_id}")
+}
+
+/// The hash of the JSON serialization of the request, which (unlike the request) can be kept.
+fn request_hash(request: &NewSession) -> blake3::Hash {
+    blake3::hash(&serde_json::to_vec(request).unwrap_or_default())
+}
+
+/// Whether sessions need to be approved before they can be created, configured as part of the
+/// Rocket config.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct ApprovalPolicy {
+    /// Bearer token used to approve or reject requests, sessions require an approval if set.
+    pub approval_token: Option<String>,
+}
+
+/// The programs that clients may run, configured as part of the Rocket config.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct ProgramAllowlist {
+    /// Programs that clients may run, all programs may be run if not set.
+    pub allowed_programs: Option<Vec<AllowedProgram>>,
+}
+
+impl ProgramAllowlist {
+    /// An allowlist that rejects all programs.
+    pub fn deny_all() -> Self {
+        Self {
+            allowed_programs: Some(vec![]),
+        }
+    }
+}
+
+/// A program that clients may run, identified by the hash of its source code (or a raw circuit,
+/// identified by its circuit hash).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct AllowedProgram {
+    /// The hex-encoded blake3 hash of the program's source code or of the raw circuit.
+    pub program_hash: String,
+    /// Maximum number of AND gates of the compiled circuit, unlimited if not set.
+    #[serde(default)]
+    pub max_and_gates: Option<usize>,
+}
+
+/// What is reported to clients whose circuit does not match the server's circuit, configured as
+/// part of the Rocket config.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct MismatchDiagnostics {
+    /// Whether the stats of the server's circuit are reported.
+    pub circuit_diagnostics: bool,
+    /// Version of the compiler that compiled the server's circuits, e.g. `garble_lang 0.1.8`.
+    pub compiler_version: Option<String>,
+}
+
+impl Default for MismatchDiagnostics {
+    fn default() -> Self {
+        Self {
+            circuit_diagnostics: true,
+            compiler_version: None,
+        }
     }
 }
 
+/// How many engines process the messages of their clients at the same time, configured as part
+/// of the Rocket config.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct ExecutionPolicy {
+    /// Maximum number of dialog requests whose messages are processed at the same time, each on
+    /// one of Rocket's blocking threads, defaults to the number of available CPU cores. Further
+    /// requests wait until one of them is done.
+    pub engine_threads: Option<usize>,
+}
+
+impl ExecutionPolicy {
+    /// The configured number of threads, at least one.
+    pub fn threads(&self) -> usize {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        self.engine_threads.unwrap_or(cores).max(1)
+    }
+}
+
+/// Whether the server reports how long it took to respond to the dialog requests of clients,
+/// configured as part of the Rocket config.
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct TimingDiagnostics {
+    /// Whether dialog responses include a `Server-Timing` header with the time spent reading,
+    /// throttling and processing the messages of the client and the size of the session's queue.
+    pub server_timing: bool,
+}
+
 pub(crate) struct EngineRegistry {
     registry: RwLock<HashMap<EngineId, Arc<Mutex<EngineRef>>>>,
     handler: HandleMpcRequestFn,
+    id_generator: Box<dyn IdGenerator>,
+    stickiness: Option<Box<dyn StickinessProvider>>,
+    readiness: Option<ReadinessFn>,
+    catalog: Option<CatalogFn>,
+    audit_sink: Option<Box<dyn AuditSink>>,
+    external_ids: Mutex<HashMap<String, EngineId>>,
+    policy: FailurePolicy,
+    approval_policy: ApprovalPolicy,
+    allowlist: ProgramAllowlist,
+    diagnostics: MismatchDiagnostics,
+    timing: TimingDiagnostics,
+    /// Permits to process messages on a blocking thread, see [`ExecutionPolicy`].
+    engine_threads: Arc<Semaphore>,
+    throttle: Throttle,
+    quotas: ResourceQuotas,
+    idempotency: IdempotencyPolicy,
+    channel_key: Option<ChannelKeyPair>,
+    programs: ProgramStore,
+    idempotent_sessions: Mutex<HashMap<String, IdempotentSession>>,
+    /// Notifies requests waiting for a session that is being created for their idempotency key.
+    idempotent_sessions_changed: Condvar,
+    /// The AND gates of each running engine, counting towards the total quota.
+    and_gates: Mutex<HashMap<EngineId, usize>>,
+    blocked_clients: Mutex<HashMap<IpAddr, Instant>>,
+    staged_finals: Mutex<HashMap<EngineId, Msg>>,
+    finished_engines: Mutex<HashMap<EngineId, FinishedEngine>>,
+    approvals: Mutex<HashMap<String, Approval>>,
+    /// Persists the message logs of all engines, if configured.
+    store: Option<Arc<dyn SessionStore>>,
+    /// How long the message log of a running engine is stored without being used.
+    session_ttl: Duration,
+    /// Stores the sessions shared by all instances, if configured.
+    shared: Option<SharedSessions>,
+}
+
+/// The engine that answers a dialog request, see [`EngineRegistry::lookup_dialog`].
+pub(crate) enum DialogEngine {
+    Engine(Arc<Mutex<EngineRef>>),
+    /// The persisted messages of an engine that is gone, which can only answer repeated requests.
+    Log(MessageLog),
 }
 
 impl EngineRegistry {
-    pub(crate) fn new(handler: HandleMpcRequestFn) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        handler: HandleMpcRequestFn,
+        config: ServerConfig,
+        policy: FailurePolicy,
+        approval_policy: ApprovalPolicy,
+        allowlist: ProgramAllowlist,
+        diagnostics: MismatchDiagnostics,
+        timing: TimingDiagnostics,
+        execution: ExecutionPolicy,
+        bandwidth: BandwidthPolicy,
+        quotas: ResourceQuotas,
+        idempotency: IdempotencyPolicy,
+        channel_key: Option<ChannelKeyPair>,
+        program_store: ProgramStorePolicy,
+        store: Option<Arc<dyn SessionStore>>,
+        session_ttl: Duration,
+        shared: Option<SharedSessions>,
+    ) -> Self {
         Self {
             registry: RwLock::new(HashMap::new()),
             handler,
+            id_generator: config.id_generator,
+            stickiness: config.stickiness,
+            readiness: config.readiness,
+            catalog: config.catalog,
+            audit_sink: config.audit_sink,
+            external_ids: Mutex::new(HashMap::new()),
+            policy,
+            approval_policy,
+            allowlist,
+            diagnostics,
+            timing,
+            engine_threads: Arc::new(Semaphore::new(execution.threads())),
+            throttle: Throttle::new(bandwidth),
+            quotas,
+            idempotency,
+            channel_key,
+            programs: ProgramStore {
+                policy: program_store,
+                preregistered: config.programs,
+                stored: Mutex::new(HashMap::new()),
+            },
+            idempotent_sessions: Mutex::new(HashMap::new()),
+            idempotent_sessions_changed: Condvar::new(),
+            and_gates: Mutex::new(HashMap::new()),
+            blocked_clients: Mutex::new(HashMap::new()),
+            staged_finals: Mutex::new(HashMap::new()),
+            finished_engines: Mutex::new(HashMap::new()),
+            approvals: Mutex::new(HashMap::new()),
+            store,
+            session_ttl,
+            shared,
         }
     }
 
-    pub(crate) fn insert_engine(&self, engine_id: EngineId, engine: Arc<Mutex<EngineRef>>) -> bool {
+    /// Whether sessions are shared by all instances, see [`crate::SessionStore`].
+    pub(crate) fn is_shared(&self) -> bool {
+        self.shared.is_some()
+    }
+
+    /// Whether the capability can be negotiated for new sessions.
+    ///
+    /// Shared sessions do not support the capabilities whose state cannot be stored: encrypted
+    /// channels and follow-up rounds. Disclosed outputs are supported, the output handler is
+    /// chosen again by the instance that receives the output, see [`EngineRef::output_request`].
+    pub(crate) fn supports(&self, capability: &str) -> bool {
+        !self.is_shared() || !matches!(capability, "encrypted_channel" | "multi_round")
+    }
+
+    /// Returns the engine id and the external id (if any) for a new session.
+    pub(crate) fn new_ids(
+        &self,
+        request: &MpcRequest,
+    ) -> Result<(EngineId, Option<String>), Error> {
+        let engine_id = self.id_generator.engine_id(request);
+        let is_valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '~');
+        if engine_id.is_empty() || !engine_id.chars().all(is_valid) {
+            error!("Invalid engine id generated: '{engine_id}'");
+            return Err(Error::Internal {
+                message: "invalid engine id".to_string(),
+            });
+        }
+        Ok((engine_id, self.id_generator.external_id(request)))
+    }
+
+    /// Inserts the engine, which can be looked up by its external id (if any) while it is running.
+    ///
+    /// The AND gates of the engine count towards the total quota until the engine is dropped.
+    pub(crate) fn insert_engine(
+        &self,
+        engine_id: EngineId,
+        external_id: Option<String>,
+        engine: Arc<Mutex<EngineRef>>,
+    ) -> Result<(), Error> {
+        let engine_and_gates = engine.lock().unwrap().and_gates;
         let mut r = self.registry.write().unwrap();
-        if let Entry::Vacant(e) = r.entry(engine_id) {
-            e.insert(engine);
-            true
-        } else {
-            false
+        let mut external_ids = self.external_ids.lock().unwrap();
+        let mut and_gates = self.and_gates.lock().unwrap();
+        if r.contains_key(&engine_id) {
+            return Err(Error::DuplicateEngineId { engine_id });
         }
+        if let Some(limit) = self.quotas.max_total_and_gates {
+            let requested = and_gates.values().sum::<usize>() + engine_and_gates;
+            if requested > limit {
+                return Err(Error::QuotaExceeded {
+                    quota: Quota::TotalAndGates,
+                    limit,
+                    requested,
+                });
+            }
+        }
+        if let Some(external_id) = external_id {
+            match external_ids.entry(external_id) {
+                Entry::Vacant(e) => e.insert(engine_id.clone()),
+                Entry::Occupied(e) => {
+                    return Err(Error::DuplicateExternalId {
+                        external_id: e.key().clone(),
+                    })
+                }
+            };
+        }
+        and_gates.insert(engine_id.clone(), engine_and_gates);
+        r.insert(engine_id, engine);
+        Ok(())
     }
 
     pub(crate) fn drop_engine(&self, engine_id: &EngineId) -> bool {
         let mut r = self.registry.write().unwrap();
-        r.remove(engine_id).is_some()
+        let removed = r.remove(engine_id).is_some();
+        if removed {
+            let mut external_ids = self.external_ids.lock().unwrap();
+            external_ids.retain(|_, id| id != engine_id);
+            self.and_gates.lock().unwrap().remove(engine_id);
+        }
+        removed
+    }
+
+    /// Checks that the circuit of a new session stays within the configured quotas, before an
+    /// engine is created for it.
+    ///
+    /// The total quota is checked again when the engine is inserted, as other sessions might be
+    /// created in the meantime.
+    pub(crate) fn check_quotas(&self, circuit: &Circuit) -> Result<(), Error> {
+        let requested = circuit.and_gates();
+        if let Some(limit) = self.quotas.max_session_and_gates {
+            if requested > limit {
+                return Err(Error::QuotaExceeded {
+                    quota: Quota::SessionAndGates,
+                    limit,
+                    requested,
+                });
+            }
+        }
+        if let Some(limit) = self.quotas.max_total_and_gates {
+            let requested = self.and_gates.lock().unwrap().values().sum::<usize>() + requested;
+            if requested > limit {
+                return Err(Error::QuotaExceeded {
+                    quota: Quota::TotalAndGates,
+                    limit,
+                    requested,
+                });
+            }
+        }
+        if let Some(limit) = self.quotas.max_queue_bytes {
+            // sessions whose messages cannot be queued at all would fail halfway:
+            let plan = ProtocolPlan::new(circuit);
+            let hints = plan.message_size_hints();
+            let requested = hints.iter().map(|h| h.contributor).max().unwrap_or(0);
+            if requested > limit {
+                return Err(Error::QuotaExceeded {
+                    quota: Quota::QueueBytes,
+                    limit,
+                    requested,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the engine id of the running session with the specified external id.
+    pub(crate) fn resolve_external_id(&self, external_id: &str) -> Result<EngineId, Error> {
+        let engine_id = self.external_ids.lock().unwrap().get(external_id).cloned();
+        let engine_id = match (engine_id, &self.shared) {
+            (None, Some(shared)) => shared.load_external_id(external_id)?,
+            (engine_id, _) => engine_id,
+        };
+        engine_id.ok_or_else(|| Error::NoSuchExternalId {
+            external_id: external_id.to_string(),
+        })
     }
 
+    /// Looks up the running engine, restoring it from the session store if it is shared.
     pub(crate) fn lookup(&self, engine_id: &EngineId) -> Result<Arc<Mutex<EngineRef>>, Error> {
+        self.lookup_local(engine_id)
+            .or_else(|e| self.lookup_shared(engine_id)?.ok_or(e))
+    }
+
+    fn lookup_local(&self, engine_id: &EngineId) -> Result<Arc<Mutex<EngineRef>>, Error> {
         let r = self.registry.read().unwrap();
         match r.get(engine_id).map(Arc::clone) {
             Some(e) => Ok(e),
@@ -109,7 +1338,703 @@ impl EngineRegistry {
         }
     }
 
+    /// Looks up the engine of a dialog request, which might have finished recently if the client
+    /// repeats a request whose response was lost, see [`EngineRegistry::finish_engine`].
+    ///
+    /// If the engine is gone (because the server was restarted or the engine was run by another
+    /// instance), only its persisted message log is returned, see [`EngineRegistry::save_log`].
+    pub(crate) fn lookup_dialog(&self, engine_id: &EngineId) -> Result<DialogEngine, Error> {
+        let e = match self.lookup(engine_id) {
+            Ok(engine) => return Ok(DialogEngine::Engine(engine)),
+            Err(e) => e,
+        };
+        let finished = {
+            let mut finished = self.finished_engines.lock().unwrap();
+            let now = Instant::now();
+            finished.retain(|_, engine| engine.expires > now);
+            finished
+                .get(engine_id)
+                .map(|finished| Arc::clone(&finished.engine))
+        };
+        if let Some(engine) = finished {
+            return Ok(DialogEngine::Engine(engine));
+        }
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Err(e),
+        };
+        match store.load(&log_key(engine_id)).map_err(store_failed)? {
+            Some((_, log)) => Ok(DialogEngine::Log(bincode::deserialize(&log)?)),
+            None => Err(e),
+        }
+    }
+
+    /// Persists the message log of the engine after it processed a request of its client, so that
+    /// the request can be answered again even if the engine is gone, see
+    /// [`EngineRegistry::lookup_dialog`]. Does nothing unless a [`crate::SessionStore`] is
+    /// configured.
+    ///
+    /// The log of a running engine expires after the configured `session_ttl_secs` without
+    /// requests, the log of a finished engine after its `redelivery_ttl_secs`.
+    pub(crate) fn save_log(
+        &self,
+        engine_id: &EngineId,
+        engine: &mut EngineRef,
+    ) -> Result<(), Error> {
+        let store = match &self.store {
+            Some(store) => store,
+            None => return Ok(()),
+        };
+        let key = log_key(engine_id);
+        let ttl = if engine.is_done() {
+            Duration::from_secs(self.idempotency.redelivery_ttl_secs)
+        } else {
+            self.session_ttl
+        };
+        if ttl.is_zero() {
+            return store.remove(&key).map_err(store_failed);
+        }
+        let log = bincode::serialize(&engine.message_log())?;
+        let version = engine.log_version + 1;
+        if !store
+            .store(&key, version, &log, ttl)
+            .map_err(store_failed)?
+        {
+            // another instance processed a request of the shared session since it was restored:
+            if engine.shared.is_some() {
+                self.drop_engine(engine_id);
+                self.finished_engines.lock().unwrap().remove(engine_id);
+                return Err(Error::SessionConflict {
+                    engine_id: engine_id.clone(),
+                });
+            }
+            error!("The message log of {engine_id} was stored by another engine");
+            return Err(Error::Internal {
+                message: "the message log could not be stored".to_string(),
+            });
+        }
+        engine.log_version = version;
+        Ok(())
+    }
+
+    /// Removes the message log of an engine that was dropped, logging any failure.
+    pub(crate) fn remove_log(&self, engine_id: &EngineId) {
+        if let Some(store) = &self.store {
+            if let Err(e) = store.remove(&log_key(engine_id)) {
+                warn!("Could not remove the message log of {engine_id}: {e}");
+            }
+        }
+    }
+
+    /// Restores a shared engine from the session store, if there is such a session.
+    ///
+    /// The engine is inserted into the registry until it is stored again, see
+    /// [`EngineRegistry::save_engine`].
+    fn lookup_shared(&self, engine_id: &EngineId) -> Result<Option<Arc<Mutex<EngineRef>>>, Error> {
+        let shared = match &self.shared {
+            Some(shared) => shared,
+            None => return Ok(None),
+        };
+        let (version, stored) = match shared.load_engine(engine_id)? {
+            Some(stored) => stored,
+            None => return Ok(None),
+        };
+        let bandwidth = self.session_bandwidth();
+        let mut engine = EngineRef::from_shared(version, stored, shared.state_key(), bandwidth)?;
+        if let Some(request) = engine.output_request()? {
+            let handled = self
+                .handle_input(request)
+                .map_err(Error::MpcRequestRejected)?;
+            engine.on_output = handled.on_output;
+        }
+        // another request of the session might have restored the engine in the meantime:
+        let mut r = self.registry.write().unwrap();
+        let engine = match r.entry(engine_id.clone()) {
+            Entry::Occupied(e) => Arc::clone(e.get()),
+            Entry::Vacant(e) => {
+                if let Some(external_id) = &engine.session.external_id {
+                    let mut external_ids = self.external_ids.lock().unwrap();
+                    external_ids.insert(external_id.clone(), engine_id.clone());
+                }
+                let mut and_gates = self.and_gates.lock().unwrap();
+                and_gates.insert(engine_id.clone(), engine.and_gates);
+                Arc::clone(e.insert(Arc::new(Mutex::new(engine))))
+            }
+        };
+        Ok(Some(engine))
+    }
+
+    /// Stores a shared engine after it processed a request of its client, so that any instance
+    /// can process the next request, and drops it from this instance. Does nothing unless the
+    /// engine is shared.
+    ///
+    /// Finished and dropped engines are removed from the session store, the repeated requests of
+    /// finished engines are answered from their message log, see [`EngineRegistry::save_log`].
+    /// Fails with [`Error::SessionConflict`] if another instance stored the engine since it was
+    /// restored, in which case the messages of the engine must not be sent to the client.
+    pub(crate) fn save_engine(
+        &self,
+        engine_id: &EngineId,
+        engine: &mut EngineRef,
+    ) -> Result<(), Error> {
+        let (shared, version) = match (&self.shared, &engine.shared) {
+            (Some(shared), Some(state)) => (shared, state.version),
+            _ => return Ok(()),
+        };
+        if !self.registry.read().unwrap().contains_key(engine_id) {
+            let external_id = engine.session.external_id.clone();
+            self.remove_shared(engine_id, external_id.as_deref());
+            engine.shared = None;
+            return Ok(());
+        }
+        let stored = engine.to_shared(shared.state_key()).and_then(|stored| {
+            shared.store_engine(engine_id, version + 1, &stored, shared.session_ttl)
+        });
+        self.drop_engine(engine_id);
+        if !stored? {
+            return Err(Error::SessionConflict {
+                engine_id: engine_id.clone(),
+            });
+        }
+        if let (0, Some(external_id)) = (version, &engine.session.external_id) {
+            // the external id resolves to the session of another instance:
+            if let Err(e) = shared.store_external_id(external_id, engine_id) {
+                self.remove_shared(engine_id, None);
+                return Err(e);
+            }
+        }
+        engine.shared.as_mut().expect("shared engine").version = version + 1;
+        Ok(())
+    }
+
+    /// Removes a shared engine that was dropped from the session store, logging any failure.
+    pub(crate) fn remove_shared(&self, engine_id: &EngineId, external_id: Option<&str>) {
+        if let Some(shared) = &self.shared {
+            let removed = shared.remove_engine(engine_id);
+            let removed = match (removed, external_id) {
+                (Ok(()), Some(external_id)) => shared.remove_external_id(external_id),
+                (removed, _) => removed,
+            };
+            if removed.is_err() {
+                warn!("Could not remove the shared session {engine_id}");
+            }
+        }
+    }
+
+    /// Drops the finished engine like [`EngineRegistry::drop_engine`], but keeps answering the
+    /// dialog requests of its client for the configured `redelivery_ttl_secs`.
+    pub(crate) fn finish_engine(&self, engine_id: &EngineId) -> bool {
+        let engine = match self.registry.read().unwrap().get(engine_id) {
+            Some(engine) => Arc::clone(engine),
+            None => return false,
+        };
+        if !self.drop_engine(engine_id) {
+            return false;
+        }
+        if self.idempotency.redelivery_ttl_secs > 0 {
+            let ttl = Duration::from_secs(self.idempotency.redelivery_ttl_secs);
+            let mut finished = self.finished_engines.lock().unwrap();
+            let now = Instant::now();
+            finished.retain(|_, engine| engine.expires > now);
+            let expires = now + ttl;
+            finished.insert(engine_id.clone(), FinishedEngine { engine, expires });
+        }
+        true
+    }
+
+    /// Drops the engine if it exceeded the failure threshold, emitting an audit event and
+    /// temporarily blocking the client if configured.
+    pub(crate) fn check_failures(
+        &self,
+        engine_id: &EngineId,
+        engine: &EngineRef,
+        client: Option<IpAddr>,
+    ) {
+        if engine.failures() < self.policy.engine_failure_threshold {
+            return;
+        }
+        if self.drop_engine(engine_id) {
+            self.remove_log(engine_id);
+            let external_id = engine.session().external_id.as_deref();
+            self.remove_shared(engine_id, external_id);
+            let error = format!("dropped after {} failures", engine.failures());
+            self.audit(engine.session(), AuditOutcome::Failed, Some(error));
+        }
+        let client_str = client.map_or("unknown".to_string(), |ip| ip.to_string());
+        warn!(
+            "Audit: dropped engine {engine_id} after {} failures (client: {client_str})",
+            engine.failures()
+        );
+        if let (Some(ip), true) = (client, self.policy.engine_failure_block_secs > 0) {
+            let until = Instant::now() + Duration::from_secs(self.policy.engine_failure_block_secs);
+            self.blocked_clients.lock().unwrap().insert(ip, until);
+            warn!(
+                "Audit: blocked client {ip} for {} seconds",
+                self.policy.engine_failure_block_secs
+            );
+        }
+    }
+
+    /// Returns the session created for an earlier request with the same idempotency key, if it has
+    /// not expired yet, waiting for it if the earlier request is still creating the session.
+    /// Otherwise reserves the key for this request.
+    pub(crate) fn idempotent_session(
+        &self,
+        request: &NewSession,
+    ) -> Result<Idempotency<'_>, Error> {
+        let key = match &request.idempotency_key {
+            Some(key) if self.idempotency.idempotency_ttl_secs > 0 => key,
+            _ => {
+                return Ok(Idempotency::Reserved(IdempotencyReservation {
+                    registry: self,
+                    key: None,
+                }))
+            }
+        };
+        let request_hash = request_hash(request);
+        let mut sessions = self.idempotent_sessions.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            // reserved keys are kept until their reservation is completed or dropped:
+            sessions.retain(|_, session| session.created.is_none() || session.expires > now);
+            match sessions.get(key) {
+                Some(session) if session.request_hash != request_hash => {
+                    return Err(Error::IdempotencyKeyReused {
+                        idempotency_key: key.clone(),
+                    })
+                }
+                Some(IdempotentSession {
+                    created: Some(created),
+                    ..
+                }) => return Ok(Idempotency::Created(Box::new(created.clone()))),
+                Some(_) => sessions = self.idempotent_sessions_changed.wait(sessions).unwrap(),
+                None => {
+                    let session = IdempotentSession {
+                        request_hash,
+                        created: None,
+                        expires: now,
+                    };
+                    sessions.insert(key.clone(), session);
+                    return Ok(Idempotency::Reserved(IdempotencyReservation {
+                        registry: self,
+                        key: Some(key.clone()),
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Returns an error if the client is (still) blocked due to repeated failures.
+    pub(crate) fn check_client(&self, client: Option<IpAddr>) -> Result<(), Error> {
+        let ip = match client {
+            Some(ip) => ip,
+            None => return Ok(()),
+        };
+        let mut blocked = self.blocked_clients.lock().unwrap();
+        let now = Instant::now();
+        blocked.retain(|_, until| *until > now);
+        if blocked.contains_key(&ip) {
+            Err(Error::ClientBlocked)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Stages the final message of the engine, to be downloaded once using
+    /// [`EngineRegistry::download_final`].
+    ///
+    /// The final messages of shared sessions are staged in the session store, so that they can be
+    /// downloaded from any instance.
+    pub(crate) fn stage_final(&self, engine_id: EngineId, msg: Msg) -> Result<(), Error> {
+        match &self.shared {
+            Some(shared) => shared.store_final(&engine_id, &msg),
+            None => {
+                self.staged_finals.lock().unwrap().insert(engine_id, msg);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the requested range of the staged final message of the engine.
+    ///
+    /// The message is removed as soon as its last byte has been returned, interrupted downloads
+    /// can be resumed by requesting the remaining range.
+    pub(crate) fn download_final(
+        &self,
+        engine_id: &EngineId,
+        range: Option<ByteRange>,
+    ) -> Result<Download, Error> {
+        let mut staged = self.staged_finals.lock().unwrap();
+        let shared = match &self.shared {
+            Some(shared) => shared.load_final(engine_id)?,
+            None => None,
+        };
+        let msg = shared
+            .as_ref()
+            .or_else(|| staged.get(engine_id))
+            .ok_or_else(|| Error::NoSuchEngineId {
+                engine_id: engine_id.clone(),
+            })?;
+        let total = msg.len();
+        let (start, end) = match range {
+            Some(ByteRange { start, end }) => {
+                let end = end.map_or(total, |end| end.saturating_add(1).min(total));
+                if start >= end {
+                    return Err(Error::RangeNotSatisfiable { total });
+                }
+                (start, end)
+            }
+            None => (0, total),
+        };
+        let download = Download {
+            bytes: msg[start..end].to_vec(),
+            range: range.map(|_| (start, end)),
+            total,
+        };
+        if end == total {
+            staged.remove(engine_id);
+            if let Some(shared) = &self.shared {
+                shared.remove_final(engine_id)?;
+            }
+        }
+        Ok(download)
+    }
+
+    /// Stores the request of a client as pending until it is approved or rejected.
+    pub(crate) fn request_approval(
+        &self,
+        approval_id: String,
+        request: NewApproval,
+    ) -> Result<Approval, Error> {
+        if self.approval_policy.approval_token.is_none() {
+            return Err(Error::ApprovalsDisabled);
+        }
+        let approval = Approval {
+            approval_id: approval_id.clone(),
+            status: ApprovalStatus::Pending,
+            program_hash: request.program_hash,
+            function: request.function,
+            plaintext_metadata: request.plaintext_metadata,
+            input_commitment: request.input_commitment,
+        };
+        info!(
+            "Audit: approval {approval_id} requested for function '{}' (input commitment: {})",
+            approval.function,
+            blake3::Hash::from(approval.input_commitment).to_hex()
+        );
+        let mut approvals = self.approvals.lock().unwrap();
+        if let Entry::Vacant(e) = approvals.entry(approval_id) {
+            e.insert(approval.clone());
+            Ok(approval)
+        } else {
+            Err(Error::Internal {
+                message: "duplicate approval id".to_string(),
+            })
+        }
+    }
+
+    pub(crate) fn approval(&self, approval_id: &str) -> Result<Approval, Error> {
+        let approvals = self.approvals.lock().unwrap();
+        approvals
+            .get(approval_id)
+            .cloned()
+            .ok_or_else(|| Error::NoSuchApproval {
+                approval_id: approval_id.to_string(),
+            })
+    }
+
+    /// Approves or rejects a pending request, if the token matches the configured token.
+    pub(crate) fn decide_approval(
+        &self,
+        approval_id: &str,
+        token: &str,
+        approve: bool,
+    ) -> Result<Approval, Error> {
+        let expected = match &self.approval_policy.approval_token {
+            Some(token) => token,
+            None => return Err(Error::ApprovalsDisabled),
+        };
+        // comparing the hashes keeps the comparison constant-time:
+        if blake3::hash(token.as_bytes()) != blake3::hash(expected.as_bytes()) {
+            return Err(Error::Unauthorized);
+        }
+        let mut approvals = self.approvals.lock().unwrap();
+        let approval = approvals
+            .get_mut(approval_id)
+            .ok_or_else(|| Error::NoSuchApproval {
+                approval_id: approval_id.to_string(),
+            })?;
+        if approval.status != ApprovalStatus::Pending {
+            return Err(Error::ApprovalAlreadyDecided {
+                approval_id: approval_id.to_string(),
+            });
+        }
+        approval.status = if approve {
+            ApprovalStatus::Approved
+        } else {
+            ApprovalStatus::Rejected
+        };
+        info!("Audit: approval {approval_id} {:?}", approval.status);
+        Ok(approval.clone())
+    }
+
+    /// Checks that the session was approved (if approvals are required) and consumes the approval,
+    /// so that it can only be used for a single session.
+    pub(crate) fn take_approval(&self, session: &NewSession) -> Result<(), Error> {
+        if self.approval_policy.approval_token.is_none() {
+            return Ok(());
+        }
+        let approval_id = session
+            .approval_id
+            .as_ref()
+            .ok_or(Error::ApprovalRequired)?;
+        let mut approvals = self.approvals.lock().unwrap();
+        let approval = approvals
+            .get(approval_id)
+            .ok_or_else(|| Error::NoSuchApproval {
+                approval_id: approval_id.clone(),
+            })?;
+        if approval.status != ApprovalStatus::Approved {
+            return Err(Error::ApprovalNotGranted {
+                approval_id: approval_id.clone(),
+            });
+        }
+        if approval.program_hash != session.program_hash()
+            || approval.function != session.function
+            || approval.plaintext_metadata != session.plaintext_metadata_string()
+        {
+            return Err(Error::ApprovalMismatch);
+        }
+        approvals.remove(approval_id);
+        Ok(())
+    }
+
+    /// Returns the request with the program that the client referenced by its circuit hash, if the
+    /// client did not send the program itself, see [`EngineRegistry::store_program`].
+    pub(crate) fn resolve_program<'a>(
+        &self,
+        request: &'a NewSession,
+    ) -> Result<Cow<'a, NewSession>, Error> {
+        if !request.program.is_empty() || request.circuit.is_some() {
+            return Ok(Cow::Borrowed(request));
+        }
+        match self.programs.get(&request.circuit_hash) {
+            Some(program) => Ok(Cow::Owned(NewSession {
+                program,
+                ..request.clone()
+            })),
+            None => Err(Error::UnknownProgram {
+                circuit_hash: blake3::Hash::from(request.circuit_hash)
+                    .to_hex()
+                    .to_string(),
+            }),
+        }
+    }
+
+    /// Stores the program of a session under the hash of its circuit (once the handler has
+    /// compiled the program to a circuit with this hash), so that later sessions of the client can
+    /// reference the program by the hash alone.
+    pub(crate) fn store_program(&self, circuit_hash: CircuitBlake3Hash, program: &str) {
+        self.programs.insert(circuit_hash, program);
+    }
+
+    /// Checks that the program with the hash (see [`NewSession::program_hash`]) is allowed (if an
+    /// allowlist is configured), before it is compiled by the handler.
+    pub(crate) fn check_program(&self, program_hash: &[u8; 32]) -> Result<(), Error> {
+        self.allowed_program(program_hash).map(|_| ())
+    }
+
+    /// Checks that the compiled circuit of an allowed program does not exceed the maximum number
+    /// of AND gates of the program.
+    pub(crate) fn check_program_circuit(
+        &self,
+        program_hash: &[u8; 32],
+        circuit: &Circuit,
+    ) -> Result<(), Error> {
+        let max_and_gates = match self.allowed_program(program_hash)? {
+            Some(AllowedProgram {
+                max_and_gates: Some(max_and_gates),
+                ..
+            }) => *max_and_gates,
+            _ => return Ok(()),
+        };
+        if circuit.stats().and_gates > max_and_gates {
+            return Err(Error::ProgramNotAllowed {
+                program_hash: blake3::Hash::from(*program_hash).to_hex().to_string(),
+                max_and_gates: Some(max_and_gates),
+            });
+        }
+        Ok(())
+    }
+
+    fn allowed_program(&self, program_hash: &[u8; 32]) -> Result<Option<&AllowedProgram>, Error> {
+        let allowed_programs = match &self.allowlist.allowed_programs {
+            Some(allowed_programs) => allowed_programs,
+            None => return Ok(None),
+        };
+        let hash = blake3::Hash::from(*program_hash).to_hex();
+        let allowed = allowed_programs
+            .iter()
+            .find(|p| p.program_hash.eq_ignore_ascii_case(&hash));
+        match allowed {
+            Some(allowed) => Ok(Some(allowed)),
+            None => Err(Error::ProgramNotAllowed {
+                program_hash: hash.to_string(),
+                max_and_gates: None,
+            }),
+        }
+    }
+
+    /// Returns the error for a client whose circuit does not match the circuit of the server.
+    pub(crate) fn circuit_mismatch(&self, circuit: &Circuit) -> Error {
+        Error::CircuitHashMismatch {
+            server_circuit: self
+                .diagnostics
+                .circuit_diagnostics
+                .then(|| circuit.stats()),
+            compiler_version: self.diagnostics.compiler_version.clone(),
+        }
+    }
+
+    /// Returns the static key authenticating the encrypted channels of sessions, if configured.
+    pub(crate) fn channel_key(&self) -> Option<&ChannelKeyPair> {
+        self.channel_key.as_ref()
+    }
+
+    /// Returns a new bucket limiting the bandwidth of a session, if configured.
+    pub(crate) fn session_bandwidth(&self) -> Option<TokenBucket> {
+        self.throttle.session_bucket()
+    }
+
+    /// The maximum number of bytes queued for a session, if configured.
+    pub(crate) fn max_queue_bytes(&self) -> Option<usize> {
+        self.quotas.max_queue_bytes
+    }
+
+    /// Waits until an engine may process messages on a blocking thread, which it may do until the
+    /// permit is dropped, see [`ExecutionPolicy`].
+    pub(crate) async fn engine_permit(&self) -> OwnedSemaphorePermit {
+        Arc::clone(&self.engine_threads)
+            .acquire_owned()
+            .await
+            .expect("the semaphore of the engine threads is never closed")
+    }
+
+    /// Returns `true` if dialog responses include a `Server-Timing` header.
+    pub(crate) fn server_timing(&self) -> bool {
+        self.timing.server_timing
+    }
+
+    /// Returns `true` if the request bodies of clients are throttled.
+    pub(crate) fn throttle_requests(&self) -> bool {
+        self.throttle.throttle_requests()
+    }
+
+    /// Splits the bytes to be sent to the client of the engine into chunks, each paired with the
+    /// instant at which it may be sent without exceeding the bandwidth limits.
+    ///
+    /// The bytes are returned as a single chunk that can be sent immediately if no limits are
+    /// configured.
+    pub(crate) fn throttle(
+        &self,
+        engine: &mut EngineRef,
+        bytes: Vec<u8>,
+    ) -> Vec<(Vec<u8>, Instant)> {
+        if !self.throttle.is_enabled() {
+            return vec![(bytes, Instant::now())];
+        }
+        let deadlines = self
+            .throttle
+            .reserve(engine.bandwidth.as_mut(), bytes.len());
+        bytes
+            .chunks(THROTTLE_CHUNK_SIZE)
+            .map(<[u8]>::to_vec)
+            .zip(deadlines)
+            .collect()
+    }
+
+    /// Reserves the bandwidth for `bytes` bytes received from the client of the engine, returning
+    /// the instant at which the bytes may be processed.
+    pub(crate) fn throttle_request(&self, engine: &mut EngineRef, bytes: usize) -> Instant {
+        let deadlines = self.throttle.reserve(engine.bandwidth.as_mut(), bytes);
+        deadlines.last().copied().unwrap_or_else(Instant::now)
+    }
+
+    /// Returns the number of running sessions and how much they were throttled.
+    pub(crate) fn metrics(&self) -> Metrics {
+        Metrics {
+            sessions: self.registry.read().unwrap().len(),
+            throttled_millis: self.throttle.throttled_time().as_millis() as u64,
+            throttled_bytes: self.throttle.throttled_bytes(),
+        }
+    }
+
+    /// Records an audit event for the session, if an audit sink is configured.
+    pub(crate) fn audit(
+        &self,
+        session: &SessionInfo,
+        outcome: AuditOutcome,
+        error: Option<String>,
+    ) {
+        let audit_sink = match &self.audit_sink {
+            Some(audit_sink) => audit_sink,
+            None => return,
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        audit_sink.record(&AuditEvent {
+            outcome,
+            timestamp_millis: timestamp.as_millis() as u64,
+            duration_millis: session.requested.elapsed().as_millis() as u64,
+            engine_id: session.engine_id.clone(),
+            external_id: session.external_id.clone(),
+            program_hash: session.program_hash.clone(),
+            function: session.function.clone(),
+            plaintext_metadata: session.plaintext_metadata.clone(),
+            client: session.client,
+            error,
+            transcript_confirmed: session.transcript_confirmed,
+        });
+    }
+
+    /// Returns the readiness reported by the configured readiness function, if any.
+    pub(crate) fn readiness(&self) -> Readiness {
+        match &self.readiness {
+            Some(readiness) => readiness(),
+            None => Readiness::default(),
+        }
+    }
+
+    /// Returns the functions listed by the configured catalog, or `None` without a catalog.
+    pub(crate) fn catalog(&self) -> Option<Vec<PublishedFunction>> {
+        self.catalog.as_ref().map(|catalog| catalog())
+    }
+
+    /// Returns the configured limits of sessions, by the name of their config key.
+    pub(crate) fn limits(&self) -> BTreeMap<String, u64> {
+        let quotas = [
+            ("max_session_and_gates", self.quotas.max_session_and_gates),
+            ("max_total_and_gates", self.quotas.max_total_and_gates),
+            ("max_queue_bytes", self.quotas.max_queue_bytes),
+        ];
+        quotas
+            .into_iter()
+            .filter_map(|(name, limit)| Some((name.to_string(), limit? as u64)))
+            .collect()
+    }
+
+    /// Chooses the circuit and input of the session using the handler, adding the headers of the
+    /// [`StickinessProvider`] (if any) to the headers chosen by the handler.
     pub(crate) fn handle_input(&self, invocation: MpcRequest) -> Result<MpcSession, String> {
-        self.handler.as_ref()(invocation)
+        let headers = match &self.stickiness {
+            Some(stickiness) => stickiness.request_headers(&invocation),
+            None => HashMap::new(),
+        };
+        let mut session = self.handler.as_ref()(invocation)?;
+        for (name, value) in headers {
+            session.request_headers.entry(name).or_insert(value);
+        }
+        Ok(session)
     }
 }
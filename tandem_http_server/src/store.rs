@@ -0,0 +1,160 @@
+//! Storage for the state of sessions outside of the server, so that it survives restarts of the
+//! server and can be read by all of its instances.
+//!
+//! The message log of each session is written to the [`SessionStore`] after every dialog request,
+//! see [`crate::msg_queue::MessageLog`].
+
+use std::time::Duration;
+
+use rocket::serde::Deserialize;
+
+use crate::responses::Error;
+
+/// Stores the state of sessions outside of the server, see
+/// [`crate::ServerConfig::with_session_store`].
+pub trait SessionStore: Send + Sync {
+    /// Returns the value stored under the key together with its version, or `None` if there is no
+    /// such key or it expired.
+    fn load(&self, key: &str) -> Result<Option<(u64, Vec<u8>)>, String>;
+
+    /// Stores the value under the key as the specified version until the `ttl` expires, unless the
+    /// stored version is not the previous version (with no stored key counting as version `0`).
+    ///
+    /// Returns `false` without storing the value if the version does not follow the stored
+    /// version. The comparison and the write must be atomic.
+    fn store(&self, key: &str, version: u64, value: &[u8], ttl: Duration) -> Result<bool, String>;
+
+    /// Removes the key, if it exists.
+    fn remove(&self, key: &str) -> Result<(), String>;
+}
+
+/// A [`SessionStore`] backed by Redis, used for the `session_store` of the Rocket config.
+///
+/// Each key is stored as a hash of its `version` and `value`, the version is compared and updated
+/// by a Lua script. The connection is re-established if a command fails.
+#[cfg(feature = "redis")]
+pub struct RedisSessionStore {
+    client: redis::Client,
+    connection: std::sync::Mutex<Option<redis::Connection>>,
+    store: redis::Script,
+}
+
+#[cfg(feature = "redis")]
+impl RedisSessionStore {
+    /// Connects to the Redis server with the specified URL, such as `redis://127.0.0.1/`.
+    pub fn open(url: &str) -> Result<Self, String> {
+        let client = redis::Client::open(url).map_err(|e| e.to_string())?;
+        let connection = client.get_connection().map_err(|e| e.to_string())?;
+        let store = redis::Script::new(
+            r"
+            local version = tonumber(redis.call('HGET', KEYS[1], 'version') or '0')
+            if version + 1 ~= tonumber(ARGV[1]) then
+                return 0
+            end
+            redis.call('HSET', KEYS[1], 'version', ARGV[1], 'value', ARGV[2])
+            redis.call('PEXPIRE', KEYS[1], ARGV[3])
+            return 1
+            ",
+        );
+        Ok(Self {
+            client,
+            connection: std::sync::Mutex::new(Some(connection)),
+            store,
+        })
+    }
+
+    /// Runs the command on the connection, reconnecting first if the last command failed.
+    fn with_connection<T>(
+        &self,
+        command: impl FnOnce(&mut redis::Connection) -> redis::RedisResult<T>,
+    ) -> Result<T, String> {
+        let mut connection = self.connection.lock().unwrap();
+        let mut con = match connection.take() {
+            Some(con) => con,
+            None => self.client.get_connection().map_err(|e| e.to_string())?,
+        };
+        let result = command(&mut con).map_err(|e| e.to_string())?;
+        *connection = Some(con);
+        Ok(result)
+    }
+}
+
+#[cfg(feature = "redis")]
+impl SessionStore for RedisSessionStore {
+    fn load(&self, key: &str) -> Result<Option<(u64, Vec<u8>)>, String> {
+        let (version, value): (Option<u64>, Option<Vec<u8>>) = self.with_connection(|con| {
+            redis::cmd("HMGET")
+                .arg(key)
+                .arg("version")
+                .arg("value")
+                .query(con)
+        })?;
+        Ok(version.zip(value))
+    }
+
+    fn store(&self, key: &str, version: u64, value: &[u8], ttl: Duration) -> Result<bool, String> {
+        let ttl_millis = ttl.as_millis().max(1) as u64;
+        let stored: i32 = self.with_connection(|con| {
+            self.store
+                .key(key)
+                .arg(version)
+                .arg(value)
+                .arg(ttl_millis)
+                .invoke(con)
+        })?;
+        Ok(stored == 1)
+    }
+
+    fn remove(&self, key: &str) -> Result<(), String> {
+        self.with_connection(|con| redis::cmd("DEL").arg(key).query(con))
+    }
+}
+
+/// Where the state of sessions is stored, configured as part of the Rocket config.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(crate = "rocket::serde", default)]
+pub(crate) struct StorePolicy {
+    /// URL of the Redis server that stores the state of sessions (requires the `redis` feature),
+    /// the state is only kept in memory if not set.
+    pub session_store: Option<String>,
+    /// Seconds after which the stored state of a session expires if none of its requests is
+    /// processed.
+    pub session_ttl_secs: u64,
+}
+
+impl Default for StorePolicy {
+    fn default() -> Self {
+        Self {
+            session_store: None,
+            session_ttl_secs: 3600,
+        }
+    }
+}
+
+impl StorePolicy {
+    /// Connects to the configured store, if any.
+    pub fn open(&self) -> Result<Option<Box<dyn SessionStore>>, String> {
+        match &self.session_store {
+            None => Ok(None),
+            #[cfg(feature = "redis")]
+            Some(url) => match RedisSessionStore::open(url) {
+                Ok(store) => Ok(Some(Box::new(store))),
+                Err(e) => Err(format!("could not connect to the session store: {e}")),
+            },
+            #[cfg(not(feature = "redis"))]
+            Some(_) => Err("the session store requires the `redis` feature".to_string()),
+        }
+    }
+
+    pub fn session_ttl(&self) -> Duration {
+        Duration::from_secs(self.session_ttl_secs)
+    }
+}
+
+/// Logs the failure of the store, which is only reported to the client as an internal error.
+pub(crate) fn store_failed(e: String) -> Error {
+    error!("The session store failed: {e}");
+    Error::Internal {
+        message: "the session store failed".to_string(),
+    }
+}
@@ -1,25 +1,36 @@
 #![allow(dead_code)]
 
 use crate::{
-    build,
+    build, build_with_config, frames,
     msg_queue::{MessageId, MsgQueue},
-    requests::NewSession,
-    types::{EngineCreationResult, MpcSession},
-    MpcRequest,
+    requests::{NewApproval, NewSession},
+    responses::{Error, Quota},
+    state::EngineRegistry,
+    types::{
+        Approval, ApprovalStatus, Capabilities, EngineCreationResult, ExternalId, Health, Metrics,
+        MpcSession, SessionCommitment,
+    },
+    AuditEvent, AuditOutcome, AuditSink, IdGenerator, JsonLinesAuditSink, MpcRequest,
+    PublishedFunction, Readiness, ServerConfig, SessionStore, StaticHeaders, StickinessProvider,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
 };
-use std::collections::HashMap;
 
 use crate::engine;
 
 use rand::SeedableRng;
 use rand_chacha::ChaCha20Rng;
 use rocket::{
-    http::Status,
+    http::{Header, Status},
     local::blocking::{Client, LocalResponse},
 };
 use tandem::{
+    channel::{ChannelKeyPair, Handshake, SecureChannel},
     states::{Evaluator, Msg},
-    Circuit,
+    ValidatedCircuit,
 };
 use tandem_garble_interop::{
     check_program, compile_program, deserialize_output, serialize_input, Role, TypedCircuit,
@@ -29,25 +40,27 @@ type MessageLog = Vec<(tandem::states::Msg, crate::msg_queue::MessageId)>;
 
 #[launch]
 pub fn _rocket() -> _ {
-    let handler = |r: MpcRequest| -> Result<MpcSession, String> {
-        let prg = check_program(&r.program)?;
-        let circuit = compile_program(&prg, &r.function)?;
-        let headers = HashMap::new();
-        let input = serialize_input(
-            Role::Contributor,
-            &prg,
-            &circuit.fn_def,
-            &r.plaintext_metadata,
-        )?;
-        Ok(MpcSession {
-            circuit: circuit.gates,
-            input_from_server: input,
-            request_headers: headers,
-        })
-    };
     build(Box::new(handler))
 }
 
+fn handler(r: MpcRequest) -> Result<MpcSession, String> {
+    let prg = check_program(&r.program)?;
+    let circuit = compile_program(&prg, &r.function)?;
+    let headers = HashMap::new();
+    let input = serialize_input(
+        Role::Contributor,
+        &prg,
+        &circuit.fn_def,
+        &r.plaintext_metadata,
+    )?;
+    Ok(MpcSession {
+        circuit: circuit.gates.into_inner(),
+        input_from_server: input,
+        request_headers: headers,
+        on_output: None,
+    })
+}
+
 #[test]
 fn test_multiple_engines() {
     let client = &Client::tracked(_rocket()).unwrap();
@@ -79,6 +92,307 @@ fn test_delete_session() {
     assert_eq!(r4.status(), Status::Created);
 }
 
+#[test]
+fn test_healthz() {
+    let client = &Client::tracked(_rocket()).unwrap();
+
+    let r = client.get(uri!(engine::healthz(_))).dispatch();
+    assert_eq!(r.status(), Status::Ok);
+
+    let health = r.into_json::<Health>().unwrap();
+    assert_eq!(health.status, "ok");
+    assert_eq!(health.server_version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(health.protocol_version, tandem::PROTOCOL_VERSION);
+    assert_eq!(health.wire_version, crate::WIRE_VERSION);
+    assert_eq!(health.self_test, None);
+}
+
+#[test]
+fn test_healthz_self_test() {
+    let config = rocket::Config::figment().merge(("self_test_on_startup", false));
+    let client = &Client::tracked(_rocket().configure(config)).unwrap();
+
+    let r = client.get(uri!(engine::healthz(Some(true)))).dispatch();
+    assert_eq!(r.status(), Status::Ok);
+
+    let health = r.into_json::<Health>().unwrap();
+    assert_eq!(health.status, "ok");
+    let report = health.self_test.unwrap();
+    assert!(report.passed());
+    assert_eq!(report.checks.len(), 5);
+}
+
+#[test]
+fn test_readyz() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let r = client.get(uri!(engine::readyz())).dispatch();
+    assert_eq!(r.status(), Status::Ok);
+    assert_eq!(r.into_json::<Readiness>().unwrap(), Readiness::default());
+
+    let readiness = || Readiness {
+        ready: false,
+        components: [("circuit main".to_string(), "compiling".to_string())].into(),
+    };
+    let config = ServerConfig::default().with_readiness(Box::new(readiness));
+    let client = &Client::tracked(build_with_config(Box::new(handler), config)).unwrap();
+    let r = client.get(uri!(engine::readyz())).dispatch();
+    assert_eq!(r.status(), Status::ServiceUnavailable);
+    assert_eq!(r.into_json::<Readiness>().unwrap(), readiness());
+}
+
+#[test]
+fn test_capabilities() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let r = client.get(uri!(engine::capabilities())).dispatch();
+    assert_eq!(r.status(), Status::Ok);
+    let capabilities = r.into_json::<Capabilities>().unwrap();
+    assert_eq!(capabilities.server_version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(capabilities.wire_version, crate::WIRE_VERSION);
+    assert_eq!(capabilities.min_wire_version, crate::MIN_WIRE_VERSION);
+    assert_eq!(capabilities.features, crate::CAPABILITIES);
+    assert!(capabilities.limits.is_empty());
+
+    let config = rocket::Config::figment().merge(("max_session_and_gates", 1000));
+    let client = &Client::tracked(_rocket().configure(config)).unwrap();
+    let r = client.get(uri!(engine::capabilities())).dispatch();
+    let capabilities = r.into_json::<Capabilities>().unwrap();
+    assert_eq!(
+        capabilities.limits,
+        [("max_session_and_gates".to_string(), 1000)].into()
+    );
+}
+
+#[test]
+fn test_programs() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let r = client.get(uri!(engine::programs())).dispatch();
+    assert_eq!(r.status(), Status::NotFound);
+
+    let catalog = || {
+        vec![PublishedFunction {
+            function: "main".to_string(),
+            signature: Some("fn main(x: u8, y: u8) -> u8".to_string()),
+            program_hash: None,
+        }]
+    };
+    let config = ServerConfig::default().with_catalog(Box::new(catalog));
+    let client = &Client::tracked(build_with_config(Box::new(handler), config)).unwrap();
+    let r = client.get(uri!(engine::programs())).dispatch();
+    assert_eq!(r.status(), Status::Ok);
+    assert_eq!(r.into_json::<Vec<PublishedFunction>>().unwrap(), catalog());
+}
+
+#[test]
+fn test_version_negotiation() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let program = xor_and_program();
+    let prg = check_program(&program).unwrap();
+    let circuit = compile_program(&prg, "main").unwrap();
+    let session = |client_version: &str, protocol_version, wire_version| NewSession {
+        plaintext_metadata: "false".into(),
+        program: program.clone(),
+        function: "main".to_string(),
+        circuit_hash: circuit.gates.blake3_hash(),
+        client_version: client_version.to_string(),
+        protocol_version,
+        wire_version,
+        wire_versions: None,
+        capabilities: vec![],
+        stage_final: false,
+        approval_id: None,
+        idempotency_key: None,
+        session_nonce: None,
+        channel_key: None,
+        follow_ups: None,
+        circuit: None,
+    };
+    let create_sess_uri = uri!(engine::create_session());
+
+    // matching protocol versions take precedence over different crate versions:
+    let req = session(
+        "0.0.0",
+        Some(tandem::PROTOCOL_VERSION),
+        Some(crate::WIRE_VERSION),
+    );
+    let r = client.post(create_sess_uri.clone()).json(&req).dispatch();
+    assert_eq!(r.status(), Status::Created);
+    let r = r.into_json::<EngineCreationResult>().unwrap();
+    assert_eq!(r.protocol_version, tandem::PROTOCOL_VERSION);
+    assert_eq!(r.wire_version, crate::WIRE_VERSION);
+
+    let req = session(
+        env!("CARGO_PKG_VERSION"),
+        Some(tandem::PROTOCOL_VERSION + 1),
+        Some(crate::WIRE_VERSION),
+    );
+    let r = client.post(create_sess_uri.clone()).json(&req).dispatch();
+    assert_eq!(r.status(), Status::BadRequest);
+    assert!(r
+        .into_string()
+        .unwrap()
+        .contains("IncompatibleProtocolVersions"));
+
+    // clients without protocol versions fall back to comparing crate versions:
+    let req = session("0.0.0", None, None);
+    let r = client.post(create_sess_uri.clone()).json(&req).dispatch();
+    assert_eq!(r.status(), Status::BadRequest);
+    assert!(r.into_string().unwrap().contains("IncompatibleVersions"));
+
+    let req = session(env!("CARGO_PKG_VERSION"), None, None);
+    let r = client.post(create_sess_uri).json(&req).dispatch();
+    assert_eq!(r.status(), Status::Created);
+    let r = r.into_json::<EngineCreationResult>().unwrap();
+    assert_eq!(r.wire_version, crate::MIN_WIRE_VERSION);
+}
+
+#[test]
+fn test_session_commitment() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let mut req = session_request(xor_and_program(), "false".to_string(), false);
+    let r = client
+        .post(uri!(engine::create_session()))
+        .json(&req)
+        .dispatch();
+    let r = r.into_json::<EngineCreationResult>().unwrap();
+    assert_eq!(r.session_commitment, None);
+
+    req.session_nonce = Some([7; 32]);
+    let r = client
+        .post(uri!(engine::create_session()))
+        .json(&req)
+        .dispatch();
+    let r = r.into_json::<EngineCreationResult>().unwrap();
+    let SessionCommitment {
+        server_nonce,
+        commitment,
+    } = r.session_commitment.unwrap();
+    let expected = |capabilities: &[String]| {
+        SessionCommitment::commit(
+            &req,
+            &[7; 32],
+            &server_nonce,
+            &r.engine_id,
+            r.wire_version,
+            capabilities,
+            None,
+        )
+        .unwrap()
+    };
+    assert_eq!(commitment, expected(&r.capabilities));
+    assert_ne!(commitment, expected(&[]));
+
+    // the client computes the same commitment for the same inputs:
+    let req = NewSession {
+        plaintext_metadata: "".into(),
+        program: String::new(),
+        function: "main".to_string(),
+        circuit_hash: [3; 32],
+        client_version: String::new(),
+        protocol_version: Some(tandem::PROTOCOL_VERSION),
+        wire_version: Some(1),
+        wire_versions: Some(vec![1, 2]),
+        capabilities: vec!["streaming".to_string()],
+        stage_final: true,
+        approval_id: None,
+        idempotency_key: None,
+        session_nonce: Some([1; 32]),
+        channel_key: None,
+        follow_ups: None,
+        circuit: None,
+    };
+    let commitment = SessionCommitment::commit(
+        &req,
+        &[1; 32],
+        &[2; 32],
+        "engine",
+        2,
+        &["streaming".to_string()],
+        None,
+    )
+    .unwrap();
+    assert_eq!(
+        blake3::Hash::from(commitment).to_hex().as_str(),
+        "9055bd8004e77b4d71c88a552922097e74dbe794e545c3e2cf5364c4840f658e"
+    );
+    let keyed = SessionCommitment::commit(
+        &req,
+        &[1; 32],
+        &[2; 32],
+        "engine",
+        2,
+        &["streaming".to_string()],
+        Some(&[4; 32]),
+    )
+    .unwrap();
+    assert_eq!(
+        blake3::Hash::from(keyed).to_hex().as_str(),
+        "616b1ff15be485dae8b006963dc0d75098170265b5ce70bf07555b9e0d9c6fcc"
+    );
+}
+
+#[test]
+fn test_wire_version_negotiation() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let program = xor_and_program();
+    let session = |wire_versions: Vec<u32>, capabilities: &[&str]| {
+        let mut session = session_request(program.clone(), "false".to_string(), false);
+        session.wire_version = wire_versions.last().copied();
+        session.wire_versions = Some(wire_versions);
+        session.capabilities = capabilities.iter().map(|c| c.to_string()).collect();
+        client
+            .post(uri!(engine::create_session()))
+            .json(&session)
+            .dispatch()
+    };
+    let empty_dialog = bincode::serialize(&(None::<u32>, Vec::<(Msg, MessageId)>::new())).unwrap();
+
+    // the newest common wire version and all common capabilities are picked:
+    let r = session(vec![1, 2, 99], &["compression", "streaming"]);
+    assert_eq!(r.status(), Status::Created);
+    let r = r.into_json::<EngineCreationResult>().unwrap();
+    assert_eq!(r.wire_version, 2);
+    assert_eq!(r.capabilities, vec!["streaming".to_string()]);
+    let body = client
+        .post(uri!(engine::dialog(&r.engine_id)))
+        .body(&empty_dialog)
+        .dispatch()
+        .into_bytes()
+        .unwrap();
+    let (msgs, _) = frames::decode(&body).unwrap();
+    assert_eq!(msgs, r.messages);
+
+    // without streaming, the messages are sent as a single message log:
+    let r = session(vec![2], &[]);
+    let r = r.into_json::<EngineCreationResult>().unwrap();
+    assert_eq!(r.capabilities, Vec::<String>::new());
+    let body = client
+        .post(uri!(engine::dialog(&r.engine_id)))
+        .body(&empty_dialog)
+        .dispatch()
+        .into_bytes()
+        .unwrap();
+    let (msgs, _): (MessageLog, Option<MessageId>) = bincode::deserialize(&body).unwrap();
+    assert_eq!(msgs, r.messages);
+
+    // capabilities are not negotiated with clients of the first wire version:
+    let r = session(vec![1], &["streaming"]);
+    let r = r.into_json::<EngineCreationResult>().unwrap();
+    assert_eq!(r.wire_version, 1);
+    assert_eq!(r.capabilities, Vec::<String>::new());
+
+    let r = session(vec![99, 100], &["streaming"]);
+    assert_eq!(r.status(), Status::BadRequest);
+    assert!(matches!(
+        r.into_json::<Error>().unwrap(),
+        Error::IncompatibleProtocolVersions {
+            client_wire_version: 100,
+            server_wire_version: crate::WIRE_VERSION,
+            server_min_wire_version: crate::MIN_WIRE_VERSION,
+            ..
+        }
+    ));
+}
+
 #[test]
 fn test_protocol_xor_and() {
     let client = &Client::tracked(_rocket()).unwrap();
@@ -89,10 +403,25 @@ fn test_protocol_xor_and() {
             let r1 = new_session(client, program.clone(), input_party_a.to_string());
             assert_eq!(r1.status(), Status::Created);
 
-            let EngineCreationResult { engine_id, .. } = r1.into_json().unwrap();
+            let EngineCreationResult {
+                engine_id,
+                messages,
+                ..
+            } = r1.into_json().unwrap();
             let prg = check_program(&program).unwrap();
             let TypedCircuit { gates, fn_def, .. } = compile_program(&prg, "main").unwrap();
-            let result = tandem_http_protocol(client, &engine_id, gates, vec![input_party_b]);
+            let replies = tandem::ProtocolPlan::new(&gates).message_size_hints().len() - 1;
+            let (result, rounds) = tandem_http_protocol(
+                client,
+                &engine_id,
+                gates,
+                vec![input_party_b],
+                None,
+                messages,
+                None,
+            );
+            // the initial message was piggybacked, each round sends one of the client's replies:
+            assert_eq!(rounds, replies);
             let result = deserialize_output(&prg, &fn_def, &result)
                 .unwrap()
                 .as_bits(&prg);
@@ -107,79 +436,2181 @@ fn test_protocol_xor_and() {
     // create engine session
 }
 
-/// runs protocol with upstream
-///
-/// assumes upstream session was already created
-fn tandem_http_protocol(
-    client: &Client,
-    engine_id: &String,
-    program: Circuit,
-    input: Vec<bool>,
-) -> Vec<bool> {
-    let mut context = MsgQueue::new();
-    let mut evaluator = Evaluator::new(program, input, ChaCha20Rng::from_entropy()).unwrap();
+#[test]
+fn test_download_final() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let program = xor_and_program();
+    let prg = check_program(&program).unwrap();
+    let TypedCircuit { gates, fn_def, .. } = compile_program(&prg, "main").unwrap();
 
-    let mut last_durably_received_offset: Option<MessageId> = None;
-    let mut steps_remaining = evaluator.steps();
-    loop {
-        let messages: Vec<(&Msg, MessageId)> = context.msgs_iter().collect();
-        let (upstream_msgs, server_commited_offset) =
-            dialog(client, engine_id, last_durably_received_offset, &messages);
-        assert_eq!(messages.last().map(|v| v.1), server_commited_offset);
+    let r = new_session_with(client, program.clone(), "true".to_string(), true);
+    let EngineCreationResult {
+        engine_id,
+        final_url,
+        ..
+    } = r.into_json().unwrap();
+    let final_url = final_url.unwrap();
+    assert_eq!(final_url, format!("/{engine_id}/final"));
 
-        if let Some(last_durably_received_offset) = server_commited_offset {
-            context.flush_queue(last_durably_received_offset);
-        }
+    // the initial message is also sent as part of the dialog until the client acknowledges it:
+    let replies = tandem::ProtocolPlan::new(&gates).message_size_hints().len() - 1;
+    let (result, rounds) = tandem_http_protocol(
+        client,
+        &engine_id,
+        gates,
+        vec![true],
+        Some(final_url.clone()),
+        vec![],
+        None,
+    );
+    assert_eq!(rounds, replies + 1);
+    let result = deserialize_output(&prg, &fn_def, &result)
+        .unwrap()
+        .as_bits(&prg);
+    assert_eq!(result, vec![false, true]);
 
-        for (msg, server_offset) in &upstream_msgs {
-            assert_eq!(
-                *server_offset,
-                last_durably_received_offset.map(|o| o + 1).unwrap_or(0)
-            );
+    // the download is only available once:
+    let r = client.get(final_url).dispatch();
+    assert_eq!(r.status(), Status::NotFound);
+}
 
-            if steps_remaining > 0 {
-                let (next_state, msg) = evaluator.run(msg).unwrap();
-                evaluator = next_state;
-                steps_remaining -= 1;
-                context.send(msg);
-            } else {
-                return evaluator.output(msg).unwrap();
-            }
-            last_durably_received_offset = Some(*server_offset);
-        }
-    }
+#[test]
+fn test_download_final_range() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let r = new_session_with(client, xor_and_program(), "true".to_string(), true);
+    let EngineCreationResult { engine_id, .. } = r.into_json().unwrap();
+    let registry = client.rocket().state::<Arc<EngineRegistry>>().unwrap();
+    registry
+        .stage_final(engine_id.clone(), (0..100).collect())
+        .unwrap();
+    let final_url = format!("/{engine_id}/final");
+
+    let r = client
+        .get(final_url.clone())
+        .header(rocket::http::Header::new("Range", "bytes=90-"))
+        .dispatch();
+    assert_eq!(r.status(), Status::PartialContent);
+    assert_eq!(
+        r.headers().get_one("Content-Range"),
+        Some("bytes 90-99/100")
+    );
+    assert_eq!(r.into_bytes().unwrap(), (90..100).collect::<Vec<u8>>());
+
+    // the download was completed:
+    let r = client.get(final_url.clone()).dispatch();
+    assert_eq!(r.status(), Status::NotFound);
+
+    registry.stage_final(engine_id, (0..100).collect()).unwrap();
+    let r = client
+        .get(final_url.clone())
+        .header(rocket::http::Header::new("Range", "bytes=100-"))
+        .dispatch();
+    assert_eq!(r.status(), Status::RangeNotSatisfiable);
+    assert_eq!(
+        download(client, &final_url, "bytes=0-49", Status::PartialContent).len(),
+        50
+    );
+
+    let r = client.get(final_url.clone()).dispatch();
+    assert_eq!(r.status(), Status::Ok);
+    assert_eq!(r.headers().get_one("Accept-Ranges"), Some("bytes"));
+    assert_eq!(r.into_bytes().unwrap(), (0..100).collect::<Vec<u8>>());
+    let r = client.get(final_url).dispatch();
+    assert_eq!(r.status(), Status::NotFound);
 }
 
-fn dialog<'a>(
-    client: &'a Client,
-    engine_id: &String,
-    last_durably_received_offset: Option<u32>,
-    messages: &Vec<(&Msg, MessageId)>,
-) -> (MessageLog, Option<MessageId>) {
-    let dialog_uri = uri!(engine::dialog(engine_id));
-    let messages = bincode::serialize(&(last_durably_received_offset, messages)).unwrap();
-    let res = client.post(dialog_uri).body(messages).dispatch();
-    assert_eq!(res.status(), Status::Ok);
+#[test]
+fn test_abort_by_client() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let r = new_session(client, xor_and_program(), "false".to_string());
+    let EngineCreationResult { engine_id, .. } = r.into_json().unwrap();
 
-    bincode::deserialize(&res.into_bytes().unwrap()).unwrap()
+    let abort = tandem::abort_message(tandem::AbortReason::Cancelled);
+    let (upstream_msgs, offset) = dialog(client, &engine_id, None, &vec![(&abort, 0)]);
+    assert_eq!(offset, Some(0));
+    assert_eq!(upstream_msgs.len(), 1);
+
+    // the engine is dropped immediately:
+    let r = delete_session(client, &engine_id);
+    assert_eq!(r.status(), Status::NotFound);
 }
 
-fn new_session<'a>(client: &'a Client, program: String, input: String) -> LocalResponse<'a> {
+#[test]
+fn test_abort_by_server() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let program = xor_and_program();
+    let r = new_session(client, program.clone(), "false".to_string());
+    let EngineCreationResult { engine_id, .. } = r.into_json().unwrap();
+
+    let prg = check_program(&program).unwrap();
+    let circuit = compile_program(&prg, "main").unwrap().gates;
+    let evaluator = Evaluator::new(circuit, vec![true], ChaCha20Rng::from_entropy()).unwrap();
+
+    let invalid_msg = vec![1, 2, 3];
+    let (upstream_msgs, _) = dialog(client, &engine_id, None, &vec![(&invalid_msg, 0)]);
+    let (abort, offset) = upstream_msgs.last().unwrap();
+    assert_eq!(*offset, 1);
+    assert_eq!(
+        evaluator.run(abort).err(),
+        Some(tandem::Error::PeerAborted(
+            tandem::AbortReason::ProtocolViolation
+        ))
+    );
+
+    let r = delete_session(client, &engine_id);
+    assert_eq!(r.status(), Status::NotFound);
+}
+
+#[test]
+fn test_engine_failure_threshold() {
+    let config = rocket::Config::figment()
+        .merge(("engine_failure_threshold", 2))
+        .merge(("engine_failure_block_secs", 60));
+    let client = &Client::tracked(_rocket().configure(config)).unwrap();
+    let remote = "192.0.2.1:8000".parse().unwrap();
+
+    let r = new_session(client, xor_and_program(), "false".to_string());
+    let EngineCreationResult { engine_id, .. } = r.into_json().unwrap();
+
+    let unexpected_offset = bincode::serialize(&(None::<u32>, vec![(vec![0u8], 5u32)])).unwrap();
+    let dialog_uri = uri!(engine::dialog(&engine_id));
+    let r = client
+        .post(dialog_uri.clone())
+        .remote(remote)
+        .body(&unexpected_offset)
+        .dispatch();
+    assert_eq!(r.status(), Status::BadRequest);
+    let r = client
+        .post(dialog_uri.clone())
+        .remote(remote)
+        .body(&unexpected_offset)
+        .dispatch();
+    assert_eq!(r.status(), Status::BadRequest);
+
+    // the engine is dropped and the client is blocked:
+    let r = delete_session(client, &engine_id);
+    assert_eq!(r.status(), Status::NotFound);
+    let r = client
+        .post(dialog_uri)
+        .remote(remote)
+        .body(&unexpected_offset)
+        .dispatch();
+    assert_eq!(r.status(), Status::TooManyRequests);
+
+    // other clients are not affected:
+    let r = new_session(client, xor_and_program(), "false".to_string());
+    assert_eq!(r.status(), Status::Created);
+}
+
+#[test]
+fn test_truncated_dialog_body() {
+    let program = xor_and_program();
+    let prg = check_program(&program).unwrap();
+    let TypedCircuit { gates, .. } = compile_program(&prg, "main").unwrap();
+    let first_reply = |messages: &MessageLog| {
+        let evaluator =
+            Evaluator::new(gates.clone(), vec![true], ChaCha20Rng::from_entropy()).unwrap();
+        let (_, reply) = evaluator.run(&messages[0].0).unwrap();
+        bincode::serialize(&(None::<u32>, vec![(reply, 0u32)])).unwrap()
+    };
+
+    let client = &Client::tracked(_rocket()).unwrap();
+    let r = new_session(client, program.clone(), "false".to_string());
+    let EngineCreationResult {
+        engine_id,
+        messages,
+        ..
+    } = r.into_json().unwrap();
+    let body = first_reply(&messages);
+    let dialog_uri = uri!(engine::dialog(&engine_id));
+    let r = client
+        .post(dialog_uri.clone())
+        .body(&body[..body.len() / 2])
+        .dispatch();
+    assert_eq!(r.status(), Status::BadRequest);
+    assert_eq!(r.into_json::<Error>().unwrap(), Error::Bincode);
+
+    // the session is still usable after the failed request:
+    let r = client.post(dialog_uri).body(&body).dispatch();
+    assert_eq!(r.status(), Status::Ok);
+
+    // bodies are truncated at the configured limit:
+    let config = rocket::Config::figment().merge(("limits.dialog", 64));
+    let client = &Client::tracked(_rocket().configure(config)).unwrap();
+    let r = new_session(client, program, "false".to_string());
+    let EngineCreationResult {
+        engine_id,
+        messages,
+        ..
+    } = r.into_json().unwrap();
+    let body = first_reply(&messages);
+    let r = client
+        .post(uri!(engine::dialog(&engine_id)))
+        .body(&body)
+        .dispatch();
+    assert_eq!(r.status(), Status::BadRequest);
+    assert_eq!(
+        r.into_json::<Error>().unwrap(),
+        Error::UnexpectedWireFormat(
+            "request body exceeds the expected maximum of 64 bytes".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_bandwidth_throttling() {
+    let config = rocket::Config::figment()
+        .merge(("session_bandwidth_limit", 1_000_000))
+        .merge(("bandwidth_burst", 1_000))
+        .merge(("throttle_requests", true));
+    let client = &Client::tracked(_rocket().configure(config)).unwrap();
+    let program = xor_and_program();
+
+    let r = new_session(client, program.clone(), "true".to_string());
+    let EngineCreationResult {
+        engine_id,
+        messages,
+        ..
+    } = r.into_json().unwrap();
+    let metrics: Metrics = client
+        .get(uri!(engine::metrics()))
+        .dispatch()
+        .into_json()
+        .unwrap();
+    assert_eq!(metrics.sessions, 1);
+    assert_eq!(metrics.throttled_millis, 0);
+
+    let prg = check_program(&program).unwrap();
+    let TypedCircuit { gates, fn_def, .. } = compile_program(&prg, "main").unwrap();
+    let (result, _) =
+        tandem_http_protocol(client, &engine_id, gates, vec![true], None, messages, None);
+    let result = deserialize_output(&prg, &fn_def, &result)
+        .unwrap()
+        .as_bits(&prg);
+    assert_eq!(result, vec![false, true]);
+
+    // the protocol exchanges far more than the burst of 1KB:
+    let metrics: Metrics = client
+        .get(uri!(engine::metrics()))
+        .dispatch()
+        .into_json()
+        .unwrap();
+    assert_eq!(metrics.sessions, 0);
+    assert!(metrics.throttled_millis > 0);
+    assert!(metrics.throttled_bytes > 0);
+}
+
+#[test]
+fn test_server_timing() {
+    let program = xor_and_program();
+    let prg = check_program(&program).unwrap();
+    let TypedCircuit { gates, .. } = compile_program(&prg, "main").unwrap();
+    let first_reply = |messages: &MessageLog| {
+        let evaluator =
+            Evaluator::new(gates.clone(), vec![true], ChaCha20Rng::from_entropy()).unwrap();
+        let (_, reply) = evaluator.run(&messages[0].0).unwrap();
+        bincode::serialize(&(None::<u32>, vec![(reply, 0u32)])).unwrap()
+    };
+    for server_timing in [false, true] {
+        let config = rocket::Config::figment()
+            .merge(("server_timing", server_timing))
+            .merge(("engine_threads", 1));
+        let client = &Client::tracked(_rocket().configure(config)).unwrap();
+        let r = new_session(client, program.clone(), "false".to_string());
+        let EngineCreationResult {
+            engine_id,
+            messages,
+            ..
+        } = r.into_json().unwrap();
+        let r = client
+            .post(uri!(engine::dialog(&engine_id)))
+            .body(first_reply(&messages))
+            .dispatch();
+        assert_eq!(r.status(), Status::Ok);
+        let timing = r.headers().get_one("Server-Timing");
+        if server_timing {
+            let timing = timing.unwrap();
+            assert!(timing.starts_with("read;dur="), "{timing}");
+            assert!(timing.contains(", wait;dur="), "{timing}");
+            assert!(timing.contains(", process;dur="), "{timing}");
+            assert!(!timing.contains("queued-messages;desc=0"), "{timing}");
+        } else {
+            assert_eq!(timing, None);
+        }
+    }
+}
+
+#[test]
+fn test_circuit_hash_mismatch() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let program = xor_and_program();
+    let mut session = session_request(program.clone(), "false".to_string(), false);
+    session.circuit_hash = [0; 32];
+    let r = client
+        .post(uri!(engine::create_session()))
+        .json(&session)
+        .dispatch();
+    assert_eq!(r.status(), Status::BadRequest);
+
     let prg = check_program(&program).unwrap();
     let circuit = compile_program(&prg, "main").unwrap();
-    let create_sess_uri = uri!(engine::create_session());
-    let session = NewSession {
-        plaintext_metadata: input,
-        program,
+    assert_eq!(
+        r.into_json::<Error>().unwrap(),
+        Error::CircuitHashMismatch {
+            server_circuit: Some(circuit.gates.stats()),
+            compiler_version: None,
+        }
+    );
+
+    let config = rocket::Config::figment().merge(("circuit_diagnostics", false));
+    let client = &Client::tracked(_rocket().configure(config)).unwrap();
+    let r = client
+        .post(uri!(engine::create_session()))
+        .json(&session)
+        .dispatch();
+    assert_eq!(
+        r.into_json::<Error>().unwrap(),
+        Error::CircuitHashMismatch {
+            server_circuit: None,
+            compiler_version: None,
+        }
+    );
+}
+
+#[test]
+fn test_trace_id() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let mut session = session_request(xor_and_program(), "false".to_string(), false);
+    session.circuit_hash = [0; 32];
+    let trace_id = |r: LocalResponse| {
+        let json = r.into_json::<serde_json::Value>().unwrap();
+        json.get("trace_id")
+            .and_then(|t| t.as_str())
+            .map(String::from)
+    };
+
+    let r = client
+        .post(uri!(engine::create_session()))
+        .header(Header::new(crate::TRACE_ID_HEADER, "trace-1"))
+        .json(&session)
+        .dispatch();
+    assert_eq!(r.status(), Status::BadRequest);
+    assert_eq!(trace_id(r), Some("trace-1".to_string()));
+
+    let r = client
+        .post(uri!(engine::dialog("unknown")))
+        .header(Header::new(crate::TRACE_ID_HEADER, "trace-2"))
+        .body(bincode::serialize(&(None::<MessageId>, MessageLog::new())).unwrap())
+        .dispatch();
+    assert_eq!(r.status(), Status::NotFound);
+    assert_eq!(trace_id(r), Some("trace-2".to_string()));
+
+    // invalid trace ids are ignored instead of being echoed:
+    let r = client
+        .post(uri!(engine::create_session()))
+        .header(Header::new(crate::TRACE_ID_HEADER, "trace 3"))
+        .json(&session)
+        .dispatch();
+    assert_eq!(r.status(), Status::BadRequest);
+    assert_eq!(trace_id(r), None);
+}
+
+#[test]
+fn test_approvals() {
+    let config = rocket::Config::figment().merge(("approval_token", "secret"));
+    let client = &Client::tracked(_rocket().configure(config)).unwrap();
+    let program = xor_and_program();
+    let request_approval = |plaintext_metadata: &str| {
+        let request = NewApproval {
+            program_hash: *blake3::hash(program.as_bytes()).as_bytes(),
+            function: "main".to_string(),
+            plaintext_metadata: plaintext_metadata.to_string(),
+            input_commitment: [7; 32],
+        };
+        let r = client
+            .post(uri!(engine::create_approval()))
+            .json(&request)
+            .dispatch();
+        assert_eq!(r.status(), Status::Created);
+        r.into_json::<Approval>().unwrap()
+    };
+    let decide = |approval_id: &str, token: &str, approve: bool| {
+        let uri = if approve {
+            uri!(engine::approve(approval_id))
+        } else {
+            uri!(engine::reject(approval_id))
+        };
+        client
+            .post(uri)
+            .header(Header::new("Authorization", format!("Bearer {token}")))
+            .dispatch()
+            .status()
+    };
+    let create = |approval_id: Option<String>, plaintext_metadata: &str| {
+        let mut session = session_request(program.clone(), plaintext_metadata.to_string(), false);
+        session.approval_id = approval_id;
+        client
+            .post(uri!(engine::create_session()))
+            .json(&session)
+            .dispatch()
+            .status()
+    };
+
+    assert_eq!(create(None, "false"), Status::Forbidden);
+
+    let approval = request_approval("false");
+    assert_eq!(approval.status, ApprovalStatus::Pending);
+    let id = approval.approval_id;
+    assert_eq!(create(Some(id.clone()), "false"), Status::Forbidden);
+    assert_eq!(decide(&id, "wrong", true), Status::Unauthorized);
+    let r = client.post(uri!(engine::approve(&id))).dispatch();
+    assert_eq!(r.status(), Status::Unauthorized);
+    assert_eq!(decide(&id, "secret", true), Status::Ok);
+    assert_eq!(decide(&id, "secret", false), Status::Conflict);
+    let r = client.get(uri!(engine::approval(&id))).dispatch();
+    assert_eq!(
+        r.into_json::<Approval>().unwrap().status,
+        ApprovalStatus::Approved
+    );
+
+    // the session must match the approved request and can only be created once:
+    assert_eq!(create(Some(id.clone()), "true"), Status::Forbidden);
+    assert_eq!(create(Some(id.clone()), "false"), Status::Created);
+    assert_eq!(create(Some(id), "false"), Status::NotFound);
+
+    let rejected = request_approval("true").approval_id;
+    assert_eq!(decide(&rejected, "secret", false), Status::Ok);
+    assert_eq!(create(Some(rejected), "true"), Status::Forbidden);
+}
+
+#[test]
+fn test_approvals_disabled() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let request = NewApproval {
+        program_hash: [0; 32],
         function: "main".to_string(),
-        circuit_hash: circuit.gates.blake3_hash(),
-        client_version: env!("CARGO_PKG_VERSION").to_string(),
+        plaintext_metadata: "false".to_string(),
+        input_commitment: [0; 32],
     };
-    client.post(create_sess_uri).json(&session).dispatch()
+    let r = client
+        .post(uri!(engine::create_approval()))
+        .json(&request)
+        .dispatch();
+    assert_eq!(r.status(), Status::NotFound);
 }
 
-fn xor_and_program() -> String {
-    "pub fn main(a: bool, b: bool) -> (bool, bool) { (a ^ b, a & b) }".to_string()
+#[test]
+fn test_program_allowlist() {
+    let allowed = |allowed_programs: serde_json::Value| {
+        let config = rocket::Config::figment().merge(("allowed_programs", allowed_programs));
+        Client::tracked(_rocket().configure(config)).unwrap()
+    };
+    let program = xor_and_program();
+    let program_hash = blake3::hash(program.as_bytes()).to_hex().to_string();
+    let other_program = "pub fn main(a: bool, b: bool) -> bool { a | b }".to_string();
+
+    let client = &allowed(serde_json::json!([{ "program_hash": program_hash }]));
+    let r = new_session(client, program.clone(), "false".to_string());
+    assert_eq!(r.status(), Status::Created);
+    let r = new_session(client, other_program.clone(), "false".to_string());
+    assert_eq!(r.status(), Status::Forbidden);
+    assert_eq!(
+        r.into_json::<Error>().unwrap(),
+        Error::ProgramNotAllowed {
+            program_hash: blake3::hash(other_program.as_bytes()).to_hex().to_string(),
+            max_and_gates: None,
+        }
+    );
+
+    // the xor_and program has a single AND gate:
+    let client = &allowed(serde_json::json!([{
+        "program_hash": program_hash.to_uppercase(),
+        "max_and_gates": 0,
+    }]));
+    let r = new_session(client, program.clone(), "false".to_string());
+    assert_eq!(r.status(), Status::Forbidden);
+    assert_eq!(
+        r.into_json::<Error>().unwrap(),
+        Error::ProgramNotAllowed {
+            program_hash: program_hash.clone(),
+            max_and_gates: Some(0),
+        }
+    );
+
+    // an invalid allowlist rejects all programs:
+    let client = &allowed(serde_json::json!(program_hash));
+    let r = new_session(client, program, "false".to_string());
+    assert_eq!(r.status(), Status::Forbidden);
+}
+
+#[test]
+fn test_program_store() {
+    let store = |max_stored_programs: usize| {
+        let config = rocket::Config::figment().merge(("max_stored_programs", max_stored_programs));
+        Client::tracked(_rocket().configure(config)).unwrap()
+    };
+    let by_hash = |session: &NewSession| NewSession {
+        program: String::new(),
+        ..session.clone()
+    };
+    let create = |client: &Client, session: &NewSession| {
+        let r = client
+            .post(uri!(engine::create_session()))
+            .json(session)
+            .dispatch();
+        (r.status(), r.into_json::<serde_json::Value>().unwrap())
+    };
+    let mut session = session_request(xor_and_program(), "false".to_string(), false);
+    session.capabilities.push("program_store".to_string());
+    let other_program = "pub fn main(a: bool, b: bool) -> bool { a | b }".to_string();
+    let mut other_session = session_request(other_program, "false".to_string(), false);
+    other_session.capabilities.push("program_store".to_string());
+
+    let client = &store(1);
+    let (status, error) = create(client, &by_hash(&session));
+    assert_eq!(status, Status::NotFound);
+    assert_eq!(
+        serde_json::from_value::<Error>(error).unwrap(),
+        Error::UnknownProgram {
+            circuit_hash: blake3::Hash::from(session.circuit_hash)
+                .to_hex()
+                .to_string(),
+        }
+    );
+
+    // sessions without the capability do not store their program:
+    let mut without_store = session_request(xor_and_program(), "false".to_string(), false);
+    let (status, _) = create(client, &without_store);
+    assert_eq!(status, Status::Created);
+    without_store.program = String::new();
+    assert_eq!(create(client, &without_store).0, Status::NotFound);
+
+    let (status, created) = create(client, &session);
+    assert_eq!(status, Status::Created);
+    assert!(created["capabilities"]
+        .as_array()
+        .unwrap()
+        .contains(&"program_store".into()));
+    let (status, created) = create(client, &by_hash(&session));
+    assert_eq!(status, Status::Created);
+    let engine_id = created["engine_id"].as_str().unwrap();
+    assert_eq!(
+        delete_session(client, &engine_id.to_string()).status(),
+        Status::Ok
+    );
+
+    // the least recently used program is evicted:
+    assert_eq!(create(client, &other_session).0, Status::Created);
+    assert_eq!(create(client, &by_hash(&other_session)).0, Status::Created);
+    assert_eq!(create(client, &by_hash(&session)).0, Status::NotFound);
+
+    let client = &store(0);
+    assert_eq!(create(client, &session).0, Status::Created);
+    assert_eq!(create(client, &by_hash(&session)).0, Status::NotFound);
+
+    // pre-registered programs are never evicted, but still compiled by the handler:
+    let config = ServerConfig::default().with_program(session.circuit_hash, xor_and_program());
+    let rocket = build_with_config(Box::new(handler), config)
+        .configure(rocket::Config::figment().merge(("max_stored_programs", 1)));
+    let client = &Client::tracked(rocket).unwrap();
+    assert_eq!(create(client, &other_session).0, Status::Created);
+    assert_eq!(create(client, &by_hash(&session)).0, Status::Created);
+    let config = ServerConfig::default().with_program(session.circuit_hash, other_session.program);
+    let client = &Client::tracked(build_with_config(Box::new(handler), config)).unwrap();
+    let (status, error) = create(client, &by_hash(&session));
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(error["error"], "CircuitHashMismatch");
+}
+
+#[test]
+fn test_raw_circuit() {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    let raw_circuit_handler = |r: MpcRequest| -> Result<MpcSession, String> {
+        let circuit = r.circuit.ok_or("expected a raw circuit")?;
+        Ok(MpcSession {
+            input_from_server: vec![false; circuit.contrib_inputs()],
+            circuit,
+            request_headers: HashMap::new(),
+            on_output: None,
+        })
+    };
+    let create = |client: &Client, session: &NewSession| {
+        let r = client
+            .post(uri!(engine::create_session()))
+            .json(session)
+            .dispatch();
+        (r.status(), r.into_json::<serde_json::Value>().unwrap())
+    };
+    let prg = check_program(&xor_and_program()).unwrap();
+    let circuit = compile_program(&prg, "main").unwrap().gates.into_inner();
+    let mut columnar = vec![];
+    circuit.write_columnar(&mut columnar).unwrap();
+    let mut session = session_request(xor_and_program(), String::new(), false);
+    session.program = String::new();
+    session.function = String::new();
+    session.circuit = Some(STANDARD.encode(&columnar));
+    session.capabilities.push("multi_round".to_string());
+    session.capabilities.push("program_store".to_string());
+
+    let client = &Client::tracked(build(Box::new(raw_circuit_handler))).unwrap();
+    let (status, created) = create(client, &session);
+    assert_eq!(status, Status::Created);
+    assert_eq!(created["capabilities"], serde_json::json!(["streaming"]));
+
+    let invalid = NewSession {
+        circuit: Some(STANDARD.encode(&columnar[..columnar.len() - 1])),
+        ..session.clone()
+    };
+    let (status, error) = create(client, &invalid);
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(error["error"], "UnexpectedWireFormat");
+
+    // handlers that compile programs reject raw circuits:
+    let client = &Client::tracked(_rocket()).unwrap();
+    let (status, error) = create(client, &session);
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(error["error"], "MpcRequestRejected");
+
+    // raw circuits are allowlisted by their circuit hash:
+    let allowed = |program_hash: String| {
+        let allowed = serde_json::json!([{ "program_hash": program_hash, "max_and_gates": 1 }]);
+        let config = rocket::Config::figment().merge(("allowed_programs", allowed));
+        Client::tracked(build(Box::new(raw_circuit_handler)).configure(config)).unwrap()
+    };
+    let circuit_hash = blake3::Hash::from(session.circuit_hash).to_hex();
+    let client = &allowed(circuit_hash.to_string());
+    assert_eq!(create(client, &session).0, Status::Created);
+    let program_hash = blake3::hash(xor_and_program().as_bytes()).to_hex();
+    let client = &allowed(program_hash.to_string());
+    assert_eq!(create(client, &session).0, Status::Forbidden);
+}
+
+#[test]
+fn test_resource_quotas() {
+    let limited = |quota: &str, limit: usize| {
+        let config = rocket::Config::figment().merge((quota, limit));
+        Client::tracked(_rocket().configure(config)).unwrap()
+    };
+    let program = xor_and_program();
+    let prg = check_program(&program).unwrap();
+    let TypedCircuit { gates, .. } = compile_program(&prg, "main").unwrap();
+
+    // the xor_and program has a single AND gate:
+    let client = &limited("max_session_and_gates", 0);
+    let r = new_session(client, program.clone(), "false".to_string());
+    assert_eq!(r.status(), Status::PayloadTooLarge);
+    assert_eq!(
+        r.into_json::<Error>().unwrap(),
+        Error::QuotaExceeded {
+            quota: Quota::SessionAndGates,
+            limit: 0,
+            requested: 1,
+        }
+    );
+
+    // the AND gates of running sessions count towards the total until they are dropped:
+    let client = &limited("max_total_and_gates", 1);
+    let r = new_session(client, program.clone(), "false".to_string());
+    assert_eq!(r.status(), Status::Created);
+    let EngineCreationResult { engine_id, .. } = r.into_json().unwrap();
+    let r = new_session(client, program.clone(), "false".to_string());
+    assert_eq!(r.status(), Status::ServiceUnavailable);
+    assert_eq!(
+        r.into_json::<Error>().unwrap(),
+        Error::QuotaExceeded {
+            quota: Quota::TotalAndGates,
+            limit: 1,
+            requested: 2,
+        }
+    );
+    assert_eq!(delete_session(client, &engine_id).status(), Status::Ok);
+    let r = new_session(client, program.clone(), "false".to_string());
+    assert_eq!(r.status(), Status::Created);
+
+    // sessions are rejected upfront if a single message exceeds the queue:
+    let plan = tandem::ProtocolPlan::new(&gates);
+    let max_msg = plan
+        .message_size_hints()
+        .iter()
+        .map(|h| h.contributor)
+        .max()
+        .unwrap();
+    let client = &limited("max_queue_bytes", max_msg - 1);
+    let r = new_session(client, program.clone(), "false".to_string());
+    assert_eq!(r.status(), Status::PayloadTooLarge);
+    assert_eq!(
+        r.into_json::<Error>().unwrap(),
+        Error::QuotaExceeded {
+            quota: Quota::QueueBytes,
+            limit: max_msg - 1,
+            requested: max_msg,
+        }
+    );
+
+    // a client that never acknowledges the messages of the server fills the queue:
+    let client = &limited("max_queue_bytes", max_msg);
+    let r = new_session(client, program, "false".to_string());
+    assert_eq!(r.status(), Status::Created);
+    let EngineCreationResult {
+        engine_id,
+        messages,
+        ..
+    } = r.into_json().unwrap();
+    let mut evaluator = Evaluator::new(gates, vec![true], ChaCha20Rng::from_entropy()).unwrap();
+    let (mut msg, mut last_offset) = messages.last().cloned().unwrap();
+    for client_offset in 0.. {
+        assert!(client_offset < evaluator.steps());
+        let (next_state, reply) = evaluator.run(&msg).unwrap();
+        evaluator = next_state;
+        let dialog = |ack: Option<u32>| {
+            let body = bincode::serialize(&(ack, vec![(reply.clone(), client_offset)])).unwrap();
+            client
+                .post(uri!(engine::dialog(&engine_id)))
+                .body(body)
+                .dispatch()
+        };
+        let r = dialog(None);
+        if r.status() != Status::Ok {
+            assert_eq!(r.status(), Status::TooManyRequests);
+            match r.into_json::<Error>().unwrap() {
+                Error::QueueFull {
+                    queued_bytes,
+                    limit,
+                } => assert!(limit == max_msg && queued_bytes >= max_msg),
+                e => panic!("unexpected error {e:?}"),
+            }
+            // the message is processed once the queued messages are acknowledged:
+            let r = dialog(Some(last_offset));
+            assert_eq!(r.status(), Status::Ok);
+            let (msgs, _) = frames::decode(&r.into_bytes().unwrap()).unwrap();
+            assert_eq!(msgs[0].1, last_offset + 1);
+            break;
+        }
+        let (msgs, _) = frames::decode(&r.into_bytes().unwrap()).unwrap();
+        // all unacknowledged messages are resent:
+        assert_eq!(msgs[0].1, 0);
+        (msg, last_offset) = msgs.last().cloned().unwrap();
+        assert_eq!(last_offset as usize, msgs.len() - 1);
+    }
+}
+
+#[test]
+fn test_stickiness_provider() {
+    /// Routes the sessions of each function to their own pool of instances.
+    struct FunctionPools;
+
+    impl StickinessProvider for FunctionPools {
+        fn request_headers(&self, request: &MpcRequest) -> HashMap<String, String> {
+            HashMap::from([("x-pool".to_string(), request.function.clone())])
+        }
+    }
+
+    let headers = |config: ServerConfig| {
+        let client = &Client::tracked(build_with_config(Box::new(handler), config)).unwrap();
+        let r = new_session(client, xor_and_program(), "false".to_string());
+        assert_eq!(r.status(), Status::Created);
+        r.into_json::<EngineCreationResult>()
+            .unwrap()
+            .request_headers
+    };
+
+    let config = ServerConfig::default().with_stickiness_provider(Box::new(FunctionPools));
+    assert_eq!(headers(config)["x-pool"], "main");
+
+    let instance = HashMap::from([("fly-force-instance-id".to_string(), "b996131a".to_string())]);
+    let config =
+        ServerConfig::default().with_stickiness_provider(Box::new(StaticHeaders(instance)));
+    assert_eq!(headers(config)["fly-force-instance-id"], "b996131a");
+}
+
+#[test]
+fn test_external_ids() {
+    /// Assigns all sessions to the same order, except for sessions with the metadata `"true"`,
+    /// which get an invalid engine id.
+    struct OrderIds(u32);
+
+    impl IdGenerator for OrderIds {
+        fn engine_id(&self, request: &MpcRequest) -> String {
+            match self.external_id(request) {
+                Some(order) => format!("{order}-{}", uuid::Uuid::new_v4()),
+                None => "has spaces".to_string(),
+            }
+        }
+
+        fn external_id(&self, request: &MpcRequest) -> Option<String> {
+            (request.plaintext_metadata != "true").then(|| format!("order-{}", self.0))
+        }
+    }
+
+    let config = ServerConfig::default().with_id_generator(OrderIds(1));
+    let client = &Client::tracked(build_with_config(Box::new(handler), config)).unwrap();
+    let lookup = |external_id: &str| {
+        client
+            .get(uri!(engine::external_id(external_id)))
+            .dispatch()
+    };
+
+    let r = new_session(client, xor_and_program(), "false".to_string());
+    assert_eq!(r.status(), Status::Created);
+    let EngineCreationResult {
+        engine_id,
+        external_id,
+        ..
+    } = r.into_json().unwrap();
+    assert!(engine_id.starts_with("order-1-"));
+    assert_eq!(external_id.as_deref(), Some("order-1"));
+
+    let r = lookup("order-1");
+    assert_eq!(r.status(), Status::Ok);
+    assert_eq!(
+        r.into_json::<ExternalId>().unwrap(),
+        ExternalId {
+            external_id: "order-1".to_string(),
+            engine_id: engine_id.clone(),
+        }
+    );
+    assert_eq!(lookup("order-2").status(), Status::NotFound);
+
+    // external ids are unique among running sessions:
+    let r = new_session(client, xor_and_program(), "false".to_string());
+    assert_eq!(r.status(), Status::Conflict);
+    assert_eq!(
+        r.into_json::<Error>(),
+        Some(Error::DuplicateExternalId {
+            external_id: "order-1".to_string()
+        })
+    );
+
+    // the external id is released once the session is dropped:
+    assert_eq!(delete_session(client, &engine_id).status(), Status::Ok);
+    assert_eq!(lookup("order-1").status(), Status::NotFound);
+    let r = new_session(client, xor_and_program(), "false".to_string());
+    assert_eq!(r.status(), Status::Created);
+
+    // invalid engine ids are rejected:
+    let r = new_session(client, xor_and_program(), "true".to_string());
+    assert_eq!(r.status(), Status::InternalServerError);
+}
+
+#[test]
+fn test_structured_metadata() {
+    use std::sync::{Arc, Mutex};
+
+    let received = Arc::new(Mutex::new(vec![]));
+    let recorded = Arc::clone(&received);
+    let handler = move |r: MpcRequest| {
+        let metadata = (
+            r.plaintext_metadata.clone(),
+            r.plaintext_metadata_json.clone(),
+        );
+        recorded.lock().unwrap().push(metadata);
+        handler(MpcRequest {
+            plaintext_metadata: "false".to_string(),
+            ..r
+        })
+    };
+    let client = &Client::tracked(build(Box::new(handler))).unwrap();
+
+    let metadata = serde_json::json!({"input": "false", "tags": [1, 2]});
+    let mut session = session_request(xor_and_program(), "false".to_string(), false);
+    session.plaintext_metadata = metadata.clone();
+    let r = client
+        .post(uri!(engine::create_session()))
+        .json(&session)
+        .dispatch();
+    assert_eq!(r.status(), Status::Created);
+
+    // plain strings are passed to the handler unchanged:
+    let r = new_session(client, xor_and_program(), "plain".to_string());
+    assert_eq!(r.status(), Status::Created);
+
+    assert_eq!(
+        *received.lock().unwrap(),
+        vec![
+            (r#"{"input":"false","tags":[1,2]}"#.to_string(), metadata),
+            ("plain".to_string(), serde_json::json!("plain")),
+        ]
+    );
+}
+
+/// runs protocol with upstream
+///
+/// assumes upstream session was already created, downloads the final message in 2 parts from
+/// `final_url` if it was staged
+/// Runs the protocol as the evaluator, starting with the messages piggybacked on the creation of
+/// the session (if any), and returns the output together with the number of dialog rounds.
+#[cfg(feature = "chaos")]
+#[test]
+fn test_chaos() {
+    use rocket::error::ErrorKind;
+
+    let chaos = |fault: &str| {
+        let config = rocket::Config::figment()
+            .merge(("chaos.seed", 1))
+            .merge((format!("chaos.routes.dialog.{fault}_probability"), 1.0));
+        Client::tracked(_rocket().configure(config)).unwrap()
+    };
+    let empty_dialog = bincode::serialize(&(None::<u32>, Vec::<(Msg, MessageId)>::new())).unwrap();
+
+    let client = &chaos("error");
+    let r = new_session(client, xor_and_program(), "false".to_string());
+    assert_eq!(r.status(), Status::Created);
+    let EngineCreationResult { engine_id, .. } = r.into_json().unwrap();
+    let r = client
+        .post(uri!(engine::dialog(&engine_id)))
+        .body(&empty_dialog)
+        .dispatch();
+    assert_eq!(r.status(), Status::ServiceUnavailable);
+    assert!(matches!(r.into_json().unwrap(), Error::Internal { .. }));
+
+    let client = &chaos("drop");
+    let r = new_session(client, xor_and_program(), "false".to_string());
+    let EngineCreationResult { engine_id, .. } = r.into_json().unwrap();
+    let r = client
+        .post(uri!(engine::dialog(&engine_id)))
+        .body(&empty_dialog)
+        .dispatch();
+    assert_eq!(r.into_bytes(), None);
+
+    // the messages that have not been acknowledged yet are sent twice:
+    let client = &chaos("duplicate");
+    let r = new_session(client, xor_and_program(), "false".to_string());
+    let EngineCreationResult {
+        engine_id,
+        messages,
+        ..
+    } = r.into_json().unwrap();
+    let (msgs, _) = dialog(client, &engine_id, None, &vec![]);
+    assert!(!messages.is_empty());
+    assert_eq!(msgs, [messages.clone(), messages].concat());
+
+    // routes without faults are not affected:
+    let r = delete_session(client, &engine_id);
+    assert_eq!(r.status(), Status::Ok);
+
+    // invalid probabilities prevent the server from launching:
+    let config = rocket::Config::figment().merge(("chaos.routes.dialog.drop_probability", 2.0));
+    match Client::tracked(_rocket().configure(config)) {
+        Err(e) => assert!(matches!(e.kind(), ErrorKind::FailedFairings(_))),
+        Ok(_) => panic!("the server was launched despite an invalid chaos config"),
+    }
+}
+
+#[derive(Clone, Default)]
+struct RecordedEvents(Arc<Mutex<Vec<AuditEvent>>>);
+
+impl AuditSink for RecordedEvents {
+    fn record(&self, event: &AuditEvent) {
+        self.0.lock().unwrap().push(event.clone());
+    }
+}
+
+#[test]
+fn test_idempotent_sessions() {
+    let events = RecordedEvents::default();
+    let config = ServerConfig::default().with_audit_sink(events.clone());
+    let client = &Client::tracked(build_with_config(Box::new(handler), config)).unwrap();
+    let program = xor_and_program();
+    let create = |session: &NewSession| {
+        let r = client
+            .post(uri!(engine::create_session()))
+            .json(session)
+            .dispatch();
+        (r.status(), r.into_json::<serde_json::Value>().unwrap())
+    };
+    let mut session = session_request(program.clone(), "false".to_string(), false);
+    session.idempotency_key = Some("key-1".to_string());
+
+    // a retried request returns the session created by the first request:
+    let (status, first) = create(&session);
+    assert_eq!(status, Status::Created);
+    let (status, retried) = create(&session);
+    assert_eq!(status, Status::Created);
+    assert_eq!(first, retried);
+    assert_eq!(events.0.lock().unwrap().len(), 1);
+    let engine_id = first["engine_id"].as_str().unwrap().to_string();
+    assert_eq!(delete_session(client, &engine_id).status(), Status::Ok);
+
+    // the key cannot be reused for a different request:
+    let mut other = session_request(program.clone(), "true".to_string(), false);
+    other.idempotency_key = session.idempotency_key.clone();
+    let (status, error) = create(&other);
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(error["error"], "IdempotencyKeyReused");
+
+    // other keys (or no key) create new sessions:
+    other.idempotency_key = Some("key-2".to_string());
+    let (_, second) = create(&other);
+    other.idempotency_key = None;
+    let (_, third) = create(&other);
+    let (_, fourth) = create(&other);
+    assert_ne!(second["engine_id"], first["engine_id"]);
+    assert_ne!(third["engine_id"], fourth["engine_id"]);
+
+    let config = rocket::Config::figment().merge(("idempotency_ttl_secs", 0));
+    let client = &Client::tracked(_rocket().configure(config)).unwrap();
+    let r = client
+        .post(uri!(engine::create_session()))
+        .json(&session)
+        .dispatch();
+    let first = r.into_json::<EngineCreationResult>().unwrap();
+    let r = client
+        .post(uri!(engine::create_session()))
+        .json(&session)
+        .dispatch();
+    let retried = r.into_json::<EngineCreationResult>().unwrap();
+    assert_ne!(first.engine_id, retried.engine_id);
+}
+
+#[test]
+fn test_concurrent_idempotent_sessions() {
+    let events = RecordedEvents::default();
+    let calls = Arc::new(Mutex::new(0));
+    let slow_handler = {
+        let calls = Arc::clone(&calls);
+        move |r: MpcRequest| {
+            *calls.lock().unwrap() += 1;
+            // the retry arrives while the first request is still compiling its circuit:
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            handler(r)
+        }
+    };
+    let config = ServerConfig::default().with_audit_sink(events.clone());
+    let client = &Client::tracked(build_with_config(Box::new(slow_handler), config)).unwrap();
+    let registry = client.rocket().state::<Arc<EngineRegistry>>().unwrap();
+    let mut session = session_request(xor_and_program(), "false".to_string(), false);
+    session.idempotency_key = Some("key-1".to_string());
+
+    let spawn = |session: NewSession| {
+        let registry = Arc::clone(registry);
+        std::thread::spawn(move || engine::new_session(&registry, &session, None))
+    };
+    let (first, retried) = (spawn(session.clone()), spawn(session));
+    let (first, retried) = (first.join().unwrap(), retried.join().unwrap());
+    let (first, retried) = (first.unwrap(), retried.unwrap());
+    assert_eq!(first.engine_id, retried.engine_id);
+    assert_eq!(*calls.lock().unwrap(), 1);
+    assert_eq!(events.0.lock().unwrap().len(), 1);
+
+    // a failed request releases the key, so that a retry can create the session:
+    let mut invalid = session_request(xor_and_program(), "false".to_string(), false);
+    invalid.idempotency_key = Some("key-2".to_string());
+    invalid.function = "missing".to_string();
+    assert!(engine::new_session(registry, &invalid, None).is_err());
+    invalid.function = "main".to_string();
+    assert!(engine::new_session(registry, &invalid, None).is_ok());
+}
+
+#[test]
+fn test_redelivered_dialog() {
+    let client = &Client::tracked(_rocket()).unwrap();
+    let program = xor_and_program();
+    let r = new_session(client, program.clone(), "true".to_string());
+    assert_eq!(r.status(), Status::Created);
+    let EngineCreationResult {
+        engine_id,
+        messages,
+        ..
+    } = r.into_json().unwrap();
+    let post = |body: &[u8]| {
+        client
+            .post(uri!(engine::dialog(&engine_id)))
+            .body(body)
+            .dispatch()
+    };
+
+    // messages that skip an offset are rejected:
+    let gap = bincode::serialize(&(None::<u32>, vec![(vec![0u8], 1u32)])).unwrap();
+    assert_eq!(post(&gap).status(), Status::BadRequest);
+
+    let prg = check_program(&program).unwrap();
+    let TypedCircuit { gates, fn_def, .. } = compile_program(&prg, "main").unwrap();
+    let mut evaluator = Evaluator::new(gates, vec![true], ChaCha20Rng::from_entropy()).unwrap();
+    let mut steps_remaining = evaluator.steps();
+    let mut context = MsgQueue::new(None);
+    let mut last_offset: Option<MessageId> = None;
+    let mut stale_request: Option<Vec<u8>> = None;
+    let mut upstream_msgs = messages;
+    let output = 'protocol: loop {
+        // messages that were already processed are ignored:
+        for (msg, offset) in upstream_msgs.iter() {
+            if Some(*offset) <= last_offset {
+                continue;
+            }
+            assert_eq!(*offset, last_offset.map_or(0, |o| o + 1));
+            if steps_remaining == 0 {
+                break 'protocol evaluator.output(msg).unwrap();
+            }
+            let (next_state, reply) = evaluator.run(msg).unwrap();
+            evaluator = next_state;
+            steps_remaining -= 1;
+            context.send(reply);
+            last_offset = Some(*offset);
+        }
+        let messages: Vec<(&Msg, MessageId)> = context.msgs_iter().collect();
+        let last_sent = messages.last().map(|m| m.1);
+        let body = bincode::serialize(&(last_offset, messages)).unwrap();
+        // a late request of the previous round does not affect the session:
+        if let Some(stale) = stale_request.replace(body.clone()) {
+            assert_eq!(post(&stale).status(), Status::Ok);
+        }
+        // the response to the first request is lost, so the client sends the request again:
+        assert_eq!(post(&body).status(), Status::Ok);
+        let r = post(&body);
+        assert_eq!(r.status(), Status::Ok);
+        let (msgs, server_offset) = frames::decode(&r.into_bytes().unwrap()).unwrap();
+        assert_eq!(server_offset, last_sent);
+        if let Some(offset) = server_offset {
+            context.flush_queue(offset);
+        }
+        upstream_msgs = msgs;
+    };
+    let output = deserialize_output(&prg, &fn_def, &output)
+        .unwrap()
+        .as_bits(&prg);
+    assert_eq!(output, vec![false, true]);
+    // the finished session is only kept to answer repeated requests:
+    assert_eq!(
+        delete_session(client, &engine_id).status(),
+        Status::NotFound
+    );
+    assert_eq!(post(stale_request.as_ref().unwrap()).status(), Status::Ok);
+
+    let config = rocket::Config::figment().merge(("redelivery_ttl_secs", 0));
+    let client = &Client::tracked(_rocket().configure(config)).unwrap();
+    let r = new_session(client, program, "true".to_string());
+    let EngineCreationResult { engine_id, .. } = r.into_json().unwrap();
+    let abort = bincode::serialize(&(None::<u32>, vec![(vec![0u8], 0u32)])).unwrap();
+    let dialog = |body: &[u8]| {
+        client
+            .post(uri!(engine::dialog(&engine_id)))
+            .body(body)
+            .dispatch()
+            .status()
+    };
+    assert_eq!(dialog(&abort), Status::Ok);
+    assert_eq!(dialog(&abort), Status::NotFound);
+}
+
+#[test]
+fn test_persisted_message_log() {
+    let store = MemorySessionStore::default();
+    let server = || {
+        let config = ServerConfig::default().with_session_store(store.clone());
+        Client::tracked(build_with_config(Box::new(handler), config)).unwrap()
+    };
+    let (a, b) = (&server(), &server());
+    let program = xor_and_program();
+    let r = new_session(a, program.clone(), "true".to_string());
+    let EngineCreationResult {
+        engine_id,
+        messages,
+        ..
+    } = r.into_json().unwrap();
+
+    let prg = check_program(&program).unwrap();
+    let TypedCircuit { gates, .. } = compile_program(&prg, "main").unwrap();
+    let evaluator = Evaluator::new(gates, vec![true], ChaCha20Rng::from_entropy()).unwrap();
+    let (_, reply) = evaluator.run(&messages[0].0).unwrap();
+    let body = bincode::serialize(&(Some(messages[0].1), vec![(reply, 0u32)])).unwrap();
+    let post = |client: &Client, body: &[u8]| {
+        let r = client
+            .post(uri!(engine::dialog(&engine_id)))
+            .body(body)
+            .dispatch();
+        (r.status(), r.into_bytes())
+    };
+    let (status, response) = post(a, &body);
+    assert_eq!(status, Status::Ok);
+
+    // another server (or the same one after a restart) answers the repeated request from the log:
+    assert_eq!(post(b, &body), (Status::Ok, response));
+    // but cannot continue the session:
+    let next = bincode::serialize(&(Some(1u32), vec![(vec![0u8], 1u32)])).unwrap();
+    assert_eq!(post(b, &next).0, Status::NotFound);
+
+    // deleted sessions are removed from the store:
+    assert_eq!(delete_session(a, &engine_id).status(), Status::Ok);
+    assert_eq!(post(b, &body).0, Status::NotFound);
+    assert!(store.0.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_audit_events() {
+    let events = RecordedEvents::default();
+    let config = ServerConfig::default().with_audit_sink(events.clone());
+    let client = &Client::tracked(build_with_config(Box::new(handler), config)).unwrap();
+    let program = xor_and_program();
+
+    let r = new_session(client, program.clone(), "true".to_string());
+    let EngineCreationResult {
+        engine_id: completed,
+        messages,
+        ..
+    } = r.into_json().unwrap();
+    let circuit = compile_program(&check_program(&program).unwrap(), "main")
+        .unwrap()
+        .gates;
+    tandem_http_protocol(
+        client,
+        &completed,
+        circuit,
+        vec![false],
+        None,
+        messages,
+        None,
+    );
+
+    let r = new_session(client, program.clone(), "invalid".to_string());
+    assert_eq!(r.status(), Status::BadRequest);
+
+    let r = new_session(client, program.clone(), "false".to_string());
+    let EngineCreationResult {
+        engine_id: aborted, ..
+    } = r.into_json().unwrap();
+    let abort = tandem::abort_message(tandem::AbortReason::Cancelled);
+    dialog(client, &aborted, None, &vec![(&abort, 0)]);
+
+    let r = new_session(client, program.clone(), "false".to_string());
+    let EngineCreationResult {
+        engine_id: deleted, ..
+    } = r.into_json().unwrap();
+    assert_eq!(delete_session(client, &deleted).status(), Status::Ok);
+
+    let events = events.0.lock().unwrap();
+    let outcomes: Vec<_> = events.iter().map(|e| e.outcome).collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            AuditOutcome::Created,
+            AuditOutcome::Completed,
+            AuditOutcome::Failed,
+            AuditOutcome::Created,
+            AuditOutcome::Failed,
+            AuditOutcome::Created,
+            AuditOutcome::Failed,
+        ]
+    );
+    let engine_ids: Vec<_> = events
+        .iter()
+        .map(|e| e.engine_id.clone().unwrap())
+        .collect();
+    assert_eq!(engine_ids[0..2], [completed.clone(), completed]);
+    assert_eq!(engine_ids[3..5], [aborted.clone(), aborted]);
+    assert_eq!(engine_ids[5..7], [deleted.clone(), deleted]);
+    let program_hash = blake3::hash(program.as_bytes()).to_hex().to_string();
+    assert!(events
+        .iter()
+        .all(|e| e.program_hash == program_hash && e.function == "main"));
+    assert_eq!(events[0].plaintext_metadata, "true");
+    assert!(events[2]
+        .error
+        .as_ref()
+        .unwrap()
+        .contains("MpcRequestRejected"));
+    assert!(events[4]
+        .error
+        .as_ref()
+        .unwrap()
+        .starts_with("aborted by the client"));
+    assert_eq!(events[6].error.as_deref(), Some("deleted by the client"));
+}
+
+#[test]
+fn test_json_lines_audit_sink() {
+    let path = std::env::temp_dir().join(format!("tandem_audit_{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let event = |outcome| AuditEvent {
+        outcome,
+        timestamp_millis: 0,
+        duration_millis: 0,
+        engine_id: Some("engine".to_string()),
+        external_id: None,
+        program_hash: "hash".to_string(),
+        function: "main".to_string(),
+        plaintext_metadata: "_".to_string(),
+        client: None,
+        error: None,
+        transcript_confirmed: None,
+    };
+
+    let sink = JsonLinesAuditSink::open(&path).unwrap();
+    sink.record(&event(AuditOutcome::Created));
+    sink.record(&event(AuditOutcome::Completed));
+    drop(sink);
+    // reopening the log continues the chain of hashes:
+    let sink = JsonLinesAuditSink::open(&path).unwrap();
+    sink.record(&event(AuditOutcome::Created));
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 3);
+    let mut prev_hash = serde_json::Value::Null;
+    for line in lines {
+        let json: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(json["prev_hash"], prev_hash);
+        assert_eq!(json["function"], "main");
+        prev_hash = blake3::hash(line.as_bytes()).to_hex().to_string().into();
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_conformance_vectors() {
+    use crate::state::{EngineRef, SessionInfo};
+    use tandem::conformance::{generate, CONFORMANCE_SEED};
+
+    let session = SessionInfo::new(
+        &session_request(xor_and_program(), "false".into(), false),
+        None,
+    );
+    for vector in generate(CONFORMANCE_SEED).unwrap() {
+        let reference = vector.reference_circuit().unwrap();
+        for stage_final in [false, true] {
+            // the engine replays the recorded messages of the evaluator, using the recorded RNG:
+            let mut engine = EngineRef::new(
+                vector.contributor.rng(),
+                reference.circuit.clone(),
+                vector.contributor_input.clone(),
+                stage_final,
+                None,
+                None,
+                &[],
+                None,
+                None,
+                None,
+                session.clone(),
+            )
+            .unwrap();
+            for (offset, msg) in vector.evaluator.sent.iter().enumerate() {
+                engine.process_message(msg, offset as MessageId).unwrap();
+            }
+            assert!(engine.is_done());
+            assert_eq!(engine.failures(), 0);
+
+            let mut sent: Vec<Msg> = engine
+                .dump_messages()
+                .into_iter()
+                .map(|(msg, _)| msg.clone())
+                .collect();
+            if stage_final {
+                assert_eq!(sent.pop(), Some(vec![]));
+                sent.push(engine.take_staged_final().unwrap());
+            }
+            assert_eq!(sent, vector.contributor.sent, "{}", vector.name);
+        }
+    }
+}
+
+#[test]
+fn test_transcript_confirmation() {
+    use crate::state::{EngineRef, SessionInfo};
+
+    let request = session_request(xor_and_program(), "false".into(), false);
+    let session = SessionInfo::new(&request, None);
+    let prg = check_program(&request.program).unwrap();
+    let circuit = compile_program(&prg, "main").unwrap().gates.into_inner();
+    let capabilities = vec!["transcript_confirmation".to_string()];
+    let run = |corrupt_hash: bool| {
+        let mut engine = EngineRef::new(
+            ChaCha20Rng::from_entropy(),
+            circuit.clone(),
+            vec![false],
+            false,
+            None,
+            None,
+            &capabilities,
+            None,
+            None,
+            None,
+            session.clone(),
+        )
+        .unwrap();
+        let mut eval =
+            Evaluator::new(circuit.clone(), vec![true], ChaCha20Rng::from_entropy()).unwrap();
+        for offset in 0..eval.steps() {
+            let (msg, _) = engine.dump_messages()[offset as usize];
+            let (next_state, reply) = eval.run(msg).unwrap();
+            eval = next_state;
+            engine.process_message(&reply, offset).unwrap();
+        }
+        // the final message is followed by the transcript hash of the engine:
+        let msgs = engine.dump_messages();
+        let (final_msg, _) = msgs[msgs.len() - 2];
+        let (hash, _) = msgs[msgs.len() - 1];
+        eval.expect_transcript_hash(hash);
+        let report = eval.output_with_report(final_msg).unwrap();
+        assert!(report.verified);
+        assert!(!engine.is_done());
+        assert_eq!(engine.session().transcript_confirmed, None);
+
+        let mut hash = report.transcript_hash.to_vec();
+        if corrupt_hash {
+            hash[0] ^= 1;
+        }
+        let offset = engine.last_durably_received_client_event_offset().unwrap() + 1;
+        engine.process_message(&hash, offset).unwrap();
+        assert!(engine.is_done());
+        engine
+    };
+
+    let engine = run(false);
+    assert_eq!(engine.session().transcript_confirmed, Some(true));
+    assert_eq!(engine.outcome(), (AuditOutcome::Completed, None));
+
+    let engine = run(true);
+    assert_eq!(engine.session().transcript_confirmed, Some(false));
+    assert_eq!(engine.failures(), 1);
+    assert_eq!(engine.outcome().0, AuditOutcome::Failed);
+}
+
+#[test]
+fn test_output_disclosure() {
+    use crate::{
+        state::{EngineRef, SessionInfo},
+        OutputHandler,
+    };
+    use std::sync::{Arc, Mutex};
+
+    let request = session_request(xor_and_program(), "false".into(), false);
+    let session = SessionInfo::new(&request, None);
+    let prg = check_program(&request.program).unwrap();
+    let typed = compile_program(&prg, "main").unwrap();
+    let circuit = typed.gates.clone().into_inner();
+    let capabilities = vec!["output_disclosure".to_string()];
+    let run = |forge_output: bool| {
+        let outputs = Arc::new(Mutex::new(vec![]));
+        let disclosed = Arc::clone(&outputs);
+        let on_output = OutputHandler::new(move |o| disclosed.lock().unwrap().push(o));
+        let mut engine = EngineRef::new(
+            ChaCha20Rng::from_entropy(),
+            circuit.clone(),
+            vec![false],
+            false,
+            None,
+            None,
+            &capabilities,
+            None,
+            Some(on_output),
+            None,
+            session.clone(),
+        )
+        .unwrap();
+        let mut eval =
+            Evaluator::new(circuit.clone(), vec![true], ChaCha20Rng::from_entropy()).unwrap();
+        for offset in 0..eval.steps() {
+            let (msg, _) = engine.dump_messages()[offset as usize];
+            let (next_state, reply) = eval.run(msg).unwrap();
+            eval = next_state;
+            engine.process_message(&reply, offset).unwrap();
+        }
+        let msgs = engine.dump_messages();
+        let (final_msg, _) = msgs[msgs.len() - 1];
+        let report = eval.output_with_report(final_msg).unwrap();
+        assert!(report.verified);
+        assert!(!engine.is_done());
+
+        // the client's disclosure of a different output fails the checks of the engine:
+        let mut disclosure = report.disclosure.clone();
+        if forge_output {
+            let last = disclosure.len() - 1;
+            disclosure[last] ^= 1;
+        }
+        let offset = engine.last_durably_received_client_event_offset().unwrap() + 1;
+        let result = engine.process_message(&disclosure, offset);
+        assert!(engine.is_done());
+        let outputs = outputs.lock().unwrap().clone();
+        (result.is_ok(), outputs)
+    };
+
+    let (ok, outputs) = run(false);
+    assert!(ok);
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[0].function, "main");
+    let literal = deserialize_output(&prg, &typed.fn_def, &outputs[0].output).unwrap();
+    assert_eq!(literal.to_string(), "(true, false)");
+
+    let (_, outputs) = run(true);
+    assert!(outputs.is_empty());
+}
+
+#[test]
+fn test_multi_round() {
+    use crate::{
+        requests::{FollowUps, NextRound},
+        types::NextRoundResult,
+    };
+    use tandem::{preprocessing_circuit, ProtocolOptions};
+
+    let client = &Client::tracked(_rocket()).unwrap();
+    let program = xor_and_program();
+    let prg = check_program(&program).unwrap();
+    let typed = compile_program(&prg, "main").unwrap();
+    let circuit = typed.gates.clone().into_inner();
+
+    // one follow-up round of the same function is reserved:
+    let mut request = session_request(program, "false".into(), false);
+    request.capabilities.push("multi_round".to_string());
+    request.follow_ups = Some(FollowUps {
+        and_gates: circuit.and_gates(),
+        input_bits: circuit.contrib_inputs() + circuit.eval_inputs(),
+    });
+    let r = client
+        .post(uri!(engine::create_session()))
+        .json(&request)
+        .dispatch();
+    assert_eq!(r.status(), Status::Created);
+    let EngineCreationResult {
+        engine_id,
+        capabilities,
+        messages,
+        ..
+    } = r.into_json().unwrap();
+    assert!(capabilities.contains(&"multi_round".to_string()));
+
+    let next_round = |input: &str| {
+        let next = NextRound {
+            plaintext_metadata: input.into(),
+            function: "main".to_string(),
+            circuit_hash: circuit.blake3_hash(),
+        };
+        client
+            .post(uri!(engine::next_round(&engine_id)))
+            .json(&next)
+            .dispatch()
+    };
+    let error = |r: LocalResponse| r.into_json::<Error>().unwrap();
+
+    // the first round needs to be completed before the next round can start:
+    let r = next_round("true");
+    assert_eq!(r.status(), Status::Conflict);
+    assert_eq!(error(r), Error::RoundInProgress);
+
+    let and_gates = 2 * circuit.and_gates();
+    let input_bits = 2 * (circuit.contrib_inputs() + circuit.eval_inputs());
+    let capacity = preprocessing_circuit(and_gates, input_bits);
+    let options = ProtocolOptions::default();
+    let rng = ChaCha20Rng::from_entropy();
+    let mut eval = Evaluator::new_preprocessing(capacity, rng, options).unwrap();
+    let mut upstream_msgs = messages;
+    let mut client_offset = 0;
+    for _ in 0..eval.steps() {
+        let (msg, server_offset) = &upstream_msgs[0];
+        let (next_state, reply) = eval.run(msg).unwrap();
+        eval = next_state;
+        let messages = vec![(&reply, client_offset)];
+        client_offset += 1;
+        upstream_msgs = dialog(client, &engine_id, Some(*server_offset), &messages).0;
+    }
+    let mut triples = eval.preprocessed().unwrap();
+
+    let mut run_round = |input: bool, mut upstream_msgs: MessageLog| {
+        let rng = ChaCha20Rng::from_entropy();
+        let triples = triples.split_off(&circuit).unwrap();
+        let mut eval = Evaluator::from_preprocessed(&circuit, vec![input], rng, triples).unwrap();
+        for _ in 0..eval.steps() {
+            let (msg, server_offset) = &upstream_msgs[0];
+            let (next_state, reply) = eval.run(msg).unwrap();
+            eval = next_state;
+            let messages = vec![(&reply, client_offset)];
+            client_offset += 1;
+            upstream_msgs = dialog(client, &engine_id, Some(*server_offset), &messages).0;
+        }
+        let output = eval.output(&upstream_msgs[0].0).unwrap();
+        deserialize_output(&prg, &typed.fn_def, &output)
+            .unwrap()
+            .to_string()
+    };
+    assert_eq!(run_round(true, upstream_msgs), "(true, false)");
+
+    // the engine is kept for the follow-up round, which only sends the messages of the round:
+    let r = next_round("true");
+    assert_eq!(r.status(), Status::Ok);
+    let NextRoundResult { messages, .. } = r.into_json().unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(run_round(true, messages), "(false, true)");
+
+    // the reserved triples are used up:
+    let r = next_round("true");
+    assert_eq!(r.status(), Status::Conflict);
+    assert_eq!(
+        error(r),
+        Error::NoFollowUp {
+            remaining_and_gates: 0
+        }
+    );
+    assert_eq!(delete_session(client, &engine_id).status(), Status::Ok);
+}
+
+#[test]
+fn test_encrypted_channel() {
+    let server_key = ChannelKeyPair::generate(&mut ChaCha20Rng::from_entropy());
+    let secret_key = blake3::Hash::from(server_key.secret_bytes()).to_hex();
+    let config = rocket::Config::figment().merge(("channel_secret_key", secret_key.as_str()));
+    let client = &Client::tracked(_rocket().configure(config)).unwrap();
+    let program = xor_and_program();
+
+    let handshake = Handshake::initiate(&mut ChaCha20Rng::from_entropy());
+    let mut request = session_request(program.clone(), "true".to_string(), true);
+    request.capabilities.push("encrypted_channel".to_string());
+    request.channel_key = Some(handshake.ephemeral_key());
+    request.session_nonce = Some([7; 32]);
+    let r = client
+        .post(uri!(engine::create_session()))
+        .json(&request)
+        .dispatch();
+    assert_eq!(r.status(), Status::Created);
+    let EngineCreationResult {
+        engine_id,
+        wire_version,
+        capabilities,
+        final_url,
+        messages,
+        session_commitment,
+        channel,
+        ..
+    } = r.into_json().unwrap();
+    assert!(capabilities.contains(&"encrypted_channel".to_string()));
+    let reply = channel.unwrap();
+    assert_eq!(reply.static_key, Some(server_key.public_key()));
+    let channel = handshake
+        .finish(&reply, Some(&server_key.public_key()))
+        .unwrap();
+
+    // the commitment to the session is keyed by the channel:
+    let SessionCommitment {
+        server_nonce,
+        commitment,
+    } = session_commitment.unwrap();
+    let expected = |key: Option<&[u8; 32]>| {
+        let (id, caps) = (&engine_id, &capabilities);
+        SessionCommitment::commit(
+            &request,
+            &[7; 32],
+            &server_nonce,
+            id,
+            wire_version,
+            caps,
+            key,
+        )
+        .unwrap()
+    };
+    assert_eq!(commitment, expected(Some(channel.binding_key())));
+    assert_ne!(commitment, expected(None));
+
+    // messages are bound to their ids:
+    let (msg, offset) = &messages[0];
+    assert!(channel.open(u64::from(offset + 1), msg).is_err());
+
+    let prg = check_program(&program).unwrap();
+    let TypedCircuit { gates, fn_def, .. } = compile_program(&prg, "main").unwrap();
+    let (result, _) = tandem_http_protocol(
+        client,
+        &engine_id,
+        gates,
+        vec![true],
+        final_url,
+        messages,
+        Some(&channel),
+    );
+    let result = deserialize_output(&prg, &fn_def, &result)
+        .unwrap()
+        .as_bits(&prg);
+    assert_eq!(result, vec![false, true]);
+
+    // the channel is not negotiated without a key of the client:
+    let mut request = session_request(program.clone(), "true".to_string(), false);
+    request.capabilities.push("encrypted_channel".to_string());
+    let r = client
+        .post(uri!(engine::create_session()))
+        .json(&request)
+        .dispatch();
+    let created = r.into_json::<EngineCreationResult>().unwrap();
+    assert_eq!(created.capabilities, vec!["streaming".to_string()]);
+    assert_eq!(created.channel, None);
+
+    // invalid keys of the client are rejected:
+    request.channel_key = Some([0; 32]);
+    let r = client
+        .post(uri!(engine::create_session()))
+        .json(&request)
+        .dispatch();
+    assert_eq!(r.status(), Status::BadRequest);
+
+    // the server refuses to launch with an invalid secret key:
+    let config = rocket::Config::figment().merge(("channel_secret_key", "invalid"));
+    match Client::tracked(_rocket().configure(config)) {
+        Err(e) => assert!(matches!(
+            e.kind(),
+            rocket::error::ErrorKind::FailedFairings(_)
+        )),
+        Ok(_) => panic!("the server was launched despite an invalid channel secret key"),
+    }
+}
+
+fn shared_server(store: &MemorySessionStore) -> Client {
+    shared_server_with(ServerConfig::default().with_session_store(store.clone()))
+}
+
+fn shared_server_with(server_config: ServerConfig) -> Client {
+    let key = blake3::hash(b"shared state key").to_hex();
+    let config = rocket::Config::figment()
+        .merge(("shared_registry", true))
+        .merge(("shared_state_key", key.as_str()));
+    Client::tracked(build_with_config(Box::new(handler), server_config).configure(config)).unwrap()
+}
+
+#[test]
+fn test_shared_sessions() {
+    let store = MemorySessionStore::default();
+    let (a, b) = (&shared_server(&store), &shared_server(&store));
+    let program = xor_and_program();
+    let prg = check_program(&program).unwrap();
+    let TypedCircuit { gates, fn_def, .. } = compile_program(&prg, "main").unwrap();
+
+    // capabilities whose state cannot be stored are not offered:
+    let r = a.get(uri!(engine::capabilities())).dispatch();
+    let capabilities = r.into_json::<Capabilities>().unwrap();
+    assert_eq!(
+        capabilities.features,
+        vec![
+            "streaming",
+            "transcript_confirmation",
+            "output_disclosure",
+            "program_store"
+        ]
+    );
+
+    // each request of the session can be processed by another instance:
+    let r = new_session(a, program.clone(), "true".to_string());
+    assert_eq!(r.status(), Status::Created);
+    let EngineCreationResult {
+        engine_id,
+        messages,
+        ..
+    } = r.into_json().unwrap();
+    let (result, rounds) = tandem_http_protocol_on(
+        &[b, a],
+        &engine_id,
+        gates.clone(),
+        vec![false],
+        None,
+        messages,
+        None,
+    );
+    assert!(rounds > 1);
+    let result = deserialize_output(&prg, &fn_def, &result)
+        .unwrap()
+        .as_bits(&prg);
+    assert_eq!(result, vec![true, false]);
+
+    // the final message can be downloaded from another instance:
+    let r = new_session_with(a, program.clone(), "true".to_string(), true);
+    let EngineCreationResult {
+        engine_id,
+        final_url,
+        ..
+    } = r.into_json().unwrap();
+    let (result, _) = tandem_http_protocol_on(
+        &[b, a],
+        &engine_id,
+        gates,
+        vec![true],
+        final_url,
+        vec![],
+        None,
+    );
+    let result = deserialize_output(&prg, &fn_def, &result)
+        .unwrap()
+        .as_bits(&prg);
+    assert_eq!(result, vec![false, true]);
+
+    // an instance must not respond based on a state that another instance has already advanced:
+    let r = new_session(a, program.clone(), "true".to_string());
+    let EngineCreationResult { engine_id, .. } = r.into_json().unwrap();
+    let registry = a.rocket().state::<Arc<EngineRegistry>>().unwrap();
+    registry.lookup(&engine_id).unwrap();
+    dialog(b, &engine_id, None, &vec![]);
+    let empty_dialog = bincode::serialize(&(None::<MessageId>, Vec::<(Msg, MessageId)>::new()));
+    let r = a
+        .post(uri!(engine::dialog(&engine_id)))
+        .body(empty_dialog.unwrap())
+        .dispatch();
+    assert_eq!(r.status(), Status::ServiceUnavailable);
+    // the instance continues with the stored state once the client repeats the request:
+    dialog(a, &engine_id, None, &vec![]);
+
+    // sessions deleted by one instance are deleted for all instances:
+    assert_eq!(delete_session(b, &engine_id).status(), Status::Ok);
+    assert_eq!(delete_session(a, &engine_id).status(), Status::NotFound);
+    let key = format!("tandem:session:{engine_id}");
+    assert!(!store.0.lock().unwrap().contains_key(&key));
+
+    // the server refuses to launch a shared registry without a store or without a key:
+    let config = rocket::Config::figment().merge(("shared_registry", true));
+    match Client::tracked(_rocket().configure(config.clone())) {
+        Err(e) => assert!(matches!(
+            e.kind(),
+            rocket::error::ErrorKind::FailedFairings(_)
+        )),
+        Ok(_) => panic!("the server was launched without a session store"),
+    }
+    let server_config = ServerConfig::default().with_session_store(store);
+    match Client::tracked(build_with_config(Box::new(handler), server_config).configure(config)) {
+        Err(e) => assert!(matches!(
+            e.kind(),
+            rocket::error::ErrorKind::FailedFairings(_)
+        )),
+        Ok(_) => panic!("the server was launched without a shared state key"),
+    }
+
+    // the key must consist of exactly 32 hex-encoded bytes:
+    let config = rocket::Config::figment()
+        .merge(("shared_registry", true))
+        .merge(("shared_state_key", "abcd"));
+    let server_config = ServerConfig::default().with_session_store(MemorySessionStore::default());
+    match Client::tracked(build_with_config(Box::new(handler), server_config).configure(config)) {
+        Err(e) => assert!(matches!(
+            e.kind(),
+            rocket::error::ErrorKind::FailedFairings(_)
+        )),
+        Ok(_) => panic!("the server was launched with an invalid shared state key"),
+    }
+
+    // shared sessions cannot authenticate the server using a channel key:
+    let server_key = ChannelKeyPair::generate(&mut ChaCha20Rng::from_entropy());
+    let secret_key = blake3::Hash::from(server_key.secret_bytes()).to_hex();
+    let config = rocket::Config::figment()
+        .merge(("shared_registry", true))
+        .merge(("shared_state_key", blake3::hash(b"key").to_hex().as_str()))
+        .merge(("channel_secret_key", secret_key.as_str()));
+    let server_config = ServerConfig::default().with_session_store(MemorySessionStore::default());
+    match Client::tracked(build_with_config(Box::new(handler), server_config).configure(config)) {
+        Err(e) => assert!(matches!(
+            e.kind(),
+            rocket::error::ErrorKind::FailedFairings(_)
+        )),
+        Ok(_) => panic!("the server was launched with a channel key for shared sessions"),
+    }
+}
+
+#[test]
+fn test_shared_output_disclosure() {
+    use crate::OutputHandler;
+
+    let outputs = Arc::new(Mutex::new(vec![]));
+    let store = MemorySessionStore::default();
+    let shared = || {
+        let disclosed = Arc::clone(&outputs);
+        let handler = move |r: MpcRequest| {
+            let disclosed = Arc::clone(&disclosed);
+            let on_output = OutputHandler::new(move |o| disclosed.lock().unwrap().push(o));
+            Ok(MpcSession {
+                on_output: Some(on_output),
+                ..handler(r)?
+            })
+        };
+        let key = blake3::hash(b"shared state key").to_hex();
+        let config = rocket::Config::figment()
+            .merge(("shared_registry", true))
+            .merge(("shared_state_key", key.as_str()));
+        let server_config = ServerConfig::default().with_session_store(store.clone());
+        let rocket = build_with_config(Box::new(handler), server_config).configure(config);
+        Client::tracked(rocket).unwrap()
+    };
+    let (a, b) = (&shared(), &shared());
+    let program = xor_and_program();
+    let prg = check_program(&program).unwrap();
+    let typed = compile_program(&prg, "main").unwrap();
+
+    let mut request = session_request(program, "false".into(), false);
+    request.capabilities.push("output_disclosure".to_string());
+    let r = a
+        .post(uri!(engine::create_session()))
+        .json(&request)
+        .dispatch();
+    assert_eq!(r.status(), Status::Created);
+    let EngineCreationResult {
+        engine_id,
+        capabilities,
+        messages,
+        ..
+    } = r.into_json().unwrap();
+    assert!(capabilities.contains(&"output_disclosure".to_string()));
+
+    // the requests of the session alternate between the instances:
+    let circuit = typed.gates.clone();
+    let mut eval = Evaluator::new(circuit, vec![true], ChaCha20Rng::from_entropy()).unwrap();
+    let mut steps_remaining = eval.steps();
+    let mut context = MsgQueue::new(None);
+    let mut last_durably_received_offset = None;
+    let mut upstream_msgs = messages;
+    let mut rounds = 0;
+    let final_msg = loop {
+        let mut final_msg = None;
+        for (msg, offset) in upstream_msgs {
+            if steps_remaining > 0 {
+                let (next_state, reply) = eval.run(&msg).unwrap();
+                eval = next_state;
+                steps_remaining -= 1;
+                context.send(reply);
+            } else {
+                final_msg = Some(msg);
+            }
+            last_durably_received_offset = Some(offset);
+        }
+        if let Some(final_msg) = final_msg {
+            break final_msg;
+        }
+        let messages = context.msgs_iter().collect();
+        let client = [b, a][rounds % 2];
+        let (msgs, offset) = dialog(client, &engine_id, last_durably_received_offset, &messages);
+        if let Some(offset) = offset {
+            context.flush_queue(offset);
+        }
+        upstream_msgs = msgs;
+        rounds += 1;
+    };
+    let report = eval.output_with_report(&final_msg).unwrap();
+    assert!(outputs.lock().unwrap().is_empty());
+
+    // the instance that receives the disclosed output asks the handler for its output handler:
+    context.send(report.disclosure);
+    let messages = context.msgs_iter().collect();
+    let client = [b, a][rounds % 2];
+    dialog(client, &engine_id, last_durably_received_offset, &messages);
+    let outputs = outputs.lock().unwrap().clone();
+    assert_eq!(outputs.len(), 1);
+    assert_eq!(outputs[0].engine_id, engine_id);
+    let literal = deserialize_output(&prg, &typed.fn_def, &outputs[0].output).unwrap();
+    assert_eq!(literal.to_string(), "(true, false)");
+}
+
+#[test]
+fn test_shared_external_ids() {
+    /// Assigns all sessions to the same order.
+    struct OrderIds;
+
+    impl IdGenerator for OrderIds {
+        fn engine_id(&self, _request: &MpcRequest) -> String {
+            format!("order-1-{}", uuid::Uuid::new_v4())
+        }
+
+        fn external_id(&self, _request: &MpcRequest) -> Option<String> {
+            Some("order-1".to_string())
+        }
+    }
+
+    let store = MemorySessionStore::default();
+    let shared = |store: &MemorySessionStore| {
+        let config = ServerConfig::default()
+            .with_session_store(store.clone())
+            .with_id_generator(OrderIds);
+        shared_server_with(config)
+    };
+    let (a, b) = (&shared(&store), &shared(&store));
+
+    let r = new_session(a, xor_and_program(), "true".to_string());
+    assert_eq!(r.status(), Status::Created);
+    let EngineCreationResult { engine_id, .. } = r.into_json().unwrap();
+
+    // external ids are unique among the running sessions of all instances:
+    let r = new_session(b, xor_and_program(), "true".to_string());
+    assert_eq!(r.status(), Status::Conflict);
+    assert_eq!(
+        r.into_json::<Error>(),
+        Some(Error::DuplicateExternalId {
+            external_id: "order-1".to_string()
+        })
+    );
+    let r = b.get(uri!(engine::external_id("order-1"))).dispatch();
+    assert_eq!(
+        r.into_json::<ExternalId>().map(|id| id.engine_id),
+        Some(engine_id)
+    );
+    assert_eq!(store.0.lock().unwrap().len(), 2);
+}
+
+fn tandem_http_protocol(
+    client: &Client,
+    engine_id: &String,
+    program: ValidatedCircuit,
+    input: Vec<bool>,
+    final_url: Option<String>,
+    upstream_msgs: MessageLog,
+    channel: Option<&SecureChannel>,
+) -> (Vec<bool>, usize) {
+    tandem_http_protocol_on(
+        &[client],
+        engine_id,
+        program,
+        input,
+        final_url,
+        upstream_msgs,
+        channel,
+    )
+}
+
+/// Runs the protocol like [`tandem_http_protocol`], sending each request to the next client.
+fn tandem_http_protocol_on(
+    clients: &[&Client],
+    engine_id: &String,
+    program: ValidatedCircuit,
+    input: Vec<bool>,
+    final_url: Option<String>,
+    mut upstream_msgs: MessageLog,
+    channel: Option<&SecureChannel>,
+) -> (Vec<bool>, usize) {
+    let mut context = MsgQueue::new(None);
+    let mut evaluator = Evaluator::new(program, input, ChaCha20Rng::from_entropy()).unwrap();
+    let open = |msg: &[u8], offset: MessageId| match channel {
+        Some(channel) => channel.open(u64::from(offset), msg).unwrap(),
+        None => msg.to_vec(),
+    };
+
+    let mut last_durably_received_offset: Option<MessageId> = None;
+    let mut steps_remaining = evaluator.steps();
+    let mut rounds = 0;
+    loop {
+        let client = clients[rounds % clients.len()];
+        for (msg, server_offset) in &upstream_msgs {
+            assert_eq!(
+                *server_offset,
+                last_durably_received_offset.map(|o| o + 1).unwrap_or(0)
+            );
+
+            if steps_remaining > 0 {
+                let (next_state, msg) = evaluator.run(&open(msg, *server_offset)).unwrap();
+                evaluator = next_state;
+                steps_remaining -= 1;
+                let msg = match channel {
+                    Some(channel) => channel.seal(u64::from(context.next_id()), &msg),
+                    None => msg,
+                };
+                context.send(msg);
+            } else if let Some(final_url) = &final_url {
+                assert!(msg.is_empty());
+                let mut msg = download(client, final_url, "bytes=0-9", Status::PartialContent);
+                msg.extend(download(
+                    client,
+                    final_url,
+                    "bytes=10-",
+                    Status::PartialContent,
+                ));
+                let msg = open(&msg, *server_offset);
+                return (evaluator.output(&msg).unwrap(), rounds);
+            } else {
+                let msg = open(msg, *server_offset);
+                return (evaluator.output(&msg).unwrap(), rounds);
+            }
+            last_durably_received_offset = Some(*server_offset);
+        }
+
+        let messages: Vec<(&Msg, MessageId)> = context.msgs_iter().collect();
+        let (msgs, server_commited_offset) =
+            dialog(client, engine_id, last_durably_received_offset, &messages);
+        assert_eq!(messages.last().map(|v| v.1), server_commited_offset);
+        rounds += 1;
+
+        if let Some(last_durably_received_offset) = server_commited_offset {
+            context.flush_queue(last_durably_received_offset);
+        }
+        upstream_msgs = msgs;
+    }
+}
+
+fn download(client: &Client, final_url: &str, range: &str, status: Status) -> Vec<u8> {
+    let r = client
+        .get(final_url.to_string())
+        .header(rocket::http::Header::new("Range", range.to_string()))
+        .dispatch();
+    assert_eq!(r.status(), status);
+    r.into_bytes().unwrap()
+}
+
+fn dialog(
+    client: &Client,
+    engine_id: &String,
+    last_durably_received_offset: Option<u32>,
+    messages: &Vec<(&Msg, MessageId)>,
+) -> (MessageLog, Option<MessageId>) {
+    let dialog_uri = uri!(engine::dialog(engine_id));
+    let messages = bincode::serialize(&(last_durably_received_offset, messages)).unwrap();
+    let res = client.post(dialog_uri).body(messages).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    frames::decode(&res.into_bytes().unwrap()).unwrap()
+}
+
+fn new_session<'a>(client: &'a Client, program: String, input: String) -> LocalResponse<'a> {
+    new_session_with(client, program, input, false)
+}
+
+fn new_session_with<'a>(
+    client: &'a Client,
+    program: String,
+    input: String,
+    stage_final: bool,
+) -> LocalResponse<'a> {
+    let session = session_request(program, input, stage_final);
+    client
+        .post(uri!(engine::create_session()))
+        .json(&session)
+        .dispatch()
+}
+
+fn session_request(program: String, input: String, stage_final: bool) -> NewSession {
+    let prg = check_program(&program).unwrap();
+    let circuit = compile_program(&prg, "main").unwrap();
+    NewSession {
+        plaintext_metadata: input.into(),
+        program,
+        function: "main".to_string(),
+        circuit_hash: circuit.gates.blake3_hash(),
+        client_version: env!("CARGO_PKG_VERSION").to_string(),
+        protocol_version: Some(tandem::PROTOCOL_VERSION),
+        wire_version: Some(crate::WIRE_VERSION),
+        wire_versions: None,
+        capabilities: vec!["streaming".to_string()],
+        stage_final,
+        approval_id: None,
+        idempotency_key: None,
+        session_nonce: None,
+        channel_key: None,
+        follow_ups: None,
+        circuit: None,
+    }
+}
+
+fn xor_and_program() -> String {
+    "pub fn main(a: bool, b: bool) -> (bool, bool) { (a ^ b, a & b) }".to_string()
+}
+
+/// Keeps the message logs of all servers in memory, like a Redis server shared by all instances.
+#[derive(Clone, Default)]
+struct MemorySessionStore(Arc<Mutex<HashMap<String, VersionedValue>>>);
+
+type VersionedValue = (u64, Vec<u8>);
+
+impl SessionStore for MemorySessionStore {
+    fn load(&self, key: &str) -> Result<Option<(u64, Vec<u8>)>, String> {
+        Ok(self.0.lock().unwrap().get(key).cloned())
+    }
+
+    fn store(&self, key: &str, version: u64, value: &[u8], _ttl: Duration) -> Result<bool, String> {
+        let mut stored = self.0.lock().unwrap();
+        if stored.get(key).map(|(v, _)| *v).unwrap_or(0) + 1 != version {
+            return Ok(false);
+        }
+        stored.insert(key.to_string(), (version, value.to_vec()));
+        Ok(true)
+    }
+
+    fn remove(&self, key: &str) -> Result<(), String> {
+        self.0.lock().unwrap().remove(key);
+        Ok(())
+    }
 }
 
 fn delete_session<'a>(client: &'a Client, engine_id: &String) -> LocalResponse<'a> {
@@ -0,0 +1,168 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::state::BandwidthPolicy;
+
+/// Size of the chunks in which throttled responses are streamed to the client.
+pub(crate) const THROTTLE_CHUNK_SIZE: usize = 16 * 1024;
+
+/// A token bucket, which is refilled with `rate` bytes per second up to `burst` bytes.
+///
+/// Taking more bytes than available leaves the bucket in debt, so that later callers have to wait
+/// until the debt has been paid off.
+#[derive(Debug, Clone)]
+pub(crate) struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(bytes_per_sec: u64, burst_bytes: u64, now: Instant) -> Self {
+        let burst = burst_bytes.max(1) as f64;
+        Self {
+            rate: bytes_per_sec.max(1) as f64,
+            burst,
+            tokens: burst,
+            updated: now,
+        }
+    }
+
+    /// Takes `bytes` tokens from the bucket, returning how long the caller needs to wait (starting
+    /// at `now`) until the bytes may be sent.
+    pub(crate) fn take(&mut self, bytes: usize, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.updated = now;
+        self.tokens -= bytes as f64;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+
+    /// The tokens in the bucket (negative if it is in debt) at the time of its last update.
+    pub(crate) fn state(&self) -> (f64, Instant) {
+        (self.tokens, self.updated)
+    }
+
+    /// Continues from the state of a bucket with the same limits, see [`TokenBucket::state`].
+    pub(crate) fn restore(&mut self, tokens: f64, updated: Instant) {
+        self.tokens = tokens.min(self.burst);
+        self.updated = updated;
+    }
+}
+
+/// Bandwidth limits shared by all sessions, together with the time sessions were throttled.
+pub(crate) struct Throttle {
+    policy: BandwidthPolicy,
+    global: Option<Mutex<TokenBucket>>,
+    throttled_micros: AtomicU64,
+    throttled_bytes: AtomicU64,
+}
+
+impl Throttle {
+    pub(crate) fn new(policy: BandwidthPolicy) -> Self {
+        let now = Instant::now();
+        let global = policy
+            .global_bandwidth_limit
+            .map(|limit| Mutex::new(TokenBucket::new(limit, policy.burst(limit), now)));
+        Self {
+            policy,
+            global,
+            throttled_micros: AtomicU64::new(0),
+            throttled_bytes: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns `true` if any bandwidth limit is configured.
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.global.is_some() || self.policy.session_bandwidth_limit.is_some()
+    }
+
+    /// Returns `true` if request bodies are throttled in addition to responses.
+    pub(crate) fn throttle_requests(&self) -> bool {
+        self.policy.throttle_requests
+    }
+
+    /// Returns a new bucket for a session, if the bandwidth of sessions is limited.
+    pub(crate) fn session_bucket(&self) -> Option<TokenBucket> {
+        self.policy
+            .session_bandwidth_limit
+            .map(|limit| TokenBucket::new(limit, self.policy.burst(limit), Instant::now()))
+    }
+
+    /// Reserves the bandwidth for transferring `bytes` bytes of a session in chunks of
+    /// [`THROTTLE_CHUNK_SIZE`], returning the instant at which each chunk may be transferred
+    /// without exceeding the session's or the global limit.
+    pub(crate) fn reserve(
+        &self,
+        mut session: Option<&mut TokenBucket>,
+        bytes: usize,
+    ) -> Vec<Instant> {
+        let now = Instant::now();
+        let mut deadlines = Vec::with_capacity(bytes / THROTTLE_CHUNK_SIZE + 1);
+        let mut remaining = bytes;
+        loop {
+            let chunk = remaining.min(THROTTLE_CHUNK_SIZE);
+            let mut wait = match session.as_mut() {
+                Some(bucket) => bucket.take(chunk, now),
+                None => Duration::ZERO,
+            };
+            if let Some(global) = &self.global {
+                wait = wait.max(global.lock().unwrap().take(chunk, now));
+            }
+            deadlines.push(now + wait);
+            remaining -= chunk;
+            if remaining == 0 {
+                break;
+            }
+        }
+        if let Some(wait) = deadlines.last().map(|deadline| *deadline - now) {
+            if !wait.is_zero() {
+                let micros = u64::try_from(wait.as_micros()).unwrap_or(u64::MAX);
+                self.throttled_micros.fetch_add(micros, Ordering::Relaxed);
+                self.throttled_bytes
+                    .fetch_add(bytes as u64, Ordering::Relaxed);
+            }
+        }
+        deadlines
+    }
+
+    /// Total time that transfers were delayed due to bandwidth limits.
+    pub(crate) fn throttled_time(&self) -> Duration {
+        Duration::from_micros(self.throttled_micros.load(Ordering::Relaxed))
+    }
+
+    /// Total number of bytes whose transfer was delayed due to bandwidth limits.
+    pub(crate) fn throttled_bytes(&self) -> u64 {
+        self.throttled_bytes.load(Ordering::Relaxed)
+    }
+}
+
+#[test]
+fn test_token_bucket() {
+    let start = Instant::now();
+    let mut bucket = TokenBucket::new(1000, 500, start);
+    assert_eq!(bucket.take(500, start), Duration::ZERO);
+    assert_eq!(bucket.take(250, start), Duration::from_millis(250));
+    // the debt is paid off after 250ms, after which the bucket refills again:
+    let later = start + Duration::from_millis(500);
+    assert_eq!(bucket.take(250, later), Duration::ZERO);
+    // the bucket never holds more than the burst size:
+    let much_later = later + Duration::from_secs(10);
+    assert_eq!(bucket.take(1000, much_later), Duration::from_millis(500));
+
+    // a bucket restored from the state of another one keeps its debt:
+    let (tokens, updated) = bucket.state();
+    let mut restored = TokenBucket::new(1000, 500, much_later + Duration::from_secs(10));
+    restored.restore(tokens, updated);
+    assert_eq!(restored.take(0, much_later), Duration::from_millis(500));
+}
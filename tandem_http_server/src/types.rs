@@ -1,13 +1,112 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::Arc,
+};
 
+use rand::Rng;
+use rand_chacha::{rand_core::SeedableRng, ChaCha20Rng};
 use rocket::serde::{Deserialize, Serialize};
-use tandem::Circuit;
+use tandem::{channel::HandshakeReply, states::Msg, Circuit};
+
+use crate::{msg_queue::MessageId, requests::NewSession};
 
 pub type EngineId = String;
 
 /// Custom logic to choose a server's circuit and input.
 pub type HandleMpcRequestFn = Box<dyn Fn(MpcRequest) -> Result<MpcSession, String> + Send + Sync>;
 
+/// Custom logic to choose the ids of new sessions, see [`crate::ServerConfig::with_id_generator`].
+///
+/// The engine id of a session is part of the URLs used by the client during the protocol and acts
+/// as a capability for the session, so it should always include a random part that cannot be
+/// guessed, even if it embeds a correlation id such as an order id.
+pub trait IdGenerator: Send + Sync {
+    /// Returns the engine id for a new session, a random UUID by default.
+    ///
+    /// Engine ids must be unique among running sessions and may only contain ASCII letters,
+    /// digits, `-`, `_`, `.` and `~`.
+    fn engine_id(&self, _request: &MpcRequest) -> String {
+        let rng: [u8; 16] = ChaCha20Rng::from_entropy().gen();
+        uuid::Builder::from_random_bytes(rng)
+            .into_uuid()
+            .to_string()
+    }
+
+    /// Returns the id of the session in an external system (e.g. an order or case number), which
+    /// can be used to look up the engine id while the session is running. `None` by default.
+    fn external_id(&self, _request: &MpcRequest) -> Option<String> {
+        None
+    }
+}
+
+/// The default [`IdGenerator`], generating random UUIDs without external ids.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RandomIds;
+
+impl IdGenerator for RandomIds {}
+
+/// Chooses the headers that the client sends with every request of a session, so that load
+/// balancers route all requests of the session to the instance of the server that runs it, see
+/// [`crate::ServerConfig::with_stickiness_provider`].
+///
+/// The headers are added to the [`MpcSession::request_headers`] of every session, headers chosen
+/// by the handler take precedence.
+pub trait StickinessProvider: Send + Sync {
+    /// Returns the headers for the session requested by the client.
+    fn request_headers(&self, request: &MpcRequest) -> HashMap<String, String>;
+}
+
+/// A [`StickinessProvider`] that sends the same headers for all sessions, for load balancers that
+/// only need to identify the instance of the server.
+#[derive(Debug, Clone, Default)]
+pub struct StaticHeaders(pub HashMap<String, String>);
+
+impl StickinessProvider for StaticHeaders {
+    fn request_headers(&self, _request: &MpcRequest) -> HashMap<String, String> {
+        self.0.clone()
+    }
+}
+
+/// Custom logic to report whether the server is ready, see [`crate::ServerConfig::with_readiness`].
+pub type ReadinessFn = Box<dyn Fn() -> Readiness + Send + Sync>;
+
+/// Whether the server is ready to handle sessions, as reported by `GET /readyz`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct Readiness {
+    /// If `false`, `GET /readyz` responds with `503 Service Unavailable`.
+    pub ready: bool,
+    /// Status of the individual components of the server, such as the compilation status of each
+    /// configured function.
+    pub components: BTreeMap<String, String>,
+}
+
+impl Default for Readiness {
+    fn default() -> Self {
+        Self {
+            ready: true,
+            components: BTreeMap::new(),
+        }
+    }
+}
+
+/// Custom logic to list the functions that the server publishes, see
+/// [`crate::ServerConfig::with_catalog`].
+pub type CatalogFn = Box<dyn Fn() -> Vec<PublishedFunction> + Send + Sync>;
+
+/// A function published by the server, as reported by `GET /programs`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub struct PublishedFunction {
+    /// Name of the Garble function.
+    pub function: String,
+    /// Signature of the Garble function, e.g. `fn main(x: u8, y: u8) -> u8`.
+    pub signature: Option<String>,
+    /// Hash of the Garble program containing the function.
+    pub program_hash: Option<String>,
+}
+
 /// Session information used by the server to start executing the MPC protocol.
 #[derive(Debug, Clone)]
 pub struct MpcSession {
@@ -21,22 +120,265 @@ pub struct MpcSession {
     /// initial request and to e.g. ensure that every request during the MPC execution is routed to
     /// the same server instance.
     pub request_headers: HashMap<String, String>,
+    /// Processes the output of the session, which requires the client to disclose its output to
+    /// the server (see the `output_disclosure` capability of [`crate::CAPABILITIES`]).
+    ///
+    /// Sessions of clients that do not agree to disclose their output are rejected.
+    pub on_output: Option<OutputHandler>,
+}
+
+/// The output of a session that the client disclosed to the server, see
+/// [`MpcSession::on_output`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MpcOutput {
+    /// The id of the engine that ran the session.
+    pub engine_id: EngineId,
+    /// The (hex-encoded) blake3 hash of the program.
+    pub program_hash: String,
+    /// The name of the function that was executed.
+    pub function: String,
+    /// The plaintext metadata of the session, see [`MpcRequest::plaintext_metadata`].
+    pub plaintext_metadata: String,
+    /// The output bits of the circuit, verified against the server's keys.
+    pub output: Vec<bool>,
+}
+
+/// Custom logic to log, persist or act on the output of a session, see
+/// [`MpcSession::on_output`].
+///
+/// The handler is called while the session is locked, so slow work (such as sending the output to
+/// another service) should be moved to a separate thread.
+#[derive(Clone)]
+pub struct OutputHandler(Arc<dyn Fn(MpcOutput) + Send + Sync>);
+
+impl OutputHandler {
+    /// Processes the outputs using the specified function.
+    pub fn new(f: impl Fn(MpcOutput) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    pub(crate) fn call(&self, output: MpcOutput) {
+        (self.0)(output)
+    }
+}
+
+impl fmt::Debug for OutputHandler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutputHandler").finish_non_exhaustive()
+    }
 }
 
 /// A request by a client to start a Multi-Party Computation.
 pub struct MpcRequest {
     /// Plaintext freely chosen by the client to influence the server's choice of its input.
+    ///
+    /// If the client sent structured metadata, this is its (compact) JSON serialization, see
+    /// [`MpcRequest::plaintext_metadata_json`].
     pub plaintext_metadata: String,
+    /// The plaintext metadata as sent by the client, which is a JSON string for clients that only
+    /// support plain strings as metadata.
+    pub plaintext_metadata_json: serde_json::Value,
     /// The Garble program to execute.
     pub program: String,
     /// The name of the function in the Garble program to execute using MPC.
     pub function: String,
+    /// The raw circuit that the client asks to compute instead of a Garble program, in which case
+    /// `program` and `function` are empty.
+    ///
+    /// Handlers that compute raw circuits return it as the circuit of their [`MpcSession`] (once
+    /// they have checked that they accept it), all other handlers should reject such requests.
+    pub circuit: Option<Circuit>,
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(crate = "rocket::serde")]
 pub(crate) struct EngineCreationResult {
     pub engine_id: String,
     pub request_headers: HashMap<String, String>,
     pub server_version: String,
+    pub protocol_version: u32,
+    /// The wire version negotiated for the session.
+    pub wire_version: u32,
+    /// The optional features negotiated for the session, see [`crate::CAPABILITIES`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub capabilities: Vec<String>,
+    /// The id of the session in an external system, if assigned by the server's [`IdGenerator`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    /// Path of the download of the staged final message, if requested by the client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub final_url: Option<String>,
+    /// Messages that the server can send without waiting for the client, piggybacked so that the
+    /// client does not need a dialog round just to fetch them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<(Msg, MessageId)>,
+    /// Only sent if the client sent a session nonce.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_commitment: Option<SessionCommitment>,
+    /// The reply of the server to the handshake of an encrypted channel, if negotiated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub channel: Option<HandshakeReply>,
+}
+
+/// The response to a request for the next round of a session, see [`crate::requests::NextRound`].
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct NextRoundResult {
+    pub request_headers: HashMap<String, String>,
+    /// The initial messages of the round, see [`EngineCreationResult::messages`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<(Msg, MessageId)>,
+}
+
+/// A commitment of the server to the session that it created for a request, which lets the client
+/// detect a result that was replayed from another session or whose protocol configuration does not
+/// match the configuration negotiated by the server.
+///
+/// The commitment is the blake3 hash of the bincode serialization of the nonces of client and
+/// server, the engine id, the circuit hash, the function name, the protocol version, the wire
+/// versions and capabilities offered by the client, the negotiated wire version and capabilities
+/// and whether the final message is staged.
+///
+/// If the session uses an encrypted channel, the hash is keyed using the binding key of the
+/// channel (see [`tandem::channel::SecureChannel::binding_key`]), so that only the ends of the
+/// channel can compute it. A middlebox that alters the request or its result (for example by
+/// stripping capabilities) is then detected, as long as the client pinned the channel key of the
+/// server. Otherwise, the hash is computed in key derivation mode over public values and can be
+/// recomputed by anyone: it only detects results that were mixed up by accident (for example by a
+/// misconfigured cache), not a middlebox, against which the request still needs to be protected
+/// by TLS.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct SessionCommitment {
+    pub server_nonce: [u8; 32],
+    pub commitment: [u8; 32],
+}
+
+/// The key derivation context of a [`SessionCommitment`].
+const SESSION_COMMITMENT_CONTEXT: &str = "tandem 2025-01-01 session commitment v1";
+
+impl SessionCommitment {
+    /// Commits to the session created for the request, using a fresh server nonce.
+    pub(crate) fn new(
+        request: &NewSession,
+        client_nonce: &[u8; 32],
+        engine_id: &str,
+        wire_version: u32,
+        capabilities: &[String],
+        key: Option<&[u8; 32]>,
+    ) -> Result<Self, bincode::Error> {
+        let server_nonce: [u8; 32] = ChaCha20Rng::from_entropy().gen();
+        let commitment = Self::commit(
+            request,
+            client_nonce,
+            &server_nonce,
+            engine_id,
+            wire_version,
+            capabilities,
+            key,
+        )?;
+        Ok(Self {
+            server_nonce,
+            commitment,
+        })
+    }
+
+    /// Returns the commitment to the session for the nonces of client and server, keyed using the
+    /// binding key of the session's encrypted channel (if any).
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn commit(
+        request: &NewSession,
+        client_nonce: &[u8; 32],
+        server_nonce: &[u8; 32],
+        engine_id: &str,
+        wire_version: u32,
+        capabilities: &[String],
+        key: Option<&[u8; 32]>,
+    ) -> Result<[u8; 32], bincode::Error> {
+        let committed = bincode::serialize(&(
+            client_nonce,
+            &server_nonce,
+            engine_id,
+            &request.circuit_hash,
+            &request.function,
+            tandem::PROTOCOL_VERSION,
+            request.supported_wire_versions().unwrap_or_default(),
+            &request.capabilities,
+            wire_version,
+            capabilities,
+            request.stage_final,
+        ))?;
+        let mut hasher = match key {
+            Some(key) => blake3::Hasher::new_keyed(key),
+            None => blake3::Hasher::new_derive_key(SESSION_COMMITMENT_CONTEXT),
+        };
+        Ok(hasher.update(&committed).finalize().into())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Health {
+    pub status: String,
+    pub server_version: String,
+    pub protocol_version: u32,
+    pub wire_version: u32,
+    /// Report of the self test, only run if requested via `/healthz?self_test=true`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub self_test: Option<tandem::SelfTestReport>,
+}
+
+/// The optional features and the limits of the server, as reported by `GET /capabilities`.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Capabilities {
+    pub server_version: String,
+    pub wire_version: u32,
+    pub min_wire_version: u32,
+    /// See [`crate::CAPABILITIES`].
+    pub features: Vec<String>,
+    /// The configured limits of sessions, such as `max_session_and_gates`.
+    pub limits: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Metrics {
+    /// Number of sessions that are currently running.
+    pub sessions: usize,
+    /// Total time (in milliseconds) that dialog requests and responses were delayed to stay
+    /// within the configured bandwidth limits.
+    pub throttled_millis: u64,
+    /// Total number of bytes whose transfer was delayed to stay within the bandwidth limits.
+    pub throttled_bytes: u64,
+}
+
+/// The engine id of a running session, looked up by its external id.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct ExternalId {
+    pub external_id: String,
+    pub engine_id: String,
+}
+
+/// The state of an [`Approval`], which can only be decided once.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub(crate) enum ApprovalStatus {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+/// A request of a client to run a computation, which must be approved before a session referencing
+/// it can be created.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(crate = "rocket::serde")]
+pub(crate) struct Approval {
+    pub approval_id: String,
+    pub status: ApprovalStatus,
+    pub program_hash: [u8; 32],
+    pub function: String,
+    pub plaintext_metadata: String,
+    pub input_commitment: [u8; 32],
 }
@@ -0,0 +1,11 @@
+#!/bin/bash
+
+errorhandler () {
+    kill $(jobs -p)
+}
+trap errorhandler ERR EXIT
+
+cargo build --features "bin"
+cargo run -p tandem_http_server --features "bin" &
+sleep 2
+WASM_BINDGEN_TEST_TIMEOUT=300 wasm-pack test --release --node tandem_http_client --test node